@@ -20,9 +20,17 @@
     unused_results
 )]
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 pub use slog::{self, Drain};
 pub use slog_scope::{self as log, logger};
 
+/// Current verbosity level of the [`main_logger`], represented as
+/// [`slog::Level::as_usize()`].
+///
+/// May be changed at runtime via [`set_level()`].
+static LEVEL: AtomicUsize = AtomicUsize::new(4); // `slog::Level::Info`
+
 /// Initializes global logger with the given verbosity `level` ([`Info`] by
 /// default, if [`None`]), returning its guard that should be held as long as
 /// program runs.
@@ -42,8 +50,29 @@ pub fn init(level: Option<slog::Level>) -> slog_scope::GlobalLoggerGuard {
     guard
 }
 
+/// Returns the current verbosity level of the [`main_logger`].
+#[inline]
+#[must_use]
+pub fn level() -> slog::Level {
+    slog::Level::from_usize(LEVEL.load(Ordering::Relaxed))
+        .unwrap_or(slog::Level::Info)
+}
+
+/// Changes verbosity level of the [`main_logger`] at runtime.
+///
+/// Takes effect immediately for all the already created [`Logger`]s derived
+/// from the [`main_logger`].
+///
+/// [`Logger`]: slog::Logger
+#[inline]
+pub fn set_level(level: slog::Level) {
+    LEVEL.store(level.as_usize(), Ordering::Relaxed)
+}
+
 /// Creates, configures and returns main [`Logger`] of the application.
 ///
+/// The given `level` may be changed later at runtime via [`set_level()`].
+///
 /// [`Logger`]: slog::Logger
 #[must_use]
 pub fn main_logger(level: slog::Level) -> slog::Logger {
@@ -54,7 +83,6 @@ pub fn main_logger(level: slog::Level) -> slog::Logger {
     let drain = slog_term::CompactFormat::new(decorator).build().fuse();
 
     let drain = drain
-        .filter_level(level)
         .filter(|rec| {
             // Disable annoying DEBUG logs from `hyper` crate.
             !(rec.level() == slog::Level::Debug
@@ -67,5 +95,34 @@ pub fn main_logger(level: slog::Level) -> slog::Logger {
         .build()
         .fuse();
 
-    slog::Logger::root(drain, slog::o!())
+    set_level(level);
+    slog::Logger::root(RuntimeLevelFilter(drain).fuse(), slog::o!())
+}
+
+/// [`Drain`] filtering out records less severe than the current [`level()`],
+/// which may be changed at runtime via [`set_level()`].
+#[derive(Debug)]
+struct RuntimeLevelFilter<D>(D);
+
+impl<D: Drain> Drain for RuntimeLevelFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    #[inline]
+    fn log(
+        &self,
+        record: &slog::Record<'_>,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if record.level().is_at_least(level()) {
+            self.0.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    fn is_enabled(&self, lvl: slog::Level) -> bool {
+        lvl.is_at_least(level()) && self.0.is_enabled(lvl)
+    }
 }
@@ -1,6 +1,13 @@
+use std::env;
+
 use actix_web_static_files::NpmBuild;
 
 fn main() -> anyhow::Result<()> {
+    // Web UI is embedded only with `ui` feature enabled.
+    if env::var_os("CARGO_FEATURE_UI").is_none() {
+        return Ok(());
+    }
+
     NpmBuild::new("./")
         .executable("yarn")
         .install()?
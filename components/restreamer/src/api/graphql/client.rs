@@ -2,41 +2,97 @@
 //!
 //! [GraphQL]: https://graphql.com
 
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    convert::{TryFrom as _, TryInto as _},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
 use actix_web::http::StatusCode;
 use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use ephyr_log::log;
 use futures::stream::BoxStream;
-use futures_signals::signal::SignalExt as _;
-use juniper::{graphql_object, graphql_subscription, GraphQLObject, RootNode};
-use once_cell::sync::Lazy;
-use rand::Rng as _;
+use futures_signals::{map_ref, signal::SignalExt as _};
+use juniper::{
+    graphql_object, graphql_subscription, EmptyMutation, GraphQLInputObject,
+    GraphQLObject, RootNode,
+};
+use tokio::{fs, time};
 
 use crate::{
-    api::graphql,
-    dvr, spec,
+    api::{
+        self,
+        graphql::{self, Role},
+    },
+    check, dvr, fault, ffmpeg, overlay, password, paths,
+    proxy::IpRange,
+    reachability,
+    schedule::{InputSchedule, ScheduleRange, Weekday},
+    server, spec, srs,
     state::{
-        Delay, InputEndpointKind, InputId, InputKey, InputSrcUrl, Label,
-        MixinId, MixinSrcUrl, OutputDstUrl, OutputId, Restream, RestreamId,
-        RestreamKey, Volume,
+        self, AudioSampleRate, Delay, DisableReason, DisableReasonKind,
+        DstConflict, Duplicate, FileInputSrc, InputEndpointKind, InputId,
+        InputKey, InputSrc, InputSrcUrl, Label, LocalTimeError, LogLevel,
+        LoudnessTarget, MixinId, MixinSrcUrl, Output, OutputBootPolicy,
+        OutputConditioning, OutputConditioningPreset, OutputConstraints,
+        OutputDestination, OutputDstUrl, OutputId, OutputLimits,
+        OutputMetadata, OutputOverlay, OutputTest, OverlayPosition,
+        PendingDstError, PoolProcess, PrewarmStrategy, PublicHost,
+        PublisherPolicy, QuotaExceeded, Rejected, Replication, Restream,
+        RestreamGroup, RestreamId, RestreamKey, RtmpPullOptions, SpecReload,
+        TimeZone, Volume,
     },
-    Spec,
+    sweep, text, trace, Spec,
 };
 
-use super::Context;
+use super::{complexity::Limited, Context};
 use url::Url;
+use uuid::Uuid;
 
-/// Full schema of [`api::graphql::client`].
+/// Full schema of [`api::graphql::client`], served to [`Role::Admin`]s only.
 ///
 /// [`api::graphql::client`]: graphql::client
-pub type Schema =
-    RootNode<'static, QueriesRoot, MutationsRoot, SubscriptionsRoot>;
+pub type Schema = RootNode<
+    'static,
+    Limited<QueriesRoot>,
+    Limited<MutationsRoot>,
+    Limited<SubscriptionsRoot>,
+>;
 
 /// Constructs and returns new [`Schema`], ready for use.
 #[inline]
 #[must_use]
 pub fn schema() -> Schema {
-    Schema::new(QueriesRoot, MutationsRoot, SubscriptionsRoot)
+    Schema::new(
+        Limited(QueriesRoot),
+        Limited(MutationsRoot),
+        Limited(SubscriptionsRoot),
+    )
+}
+
+/// Read-only schema of [`api::graphql::client`], sharing its queries and
+/// subscriptions with the full [`Schema`], but having no mutations at all, so
+/// they cannot be even parsed by its clients.
+///
+/// [`api::graphql::client`]: graphql::client
+pub type ReadOnlySchema = RootNode<
+    'static,
+    Limited<QueriesRoot>,
+    EmptyMutation<Context>,
+    Limited<SubscriptionsRoot>,
+>;
+
+/// Constructs and returns new [`ReadOnlySchema`], ready for use.
+#[inline]
+#[must_use]
+pub fn read_only_schema() -> ReadOnlySchema {
+    ReadOnlySchema::new(
+        Limited(QueriesRoot),
+        EmptyMutation::new(),
+        Limited(SubscriptionsRoot),
+    )
 }
 
 /// Root of all [GraphQL mutations][1] in the [`Schema`].
@@ -53,6 +109,26 @@ impl MutationsRoot {
     /// the one defined by the `spec`. Otherwise, merges the `spec` with
     /// existing `Restream`s.
     ///
+    /// If `applyCredentials` is `true` and the `spec` contains credentials
+    /// (see `Query.export`), then restores them too, invalidating all the
+    /// existing browser sessions.
+    ///
+    /// ### Errors
+    ///
+    /// Errors with `INVALID_FILE_SRC` or `INVALID_OVERLAY_IMAGE` if any media
+    /// file path in the `spec` cannot be resolved or is outside the media root
+    /// directory of this server, specifying the failure via
+    /// `extensions.reason`.
+    ///
+    /// Errors with `QUOTA_EXCEEDED` if applying the `spec` exceeds the maximum
+    /// number of `Restream`s or `Output`s allowed on this server, reporting
+    /// the exceeded limit via `extensions.limit` and the amount it's exceeded
+    /// by via `extensions.excess` (and the offending `Restream` via
+    /// `extensions.restreamKey`, if the limit is per `Restream`).
+    ///
+    /// Errors with `CYCLIC_RESTREAMS_CHAIN` if applying the `spec` makes
+    /// chained `Restream`s pull live streams from each other in a cycle.
+    ///
     /// ### Result
     ///
     /// Returns `null` if a `Restream` with the given `id` doesn't exist,
@@ -67,17 +143,25 @@ impl MutationsRoot {
         restream_id(description = "Optional ID of a concrete `Restream` \
                                    to apply the `spec` to without touching \
                                    other `Restream`s."),
+        apply_credentials(
+            description = "Indicator whether credentials contained in the \
+                           `spec` should be restored.",
+            default = false,
+        ),
     ))]
     fn import(
         spec: String,
         replace: bool,
         restream_id: Option<RestreamId>,
+        apply_credentials: bool,
         context: &Context,
     ) -> Result<Option<bool>, graphql::Error> {
-        let spec = serde_json::from_str::<Spec>(&spec)?.into_v1();
+        context.require_writable()?;
+        let mut spec = serde_json::from_str::<Spec>(&spec)?.into_v1();
+        let credentials = spec.credentials.take().filter(|_| apply_credentials);
 
-        Ok(if let Some(id) = restream_id {
-            let spec = (spec.restreams.len() == 1)
+        let imported = if let Some(id) = restream_id {
+            let mut spec = (spec.restreams.len() == 1)
                 .then(|| spec.restreams.into_iter().next())
                 .flatten()
                 .ok_or_else(|| {
@@ -87,22 +171,25 @@ impl MutationsRoot {
                             "JSON spec should contain exactly one Restream",
                         )
                 })?;
-            #[allow(clippy::manual_find_map)]
-            // due to moving `spec` inside closure
+            // The targeted `Restream` keeps its own ID.
+            spec.id = None;
             context
                 .state()
-                .restreams
-                .lock_mut()
-                .iter_mut()
-                .find(|r| r.id == id)
-                .map(|r| {
-                    r.apply(spec, replace);
-                    true
-                })
+                .apply_restream_checked(id, spec, replace)
+                .map_err(rejected_error)?
+                .map(|()| true)
         } else {
-            context.state().apply(spec, replace);
+            context
+                .state()
+                .apply_checked(spec, replace)
+                .map_err(rejected_error)?;
             Some(true)
-        })
+        };
+
+        if let (Some(creds), Some(true)) = (credentials, imported) {
+            context.state().settings.lock_mut().apply_credentials(creds);
+        }
+        Ok(imported)
     }
 
     /// Sets a new `Restream` or updates an existing one (if `id` is specified).
@@ -112,23 +199,277 @@ impl MutationsRoot {
     /// Idempotent if `id` is specified. Otherwise is non-idempotent, always
     /// creates a new `Restream` and errors on the `key` duplicates.
     ///
+    /// ### Errors
+    ///
+    /// Errors with `DUPLICATE_RESTREAM_KEY` if the `key` is used already,
+    /// pointing to the conflicting `Restream` via `extensions.restreamId` (and
+    /// `extensions.restreamLabel`, if it has one).
+    ///
+    /// Errors with `DUPLICATE_ID` if the `newId` is used already, repeating it
+    /// via `extensions.id`.
+    ///
+    /// Errors with `QUOTA_EXCEEDED` if a new `Restream` exceeds the maximum
+    /// number of `Restream`s allowed on this server, reporting the limit via
+    /// `extensions.limit`.
+    ///
     /// ### Result
     ///
     /// Returns `null` if a `Restream` with the given `id` doesn't exist,
     /// otherwise always returns `true`.
+    #[graphql(
+        deprecated = "Use `Mutation.createInput` for creating a `Restream` and \
+                      `Mutation.setRestreamV2` for updating it.",
+        arguments(
+            key(description = "Unique (case-insensitively) key to set the \
+                               `Restream` with.\
+                               \n\n\
+                               Should meet `[a-zA-Z0-9_-]{1,64}` format, and \
+                               should not start with `pull_` prefix."),
+            label(description = "Optional label to set the `Restream` with."),
+            group(description = "Optional group to put the `Restream` into.\
+                                 \n\n\
+                                 Should be a non-blank string of at most 100 \
+                                 characters."),
+            src(description = "URL to pull a live stream from.\
+                               \n\n\
+                               Another `Restream` may be referred as \
+                               `ephyr://restream/<id>` to pull its live stream \
+                               locally.\
+                               \n\n\
+                               If not specified then `Restream` will await for \
+                               a live stream being pushed to its endpoint."),
+            file_src(
+                description = "Path to a local media file on the server to \
+                               pull a live stream from, instead of `src`.\
+                               \n\n\
+                               It should point to an existing readable file \
+                               inside the media root directory of this server \
+                               (if it's configured).",
+            ),
+            loop_file_src(
+                description = "Indicator whether the `fileSrc` should be \
+                               looped infinitely, rather than streamed once.",
+                default = true,
+            ),
+            backup_src(
+                description = "URL to pull a live stream from for a backup \
+                               endpoint.\
+                               \n\n\
+                               If not specified then `Restream` will await for \
+                               a live stream being pushed to its backup \
+                               endpoint.\
+                               \n\n\
+                               Has no effect if `withBackup` argument is not \
+                               `true`.",
+            ),
+            with_backup(
+                description = "Indicator whether the `Restream` should have a \
+                               backup endpoint for a live stream.",
+                default = false,
+            ),
+            with_hls(
+                description = "Indicator whether the `Restream` should have an \
+                               additional endpoint for serving a live stream \
+                               via HLS.",
+                default = false,
+            ),
+            max_pull_lifetime(
+                description = "Maximum lifetime (in seconds) of a connection \
+                               pulling a live stream from `src` or \
+                               `backupSrc`, after which it's proactively \
+                               re-established.\
+                               \n\n\
+                               If not specified then the connection is kept as \
+                               long as it's alive.",
+            ),
+            connect_timeout_secs(
+                description = "Timeout (in seconds) of establishing a \
+                               connection for pulling a live stream from `src` \
+                               or `backupSrc`.\
+                               \n\n\
+                               If not specified then the default one is used.",
+            ),
+            idle_timeout_secs(
+                description = "Timeout (in seconds) of a connection pulling a \
+                               live stream from `src` or `backupSrc` receiving \
+                               no data, after which it's considered stalled \
+                               and is re-established.\
+                               \n\n\
+                               If not specified then the default one is used.",
+            ),
+            queue_publishers(
+                description = "Indicator whether publishers of the `Restream` \
+                               should be allowed to connect even when its \
+                               `Input` is disabled, being rejected only on \
+                               publishing.",
+                default = false,
+            ),
+            publisher_policy(
+                description = "Policy of resolving concurrent publishing of a \
+                               live stream into the same `Input` of the \
+                               `Restream`.\
+                               \n\n\
+                               If not specified then `REJECT_NEW` is used.",
+            ),
+            public_host_override(
+                description = "Host to be used in public URLs of the \
+                               `Restream` instead of the globally configured \
+                               one.\
+                               \n\n\
+                               If not specified then the global one is used.",
+            ),
+            auto_disable_after_idle_secs(
+                description = "Number of seconds the `Input` of the `Restream` \
+                               should stay idle (receiving no live stream) \
+                               continuously for, to be disabled automatically.\
+                               \n\n\
+                               If not specified then the `Input` is never \
+                               disabled automatically.",
+            ),
+            max_session_secs(
+                description = "Maximum number of seconds a single session of \
+                               an external client publishing a live stream \
+                               into the `Input` of the `Restream` may last \
+                               for, before the client is kicked.\
+                               \n\n\
+                               If not specified then publishing sessions are \
+                               not limited.",
+            ),
+            disable_on_expiry(
+                description = "Indicator whether the `Input` of the `Restream` \
+                               should be disabled once a publishing session \
+                               exceeds `maxSessionSecs`.",
+                default = false,
+            ),
+            id(
+                description = "ID of the `Restream` to be updated rather than \
+                               creating a new one."
+            ),
+            new_id(
+                description = "ID to create a new `Restream` with, instead of \
+                               a random one.\
+                               \n\n\
+                               Cannot be specified along with `id`."
+            ),
+        )
+    )]
+    async fn set_restream(
+        key: RestreamKey,
+        label: Option<Label>,
+        group: Option<RestreamGroup>,
+        src: Option<InputSrcUrl>,
+        file_src: Option<String>,
+        loop_file_src: bool,
+        backup_src: Option<InputSrcUrl>,
+        with_backup: bool,
+        with_hls: bool,
+        max_pull_lifetime: Option<i32>,
+        connect_timeout_secs: Option<i32>,
+        idle_timeout_secs: Option<i32>,
+        queue_publishers: bool,
+        publisher_policy: Option<PublisherPolicy>,
+        public_host_override: Option<PublicHost>,
+        auto_disable_after_idle_secs: Option<i32>,
+        max_session_secs: Option<i32>,
+        disable_on_expiry: bool,
+        id: Option<RestreamId>,
+        new_id: Option<RestreamId>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let spec = restream_spec(
+            RestreamArgs {
+                key,
+                label,
+                group,
+                src,
+                file_src,
+                loop_file_src,
+                backup_src,
+                with_backup,
+                with_hls,
+                max_pull_lifetime,
+                connect_timeout_secs,
+                idle_timeout_secs,
+                schedule: None,
+                rtmp_options: None,
+                queue_publishers,
+                publisher_policy,
+                public_host_override,
+                auto_disable_after_idle_secs,
+                max_session_secs,
+                disable_on_expiry,
+                id,
+                new_id,
+            },
+            context,
+        )
+        .await?;
+
+        #[allow(clippy::option_if_let_else)] // due to consuming `spec`
+        Ok(if let Some(id) = id {
+            context
+                .state()
+                .edit_restream(id, spec)
+                .map_err(duplicate_error)?
+        } else {
+            context
+                .state()
+                .add_restream(spec)
+                .map(Some)
+                .map_err(rejected_error)?
+        }
+        .map(|_| true))
+    }
+
+    /// Sets a new `Restream` or updates an existing one (if `id` is
+    /// specified), exactly as `Mutation.setRestream` does, taking the same
+    /// arguments.
+    ///
+    /// ### Result
+    ///
+    /// Returns the set `Restream`, so its generated IDs are known without
+    /// re-querying.
+    ///
+    /// Errors with `UNKNOWN_RESTREAM` if a `Restream` with the given `id`
+    /// doesn't exist.
     #[graphql(arguments(
-        key(description = "Unique key to set the `Restream` with."),
+        key(description = "Unique (case-insensitively) key to set the \
+                           `Restream` with.\
+                           \n\n\
+                           Should meet `[a-zA-Z0-9_-]{1,64}` format, and \
+                           should not start with `pull_` prefix."),
         label(description = "Optional label to set the `Restream` with."),
+        group(description = "Optional group to put the `Restream` into.\
+                             \n\n\
+                             Should be a non-blank string of at most 100 \
+                             characters."),
         src(description = "URL to pull a live stream from.\
                            \n\n\
-                           If not specified then `Restream` will await for a \
-                           live stream being pushed to its endpoint."),
+                           Another `Restream` may be referred as \
+                           `ephyr://restream/<id>` to pull its live stream \
+                           locally.\
+                           \n\n\
+                           If not specified then `Restream` will await for \
+                           a live stream being pushed to its endpoint."),
+        file_src(description = "Path to a local media file on the server to \
+                                pull a live stream from, instead of `src`.\
+                                \n\n\
+                                It should point to an existing readable \
+                                file inside the media root directory of \
+                                this server (if it's configured)."),
+        loop_file_src(
+            description = "Indicator whether the `fileSrc` should be \
+                           looped infinitely, rather than streamed once.",
+            default = true,
+        ),
         backup_src(
             description = "URL to pull a live stream from for a backup \
                            endpoint.\
                            \n\n\
-                           If not specified then `Restream` will await for a \
-                           live stream being pushed to its backup endpoint.\
+                           If not specified then `Restream` will await for \
+                           a live stream being pushed to its backup \
+                           endpoint.\
                            \n\n\
                            Has no effect if `withBackup` argument is not \
                            `true`.",
@@ -140,736 +481,5540 @@ impl MutationsRoot {
         ),
         with_hls(
             description = "Indicator whether the `Restream` should have an \
-                           additional endpoint for serving a live stream via \
-                           HLS.",
+                           additional endpoint for serving a live stream \
+                           via HLS.",
+            default = false,
+        ),
+        max_pull_lifetime(
+            description = "Maximum lifetime (in seconds) of a connection \
+                           pulling a live stream from `src` or \
+                           `backupSrc`, after which it's proactively \
+                           re-established.\
+                           \n\n\
+                           If not specified then the connection is kept as \
+                           long as it's alive.",
+        ),
+        connect_timeout_secs(
+            description = "Timeout (in seconds) of establishing a \
+                           connection for pulling a live stream from `src` \
+                           or `backupSrc`.\
+                           \n\n\
+                           If not specified then the default one is used.",
+        ),
+        idle_timeout_secs(
+            description = "Timeout (in seconds) of a connection pulling a \
+                           live stream from `src` or `backupSrc` receiving \
+                           no data, after which it's considered stalled \
+                           and is re-established.\
+                           \n\n\
+                           If not specified then the default one is used.",
+        ),
+        queue_publishers(
+            description = "Indicator whether publishers of the `Restream` \
+                           should be allowed to connect even when its \
+                           `Input` is disabled, being rejected only on \
+                           publishing.",
+            default = false,
+        ),
+        publisher_policy(
+            description = "Policy of resolving concurrent publishing of a \
+                           live stream into the same `Input` of the \
+                           `Restream`.\
+                           \n\n\
+                           If not specified then `REJECT_NEW` is used.",
+        ),
+        public_host_override(
+            description = "Host to be used in public URLs of the \
+                           `Restream` instead of the globally configured \
+                           one.\
+                           \n\n\
+                           If not specified then the global one is used.",
+        ),
+        auto_disable_after_idle_secs(
+            description = "Number of seconds the `Input` of the `Restream` \
+                           should stay idle (receiving no live stream) \
+                           continuously for, to be disabled automatically.\
+                           \n\n\
+                           If not specified then the `Input` is never \
+                           disabled automatically.",
+        ),
+        max_session_secs(
+            description = "Maximum number of seconds a single session of \
+                           an external client publishing a live stream \
+                           into the `Input` of the `Restream` may last \
+                           for, before the client is kicked.\
+                           \n\n\
+                           If not specified then publishing sessions are \
+                           not limited.",
+        ),
+        disable_on_expiry(
+            description = "Indicator whether the `Input` of the `Restream` \
+                           should be disabled once a publishing session \
+                           exceeds `maxSessionSecs`.",
             default = false,
         ),
         id(description = "ID of the `Restream` to be updated rather than \
                           creating a new one."),
+        new_id(
+            description = "ID to create a new `Restream` with, instead of \
+                           a random one.\
+                           \n\n\
+                           Cannot be specified along with `id`."
+        ),
     ))]
-    fn set_restream(
+    async fn set_restream_v2(
         key: RestreamKey,
         label: Option<Label>,
+        group: Option<RestreamGroup>,
         src: Option<InputSrcUrl>,
+        file_src: Option<String>,
+        loop_file_src: bool,
         backup_src: Option<InputSrcUrl>,
         with_backup: bool,
         with_hls: bool,
+        max_pull_lifetime: Option<i32>,
+        connect_timeout_secs: Option<i32>,
+        idle_timeout_secs: Option<i32>,
+        queue_publishers: bool,
+        publisher_policy: Option<PublisherPolicy>,
+        public_host_override: Option<PublicHost>,
+        auto_disable_after_idle_secs: Option<i32>,
+        max_session_secs: Option<i32>,
+        disable_on_expiry: bool,
         id: Option<RestreamId>,
+        new_id: Option<RestreamId>,
         context: &Context,
-    ) -> Result<Option<bool>, graphql::Error> {
-        let input_src = if with_backup {
-            Some(spec::v1::InputSrc::FailoverInputs(vec![
-                spec::v1::Input {
-                    key: InputKey::new("main").unwrap(),
-                    endpoints: vec![spec::v1::InputEndpoint {
-                        kind: InputEndpointKind::Rtmp,
-                    }],
-                    src: src.map(spec::v1::InputSrc::RemoteUrl),
-                    enabled: true,
-                },
-                spec::v1::Input {
-                    key: InputKey::new("backup").unwrap(),
-                    endpoints: vec![spec::v1::InputEndpoint {
-                        kind: InputEndpointKind::Rtmp,
-                    }],
-                    src: backup_src.map(spec::v1::InputSrc::RemoteUrl),
-                    enabled: true,
-                },
-            ]))
-        } else {
-            src.map(spec::v1::InputSrc::RemoteUrl)
+    ) -> Result<Restream, graphql::Error> {
+        let not_found = || {
+            graphql::Error::new("UNKNOWN_RESTREAM")
+                .status(StatusCode::NOT_FOUND)
+                .message("Restream doesn't exist")
         };
-
-        let mut endpoints = vec![spec::v1::InputEndpoint {
-            kind: InputEndpointKind::Rtmp,
-        }];
-        if with_hls {
-            endpoints.push(spec::v1::InputEndpoint {
-                kind: InputEndpointKind::Hls,
-            });
-        }
-
-        let spec = spec::v1::Restream {
-            key,
+        let _ = Self::set_restream(
+            key.clone(),
             label,
-            input: spec::v1::Input {
-                key: InputKey::new("origin").unwrap(),
-                endpoints,
-                src: input_src,
-                enabled: true,
-            },
-            outputs: vec![],
-        };
-
-        #[allow(clippy::option_if_let_else)] // due to consuming `spec`
-        Ok(if let Some(id) = id {
-            context.state().edit_restream(id, spec)
-        } else {
-            context.state().add_restream(spec).map(Some)
-        }
-        .map_err(|e| {
-            graphql::Error::new("DUPLICATE_RESTREAM_KEY")
-                .status(StatusCode::CONFLICT)
-                .message(&e)
-        })?
-        .map(|_| true))
-    }
+            group,
+            src,
+            file_src,
+            loop_file_src,
+            backup_src,
+            with_backup,
+            with_hls,
+            max_pull_lifetime,
+            connect_timeout_secs,
+            idle_timeout_secs,
+            queue_publishers,
+            publisher_policy,
+            public_host_override,
+            auto_disable_after_idle_secs,
+            max_session_secs,
+            disable_on_expiry,
+            id,
+            new_id,
+            context,
+        )
+        .await?
+        .ok_or_else(not_found)?;
 
-    /// Removes a `Restream` by its `id`.
-    ///
-    /// ### Result
-    ///
-    /// Returns `null` if `Restream` with the given `id` doesn't exist,
-    /// otherwise always returns `true`.
-    #[graphql(arguments(id(
-        description = "ID of the `Restream` to be removed."
-    )))]
-    fn remove_restream(id: RestreamId, context: &Context) -> Option<bool> {
-        context.state().remove_restream(id)?;
-        Some(true)
+        // `Restream.key`s are unique, so identify a new `Restream` well.
+        context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| id.map_or_else(|| r.key == key, |id| r.id == id))
+            .cloned()
+            .ok_or_else(not_found)
     }
 
-    /// Enables a `Restream` by its `id`.
+    /// Creates a new `Restream` with its `Input` either awaiting a live
+    /// stream being pushed into it, or pulling it from a remote URL, setting
+    /// all the specified optional settings at once.
     ///
-    /// Enabled `Restream` is allowed to accept or pull a live stream.
+    /// Unlike a sequence of `Mutation.setRestream` and dedicated setters, the
+    /// `Restream` appears with all its settings applied, or doesn't appear at
+    /// all, if any of them is invalid.
     ///
-    /// ### Result
+    /// ### Errors
     ///
-    /// Returns `true` if a `Restream` with the given `id` has been enabled,
-    /// `false` if it has been enabled already, and `null` if it doesn't exist.
-    #[graphql(arguments(id(
-        description = "ID of the `Restream` to be enabled."
-    )))]
-    fn enable_restream(id: RestreamId, context: &Context) -> Option<bool> {
-        context.state().enable_restream(id)
-    }
-
-    /// Disables a `Restream` by its `id`.
+    /// Errors with `AMBIGUOUS_INPUT` if none or both of `push` and `pull` are
+    /// specified.
     ///
-    /// Disabled `Restream` stops all on-going re-streaming processes and is not
-    /// allowed to accept or pull a live stream.
+    /// Errors with `DUPLICATE_RESTREAM_KEY`, `DUPLICATE_ID` or
+    /// `QUOTA_EXCEEDED` the same way `Mutation.setRestream` does, along with
+    /// any validation error of the specified settings.
     ///
     /// ### Result
     ///
-    /// Returns `true` if a `Restream` with the given `id` has been disabled,
-    /// `false` if it has been disabled already, and `null` if it doesn't exist.
-    #[graphql(arguments(id(
-        description = "ID of the `Restream` to be disabled."
-    )))]
-    fn disable_restream(id: RestreamId, context: &Context) -> Option<bool> {
-        context.state().disable_restream(id)
+    /// Returns the created `Restream`.
+    #[graphql(arguments(
+        push(description = "`Input` awaiting a live stream pushed into it."),
+        pull(description = "`Input` pulling a live stream from a remote URL."),
+    ))]
+    async fn create_input(
+        push: Option<NewPushInput>,
+        pull: Option<NewPullInput>,
+        context: &Context,
+    ) -> Result<Restream, graphql::Error> {
+        context.require_writable()?;
+        let (low_latency, public, args) = match (push, pull) {
+            (Some(i), None) => (i.low_latency, i.public, i.into_args()),
+            (None, Some(i)) => (i.low_latency, i.public, i.into_args()),
+            _ => {
+                return Err(graphql::Error::new("AMBIGUOUS_INPUT")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(
+                        "Exactly one of `push` or `pull` must be specified",
+                    ))
+            }
+        };
+
+        let mut spec = restream_spec(args, context).await?;
+        spec.low_latency = low_latency.unwrap_or_default();
+        spec.public = public.unwrap_or_default();
+
+        let key = spec.key.clone();
+        context.state().add_restream(spec).map_err(rejected_error)?;
+
+        // `Restream.key`s are unique, so identify a new `Restream` well.
+        context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.key == key)
+            .cloned()
+            .ok_or_else(|| {
+                graphql::Error::new("UNKNOWN_RESTREAM")
+                    .status(StatusCode::NOT_FOUND)
+                    .message("Restream doesn't exist")
+            })
     }
 
-    /// Enables an `Input` by its `id`.
+    /// Sets IP ranges that external clients are allowed to publish a live
+    /// stream into the `Input` of the specified `Restream` from.
     ///
-    /// Enabled `Input` is allowed to accept or pull a live stream.
+    /// Publishing attempts from other IP addresses are rejected and recorded
+    /// into `Restream.connectionHistory`. Local clients are always allowed.
     ///
     /// ### Result
     ///
-    /// Returns `true` if an `Input` with the given `id` has been enabled,
-    /// `false` if it has been enabled already, and `null` if it doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, otherwise always returns `true`.
     #[graphql(arguments(
-        id(description = "ID of the `Input` to be enabled."),
-        restream_id(description = "ID of the `Restream` to enable the \
-                                   `Input` in."),
+        restream_id(
+            description = "ID of the `Restream` to set the allowlist of."
+        ),
+        ranges(description = "IP ranges in CIDR notation (like \
+                              `10.8.0.0/24`), or bare IP addresses.\
+                              \n\n\
+                              If empty, then publishing is allowed from \
+                              anywhere."),
     ))]
-    fn enable_input(
-        id: InputId,
+    fn set_publish_allowlist(
         restream_id: RestreamId,
+        ranges: Vec<String>,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().enable_input(id, restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let ranges = ranges
+            .iter()
+            .map(|r| {
+                r.parse::<IpRange>().map_err(|e| {
+                    graphql::Error::new("INVALID_IP_RANGE")
+                        .status(StatusCode::BAD_REQUEST)
+                        .message(&e)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut restreams = trace::lock_mut(
+            &context.state().restreams,
+            "set_publish_allowlist",
+        );
+        Ok(restreams.iter_mut().find(|r| r.id == restream_id).map(|r| {
+            r.publish_allowlist = ranges;
+            true
+        }))
     }
 
-    /// Disables an `Input` by its `id`.
+    /// Sets a recurring weekly schedule of the pulling `Input` of the
+    /// specified `Restream` (and its failover `Input`s), so it pulls a live
+    /// stream within the scheduled hours only.
     ///
-    /// Disabled `Input` stops all on-going re-streaming processes and is not
-    /// allowed to accept or pull a live stream.
+    /// Outside the schedule, the pulling is stopped and `InputEndpoint`s of
+    /// the `Input` have `Status.OUT_OF_SCHEDULE`, while inside it the pulling
+    /// resumes automatically. Disabled `Input` stays disabled regardless of
+    /// the schedule.
     ///
     /// ### Result
     ///
-    /// Returns `true` if an `Input` with the given `id` has been disabled,
-    /// `false` if it has been disabled already, and `null` if it doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if the schedule has been set already, otherwise `true`.
+    ///
+    /// Errors with `INVALID_SCHEDULE` if the `Restream` doesn't pull its live
+    /// stream.
+    ///
+    /// Errors with `INVALID_TIMEZONE` if the `timezone` is not a known IANA
+    /// time zone name.
     #[graphql(arguments(
-        id(description = "ID of the `Input` to be disabled."),
-        restream_id(description = "ID of the `Restream` to disable the \
-                                   `Input` in."),
+        restream_id(
+            description = "ID of the `Restream` to set the schedule of."
+        ),
+        ranges(description = "Ranges of time (in UTC, unless the `timezone` \
+                              is specified) to pull the live stream within.\
+                              \n\n\
+                              If empty, then the live stream is pulled at \
+                              any time."),
+        timezone(description = "IANA name of the time zone (like \
+                                `Europe/Kyiv`) of the `ranges`.\
+                                \n\n\
+                                The `ranges` are converted into UTC with \
+                                the current offset of the time zone, moving \
+                                to adjacent days if they cross midnight on \
+                                that, so are not shifted by DST transitions \
+                                happening later."),
     ))]
-    fn disable_input(
-        id: InputId,
+    fn set_input_schedule(
         restream_id: RestreamId,
+        ranges: Vec<NewScheduleRange>,
+        timezone: Option<String>,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().disable_input(id, restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let timezone = timezone.as_deref().map(time_zone).transpose()?;
+        let schedule = input_schedule(ranges, timezone.as_ref())?;
+
+        let mut restreams =
+            trace::lock_mut(&context.state().restreams, "set_input_schedule");
+        let input = match restreams.iter_mut().find(|r| r.id == restream_id) {
+            Some(r) => &mut r.input,
+            None => return Ok(None),
+        };
+        if !matches!(
+            input.src,
+            Some(InputSrc::Remote(_)) | Some(InputSrc::Failover(_)),
+        ) {
+            return Err(graphql::Error::new("INVALID_SCHEDULE")
+                .status(StatusCode::BAD_REQUEST)
+                .message("Only a pulling Input may have a schedule"));
+        }
+        Ok(Some(input.set_schedule(schedule)))
     }
 
-    /// Sets a new `Output` or updates an existing one (if `id` is specified).
-    ///
-    /// ### Idempotency
+    /// Sets additional options of pulling a live stream from a remote RTMP
+    /// `RemoteInputSrc.url` of the specified `Input`, required by some CDNs:
+    /// an explicit playpath, SWF player and web page URLs, and token query
+    /// parameters.
     ///
-    /// Idempotent if `id` is specified. Otherwise is non-idempotent, always
-    /// creates a new `Output` and errors on the `dst` duplicates within the
-    /// specified `Restream`.
+    /// Values of the token query parameters are masked once set.
     ///
     /// ### Result
     ///
-    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
-    /// exist, or an `Output` with the given `id` doesn't exist, otherwise
-    /// always returns `true`.
+    /// Returns `null` if an `Input` with the given `id` doesn't exist, `false`
+    /// if the options have been set already, otherwise `true`.
+    ///
+    /// Errors with `INVALID_RTMP_OPTIONS` if the `Input` doesn't pull its live
+    /// stream from a remote RTMP URL, or any of the options is invalid.
     #[graphql(arguments(
+        id(description = "ID of the `Input` to set the options of."),
         restream_id(
-            description = "ID of the `Restream` to add a new `Output` \
-                           to."
+            description = "ID of the `Restream` to set the options of \
+                                   the `Input` in."
         ),
-        dst(description = "Destination URL to re-stream a live stream onto.\
-                           \n\n\
-                           At the moment only [RTMP] and [Icecast] are \
-                           supported.\
-                           \n\n\
-                           [Icecast]: https://icecast.org\n\
-                           [RTMP]: https://en.wikipedia.org/wiki/\
-                                   Real-Time_Messaging_Protocol"),
-        label(description = "Optional label to add a new `Output` with."),
-        mixins(
-            description = "Optional `MixinSrcUrl`s to mix into this `Output`.",
-            default = Vec::new(),
-        ),
-        id(description = "ID of the `Output` to be updated rather than \
-                          creating a new one."),
+        options(description = "Options to be set.\
+                               \n\n\
+                               If all of them are empty, then the existing \
+                               options are removed."),
     ))]
-    fn set_output(
+    fn set_input_rtmp_options(
+        id: InputId,
         restream_id: RestreamId,
-        dst: OutputDstUrl,
-        label: Option<Label>,
-        preview_url: Option<Url>,
-        mixins: Vec<MixinSrcUrl>,
-        id: Option<OutputId>,
+        options: NewRtmpPullOptions,
         context: &Context,
     ) -> Result<Option<bool>, graphql::Error> {
-        if mixins.len() > 5 {
-            return Err(graphql::Error::new("TOO_MUCH_MIXIN_URLS")
-                .status(StatusCode::BAD_REQUEST)
-                .message("Maximum 5 mixing URLs are allowed"));
-        }
-        if !mixins.is_empty() {
-            let mut unique = HashSet::with_capacity(mixins.len());
-            for m in &mixins {
-                if let Some(dup) = unique.replace(m) {
-                    return Err(graphql::Error::new("DUPLICATE_MIXIN_URL")
-                        .status(StatusCode::BAD_REQUEST)
-                        .message(&format!(
-                            "Duplicate Output.mixin.src: {}",
-                            dup,
-                        )));
-                }
-            }
-            if mixins.iter().filter(|u| u.scheme() == "ts").take(2).count() > 1
-            {
-                return Err(graphql::Error::new(
-                    "TOO_MUCH_TEAMSPEAK_MIXIN_URLS",
-                )
-                .status(StatusCode::BAD_REQUEST)
-                .message("Only one TeamSpeak URL is allowed"));
-            }
-        }
-
-        let spec = spec::v1::Output {
-            dst,
-            label,
-            preview_url,
-            volume: Volume::ORIGIN,
-            mixins: mixins
-                .into_iter()
-                .map(|src| {
-                    let delay = (src.scheme() == "ts")
-                        .then(|| Delay::from_millis(3500))
-                        .flatten()
-                        .unwrap_or_default();
-                    spec::v1::Mixin {
-                        src,
-                        volume: Volume::ORIGIN,
-                        delay,
-                    }
-                })
-                .collect(),
-            enabled: false,
-        };
+        context.require_writable()?;
+        let options = rtmp_pull_options(options)?;
 
-        #[allow(clippy::option_if_let_else)] // due to consuming `spec`
-        Ok(if let Some(id) = id {
-            context.state().edit_output(restream_id, id, spec)
-        } else {
-            context.state().add_output(restream_id, spec)
-        }
-        .map_err(|e| {
-            graphql::Error::new("DUPLICATE_OUTPUT_URL")
-                .status(StatusCode::CONFLICT)
-                .message(&e)
-        })?
-        .map(|_| true))
+        context
+            .state()
+            .set_input_rtmp_options(id, restream_id, options)
+            .map_err(|e| {
+                graphql::Error::new("INVALID_RTMP_OPTIONS")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&e)
+            })
     }
 
-    /// Removes an `Output` by its `id` from the specified `Restream`.
+    /// Sets whether the live stream of the specified `Restream` should be
+    /// served with as low latency as possible, having no GOP cache, at the
+    /// cost of slower start of its players.
+    ///
+    /// Low-latency `Restream` is served on its dedicated SRS vhost (see
+    /// `Restream.srsVhost`), so its publishers should reconnect with the
+    /// changed URL. SRS is reloaded, without affecting other `Restream`s.
     ///
     /// ### Result
     ///
-    /// Returns `null` if the specified `Restream`/`Output` doesn't exist,
-    /// otherwise always returns `true`.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
     #[graphql(arguments(
-        id(description = "ID of the `Output` to be removed."),
-        restream_id(description = "ID of the `Restream` to remove the \
-                                   `Output` from."),
+        restream_id(description = "ID of the `Restream` to be tuned."),
+        low_latency(description = "Indicator whether the `Restream` should \
+                                   be served with low latency."),
     ))]
-    fn remove_output(
-        id: OutputId,
+    fn set_restream_low_latency(
         restream_id: RestreamId,
+        low_latency: bool,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().remove_output(id, restream_id).map(|_| true)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let mut restreams = trace::lock_mut(
+            &context.state().restreams,
+            "set_restream_low_latency",
+        );
+        Ok(restreams.iter_mut().find(|r| r.id == restream_id).map(|r| {
+            let changed = r.low_latency != low_latency;
+            r.low_latency = low_latency;
+            changed
+        }))
     }
 
-    /// Enables an `Output` by its `id` in the specified `Restream`.
+    /// Sets whether the live stream of the specified `Restream` is served via
+    /// HLS by SRS itself, so it can be watched over plain HTTP by the
+    /// `Restream.hlsUrl`.
     ///
-    /// Enabled `Output` starts re-streaming a live stream to its destination.
+    /// HLS `Restream` is served on its dedicated SRS vhost (see
+    /// `Restream.srsVhost`), so its publishers should reconnect with the
+    /// changed URL. SRS is reloaded, without affecting other `Restream`s. Once
+    /// disabled, the produced HLS files are reclaimed by sweeping.
     ///
     /// ### Result
     ///
-    /// Returns `true` if an `Output` with the given `id` has been enabled,
-    /// `false` if it has been enabled already, and `null` if the specified
-    /// `Restream`/`Output` doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
     #[graphql(arguments(
-        id(description = "ID of the `Output` to be enabled."),
-        restream_id(description = "ID of the `Restream` to enable the \
-                                   `Output` in."),
+        restream_id(description = "ID of the `Restream` to be tuned."),
+        hls(
+            description = "Indicator whether the `Restream` should be served \
+                           via HLS."
+        ),
     ))]
-    fn enable_output(
-        id: OutputId,
+    fn set_restream_hls(
         restream_id: RestreamId,
+        hls: bool,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().enable_output(id, restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_restream_hls(restream_id, hls))
     }
 
-    /// Disables an `Output` by its `id` in the specified `Restream`.
-    ///
-    /// Disabled `Output` stops re-streaming a live stream to its destination.
+    /// Sets whether the specified `Restream` is listed on the public status
+    /// page of this server, showing only its `label` and whether it's online
+    /// to anyone.
     ///
     /// ### Result
     ///
-    /// Returns `true` if an `Output` with the given `id` has been disabled,
-    /// `false` if it has been disabled already, and `null` if the specified
-    /// `Restream`/`Output` doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
     #[graphql(arguments(
-        id(description = "ID of the `Output` to be disabled."),
-        restream_id(description = "ID of the `Restream` to disable the \
-                                   `Output` in."),
+        restream_id(description = "ID of the `Restream` to be listed."),
+        public(description = "Indicator whether the `Restream` should be \
+                              listed on the public status page."),
     ))]
-    fn disable_output(
-        id: OutputId,
+    fn set_restream_public(
         restream_id: RestreamId,
+        public: bool,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().disable_output(id, restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let mut restreams =
+            trace::lock_mut(&context.state().restreams, "set_restream_public");
+        Ok(restreams.iter_mut().find(|r| r.id == restream_id).map(|r| {
+            let changed = r.public != public;
+            r.public = public;
+            changed
+        }))
     }
 
-    /// Enables all `Output`s in the specified `Restream`.
+    /// Advances the advisory "go live" `Workflow` of the specified `Restream`
+    /// to its next `WorkflowStage`, recording the moment it's reached.
     ///
-    /// Enabled `Output`s start re-streaming a live stream to their
-    /// destinations.
+    /// The `Workflow` only tracks the progress of operators, so never affects
+    /// re-streaming in any way.
     ///
     /// ### Result
     ///
-    /// Returns `true` if at least one `Output` has been enabled, `false` if all
-    /// `Output`s have been enabled already, and `null` if the specified
-    /// `Restream` doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if its `Workflow` is `WRAPPED` already, otherwise
+    /// `true`.
     #[graphql(arguments(restream_id(
-        description = "ID of the `Restream` to enable all `Output`s in."
+        description = "ID of the `Restream` to advance the `Workflow` of."
     )))]
-    fn enable_all_outputs(
+    fn advance_restream_workflow(
         restream_id: RestreamId,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().enable_all_outputs(restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().advance_restream_workflow(restream_id))
     }
 
-    /// Disables all `Output`s in the specified `Restream`.
-    ///
-    /// Disabled `Output`s stop re-streaming a live stream to their
-    /// destinations.
+    /// Resets the advisory "go live" `Workflow` of the specified `Restream`
+    /// back to `PLANNED`, forgetting all its `WorkflowStep`s.
     ///
     /// ### Result
     ///
-    /// Returns `true` if at least one `Output` has been disabled, `false` if
-    /// all `Output`s have been disabled already, and `null` if the specified
-    /// `Restream` doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if its `Workflow` has been reset already, otherwise
+    /// `true`.
     #[graphql(arguments(restream_id(
-        description = "ID of the `Restream` to disable all `Output`s in."
+        description = "ID of the `Restream` to reset the `Workflow` of."
     )))]
-    fn disable_all_outputs(
+    fn reset_restream_workflow(
         restream_id: RestreamId,
         context: &Context,
-    ) -> Option<bool> {
-        context.state().disable_all_outputs(restream_id)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().reset_restream_workflow(restream_id))
     }
 
-    /// Disables all `Output`s in all `Restream`s.
-    ///
-    /// Disabled `Output`s stop re-streaming a live stream to their
-    /// destinations.
+    /// Sets whether the advisory "go live" `Workflow` of the specified
+    /// `Restream` should be advanced from `PLANNED` to `INPUT_CONFIRMED`
+    /// automatically, once its `Input` serves a live stream.
     ///
     /// ### Result
     ///
-    /// Returns `true` if at least one `Output` has been disabled, `false` if
-    /// all `Output`s have been disabled already or there are no outputs
-    #[graphql(arguments())]
-    fn disable_all_outputs_of_restreams(context: &Context) -> bool {
-        context.state().disable_all_outputs_of_restreams()
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to set the `Workflow` of."
+        ),
+        auto_confirm_input(
+            description = "Indicator whether the `Input` should be confirmed \
+                           automatically."
+        ),
+    ))]
+    fn set_restream_workflow_auto_confirm(
+        restream_id: RestreamId,
+        auto_confirm_input: bool,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_restream_workflow_auto_confirm(
+            restream_id,
+            auto_confirm_input,
+        ))
     }
 
-    /// Enables all `Output`s in all `Restream`s.
-    ///
-    /// Enabled `Output`s start re-streaming a live stream to their
-    /// destinations.
+    /// Sets whether the re-streaming processes of the specified `Restream` are
+    /// never cycled automatically within the maintenance window of this
+    /// server (see `--maintenance-window` CLI option).
     ///
     /// ### Result
     ///
-    /// Returns `true` if at least one `Output` has been enabled, `false` if all
-    /// `Output`s have been enabled already or there are no outputs
-    #[graphql(arguments())]
-    fn enables_all_outputs_of_restreams(context: &Context) -> bool {
-        context.state().enable_all_outputs_of_restreams()
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to be tuned."),
+        no_auto_cycle(description = "Indicator whether the re-streaming \
+                                     processes of the `Restream` should never \
+                                     be cycled automatically."),
+    ))]
+    fn set_restream_no_auto_cycle(
+        restream_id: RestreamId,
+        no_auto_cycle: bool,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .set_restream_no_auto_cycle(restream_id, no_auto_cycle))
     }
 
-    /// Tunes a `Volume` rate of the specified `Output` or one of its `Mixin`s.
+    /// Sets whether the copy-only `Output`s of the specified `Restream` are
+    /// re-streamed by a single FFmpeg process (via its `tee` muxer), rather
+    /// than by a separate process each.
+    ///
+    /// Consolidating saves CPU and memory on `Restream`s with many
+    /// destinations. Failures of a single destination are still reported on
+    /// its own `Output`, but enabling or disabling any consolidated `Output`
+    /// restarts the shared process, briefly interrupting the others.
     ///
     /// ### Result
     ///
-    /// Returns `true` if a `Volume` rate has been changed, `false` if it has
-    /// the same value already, or `null` if the specified `Output` or `Mixin`
-    /// doesn't exist.
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, `false` if it has been set already, otherwise `true`.
     #[graphql(arguments(
-        restream_id(description = "ID of the `Restream` to tune the \
-                                   `Output` in."),
-        output_id(description = "ID of the tuned `Output`."),
-        mixin_id(description = "Optional ID of the tuned `Mixin`.\
-                                \n\n\
-                                If set, then tunes the `Mixin` rather than \
-                                the `Output`."),
-        volume(description = "Volume rate in percents to be set."),
+        restream_id(description = "ID of the `Restream` to be tuned."),
+        consolidate_outputs(description = "Indicator whether the copy-only \
+                                           `Output`s of the `Restream` \
+                                           should be re-streamed by a single \
+                                           process."),
     ))]
-    fn tune_volume(
+    fn set_restream_consolidate_outputs(
         restream_id: RestreamId,
-        output_id: OutputId,
-        mixin_id: Option<MixinId>,
-        volume: Volume,
+        consolidate_outputs: bool,
         context: &Context,
-    ) -> Option<bool> {
-        context
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
             .state()
-            .tune_volume(restream_id, output_id, mixin_id, volume)
+            .set_restream_consolidate_outputs(restream_id, consolidate_outputs))
     }
 
-    /// Tunes a `Delay` of the specified `Mixin` before mix it into its
-    /// `Output`.
+    /// Removes a `Restream` by its `id` into the trash (see
+    /// `Query.trashedRestreams`), so it may be restored later.
+    ///
+    /// Refuses to remove the `Restream` being live (its `Input` serves a live
+    /// stream, or any of its `Output`s is `Status.ONLINE`) with a `LIVE` error,
+    /// unless `force` is specified.
     ///
     /// ### Result
     ///
-    /// Returns `true` if a `Delay` has been changed, `false` if it has the same
-    /// value already, or `null` if the specified `Output` or `Mixin` doesn't
-    /// exist.
+    /// Returns `null` if `Restream` with the given `id` doesn't exist,
+    /// otherwise always returns `true`.
     #[graphql(arguments(
-        restream_id(description = "ID of the `Restream` to tune the the \
-                                   `Mixin` in."),
-        output_id(description = "ID of the `Output` of the tuned `Mixin`."),
-        mixin_id(description = "ID of the tuned `Mixin`."),
-        delay(description = "Number of milliseconds to delay the `Mixin` \
-                             before mix it into its `Output`."),
+        id(description = "ID of the `Restream` to be removed."),
+        force(
+            description = "Indicator whether the `Restream` should be \
+                           removed even if it's live at the moment.",
+            default = false,
+        ),
     ))]
-    fn tune_delay(
-        restream_id: RestreamId,
-        output_id: OutputId,
-        mixin_id: MixinId,
-        delay: Delay,
+    fn remove_restream(
+        id: RestreamId,
+        force: bool,
         context: &Context,
-    ) -> Option<bool> {
-        context
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
             .state()
-            .tune_delay(restream_id, output_id, mixin_id, delay)
+            .remove_restream(id, force)
+            .map_err(|e| {
+                graphql::Error::new("LIVE")
+                    .status(StatusCode::CONFLICT)
+                    .message(&e)
+            })?
+            .map(|_| true))
     }
 
-    /// Removes the specified recorded file.
+    /// Restores a `Restream` by its `id` from the trash (see
+    /// `Query.trashedRestreams`).
+    ///
+    /// Errors with `DUPLICATE_RESTREAM_KEY` if another `Restream` with the same
+    /// `key` has been added since the removal.
+    ///
+    /// Errors with `QUOTA_EXCEEDED` if the `Restream` exceeds the maximum
+    /// number of `Restream`s or `Output`s allowed on this server, reporting
+    /// the limit via `extensions.limit`.
     ///
     /// ### Result
     ///
-    /// Returns `true` if the specified recorded file was removed, otherwise
-    /// `false` if nothing changes.
-    #[graphql(arguments(path(
-        description = "Relative path of the recorded file to be removed.\
-                       \n\n\
-                       Use the exact value returned by `Query.dvrFiles`."
+    /// Returns `null` if there is no `Restream` with the given `id` in the
+    /// trash, otherwise always returns `true`.
+    #[graphql(arguments(id(
+        description = "ID of the `Restream` to be restored."
     )))]
-    async fn remove_dvr_file(path: String) -> Result<bool, graphql::Error> {
-        if path.starts_with('/') || path.contains("../") {
-            return Err(graphql::Error::new("INVALID_DVR_FILE_PATH")
-                .status(StatusCode::BAD_REQUEST)
-                .message(&format!("Invalid DVR file path: {}", path)));
-        }
+    fn restore_restream(
+        id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .restore_restream(id)
+            .map_err(rejected_error)?
+            .map(|_| true))
+    }
 
-        Ok(dvr::Storage::global().remove_file(path).await)
+    /// Purges a `Restream` by its `id` from the trash (see
+    /// `Query.trashedRestreams`), along with its recorded files, so it cannot
+    /// be restored anymore.
+    ///
+    /// ### Result
+    ///
+    /// Returns `null` if there is no `Restream` with the given `id` in the
+    /// trash, otherwise always returns `true`.
+    #[graphql(arguments(id(
+        description = "ID of the `Restream` to be purged."
+    )))]
+    fn purge_restream(
+        id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().purge_restream(id).map(|_| true))
     }
 
-    /// Sets or unsets the password to protect this GraphQL API with.
+    /// Enables a `Restream` by its `id`.
     ///
-    /// Once password is set, any subsequent requests to this GraphQL API should
-    /// perform [HTTP Basic auth][1], where any username is allowed, but the
-    /// password should match the one being set.
+    /// Enabled `Restream` is allowed to accept or pull a live stream.
     ///
     /// ### Result
     ///
-    /// Returns `true` if password has been changed or unset, otherwise `false`
-    /// if nothing changes.
-    ///
-    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
-    #[graphql(arguments(
-        new(
-            description = "New password to be set. In `null` then unsets the \
-                           current password."
-        ),
-        old(description = "Old password for authorization, if it was set \
-                           previously."),
-    ))]
-    fn set_password(
-        new: Option<String>,
-        old: Option<String>,
+    /// Returns `true` if a `Restream` with the given `id` has been enabled,
+    /// `false` if it has been enabled already, and `null` if it doesn't exist.
+    #[graphql(arguments(id(
+        description = "ID of the `Restream` to be enabled."
+    )))]
+    fn enable_restream(
+        id: RestreamId,
         context: &Context,
-    ) -> Result<bool, graphql::Error> {
-        static HASH_CFG: Lazy<argon2::Config<'static>> =
-            Lazy::new(argon2::Config::default);
-
-        let mut settings = context.state().settings.lock_mut();
-
-        if let Some(hash) = &settings.password_hash {
-            match old {
-                None => {
-                    return Err(graphql::Error::new("NO_OLD_PASSWORD")
-                        .status(StatusCode::FORBIDDEN)
-                        .message("Old password required for this action"))
-                }
-                Some(pass) => {
-                    if !argon2::verify_encoded(hash, pass.as_bytes()).unwrap() {
-                        return Err(graphql::Error::new("WRONG_OLD_PASSWORD")
-                            .status(StatusCode::FORBIDDEN)
-                            .message("Wrong old password specified"));
-                    }
-                }
-            }
-        }
-
-        if settings.password_hash.is_none() && new.is_none() {
-            return Ok(false);
-        }
-
-        settings.password_hash = new.map(|v| {
-            argon2::hash_encoded(
-                v.as_bytes(),
-                &rand::thread_rng().gen::<[u8; 32]>(),
-                &*HASH_CFG,
-            )
-            .unwrap()
-        });
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_restream(id))
+    }
 
-        Ok(true)
+    /// Disables a `Restream` by its `id`.
+    ///
+    /// Disabled `Restream` stops all on-going re-streaming processes and is not
+    /// allowed to accept or pull a live stream.
+    ///
+    /// Records a `MANUAL` `Input.disabledReason`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if a `Restream` with the given `id` has been disabled,
+    /// `false` if it has been disabled already, and `null` if it doesn't exist.
+    #[graphql(arguments(id(
+        description = "ID of the `Restream` to be disabled."
+    )))]
+    fn disable_restream(
+        id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .disable_restream(id, DisableReason::manual("GraphQL API")))
     }
 
-    /// Sets settings of the server
+    /// Enables an `Input` by its `id`.
+    ///
+    /// Enabled `Input` is allowed to accept or pull a live stream.
     ///
     /// ### Result
     ///
-    /// Returns `false` if title does not pass validation for max allowed
-    /// characters length. Otherwise returns `true`
+    /// Returns `true` if an `Input` with the given `id` has been enabled,
+    /// `false` if it has been enabled already, and `null` if it doesn't exist.
     #[graphql(arguments(
-        title(description = "Title for the server"),
-        delete_confirmation(
-            description = "Whether do we need to confirm deletion of inputs \
-            and outputs"
-        ),
-        enable_confirmation(
-            description = "Whether do we need to confirm enabling/disabling of \
-            inputs or outputs"
-        )
+        id(description = "ID of the `Input` to be enabled."),
+        restream_id(description = "ID of the `Restream` to enable the \
+                                   `Input` in."),
     ))]
-    fn set_settings(
-        title: Option<String>,
-        delete_confirmation: Option<bool>,
-        enable_confirmation: Option<bool>,
+    fn enable_input(
+        id: InputId,
+        restream_id: RestreamId,
         context: &Context,
-    ) -> Result<bool, graphql::Error> {
-        // Validate title
-        let value = title.unwrap_or_default();
-        if value.len() > 70 {
-            return Err(graphql::Error::new("WRONG_TITLE_LENGTH")
-                .status(StatusCode::BAD_REQUEST)
-                .message("Title exceeds max allowed length of 70 characters"));
-        }
-
-        let mut settings = context.state().settings.lock_mut();
-        settings.title = Some(value);
-        settings.delete_confirmation = delete_confirmation;
-        settings.enable_confirmation = enable_confirmation;
-        Ok(true)
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_input(id, restream_id))
     }
-}
 
-/// Root of all [GraphQL queries][1] in the [`Schema`].
-///
-/// [1]: https://spec.graphql.org/June2018/#sec-Root-Operation-Types
-#[derive(Clone, Copy, Debug)]
-pub struct QueriesRoot;
-
-#[graphql_object(name = "Query", context = Context)]
-impl QueriesRoot {
-    /// Returns the current `Info` parameters of this server.
-    fn info(context: &Context) -> Info {
-        let settings = context.state().settings.get_cloned();
-        Info {
-            public_host: context.config().public_host.clone().unwrap(),
-            password_hash: settings.password_hash,
-            title: settings.title,
-            delete_confirmation: settings.delete_confirmation,
-            enable_confirmation: settings.enable_confirmation,
-        }
-    }
-
-    /// Returns all the `Restream`s happening on this server.
-    fn all_restreams(context: &Context) -> Vec<Restream> {
-        context.state().restreams.get_cloned()
+    /// Disables an `Input` by its `id`.
+    ///
+    /// Disabled `Input` stops all on-going re-streaming processes and is not
+    /// allowed to accept or pull a live stream.
+    ///
+    /// Records a `MANUAL` `Input.disabledReason`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if an `Input` with the given `id` has been disabled,
+    /// `false` if it has been disabled already, and `null` if it doesn't exist.
+    #[graphql(arguments(
+        id(description = "ID of the `Input` to be disabled."),
+        restream_id(description = "ID of the `Restream` to disable the \
+                                   `Input` in."),
+    ))]
+    fn disable_input(
+        id: InputId,
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().disable_input(
+            id,
+            restream_id,
+            DisableReason::manual("GraphQL API"),
+        ))
     }
 
-    /// Returns list of recorded files of the specified `Output`.
+    /// Sets a new `Output` or updates an existing one (if `id` is specified).
     ///
-    /// If returned list is empty, the there is no recorded files for the
-    /// specified `Output`.
+    /// ### Idempotency
     ///
-    /// Each recorded file is represented as a relative path on [SRS] HTTP
-    /// server in `dvr/` directory, so the download link should look like this:
-    /// ```ignore
-    /// http://my.host:8080/dvr/returned/file/path.flv
-    /// ```
+    /// Idempotent if `id` is specified. Otherwise is non-idempotent, always
+    /// creates a new `Output` and errors on the `dst` duplicates within the
+    /// specified `Restream`.
     ///
-    /// [SRS]: https://github.com/ossrs/srs
-    #[graphql(arguments(id(
-        description = "ID of the `Output` to return recorded files of."
-    )))]
-    async fn dvr_files(id: OutputId) -> Vec<String> {
-        dvr::Storage::global().list_files(id).await
+    /// The `dst` used already by `Output`s of other `Restream`s is allowed,
+    /// but reported via `Output.conflictsWith`, unless rejected by
+    /// `Mutation.setRejectDstConflicts`.
+    ///
+    /// ### Errors
+    ///
+    /// Errors with `DUPLICATE_OUTPUT_URL` if the `dst` is used already,
+    /// pointing to the conflicting `Output` via `extensions.outputId` (and
+    /// `extensions.outputLabel`, if it has one).
+    ///
+    /// Errors with `CONFLICTING_OUTPUT_URL` if the `dst` is used already by
+    /// another `Restream` while such conflicts are rejected, pointing to the
+    /// conflicting `Output` via `extensions.restreamId` and
+    /// `extensions.outputId` (and `extensions.outputLabel`, if it has one).
+    ///
+    /// Errors with `DUPLICATE_ID` if the `newId` is used already, repeating it
+    /// via `extensions.id`.
+    ///
+    /// Errors with `UNREACHABLE` if the `dst` is requested to be verified, but
+    /// is unreachable, specifying the failed stage via `extensions.reason`
+    /// (see `UrlProbe.reason`), or with `RATE_LIMITED` if too many probes have
+    /// been performed recently.
+    ///
+    /// Errors with `QUOTA_EXCEEDED` if a new `Output` exceeds the maximum
+    /// number of `Output`s allowed in a single `Restream` on this server,
+    /// reporting the limit via `extensions.limit`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `null` if a `Restream` with the given `restreamId` doesn't
+    /// exist, or an `Output` with the given `id` doesn't exist, otherwise
+    /// always returns `true`.
+    #[graphql(
+        deprecated = "Use `Mutation.createOutput` for creating an `Output` and \
+                      `Mutation.setOutputV2` for updating it.",
+        arguments(
+            restream_id(
+                description = "ID of the `Restream` to add a new `Output` \
+                               to."
+            ),
+            dst(description = "Destination URL to re-stream a live stream onto.\
+                               \n\n\
+                               At the moment only [RTMP] and [Icecast] are \
+                               supported.\
+                               \n\n\
+                               [Icecast]: https://icecast.org\n\
+                               [RTMP]: https://en.wikipedia.org/wiki/\
+                                       Real-Time_Messaging_Protocol"),
+            label(description = "Optional label to add a new `Output` with."),
+            preview_url(
+                description = "Optional URL of the `Output`'s live stream \
+                               preview."
+            ),
+            mixins(
+                description = "Optional `MixinSrcUrl`s to mix into this \
+                               `Output`.",
+                default = Vec::new(),
+            ),
+            id(description = "ID of the `Output` to be updated rather than \
+                              creating a new one."),
+            new_id(description = "ID to create a new `Output` with, instead of \
+                                  a random one.\
+                                  \n\n\
+                                  Cannot be specified along with `id`."),
+            verify(
+                description = "Indicator whether the `dst` should be probed \
+                               for reachability before accepting it (see \
+                               `Query.probeUrl`).",
+                default = false,
+            ),
+        ),
+    )]
+    async fn set_output(
+        restream_id: RestreamId,
+        dst: OutputDstUrl,
+        label: Option<Label>,
+        preview_url: Option<Url>,
+        mixins: Vec<MixinSrcUrl>,
+        id: Option<OutputId>,
+        new_id: Option<OutputId>,
+        verify: bool,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let spec = output_spec(
+            OutputArgs {
+                restream_id,
+                dst,
+                label,
+                preview_url,
+                mixins,
+                id,
+                new_id,
+                verify,
+            },
+            context,
+        )
+        .await?;
+
+        #[allow(clippy::option_if_let_else)] // due to consuming `spec`
+        Ok(if let Some(id) = id {
+            context
+                .state()
+                .edit_output(restream_id, id, spec)
+                .map_err(duplicate_error)?
+        } else {
+            context
+                .state()
+                .add_output(restream_id, spec)
+                .map_err(rejected_error)?
+        }
+        .map(|_| true))
     }
 
-    /// Returns `Restream`s happening on this server and identifiable by the
-    /// given `ids` in an exportable JSON format.
+    /// Sets a new `Output` or updates an existing one (if `id` is specified),
+    /// exactly as `Mutation.setOutput` does, taking the same arguments.
     ///
-    /// If no `ids` specified, then returns all the `Restream`s happening on
-    /// this server at the moment.
-    #[graphql(arguments(ids(
-        description = "IDs of `Restream`s to be exported.\
-                       \n\n\
-                       If empty, then all the `Restream`s will be exported."
-        default = Vec::new(),
-    )))]
-    fn export(
-        ids: Vec<RestreamId>,
+    /// ### Result
+    ///
+    /// Returns the set `Output`, so its generated ID (and its
+    /// `Output.conflictsWith`) is known without re-querying.
+    ///
+    /// Errors with `UNKNOWN_OUTPUT` if a `Restream` with the given
+    /// `restreamId` doesn't exist, or an `Output` with the given `id` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to add a new `Output` \
+                           to."
+        ),
+        dst(description = "Destination URL to re-stream a live stream onto.\
+                           \n\n\
+                           At the moment only [RTMP] and [Icecast] are \
+                           supported.\
+                           \n\n\
+                           [Icecast]: https://icecast.org\n\
+                           [RTMP]: https://en.wikipedia.org/wiki/\
+                                   Real-Time_Messaging_Protocol"),
+        label(description = "Optional label to add a new `Output` with."),
+        preview_url(
+            description = "Optional URL of the `Output`'s live stream \
+                           preview."
+        ),
+        mixins(
+            description = "Optional `MixinSrcUrl`s to mix into this \
+                           `Output`.",
+            default = Vec::new(),
+        ),
+        id(description = "ID of the `Output` to be updated rather than \
+                          creating a new one."),
+        new_id(description = "ID to create a new `Output` with, instead of \
+                              a random one.\
+                              \n\n\
+                              Cannot be specified along with `id`."),
+        verify(
+            description = "Indicator whether the `dst` should be probed \
+                           for reachability before accepting it (see \
+                           `Query.probeUrl`).",
+            default = false,
+        ),
+    ))]
+    async fn set_output_v2(
+        restream_id: RestreamId,
+        dst: OutputDstUrl,
+        label: Option<Label>,
+        preview_url: Option<Url>,
+        mixins: Vec<MixinSrcUrl>,
+        id: Option<OutputId>,
+        new_id: Option<OutputId>,
+        verify: bool,
         context: &Context,
-    ) -> Result<Option<String>, graphql::Error> {
-        let settings = context.state().settings.get_cloned().export();
-        let restreams = context
+    ) -> Result<Output, graphql::Error> {
+        let not_found = || {
+            graphql::Error::new("UNKNOWN_OUTPUT")
+                .status(StatusCode::NOT_FOUND)
+                .message("Restream or Output doesn't exist")
+        };
+        let _ = Self::set_output(
+            restream_id,
+            dst.clone(),
+            label,
+            preview_url,
+            mixins,
+            id,
+            new_id,
+            verify,
+            context,
+        )
+        .await?
+        .ok_or_else(not_found)?;
+
+        // `Output.dst`s are unique within a `Restream`, so identify a new
+        // `Output` well.
+        context
             .state()
             .restreams
-            .get_cloned()
-            .into_iter()
-            .filter_map(|r| {
-                (ids.is_empty() || ids.contains(&r.id)).then(|| r.export())
-            })
-            .collect::<Vec<_>>();
-        (!restreams.is_empty())
-            .then(|| {
-                let spec: Spec = spec::v1::Spec {
-                    settings: Some(settings),
-                    restreams,
-                }
-                .into();
-                serde_json::to_string(&spec).map_err(|e| {
-                    anyhow!("Failed to JSON-serialize spec: {}", e).into()
-                })
+            .lock_ref()
+            .iter()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| {
+                r.outputs
+                    .iter()
+                    .find(|o| id.map_or_else(|| o.dst == dst, |id| o.id == id))
             })
-            .transpose()
+            .cloned()
+            .ok_or_else(not_found)
     }
-}
 
-/// Root of all [GraphQL subscriptions][1] in the [`Schema`].
-///
-/// [1]: https://spec.graphql.org/June2018/#sec-Root-Operation-Types
-#[derive(Clone, Copy, Debug)]
-pub struct SubscriptionsRoot;
+    /// Creates a new `Output` in the specified `Restream`, setting all the
+    /// specified optional settings at once.
+    ///
+    /// Unlike a sequence of `Mutation.setOutput` and dedicated setters, the
+    /// `Output` appears with all its settings applied, or doesn't appear at
+    /// all, if any of them is invalid. So, an `Output` created enabled starts
+    /// re-streaming with its final settings right away.
+    ///
+    /// ### Errors
+    ///
+    /// Errors with `UNKNOWN_RESTREAM` if a `Restream` with the given
+    /// `restreamId` doesn't exist.
+    ///
+    /// Errors with `DUPLICATE_OUTPUT_URL`, `CONFLICTING_OUTPUT_URL`,
+    /// `DUPLICATE_ID`, `UNREACHABLE` or `QUOTA_EXCEEDED` the same way
+    /// `Mutation.setOutput` does, along with any validation error of the
+    /// specified settings.
+    ///
+    /// Errors with `UNKNOWN_STREAM` if the `Restream` has no `Input` with the
+    /// specified `NewOutput.srcStream` key.
+    ///
+    /// ### Result
+    ///
+    /// Returns the created `Output`.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to add a new `Output` \
+                           to."
+        ),
+        output(description = "`Output` to be created."),
+    ))]
+    async fn create_output(
+        restream_id: RestreamId,
+        output: NewOutput,
+        context: &Context,
+    ) -> Result<Output, graphql::Error> {
+        context.require_writable()?;
+        let not_found = || {
+            graphql::Error::new("UNKNOWN_RESTREAM")
+                .status(StatusCode::NOT_FOUND)
+                .message("Restream doesn't exist")
+        };
+        let NewOutput {
+            dst,
+            label,
+            preview_url,
+            mixins,
+            id,
+            verify,
+            backup_dst,
+            conditioning,
+            audio_sample_rate,
+            metadata,
+            loudnorm,
+            limits,
+            src_stream,
+            delay_secs,
+            max_bitrate_kbps,
+            prewarm,
+            prewarm_strategy,
+            drop_frames_when_behind,
+            on_boot,
+            enabled,
+        } = output;
+
+        if let Some(backup) = &backup_dst {
+            check_backup_dst(Some(&dst), backup)?;
+        }
+        let delay_secs = delay_secs.map(output_delay).transpose()?;
+        let max_bitrate_kbps =
+            max_bitrate_kbps.map(output_bitrate).transpose()?;
+        let metadata = metadata
+            .map(|m| OutputMetadata::new(m.title, m.author, m.comment))
+            .transpose()?
+            .unwrap_or_default();
+        let loudnorm = loudnorm
+            .map(|l| {
+                loudness_target(
+                    l.integrated,
+                    l.true_peak.unwrap_or(LoudnessTarget::DEFAULT_TRUE_PEAK),
+                )
+            })
+            .transpose()?;
+        let limits = limits
+            .map(|l| output_limits(l.nice, l.cpu_quota))
+            .transpose()?
+            .flatten();
+
+        let mut spec = output_spec(
+            OutputArgs {
+                restream_id,
+                dst: dst.clone(),
+                label,
+                preview_url,
+                mixins: mixins.unwrap_or_default(),
+                id: None,
+                new_id: id,
+                verify: verify.unwrap_or_default(),
+            },
+            context,
+        )
+        .await?;
+        spec.backup_dst = backup_dst;
+        spec.conditioning = conditioning.map(|preset| OutputConditioning {
+            preset,
+            audio_sample_rate,
+        });
+        spec.metadata = metadata;
+        spec.loudnorm = loudnorm;
+        spec.limits = limits;
+        spec.src_stream = src_stream;
+        spec.delay_secs = delay_secs;
+        spec.max_bitrate_kbps = max_bitrate_kbps;
+        spec.prewarm = prewarm.unwrap_or_default();
+        spec.prewarm_strategy = prewarm_strategy.unwrap_or_default();
+        spec.drop_frames_when_behind =
+            drop_frames_when_behind.unwrap_or_default();
+        spec.on_boot = on_boot.unwrap_or_default();
+        spec.enabled = enabled.unwrap_or_default();
 
-#[graphql_subscription(name = "Subscription", context = Context)]
-impl SubscriptionsRoot {
-    /// Subscribes to updates of `Info` parameters of this server.
-    async fn info(context: &Context) -> BoxStream<'static, Info> {
-        let public_host = context.config().public_host.clone().unwrap();
         context
             .state()
-            .settings
-            .signal_cloned()
-            .dedupe_cloned()
-            .map(move |h| Info {
-                public_host: public_host.clone(),
-                password_hash: h.password_hash,
-                title: h.title,
-                delete_confirmation: h.delete_confirmation,
-                enable_confirmation: h.enable_confirmation,
-            })
-            .to_stream()
-            .boxed()
-    }
+            .add_output(restream_id, spec)
+            .map_err(rejected_error)?
+            .ok_or_else(not_found)?;
 
-    /// Subscribes to updates of all `Restream`s happening on this server.
-    async fn all_restreams(
-        context: &Context,
-    ) -> BoxStream<'static, Vec<Restream>> {
+        // `Output.dst`s are unique within a `Restream`, so identify a new
+        // `Output` well.
         context
             .state()
             .restreams
-            .signal_cloned()
-            .dedupe_cloned()
-            .to_stream()
-            .boxed()
+            .lock_ref()
+            .iter()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter().find(|o| o.dst == dst))
+            .cloned()
+            .ok_or_else(not_found)
     }
-}
 
-/// Information about parameters that this server operates with.
-#[derive(Clone, Debug, GraphQLObject)]
-pub struct Info {
-    /// Host that this server is reachable via in public.
+    /// Removes an `Output` by its `id` from the specified `Restream`.
     ///
-    /// Use it for constructing URLs to this server.
-    pub public_host: String,
-
-    /// Title of the server
-    pub title: Option<String>,
-
-    /// Whether do we need to confirm deletion of inputs and outputs
-    pub delete_confirmation: Option<bool>,
-
-    /// Whether do we need to confirm enabling/disabling of inputs or outputs
-    pub enable_confirmation: Option<bool>,
+    /// Refuses to remove the `Output` being `Status.ONLINE` with a `LIVE`
+    /// error, unless `force` is specified.
+    ///
+    /// ### Result
+    ///
+    /// Returns `null` if the specified `Restream`/`Output` doesn't exist,
+    /// otherwise always returns `true`.
+    #[graphql(arguments(
+        id(description = "ID of the `Output` to be removed."),
+        restream_id(description = "ID of the `Restream` to remove the \
+                                   `Output` from."),
+        force(
+            description = "Indicator whether the `Output` should be removed \
+                           even if it's live at the moment.",
+            default = false,
+        ),
+    ))]
+    fn remove_output(
+        id: OutputId,
+        restream_id: RestreamId,
+        force: bool,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .remove_output(id, restream_id, force)
+            .map_err(|e| {
+                graphql::Error::new("LIVE")
+                    .status(StatusCode::CONFLICT)
+                    .message(&e)
+            })?
+            .map(|_| true))
+    }
 
-    /// [Argon2] hash of the password that this server's GraphQL API is
-    /// protected with, if any.
+    /// Enables an `Output` by its `id` in the specified `Restream`.
     ///
-    /// Non-`null` value means that any request to GraphQL API should perform
-    /// [HTTP Basic auth][1]. Any username is allowed, but the password should
-    /// match this hash.
+    /// Enabled `Output` starts re-streaming a live stream to its destination.
     ///
-    /// [Argon2]: https://en.wikipedia.org/wiki/Argon2
-    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
-    pub password_hash: Option<String>,
+    /// ### Result
+    ///
+    /// Returns `true` if an `Output` with the given `id` has been enabled,
+    /// `false` if it has been enabled already, and `null` if the specified
+    /// `Restream`/`Output` doesn't exist.
+    #[graphql(arguments(
+        id(description = "ID of the `Output` to be enabled."),
+        restream_id(description = "ID of the `Restream` to enable the \
+                                   `Output` in."),
+    ))]
+    fn enable_output(
+        id: OutputId,
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_output(id, restream_id))
+    }
+
+    /// Disables an `Output` by its `id` in the specified `Restream`.
+    ///
+    /// Disabled `Output` stops re-streaming a live stream to its destination.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if an `Output` with the given `id` has been disabled,
+    /// `false` if it has been disabled already, and `null` if the specified
+    /// `Restream`/`Output` doesn't exist.
+    #[graphql(arguments(
+        id(description = "ID of the `Output` to be disabled."),
+        restream_id(description = "ID of the `Restream` to disable the \
+                                   `Output` in."),
+    ))]
+    fn disable_output(
+        id: OutputId,
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().disable_output(id, restream_id))
+    }
+
+    /// Pauses an `Output` by its `id` in the specified `Restream`.
+    ///
+    /// Paused `Output` re-streams a slate (black frame with silent audio)
+    /// instead of its live stream, while staying connected to its destination.
+    /// Depending on `Output.pauseMode`, it's switched to the slate either in
+    /// real-time, or by a fast restart of its re-streaming process (for an
+    /// `Output` copying its video "as is", as such video cannot be switched in
+    /// real-time), so its destination sees a short reconnection.
+    ///
+    /// Pausing is not persisted, so is reset on the server restart.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if an `Output` with the given `id` has been paused,
+    /// `false` if it has been paused already, and `null` if the specified
+    /// `Restream`/`Output` doesn't exist.
+    #[graphql(arguments(
+        id(description = "ID of the `Output` to be paused."),
+        restream_id(description = "ID of the `Restream` to pause the \
+                                   `Output` in."),
+    ))]
+    fn pause_output(
+        id: OutputId,
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_paused(id, restream_id, true))
+    }
+
+    /// Resumes a paused `Output` by its `id` in the specified `Restream`.
+    ///
+    /// Resumed `Output` re-streams its live stream again, in the same way
+    /// (`Output.pauseMode`) it has been paused.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if an `Output` with the given `id` has been resumed,
+    /// `false` if it hasn't been paused, and `null` if the specified
+    /// `Restream`/`Output` doesn't exist.
+    #[graphql(arguments(
+        id(description = "ID of the `Output` to be resumed."),
+        restream_id(description = "ID of the `Restream` to resume the \
+                                   `Output` in."),
+    ))]
+    fn resume_output(
+        id: OutputId,
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_paused(id, restream_id, false))
+    }
+
+    /// Enables all `Output`s in the specified `Restream`.
+    ///
+    /// Enabled `Output`s start re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been enabled, `false` if all
+    /// `Output`s have been enabled already, and `null` if the specified
+    /// `Restream` doesn't exist.
+    #[graphql(arguments(restream_id(
+        description = "ID of the `Restream` to enable all `Output`s in."
+    )))]
+    fn enable_all_outputs(
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_all_outputs(restream_id))
+    }
+
+    /// Disables all `Output`s in the specified `Restream`.
+    ///
+    /// Disabled `Output`s stop re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been disabled, `false` if
+    /// all `Output`s have been disabled already, and `null` if the specified
+    /// `Restream` doesn't exist.
+    #[graphql(arguments(restream_id(
+        description = "ID of the `Restream` to disable all `Output`s in."
+    )))]
+    fn disable_all_outputs(
+        restream_id: RestreamId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().disable_all_outputs(restream_id))
+    }
+
+    /// Disables all `Output`s in all `Restream`s.
+    ///
+    /// Disabled `Output`s stop re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been disabled, `false` if
+    /// all `Output`s have been disabled already or there are no outputs
+    #[graphql(arguments())]
+    fn disable_all_outputs_of_restreams(
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().disable_all_outputs_of_restreams())
+    }
+
+    /// Enables all `Output`s in all `Restream`s.
+    ///
+    /// Enabled `Output`s start re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been enabled, `false` if all
+    /// `Output`s have been enabled already or there are no outputs
+    #[graphql(arguments())]
+    fn enables_all_outputs_of_restreams(
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_all_outputs_of_restreams())
+    }
+
+    /// Enables all `Output`s in all `Restream`s of the specified group.
+    ///
+    /// Enabled `Output`s start re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been enabled, `false` if all
+    /// `Output`s have been enabled already, and `null` if no `Restream`
+    /// belongs to the specified group.
+    #[graphql(arguments(group(
+        description = "Group of the `Restream`s to enable all `Output`s in."
+    )))]
+    fn enable_group_outputs(
+        group: RestreamGroup,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().enable_group_outputs(&group))
+    }
+
+    /// Disables all `Output`s in all `Restream`s of the specified group.
+    ///
+    /// Disabled `Output`s stop re-streaming a live stream to their
+    /// destinations.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Output` has been disabled, `false` if
+    /// all `Output`s have been disabled already, and `null` if no `Restream`
+    /// belongs to the specified group.
+    #[graphql(arguments(group(
+        description = "Group of the `Restream`s to disable all `Output`s in."
+    )))]
+    fn disable_group_outputs(
+        group: RestreamGroup,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().disable_group_outputs(&group))
+    }
+
+    /// Renames a group of `Restream`s, by rewriting `Restream.group` of all its
+    /// members at once.
+    ///
+    /// Renaming into an already existing group merges them.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the group has been renamed, `false` if it has the
+    /// same name already, and `null` if no `Restream` belongs to the `from`
+    /// group.
+    #[graphql(arguments(
+        from(description = "Group to be renamed."),
+        to(description = "New name of the group."),
+    ))]
+    fn rename_group(
+        from: RestreamGroup,
+        to: RestreamGroup,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().rename_restream_group(&from, to))
+    }
+
+    /// Resets the counter of bytes sent by the specified `Output`, or by all
+    /// `Output`s of the specified `Restream`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the counter has been reset, or `null` if the
+    /// specified `Restream` or `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to reset the counter \
+                                   in."
+        ),
+        output_id(description = "Optional ID of the `Output` to reset the \
+                                 counter of.\
+                                 \n\n\
+                                 If not specified, then counters of all the \
+                                 `Output`s of the `Restream` are reset."),
+    ))]
+    fn reset_bytes_sent(
+        restream_id: RestreamId,
+        output_id: Option<OutputId>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_admin()?;
+        let ids: Vec<_> = match context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.id == restream_id)
+        {
+            Some(r) => r
+                .outputs
+                .iter()
+                .filter_map(|o| {
+                    output_id.map_or(true, |id| o.id == id).then(|| o.id)
+                })
+                .collect(),
+            None => return Ok(None),
+        };
+        if ids.is_empty() && output_id.is_some() {
+            return Ok(None);
+        }
+        for id in ids {
+            if let Some(s) = ffmpeg::Stats::global().get(id) {
+                s.reset_bytes_sent();
+            }
+        }
+        Ok(Some(true))
+    }
+
+    /// Resets the usage counters (`Restream.inputOnlineSecs` and
+    /// `Output.onlineSecs`) of the `Restream` owning the specified `Input`, so
+    /// they're counted since now (see `Restream.usageSince`).
+    ///
+    /// The discarded counters are kept for auditing.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the counters have been reset, or `null` if the
+    /// specified `Input` doesn't exist.
+    #[graphql(arguments(input_id(
+        description = "ID of the `Input` to reset the usage counters of."
+    )))]
+    fn reset_usage_counters(
+        input_id: InputId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_admin()?;
+        let reset = match context.state().reset_usage_counters(input_id) {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        log::warn!(
+            "Reset usage counters of {} input, discarding {}s of input and \
+             {}s of outputs online time",
+            input_id,
+            reset.input_online_secs,
+            reset.outputs_online_secs,
+        );
+        Ok(Some(true))
+    }
+
+    /// Tunes a `Volume` rate of the specified `Output` or one of its `Mixin`s.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if a `Volume` rate has been changed, `false` if it has
+    /// the same value already, or `null` if the specified `Output` or `Mixin`
+    /// doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to tune the \
+                                   `Output` in."),
+        output_id(description = "ID of the tuned `Output`."),
+        mixin_id(description = "Optional ID of the tuned `Mixin`.\
+                                \n\n\
+                                If set, then tunes the `Mixin` rather than \
+                                the `Output`."),
+        volume(description = "Volume rate in percents to be set."),
+    ))]
+    fn tune_volume(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        mixin_id: Option<MixinId>,
+        volume: Volume,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().tune_volume(
+            restream_id,
+            output_id,
+            mixin_id,
+            volume,
+        ))
+    }
+
+    /// Sets metadata (title, author and comment) to be injected into the live
+    /// stream re-streamed by the specified `Output`.
+    ///
+    /// Blank values are omitted. Changing metadata restarts re-streaming of the
+    /// specified `Output` only.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputMetadata` has been changed, `false` if it has
+    /// the same value already, or `null` if the specified `Output` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to set metadata of \
+                                   the `Output` in."),
+        output_id(description = "ID of the `Output` to set metadata of."),
+        title(description = "Title of the live stream."),
+        author(description = "Author of the live stream."),
+        comment(description = "Arbitrary comment to the live stream."),
+    ))]
+    fn set_output_metadata(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        title: Option<String>,
+        author: Option<String>,
+        comment: Option<String>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_metadata(
+            restream_id,
+            output_id,
+            OutputMetadata::new(title, author, comment)?,
+        ))
+    }
+
+    /// Sets conditioning of the live stream re-streamed by the specified
+    /// `Output`, re-encoding it to meet the requirements of the specified
+    /// streaming platform.
+    ///
+    /// Changing conditioning restarts re-streaming of the specified `Output`
+    /// only.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputConditioning` has been changed, `false` if it
+    /// has the same value already, or `null` if the specified `Output` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to set conditioning \
+                                   of the `Output` in."),
+        output_id(description = "ID of the `Output` to set conditioning of."),
+        preset(description = "Preset of the streaming platform to meet the \
+                              requirements of.\
+                              \n\n\
+                              If not specified, then conditioning is \
+                              disabled and the live stream is re-streamed \
+                              \"as is\"."),
+        audio_sample_rate(description = "Sample rate to re-encode audio \
+                                         with.\
+                                         \n\n\
+                                         If not specified, then the default \
+                                         one of the `preset` is used."),
+    ))]
+    fn set_output_conditioning(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        preset: Option<OutputConditioningPreset>,
+        audio_sample_rate: Option<AudioSampleRate>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_conditioning(
+            restream_id,
+            output_id,
+            preset.map(|preset| OutputConditioning {
+                preset,
+                audio_sample_rate,
+            }),
+        ))
+    }
+
+    /// Sets a target loudness to normalize the audio of the specified `Output`
+    /// to, or clears it.
+    ///
+    /// Normalization requires re-encoding the audio, while the video is still
+    /// copied "as is". If the `Output` has `Mixin`s, then the mixed audio is
+    /// normalized.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `LoudnessTarget` has been changed, `false` if it has
+    /// the same value already, or `null` if the specified `Output` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to normalize the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to normalize audio of."),
+        integrated(description = "Integrated loudness in LUFS to normalize \
+                                  to, within `-70.0..=-5.0` range.\
+                                  \n\n\
+                                  If not specified, then normalization is \
+                                  disabled."),
+        true_peak(
+            description = "Maximum true peak in dBTP, within `-9.0..=0.0` \
+                           range.",
+            default = LoudnessTarget::DEFAULT_TRUE_PEAK,
+        ),
+    ))]
+    fn set_output_loudnorm(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        integrated: Option<f64>,
+        true_peak: f64,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let loudnorm = integrated
+            .map(|i| loudness_target(i, true_peak))
+            .transpose()?;
+        Ok(context.state().set_output_loudnorm(
+            restream_id,
+            output_id,
+            loudnorm,
+        ))
+    }
+
+    /// Sets limits of OS resources the re-streaming process of the specified
+    /// `Output` is allowed to consume, or clears them.
+    ///
+    /// Limits are enforced on a best-effort basis with the mechanisms
+    /// available in OS (see `PoolProcess.limitedBy`), and are ignored silently
+    /// where none is.
+    ///
+    /// Changing limits restarts re-streaming of the specified `Output` only.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputLimits` have been changed, `false` if they
+    /// have the same value already, or `null` if the specified `Output`
+    /// doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to limit the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to be limited."),
+        nice(description = "Niceness of the re-streaming process, within \
+                            `0..=19` range."),
+        cpu_quota(description = "Maximum CPU usage of the re-streaming \
+                                 process, in percents of a single CPU core.\
+                                 \n\n\
+                                 Cannot exceed `100` multiplied by the \
+                                 number of CPU cores of the server."),
+    ))]
+    fn set_output_limits(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        nice: Option<i32>,
+        cpu_quota: Option<i32>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let limits = output_limits(nice, cpu_quota)?;
+        Ok(context
+            .state()
+            .set_output_limits(restream_id, output_id, limits))
+    }
+
+    /// Sets constraints of the downstream destination of the specified
+    /// `Output` on the live stream it accepts (like the codecs), or clears
+    /// them.
+    ///
+    /// The constraints are checked against the `Input.codecs` of the live
+    /// stream re-streamed by the `Output`, considering the tracks re-encoded
+    /// by it. Once they're not met, the `Output.problem` of
+    /// `ProblemKind.INCOMPATIBLE` is reported, and, if the constraints are
+    /// enforced, the live stream is not re-streamed to the destination at
+    /// all.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputConstraints` have been changed, `false` if
+    /// they have the same value already, or `null` if the specified `Output`
+    /// doesn't exist.
+    ///
+    /// Errors with `INVALID_OUTPUT_CONSTRAINTS` if any of the constraints is
+    /// invalid.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to constrain the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to be constrained."),
+        constraints(description = "Constraints to be set.\
+                                   \n\n\
+                                   If not specified, or all of them are \
+                                   empty, then the existing constraints are \
+                                   removed."),
+    ))]
+    fn set_output_constraints(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        constraints: Option<NewOutputConstraints>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let constraints =
+            constraints.map(output_constraints).transpose()?.flatten();
+        Ok(context.state().set_output_constraints(
+            restream_id,
+            output_id,
+            constraints,
+        ))
+    }
+
+    /// Enables or disables prewarming of the specified `Output`.
+    ///
+    /// Prewarmed `Output` keeps its re-streaming process connected to its
+    /// destination while being disabled and its `Input` is online, in the way
+    /// defined by the `PrewarmStrategy`, so enabling it switches to the live
+    /// stream near-instantly, without reconnecting.
+    ///
+    /// Prewarming takes effect only for an `Output` having `LIVE`
+    /// `Output.pauseMode`, being ignored otherwise. Prewarmed processes count
+    /// towards capacity of this server and the limit of its re-streaming
+    /// processes as any other ones, but the processes of enabled `Output`s
+    /// take precedence over them.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `Output.prewarm` or `Output.prewarmStrategy` has
+    /// been changed, `false` if they have the same values already, or `null`
+    /// if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to prewarm the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to be prewarmed."),
+        prewarm(description = "Indicator whether the `Output` should be \
+                               prewarmed."),
+        strategy(
+            description = "Strategy of keeping the prewarmed `Output` \
+                           connected to its destination.",
+            default = PrewarmStrategy::Slate,
+        ),
+    ))]
+    fn set_output_prewarm(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        prewarm: bool,
+        strategy: PrewarmStrategy,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_prewarm(
+            restream_id,
+            output_id,
+            prewarm,
+            strategy,
+        ))
+    }
+
+    /// Enables or disables dropping frames by the re-streaming process of the
+    /// specified `Output` once its destination cannot keep up with the live
+    /// stream.
+    ///
+    /// Enabled, it bounds the send buffer of the process, so a momentarily
+    /// slow destination makes the process drop data instead of stalling and
+    /// dropping the connection eventually. The exact options depend on the
+    /// destination protocol, and are visible in `Query.outputCommand`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `Output.dropFramesWhenBehind` has been changed,
+    /// `false` if it has the same value already, or `null` if the specified
+    /// `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to tune the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to be tuned."),
+        drop(description = "Indicator whether the `Output` should drop \
+                            frames once its destination cannot keep up."),
+    ))]
+    fn set_output_drop_frames_when_behind(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        drop: bool,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().set_output_drop_frames_when_behind(
+            restream_id,
+            output_id,
+            drop,
+        ))
+    }
+
+    /// Sets the stream of the specified `Output` to be re-streamed: the live
+    /// stream of the main `Restream.input`, or of any of its
+    /// `FailoverInputSrc.inputs` published separately (like a clean feed
+    /// along with the program one).
+    ///
+    /// Re-streaming a stream other than the main one doesn't support
+    /// `Output.delaySecs`, so such delayed `Output` never goes live.
+    ///
+    /// Errors with `UNKNOWN_STREAM` if the `Restream` has no `Input` with the
+    /// specified `key`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `Output.srcStream` has been changed, `false` if it
+    /// has the same value already, or `null` if the specified `Output`
+    /// doesn't exist.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to set the stream of \
+                                   the `Output` in."
+        ),
+        output_id(description = "ID of the `Output` to set the stream of."),
+        key(description = "Key of the `Input` whose live stream should be \
+                           re-streamed by the `Output`.\
+                           \n\n\
+                           If not specified, then the live stream of the main \
+                           `Restream.input` is re-streamed."),
+    ))]
+    fn set_output_src_stream(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        key: Option<InputKey>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        context
+            .state()
+            .set_output_src_stream(restream_id, output_id, key)
+            .map_err(|e| {
+                graphql::Error::new("UNKNOWN_STREAM")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&e)
+            })
+    }
+
+    /// Sets an image to be drawn over the video of the specified `Output`
+    /// (a watermark, for example).
+    ///
+    /// The image is either the one uploaded via `PUT /overlays/{output_id}`
+    /// HTTP endpoint, or the one referenced by its `path` on the server. It's
+    /// validated to exist and be decodable.
+    ///
+    /// Drawing an overlay forces the video of the `Output` to be re-encoded.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputOverlay` has been changed, `false` if it has
+    /// the same value already, or `null` if the specified `Output` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to set the overlay \
+                                   of the `Output` in."),
+        output_id(description = "ID of the `Output` to set the overlay of."),
+        path(description = "Path to the image file on the server.\
+                            \n\n\
+                            It should be inside the media root directory of \
+                            this server (if it's configured).\
+                            \n\n\
+                            If not specified, then the image uploaded via \
+                            `PUT /overlays/{output_id}` is used."),
+        position(
+            description = "Position of the image in the video.",
+            default = OverlayPosition::default(),
+        ),
+        opacity(
+            description = "Opacity of the image in percents.",
+            default = OutputOverlay::MAX_OPACITY.into(),
+        ),
+    ))]
+    async fn set_output_overlay(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        path: Option<String>,
+        position: OverlayPosition,
+        opacity: i32,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let opacity = opacity
+            .try_into()
+            .ok()
+            .filter(|o| *o <= OutputOverlay::MAX_OPACITY)
+            .ok_or_else(|| {
+                graphql::Error::new("INVALID_OVERLAY_OPACITY")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message("Opacity must be within 0..100 percents")
+            })?;
+
+        if let Some(path) = &path {
+            text::check("path", path, text::MAX_PATH_LEN)?;
+        }
+
+        let cfg = context.config();
+        let path = match path {
+            Some(p) => media_path(&p, context)
+                .map_err(|e| path_error("INVALID_OVERLAY_IMAGE", &e))?,
+            None => overlay::uploaded_path(&cfg.overlays_dir, output_id),
+        };
+        let is_image =
+            overlay::probe(&cfg.ffprobe_path, &path)
+                .await
+                .map_err(|e| {
+                    graphql::Error::new("OVERLAY_PROBE_FAILED").message(
+                        &format!("Failed to probe overlay image: {}", e,),
+                    )
+                })?;
+        if !is_image {
+            return Err(graphql::Error::new("INVALID_OVERLAY_IMAGE")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!(
+                    "'{}' is not an existing decodable image",
+                    path.display(),
+                )));
+        }
+        let path = fs::canonicalize(&path).await.map_err(|e| {
+            graphql::Error::new("INVALID_OVERLAY_IMAGE")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!(
+                    "Failed to resolve '{}' path: {}",
+                    path.display(),
+                    e,
+                ))
+        })?;
+
+        Ok(context.state().set_output_overlay(
+            restream_id,
+            output_id,
+            Some(OutputOverlay {
+                path,
+                position,
+                opacity,
+            }),
+        ))
+    }
+
+    /// Removes the image drawn over the video of the specified `Output`, so
+    /// its video is copied "as is" again.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if `OutputOverlay` has been removed, `false` if there
+    /// was no one, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to remove the \
+                                   overlay of the `Output` in."),
+        output_id(description = "ID of the `Output` to remove the overlay \
+                                 of."),
+    ))]
+    fn remove_output_overlay(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .set_output_overlay(restream_id, output_id, None))
+    }
+
+    /// Delays (time-shifts) the live stream re-streamed by the specified
+    /// `Output` for the given number of seconds, or re-streams it live again.
+    ///
+    /// The delay is performed by buffering the live stream of the `Restream`
+    /// on the server's disk, so the delayed `Output` starts reading it
+    /// `delaySecs` behind the live edge. `Output.mixins` are mixed in live,
+    /// without the delay.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the delay has been changed, `false` if it has the
+    /// same value already, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to delay the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to be delayed."),
+        delay_secs(description = "Number of seconds to delay the `Output` \
+                                  for, not greater than `300`.\
+                                  \n\n\
+                                  If not specified or zero, then the \
+                                  `Output` is re-streamed live."),
+    ))]
+    fn set_output_delay(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        delay_secs: Option<i32>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let delay_secs = delay_secs.map(output_delay).transpose()?;
+        Ok(context
+            .state()
+            .set_output_delay(restream_id, output_id, delay_secs))
+    }
+
+    /// Caps the bitrate the live stream is re-streamed by the specified
+    /// `Output` with, or removes the cap.
+    ///
+    /// Intended for destinations ingesting over constrained links, which choke
+    /// on bursts even if the average bitrate fits. Only the re-streaming
+    /// process of the specified `Output` is restarted to apply the cap.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the cap has been changed, `false` if it has the same
+    /// value already, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to cap the bitrate \
+                                   of the `Output` in."),
+        output_id(description = "ID of the `Output` to cap the bitrate of."),
+        kbps(description = "Maximum bitrate in kbit/s.\
+                            \n\n\
+                            If not specified or zero, then the bitrate is not \
+                            capped."),
+    ))]
+    fn set_output_max_bitrate(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        kbps: Option<i32>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let kbps = kbps.map(output_bitrate).transpose()?;
+        Ok(context
+            .state()
+            .set_output_max_bitrate(restream_id, output_id, kbps))
+    }
+
+    /// Sets a backup downstream destination of the specified `Output`, or
+    /// removes it.
+    ///
+    /// For platforms providing primary and backup ingest URLs for the same
+    /// stream key. Only one of them is re-streamed to at a time: the
+    /// `Output.dst` while it's healthy, and the backup one while the former
+    /// keeps failing (see `Output.dstHealth`). Resets the health scores of the
+    /// `Output` and its `Output.dstOverride`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the backup destination has been changed, `false` if
+    /// it has the same value already, or `null` if the specified `Output`
+    /// doesn't exist.
+    ///
+    /// Errors with `INVALID_BACKUP_DST` if the backup destination is the same
+    /// as the `Output.dst`, or any of them is a local file.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to set the backup \
+                                   destination of the `Output` in."),
+        output_id(description = "ID of the `Output` to set the backup \
+                                 destination of."),
+        dst(description = "Backup downstream URL to re-stream to.\
+                           \n\n\
+                           If not specified, then the backup destination is \
+                           removed."),
+    ))]
+    fn set_output_backup_dst(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: Option<OutputDstUrl>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        if let Some(backup) = &dst {
+            let primary = context
+                .state()
+                .restreams
+                .lock_ref()
+                .iter()
+                .find(|r| r.id == restream_id)
+                .and_then(|r| r.outputs.iter().find(|o| o.id == output_id))
+                .map(|o| o.dst.clone());
+            check_backup_dst(primary.as_ref(), backup)?;
+        }
+        Ok(context
+            .state()
+            .set_output_backup_dst(restream_id, output_id, dst))
+    }
+
+    /// Schedules a change of the downstream destination of the specified
+    /// `Output`, like a fresh stream key of an upcoming event prepared ahead.
+    ///
+    /// The `dst` is set as `Output.pendingDst`, replacing the `Output.dst` at
+    /// the `applyAt` moment (right away, if it has passed already), or by
+    /// `Mutation.applyPendingDst` only, if no `applyAt` is specified. Only the
+    /// re-streaming process of the `Output` is restarted then. Rescheduling
+    /// replaces the previously scheduled change.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the scheduled change has been changed, `false` if
+    /// it's the same already, or `null` if the specified `Output` doesn't
+    /// exist.
+    ///
+    /// Errors with `INVALID_PENDING_DST` if the `dst` is the same as the
+    /// `Output.dst`.
+    ///
+    /// Errors with `DUPLICATE_OUTPUT_URL` or `CONFLICTING_OUTPUT_URL` in the
+    /// same way `Mutation.setOutput` does.
+    ///
+    /// Errors with `INVALID_APPLY_AT` if the `localApplyAt` is malformed, or
+    /// specified along with the `applyAt` or without the `timezone`.
+    ///
+    /// Errors with `INVALID_TIMEZONE` if the `timezone` is not a known IANA
+    /// time zone name, or with `NONEXISTENT_LOCAL_TIME` and
+    /// `AMBIGUOUS_LOCAL_TIME` if the `localApplyAt` is skipped or repeated by
+    /// a DST transition in the `timezone`.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to schedule the \
+                                   destination change of the `Output` in."),
+        output_id(description = "ID of the `Output` to schedule the \
+                                 destination change of."),
+        dst(
+            description = "Downstream URL to re-stream to once the change is \
+                           applied."
+        ),
+        apply_at(description = "Moment to apply the change at.\
+                                \n\n\
+                                If neither it nor `localApplyAt` is \
+                                specified, then the change is applied by \
+                                `Mutation.applyPendingDst` only."),
+        local_apply_at(description = "Wall-clock date and time in the \
+                                      `timezone` to apply the change at, \
+                                      formatted as `YYYY-MM-DDTHH:MM`."),
+        timezone(description = "IANA name of the time zone (like \
+                                `Europe/Kyiv`) of the `localApplyAt`."),
+    ))]
+    fn schedule_output_dst_change(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: OutputDstUrl,
+        apply_at: Option<graphql::DateTime>,
+        local_apply_at: Option<String>,
+        timezone: Option<String>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let apply_at = match (apply_at, local_apply_at, timezone) {
+            (at, None, None) => at,
+            (None, Some(local), Some(tz)) => {
+                Some(local_date_time(&local, &time_zone(&tz)?)?)
+            }
+            _ => {
+                return Err(graphql::Error::new("INVALID_APPLY_AT")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(
+                        "`localApplyAt` requires `timezone` and excludes \
+                         `applyAt`",
+                    ))
+            }
+        };
+        context
+            .state()
+            .schedule_output_dst_change(restream_id, output_id, dst, apply_at)
+            .map_err(pending_dst_error)
+    }
+
+    /// Cancels the scheduled change of the downstream destination of the
+    /// specified `Output` (see `Mutation.scheduleOutputDstChange`).
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the scheduled change has been cancelled, `false` if
+    /// there is no one, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to cancel the \
+                                   destination change of the `Output` in."),
+        output_id(description = "ID of the `Output` to cancel the \
+                                 destination change of."),
+    ))]
+    fn cancel_output_dst_change(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .cancel_output_dst_change(restream_id, output_id))
+    }
+
+    /// Applies the scheduled change of the downstream destination of the
+    /// specified `Output` right away, regardless of its `Output.applyAt`
+    /// (see `Mutation.scheduleOutputDstChange`).
+    ///
+    /// The `Output.pendingDst` replaces the `Output.dst`, and the
+    /// re-streaming process of the `Output` is restarted.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the change has been applied, `false` if there is no
+    /// scheduled change, or `null` if the specified `Output` doesn't exist.
+    ///
+    /// Errors with `DUPLICATE_OUTPUT_URL` or `CONFLICTING_OUTPUT_URL` if the
+    /// `Output.pendingDst` is used by another `Output` at the moment, keeping
+    /// the change scheduled.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to apply the \
+                                   destination change of the `Output` in."),
+        output_id(
+            description = "ID of the `Output` to apply the destination \
+                                 change of."
+        ),
+    ))]
+    fn apply_pending_dst(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        context
+            .state()
+            .apply_pending_output_dst(restream_id, output_id, false)
+            .map_err(duplicate_error)
+    }
+
+    /// Switches the specified `Output` to re-stream to the given destination,
+    /// overriding the automatic switching between its `Output.dst` and
+    /// `Output.backupDst`, or returns it to the automatic switching.
+    ///
+    /// The re-streaming process of the `Output` is restarted if its
+    /// destination changes.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the `Output.dstOverride` has been changed, `false` if
+    /// it has the same value already, or `null` if the specified `Output`
+    /// doesn't exist, or has no `Output.backupDst`.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to switch the \
+                                   `Output` in."),
+        output_id(description = "ID of the `Output` to switch."),
+        dst(description = "Destination to re-stream to.\
+                           \n\n\
+                           If not specified, then the destination is chosen \
+                           automatically."),
+    ))]
+    fn switch_output_destination(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: Option<OutputDestination>,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .switch_output_dst(restream_id, output_id, dst))
+    }
+
+    /// Sets the `OutputBootPolicy` of the specified `Output`, defining whether
+    /// it's enabled after the server restart.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the policy has been changed, `false` if it has the
+    /// same value already, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to set the policy of \
+                                   the `Output` in."
+        ),
+        output_id(description = "ID of the `Output` to set the policy of."),
+        on_boot(description = "`OutputBootPolicy` to be set."),
+    ))]
+    fn set_output_on_boot(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        on_boot: OutputBootPolicy,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .set_output_on_boot(restream_id, output_id, on_boot))
+    }
+
+    /// Starts an `OutputTest` of the specified disabled `Output`, re-streaming
+    /// a test signal (SMPTE color bars with a tone) directly to its downstream
+    /// destination for the given number of seconds, so the destination (and
+    /// its stream key) can be verified before going live.
+    ///
+    /// The `Output` has `Status.TESTING` while the `OutputTest` runs, and its
+    /// result is reported via `Output.lastTest` once finished.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the `OutputTest` has been started, `false` if another
+    /// one is running already, or `null` if the specified `Output` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to test the `Output` \
+                                   in."
+        ),
+        output_id(description = "ID of the `Output` to be tested."),
+        duration_secs(
+            description = "Number of seconds to re-stream the test signal \
+                           for, not greater than `300`.",
+            default = 30,
+        ),
+    ))]
+    fn test_output(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        duration_secs: i32,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        let duration_secs = positive_secs(duration_secs)
+            .filter(|d| *d <= OutputTest::MAX_DURATION_SECS)
+            .ok_or_else(|| {
+                graphql::Error::new("INVALID_TEST_DURATION")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&format!(
+                        "Test duration must be within 1..{} seconds",
+                        OutputTest::MAX_DURATION_SECS,
+                    ))
+            })?;
+
+        let is_file = context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter().find(|o| o.id == output_id))
+            .map(|o| o.dst.scheme() == "file");
+        if is_file == Some(true) {
+            return Err(graphql::Error::new("UNTESTABLE_OUTPUT")
+                .status(StatusCode::BAD_REQUEST)
+                .message("File `Output`s cannot be tested"));
+        }
+
+        context
+            .state()
+            .start_output_test(output_id, restream_id, duration_secs)
+            .map_err(|e| {
+                graphql::Error::new("OUTPUT_ACTIVE")
+                    .status(StatusCode::CONFLICT)
+                    .message(&e)
+            })
+    }
+
+    /// Stops the running `OutputTest` of the specified `Output`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the `OutputTest` has been stopped, `false` if there
+    /// was none running, or `null` if the specified `Output` doesn't exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to stop the \
+                                   `OutputTest` in."),
+        output_id(
+            description = "ID of the `Output` to stop the `OutputTest` \
+                                 of."
+        ),
+    ))]
+    fn stop_test(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context.state().stop_output_test(output_id, restream_id))
+    }
+
+    /// Tunes a `Delay` of the specified `Mixin` before mix it into its
+    /// `Output`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if a `Delay` has been changed, `false` if it has the same
+    /// value already, or `null` if the specified `Output` or `Mixin` doesn't
+    /// exist.
+    #[graphql(arguments(
+        restream_id(description = "ID of the `Restream` to tune the the \
+                                   `Mixin` in."),
+        output_id(description = "ID of the `Output` of the tuned `Mixin`."),
+        mixin_id(description = "ID of the tuned `Mixin`."),
+        delay(description = "Number of milliseconds to delay the `Mixin` \
+                             before mix it into its `Output`."),
+    ))]
+    fn tune_delay(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        mixin_id: MixinId,
+        delay: Delay,
+        context: &Context,
+    ) -> Result<Option<bool>, graphql::Error> {
+        context.require_writable()?;
+        Ok(context
+            .state()
+            .tune_delay(restream_id, output_id, mixin_id, delay))
+    }
+
+    /// Removes the specified recorded file.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the specified recorded file was removed, otherwise
+    /// `false` if nothing changes.
+    #[graphql(arguments(path(
+        description = "Relative path of the recorded file to be removed.\
+                       \n\n\
+                       Use the exact value returned by `Query.dvrFiles`."
+    )))]
+    async fn remove_dvr_file(
+        path: String,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        text::check("path", &path, text::MAX_PATH_LEN)?;
+        if path.starts_with('/') {
+            return Err(graphql::Error::new("INVALID_DVR_FILE_PATH")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!("Invalid DVR file path: {}", path)));
+        }
+        let storage = dvr::Storage::global();
+        let _ = paths::resolve(&path, Some(&storage.root_path))
+            .map_err(|e| path_error("INVALID_DVR_FILE_PATH", &e))?;
+
+        Ok(storage.remove_file(path).await)
+    }
+
+    /// Sets or unsets the password to protect this GraphQL API with.
+    ///
+    /// Once password is set, any subsequent requests to this GraphQL API should
+    /// perform [HTTP Basic auth][1], where any username is allowed, but the
+    /// password should match the one being set.
+    ///
+    /// Unsetting the password unsets the viewer one too (see
+    /// `Mutation.setViewerPassword`).
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if password has been changed or unset, otherwise `false`
+    /// if nothing changes.
+    ///
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    #[graphql(arguments(
+        new(
+            description = "New password to be set. In `null` then unsets the \
+                           current password."
+        ),
+        old(description = "Old password for authorization, if it was set \
+                           previously."),
+    ))]
+    async fn set_password(
+        new: Option<String>,
+        old: Option<String>,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        let current = context.state().settings.lock_ref().password_hash.clone();
+
+        if let Some(hash) = current.clone() {
+            match old {
+                None => {
+                    return Err(graphql::Error::new("NO_OLD_PASSWORD")
+                        .status(StatusCode::FORBIDDEN)
+                        .message("Old password required for this action"))
+                }
+                Some(pass) => {
+                    if !password::verify_blocking(hash, pass).await {
+                        return Err(graphql::Error::new("WRONG_OLD_PASSWORD")
+                            .status(StatusCode::FORBIDDEN)
+                            .message("Wrong old password specified"));
+                    }
+                }
+            }
+        }
+
+        if current.is_none() && new.is_none() {
+            return Ok(false);
+        }
+
+        let hash = match new {
+            Some(pass) => Some(hash_password(pass).await?),
+            None => None,
+        };
+        let mut settings = context.state().settings.lock_mut();
+        if settings.password_hash != current {
+            return Err(password_changed_error());
+        }
+        settings.password_hash = hash;
+        if settings.password_hash.is_none() {
+            settings.viewer_password_hash = None;
+        }
+
+        Ok(true)
+    }
+
+    /// Sets or unsets the password granting read-only access (`Role.VIEWER`)
+    /// to this GraphQL API.
+    ///
+    /// Clients authorized with this password may perform queries and
+    /// subscriptions only, while any mutation fails with `UNAUTHORIZED` error.
+    ///
+    /// Requires the admin password to be set (see `Mutation.setPassword`), and
+    /// must differ from it.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if viewer password has been changed or unset, otherwise
+    /// `false` if nothing changes.
+    #[graphql(arguments(new(
+        description = "New viewer password to be set. If `null` then unsets \
+                       the current viewer password."
+    )))]
+    async fn set_viewer_password(
+        new: Option<String>,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        let admin_hash =
+            context.state().settings.lock_ref().password_hash.clone();
+
+        let admin_hash = match admin_hash {
+            Some(h) => h,
+            None => {
+                return Err(graphql::Error::new("NO_PASSWORD")
+                    .status(StatusCode::CONFLICT)
+                    .message("Admin password should be set first"))
+            }
+        };
+        let hash = match new {
+            Some(pass) => {
+                if password::verify_blocking(admin_hash.clone(), pass.clone())
+                    .await
+                {
+                    return Err(graphql::Error::new("SAME_AS_ADMIN_PASSWORD")
+                        .status(StatusCode::BAD_REQUEST)
+                        .message(
+                            "Viewer password must differ from admin one",
+                        ));
+                }
+                Some(hash_password(pass).await?)
+            }
+            None => None,
+        };
+
+        let mut settings = context.state().settings.lock_mut();
+        if settings.password_hash.as_ref() != Some(&admin_hash) {
+            return Err(password_changed_error());
+        }
+        if settings.viewer_password_hash.is_none() && hash.is_none() {
+            return Ok(false);
+        }
+
+        settings.viewer_password_hash = hash;
+
+        Ok(true)
+    }
+
+    /// Sets settings of the server
+    ///
+    /// ### Result
+    ///
+    /// Returns `false` if title does not pass validation for max allowed
+    /// characters length. Otherwise returns `true`
+    #[graphql(arguments(
+        title(description = "Title for the server"),
+        delete_confirmation(
+            description = "Whether do we need to confirm deletion of inputs \
+            and outputs"
+        ),
+        enable_confirmation(
+            description = "Whether do we need to confirm enabling/disabling of \
+            inputs or outputs"
+        )
+    ))]
+    fn set_settings(
+        title: Option<String>,
+        delete_confirmation: Option<bool>,
+        enable_confirmation: Option<bool>,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_writable()?;
+        let value = text::sanitize(
+            "title",
+            &title.unwrap_or_default(),
+            text::MAX_TITLE_LEN,
+        )?;
+
+        let mut settings = context.state().settings.lock_mut();
+        settings.title = Some(value);
+        settings.delete_confirmation = delete_confirmation;
+        settings.enable_confirmation = enable_confirmation;
+        Ok(true)
+    }
+
+    /// Changes verbosity level of this server's logs (including [SRS] logs) at
+    /// runtime, without restarting it.
+    ///
+    /// The changed level doesn't survive server restarts, unless `persist` is
+    /// `true`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the current `LogLevel` has been changed, or `false` if
+    /// it has the same value already.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[graphql(arguments(
+        level(description = "New verbosity level of logs to be set."),
+        persist(
+            description = "Indicator whether the `level` should be persisted \
+                           and survive server restarts.",
+            default = false,
+        ),
+    ))]
+    fn set_log_level(
+        level: LogLevel,
+        persist: bool,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        let state = context.state();
+        if persist {
+            state.settings.lock_mut().log_level = Some(level);
+        }
+        Ok(state.log_level.replace(level) != level)
+    }
+
+    /// Switches drain mode of this server.
+    ///
+    /// While draining, new publishers are rejected and no new re-streaming
+    /// processes are spawned, while the currently running ones continue.
+    /// Drain mode doesn't survive server restarts.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if drain mode has been switched, or `false` if it's in
+    /// the requested mode already.
+    #[graphql(arguments(enabled(
+        description = "Indicator whether drain mode should be enabled."
+    )))]
+    fn set_drain_mode(
+        enabled: bool,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        Ok(context.state().set_draining(enabled))
+    }
+
+    /// Switches serving of [GraphQL Playground][1] by this server at runtime,
+    /// without restarting it.
+    ///
+    /// While disabled, its endpoints respond with `404 Not Found`. The switch
+    /// doesn't survive server restarts, where the playground is enabled in
+    /// debug mode only.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the playground has been switched, or `false` if it's
+    /// in the requested mode already.
+    ///
+    /// [1]: https://github.com/graphql/graphql-playground
+    #[graphql(arguments(enabled(
+        description = "Indicator whether the playground should be served."
+    )))]
+    fn set_playground_enabled(
+        enabled: bool,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        Ok(context.state().playground_enabled.replace(enabled) != enabled)
+    }
+
+    /// Switches rejection of `Output`s re-streaming to the same downstream
+    /// destination as `Output`s of other `Restream`s do.
+    ///
+    /// Once enabled, `Mutation.setOutput` errors with `CONFLICTING_OUTPUT_URL`
+    /// on such `Output`s, rather than just reporting them via
+    /// `Output.conflictsWith`. Already existing conflicts are left untouched
+    /// (see `Query.conflicts`).
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the rejection has been switched, or `false` if it's in
+    /// the requested mode already.
+    #[graphql(arguments(reject(
+        description = "Indicator whether conflicting `Output`s should be \
+                       rejected."
+    )))]
+    fn set_reject_dst_conflicts(
+        reject: bool,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_writable()?;
+        let mut settings = context.state().settings.lock_mut();
+        if settings.reject_dst_conflicts == reject {
+            return Ok(false);
+        }
+        settings.reject_dst_conflicts = reject;
+        Ok(true)
+    }
+
+    /// Sets the time zone that clients should display timestamps and
+    /// schedules of this server in.
+    ///
+    /// It affects displaying only, as all the timestamps are stored and
+    /// reported in UTC anyway.
+    ///
+    /// ### Errors
+    ///
+    /// Errors with `INVALID_TIMEZONE` if the `timezone` is not a known
+    /// [IANA time zone database][1] name.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if the time zone has been changed, or `false` if it has
+    /// the same value already.
+    ///
+    /// [1]: https://www.iana.org/time-zones
+    #[graphql(arguments(timezone(
+        description = "IANA name of the time zone (like `Europe/Kyiv`) to \
+                       display timestamps in. Displays UTC, if `null`."
+    )))]
+    fn set_display_timezone(
+        timezone: Option<String>,
+        context: &Context,
+    ) -> Result<bool, graphql::Error> {
+        context.require_writable()?;
+        let timezone = timezone.as_deref().map(time_zone).transpose()?;
+
+        let mut settings = context.state().settings.lock_mut();
+        if settings.display_timezone == timezone {
+            return Ok(false);
+        }
+        settings.display_timezone = timezone;
+        Ok(true)
+    }
+
+    /// Disables all `Input`s and `Output`s of all `Restream`s at once,
+    /// stopping all the re-streaming on this server.
+    ///
+    /// Records a `MANUAL` `Input.disabledReason` of each disabled `Input`.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if at least one `Input` or `Output` has been disabled,
+    /// or `false` if all of them have been disabled already.
+    fn stop_everything(context: &Context) -> Result<bool, graphql::Error> {
+        context.require_admin()?;
+        Ok(context.state().stop_everything(&DisableReason::new(
+            DisableReasonKind::Manual,
+            "Stopped everything on the server",
+            "GraphQL API",
+        )))
+    }
+
+    /// Injects a `Fault` into this server for the specified duration, for
+    /// rehearsing failures handling.
+    ///
+    /// Available only if the server is built with `fault-injection` feature
+    /// and started with `--enable-fault-injection` CLI option. `Fault`s expire
+    /// automatically, and the active ones are listed by `faults` query.
+    ///
+    /// ### Result
+    ///
+    /// Returns the injected `Fault`.
+    #[graphql(arguments(
+        kind(description = "Kind of the `Fault` to be injected."),
+        target(description = "ID of the `Input` to be forced offline, or of \
+                              the `Output` to have its process killed.\
+                              \n\n\
+                              Ignored for `DELAY_CALLBACKS` `Fault`."),
+        duration_secs(description = "Duration of the `Fault` (in seconds), \
+                                     within `1..=3600` range."),
+        delay_ms(description = "Delay of processing SRS HTTP callbacks (in \
+                                milliseconds), within `1..=60000` range.\
+                                \n\n\
+                                Required for `DELAY_CALLBACKS` `Fault` \
+                                only."),
+    ))]
+    fn inject_fault(
+        kind: fault::FaultKind,
+        target: Option<String>,
+        duration_secs: i32,
+        delay_ms: Option<i32>,
+        context: &Context,
+    ) -> Result<fault::Fault, graphql::Error> {
+        context.require_admin()?;
+        if !context.config().enable_fault_injection {
+            return Err(graphql::Error::new("FAULT_INJECTION_DISABLED")
+                .status(StatusCode::FORBIDDEN)
+                .message("Fault injection is not enabled on this server"));
+        }
+
+        let invalid = |msg: &str| {
+            graphql::Error::new("INVALID_FAULT")
+                .status(StatusCode::BAD_REQUEST)
+                .message(msg)
+        };
+        let duration = u64::try_from(duration_secs)
+            .ok()
+            .filter(|s| (1..=3600).contains(s))
+            .map(Duration::from_secs)
+            .ok_or_else(|| {
+                invalid("Duration must be within 1..3600 seconds")
+            })?;
+        let target = match kind {
+            fault::FaultKind::DelayCallbacks => None,
+            _ => Some(
+                target
+                    .as_deref()
+                    .and_then(|t| Uuid::parse_str(t).ok())
+                    .ok_or_else(|| invalid("Target must be a valid ID"))?,
+            ),
+        };
+        let delay = match kind {
+            fault::FaultKind::DelayCallbacks => Some(
+                delay_ms
+                    .and_then(|ms| u64::try_from(ms).ok())
+                    .filter(|ms| (1..=60_000).contains(ms))
+                    .map(Duration::from_millis)
+                    .ok_or_else(|| {
+                        invalid("Delay must be within 1..60000 milliseconds")
+                    })?,
+            ),
+            _ => None,
+        };
+
+        fault::inject(context.state(), kind, target, duration, delay).map_err(
+            |e| {
+                let status = match e {
+                    fault::InjectError::Disabled => StatusCode::FORBIDDEN,
+                    fault::InjectError::UnknownTarget => StatusCode::NOT_FOUND,
+                    fault::InjectError::NotRunning => StatusCode::CONFLICT,
+                };
+                graphql::Error::new("FAULT_NOT_INJECTED")
+                    .status(status)
+                    .message(&e)
+            },
+        )
+    }
+
+    /// Promotes this standby server to a primary one, so it stops replicating
+    /// the state of the primary server it was started with, accepts local
+    /// edits, and enables the `Output`s which were enabled on the primary
+    /// server.
+    ///
+    /// ### Result
+    ///
+    /// Returns `true` if this server has been promoted, `false` if it has been
+    /// promoted already, or `null` if it's not a standby server at all.
+    fn promote(context: &Context) -> Result<Option<bool>, graphql::Error> {
+        context.require_admin()?;
+        Ok(context.state().promote())
+    }
+}
+
+/// Root of all [GraphQL queries][1] in the [`Schema`].
+///
+/// [1]: https://spec.graphql.org/June2018/#sec-Root-Operation-Types
+#[derive(Clone, Copy, Debug)]
+pub struct QueriesRoot;
+
+#[graphql_object(name = "Query", context = Context)]
+impl QueriesRoot {
+    /// Returns information about the client performing this request.
+    ///
+    /// Use it to hide actions not allowed for the client's `Role`.
+    fn me(context: &Context) -> Me {
+        Me {
+            role: context.role(),
+        }
+    }
+
+    /// Returns the current `Info` parameters of this server.
+    #[allow(clippy::cast_precision_loss)] // exact up to 2^53
+    fn info(context: &Context) -> Info {
+        let settings = context.state().settings.get_cloned();
+        let persistence = context.state().persistence_health.get_cloned();
+        Info {
+            public_host: context.config().public_host.clone().unwrap(),
+            srs_rtmp_port: context.config().srs_rtmp_port.into(),
+            http_flv: !context.config().no_http_flv,
+            password_hash: settings.password_hash,
+            title: settings.title,
+            delete_confirmation: settings.delete_confirmation,
+            enable_confirmation: settings.enable_confirmation,
+            log_level: context.state().log_level.get(),
+            draining: context.state().draining.get(),
+            playground_enabled: context.state().playground_enabled.get(),
+            persistence_healthy: persistence.is_healthy(),
+            persistence_error: persistence.error().map(ToOwned::to_owned),
+            srs: context.srs().into(),
+            ffmpeg: FfmpegInfo::new(
+                context.state().ffmpeg_capabilities.get_cloned().as_deref(),
+                &context.state().missing_ffmpeg_capabilities.lock_ref(),
+            ),
+            spec_reload: context.state().spec_reload.get_cloned(),
+            replication: context.state().replication.get_cloned(),
+            ws_connections: server::client::ws_connections()
+                .try_into()
+                .unwrap_or(i32::MAX),
+            ws_max_connections: context
+                .config()
+                .ws_max_connections
+                .try_into()
+                .unwrap_or(i32::MAX),
+            ws_max_operations: context
+                .config()
+                .ws_max_operations
+                .try_into()
+                .unwrap_or(i32::MAX),
+            graphql_max_depth: context
+                .config()
+                .graphql_max_depth
+                .try_into()
+                .unwrap_or(i32::MAX),
+            graphql_max_complexity: context
+                .config()
+                .graphql_max_complexity
+                .try_into()
+                .unwrap_or(i32::MAX),
+            http_workers: context
+                .config()
+                .http_workers_count()
+                .try_into()
+                .unwrap_or(i32::MAX),
+            blocking_threads: context
+                .config()
+                .blocking_threads
+                .try_into()
+                .unwrap_or(i32::MAX),
+            client_http_addr: context.config().client_http_addr().to_string(),
+            callback_http_addr: context
+                .config()
+                .callback_http_addr()
+                .map(|a| a.to_string()),
+            reject_dst_conflicts: settings.reject_dst_conflicts,
+            display_timezone: settings.display_timezone.map(Into::into),
+            quota: QuotaInfo::new(
+                context.state().quota.get(),
+                QuotaInfo::usage(&context.state().restreams.lock_ref()),
+            ),
+            change_seq: context.state().changes.seq(),
+        }
+    }
+
+    /// Returns all the `Restream`s happening on this server.
+    ///
+    /// Polling clients may pass the `Query.restreamsVersion` they've seen the
+    /// last time as `ifVersion`, to receive an empty list if nothing has
+    /// changed since then.
+    #[graphql(arguments(
+        group(description = "Group to return the `Restream`s of only.\
+                             \n\n\
+                             If not specified then all the `Restream`s are \
+                             returned."),
+        if_version(description = "`Query.restreamsVersion` known to the \
+                                  client.\
+                                  \n\n\
+                                  If it matches the current one, then an \
+                                  empty list is returned, meaning \
+                                  \"not modified\"."),
+    ))]
+    fn all_restreams(
+        group: Option<RestreamGroup>,
+        if_version: Option<String>,
+        context: &Context,
+    ) -> Vec<Restream> {
+        if if_version
+            .map_or(false, |v| v == context.state().restreams_version())
+        {
+            return vec![];
+        }
+        let mut restreams = context.state().restreams.get_cloned();
+        state::sort_by_position(&mut restreams);
+        match group {
+            Some(g) => restreams
+                .into_iter()
+                .filter(|r| r.group.as_ref() == Some(&g))
+                .collect(),
+            None => restreams,
+        }
+    }
+
+    /// Returns the current version of all the `Restream`s happening on this
+    /// server.
+    ///
+    /// It changes whenever any `Restream` changes (including statuses), so
+    /// may be passed as `ifVersion` to `Query.allRestreams` by polling
+    /// clients, or compared with the previously seen one directly.
+    fn restreams_version(context: &Context) -> String {
+        context.state().restreams_version()
+    }
+
+    /// Returns all the `Restream`s created, updated or deleted since the
+    /// specified `cursor`, along with the new cursor to pass the next time.
+    ///
+    /// Every `Restream` is returned once, with its latest configuration, even
+    /// if it has changed multiple times. Deleted `Restream`s are represented
+    /// by their IDs only.
+    ///
+    /// Fails with `RESET_REQUIRED` error if the `cursor` is older than the
+    /// changes kept by this server (or is unknown to it), so a full resync is
+    /// required: `Query.info.changeSeq` should be read first, and only then
+    /// all the `Restream`s re-fetched via `Query.allRestreams`, using the
+    /// read `changeSeq` as the cursor. Reading them in the opposite order may
+    /// lose changes happened in between.
+    #[graphql(arguments(cursor(
+        description = "Sequence number seen previously, either \
+                       `Query.info.changeSeq` or `ChangesSince.cursor`."
+    )))]
+    fn changes_since(
+        cursor: state::ChangeSeq,
+        context: &Context,
+    ) -> Result<ChangesSince, graphql::Error> {
+        let (seq, changes) =
+            context.state().changes.since(cursor).ok_or_else(|| {
+                graphql::Error::new("RESET_REQUIRED")
+                    .status(StatusCode::GONE)
+                    .message(
+                        "Cursor is older than the kept changes, full resync \
+                         is required",
+                    )
+            })?;
+
+        let restreams = context.state().restreams.get_cloned();
+        let changes = changes
+            .into_iter()
+            .map(|c| {
+                let restream = (c.kind != state::ChangeKind::Deleted)
+                    .then(|| restreams.iter().find(|r| r.id == c.restream_id))
+                    .flatten()
+                    .cloned();
+                RestreamChange {
+                    seq: c.seq,
+                    kind: if restream.is_some() {
+                        c.kind
+                    } else {
+                        state::ChangeKind::Deleted
+                    },
+                    restream_id: c.restream_id,
+                    restream,
+                }
+            })
+            .collect();
+        Ok(ChangesSince {
+            cursor: seq,
+            changes,
+        })
+    }
+
+    /// Returns all the `Restream`s removed into the trash of this server, so
+    /// may be restored via `Mutation.restoreRestream`.
+    ///
+    /// `Restream`s are purged from the trash automatically once stay there
+    /// longer than the configured retention period.
+    fn trashed_restreams(context: &Context) -> Vec<Restream> {
+        context.state().trash.get_cloned()
+    }
+
+    /// Returns all the `DstConflict`s present on this server at the moment,
+    /// when `Output`s of different `Restream`s re-stream to the same
+    /// downstream destination.
+    fn conflicts(context: &Context) -> Vec<DstConflict> {
+        context.state().dst_conflicts()
+    }
+
+    /// Returns total number of bytes sent by all the `Output`s of this server
+    /// since its start (or the last `Mutation.resetBytesSent`).
+    ///
+    /// Represented as `Float`, so is exact up to 2^53 bytes (~9 PB).
+    #[allow(clippy::cast_precision_loss)] // intended, see description
+    fn bytes_sent(context: &Context) -> f64 {
+        ffmpeg::Stats::global().bytes_sent(
+            context
+                .state()
+                .restreams
+                .lock_ref()
+                .iter()
+                .flat_map(|r| r.outputs.iter().map(|o| o.id)),
+        ) as f64
+    }
+
+    /// Returns list of recorded files of the specified `Output`.
+    ///
+    /// If returned list is empty, the there is no recorded files for the
+    /// specified `Output`.
+    ///
+    /// Each recorded file is represented as a relative path on [SRS] HTTP
+    /// server in `dvr/` directory, so the download link should look like this:
+    /// ```ignore
+    /// http://my.host:8080/dvr/returned/file/path.flv
+    /// ```
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[graphql(arguments(id(
+        description = "ID of the `Output` to return recorded files of."
+    )))]
+    async fn dvr_files(id: OutputId) -> Vec<String> {
+        dvr::Storage::global().list_files(id).await
+    }
+
+    /// Returns `Restream`s happening on this server and identifiable by the
+    /// given `ids` in an exportable JSON format.
+    ///
+    /// If no `ids` specified, then returns all the `Restream`s happening on
+    /// this server at the moment.
+    ///
+    /// Credentials (password hashes) are never exported, unless
+    /// `includeCredentials` is `true`, which requires `Role.ADMIN`. Such
+    /// export is a secret, so should be handled with care.
+    #[graphql(arguments(
+        ids(
+            description = "IDs of `Restream`s to be exported.\
+                           \n\n\
+                           If empty, then all the `Restream`s will be \
+                           exported."
+            default = Vec::new(),
+        ),
+        include_credentials(
+            description = "Indicator whether password hashes should be \
+                           exported too, for a full migration of this \
+                           server.",
+            default = false,
+        ),
+    ))]
+    fn export(
+        ids: Vec<RestreamId>,
+        include_credentials: bool,
+        context: &Context,
+    ) -> Result<Option<String>, graphql::Error> {
+        if include_credentials {
+            context.require_admin()?;
+        }
+        let settings = context.state().settings.get_cloned();
+        let credentials =
+            include_credentials.then(|| settings.export_credentials());
+        let settings = settings.export();
+        let restreams = context
+            .state()
+            .restreams
+            .get_cloned()
+            .into_iter()
+            .filter_map(|r| {
+                (ids.is_empty() || ids.contains(&r.id)).then(|| r.export())
+            })
+            .collect::<Vec<_>>();
+        (!restreams.is_empty())
+            .then(|| {
+                let spec: Spec = spec::v1::Spec {
+                    settings: Some(settings),
+                    credentials,
+                    restreams,
+                }
+                .into();
+                serde_json::to_string(&spec).map_err(|e| {
+                    anyhow!("Failed to JSON-serialize spec: {}", e).into()
+                })
+            })
+            .transpose()
+    }
+
+    /// Probes reachability of the given destination `url` exactly as
+    /// `Mutation.setOutput` does with `verify: true`: resolves its host and
+    /// connects to its port via TCP (without any protocol handshake), giving
+    /// up in 3 seconds.
+    ///
+    /// ### Errors
+    ///
+    /// Errors with `RATE_LIMITED` if too many probes have been performed
+    /// recently.
+    #[graphql(arguments(url(description = "Destination URL to be probed.")))]
+    async fn probe_url(
+        url: OutputDstUrl,
+        context: &Context,
+    ) -> Result<UrlProbe, graphql::Error> {
+        context.require_admin()?;
+        match reachability::probe(&url).await {
+            Ok(addr) => Ok(UrlProbe {
+                reachable: true,
+                address: addr.map(|a| a.to_string()),
+                reason: None,
+                error: None,
+            }),
+            Err(e) if e == reachability::ProbeError::RateLimited => {
+                Err(probe_error(e))
+            }
+            Err(e) => Ok(UrlProbe {
+                reachable: false,
+                address: None,
+                reason: Some(e.reason().into()),
+                error: Some(e.to_string()),
+            }),
+        }
+    }
+
+    /// Checks the configuration of this server against its running
+    /// environment (files, directories, [FFmpeg] capabilities), returning all
+    /// the found inconsistencies.
+    ///
+    /// Findings are advisory only: the configuration is applied regardless of
+    /// them.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    async fn check_configuration(context: &Context) -> Vec<check::Finding> {
+        check::run(context.state(), &dvr::Storage::global().root_path).await
+    }
+
+    /// Returns the leftovers of the removed `Restream`s (SRS clients still
+    /// connected to their apps, and files written for them), without
+    /// reclaiming them.
+    ///
+    /// Is a dry run of the periodic sweeping enabled by `--sweep-interval`
+    /// CLI option. Files of the `Restream`s being in trash are not considered
+    /// as leftovers, as they may be restored.
+    async fn leftovers(
+        context: &Context,
+    ) -> Result<Vec<sweep::Leftover>, graphql::Error> {
+        context.require_admin()?;
+
+        let dirs = sweep::Dirs::new(context.config());
+        Ok(sweep::find(&api::srs::Client, &dirs, context.state()).await)
+    }
+
+    /// Returns all the `Fault`s injected into this server (see `injectFault`
+    /// mutation), which haven't expired yet.
+    fn faults(context: &Context) -> Result<Vec<fault::Fault>, graphql::Error> {
+        context.require_admin()?;
+        Ok(fault::faults(context.state()))
+    }
+
+    /// Returns the [FFmpeg] command line (path to its binary followed by its
+    /// arguments), which is run for re-streaming the specified `Output` with
+    /// its current configuration, regardless whether it's enabled.
+    ///
+    /// Credentials in URLs (passwords, query parameters and stream keys of
+    /// remote RTMP endpoints) are masked, unless `revealSecrets` is `true`.
+    ///
+    /// Returns `null` if there is no `Output` with the specified ID, or its
+    /// `Restream` has no RTMP endpoint to pull a live stream from.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[graphql(arguments(
+        restream_id(
+            description = "ID of the `Restream` to return the command of the \
+                           `Output` in."
+        ),
+        output_id(
+            description = "ID of the `Output` to return the command of."
+        ),
+        reveal_secrets(
+            description = "Whether credentials in URLs should be shown \
+                           unmasked.",
+            default = false,
+        ),
+    ))]
+    async fn output_command(
+        restream_id: RestreamId,
+        output_id: OutputId,
+        reveal_secrets: bool,
+        context: &Context,
+    ) -> Result<Option<Vec<String>>, graphql::Error> {
+        context.require_admin()?;
+
+        let cfg = context.config();
+        let ffmpeg_path = fs::canonicalize(&cfg.ffmpeg_path)
+            .await
+            .unwrap_or_else(|_| cfg.ffmpeg_path.clone());
+        let buffers_dir = fs::canonicalize(&cfg.buffers_dir)
+            .await
+            .unwrap_or_else(|_| cfg.buffers_dir.clone());
+
+        let caps = context.state().ffmpeg_capabilities.get_cloned();
+        let restreams = context.state().restreams.lock_ref();
+        let cmd =
+            restreams
+                .iter()
+                .find(|r| r.id == restream_id)
+                .and_then(|r| {
+                    let output =
+                        r.outputs.iter().find(|o| o.id == output_id)?;
+                    ffmpeg::output_command(
+                        &ffmpeg_path,
+                        &buffers_dir,
+                        r,
+                        output,
+                        caps.as_deref(),
+                    )
+                });
+        Ok(cmd.map(|cmd| {
+            if reveal_secrets {
+                cmd
+            } else {
+                cmd.iter()
+                    .map(|a| ffmpeg::mask_secrets(a).into_owned())
+                    .collect()
+            }
+        }))
+    }
+
+    /// Returns all the [FFmpeg] processes managed by this server at the
+    /// moment, as its pool of re-streaming processes sees them.
+    ///
+    /// Is a debugging aid for cases when the reported statuses disagree with
+    /// the reality.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    fn pool_state(
+        context: &Context,
+    ) -> Result<Vec<PoolProcess>, graphql::Error> {
+        context.require_admin()?;
+
+        Ok(context.state().ffmpeg_pool.snapshot())
+    }
+}
+
+/// Root of all [GraphQL subscriptions][1] in the [`Schema`].
+///
+/// [1]: https://spec.graphql.org/June2018/#sec-Root-Operation-Types
+#[derive(Clone, Copy, Debug)]
+pub struct SubscriptionsRoot;
+
+#[graphql_subscription(name = "Subscription", context = Context)]
+impl SubscriptionsRoot {
+    /// Subscribes to updates of `Info` parameters of this server.
+    #[allow(clippy::cast_precision_loss)] // exact up to 2^53
+    async fn info(context: &Context) -> BoxStream<'static, Info> {
+        let public_host = context.config().public_host.clone().unwrap();
+        let srs_rtmp_port = context.config().srs_rtmp_port.into();
+        let http_flv = !context.config().no_http_flv;
+        let ws_max_connections = context
+            .config()
+            .ws_max_connections
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let ws_max_operations = context
+            .config()
+            .ws_max_operations
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let graphql_max_depth = context
+            .config()
+            .graphql_max_depth
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let graphql_max_complexity = context
+            .config()
+            .graphql_max_complexity
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let http_workers = context
+            .config()
+            .http_workers_count()
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let blocking_threads = context
+            .config()
+            .blocking_threads
+            .try_into()
+            .unwrap_or(i32::MAX);
+        let client_http_addr = context.config().client_http_addr().to_string();
+        let callback_http_addr =
+            context.config().callback_http_addr().map(|a| a.to_string());
+        let state = context.state();
+        let srs = context.srs().clone();
+        let changes = state.changes.clone();
+        let info = map_ref! {
+            let settings = state.settings.signal_cloned().dedupe_cloned(),
+            let log_level = state.log_level.signal(),
+            let draining = state.draining.signal(),
+            let playground_enabled = state.playground_enabled.signal(),
+            let ffmpeg_caps = state.ffmpeg_capabilities.signal_cloned(),
+            let ffmpeg_missing =
+                state.missing_ffmpeg_capabilities.signal_cloned(),
+            let spec_reload = state.spec_reload.signal_cloned(),
+            let replication = state.replication.signal_cloned(),
+            let quota = state.quota.signal(),
+            let usage =
+                state.restreams.signal_ref(|r| QuotaInfo::usage(r)).dedupe(),
+            let persistence = state
+                .persistence_health
+                .signal_ref(|h| (h.is_healthy(), h.error().map(str::to_owned)))
+                .dedupe_cloned(),
+            let srs_running = srs.status().running.signal(),
+            let srs_restarts = srs.status().restarts.signal() =>
+            (
+                settings.clone(),
+                *log_level,
+                *draining,
+                *playground_enabled,
+                FfmpegInfo::new(ffmpeg_caps.as_deref(), ffmpeg_missing),
+                spec_reload.clone(),
+                replication.clone(),
+                QuotaInfo::new(*quota, *usage),
+                persistence.clone(),
+                *srs_running,
+                *srs_restarts,
+            )
+        };
+        info.map(move |info| {
+            let (
+                s,
+                log_level,
+                draining,
+                playground_enabled,
+                ffmpeg,
+                spec_reload,
+                replication,
+                quota,
+                (persistence_healthy, persistence_error),
+                ..,
+            ) = info;
+            Info {
+                public_host: public_host.clone(),
+                srs_rtmp_port,
+                http_flv,
+                password_hash: s.password_hash,
+                title: s.title,
+                delete_confirmation: s.delete_confirmation,
+                enable_confirmation: s.enable_confirmation,
+                log_level,
+                draining,
+                playground_enabled,
+                persistence_healthy,
+                persistence_error,
+                srs: (&srs).into(),
+                ffmpeg,
+                spec_reload,
+                replication,
+                ws_connections: server::client::ws_connections()
+                    .try_into()
+                    .unwrap_or(i32::MAX),
+                ws_max_connections,
+                ws_max_operations,
+                graphql_max_depth,
+                graphql_max_complexity,
+                http_workers,
+                blocking_threads,
+                client_http_addr: client_http_addr.clone(),
+                callback_http_addr: callback_http_addr.clone(),
+                reject_dst_conflicts: s.reject_dst_conflicts,
+                display_timezone: s.display_timezone.map(Into::into),
+                quota,
+                change_seq: changes.seq(),
+            }
+        })
+        .to_stream()
+        .boxed()
+    }
+
+    /// Subscribes to updates of all `Restream`s happening on this server.
+    async fn all_restreams(
+        context: &Context,
+    ) -> BoxStream<'static, Vec<Restream>> {
+        let state = context.state();
+        // `Status`es are resolved from `State::statuses` on every emission, so
+        // their changes should re-emit `Restream`s too.
+        map_ref! {
+            let restreams = state.restreams.signal_cloned().dedupe_cloned(),
+            let _statuses = state.statuses.signal() => {
+                let mut restreams = restreams.clone();
+                state::sort_by_position(&mut restreams);
+                restreams
+            }
+        }
+        .to_stream()
+        .boxed()
+    }
+}
+
+/// `Restream`s changed since a cursor (see `Query.changesSince`).
+#[derive(Clone, Debug, GraphQLObject)]
+#[graphql(context = Context)]
+pub struct ChangesSince {
+    /// Sequence number to be passed as a cursor to `Query.changesSince` the
+    /// next time.
+    pub cursor: state::ChangeSeq,
+
+    /// Changes of `Restream`s, ordered by their sequence numbers.
+    pub changes: Vec<RestreamChange>,
+}
+
+/// Change of a `Restream` (see `Query.changesSince`).
+#[derive(Clone, Debug, GraphQLObject)]
+#[graphql(context = Context)]
+pub struct RestreamChange {
+    /// Sequence number this change has happened at the last time.
+    pub seq: state::ChangeSeq,
+
+    /// Kind of this change.
+    pub kind: state::ChangeKind,
+
+    /// ID of the changed `Restream`.
+    pub restream_id: RestreamId,
+
+    /// Latest configuration of the changed `Restream`.
+    ///
+    /// `null` if it has been deleted.
+    pub restream: Option<Restream>,
+}
+
+/// New `Restream` awaiting a live stream being pushed into its `Input` (see
+/// `Mutation.createInput`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewPushInput {
+    /// Unique (case-insensitively) key of the `Restream`.
+    ///
+    /// Should meet `[a-zA-Z0-9_-]{1,64}` format, and should not start with
+    /// `pull_` prefix.
+    pub key: RestreamKey,
+
+    /// Optional label of the `Restream`.
+    pub label: Option<Label>,
+
+    /// Optional group to put the `Restream` into.
+    pub group: Option<RestreamGroup>,
+
+    /// ID to create the `Restream` with, instead of a random one.
+    pub id: Option<RestreamId>,
+
+    /// Indicator whether the `Restream` should have a backup endpoint to
+    /// push a live stream into.
+    ///
+    /// If not specified then `false`.
+    pub with_backup: Option<bool>,
+
+    /// Indicator whether the `Restream` should have an additional endpoint
+    /// for serving a live stream via HLS.
+    ///
+    /// If not specified then `false`.
+    pub with_hls: Option<bool>,
+
+    /// Indicator whether concurrent publishers should be queued.
+    ///
+    /// If not specified then `false`.
+    pub queue_publishers: Option<bool>,
+
+    /// Policy of resolving concurrent publishing of a live stream.
+    ///
+    /// If not specified then `REJECT_NEW` is used.
+    pub publisher_policy: Option<PublisherPolicy>,
+
+    /// Host to be used in public URLs of the `Restream` instead of the
+    /// globally configured one.
+    pub public_host_override: Option<PublicHost>,
+
+    /// Number of seconds the `Input` should stay idle continuously for, to be
+    /// disabled automatically.
+    pub auto_disable_after_idle_secs: Option<i32>,
+
+    /// Maximum number of seconds a single publishing session may last for.
+    pub max_session_secs: Option<i32>,
+
+    /// Indicator whether the `Input` should be disabled once a publishing
+    /// session exceeds `maxSessionSecs`.
+    ///
+    /// If not specified then `false`.
+    pub disable_on_expiry: Option<bool>,
+
+    /// Indicator whether the `Restream` should be served with low latency
+    /// (see `Mutation.setRestreamLowLatency`).
+    ///
+    /// If not specified then `false`.
+    pub low_latency: Option<bool>,
+
+    /// Indicator whether the `Restream` should be listed on the public status
+    /// page (see `Mutation.setRestreamPublic`).
+    ///
+    /// If not specified then `false`.
+    pub public: Option<bool>,
+}
+
+impl NewPushInput {
+    /// Converts this [`NewPushInput`] into [`RestreamArgs`] of a new
+    /// [`Restream`].
+    fn into_args(self) -> RestreamArgs {
+        RestreamArgs {
+            key: self.key,
+            label: self.label,
+            group: self.group,
+            src: None,
+            file_src: None,
+            loop_file_src: true,
+            backup_src: None,
+            with_backup: self.with_backup.unwrap_or_default(),
+            with_hls: self.with_hls.unwrap_or_default(),
+            max_pull_lifetime: None,
+            connect_timeout_secs: None,
+            idle_timeout_secs: None,
+            schedule: None,
+            rtmp_options: None,
+            queue_publishers: self.queue_publishers.unwrap_or_default(),
+            publisher_policy: self.publisher_policy,
+            public_host_override: self.public_host_override,
+            auto_disable_after_idle_secs: self.auto_disable_after_idle_secs,
+            max_session_secs: self.max_session_secs,
+            disable_on_expiry: self.disable_on_expiry.unwrap_or_default(),
+            id: None,
+            new_id: self.id,
+        }
+    }
+}
+
+/// New `Restream` pulling a live stream into its `Input` from a remote URL
+/// (see `Mutation.createInput`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewPullInput {
+    /// Unique (case-insensitively) key of the `Restream`.
+    ///
+    /// Should meet `[a-zA-Z0-9_-]{1,64}` format, and should not start with
+    /// `pull_` prefix.
+    pub key: RestreamKey,
+
+    /// Optional label of the `Restream`.
+    pub label: Option<Label>,
+
+    /// Optional group to put the `Restream` into.
+    pub group: Option<RestreamGroup>,
+
+    /// ID to create the `Restream` with, instead of a random one.
+    pub id: Option<RestreamId>,
+
+    /// URL to pull a live stream from.
+    ///
+    /// Another `Restream` may be referred as `ephyr://restream/<id>` to pull
+    /// its live stream locally.
+    pub src: InputSrcUrl,
+
+    /// URL to pull a live stream from for a backup endpoint.
+    ///
+    /// If not specified then the `Restream` has no backup endpoint.
+    pub backup_src: Option<InputSrcUrl>,
+
+    /// Indicator whether the `Restream` should have an additional endpoint
+    /// for serving a live stream via HLS.
+    ///
+    /// If not specified then `false`.
+    pub with_hls: Option<bool>,
+
+    /// Maximum number of seconds a single pulling session may last for,
+    /// before being restarted.
+    pub max_pull_lifetime: Option<i32>,
+
+    /// Number of seconds to wait for the `src` to be connected to.
+    pub connect_timeout_secs: Option<i32>,
+
+    /// Number of seconds to wait for data from the connected `src`.
+    pub idle_timeout_secs: Option<i32>,
+
+    /// Recurring weekly schedule (in UTC) to pull the live stream within only
+    /// (see `Mutation.setInputSchedule`).
+    ///
+    /// If not specified or empty then the live stream is pulled at any time.
+    pub schedule: Option<Vec<NewScheduleRange>>,
+
+    /// Additional options of pulling the live stream from the RTMP `src`
+    /// (see `Mutation.setInputRtmpOptions`).
+    ///
+    /// If not specified then no additional options are used.
+    pub rtmp_options: Option<NewRtmpPullOptions>,
+
+    /// Host to be used in public URLs of the `Restream` instead of the
+    /// globally configured one.
+    pub public_host_override: Option<PublicHost>,
+
+    /// Indicator whether the `Restream` should be served with low latency
+    /// (see `Mutation.setRestreamLowLatency`).
+    ///
+    /// If not specified then `false`.
+    pub low_latency: Option<bool>,
+
+    /// Indicator whether the `Restream` should be listed on the public status
+    /// page (see `Mutation.setRestreamPublic`).
+    ///
+    /// If not specified then `false`.
+    pub public: Option<bool>,
+}
+
+impl NewPullInput {
+    /// Converts this [`NewPullInput`] into [`RestreamArgs`] of a new
+    /// [`Restream`].
+    fn into_args(self) -> RestreamArgs {
+        RestreamArgs {
+            key: self.key,
+            label: self.label,
+            group: self.group,
+            src: Some(self.src),
+            file_src: None,
+            loop_file_src: true,
+            with_backup: self.backup_src.is_some(),
+            backup_src: self.backup_src,
+            with_hls: self.with_hls.unwrap_or_default(),
+            max_pull_lifetime: self.max_pull_lifetime,
+            connect_timeout_secs: self.connect_timeout_secs,
+            idle_timeout_secs: self.idle_timeout_secs,
+            schedule: self.schedule,
+            rtmp_options: self.rtmp_options,
+            queue_publishers: false,
+            publisher_policy: None,
+            public_host_override: self.public_host_override,
+            auto_disable_after_idle_secs: None,
+            max_session_secs: None,
+            disable_on_expiry: false,
+            id: None,
+            new_id: self.id,
+        }
+    }
+}
+
+/// New `Output` of a `Restream` (see `Mutation.createOutput`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewOutput {
+    /// Destination URL to re-stream a live stream onto.
+    pub dst: OutputDstUrl,
+
+    /// Optional label of the `Output`.
+    pub label: Option<Label>,
+
+    /// Optional URL of the `Output`'s live stream preview.
+    pub preview_url: Option<Url>,
+
+    /// `MixinSrcUrl`s to mix into the `Output`.
+    ///
+    /// If not specified then nothing is mixed in.
+    pub mixins: Option<Vec<MixinSrcUrl>>,
+
+    /// ID to create the `Output` with, instead of a random one.
+    pub id: Option<OutputId>,
+
+    /// Indicator whether the `dst` should be probed for reachability before
+    /// accepting it (see `Query.probeUrl`).
+    ///
+    /// If not specified then `false`.
+    pub verify: Option<bool>,
+
+    /// Backup downstream URL to re-stream to (see
+    /// `Mutation.setOutputBackupDst`).
+    pub backup_dst: Option<OutputDstUrl>,
+
+    /// Preset of the streaming platform to transcode the live stream for (see
+    /// `Mutation.setOutputConditioning`).
+    ///
+    /// If not specified then the live stream is re-streamed "as is".
+    pub conditioning: Option<OutputConditioningPreset>,
+
+    /// Sample rate to re-encode audio with, along with the `conditioning`.
+    ///
+    /// If not specified then the default one of the `conditioning` preset is
+    /// used. Ignored if the `conditioning` is not specified.
+    pub audio_sample_rate: Option<AudioSampleRate>,
+
+    /// Metadata to be injected into the re-streamed live stream (see
+    /// `Mutation.setOutputMetadata`).
+    pub metadata: Option<NewOutputMetadata>,
+
+    /// Target loudness to normalize the audio to (see
+    /// `Mutation.setOutputLoudnorm`).
+    ///
+    /// If not specified then the audio is not normalized.
+    pub loudnorm: Option<NewLoudnessTarget>,
+
+    /// Limits of OS resources the re-streaming process is allowed to consume
+    /// (see `Mutation.setOutputLimits`).
+    pub limits: Option<NewOutputLimits>,
+
+    /// Key of the `Input` whose live stream should be re-streamed (see
+    /// `Mutation.setOutputSrcStream`).
+    ///
+    /// If not specified then the live stream of the main `Restream.input` is
+    /// re-streamed.
+    pub src_stream: Option<InputKey>,
+
+    /// Number of seconds to delay the `Output` for, not greater than `300`
+    /// (see `Mutation.setOutputDelay`).
+    pub delay_secs: Option<i32>,
+
+    /// Maximum bitrate in kbit/s to re-stream with (see
+    /// `Mutation.setOutputMaxBitrate`).
+    pub max_bitrate_kbps: Option<i32>,
+
+    /// Indicator whether the `Output` should be prewarmed (see
+    /// `Mutation.setOutputPrewarm`).
+    ///
+    /// If not specified then `false`.
+    pub prewarm: Option<bool>,
+
+    /// Strategy of keeping the `Output` connected to its destination while
+    /// being prewarmed (see `Mutation.setOutputPrewarm`).
+    ///
+    /// If not specified then `SLATE`.
+    pub prewarm_strategy: Option<PrewarmStrategy>,
+
+    /// Indicator whether the `Output` should drop frames when falling behind
+    /// (see `Mutation.setOutputDropFramesWhenBehind`).
+    ///
+    /// If not specified then `false`.
+    pub drop_frames_when_behind: Option<bool>,
+
+    /// Policy of enabling the `Output` on server boot (see
+    /// `Mutation.setOutputOnBoot`).
+    pub on_boot: Option<OutputBootPolicy>,
+
+    /// Indicator whether the `Output` should be enabled right away.
+    ///
+    /// If not specified then `false`.
+    pub enabled: Option<bool>,
+}
+
+/// New `OutputMetadata` of an `Output` (see `Mutation.setOutputMetadata`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewOutputMetadata {
+    /// Title of the live stream.
+    pub title: Option<String>,
+
+    /// Author of the live stream.
+    pub author: Option<String>,
+
+    /// Arbitrary comment to the live stream.
+    pub comment: Option<String>,
+}
+
+/// New `LoudnessTarget` of an `Output` (see `Mutation.setOutputLoudnorm`).
+#[derive(Clone, Copy, Debug, GraphQLInputObject)]
+pub struct NewLoudnessTarget {
+    /// Integrated loudness in LUFS to normalize to, within `-70.0..=-5.0`
+    /// range.
+    pub integrated: f64,
+
+    /// Maximum true peak in dBTP, within `-9.0..=0.0` range.
+    ///
+    /// If not specified then `-1.0`.
+    pub true_peak: Option<f64>,
+}
+
+/// New `OutputLimits` of an `Output` (see `Mutation.setOutputLimits`).
+#[derive(Clone, Copy, Debug, GraphQLInputObject)]
+pub struct NewOutputLimits {
+    /// Niceness of the re-streaming process, within `0..=19` range.
+    pub nice: Option<i32>,
+
+    /// Maximum CPU usage of the re-streaming process, in percents of a single
+    /// CPU core.
+    ///
+    /// Cannot exceed `100` multiplied by the number of CPU cores of the
+    /// server.
+    pub cpu_quota: Option<i32>,
+}
+
+/// New `ScheduleRange` of an `Input.schedule` (see
+/// `Mutation.setInputSchedule`), in UTC unless a time zone is specified for
+/// it.
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewScheduleRange {
+    /// Day of a week the `ScheduleRange` starts on.
+    pub weekday: Weekday,
+
+    /// Time of the day the `ScheduleRange` starts at, formatted as `HH:MM`.
+    pub start: String,
+
+    /// Time of the day the `ScheduleRange` ends at (exclusively), formatted
+    /// as `HH:MM`.
+    ///
+    /// If it's not after the `start`, then the `ScheduleRange` spans
+    /// midnight, ending on the next day.
+    pub end: String,
+}
+
+/// New `RtmpPullOptions` of an `Input` (see `Mutation.setInputRtmpOptions`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewRtmpPullOptions {
+    /// Stream name to play, overriding the one in the path of the
+    /// `RemoteInputSrc.url`.
+    pub playpath: Option<String>,
+
+    /// URL of the SWF player to report to the remote server.
+    pub swf_url: Option<Url>,
+
+    /// URL of the web page the live stream is played on to report to the
+    /// remote server.
+    pub page_url: Option<Url>,
+
+    /// Query string with token parameters (like `token=...&e=...`) to be
+    /// appended to the `playpath`, or to the `RemoteInputSrc.url` if there
+    /// is none.
+    pub token_query: Option<String>,
+}
+
+/// New `OutputConstraints` of an `Output` (see
+/// `Mutation.setOutputConstraints`).
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct NewOutputConstraints {
+    /// Preset of the streaming platform to take the constraints not
+    /// specified explicitly from.
+    pub preset: Option<OutputConditioningPreset>,
+
+    /// Video codecs (like `h264` or `hevc`) accepted by the destination.
+    pub video_codecs: Option<Vec<String>>,
+
+    /// Audio codecs (like `aac` or `mp3`) accepted by the destination.
+    pub audio_codecs: Option<Vec<String>>,
+
+    /// Maximum number of audio channels accepted by the destination.
+    pub max_audio_channels: Option<i32>,
+
+    /// Maximum width (in pixels) of the video accepted by the destination.
+    ///
+    /// Is checked with SRS 4 or newer only, as SRS 3 doesn't report it.
+    pub max_width: Option<i32>,
+
+    /// Maximum height (in pixels) of the video accepted by the destination.
+    ///
+    /// Is checked with SRS 4 or newer only, as SRS 3 doesn't report it.
+    pub max_height: Option<i32>,
+
+    /// Indicator whether an incompatible live stream should not be
+    /// re-streamed to the destination at all.
+    ///
+    /// Live stream is not re-streamed until its codecs are known either.
+    ///
+    /// If not specified then `false`.
+    pub enforce: Option<bool>,
+}
+
+/// Information about the client accessing this server.
+#[derive(Clone, Copy, Debug, GraphQLObject)]
+pub struct Me {
+    /// `Role` the client has been authorized with.
+    pub role: Role,
+}
+
+/// Result of probing reachability of a destination URL.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct UrlProbe {
+    /// Indicator whether the URL is reachable.
+    pub reachable: bool,
+
+    /// Address the URL has been reached on.
+    ///
+    /// `null` if the URL is unreachable, or is a local file.
+    pub address: Option<String>,
+
+    /// Literal code of the stage the probing has failed on: `NO_HOST`,
+    /// `NO_PORT`, `DNS`, `CONNECT` or `TIMEOUT`.
+    ///
+    /// `null` if the URL is reachable.
+    pub reason: Option<String>,
+
+    /// Human-readable description of the failure.
+    ///
+    /// `null` if the URL is reachable.
+    pub error: Option<String>,
+}
+
+/// Information about parameters that this server operates with.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct Info {
+    /// Host that this server is reachable via in public.
+    ///
+    /// Use it for constructing URLs to this server.
+    pub public_host: String,
+
+    /// Port that this server accepts RTMP connections on.
+    ///
+    /// Use it for constructing RTMP URLs to this server.
+    pub srs_rtmp_port: i32,
+
+    /// Indicator whether live streams of `Input`s are served via [HTTP-FLV]
+    /// for previewing.
+    ///
+    /// Use it for constructing preview URLs like
+    /// `http://{publicHost}:8000/{restream.key}/{input.key}.flv`.
+    ///
+    /// [HTTP-FLV]: https://github.com/ossrs/srs/wiki/v3_EN_DeliveryHttpStream
+    pub http_flv: bool,
+
+    /// Title of the server
+    pub title: Option<String>,
+
+    /// Whether do we need to confirm deletion of inputs and outputs
+    pub delete_confirmation: Option<bool>,
+
+    /// Whether do we need to confirm enabling/disabling of inputs or outputs
+    pub enable_confirmation: Option<bool>,
+
+    /// Current verbosity level of this server's logs.
+    pub log_level: LogLevel,
+
+    /// Indicator whether this server is in drain mode, so rejects new
+    /// publishers and doesn't spawn new re-streaming processes.
+    pub draining: bool,
+
+    /// Indicator whether this server serves [GraphQL Playground][1].
+    ///
+    /// [1]: https://github.com/graphql/graphql-playground
+    pub playground_enabled: bool,
+
+    /// Indicator whether this server persists its state successfully.
+    ///
+    /// `false` means that persisting has failed several times in a row (the
+    /// disk is full or read-only, for example), so any changes made since
+    /// then won't survive a restart of this server. Persisting is retried
+    /// until it succeeds.
+    pub persistence_healthy: bool,
+
+    /// Error of the last failed persisting of this server's state.
+    ///
+    /// `null` if `persistenceHealthy` is `true`.
+    pub persistence_error: Option<String>,
+
+    /// Information about [SRS] server used by this server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub srs: SrsInfo,
+
+    /// Information about [FFmpeg] binary used by this server.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub ffmpeg: FfmpegInfo,
+
+    /// Result of the last `Spec` reloading, triggered by `SIGHUP` signal.
+    ///
+    /// `null` if no reloading has happened yet.
+    pub spec_reload: Option<SpecReload>,
+
+    /// Status of replicating the state from a primary server, if this server
+    /// is a standby one (started with `--replicate-from` option).
+    ///
+    /// `null` if this server is not a standby one.
+    pub replication: Option<Replication>,
+
+    /// Number of currently established GraphQL over WebSocket connections to
+    /// this server.
+    pub ws_connections: i32,
+
+    /// Maximum number of simultaneous GraphQL over WebSocket connections that
+    /// this server accepts.
+    pub ws_max_connections: i32,
+
+    /// Maximum number of simultaneous operations (like subscriptions) that
+    /// this server runs for a single GraphQL over WebSocket connection.
+    pub ws_max_operations: i32,
+
+    /// Maximum nesting depth of fields in a single GraphQL operation that
+    /// this server executes.
+    pub graphql_max_depth: i32,
+
+    /// Maximum complexity (total number of selected fields, with fragments
+    /// expanded) of a single GraphQL operation that this server executes.
+    pub graphql_max_complexity: i32,
+
+    /// Number of worker threads that each HTTP server of this server handles
+    /// requests with.
+    pub http_workers: i32,
+
+    /// Maximum number of threads that this server performs blocking
+    /// operations (like password hashing or filesystem access) on.
+    pub blocking_threads: i32,
+
+    /// Address that the client HTTP server of this server is bound to.
+    pub client_http_addr: String,
+
+    /// Address that the callback HTTP server of this server is bound to.
+    ///
+    /// `null` if [SRS] HTTP callbacks are served by the client HTTP server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub callback_http_addr: Option<String>,
+
+    /// Indicator whether `Output`s re-streaming to the same downstream
+    /// destination as `Output`s of other `Restream`s do are rejected.
+    pub reject_dst_conflicts: bool,
+
+    /// IANA name of the time zone (like `Europe/Kyiv`) that clients should
+    /// display timestamps and schedules in.
+    ///
+    /// `null` means UTC. All the timestamps are reported in UTC regardless.
+    pub display_timezone: Option<String>,
+
+    /// Maximum numbers of `Restream`s and `Output`s allowed on this server,
+    /// along with their current usage.
+    pub quota: QuotaInfo,
+
+    /// Current sequence number of persisted changes of this server, to be
+    /// passed as a cursor to `Query.changesSince`.
+    ///
+    /// Keeps increasing across restarts.
+    ///
+    /// Lags behind mutations slightly, as changes are journaled
+    /// asynchronously, so should be read before `Query.allRestreams` when
+    /// doing a full resync (see `Query.changesSince`).
+    pub change_seq: state::ChangeSeq,
+
+    /// [Argon2] hash of the password that this server's GraphQL API is
+    /// protected with, if any.
+    ///
+    /// Non-`null` value means that any request to GraphQL API should perform
+    /// [HTTP Basic auth][1]. Any username is allowed, but the password should
+    /// match this hash.
+    ///
+    /// [Argon2]: https://en.wikipedia.org/wiki/Argon2
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    pub password_hash: Option<String>,
+}
+
+/// Information about [SRS] server used by this server.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Debug)]
+pub struct SrsInfo {
+    /// Version of [SRS] server binary, if it has been detected.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub version: Option<String>,
+
+    /// Indicator whether [SRS] server process is running at the moment.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub running: bool,
+
+    /// ID of the running [SRS] server process, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub pid: Option<u32>,
+
+    /// Moment when the running [SRS] server process has been started, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub started_at: Option<Instant>,
+
+    /// Number of times [SRS] server process has been restarted after its
+    /// unexpected exit.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub restarts: u32,
+
+    /// [`srs::Tuning`] of [SRS] server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub tuning: srs::Tuning,
+}
+
+impl From<&srs::Server> for SrsInfo {
+    #[inline]
+    fn from(srs: &srs::Server) -> Self {
+        let status = srs.status();
+        Self {
+            version: srs.version().map(ToOwned::to_owned),
+            running: status.running.get(),
+            pid: status.pid.get(),
+            started_at: status.started_at.get(),
+            restarts: status.restarts.get(),
+            tuning: srs.tuning(),
+        }
+    }
+}
+
+#[graphql_object]
+impl SrsInfo {
+    /// Version of [SRS] server binary, if it has been detected.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Indicator whether [SRS] server process is running at the moment.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn running(&self) -> bool {
+        self.running
+    }
+
+    /// ID of the running [SRS] server process, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn pid(&self) -> Option<i32> {
+        self.pid.and_then(|pid| pid.try_into().ok())
+    }
+
+    /// Number of seconds the running [SRS] server process is up for, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn uptime(&self) -> Option<i32> {
+        self.started_at
+            .map(|at| at.elapsed().as_secs().try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Number of times [SRS] server process has been restarted after its
+    /// unexpected exit.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn restarts(&self) -> i32 {
+        self.restarts.try_into().unwrap_or(i32::MAX)
+    }
+
+    /// Active tunables of [SRS] server, affecting latency of live streams.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn tuning(&self) -> SrsTuning {
+        self.tuning.into()
+    }
+
+    /// Indicator whether [HTTP API][1] of [SRS] server responds at the moment.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+    async fn http_api_responds(&self) -> bool {
+        if !self.running {
+            return false;
+        }
+        time::timeout(Duration::from_secs(1), api::srs::Client::ping())
+            .await
+            .map_or(false, |r| r.is_ok())
+    }
+}
+
+/// Tunables of [SRS] server, affecting latency of live streams passing
+/// through it.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Copy, Debug, GraphQLObject)]
+pub struct SrsTuning {
+    /// Size (in bytes) of RTMP chunks.
+    pub chunk_size: i32,
+
+    /// Maximum length (in seconds) of the queue of a single player.
+    pub queue_length: i32,
+
+    /// Indicator whether merged-read of publishers is enabled.
+    pub mr: bool,
+
+    /// Indicator whether [SRS] prefers lower latency over throughput.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub min_latency: bool,
+
+    /// Indicator whether the last GOP is cached for players to start from.
+    pub gop_cache: bool,
+}
+
+impl From<srs::Tuning> for SrsTuning {
+    #[inline]
+    fn from(t: srs::Tuning) -> Self {
+        Self {
+            chunk_size: t.chunk_size.try_into().unwrap_or(i32::MAX),
+            queue_length: t.queue_length.try_into().unwrap_or(i32::MAX),
+            mr: t.mr,
+            min_latency: t.min_latency,
+            gop_cache: t.gop_cache,
+        }
+    }
+}
+
+/// Information about [FFmpeg] binary used by this server.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct FfmpegInfo {
+    /// Version of [FFmpeg] binary, if it has been detected.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub version: Option<String>,
+
+    /// Capabilities required by the current re-streaming, but missing in
+    /// [FFmpeg] binary, so the re-streaming processes requiring them are not
+    /// run.
+    ///
+    /// Empty list means that [FFmpeg] binary is suitable for the current
+    /// re-streaming.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub missing_capabilities: Vec<String>,
+}
+
+impl FfmpegInfo {
+    /// Creates a new [`FfmpegInfo`] out of the given detected
+    /// [`ffmpeg::Capabilities`] and the `missing` [`ffmpeg::Requirement`]s.
+    #[inline]
+    #[must_use]
+    fn new(
+        caps: Option<&ffmpeg::Capabilities>,
+        missing: &[ffmpeg::Requirement],
+    ) -> Self {
+        Self {
+            version: caps.and_then(|c| c.version.clone()),
+            missing_capabilities: missing
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// Information about [`state::Quota`] of this server and its current usage.
+#[derive(Clone, Copy, Debug, GraphQLObject)]
+pub struct QuotaInfo {
+    /// Maximum number of `Restream`s allowed on this server.
+    ///
+    /// `null` means unlimited.
+    pub max_restreams: Option<i32>,
+
+    /// Maximum number of `Output`s allowed in a single `Restream` on this
+    /// server.
+    ///
+    /// `null` means unlimited.
+    pub max_outputs_per_restream: Option<i32>,
+
+    /// Current number of `Restream`s on this server.
+    pub restreams: i32,
+
+    /// Current number of `Output`s in the `Restream` having the most of them.
+    pub max_outputs: i32,
+}
+
+impl QuotaInfo {
+    /// Creates a new [`QuotaInfo`] out of the given [`state::Quota`] and its
+    /// current `usage` (see [`QuotaInfo::usage()`]).
+    #[inline]
+    #[must_use]
+    fn new(quota: state::Quota, usage: (usize, usize)) -> Self {
+        let to_i32 = |n: u32| n.try_into().unwrap_or(i32::MAX);
+        Self {
+            max_restreams: quota.max_restreams.map(to_i32),
+            max_outputs_per_restream: quota
+                .max_outputs_per_restream
+                .map(to_i32),
+            restreams: usage.0.try_into().unwrap_or(i32::MAX),
+            max_outputs: usage.1.try_into().unwrap_or(i32::MAX),
+        }
+    }
+
+    /// Calculates the current usage of [`state::Quota`] by the given
+    /// `restreams`: their number, and the maximum number of [`Output`]s in a
+    /// single [`Restream`].
+    #[must_use]
+    fn usage(restreams: &[Restream]) -> (usize, usize) {
+        let max_outputs =
+            restreams.iter().map(|r| r.outputs.len()).max().unwrap_or(0);
+        (restreams.len(), max_outputs)
+    }
+}
+
+/// Arguments of `Mutation.setRestream` describing a [`spec::v1::Restream`] to
+/// be set.
+///
+/// Each field means the same as the argument of `Mutation.setRestream` having
+/// the same name.
+#[derive(Clone, Debug)]
+struct RestreamArgs {
+    key: RestreamKey,
+    label: Option<Label>,
+    group: Option<RestreamGroup>,
+    src: Option<InputSrcUrl>,
+    file_src: Option<String>,
+    loop_file_src: bool,
+    backup_src: Option<InputSrcUrl>,
+    with_backup: bool,
+    with_hls: bool,
+    max_pull_lifetime: Option<i32>,
+    connect_timeout_secs: Option<i32>,
+    idle_timeout_secs: Option<i32>,
+    schedule: Option<Vec<NewScheduleRange>>,
+    rtmp_options: Option<NewRtmpPullOptions>,
+    queue_publishers: bool,
+    publisher_policy: Option<PublisherPolicy>,
+    public_host_override: Option<PublicHost>,
+    auto_disable_after_idle_secs: Option<i32>,
+    max_session_secs: Option<i32>,
+    disable_on_expiry: bool,
+    id: Option<RestreamId>,
+    new_id: Option<RestreamId>,
+}
+
+/// Validates the given [`RestreamArgs`] and builds a [`spec::v1::Restream`]
+/// out of them, preserving the settings of the edited [`Restream`] (if any)
+/// being set via dedicated mutations only.
+///
+/// # Errors
+///
+/// If any of the [`RestreamArgs`] is invalid.
+#[allow(clippy::too_many_lines)]
+async fn restream_spec(
+    args: RestreamArgs,
+    context: &Context,
+) -> Result<spec::v1::Restream, graphql::Error> {
+    let RestreamArgs {
+        key,
+        label,
+        group,
+        src,
+        file_src,
+        loop_file_src,
+        backup_src,
+        with_backup,
+        with_hls,
+        max_pull_lifetime,
+        connect_timeout_secs,
+        idle_timeout_secs,
+        schedule,
+        rtmp_options,
+        queue_publishers,
+        publisher_policy,
+        public_host_override,
+        auto_disable_after_idle_secs,
+        max_session_secs,
+        disable_on_expiry,
+        id,
+        new_id,
+    } = args;
+    if id.is_some() && new_id.is_some() {
+        return Err(graphql::Error::new("AMBIGUOUS_ID")
+            .status(StatusCode::BAD_REQUEST)
+            .message("Only one of `id` or `newId` may be specified"));
+    }
+    // Keys persisted by older versions are kept as is, even if they
+    // violate the current rules.
+    let is_persisted = id.map_or(false, |id| {
+        context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .any(|r| r.id == id && r.key == key)
+    });
+    if !is_persisted {
+        RestreamKey::validate(&key).map_err(|e| {
+            graphql::Error::new("INVALID_RESTREAM_KEY")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!("Restream.key {}", e))
+        })?;
+    }
+
+    let max_pull_lifetime = max_pull_lifetime
+        .map(|secs| {
+            positive_secs(secs)
+                .map(|secs| Duration::from_secs(secs.into()))
+                .ok_or_else(|| {
+                    graphql::Error::new("INVALID_MAX_PULL_LIFETIME")
+                        .status(StatusCode::BAD_REQUEST)
+                        .message("Max pull lifetime must be positive")
+                })
+        })
+        .transpose()?;
+    let connect_timeout_secs = connect_timeout_secs
+        .map(|secs| {
+            positive_secs(secs).ok_or_else(|| {
+                graphql::Error::new("INVALID_CONNECT_TIMEOUT")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message("Connect timeout must be positive")
+            })
+        })
+        .transpose()?;
+    let idle_timeout_secs = idle_timeout_secs
+        .map(|secs| {
+            positive_secs(secs).ok_or_else(|| {
+                graphql::Error::new("INVALID_IDLE_TIMEOUT")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message("Idle timeout must be positive")
+            })
+        })
+        .transpose()?;
+    let auto_disable_after_idle_secs = auto_disable_after_idle_secs
+        .map(|secs| {
+            positive_secs(secs).ok_or_else(|| {
+                graphql::Error::new("INVALID_AUTO_DISABLE_AFTER_IDLE")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message("Auto-disable idle period must be positive")
+            })
+        })
+        .transpose()?;
+    let max_session_secs = max_session_secs
+        .map(|secs| {
+            positive_secs(secs).ok_or_else(|| {
+                graphql::Error::new("INVALID_MAX_SESSION")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message("Max session duration must be positive")
+            })
+        })
+        .transpose()?;
+
+    for upstream in [&src, &backup_src]
+        .iter()
+        .filter_map(|s| s.as_ref()?.restream_id())
+    {
+        let exists = context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .any(|r| r.id == upstream);
+        if !exists {
+            return Err(graphql::Error::new("UNKNOWN_UPSTREAM_RESTREAM")
+                .status(StatusCode::NOT_FOUND)
+                .message(&format!(
+                    "Chained Restream {} doesn't exist",
+                    upstream,
+                )));
+        }
+        if id
+            .map_or(false, |id| context.state().forms_chain_cycle(id, upstream))
+        {
+            return Err(graphql::Error::new("CYCLIC_RESTREAMS_CHAIN")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!(
+                    "Pulling from Restream {} forms a cycle",
+                    upstream,
+                )));
+        }
+    }
+
+    // `Input.schedule` is set via `Mutation.setInputSchedule` only, so should
+    // be preserved on editing, unless the `Input` doesn't pull anymore.
+    let schedule = match schedule.map(input_schedule).transpose()?.flatten() {
+        Some(_) if src.is_none() => {
+            return Err(graphql::Error::new("INVALID_SCHEDULE")
+                .status(StatusCode::BAD_REQUEST)
+                .message("Only a pulling Input may have a schedule"))
+        }
+        Some(s) => Some(s),
+        None => id.filter(|_| src.is_some()).and_then(|id| {
+            context
+                .state()
+                .restreams
+                .lock_ref()
+                .iter()
+                .find(|r| r.id == id)
+                .and_then(|r| r.input.schedule.clone())
+        }),
+    };
+
+    // `Input.rtmpOptions` are preserved on editing (unless set explicitly),
+    // unless the `Input` doesn't pull from a remote RTMP URL anymore.
+    let pulls_rtmp = src.as_ref().map_or(false, InputSrcUrl::is_rtmp);
+    let rtmp_options =
+        match rtmp_options.map(rtmp_pull_options).transpose()?.flatten() {
+            Some(_) if !pulls_rtmp => return Err(graphql::Error::new(
+                "INVALID_RTMP_OPTIONS",
+            )
+            .status(StatusCode::BAD_REQUEST)
+            .message(
+                "Only an Input pulling from a remote RTMP URL may have \
+                     RTMP options",
+            )),
+            Some(o) => Some(o),
+            None => id.filter(|_| pulls_rtmp).and_then(|id| {
+                let restreams = context.state().restreams.lock_ref();
+                let input = &restreams.iter().find(|r| r.id == id)?.input;
+                match &input.src {
+                    Some(InputSrc::Failover(s)) => s.inputs.first(),
+                    _ => Some(input),
+                }
+                .and_then(|i| i.rtmp_options.clone())
+            }),
+        };
+
+    let src = match (src, file_src) {
+        (Some(_), Some(_)) => {
+            return Err(graphql::Error::new("AMBIGUOUS_INPUT_SRC")
+                .status(StatusCode::BAD_REQUEST)
+                .message("Only one of `src` or `fileSrc` may be specified"))
+        }
+        (Some(url), None) => Some(spec::v1::InputSrc::RemoteUrl(url)),
+        (None, Some(path)) => {
+            text::check("fileSrc", &path, text::MAX_PATH_LEN)?;
+            let path = media_path(&path, context)
+                .map_err(|e| path_error("INVALID_FILE_SRC", &e))?;
+            let path = readable_file(&path).await.map_err(|e| {
+                graphql::Error::new("INVALID_FILE_SRC")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&format!("'{}' {}", path.display(), e))
+            })?;
+            Some(spec::v1::InputSrc::File(FileInputSrc {
+                path,
+                looped: loop_file_src,
+            }))
+        }
+        (None, None) => None,
+    };
+
+    let input_src = if with_backup {
+        Some(spec::v1::InputSrc::FailoverInputs(vec![
+            spec::v1::Input {
+                id: None,
+                key: InputKey::new("main").unwrap(),
+                endpoints: vec![spec::v1::InputEndpoint {
+                    kind: InputEndpointKind::Rtmp,
+                }],
+                src,
+                enabled: true,
+                max_pull_lifetime,
+                connect_timeout_secs,
+                idle_timeout_secs,
+                schedule: schedule.clone(),
+                rtmp_options: rtmp_options.clone(),
+            },
+            spec::v1::Input {
+                id: None,
+                key: InputKey::new("backup").unwrap(),
+                endpoints: vec![spec::v1::InputEndpoint {
+                    kind: InputEndpointKind::Rtmp,
+                }],
+                src: backup_src.map(spec::v1::InputSrc::RemoteUrl),
+                enabled: true,
+                max_pull_lifetime,
+                connect_timeout_secs,
+                idle_timeout_secs,
+                schedule: schedule.clone(),
+                rtmp_options: None,
+            },
+        ]))
+    } else {
+        src
+    };
+
+    let mut endpoints = vec![spec::v1::InputEndpoint {
+        kind: InputEndpointKind::Rtmp,
+    }];
+    if with_hls {
+        endpoints.push(spec::v1::InputEndpoint {
+            kind: InputEndpointKind::Hls,
+        });
+    }
+
+    // `Restream.publishAllowlist`, `Restream.lowLatency`, `Restream.hls`,
+    // `Restream.public`, `Restream.noAutoCycle` and
+    // `Restream.consolidateOutputs` are set via dedicated mutations only,
+    // so should be preserved on editing.
+    let (
+        publish_allowlist,
+        low_latency,
+        hls,
+        public,
+        no_auto_cycle,
+        consolidate_outputs,
+    ) = id
+        .and_then(|id| {
+            context
+                .state()
+                .restreams
+                .lock_ref()
+                .iter()
+                .find(|r| r.id == id)
+                .map(|r| {
+                    (
+                        r.publish_allowlist.clone(),
+                        r.low_latency,
+                        r.hls,
+                        r.public,
+                        r.no_auto_cycle,
+                        r.consolidate_outputs,
+                    )
+                })
+        })
+        .unwrap_or_default();
+
+    Ok(spec::v1::Restream {
+        id: new_id,
+        key,
+        label,
+        group,
+        input: spec::v1::Input {
+            id: None,
+            key: InputKey::new("origin").unwrap(),
+            endpoints,
+            src: input_src,
+            enabled: true,
+            max_pull_lifetime,
+            connect_timeout_secs,
+            idle_timeout_secs,
+            schedule,
+            rtmp_options: rtmp_options.filter(|_| !with_backup),
+        },
+        outputs: vec![],
+        queue_publishers,
+        publisher_policy: publisher_policy.unwrap_or_default(),
+        public_host_override,
+        auto_disable_after_idle_secs,
+        max_session_secs,
+        disable_on_expiry,
+        publish_allowlist,
+        low_latency,
+        hls,
+        public,
+        no_auto_cycle,
+        consolidate_outputs,
+    })
+}
+
+/// Arguments of `Mutation.setOutput` describing a [`spec::v1::Output`] to be
+/// set.
+///
+/// Each field means the same as the argument of `Mutation.setOutput` having
+/// the same name.
+#[derive(Clone, Debug)]
+struct OutputArgs {
+    restream_id: RestreamId,
+    dst: OutputDstUrl,
+    label: Option<Label>,
+    preview_url: Option<Url>,
+    mixins: Vec<MixinSrcUrl>,
+    id: Option<OutputId>,
+    new_id: Option<OutputId>,
+    verify: bool,
+}
+
+/// Validates the given [`OutputArgs`] and builds a disabled
+/// [`spec::v1::Output`] out of them, preserving the settings of the edited
+/// [`Output`] (if any) being set via dedicated mutations only.
+///
+/// # Errors
+///
+/// If any of the [`OutputArgs`] is invalid, or the `dst` is requested to be
+/// verified, but is unreachable.
+#[allow(clippy::too_many_lines)]
+async fn output_spec(
+    args: OutputArgs,
+    context: &Context,
+) -> Result<spec::v1::Output, graphql::Error> {
+    let OutputArgs {
+        restream_id,
+        dst,
+        label,
+        preview_url,
+        mixins,
+        id,
+        new_id,
+        verify,
+    } = args;
+    if id.is_some() && new_id.is_some() {
+        return Err(graphql::Error::new("AMBIGUOUS_ID")
+            .status(StatusCode::BAD_REQUEST)
+            .message("Only one of `id` or `newId` may be specified"));
+    }
+    if mixins.len() > 5 {
+        return Err(graphql::Error::new("TOO_MUCH_MIXIN_URLS")
+            .status(StatusCode::BAD_REQUEST)
+            .message("Maximum 5 mixing URLs are allowed"));
+    }
+    if !mixins.is_empty() {
+        let mut unique = HashSet::with_capacity(mixins.len());
+        for m in &mixins {
+            if let Some(dup) = unique.replace(m) {
+                return Err(graphql::Error::new("DUPLICATE_MIXIN_URL")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&format!("Duplicate Output.mixin.src: {}", dup)));
+            }
+        }
+        if mixins.iter().filter(|u| u.scheme() == "ts").take(2).count() > 1 {
+            return Err(graphql::Error::new("TOO_MUCH_TEAMSPEAK_MIXIN_URLS")
+                .status(StatusCode::BAD_REQUEST)
+                .message("Only one TeamSpeak URL is allowed"));
+        }
+    }
+
+    if verify {
+        let _ = reachability::probe(&dst).await.map_err(probe_error)?;
+    }
+
+    // `OutputMetadata`, `OutputConditioning`, `LoudnessTarget`,
+    // `OutputOverlay`, `OutputConstraints`, `OutputBootPolicy`,
+    // `Output.backupDst`, `Output.prewarm`, `Output.prewarmStrategy`,
+    // `Output.srcStream` and `Output.dropFramesWhenBehind` are set via
+    // dedicated mutations only, so should be preserved on editing.
+    let prev = id.and_then(|id| {
+        context
+            .state()
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter()
+            .find(|o| o.id == id)
+            .cloned()
+    });
+
+    Ok(spec::v1::Output {
+        id: new_id,
+        dst,
+        backup_dst: prev.as_ref().and_then(|o| o.backup_dst.clone()),
+        label,
+        preview_url,
+        volume: Volume::ORIGIN,
+        mixins: mixins
+            .into_iter()
+            .map(|src| {
+                let delay = (src.scheme() == "ts")
+                    .then(|| Delay::from_millis(3500))
+                    .flatten()
+                    .unwrap_or_default();
+                spec::v1::Mixin {
+                    id: None,
+                    src,
+                    volume: Volume::ORIGIN,
+                    delay,
+                }
+            })
+            .collect(),
+        metadata: prev
+            .as_ref()
+            .map(|o| o.metadata.clone())
+            .unwrap_or_default(),
+        conditioning: prev.as_ref().and_then(|o| o.conditioning),
+        loudnorm: prev.as_ref().and_then(|o| o.loudnorm),
+        overlay: prev.as_ref().and_then(|o| o.overlay.clone()),
+        delay_secs: prev.as_ref().and_then(|o| o.delay_secs),
+        max_bitrate_kbps: prev.as_ref().and_then(|o| o.max_bitrate_kbps),
+        limits: prev.as_ref().and_then(|o| o.limits),
+        constraints: prev.as_ref().and_then(|o| o.constraints.clone()),
+        prewarm: prev.as_ref().map_or(false, |o| o.prewarm),
+        prewarm_strategy: prev
+            .as_ref()
+            .map(|o| o.prewarm_strategy)
+            .unwrap_or_default(),
+        src_stream: prev.as_ref().and_then(|o| o.src_stream.clone()),
+        drop_frames_when_behind: prev
+            .as_ref()
+            .map_or(false, |o| o.drop_frames_when_behind),
+        enabled: false,
+        on_boot: prev.map(|o| o.on_boot).unwrap_or_default(),
+    })
+}
+
+/// Converts the given [`NewScheduleRange`]s into an [`InputSchedule`].
+///
+/// If the `timezone` is specified, then the [`NewScheduleRange`]s are
+/// considered in it, and are converted into UTC with its current offset (see
+/// [`ScheduleRange::to_utc()`]).
+///
+/// Returns [`None`] if there are no [`NewScheduleRange`]s.
+///
+/// # Errors
+///
+/// With `INVALID_SCHEDULE` code if any of the [`NewScheduleRange`]s has an
+/// invalid `HH:MM` time, or is empty.
+fn input_schedule(
+    ranges: Vec<NewScheduleRange>,
+    timezone: Option<&TimeZone>,
+) -> Result<Option<InputSchedule>, graphql::Error> {
+    let offset = timezone
+        .and_then(|tz| tz.utc_offset(SystemTime::now()))
+        .unwrap_or_default();
+    let ranges = ranges
+        .into_iter()
+        .map(|r| {
+            let range = ScheduleRange::new(r.weekday, &r.start, &r.end);
+            range.map(|r| r.to_utc(offset)).ok_or_else(|| {
+                graphql::Error::new("INVALID_SCHEDULE")
+                    .status(StatusCode::BAD_REQUEST)
+                    .message(&format!(
+                        "Invalid schedule range {}-{}, should be non-empty \
+                         `HH:MM-HH:MM`",
+                        r.start, r.end,
+                    ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(InputSchedule::new(ranges))
+}
+
+/// Parses the given `name` into a [`TimeZone`] known to the
+/// [IANA time zone database][1].
+///
+/// # Errors
+///
+/// With `INVALID_TIMEZONE` code if the `name` is not a known
+/// [IANA time zone database][1] name.
+///
+/// [1]: https://www.iana.org/time-zones
+fn time_zone(name: &str) -> Result<TimeZone, graphql::Error> {
+    TimeZone::new(name)
+        .filter(TimeZone::is_known)
+        .ok_or_else(|| {
+            graphql::Error::new("INVALID_TIMEZONE")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!("Unknown IANA time zone '{}'", name))
+        })
+}
+
+/// Converts the given wall-clock `local` date and time, formatted as
+/// `YYYY-MM-DDTHH:MM`, in the given `timezone` into the moment it denotes.
+///
+/// # Errors
+///
+/// - With `INVALID_APPLY_AT` code if the `local` date and time is malformed.
+/// - With [`LocalTimeError::code()`] if the `local` time is skipped or
+///   repeated by a DST transition in the `timezone`.
+fn local_date_time(
+    local: &str,
+    timezone: &TimeZone,
+) -> Result<graphql::DateTime, graphql::Error> {
+    let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M")
+        .map_err(|_| {
+            graphql::Error::new("INVALID_APPLY_AT")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!(
+                    "Invalid local date and time '{}', should be \
+                     `YYYY-MM-DDTHH:MM`",
+                    local,
+                ))
+        })?;
+    timezone.to_utc(local).map_err(local_time_error)
+}
+
+/// Converts the given [`LocalTimeError`] into a [`graphql::Error`].
+fn local_time_error(e: LocalTimeError) -> graphql::Error {
+    graphql::Error::new(e.code())
+        .status(StatusCode::BAD_REQUEST)
+        .message(&e)
+}
+
+/// Converts the given [`NewRtmpPullOptions`] into [`RtmpPullOptions`].
+///
+/// Returns [`None`] if all the [`NewRtmpPullOptions`] are empty.
+///
+/// # Errors
+///
+/// With `INVALID_RTMP_OPTIONS` code if any of the [`NewRtmpPullOptions`] is
+/// invalid.
+fn rtmp_pull_options(
+    new: NewRtmpPullOptions,
+) -> Result<Option<RtmpPullOptions>, graphql::Error> {
+    RtmpPullOptions::new(
+        new.playpath,
+        new.swf_url,
+        new.page_url,
+        new.token_query,
+    )
+    .map_err(|e| {
+        graphql::Error::new("INVALID_RTMP_OPTIONS")
+            .status(StatusCode::BAD_REQUEST)
+            .message(e)
+    })
+}
+
+/// Converts the given [`NewOutputConstraints`] into [`OutputConstraints`].
+///
+/// Returns [`None`] if no constraints are specified at all.
+///
+/// # Errors
+///
+/// With `INVALID_OUTPUT_CONSTRAINTS` code if any of the constraints is
+/// invalid.
+fn output_constraints(
+    new: NewOutputConstraints,
+) -> Result<Option<OutputConstraints>, graphql::Error> {
+    OutputConstraints::new(
+        new.preset,
+        new.video_codecs.unwrap_or_default(),
+        new.audio_codecs.unwrap_or_default(),
+        new.max_audio_channels,
+        new.max_width,
+        new.max_height,
+        new.enforce.unwrap_or_default(),
+    )
+    .map_err(|e| {
+        graphql::Error::new("INVALID_OUTPUT_CONSTRAINTS")
+            .status(StatusCode::BAD_REQUEST)
+            .message(e)
+    })
+}
+
+/// Creates a new [`LoudnessTarget`] out of the given values.
+///
+/// # Errors
+///
+/// With `INVALID_LOUDNESS_TARGET` code if any of the values is out of its
+/// range.
+fn loudness_target(
+    integrated: f64,
+    true_peak: f64,
+) -> Result<LoudnessTarget, graphql::Error> {
+    LoudnessTarget::new(integrated, true_peak).ok_or_else(|| {
+        graphql::Error::new("INVALID_LOUDNESS_TARGET")
+            .status(StatusCode::BAD_REQUEST)
+            .message(
+                "Integrated loudness must be within -70..-5 LUFS, and true \
+                 peak within -9..0 dBTP",
+            )
+    })
+}
+
+/// Creates new [`OutputLimits`] out of the given values.
+///
+/// Returns [`None`] if no limits are specified at all.
+///
+/// # Errors
+///
+/// With `INVALID_OUTPUT_LIMITS` code if any of the values is out of its range,
+/// including the CPU quota exceeding the number of CPU cores of this server.
+fn output_limits(
+    nice: Option<i32>,
+    cpu_quota: Option<i32>,
+) -> Result<Option<OutputLimits>, graphql::Error> {
+    let max_cpu_quota: i32 =
+        (num_cpus::get() * 100).try_into().unwrap_or(i32::MAX);
+    (nice.is_some() || cpu_quota.is_some())
+        .then(|| {
+            OutputLimits::new(nice, cpu_quota)
+                .filter(|_| cpu_quota.map_or(true, |q| q <= max_cpu_quota))
+                .ok_or_else(|| {
+                    graphql::Error::new("INVALID_OUTPUT_LIMITS")
+                        .status(StatusCode::BAD_REQUEST)
+                        .message(&format!(
+                            "Niceness must be within 0..19, and CPU quota \
+                             within 1..{} percents",
+                            max_cpu_quota,
+                        ))
+                })
+        })
+        .transpose()
+}
+
+/// Converts the given number of seconds into a valid [`Output::delay_secs`].
+///
+/// # Errors
+///
+/// With `INVALID_OUTPUT_DELAY` code if the delay is negative or exceeds
+/// [`Output::MAX_DELAY_SECS`].
+fn output_delay(secs: i32) -> Result<u32, graphql::Error> {
+    secs.try_into()
+        .ok()
+        .filter(|d| *d <= Output::MAX_DELAY_SECS)
+        .ok_or_else(|| {
+            graphql::Error::new("INVALID_OUTPUT_DELAY")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&format!(
+                    "Delay must be within 0..{} seconds",
+                    Output::MAX_DELAY_SECS,
+                ))
+        })
+}
+
+/// Converts the given bitrate into a valid [`Output::max_bitrate_kbps`].
+///
+/// # Errors
+///
+/// With `INVALID_OUTPUT_BITRATE` code if the bitrate is negative.
+fn output_bitrate(kbps: i32) -> Result<u32, graphql::Error> {
+    kbps.try_into().map_err(|_| {
+        graphql::Error::new("INVALID_OUTPUT_BITRATE")
+            .status(StatusCode::BAD_REQUEST)
+            .message("Bitrate must not be negative")
+    })
+}
+
+/// Checks whether the given `backup` destination may be used as
+/// [`Output::backup_dst`] of an [`Output`] with the given `primary`
+/// [`Output::dst`].
+///
+/// # Errors
+///
+/// With `INVALID_BACKUP_DST` code if the `backup` destination is the same as
+/// the `primary` one, or any of them is a local file.
+fn check_backup_dst(
+    primary: Option<&OutputDstUrl>,
+    backup: &OutputDstUrl,
+) -> Result<(), graphql::Error> {
+    let invalid = |msg| {
+        graphql::Error::new("INVALID_BACKUP_DST")
+            .status(StatusCode::BAD_REQUEST)
+            .message(msg)
+    };
+    if primary == Some(backup) {
+        return Err(invalid("Backup destination must differ from dst"));
+    }
+    if backup.scheme() == "file"
+        || primary.map_or(false, |p| p.scheme() == "file")
+    {
+        return Err(invalid("Local files cannot have backups"));
+    }
+    Ok(())
+}
+
+/// Converts the given [`reachability::ProbeError`] into a [`graphql::Error`].
+fn probe_error(e: reachability::ProbeError) -> graphql::Error {
+    if e == reachability::ProbeError::RateLimited {
+        return graphql::Error::new("RATE_LIMITED")
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .message(&e);
+    }
+    graphql::Error::new("UNREACHABLE")
+        .status(StatusCode::BAD_REQUEST)
+        .message(&e)
+        .detail("reason", e.reason())
+}
+
+/// Resolves the given user-supplied media file `path` (see
+/// [`paths::resolve()`]), confining it to the [`Opts::media_root`], if any.
+///
+/// # Errors
+///
+/// If the `path` cannot be resolved, or escapes the [`Opts::media_root`].
+///
+/// [`Opts::media_root`]: crate::cli::Opts::media_root
+fn media_path<P: AsRef<Path>>(
+    path: P,
+    context: &Context,
+) -> Result<PathBuf, paths::PathError> {
+    paths::resolve(path, context.config().media_root.as_deref())
+}
+
+/// Converts the given [`paths::PathError`] into a [`graphql::Error`] with the
+/// given `code`.
+fn path_error(code: &'static str, e: &paths::PathError) -> graphql::Error {
+    graphql::Error::new(code)
+        .status(StatusCode::BAD_REQUEST)
+        .message(e)
+        .detail("reason", e.reason())
+}
+
+/// Converts the given [`Duplicate`] error into a [`graphql::Error`], pointing
+/// to the conflicting entity via `extensions`, so clients can link to it.
+fn duplicate_error(e: Duplicate) -> graphql::Error {
+    let err = match &e {
+        Duplicate::Restream { id, label, .. } => {
+            let err = graphql::Error::new("DUPLICATE_RESTREAM_KEY")
+                .detail("restreamId", id);
+            match label {
+                Some(l) => err.detail("restreamLabel", l),
+                None => err,
+            }
+        }
+        Duplicate::Output { id, label, .. } => {
+            let err = graphql::Error::new("DUPLICATE_OUTPUT_URL")
+                .detail("outputId", id);
+            match label {
+                Some(l) => err.detail("outputLabel", l),
+                None => err,
+            }
+        }
+        Duplicate::ForeignOutput {
+            restream_id,
+            id,
+            label,
+            ..
+        } => {
+            let err = graphql::Error::new("CONFLICTING_OUTPUT_URL")
+                .detail("restreamId", restream_id)
+                .detail("outputId", id);
+            match label {
+                Some(l) => err.detail("outputLabel", l),
+                None => err,
+            }
+        }
+        Duplicate::Id { id } => {
+            graphql::Error::new("DUPLICATE_ID").detail("id", id)
+        }
+    };
+    err.status(StatusCode::CONFLICT).message(&e)
+}
+
+/// Converts the given [`Rejected`] error into a [`graphql::Error`].
+fn rejected_error(e: Rejected) -> graphql::Error {
+    match e {
+        Rejected::Duplicate(e) => duplicate_error(e),
+        Rejected::Quota(e) => quota_error(e),
+        Rejected::FileSrc(e) => path_error("INVALID_FILE_SRC", &e),
+        Rejected::OverlayImage(e) => path_error("INVALID_OVERLAY_IMAGE", &e),
+        Rejected::ChainCycle(_) => {
+            graphql::Error::new("CYCLIC_RESTREAMS_CHAIN")
+                .status(StatusCode::BAD_REQUEST)
+                .message(&e)
+        }
+        Rejected::SrcStream(_) => graphql::Error::new("UNKNOWN_STREAM")
+            .status(StatusCode::BAD_REQUEST)
+            .message(&e),
+    }
+}
+
+/// Converts the given [`PendingDstError`] into a [`graphql::Error`].
+fn pending_dst_error(e: PendingDstError) -> graphql::Error {
+    match e {
+        PendingDstError::Duplicate(e) => duplicate_error(e),
+        PendingDstError::SameDst => graphql::Error::new("INVALID_PENDING_DST")
+            .status(StatusCode::BAD_REQUEST)
+            .message(&e),
+    }
+}
+
+/// Converts the given [`QuotaExceeded`] error into a [`graphql::Error`],
+/// reporting the exceeded limit and the amount it's exceeded by via
+/// `extensions`.
+fn quota_error(e: QuotaExceeded) -> graphql::Error {
+    let err = graphql::Error::new("QUOTA_EXCEEDED")
+        .status(StatusCode::FORBIDDEN)
+        .message(&e)
+        .detail("limit", &e.limit())
+        .detail("excess", &e.excess());
+    match &e {
+        QuotaExceeded::Restreams { .. } => err,
+        QuotaExceeded::Outputs { key, .. } => err.detail("restreamKey", key),
+    }
+}
+
+/// Hashes the given `password` on the blocking threads pool (see
+/// [`password::hash_blocking()`]).
+///
+/// # Errors
+///
+/// If the blocking threads pool fails to perform the hashing.
+async fn hash_password(password: String) -> Result<String, graphql::Error> {
+    password::hash_blocking(password)
+        .await
+        .map_err(|e| anyhow!("Failed to hash password: {}", e).into())
+}
+
+/// Creates a [`graphql::Error`] of the admin password being changed
+/// concurrently while another change of it has been performed.
+fn password_changed_error() -> graphql::Error {
+    graphql::Error::new("PASSWORD_CHANGED")
+        .status(StatusCode::CONFLICT)
+        .message("Password has been changed concurrently, try again")
+}
+
+/// Converts the given number of seconds into a positive [`u32`], if possible.
+#[inline]
+fn positive_secs(secs: i32) -> Option<u32> {
+    secs.try_into().ok().filter(|&secs| secs > 0)
+}
+
+/// Checks whether the given `path` points to an existing readable file,
+/// returning its absolute path.
+///
+/// # Errors
+///
+/// If the given `path` doesn't point to an existing readable file.
+async fn readable_file(path: &Path) -> Result<PathBuf, String> {
+    let path = fs::canonicalize(path)
+        .await
+        .map_err(|e| format!("cannot be resolved: {}", e))?;
+    let file = fs::File::open(&path)
+        .await
+        .map_err(|e| format!("cannot be read: {}", e))?;
+    let is_file = file
+        .metadata()
+        .await
+        .map_err(|e| format!("cannot be read: {}", e))?
+        .is_file();
+    is_file.then(|| path).ok_or_else(|| "is not a file".into())
+}
+
+#[cfg(test)]
+mod schema_spec {
+    use juniper::IntrospectionFormat;
+    use serde_json::Value;
+
+    use crate::api::graphql::Context;
+
+    use super::{read_only_schema, schema};
+
+    fn type_names(json: &Value) -> Vec<&str> {
+        json["__schema"]["types"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t["name"].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn read_only_has_no_mutations() {
+        let (res, _) = juniper::introspect(
+            &read_only_schema(),
+            &Context::fake(),
+            IntrospectionFormat::default(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&res).unwrap();
+
+        assert!(json["__schema"]["mutationType"].is_null());
+        assert!(!type_names(&json).iter().any(|n| n.contains("Mutation")));
+        assert_eq!(json["__schema"]["queryType"]["name"], "Query");
+        assert_eq!(
+            json["__schema"]["subscriptionType"]["name"],
+            "Subscription"
+        );
+    }
+
+    #[test]
+    fn full_has_mutations() {
+        let (res, _) = juniper::introspect(
+            &schema(),
+            &Context::fake(),
+            IntrospectionFormat::default(),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&res).unwrap();
+
+        assert_eq!(json["__schema"]["mutationType"]["name"], "Mutation");
+        assert!(type_names(&json).contains(&"Mutation"));
+    }
+}
+
+#[cfg(test)]
+mod set_v2_spec {
+    use std::{env, fs};
+
+    use actix_web::{rt::System, test::TestRequest};
+    use juniper::Variables;
+    use serde_json::{json, Value};
+    use structopt::StructOpt as _;
+    use uuid::Uuid;
+
+    use crate::{api::graphql::Context, cli::Opts, state::InputSrc, State};
+
+    use super::schema;
+
+    /// Executes the given `query` against the given [`State`], returning its
+    /// data along with the codes of the errors occurred.
+    fn execute(state: &State, query: &str) -> (Value, Value) {
+        let ctx = Context::new(
+            TestRequest::default()
+                .app_data(Opts::from_iter(&["ephyr-restreamer"]))
+                .app_data(state.clone())
+                .to_http_request(),
+        );
+        let (res, errs) = System::new("test")
+            .block_on(juniper::execute(
+                query,
+                None,
+                &schema(),
+                &Variables::new(),
+                &ctx,
+            ))
+            .unwrap();
+        let errs = serde_json::to_value(errs).unwrap();
+        let codes = errs
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["extensions"]["code"].clone())
+            .collect();
+        (serde_json::to_value(res).unwrap(), codes)
+    }
+
+    #[test]
+    fn returns_created_restream() {
+        let state = State::new_in_memory();
+
+        let (data, errs) = execute(
+            &state,
+            r#"mutation {
+                setRestreamV2(key: "live", label: "Main", withHls: true) {
+                    id key label
+                    input { endpoints { kind } }
+                }
+            }"#,
+        );
+
+        assert_eq!(errs, json!([]));
+        let restream = &data["setRestreamV2"];
+        assert_eq!(restream["key"], "live");
+        assert_eq!(restream["label"], "Main");
+        assert_eq!(
+            restream["input"]["endpoints"],
+            json!([{"kind": "RTMP"}, {"kind": "HLS"}]),
+        );
+        assert_eq!(
+            restream["id"],
+            state.restreams.lock_ref()[0].id.to_string(),
+        );
+    }
+
+    #[test]
+    fn returns_updated_restream() {
+        let state = State::with_restream("live", json!({}));
+        let id = state.restreams.lock_ref()[0].id;
+
+        let (data, errs) = execute(
+            &state,
+            &format!(
+                r#"mutation {{
+                    setRestreamV2(id: "{}", key: "renamed") {{ id key }}
+                }}"#,
+                id,
+            ),
+        );
+
+        assert_eq!(errs, json!([]));
+        assert_eq!(
+            data["setRestreamV2"],
+            json!({"id": id.to_string(), "key": "renamed"}),
+        );
+    }
+
+    #[test]
+    fn errors_on_unknown_restream() {
+        let state = State::new_in_memory();
+
+        let (data, errs) = execute(
+            &state,
+            r#"mutation {
+                setRestreamV2(
+                    id: "00000000-0000-0000-0000-000000000000"
+                    key: "live"
+                ) { id }
+            }"#,
+        );
+
+        assert_eq!(data, Value::Null);
+        assert_eq!(errs, json!(["UNKNOWN_RESTREAM"]));
+    }
+
+    #[test]
+    fn pulls_from_file() {
+        let file =
+            env::temp_dir().join(format!("ephyr-file-{}.mp4", Uuid::new_v4()));
+        fs::write(&file, b"").unwrap();
+        let path = fs::canonicalize(&file).unwrap();
+        let state = State::new_in_memory();
+
+        let (_, errs) = execute(
+            &state,
+            &format!(
+                r#"mutation {{
+                    setRestreamV2(
+                        key: "live"
+                        fileSrc: "{}"
+                        loopFileSrc: false
+                    ) {{ id }}
+                }}"#,
+                file.display(),
+            ),
+        );
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(errs, json!([]));
+        match &state.restreams.lock_ref()[0].input.src {
+            Some(InputSrc::File(src)) => {
+                assert_eq!(src.path, path);
+                assert!(!src.looped);
+            }
+            src => panic!("unexpected source: {:?}", src),
+        }
+    }
+
+    #[test]
+    fn rejects_unreadable_file() {
+        let missing =
+            env::temp_dir().join(format!("ephyr-file-{}.mp4", Uuid::new_v4()));
+        for path in &[missing, env::temp_dir()] {
+            let state = State::new_in_memory();
+
+            let (data, errs) = execute(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        setRestreamV2(key: "live", fileSrc: "{}") {{ id }}
+                    }}"#,
+                    path.display(),
+                ),
+            );
+
+            assert_eq!(data, Value::Null, "for: {}", path.display());
+            assert_eq!(errs, json!(["INVALID_FILE_SRC"]));
+            assert!(state.restreams.lock_ref().is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_both_remote_and_file_src() {
+        let state = State::new_in_memory();
+
+        let (data, errs) = execute(
+            &state,
+            r#"mutation {
+                setRestreamV2(
+                    key: "live"
+                    src: "rtmp://example.com/live/stream"
+                    fileSrc: "/media/video.mp4"
+                ) { id }
+            }"#,
+        );
+
+        assert_eq!(data, Value::Null);
+        assert_eq!(errs, json!(["AMBIGUOUS_INPUT_SRC"]));
+    }
+
+    #[test]
+    fn returns_created_output() {
+        let state = State::with_restream("live", json!({}));
+        let restream_id = state.restreams.lock_ref()[0].id;
+
+        let (data, errs) = execute(
+            &state,
+            &format!(
+                r#"mutation {{
+                    setOutputV2(
+                        restreamId: "{}"
+                        dst: "rtmp://example.com/live/stream"
+                        label: "YouTube"
+                    ) {{ id dst label }}
+                }}"#,
+                restream_id,
+            ),
+        );
+
+        assert_eq!(errs, json!([]));
+        let output = &data["setOutputV2"];
+        assert_eq!(output["dst"], "rtmp://example.com/live/stream");
+        assert_eq!(output["label"], "YouTube");
+        assert_eq!(
+            output["id"],
+            state.restreams.lock_ref()[0].outputs[0].id.to_string(),
+        );
+    }
+
+    #[test]
+    fn errors_on_unknown_output() {
+        let state = State::with_restream("live", json!({}));
+        let restream_id = state.restreams.lock_ref()[0].id;
+
+        let (data, errs) = execute(
+            &state,
+            &format!(
+                r#"mutation {{
+                    setOutputV2(
+                        restreamId: "{}"
+                        id: "00000000-0000-0000-0000-000000000000"
+                        dst: "rtmp://example.com/live/stream"
+                    ) {{ id }}
+                }}"#,
+                restream_id,
+            ),
+        );
+
+        assert_eq!(data, Value::Null);
+        assert_eq!(errs, json!(["UNKNOWN_OUTPUT"]));
+    }
 }
@@ -0,0 +1,375 @@
+//! Limiting depth and complexity of [GraphQL] operations before executing
+//! them.
+//!
+//! Limits are enforced by wrapping root types of a [GraphQL] schema into
+//! [`Limited`], so they apply to all the operations regardless of the
+//! transport (plain HTTP or WebSocket) they are requested via.
+//!
+//! [GraphQL]: https://graphql.com
+
+use derive_more::Display;
+use futures::future::{self, BoxFuture};
+use juniper::{
+    meta::MetaType, Arguments, DefaultScalarValue, ExecutionResult, Executor,
+    FieldError, GraphQLSubscriptionValue, GraphQLType, GraphQLValue,
+    GraphQLValueAsync, IntoFieldError as _, LookAheadMethods as _,
+    LookAheadSelection, Registry, Value, ValuesStream,
+};
+
+use super::{Context, Error};
+
+/// Limits of a single [GraphQL] operation.
+///
+/// [GraphQL]: https://graphql.com
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Maximum nesting depth of fields.
+    ///
+    /// Top-level fields of an operation have depth `1`.
+    pub max_depth: usize,
+
+    /// Maximum total number of fields selected by a single top-level field
+    /// of an operation (including itself), with fragments expanded.
+    pub max_complexity: usize,
+}
+
+impl Limits {
+    /// Checks the given [`LookAheadSelection`] of a top-level field to fit
+    /// into these [`Limits`].
+    ///
+    /// Stops as soon as any of [`Limits`] is exceeded, so never does more
+    /// work than [`Limits::max_complexity`] allows.
+    ///
+    /// # Errors
+    ///
+    /// If the selected field is deeper or more complex than allowed.
+    pub fn check(
+        &self,
+        field: &LookAheadSelection<'_, DefaultScalarValue>,
+    ) -> Result<(), Violation> {
+        self.visit(field, 1, &mut 0)
+    }
+
+    /// Visits the given `field` and all its children recursively, being on
+    /// the given `depth` and having already counted `complexity` fields.
+    fn visit(
+        &self,
+        field: &LookAheadSelection<'_, DefaultScalarValue>,
+        depth: usize,
+        complexity: &mut usize,
+    ) -> Result<(), Violation> {
+        if depth > self.max_depth {
+            return Err(Violation::TooDeep(self.max_depth));
+        }
+        *complexity += 1;
+        if *complexity > self.max_complexity {
+            return Err(Violation::TooComplex(self.max_complexity));
+        }
+        for child in field.children() {
+            self.visit(child, depth + 1, complexity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Violation of [`Limits`] by a [GraphQL] operation.
+///
+/// [GraphQL]: https://graphql.com
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum Violation {
+    /// Operation nests its fields deeper than allowed.
+    #[display(fmt = "Query is too deep: maximum allowed depth is {}", _0)]
+    TooDeep(usize),
+
+    /// Operation selects more fields than allowed.
+    #[display(
+        fmt = "Query is too complex: maximum allowed complexity is {}",
+        _0
+    )]
+    TooComplex(usize),
+}
+
+impl Violation {
+    /// Returns unique literal code of this [`Violation`] for reporting it in
+    /// a GraphQL error.
+    #[inline]
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TooDeep(_) => "QUERY_TOO_DEEP",
+            Self::TooComplex(_) => "QUERY_TOO_COMPLEX",
+        }
+    }
+}
+
+/// Root type of a [GraphQL] schema, checking each its top-level field against
+/// [`cli::Opts::graphql_limits`] before resolving it.
+///
+/// Fields exceeding [`Limits`] are not resolved at all, and fail with
+/// a [`Violation`] error instead.
+///
+/// [`cli::Opts::graphql_limits`]: crate::cli::Opts::graphql_limits
+/// [GraphQL]: https://graphql.com
+#[derive(Clone, Copy, Debug)]
+pub struct Limited<T>(pub T);
+
+impl<T> Limited<T> {
+    /// Checks the field being resolved by the given [`Executor`] against
+    /// [`cli::Opts::graphql_limits`].
+    ///
+    /// # Errors
+    ///
+    /// If the field violates [`Limits`].
+    ///
+    /// [`cli::Opts::graphql_limits`]: crate::cli::Opts::graphql_limits
+    fn check(executor: &Executor<'_, '_, Context>) -> Result<(), FieldError> {
+        executor
+            .context()
+            .config()
+            .graphql_limits()
+            .check(&executor.look_ahead())
+            .map_err(|e| Error::from(e).into_field_error())
+    }
+}
+
+impl<T> GraphQLType for Limited<T>
+where
+    T: GraphQLType<Context = Context>,
+{
+    #[inline]
+    fn name(info: &Self::TypeInfo) -> Option<&str> {
+        T::name(info)
+    }
+
+    #[inline]
+    fn meta<'r>(
+        info: &Self::TypeInfo,
+        registry: &mut Registry<'r>,
+    ) -> MetaType<'r> {
+        T::meta(info, registry)
+    }
+}
+
+impl<T> GraphQLValue for Limited<T>
+where
+    T: GraphQLValue<Context = Context>,
+{
+    type Context = Context;
+    type TypeInfo = T::TypeInfo;
+
+    #[inline]
+    fn type_name<'i>(&self, info: &'i Self::TypeInfo) -> Option<&'i str> {
+        self.0.type_name(info)
+    }
+
+    fn resolve_field(
+        &self,
+        info: &Self::TypeInfo,
+        field_name: &str,
+        arguments: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        Self::check(executor)?;
+        self.0.resolve_field(info, field_name, arguments, executor)
+    }
+
+    #[inline]
+    fn concrete_type_name(
+        &self,
+        context: &Self::Context,
+        info: &Self::TypeInfo,
+    ) -> String {
+        self.0.concrete_type_name(context, info)
+    }
+}
+
+impl<T> GraphQLValueAsync for Limited<T>
+where
+    T: GraphQLValueAsync<Context = Context>,
+    T::TypeInfo: Sync,
+{
+    fn resolve_field_async<'a>(
+        &'a self,
+        info: &'a Self::TypeInfo,
+        field_name: &'a str,
+        arguments: &'a Arguments,
+        executor: &'a Executor<Self::Context>,
+    ) -> BoxFuture<'a, ExecutionResult> {
+        match Self::check(executor) {
+            Ok(()) => self
+                .0
+                .resolve_field_async(info, field_name, arguments, executor),
+            Err(e) => Box::pin(future::err(e)),
+        }
+    }
+}
+
+impl<T> GraphQLSubscriptionValue for Limited<T>
+where
+    T: GraphQLSubscriptionValue<Context = Context>,
+    T::TypeInfo: Sync,
+{
+    fn resolve_field_into_stream<'s, 'i, 'ft, 'args, 'e, 'ref_e, 'res, 'f>(
+        &'s self,
+        info: &'i Self::TypeInfo,
+        field_name: &'ft str,
+        arguments: Arguments<'args>,
+        executor: &'ref_e Executor<'ref_e, 'e, Self::Context>,
+    ) -> BoxFuture<'f, Result<Value<ValuesStream<'res>>, FieldError>>
+    where
+        's: 'f,
+        'ft: 'f,
+        'args: 'f,
+        'ref_e: 'f,
+        'res: 'f,
+        'i: 'res,
+        'e: 'res,
+    {
+        match Self::check(executor) {
+            Ok(()) => self.0.resolve_field_into_stream(
+                info, field_name, arguments, executor,
+            ),
+            Err(e) => Box::pin(future::err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod limited_spec {
+    use actix_web::{rt::System, test::TestRequest};
+    use futures::{future, stream, stream::BoxStream};
+    use juniper::{
+        graphql_object, graphql_subscription, EmptyMutation, RootNode,
+        Variables,
+    };
+    use serde_json::{json, Value};
+    use structopt::StructOpt as _;
+
+    use crate::{api::graphql::Context, cli::Opts};
+
+    use super::Limited;
+
+    struct Query;
+
+    #[graphql_object(context = Context)]
+    impl Query {
+        fn node() -> Node {
+            Node
+        }
+    }
+
+    struct Node;
+
+    #[graphql_object(context = Context)]
+    impl Node {
+        fn child() -> Node {
+            Node
+        }
+
+        fn value() -> i32 {
+            0
+        }
+    }
+
+    struct Subscription;
+
+    #[graphql_subscription(context = Context)]
+    impl Subscription {
+        async fn node() -> BoxStream<'static, Node> {
+            Box::pin(stream::once(future::ready(Node)))
+        }
+    }
+
+    type Schema = RootNode<
+        'static,
+        Limited<Query>,
+        EmptyMutation<Context>,
+        Limited<Subscription>,
+    >;
+
+    fn schema() -> Schema {
+        Schema::new(Limited(Query), EmptyMutation::new(), Limited(Subscription))
+    }
+
+    fn context(max_depth: usize, max_complexity: usize) -> Context {
+        let opts = Opts::from_iter(&[
+            "ephyr-restreamer".to_owned(),
+            format!("--graphql-max-depth={}", max_depth),
+            format!("--graphql-max-complexity={}", max_complexity),
+        ]);
+        Context::new(TestRequest::default().app_data(opts).to_http_request())
+    }
+
+    /// Executes the given `query` with the given [`Limits`], returning the
+    /// codes of the errors occurred.
+    ///
+    /// [`Limits`]: super::Limits
+    fn execute(query: &str, max_depth: usize, max_complexity: usize) -> Value {
+        let ctx = context(max_depth, max_complexity);
+        let (_, errs) = System::new("test")
+            .block_on(juniper::execute(
+                query,
+                None,
+                &schema(),
+                &Variables::new(),
+                &ctx,
+            ))
+            .unwrap();
+        let errs = serde_json::to_value(errs).unwrap();
+        errs.as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["extensions"]["code"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn rejects_too_deep_fields() {
+        let query = "{ node { child { value } } }";
+        assert_eq!(execute(query, 3, 100), json!([]));
+        assert_eq!(execute(query, 2, 100), json!(["QUERY_TOO_DEEP"]));
+
+        let query = "{ node { ...F } } \
+                     fragment F on Node { child { ... on Node { value } } }";
+        assert_eq!(execute(query, 2, 100), json!(["QUERY_TOO_DEEP"]));
+    }
+
+    #[test]
+    fn rejects_too_complex_fields() {
+        let query = "{ node { value child { value } } }";
+        assert_eq!(execute(query, 10, 4), json!([]));
+        assert_eq!(execute(query, 10, 3), json!(["QUERY_TOO_COMPLEX"]));
+
+        let query = "{ node { x: child { ...F } y: child { ...F } } } \
+                     fragment F on Node { value }";
+        assert_eq!(execute(query, 10, 4), json!(["QUERY_TOO_COMPLEX"]));
+    }
+
+    #[test]
+    fn measures_top_level_fields_separately() {
+        let query = "{ a: node { value } b: node { value } }";
+        assert_eq!(execute(query, 10, 2), json!([]));
+    }
+
+    #[test]
+    fn allows_introspection() {
+        let query = "{ __schema { types { name fields { name type { name \
+                     ofType { name ofType { name } } } } } } }";
+        assert_eq!(execute(query, 2, 2), json!([]));
+    }
+
+    #[test]
+    fn limits_subscriptions() {
+        let ctx = context(2, 100);
+        let res = System::new("test").block_on(juniper::resolve_into_stream(
+            "subscription { node { child { value } } }",
+            None,
+            &schema(),
+            &Variables::new(),
+            &ctx,
+        ));
+        let (_, errs) = res.unwrap();
+        let errs = serde_json::to_value(errs).unwrap();
+        assert_eq!(errs[0]["extensions"]["code"], "QUERY_TOO_DEEP");
+    }
+}
@@ -3,29 +3,50 @@
 //! [GraphQL]: https://graphql.com
 
 pub mod client;
+pub mod complexity;
 
-use std::{borrow::Cow, convert::Infallible, fmt, ops::Deref};
+use std::{
+    borrow::Cow, convert::Infallible, fmt, ops::Deref, time::SystemTime,
+};
 
 use actix_web::{http, HttpRequest};
-use derive_more::{Display, Error};
+use chrono::{SecondsFormat, Utc};
+use derive_more::{Display, Error, From};
 use juniper::{
-    graphql_value, http::GraphQLResponse, FieldError, IntoFieldError,
-    ScalarValue,
+    graphql_scalar, graphql_value, http::GraphQLResponse, FieldError,
+    GraphQLEnum, IntoFieldError, ParseScalarResult, ParseScalarValue,
+    ScalarValue, Value,
 };
 use send_wrapper::SendWrapper;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use smart_default::SmartDefault;
 
+use crate::text;
+
 /// Context containing [`HttpRequest`] for providing additional information when
 /// executing GraphQL operations.
 #[derive(Clone, Debug)]
-pub struct Context(Option<SendWrapper<HttpRequest>>);
+pub struct Context {
+    /// [`HttpRequest`] the GraphQL operations are executed for.
+    req: Option<SendWrapper<HttpRequest>>,
+
+    /// [`Role`] of the client executing GraphQL operations.
+    role: Role,
+}
 
 impl Context {
     /// Creates new [`Context`] wrapping the given [`HttpRequest`].
+    ///
+    /// [`Role`] is taken from the [`HttpRequest`]'s extensions (put there on
+    /// authorization), defaulting to [`Role::Admin`].
     #[inline]
     #[must_use]
     pub fn new(req: HttpRequest) -> Self {
-        Self(Some(SendWrapper::new(req)))
+        let role = req.extensions().get::<Role>().copied().unwrap_or_default();
+        Self {
+            req: Some(SendWrapper::new(req)),
+            role,
+        }
     }
 
     /// Creates a fake [`Context`], which panics on use.
@@ -35,7 +56,60 @@ impl Context {
     #[inline]
     #[must_use]
     pub fn fake() -> Self {
-        Self(None)
+        Self {
+            req: None,
+            role: Role::default(),
+        }
+    }
+
+    /// Overrides [`Role`] of this [`Context`].
+    #[inline]
+    #[must_use]
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Returns [`Role`] of the client executing GraphQL operations.
+    #[inline]
+    #[must_use]
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Checks whether the client executing GraphQL operations has
+    /// [`Role::Admin`], so is allowed to perform mutations.
+    ///
+    /// # Errors
+    ///
+    /// With `UNAUTHORIZED` code if the client has read-only [`Role::Viewer`].
+    pub fn require_admin(&self) -> Result<(), Error> {
+        if self.role == Role::Admin {
+            return Ok(());
+        }
+        Err(Error::new("UNAUTHORIZED")
+            .status(http::StatusCode::FORBIDDEN)
+            .message("Read-only access doesn't allow this action"))
+    }
+
+    /// Checks whether the client executing GraphQL operations is allowed to
+    /// edit this server's state: has [`Role::Admin`], and this server is not
+    /// a standby replica of a primary one (see [`State::is_replica()`]).
+    ///
+    /// # Errors
+    ///
+    /// With `UNAUTHORIZED` code if the client has read-only [`Role::Viewer`],
+    /// or with `REPLICA_READ_ONLY` code if this server is a standby replica.
+    ///
+    /// [`State::is_replica()`]: crate::State::is_replica
+    pub fn require_writable(&self) -> Result<(), Error> {
+        self.require_admin()?;
+        if !self.state().is_replica() {
+            return Ok(());
+        }
+        Err(Error::new("REPLICA_READ_ONLY")
+            .status(http::StatusCode::CONFLICT)
+            .message("Standby server cannot be edited until promoted"))
     }
 
     /// Returns [`cli::Opts`] parameters stored in [`HttpRequest`]'s context.
@@ -48,6 +122,16 @@ impl Context {
         self.app_data::<crate::cli::Opts>().unwrap()
     }
 
+    /// Returns [`srs::Server`] stored in [`HttpRequest`]'s context.
+    ///
+    /// [`srs::Server`]: crate::srs::Server
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn srs(&self) -> &crate::srs::Server {
+        self.app_data::<crate::srs::Server>().unwrap()
+    }
+
     /// Returns current [`State`] stored in [`HttpRequest`]'s context.
     ///
     /// [`State`]: crate::State
@@ -64,10 +148,26 @@ impl Deref for Context {
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &*self.0.as_ref().unwrap()
+        &*self.req.as_ref().unwrap()
     }
 }
 
+/// Role of a client accessing GraphQL API, determined by the password it has
+/// been authorized with.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq, SmartDefault)]
+pub enum Role {
+    /// Full access, including any mutations.
+    ///
+    /// Granted by the admin password, or to anyone if no password is set.
+    #[default]
+    Admin,
+
+    /// Read-only access, allowing queries and subscriptions only.
+    ///
+    /// Granted by the viewer password.
+    Viewer,
+}
+
 /// Error returned to the client by GraphQL API.
 #[derive(Clone, Debug, Display, Error, SmartDefault)]
 #[display(fmt = "{}", message)]
@@ -87,6 +187,10 @@ pub struct Error {
     /// Backtrace of this [`Error`](struct@Error).
     #[error(not(backtrace))]
     pub backtrace: Option<Vec<String>>,
+
+    /// Additional details of this [`Error`](struct@Error), describing it in a
+    /// machine-readable way.
+    pub details: Vec<(&'static str, String)>,
 }
 
 impl Error {
@@ -138,6 +242,21 @@ impl Error {
         self
     }
 
+    /// Attaches given detail with the given `name` to this
+    /// [`Error`](struct@Error).
+    ///
+    /// Goes as `errors.extensions.{name}` field of GraphQL response.
+    #[inline]
+    #[must_use]
+    pub fn detail<V: fmt::Display + ?Sized>(
+        mut self,
+        name: &'static str,
+        val: &V,
+    ) -> Self {
+        self.details.push((name, format!("{}", val)));
+        self
+    }
+
     /// Sets [`http::StatusCode`] for this [`Error`](struct@Error).
     ///
     /// Goes as `errors.extensions.status` field of GraphQL response.
@@ -170,13 +289,18 @@ impl Error {
 impl<S: ScalarValue> IntoFieldError<S> for Error {
     fn into_field_error(self) -> FieldError<S> {
         let size = if self.backtrace.is_some() { 3 } else { 2 };
-        let mut extensions = juniper::Object::with_capacity(size);
+        let mut extensions =
+            juniper::Object::with_capacity(size + self.details.len());
         let _added_code = extensions
             .add_field("code", graphql_value!(self.code.into_owned()));
         let _added_status = extensions.add_field(
             "status",
             graphql_value!(i32::from(self.status.as_u16())),
         );
+        for (name, val) in self.details {
+            let _added_detail =
+                extensions.add_field(name, juniper::Value::from(val));
+        }
         if let Some(backtrace) = self.backtrace {
             let _added_backtrace = extensions.add_field(
                 "backtrace",
@@ -220,3 +344,201 @@ impl From<serde_json::Error> for Error {
             .message(&err)
     }
 }
+
+impl From<text::Violation> for Error {
+    #[inline]
+    fn from(err: text::Violation) -> Self {
+        Self::new("INVALID_TEXT")
+            .status(http::StatusCode::BAD_REQUEST)
+            .message(&err)
+            .detail("field", err.field())
+    }
+}
+
+impl From<complexity::Violation> for Error {
+    #[inline]
+    fn from(err: complexity::Violation) -> Self {
+        Self::new(err.code())
+            .status(http::StatusCode::BAD_REQUEST)
+            .message(&err)
+    }
+}
+
+/// Moment of time in UTC.
+///
+/// Represented in [GraphQL] as an [RFC 3339] string with an explicit offset.
+///
+/// [GraphQL]: https://graphql.com
+/// [RFC 3339]: https://tools.ietf.org/html/rfc3339
+#[derive(Clone, Copy, Debug, Eq, From, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DateTime(chrono::DateTime<Utc>);
+
+impl DateTime {
+    /// Returns the current moment of time.
+    #[inline]
+    #[must_use]
+    pub fn now() -> Self {
+        Self(Utc::now())
+    }
+
+    /// Parses a [`DateTime`] from the given [RFC 3339] string, converting it
+    /// to UTC.
+    ///
+    /// Returns [`None`] if the given string is not a valid [RFC 3339] date and
+    /// time, including the cases when it has no offset specified (so is
+    /// ambiguous).
+    ///
+    /// [RFC 3339]: https://tools.ietf.org/html/rfc3339
+    #[must_use]
+    pub fn parse(val: &str) -> Option<Self> {
+        chrono::DateTime::parse_from_rfc3339(val)
+            .ok()
+            .map(|dt| Self(dt.with_timezone(&Utc)))
+    }
+
+    /// Formats this [`DateTime`] as an [RFC 3339] string in UTC.
+    ///
+    /// [RFC 3339]: https://tools.ietf.org/html/rfc3339
+    #[inline]
+    #[must_use]
+    pub fn to_rfc3339(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    #[inline]
+    fn from(time: SystemTime) -> Self {
+        Self(time.into())
+    }
+}
+
+impl From<DateTime> for SystemTime {
+    #[inline]
+    fn from(time: DateTime) -> Self {
+        time.0.into()
+    }
+}
+
+impl fmt::Display for DateTime {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_rfc3339())
+    }
+}
+
+impl Serialize for DateTime {
+    #[inline]
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateTime {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).ok_or_else(|| {
+            D::Error::custom(format!("'{}' is not RFC 3339 date and time", s))
+        })
+    }
+}
+
+/// Moment of time in UTC, formatted as an [RFC 3339] string with an explicit
+/// offset (`2021-03-01T10:00:00.000Z`, for example).
+///
+/// Values without an offset are rejected as ambiguous.
+///
+/// [RFC 3339]: https://tools.ietf.org/html/rfc3339
+#[graphql_scalar]
+impl<S> GraphQLScalar for DateTime
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.to_rfc3339())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(Self::parse)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+#[cfg(test)]
+mod context_spec {
+    use super::{Context, Role};
+
+    #[test]
+    fn allows_mutations_for_admin_only() {
+        let ctx = Context::fake();
+        assert_eq!(ctx.role(), Role::Admin);
+        assert!(ctx.require_admin().is_ok());
+
+        let err = ctx.with_role(Role::Viewer).require_admin().unwrap_err();
+        assert_eq!(err.code, "UNAUTHORIZED");
+    }
+}
+
+#[cfg(test)]
+mod date_time_spec {
+    use juniper::{DefaultScalarValue, FromInputValue as _, InputValue};
+
+    use super::DateTime;
+
+    fn parse(val: &str) -> Option<DateTime> {
+        DateTime::from_input_value(&InputValue::<DefaultScalarValue>::scalar(
+            val.to_owned(),
+        ))
+    }
+
+    #[test]
+    fn parses_and_normalizes_to_utc() {
+        for (input, expected) in &[
+            ("2021-03-01T10:00:00Z", "2021-03-01T10:00:00.000Z"),
+            ("2021-03-01T10:00:00.123Z", "2021-03-01T10:00:00.123Z"),
+            ("2021-03-01T12:00:00+02:00", "2021-03-01T10:00:00.000Z"),
+            ("2021-03-01T00:30:00-01:00", "2021-03-01T01:30:00.000Z"),
+        ] {
+            let actual = parse(input)
+                .unwrap_or_else(|| panic!("Failed to parse: {}", input));
+            assert_eq!(actual.to_string(), *expected, "for: {}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for input in &[
+            "",
+            "not a date",
+            "2021-03-01",
+            "10:00:00Z",
+            "2021-03-01T10:00:00",
+            "2021-03-01T10:00:00.123",
+            "2021-13-01T10:00:00Z",
+            "2021-02-30T10:00:00Z",
+            "2021-03-01T25:00:00Z",
+            "2021-03-01T10:00:00+25:00",
+            "1614592800",
+        ] {
+            assert!(parse(input).is_none(), "parsed: {}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_non_string_values() {
+        assert!(DateTime::from_input_value(
+            &InputValue::<DefaultScalarValue>::scalar(1_614_592_800),
+        )
+        .is_none());
+    }
+}
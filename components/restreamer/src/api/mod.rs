@@ -1,4 +1,5 @@
 //! APIs used in application or provided by it.
 
 pub mod graphql;
+pub mod rest;
 pub mod srs;
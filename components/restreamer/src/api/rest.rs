@@ -0,0 +1,1076 @@
+//! Minimal [REST] API providing the most common operations, for integrations
+//! which cannot afford speaking GraphQL.
+//!
+//! All the endpoints are served under `/api/v1` prefix, and are protected by
+//! the same authorization as [`api::graphql::client`] is.
+//!
+//! [`api::graphql::client`]: crate::api::graphql::client
+//! [REST]: https://en.wikipedia.org/wiki/Representational_state_transfer
+
+use std::time::Duration;
+
+use actix_web::{
+    delete, get,
+    http::{header, HeaderMap},
+    post, web, HttpRequest, HttpResponse, Scope,
+};
+use futures::{
+    future::{self, Either},
+    pin_mut,
+    stream::{self, StreamExt as _},
+};
+use futures_signals::signal::SignalExt as _;
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::{
+    capacity,
+    state::{
+        DisableReason, Input, InputId, Label, Output, OutputDstUrl, OutputId,
+        Restream, RestreamId, RestreamKey, State, Status, Statuses,
+    },
+};
+
+/// Creates [`Scope`] serving all the endpoints of this [REST] API.
+///
+/// [REST]: https://en.wikipedia.org/wiki/Representational_state_transfer
+#[must_use]
+pub fn scope() -> Scope {
+    web::scope("/api/v1")
+        .service(health)
+        .service(health_detail)
+        .service(status)
+        .service(spec)
+        .service(enable_output)
+        .service(disable_output)
+        .service(enable_input)
+        .service(disable_input)
+        .service(remove_restream)
+        .service(remove_output)
+}
+
+/// Endpoint reporting health of this server.
+///
+/// Responds with `{"status": "ok"}` normally, or with `{"status": "draining"}`
+/// while the server is in drain mode (see [`State::draining`]).
+///
+/// If an event broker is configured, then its connection health is reported
+/// too, as `"event_broker": "connected"` or `"event_broker": "disconnected"`
+/// (see [`State::event_broker_connected`]).
+///
+/// If persisting the server state is failing (see
+/// [`State::persistence_health`]), then `"persistence_healthy": false` is
+/// reported along with the `"persistence_error"` describing the last failure.
+#[get("/health")]
+async fn health(req: HttpRequest) -> HttpResponse {
+    let state = state(&req);
+    HttpResponse::Ok().json(HealthResponse {
+        status: if state.draining.get() {
+            Health::Draining
+        } else {
+            Health::Ok
+        },
+        event_broker: state.event_broker_connected.get().map(|connected| {
+            if connected {
+                BrokerHealth::Connected
+            } else {
+                BrokerHealth::Disconnected
+            }
+        }),
+        persistence: PersistenceFailure::of(&state),
+    })
+}
+
+/// Endpoint reporting machine-readable capacity of this server, for load
+/// balancers weighting it among other servers.
+///
+/// Responds with the counts of inputs and [`Output`]s, the number of running
+/// [FFmpeg] processes, the CPU load average, and the derived capacity score
+/// (see [`capacity`] module for its formula).
+///
+/// Is calculated out of cached values only, so is cheap to be polled often.
+/// Failing persisting of the server state is reported in the same way
+/// [`health`] endpoint does.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[get("/health/detail")]
+async fn health_detail(req: HttpRequest) -> HttpResponse {
+    let state = state(&req);
+    let config = state.capacity.get();
+    let usage = capacity::Usage {
+        counts: state
+            .restreams_counts
+            .get(&state.restreams, &state.statuses),
+        processes: state.ffmpeg_pool.len(),
+        load_average: capacity::load_average(),
+        cpus: num_cpus::get(),
+        draining: state.draining.get(),
+    };
+    HttpResponse::Ok().json(HealthDetailResponse {
+        status: if usage.draining {
+            Health::Draining
+        } else {
+            Health::Ok
+        },
+        inputs: InputsCapacity {
+            total: usage.counts.inputs,
+            online: usage.counts.online_inputs,
+        },
+        outputs: OutputsCapacity {
+            total: usage.counts.outputs,
+            enabled: usage.counts.enabled_outputs,
+            online: usage.counts.online_outputs,
+        },
+        processes: ProcessesCapacity {
+            running: usage.processes,
+            capacity: config.processes,
+        },
+        load_average: usage.load_average,
+        cpus: usage.cpus,
+        score: capacity::score(&usage, &config),
+        persistence: PersistenceFailure::of(&state),
+    })
+}
+
+/// Endpoint returning a compact [JSON] status of all [`Restream`]s.
+///
+/// Responds with an `ETag` header being the current
+/// [`State::restreams_version()`], and with `304 Not Modified` if it matches
+/// the `If-None-Match` header of the request.
+///
+/// [JSON]: https://www.json.org
+#[get("/status")]
+async fn status(req: HttpRequest) -> HttpResponse {
+    let etag = format!("\"{}\"", state(&req).restreams_version());
+    if is_none_match_satisfied(req.headers(), &etag) {
+        return HttpResponse::NotModified()
+            .header(header::ETAG, etag)
+            .finish();
+    }
+
+    let state = state(&req);
+    let restreams = state.restreams.lock_ref();
+    let statuses = state.statuses.lock_ref();
+    HttpResponse::Ok()
+        .header(header::ETAG, etag)
+        .json(StatusResponse {
+            restreams: restreams
+                .iter()
+                .map(|r| RestreamStatus::new(r, &statuses))
+                .collect(),
+        })
+}
+
+/// Maximum time the [`spec`] endpoint holds a long-polling request for.
+pub const MAX_SPEC_WAIT: Duration = Duration::from_secs(60);
+
+/// Endpoint returning the whole [`Spec`] of this server (see
+/// [`State::export()`]), without credentials, for standby servers replicating
+/// it.
+///
+/// Responds with an `ETag` header being a hash of the [`Spec`], and with
+/// `304 Not Modified` if it matches the `If-None-Match` header of the request.
+/// `?wait=<secs>` query parameter makes the latter case to be a long polling:
+/// the response is held until the [`Spec`] changes, but no longer than the
+/// given number of seconds (capped by [`MAX_SPEC_WAIT`]).
+///
+/// [`Spec`]: crate::Spec
+#[get("/spec")]
+async fn spec(req: HttpRequest, query: web::Query<SpecQuery>) -> HttpResponse {
+    let state = state(&req);
+    let changes = stream::select(
+        state.restreams.signal_ref(|_| ()).to_stream(),
+        state.settings.signal_ref(|_| ()).to_stream(),
+    );
+    let deadline =
+        time::delay_for(Duration::from_secs(query.wait).min(MAX_SPEC_WAIT));
+    pin_mut!(changes, deadline);
+
+    loop {
+        let body = serde_json::to_vec(&state.export()).unwrap();
+        let etag = format!("\"{}\"", spec_version(&body));
+        if !is_none_match_satisfied(req.headers(), &etag) {
+            return HttpResponse::Ok()
+                .header(header::ETAG, etag)
+                .content_type("application/json")
+                .body(body);
+        }
+        // `restreams` change on their runtime state too (like problems),
+        // which is not the part of the `Spec`, so it's re-checked on every
+        // change.
+        match future::select(changes.next(), deadline.as_mut()).await {
+            Either::Left((Some(()), _)) => {}
+            Either::Left((None, _)) | Either::Right(_) => {
+                return HttpResponse::NotModified()
+                    .header(header::ETAG, etag)
+                    .finish();
+            }
+        }
+    }
+}
+
+/// Endpoint enabling an [`Output`] with the given ID.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Output`] has been enabled;
+/// - `404 Not Found` if there is no such [`Output`];
+/// - `409 Conflict` if the [`Output`] is enabled already.
+#[post("/outputs/{id}/enable")]
+async fn enable_output(
+    req: HttpRequest,
+    id: web::Path<OutputId>,
+) -> HttpResponse {
+    toggle_output(&req, id.into_inner(), State::enable_output)
+}
+
+/// Endpoint disabling an [`Output`] with the given ID.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Output`] has been disabled;
+/// - `404 Not Found` if there is no such [`Output`];
+/// - `409 Conflict` if the [`Output`] is disabled already.
+#[post("/outputs/{id}/disable")]
+async fn disable_output(
+    req: HttpRequest,
+    id: web::Path<OutputId>,
+) -> HttpResponse {
+    toggle_output(&req, id.into_inner(), State::disable_output)
+}
+
+/// Endpoint enabling an [`Input`] with the given ID.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Input`] has been enabled;
+/// - `404 Not Found` if there is no such [`Input`];
+/// - `409 Conflict` if the [`Input`] is enabled already.
+#[post("/inputs/{id}/enable")]
+async fn enable_input(
+    req: HttpRequest,
+    id: web::Path<InputId>,
+) -> HttpResponse {
+    toggle_input(&req, id.into_inner(), State::enable_input)
+}
+
+/// Endpoint disabling an [`Input`] with the given ID.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Input`] has been disabled;
+/// - `404 Not Found` if there is no such [`Input`];
+/// - `409 Conflict` if the [`Input`] is disabled already.
+#[post("/inputs/{id}/disable")]
+async fn disable_input(
+    req: HttpRequest,
+    id: web::Path<InputId>,
+) -> HttpResponse {
+    toggle_input(&req, id.into_inner(), |state, id, restream_id| {
+        state.disable_input(id, restream_id, DisableReason::manual("REST API"))
+    })
+}
+
+/// Endpoint removing a [`Restream`] with the given ID.
+///
+/// Refuses to remove a live [`Restream`] (see [`Restream::is_live()`]), unless
+/// `?force=true` query parameter is specified.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Restream`] has been removed (into the trash);
+/// - `404 Not Found` if there is no such [`Restream`];
+/// - `409 Conflict` with `{"error": "LIVE"}` if the [`Restream`] is live.
+#[delete("/restreams/{id}")]
+async fn remove_restream(
+    req: HttpRequest,
+    id: web::Path<RestreamId>,
+    query: web::Query<RemoveQuery>,
+) -> HttpResponse {
+    respond_removal(state(&req).remove_restream(id.into_inner(), query.force))
+}
+
+/// Endpoint removing an [`Output`] with the given ID.
+///
+/// Refuses to remove a [`Status::Online`] [`Output`], unless `?force=true`
+/// query parameter is specified.
+///
+/// Responds with:
+/// - `204 No Content` if the [`Output`] has been removed;
+/// - `404 Not Found` if there is no such [`Output`];
+/// - `409 Conflict` with `{"error": "LIVE"}` if the [`Output`] is live.
+#[delete("/outputs/{id}")]
+async fn remove_output(
+    req: HttpRequest,
+    id: web::Path<OutputId>,
+    query: web::Query<RemoveQuery>,
+) -> HttpResponse {
+    let state = state(&req);
+    let id = id.into_inner();
+    let restream_id = state
+        .restreams
+        .lock_ref()
+        .iter()
+        .find(|r| r.outputs.iter().any(|o| o.id == id))
+        .map(|r| r.id);
+    respond_removal(
+        restream_id
+            .map_or(Ok(None), |rid| state.remove_output(id, rid, query.force)),
+    )
+}
+
+/// Checks whether the `If-None-Match` header of the given `headers` matches
+/// the given `etag`, so the resource is not modified for the client.
+///
+/// Comparison is weak, as required by [RFC 7232][1].
+///
+/// [1]: https://tools.ietf.org/html/rfc7232#section-3.2
+#[must_use]
+pub fn is_none_match_satisfied(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Calculates version of the given serialized [`Spec`], used as its `ETag`.
+///
+/// [`Spec`]: crate::Spec
+#[must_use]
+fn spec_version(body: &[u8]) -> String {
+    blake2b_simd::Params::new()
+        .hash_length(8)
+        .hash(body)
+        .to_hex()
+        .to_string()
+}
+
+/// Extracts [`State`] from the given [`HttpRequest`].
+#[inline]
+fn state(req: &HttpRequest) -> &State {
+    req.app_data::<State>().unwrap()
+}
+
+/// Toggles an [`Output`] with the given `id` via the given [`State`] method,
+/// looking up the [`Restream`] it belongs to.
+fn toggle_output(
+    req: &HttpRequest,
+    id: OutputId,
+    toggle: fn(&State, OutputId, RestreamId) -> Option<bool>,
+) -> HttpResponse {
+    let state = state(req);
+    let restream_id = state
+        .restreams
+        .lock_ref()
+        .iter()
+        .find(|r| r.outputs.iter().any(|o| o.id == id))
+        .map(|r| r.id);
+    respond(restream_id.and_then(|rid| toggle(state, id, rid)))
+}
+
+/// Toggles an [`Input`] with the given `id` via the given [`State`] method,
+/// looking up the [`Restream`] it belongs to.
+fn toggle_input(
+    req: &HttpRequest,
+    id: InputId,
+    toggle: fn(&State, InputId, RestreamId) -> Option<bool>,
+) -> HttpResponse {
+    let state = state(req);
+    let restream_id = state
+        .restreams
+        .lock_ref()
+        .iter()
+        .find(|r| r.input.find(id).is_some())
+        .map(|r| r.id);
+    respond(restream_id.and_then(|rid| toggle(state, id, rid)))
+}
+
+/// Converts the result of toggling [`State`] method into [`HttpResponse`].
+fn respond(toggled: Option<bool>) -> HttpResponse {
+    match toggled {
+        Some(true) => HttpResponse::NoContent().finish(),
+        Some(false) => HttpResponse::Conflict().finish(),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Converts the result of removing [`State`] method into [`HttpResponse`].
+fn respond_removal(removed: anyhow::Result<Option<()>>) -> HttpResponse {
+    match removed {
+        Ok(Some(())) => HttpResponse::NoContent().finish(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::Conflict().json(ErrorResponse {
+            error: "LIVE",
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Query parameters of [`remove_restream`] and [`remove_output`] endpoints.
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct RemoveQuery {
+    /// Indicator whether a live entity should be removed anyway.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Query parameters of [`spec`] endpoint.
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct SpecQuery {
+    /// Number of seconds to wait for the [`Spec`] changes, if it's not
+    /// modified for the client.
+    ///
+    /// [`Spec`]: crate::Spec
+    #[serde(default)]
+    wait: u64,
+}
+
+/// Response of an endpoint refusing to perform an operation.
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    /// Unique literal code of the error, like GraphQL API returns.
+    error: &'static str,
+
+    /// Human-readable message of the error.
+    message: String,
+}
+
+/// Response of [`health`] endpoint.
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    /// Health of the server.
+    status: Health,
+
+    /// Health of the connection to the event broker, if it's configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_broker: Option<BrokerHealth>,
+
+    /// Failure of persisting the server state, if it's failing.
+    #[serde(flatten)]
+    persistence: Option<PersistenceFailure>,
+}
+
+/// Failure of persisting the server state, reported by [`health`] and
+/// [`health_detail`] endpoints.
+#[derive(Debug, Serialize)]
+struct PersistenceFailure {
+    /// Indicator whether the server state is persisted successfully, being
+    /// always `false`.
+    persistence_healthy: bool,
+
+    /// Error of the last failed persisting.
+    persistence_error: Option<String>,
+}
+
+impl PersistenceFailure {
+    /// Returns the [`PersistenceFailure`] of the given [`State`], if its
+    /// persisting is unhealthy.
+    #[must_use]
+    fn of(state: &State) -> Option<Self> {
+        let health = state.persistence_health.lock_ref();
+        if health.is_healthy() {
+            return None;
+        }
+        Some(Self {
+            persistence_healthy: false,
+            persistence_error: health.error().map(ToOwned::to_owned),
+        })
+    }
+}
+
+/// Response of [`health_detail`] endpoint.
+#[derive(Debug, Serialize)]
+struct HealthDetailResponse {
+    /// Health of the server.
+    status: Health,
+
+    /// Capacity of [`Restream`]s' inputs.
+    inputs: InputsCapacity,
+
+    /// Capacity of [`Output`]s.
+    outputs: OutputsCapacity,
+
+    /// Capacity of [FFmpeg] processes.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    processes: ProcessesCapacity,
+
+    /// 1-minute CPU load average, if known.
+    load_average: Option<f64>,
+
+    /// Number of CPUs.
+    cpus: usize,
+
+    /// Capacity score from `0` (no capacity left) to `100` (idle server).
+    score: u8,
+
+    /// Failure of persisting the server state, if it's failing.
+    #[serde(flatten)]
+    persistence: Option<PersistenceFailure>,
+}
+
+/// Capacity of [`Restream`]s' inputs reported by [`health_detail`] endpoint.
+#[derive(Debug, Serialize)]
+struct InputsCapacity {
+    /// Total number of inputs.
+    total: usize,
+
+    /// Number of inputs ready to serve a live stream.
+    online: usize,
+}
+
+/// Capacity of [`Output`]s reported by [`health_detail`] endpoint.
+#[derive(Debug, Serialize)]
+struct OutputsCapacity {
+    /// Total number of [`Output`]s.
+    total: usize,
+
+    /// Number of enabled [`Output`]s.
+    enabled: usize,
+
+    /// Number of enabled [`Output`]s being [`Status::Online`].
+    online: usize,
+}
+
+/// Capacity of [FFmpeg] processes reported by [`health_detail`] endpoint.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Debug, Serialize)]
+struct ProcessesCapacity {
+    /// Number of running [FFmpeg] processes.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    running: usize,
+
+    /// Number of [FFmpeg] processes this server is sized to run, if
+    /// configured.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    capacity: Option<u32>,
+}
+
+/// Health of the server reported by [`health`] endpoint.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Health {
+    /// Server operates normally.
+    Ok,
+
+    /// Server is in drain mode.
+    Draining,
+}
+
+/// Health of the connection to the event broker reported by [`health`]
+/// endpoint.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum BrokerHealth {
+    /// Connection to the event broker is established.
+    Connected,
+
+    /// Connection to the event broker is lost, and is being re-established.
+    Disconnected,
+}
+
+/// Response of [`status`] endpoint.
+#[derive(Debug, Serialize)]
+struct StatusResponse<'a> {
+    /// Statuses of all [`Restream`]s.
+    restreams: Vec<RestreamStatus<'a>>,
+}
+
+/// Compact status of a [`Restream`].
+#[derive(Debug, Serialize)]
+struct RestreamStatus<'a> {
+    /// ID of the [`Restream`].
+    id: RestreamId,
+
+    /// Key of the [`Restream`].
+    key: &'a RestreamKey,
+
+    /// Label of the [`Restream`], if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a Label>,
+
+    /// Status of the [`Restream::input`].
+    input: InputStatus,
+
+    /// Statuses of the [`Restream::outputs`].
+    outputs: Vec<OutputStatus<'a>>,
+}
+
+impl<'a> RestreamStatus<'a> {
+    /// Creates a new [`RestreamStatus`] of the given [`Restream`] having the
+    /// given `statuses`.
+    fn new(r: &'a Restream, statuses: &Statuses) -> Self {
+        Self {
+            id: r.id,
+            key: &r.key,
+            label: r.label.as_ref(),
+            input: InputStatus::new(&r.input, statuses),
+            outputs: r
+                .outputs
+                .iter()
+                .map(|o| OutputStatus::new(o, statuses))
+                .collect(),
+        }
+    }
+}
+
+/// Compact status of an [`Input`].
+#[derive(Debug, Serialize)]
+struct InputStatus {
+    /// ID of the [`Input`].
+    id: InputId,
+
+    /// Indicator whether the [`Input`] is enabled.
+    enabled: bool,
+
+    /// [`Status`] of the [RTMP] endpoint of the [`Input`].
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    status: Status,
+}
+
+impl InputStatus {
+    /// Creates a new [`InputStatus`] of the given [`Input`] having the given
+    /// `statuses`.
+    fn new(i: &Input, statuses: &Statuses) -> Self {
+        Self {
+            id: i.id,
+            enabled: i.enabled,
+            status: i
+                .endpoints
+                .iter()
+                .find(|e| e.is_rtmp())
+                .map(|e| statuses.get(e.id))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Compact status of an [`Output`].
+#[derive(Debug, Serialize)]
+struct OutputStatus<'a> {
+    /// ID of the [`Output`].
+    id: OutputId,
+
+    /// Downstream URL of the [`Output`].
+    dst: &'a OutputDstUrl,
+
+    /// Label of the [`Output`], if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a Label>,
+
+    /// Indicator whether the [`Output`] is enabled.
+    enabled: bool,
+
+    /// [`Status`] of the [`Output`].
+    status: Status,
+}
+
+impl<'a> OutputStatus<'a> {
+    /// Creates a new [`OutputStatus`] of the given [`Output`] having the given
+    /// `statuses`.
+    fn new(o: &'a Output, statuses: &Statuses) -> Self {
+        Self {
+            id: o.id,
+            dst: &o.dst,
+            label: o.label.as_ref(),
+            enabled: o.enabled,
+            status: statuses.get(o.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod endpoints_spec {
+    use std::time::{Duration, Instant};
+
+    use actix_web::{
+        http::{header, StatusCode},
+        rt::System,
+        test, App,
+    };
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::{
+        capacity, spec,
+        state::{PersistenceHealth, State, Status},
+    };
+
+    use super::scope;
+
+    fn state() -> State {
+        State::with_restream(
+            "test",
+            json!({"outputs": [{"dst": "rtmp://example.com/live/stream"}]}),
+        )
+    }
+
+    #[test]
+    fn responds_not_modified_status() {
+        System::new("test").block_on(async {
+            let state = state();
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            let req = test::TestRequest::get().uri("/api/v1/status");
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let etag = resp.headers().get(header::ETAG).unwrap();
+            let etag = etag.to_str().unwrap().to_owned();
+
+            for (if_none_match, expected) in &[
+                (etag.clone(), StatusCode::NOT_MODIFIED),
+                (format!("\"other\", W/{}", etag), StatusCode::NOT_MODIFIED),
+                ("*".to_owned(), StatusCode::NOT_MODIFIED),
+                ("\"other\"".to_owned(), StatusCode::OK),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/api/v1/status")
+                    .header(header::IF_NONE_MATCH, if_none_match.as_str());
+                let resp = test::call_service(&mut app, req.to_request()).await;
+                assert_eq!(resp.status(), *expected, "{}", if_none_match);
+                let resp_etag = resp.headers().get(header::ETAG).unwrap();
+                assert_eq!(resp_etag, etag.as_str());
+            }
+
+            let id = state.restreams.lock_ref()[0].outputs[0].id;
+            let _ = state.statuses.set(id, Status::Online);
+            state.restreams_version.invalidate();
+            let req = test::TestRequest::get()
+                .uri("/api/v1/status")
+                .header(header::IF_NONE_MATCH, etag.as_str());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn long_polls_spec() {
+        System::new("test").block_on(async {
+            let state = state();
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            let req = test::TestRequest::get().uri("/api/v1/spec");
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let etag = resp.headers().get(header::ETAG).unwrap();
+            let etag = etag.to_str().unwrap().to_owned();
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["restreams"][0]["key"], "test");
+
+            let req = test::TestRequest::get()
+                .uri("/api/v1/spec?wait=0")
+                .header(header::IF_NONE_MATCH, etag.as_str());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+            let changed = state.clone();
+            drop(tokio::spawn(async move {
+                time::delay_for(Duration::from_millis(50)).await;
+                // Statuses are not the part of `Spec`.
+                let id = changed.restreams.lock_ref()[0].outputs[0].id;
+                let _ = changed.statuses.set(id, Status::Online);
+                time::delay_for(Duration::from_millis(50)).await;
+                changed.settings.lock_mut().title = Some("Changed".into());
+            }));
+            let started = Instant::now();
+            let req = test::TestRequest::get()
+                .uri("/api/v1/spec?wait=10")
+                .header(header::IF_NONE_MATCH, etag.as_str());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(started.elapsed() >= Duration::from_millis(100));
+            assert!(started.elapsed() < Duration::from_secs(5));
+            assert_ne!(resp.headers().get(header::ETAG).unwrap(), &etag);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["settings"]["title"], "Changed");
+        });
+    }
+
+    #[test]
+    fn toggles_output() {
+        System::new("test").block_on(async {
+            let state = state();
+            let id = state.restreams.get_cloned()[0].outputs[0].id;
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (action, expected) in &[
+                ("enable", StatusCode::NO_CONTENT),
+                ("enable", StatusCode::CONFLICT),
+                ("disable", StatusCode::NO_CONTENT),
+                ("disable", StatusCode::CONFLICT),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri(&format!("/api/v1/outputs/{}/{}", id, action))
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), *expected, "{} output", action);
+            }
+        });
+    }
+
+    #[test]
+    fn toggles_input() {
+        System::new("test").block_on(async {
+            let state = state();
+            let id = state.restreams.get_cloned()[0].input.id;
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (action, expected) in &[
+                ("enable", StatusCode::CONFLICT),
+                ("disable", StatusCode::NO_CONTENT),
+                ("disable", StatusCode::CONFLICT),
+                ("enable", StatusCode::NO_CONTENT),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri(&format!("/api/v1/inputs/{}/{}", id, action))
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), *expected, "{} input", action);
+            }
+        });
+    }
+
+    #[test]
+    fn responds_not_found_for_unknown_ids() {
+        System::new("test").block_on(async {
+            let mut app = test::init_service(
+                App::new().app_data(state()).service(scope()),
+            )
+            .await;
+
+            for uri in &[
+                "/api/v1/outputs/1c8f07c5-7d5b-4f0c-9b8e-6b1c9a2f3e4d/enable",
+                "/api/v1/inputs/1c8f07c5-7d5b-4f0c-9b8e-6b1c9a2f3e4d/disable",
+            ] {
+                let req = test::TestRequest::post().uri(uri).to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+            }
+        });
+    }
+
+    #[test]
+    fn refuses_removing_live_output_unless_forced() {
+        System::new("test").block_on(async {
+            let state = state();
+            let id = state.restreams.lock_ref()[0].outputs[0].id;
+            let _ = state.statuses.set(id, Status::Online);
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (query, expected) in &[
+                ("", StatusCode::CONFLICT),
+                ("?force=false", StatusCode::CONFLICT),
+                ("?force=true", StatusCode::NO_CONTENT),
+                ("?force=true", StatusCode::NOT_FOUND),
+            ] {
+                let req = test::TestRequest::delete()
+                    .uri(&format!("/api/v1/outputs/{}{}", id, query))
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), *expected, "query {:?}", query);
+            }
+        });
+    }
+
+    #[test]
+    fn refuses_removing_live_restream_unless_forced() {
+        System::new("test").block_on(async {
+            let state = state();
+            let (id, endpoint) = {
+                let restreams = state.restreams.lock_ref();
+                (restreams[0].id, restreams[0].input.endpoints[0].id)
+            };
+            let _ = state.statuses.set(endpoint, Status::Online);
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            let req = test::TestRequest::delete()
+                .uri(&format!("/api/v1/restreams/{}", id))
+                .to_request();
+            let resp: serde_json::Value =
+                test::read_response_json(&mut app, req).await;
+            assert_eq!(resp["error"], "LIVE");
+            assert_eq!(state.restreams.lock_ref().len(), 1);
+
+            let req = test::TestRequest::delete()
+                .uri(&format!("/api/v1/restreams/{}?force=true", id))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert!(state.restreams.lock_ref().is_empty());
+        });
+    }
+
+    #[test]
+    fn reports_draining_health() {
+        System::new("test").block_on(async {
+            let state = state();
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (draining, expected) in &[(false, "ok"), (true, "draining")] {
+                let _ = state.set_draining(*draining);
+                let req =
+                    test::TestRequest::get().uri("/api/v1/health").to_request();
+                let resp: serde_json::Value =
+                    test::read_response_json(&mut app, req).await;
+                assert_eq!(resp, json!({ "status": expected }));
+            }
+        });
+    }
+
+    #[test]
+    fn reports_capacity() {
+        System::new("test").block_on(async {
+            let state = state();
+            state.restreams.lock_mut()[0].outputs[0].enabled = true;
+            state.capacity.set(capacity::Config {
+                processes: Some(4),
+                weights: "load=0".parse().unwrap(),
+            });
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            let req = test::TestRequest::get()
+                .uri("/api/v1/health/detail")
+                .to_request();
+            let resp: serde_json::Value =
+                test::read_response_json(&mut app, req).await;
+
+            assert_eq!(resp["status"], "ok");
+            assert_eq!(resp["inputs"], json!({"total": 1, "online": 0}));
+            assert_eq!(
+                resp["outputs"],
+                json!({"total": 1, "enabled": 1, "online": 0}),
+            );
+            assert_eq!(resp["processes"], json!({"running": 0, "capacity": 4}));
+            // The only enabled `Output` is offline, while no processes run.
+            assert_eq!(resp["score"], 50);
+        });
+    }
+
+    #[test]
+    fn reports_event_broker_health() {
+        System::new("test").block_on(async {
+            let state = state();
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (connected, expected) in
+                &[(true, "connected"), (false, "disconnected")]
+            {
+                state.event_broker_connected.set(Some(*connected));
+                let req =
+                    test::TestRequest::get().uri("/api/v1/health").to_request();
+                let resp: serde_json::Value =
+                    test::read_response_json(&mut app, req).await;
+                assert_eq!(
+                    resp,
+                    json!({ "status": "ok", "event_broker": expected }),
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn reports_persistence_failure() {
+        System::new("test").block_on(async {
+            let state = state();
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            for (failures, expected) in &[
+                (1, json!({ "status": "ok" })),
+                (
+                    PersistenceHealth::MAX_FAILURES,
+                    json!({
+                        "status": "ok",
+                        "persistence_healthy": false,
+                        "persistence_error": "No space left on device",
+                    }),
+                ),
+            ] {
+                state.persistence_health.set(PersistenceHealth {
+                    failures: *failures,
+                    last_error: Some("No space left on device".into()),
+                });
+                let req =
+                    test::TestRequest::get().uri("/api/v1/health").to_request();
+                let resp: serde_json::Value =
+                    test::read_response_json(&mut app, req).await;
+                assert_eq!(&resp, expected);
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/v1/health/detail")
+                .to_request();
+            let resp: serde_json::Value =
+                test::read_response_json(&mut app, req).await;
+            assert_eq!(resp["persistence_healthy"], false);
+        });
+    }
+
+    #[test]
+    fn reports_status() {
+        System::new("test").block_on(async {
+            let state = state();
+            let r = state.restreams.get_cloned().remove(0);
+            let mut app = test::init_service(
+                App::new().app_data(state.clone()).service(scope()),
+            )
+            .await;
+
+            let req =
+                test::TestRequest::get().uri("/api/v1/status").to_request();
+            let resp: serde_json::Value =
+                test::read_response_json(&mut app, req).await;
+
+            assert_eq!(
+                resp,
+                json!({
+                    "restreams": [{
+                        "id": r.id,
+                        "key": "test",
+                        "input": {
+                            "id": r.input.id,
+                            "enabled": true,
+                            "status": "offline",
+                        },
+                        "outputs": [{
+                            "id": r.outputs[0].id,
+                            "dst": "rtmp://example.com/live/stream",
+                            "enabled": false,
+                            "status": "offline",
+                        }],
+                    }],
+                }),
+            );
+        });
+    }
+}
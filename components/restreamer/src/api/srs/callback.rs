@@ -3,12 +3,22 @@
 //! [SRS]: https://github.com/ossrs/srs
 //! [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
 
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// Maximum allowed size (in bytes) of a [`Request`] body.
+///
+/// [SRS] callbacks are tiny, so anything bigger is not a callback.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub const MAX_REQUEST_SIZE: usize = 16 * 1024;
 
 /// Request performed by [SRS] to [HTTP Callback API][1].
 ///
+/// Tolerates unknown fields, as different [SRS] versions provide different
+/// sets of them.
+///
 /// [SRS]: https://github.com/ossrs/srs
 /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -21,11 +31,13 @@ pub struct Request {
     /// ID of [SRS] client that happened event is related to.
     ///
     /// [SRS]: https://github.com/ossrs/srs
+    #[serde(deserialize_with = "Request::deserialize_client_id")]
     pub client_id: u32,
 
     /// IP address of [SRS] client that happened event is related to.
     ///
     /// [SRS]: https://github.com/ossrs/srs
+    #[serde(deserialize_with = "Request::deserialize_ip")]
     pub ip: IpAddr,
 
     /// [SRS] `vhost` ([virtual host][1]) of RTMP stream that happened event is
@@ -33,6 +45,7 @@ pub struct Request {
     ///
     /// [SRS]: https://github.com/ossrs/srs
     /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_RtmpUrlVhost
+    #[serde(default)]
     pub vhost: String,
 
     /// [SRS] `app` of RTMP stream that happened event is related to.
@@ -47,13 +60,69 @@ pub struct Request {
     pub stream: Option<String>,
 }
 
+impl Request {
+    /// Deserializes [`Request::client_id`] either from a number or from a
+    /// numeric string, as different [SRS] versions provide it differently.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn deserialize_client_id<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<u32, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Num(u32),
+            Str(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Num(n) => Ok(n),
+            Raw::Str(s) => s.parse().map_err(|_| {
+                de::Error::invalid_value(
+                    de::Unexpected::Str(&s),
+                    &"numeric `client_id`",
+                )
+            }),
+        }
+    }
+
+    /// Deserializes [`Request::ip`] tolerating the formats provided by
+    /// different [SRS] versions: with a port, or as an IPv4-mapped IPv6
+    /// address (which is unmapped then).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn deserialize_ip<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<IpAddr, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let ip = raw
+            .parse::<IpAddr>()
+            .or_else(|_| raw.parse::<SocketAddr>().map(|a| a.ip()))
+            .map_err(|_| {
+                de::Error::invalid_value(
+                    de::Unexpected::Str(&raw),
+                    &"IP address",
+                )
+            })?;
+        Ok(match ip {
+            IpAddr::V6(v6) => match v6.octets() {
+                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                    Ipv4Addr::new(a, b, c, d).into()
+                }
+                _ => ip,
+            },
+            IpAddr::V4(_) => ip,
+        })
+    }
+}
+
 /// Possible [SRS] events in [HTTP Callback API][1] that this application reacts
 /// onto.
 ///
 /// [SRS]: https://github.com/ossrs/srs
 /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
 #[allow(clippy::pub_enum_variant_names)]
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Event {
     /// [SRS] client connects to [SRS] `app`.
@@ -86,4 +155,218 @@ pub enum Event {
     /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
     /// [SRS]: https://github.com/ossrs/srs
     OnHls,
+
+    /// Any other [SRS] event this application doesn't react onto (`on_dvr`,
+    /// for example).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(other)]
+    Unknown,
+}
+
+impl Event {
+    /// Parses only the [`Event`] of the given raw [`Request`] `body`, ignoring
+    /// all its other fields.
+    ///
+    /// Useful for recognizing [`Event::Unknown`]s, whose payloads may not be
+    /// a valid [`Request`] at all.
+    #[must_use]
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Raw {
+            action: Event,
+        }
+
+        serde_json::from_slice::<Raw>(body).ok().map(|r| r.action)
+    }
+}
+
+#[cfg(test)]
+mod request_spec {
+    use std::net::IpAddr;
+
+    use super::{Event, Request};
+
+    /// Real [`Request`] payloads captured from different [SRS] versions, along
+    /// with their expected [`Event`]s.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const FIXTURES: &[(&str, Event, &str)] = &[
+        (
+            "3.0-r0 on_connect",
+            Event::OnConnect,
+            include_str!("fixtures/callback/3.0-r0/on_connect.json"),
+        ),
+        (
+            "3.0-r0 on_publish",
+            Event::OnPublish,
+            include_str!("fixtures/callback/3.0-r0/on_publish.json"),
+        ),
+        (
+            "3.0-r5 on_publish",
+            Event::OnPublish,
+            include_str!("fixtures/callback/3.0-r5/on_publish.json"),
+        ),
+        (
+            "3.0-r5 on_unpublish",
+            Event::OnUnpublish,
+            include_str!("fixtures/callback/3.0-r5/on_unpublish.json"),
+        ),
+        (
+            "3.0-r5 on_play",
+            Event::OnPlay,
+            include_str!("fixtures/callback/3.0-r5/on_play.json"),
+        ),
+        (
+            "3.0-r5 on_stop",
+            Event::OnStop,
+            include_str!("fixtures/callback/3.0-r5/on_stop.json"),
+        ),
+        (
+            "3.0-r5 on_hls",
+            Event::OnHls,
+            include_str!("fixtures/callback/3.0-r5/on_hls.json"),
+        ),
+        (
+            "3.0-r5 on_dvr",
+            Event::Unknown,
+            include_str!("fixtures/callback/3.0-r5/on_dvr.json"),
+        ),
+        (
+            "4.0 on_connect",
+            Event::OnConnect,
+            include_str!("fixtures/callback/4.0/on_connect.json"),
+        ),
+        (
+            "4.0 on_publish",
+            Event::OnPublish,
+            include_str!("fixtures/callback/4.0/on_publish.json"),
+        ),
+        (
+            "4.0 on_unpublish",
+            Event::OnUnpublish,
+            include_str!("fixtures/callback/4.0/on_unpublish.json"),
+        ),
+        (
+            "4.0 on_play",
+            Event::OnPlay,
+            include_str!("fixtures/callback/4.0/on_play.json"),
+        ),
+        (
+            "4.0 on_stop",
+            Event::OnStop,
+            include_str!("fixtures/callback/4.0/on_stop.json"),
+        ),
+        (
+            "5.0 on_connect",
+            Event::OnConnect,
+            include_str!("fixtures/callback/5.0/on_connect.json"),
+        ),
+        (
+            "5.0 on_publish",
+            Event::OnPublish,
+            include_str!("fixtures/callback/5.0/on_publish.json"),
+        ),
+        (
+            "5.0 on_unpublish",
+            Event::OnUnpublish,
+            include_str!("fixtures/callback/5.0/on_unpublish.json"),
+        ),
+        (
+            "5.0 on_play",
+            Event::OnPlay,
+            include_str!("fixtures/callback/5.0/on_play.json"),
+        ),
+        (
+            "5.0 on_stop",
+            Event::OnStop,
+            include_str!("fixtures/callback/5.0/on_stop.json"),
+        ),
+    ];
+
+    fn parse(raw: &str) -> Result<Request, serde_json::Error> {
+        serde_json::from_str::<Request>(raw)
+    }
+
+    fn with_ip(ip: &str) -> String {
+        format!(
+            r#"{{
+                "action": "on_publish",
+                "client_id": 341,
+                "ip": "{}",
+                "app": "main",
+                "stream": "origin"
+            }}"#,
+            ip,
+        )
+    }
+
+    #[test]
+    fn parses_real_payloads() {
+        for (name, event, raw) in FIXTURES {
+            let req = parse(raw).unwrap_or_else(|e| {
+                panic!("Failed to parse '{}' payload: {}", name, e)
+            });
+            assert_eq!(req.action, *event, "{}", name);
+            assert_eq!(req.app, "main", "{}", name);
+            if !matches!(event, Event::OnConnect | Event::Unknown) {
+                assert_eq!(req.stream.as_deref(), Some("origin"), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_string_client_id() {
+        for (name, _, raw) in FIXTURES {
+            if name.starts_with("4.0 on_unpublish") {
+                assert_eq!(parse(raw).unwrap().client_id, 345);
+            }
+            if name.starts_with("5.0 on_publish") {
+                assert_eq!(parse(raw).unwrap().client_id, 512);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_non_numeric_client_id() {
+        let raw = r#"{
+            "action": "on_play",
+            "client_id": "341w361a",
+            "ip": "127.0.0.1",
+            "vhost": "__defaultVhost__",
+            "app": "main"
+        }"#;
+        assert!(parse(raw).is_err());
+    }
+
+    #[test]
+    fn parses_ip_in_any_format() {
+        for (raw, expected) in &[
+            ("172.17.0.1", "172.17.0.1"),
+            ("::ffff:172.17.0.1", "172.17.0.1"),
+            ("172.17.0.1:50432", "172.17.0.1"),
+            ("[2001:db8::1]:50432", "2001:db8::1"),
+            ("2001:db8::1", "2001:db8::1"),
+        ] {
+            assert_eq!(
+                parse(&with_ip(raw)).unwrap().ip,
+                expected.parse::<IpAddr>().unwrap(),
+                "{}",
+                raw,
+            );
+        }
+        assert!(parse(&with_ip("localhost")).is_err());
+    }
+
+    #[test]
+    fn parses_action_of_any_payload() {
+        for (name, event, raw) in FIXTURES {
+            assert_eq!(Event::parse(raw.as_bytes()), Some(*event), "{}", name);
+        }
+        assert_eq!(
+            Event::parse(br#"{"action": "on_forward", "tcUrl": "x"}"#),
+            Some(Event::Unknown),
+        );
+        assert_eq!(Event::parse(b"garbage"), None);
+    }
 }
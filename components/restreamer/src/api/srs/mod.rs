@@ -5,7 +5,12 @@
 
 pub mod callback;
 
+use std::fmt;
+
 use derive_more::{Display, Error};
+use serde::{Deserialize, Deserializer};
+
+use crate::srs;
 
 /// Client for performing requests to [HTTP API][1] of locally spawned [SRS].
 ///
@@ -15,12 +20,51 @@ use derive_more::{Display, Error};
 pub struct Client;
 
 impl Client {
-    /// [URL] of v1 [HTTP API][1] hosted by local [SRS].
+    /// Number of clients requested from [SRS] server at once when
+    /// [listing][`Client::clients()`] them.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const CLIENTS_PAGE_SIZE: usize = 100;
+
+    /// Number of streams requested from [SRS] server at once when
+    /// [listing][`Client::streams()`] them.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const STREAMS_PAGE_SIZE: usize = 100;
+
+    /// Returns [URL] of v1 [HTTP API][1] hosted by local [SRS].
     ///
     /// [SRS]: https://github.com/ossrs/srs
     /// [URL]: https://en.wikipedia.org/wiki/URL
     /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
-    const V1_URL: &'static str = "http://127.0.0.1:8002/api/v1";
+    #[inline]
+    #[must_use]
+    fn v1_url() -> String {
+        format!("http://127.0.0.1:{}/api/v1", srs::http_api_port())
+    }
+
+    /// Checks whether [HTTP API][1] of [SRS] server responds, by requesting
+    /// its [versions][2].
+    ///
+    /// # Errors
+    ///
+    /// If API request cannot be performed, or fails. See [`Error`](enum@Error)
+    /// for details.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+    /// [2]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#versions
+    pub async fn ping() -> Result<(), Error> {
+        let resp = reqwest::Client::new()
+            .get(&format!("{}/versions", Self::v1_url()))
+            .send()
+            .await
+            .map_err(Error::RequestFailed)?;
+        if !resp.status().is_success() {
+            return Err(Error::BadStatus(resp.status()));
+        }
+        Ok(())
+    }
 
     /// [Kicks off][1] a client connected to [SRS] server by its `id`.
     ///
@@ -31,9 +75,9 @@ impl Client {
     ///
     /// [SRS]: https://github.com/ossrs/srs
     /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#kickoff-client
-    pub async fn kickoff_client(id: u32) -> Result<(), Error> {
+    pub async fn kickoff_client<I: fmt::Display>(id: I) -> Result<(), Error> {
         let resp = reqwest::Client::new()
-            .delete(&format!("{}/clients/{}", Self::V1_URL, id))
+            .delete(&format!("{}/clients/{}", Self::v1_url(), id))
             .send()
             .await
             .map_err(Error::RequestFailed)?;
@@ -42,6 +86,235 @@ impl Client {
         }
         Ok(())
     }
+
+    /// [Lists][1] all the clients connected to [SRS] server at the moment.
+    ///
+    /// # Errors
+    ///
+    /// If API request cannot be performed, or fails. See [`Error`](enum@Error)
+    /// for details.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#clients
+    pub async fn clients() -> Result<Vec<ClientInfo>, Error> {
+        /// Response of [SRS] server [listing][1] its clients.
+        ///
+        /// [SRS]: https://github.com/ossrs/srs
+        /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#clients
+        #[derive(Deserialize)]
+        struct Response {
+            /// Requested page of the clients.
+            #[serde(default)]
+            clients: Vec<ClientInfo>,
+        }
+
+        let mut all = vec![];
+        loop {
+            let resp = reqwest::Client::new()
+                .get(&format!(
+                    "{}/clients?start={}&count={}",
+                    Self::v1_url(),
+                    all.len(),
+                    Self::CLIENTS_PAGE_SIZE,
+                ))
+                .send()
+                .await
+                .map_err(Error::RequestFailed)?;
+            if !resp.status().is_success() {
+                return Err(Error::BadStatus(resp.status()));
+            }
+            let page = resp
+                .json::<Response>()
+                .await
+                .map_err(Error::RequestFailed)?
+                .clients;
+            let is_last = page.len() < Self::CLIENTS_PAGE_SIZE;
+            all.extend(page);
+            if is_last {
+                return Ok(all);
+            }
+        }
+    }
+
+    /// [Lists][1] all the streams served by [SRS] server at the moment.
+    ///
+    /// # Errors
+    ///
+    /// If API request cannot be performed, or fails. See [`Error`](enum@Error)
+    /// for details.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#streams
+    pub async fn streams() -> Result<Vec<StreamInfo>, Error> {
+        /// Response of [SRS] server [listing][1] its streams.
+        ///
+        /// [SRS]: https://github.com/ossrs/srs
+        /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#streams
+        #[derive(Deserialize)]
+        struct Response {
+            /// Requested page of the streams.
+            #[serde(default)]
+            streams: Vec<StreamInfo>,
+        }
+
+        let mut all = vec![];
+        loop {
+            let resp = reqwest::Client::new()
+                .get(&format!(
+                    "{}/streams?start={}&count={}",
+                    Self::v1_url(),
+                    all.len(),
+                    Self::STREAMS_PAGE_SIZE,
+                ))
+                .send()
+                .await
+                .map_err(Error::RequestFailed)?;
+            if !resp.status().is_success() {
+                return Err(Error::BadStatus(resp.status()));
+            }
+            let page = resp
+                .json::<Response>()
+                .await
+                .map_err(Error::RequestFailed)?
+                .streams;
+            let is_last = page.len() < Self::STREAMS_PAGE_SIZE;
+            all.extend(page);
+            if is_last {
+                return Ok(all);
+            }
+        }
+    }
+}
+
+/// Information about a client connected to [SRS] server, as reported by its
+/// [HTTP API][1].
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#clients
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ClientInfo {
+    /// ID of the client.
+    ///
+    /// Numeric in [SRS] 3.x, while arbitrary string since [SRS] 4.0.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(deserialize_with = "ClientInfo::deserialize_id")]
+    pub id: String,
+
+    /// Path of the stream URL the client is connected to, like
+    /// `/{app}/{stream}`.
+    #[serde(default)]
+    pub url: String,
+
+    /// Indicator whether the client publishes the stream, rather than plays
+    /// it.
+    #[serde(default)]
+    pub publish: bool,
+}
+
+impl ClientInfo {
+    /// Returns the `app` and the `stream` that this client is connected to,
+    /// if it's connected to any.
+    #[must_use]
+    pub fn app_stream(&self) -> Option<(&str, &str)> {
+        let path = self.url.split('?').next()?.trim_start_matches('/');
+        let mut parts = path.splitn(2, '/');
+        let app = parts.next().filter(|a| !a.is_empty())?;
+        let stream = parts.next().filter(|s| !s.is_empty())?;
+        Some((app, stream.trim_end_matches(".flv")))
+    }
+
+    /// Deserializes [`ClientInfo::id`] either from a number or from a string,
+    /// as different [SRS] versions provide it differently.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn deserialize_id<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<String, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Num(u64),
+            Str(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Num(n) => n.to_string(),
+            Raw::Str(s) => s,
+        })
+    }
+}
+
+/// Information about a stream served by [SRS] server, as reported by its
+/// [HTTP API][1].
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi#streams
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct StreamInfo {
+    /// Name of the app the stream belongs to.
+    #[serde(default)]
+    pub app: String,
+
+    /// Name of the stream inside its [`StreamInfo::app`].
+    #[serde(default)]
+    pub name: String,
+
+    /// Information about the publisher of the stream.
+    #[serde(default)]
+    pub publish: PublishInfo,
+
+    /// Information about the video track of the stream, if it has any.
+    #[serde(default)]
+    pub video: Option<VideoInfo>,
+
+    /// Information about the audio track of the stream, if it has any.
+    #[serde(default)]
+    pub audio: Option<AudioInfo>,
+}
+
+/// Information about the publisher of a [`StreamInfo`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct PublishInfo {
+    /// Indicator whether the stream is being published at the moment.
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Information about the video track of a [`StreamInfo`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct VideoInfo {
+    /// Name of the video codec, like `H264` or `HEVC`.
+    #[serde(default)]
+    pub codec: String,
+
+    /// Width of the video frame in pixels.
+    ///
+    /// Reported since [SRS] 4.0 only.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(default)]
+    pub width: Option<u32>,
+
+    /// Height of the video frame in pixels.
+    ///
+    /// Reported since [SRS] 4.0 only.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(default)]
+    pub height: Option<u32>,
+}
+
+/// Information about the audio track of a [`StreamInfo`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct AudioInfo {
+    /// Name of the audio codec, like `AAC` or `MP3`.
+    #[serde(default)]
+    pub codec: String,
+
+    /// Number of the audio channels.
+    #[serde(default)]
+    pub channel: Option<u32>,
 }
 
 /// Possible errors of performing requests to [SRS HTTP API][1].
@@ -60,3 +333,98 @@ pub enum Error {
     #[display(fmt = "SRS HTTP API responded with bad status: {}", _0)]
     BadStatus(#[error(not(source))] reqwest::StatusCode),
 }
+
+#[cfg(test)]
+mod client_info_spec {
+    use serde_json::json;
+
+    use super::ClientInfo;
+
+    #[test]
+    fn parses_id_of_any_srs_version() {
+        for (id, expected) in
+            &[(json!(107), "107"), (json!("3p0i6ve1"), "3p0i6ve1")]
+        {
+            let info: ClientInfo =
+                serde_json::from_value(json!({ "id": id, "url": "/a/b" }))
+                    .unwrap();
+            assert_eq!(info.id, *expected);
+        }
+    }
+
+    #[test]
+    fn extracts_app_and_stream() {
+        for (url, expected) in &[
+            ("/live/origin", Some(("live", "origin"))),
+            ("/live/origin.flv", Some(("live", "origin"))),
+            (
+                "/live/origin?vhost=live.lowlatency",
+                Some(("live", "origin")),
+            ),
+            ("/live", None),
+            ("", None),
+        ] {
+            let info = ClientInfo {
+                id: "1".into(),
+                url: (*url).into(),
+                publish: false,
+            };
+            assert_eq!(info.app_stream(), *expected, "for: {}", url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod stream_info_spec {
+    use serde_json::Value;
+
+    use super::StreamInfo;
+
+    #[test]
+    fn parses_codecs_of_streams() {
+        let resp: Value = serde_json::from_str(include_str!(
+            "fixtures/streams/4.0/streams.json"
+        ))
+        .unwrap();
+        let streams: Vec<StreamInfo> =
+            serde_json::from_value(resp["streams"].clone()).unwrap();
+        assert_eq!(streams.len(), 3);
+
+        let main = &streams[0];
+        assert_eq!((main.app.as_str(), main.name.as_str()), ("main", "origin"));
+        assert!(main.publish.active);
+        let video = main.video.as_ref().unwrap();
+        assert_eq!(video.codec, "H264");
+        assert_eq!((video.width, video.height), (Some(1920), Some(1080)));
+        let audio = main.audio.as_ref().unwrap();
+        assert_eq!(audio.codec, "AAC");
+        assert_eq!(audio.channel, Some(2));
+
+        let gone = &streams[2];
+        assert!(!gone.publish.active);
+        assert!(gone.video.is_none());
+        assert!(gone.audio.is_none());
+    }
+    #[test]
+    fn parses_codecs_of_srs3_streams() {
+        let resp: Value = serde_json::from_str(include_str!(
+            "fixtures/streams/3.0-r5/streams.json"
+        ))
+        .unwrap();
+        let streams: Vec<StreamInfo> =
+            serde_json::from_value(resp["streams"].clone()).unwrap();
+        assert_eq!(streams.len(), 2);
+
+        let main = &streams[0];
+        assert_eq!((main.app.as_str(), main.name.as_str()), ("main", "origin"));
+        assert!(main.publish.active);
+        let video = main.video.as_ref().unwrap();
+        assert_eq!(video.codec, "H264");
+        assert_eq!((video.width, video.height), (None, None));
+        let audio = main.audio.as_ref().unwrap();
+        assert_eq!(audio.codec, "AAC");
+        assert_eq!(audio.channel, Some(2));
+
+        assert!(!streams[1].publish.active);
+    }
+}
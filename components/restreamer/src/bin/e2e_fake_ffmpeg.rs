@@ -0,0 +1,63 @@
+//! Binary pretending to be [FFmpeg] in end-to-end tests.
+//!
+//! Capabilities are reported for detection, while any other invocation is a
+//! re-streaming process, recording its arguments into the `procs/<pid>` file
+//! next to this binary and running until its exit code is written into the
+//! `procs/<pid>.exit` file. Like a hung process, it ignores termination
+//! signals, so can be killed only.
+//!
+//! Is built with `e2e` feature only, and never shipped.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+
+use std::{env, fs, path::PathBuf, process, thread, time::Duration};
+
+fn main() {
+    for sig in &[libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: Ignoring a signal doesn't involve any handler to be run.
+        let _ = unsafe { libc::signal(*sig, libc::SIG_IGN) };
+    }
+
+    let args: Vec<_> = env::args().skip(1).collect();
+    match args.get(1).map(String::as_str) {
+        Some("-version") => {
+            println!("ffmpeg version 4.3.1");
+            return;
+        }
+        Some("-protocols") => {
+            println!("Input:\n  file\n  rtmp\nOutput:\n  file\n  rtmp");
+            return;
+        }
+        Some("-encoders") => {
+            println!("Encoders:\n ------");
+            return;
+        }
+        _ => {}
+    }
+
+    let procs = procs_dir();
+    let pid = process::id();
+    let tmp = procs.join(format!("{}.tmp", pid));
+    fs::write(&tmp, args.join("\n") + "\n")
+        .and_then(|_| fs::rename(&tmp, procs.join(pid.to_string())))
+        .expect("Failed to record arguments");
+
+    let exit = procs.join(format!("{}.exit", pid));
+    loop {
+        // The exit code may be read while being written, so is retried.
+        let code = fs::read_to_string(&exit)
+            .ok()
+            .and_then(|c| c.trim().parse().ok());
+        if let Some(code) = code {
+            process::exit(code);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Returns path to the directory to record the spawned processes in.
+fn procs_dir() -> PathBuf {
+    env::current_exe()
+        .expect("Failed to resolve path of the binary")
+        .with_file_name("procs")
+}
@@ -0,0 +1,385 @@
+//! Capacity of this server, reported to external load balancers for weighting
+//! it among other servers (see `/api/v1/health/detail` endpoint).
+//!
+//! Capacity score is a single number from `0` (no capacity left) to `100`
+//! (idle server), calculated as:
+//! ```text
+//! score = 100 * (1 - (Wp * Up + Wl * Ul + Wo * Uo) / (Wp + Wl + Wo))
+//! ```
+//! where `W` are the configured [`Weights`], and `U` are the utilizations
+//! (each clamped to `0..1`) of:
+//! - `Up`: running [FFmpeg] processes relative to [`Config::processes`], being
+//!   omitted (along with its weight) if the latter is not configured;
+//! - `Ul`: 1-minute CPU load average relative to the number of CPUs, being
+//!   omitted (along with its weight) if the load average is unknown;
+//! - `Uo`: enabled [`Output`]s not being online (see
+//!   [`Status::is_online()`]), relative to all the enabled ones.
+//!
+//! Draining server (see [`State::draining`]) always has zero score.
+//!
+//! [`Output`]: crate::state::Output
+//! [`State::draining`]: crate::State::draining
+//! [`Status::is_online()`]: crate::state::Status::is_online
+//! [FFmpeg]: https://ffmpeg.org
+
+use std::{
+    fs,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use futures_signals::signal::Mutable;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::state::{Restream, StatusRegistry, Statuses};
+
+/// Configuration of calculating a capacity score.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Config {
+    /// Number of [FFmpeg] processes this server is sized to run, being its
+    /// full process utilization.
+    ///
+    /// Is not enforced in any way.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub processes: Option<u32>,
+
+    /// [`Weights`] of the utilizations forming the score.
+    pub weights: Weights,
+}
+
+/// Weights of the utilizations forming a capacity score.
+///
+/// Parsed from a comma-separated list of `name=weight` pairs, like
+/// `processes=2,load=1,offline=0.5`, where unspecified weights are left
+/// default (`1`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    /// Weight of the running [FFmpeg] processes utilization.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub processes: f64,
+
+    /// Weight of the CPU load utilization.
+    pub load: f64,
+
+    /// Weight of the offline [`Output`]s utilization.
+    ///
+    /// [`Output`]: crate::state::Output
+    pub offline: f64,
+}
+
+impl Default for Weights {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            processes: 1.0,
+            load: 1.0,
+            offline: 1.0,
+        }
+    }
+}
+
+impl FromStr for Weights {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Self::default();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let (name, val) = (parts.next().unwrap_or_default(), parts.next());
+            let val = val
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| {
+                    anyhow!("Invalid weight '{}', should be `name=<n>`", pair)
+                })?;
+            match name.trim() {
+                "processes" => weights.processes = val,
+                "load" => weights.load = val,
+                "offline" => weights.offline = val,
+                name => return Err(anyhow!("Unknown weight '{}'", name)),
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// Counts of [`Restream`]s' inputs and [`Output`]s.
+///
+/// [`Output`]: crate::state::Output
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Counts {
+    /// Total number of [`Restream`]s' inputs.
+    pub inputs: usize,
+
+    /// Number of [`Restream`]s' inputs ready to serve a live stream.
+    pub online_inputs: usize,
+
+    /// Total number of [`Output`]s.
+    ///
+    /// [`Output`]: crate::state::Output
+    pub outputs: usize,
+
+    /// Number of enabled [`Output`]s.
+    ///
+    /// [`Output`]: crate::state::Output
+    pub enabled_outputs: usize,
+
+    /// Number of enabled [`Output`]s being online (see
+    /// [`Status::is_online()`]).
+    ///
+    /// [`Output`]: crate::state::Output
+    /// [`Status::is_online()`]: crate::state::Status::is_online
+    pub online_outputs: usize,
+}
+
+impl Counts {
+    /// Counts the inputs and [`Output`]s of the given `restreams`, having
+    /// the given `statuses`.
+    ///
+    /// [`Output`]: crate::state::Output
+    #[must_use]
+    pub fn calculate(restreams: &[Restream], statuses: &Statuses) -> Self {
+        let mut counts = Self {
+            inputs: restreams.len(),
+            ..Self::default()
+        };
+        for r in restreams {
+            if r.input.enabled && r.input.is_ready_to_serve(statuses) {
+                counts.online_inputs += 1;
+            }
+            counts.outputs += r.outputs.len();
+            for o in r.outputs.iter().filter(|o| o.enabled) {
+                counts.enabled_outputs += 1;
+                if statuses.get(o.id).is_online() {
+                    counts.online_outputs += 1;
+                }
+            }
+        }
+        counts
+    }
+}
+
+/// Cached [`Counts`] of [`State::restreams`], so reporting them doesn't lock
+/// the [`Restream`]s on every request.
+///
+/// Once calculated, they're cached until [`CountsCache::invalidate()`] is
+/// called.
+///
+/// [`State::restreams`]: crate::State::restreams
+#[derive(Clone, Debug, Default)]
+pub struct CountsCache(Arc<Mutex<Option<Counts>>>);
+
+impl CountsCache {
+    /// Returns the cached [`Counts`], calculating them for the given
+    /// `restreams` and `statuses` if there are none.
+    #[must_use]
+    pub fn get(
+        &self,
+        restreams: &Mutable<Vec<Restream>>,
+        statuses: &StatusRegistry,
+    ) -> Counts {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(|| {
+                Counts::calculate(&restreams.lock_ref(), &statuses.lock_ref())
+            })
+    }
+
+    /// Drops the cached [`Counts`], so the next [`CountsCache::get()`]
+    /// calculates them anew.
+    pub fn invalidate(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Current usage of this server to calculate its capacity [`score()`] of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Usage {
+    /// [`Counts`] of inputs and [`Output`]s.
+    ///
+    /// [`Output`]: crate::state::Output
+    pub counts: Counts,
+
+    /// Number of running [FFmpeg] processes.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub processes: usize,
+
+    /// 1-minute CPU load average, if known.
+    pub load_average: Option<f64>,
+
+    /// Number of CPUs.
+    pub cpus: usize,
+
+    /// Indicator whether this server is draining.
+    pub draining: bool,
+}
+
+/// Calculates capacity score of the given [`Usage`] according to the given
+/// [`Config`].
+///
+/// See [module-level documentation](self) for the formula.
+#[must_use]
+pub fn score(usage: &Usage, config: &Config) -> u8 {
+    /// Divides the given numbers, clamping the result to `0..1`.
+    fn ratio(num: f64, denom: f64) -> f64 {
+        if denom > 0.0 {
+            (num / denom).max(0.0).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    if usage.draining {
+        return 0;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let utilizations = [
+        config.processes.map(|max| {
+            (
+                config.weights.processes,
+                ratio(usage.processes as f64, f64::from(max)),
+            )
+        }),
+        usage
+            .load_average
+            .map(|load| (config.weights.load, ratio(load, usage.cpus as f64))),
+        Some((
+            config.weights.offline,
+            if usage.counts.enabled_outputs == 0 {
+                0.0
+            } else {
+                let offline =
+                    usage.counts.enabled_outputs - usage.counts.online_outputs;
+                ratio(offline as f64, usage.counts.enabled_outputs as f64)
+            },
+        )),
+    ];
+    let (weighted, weights) = utilizations
+        .iter()
+        .flatten()
+        .fold((0.0, 0.0), |(sum, total), (w, u)| (sum + w * u, total + w));
+    if weights <= 0.0 {
+        return 100;
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let score = (100.0 * (1.0 - weighted / weights)).round() as u8;
+    score
+}
+
+/// Returns the current 1-minute CPU load average of the system, if it can be
+/// read.
+///
+/// The read value is cached for a second, as the system itself renews it
+/// much less often.
+#[must_use]
+pub fn load_average() -> Option<f64> {
+    /// Last read load average along with the moment it has been read at.
+    static CACHE: Lazy<Mutex<Option<(Instant, Option<f64>)>>> =
+        Lazy::new(|| Mutex::new(None));
+
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    match *cache {
+        Some((at, load)) if at.elapsed() < Duration::from_secs(1) => load,
+        _ => {
+            let load = fs::read_to_string("/proc/loadavg")
+                .ok()
+                .and_then(|s| s.split_whitespace().next()?.parse().ok());
+            *cache = Some((Instant::now(), load));
+            load
+        }
+    }
+}
+
+#[cfg(test)]
+mod score_spec {
+    use super::{score, Config, Counts, Usage, Weights};
+
+    fn usage() -> Usage {
+        Usage {
+            counts: Counts {
+                inputs: 2,
+                online_inputs: 2,
+                outputs: 4,
+                enabled_outputs: 4,
+                online_outputs: 4,
+            },
+            processes: 4,
+            load_average: Some(1.0),
+            cpus: 4,
+            draining: false,
+        }
+    }
+
+    #[test]
+    fn weights_utilizations() {
+        let config = Config {
+            processes: Some(8),
+            weights: Weights::default(),
+        };
+        // (0.5 + 0.25 + 0) / 3
+        assert_eq!(score(&usage(), &config), 75);
+
+        let config = Config {
+            processes: Some(8),
+            weights: "processes=2,offline=0".parse().unwrap(),
+        };
+        // (2 * 0.5 + 0.25) / 3
+        assert_eq!(score(&usage(), &config), 58);
+    }
+
+    #[test]
+    fn omits_unknown_utilizations() {
+        let mut usage = usage();
+        usage.load_average = None;
+        usage.counts.online_outputs = 3;
+
+        assert_eq!(score(&usage, &Config::default()), 75);
+    }
+
+    #[test]
+    fn clamps_overload() {
+        let mut usage = usage();
+        usage.processes = 100;
+        usage.load_average = Some(16.0);
+        usage.counts.online_outputs = 0;
+        let config = Config {
+            processes: Some(8),
+            weights: Weights::default(),
+        };
+
+        assert_eq!(score(&usage, &config), 0);
+    }
+
+    #[test]
+    fn zeroes_draining() {
+        let mut usage = usage();
+        usage.draining = true;
+
+        assert_eq!(score(&usage, &Config::default()), 0);
+    }
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(
+            "load=0.5, processes=3".parse::<Weights>().unwrap(),
+            Weights {
+                processes: 3.0,
+                load: 0.5,
+                offline: 1.0,
+            },
+        );
+        assert!("load".parse::<Weights>().is_err());
+        assert!("load=-1".parse::<Weights>().is_err());
+        assert!("memory=1".parse::<Weights>().is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Consistency checks of a [`State`] against the running environment.
+
+use std::path::Path;
+
+use ephyr_log::log;
+use juniper::{GraphQLEnum, GraphQLObject};
+use tokio::fs;
+
+use crate::{
+    ffmpeg::{Requirement, RestreamerKind},
+    state::{self, OutputId, RestreamId, State},
+};
+
+/// Result of checking the configuration of this server against its running
+/// environment (files, directories, [FFmpeg] capabilities).
+///
+/// It's advisory only: the configuration is applied regardless of it.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct Finding {
+    /// Severity of this `Finding`.
+    pub severity: Severity,
+
+    /// ID of the `Restream` this `Finding` is related to.
+    ///
+    /// `null` if this `Finding` is related to the whole server.
+    pub restream_id: Option<RestreamId>,
+
+    /// ID of the `Output` this `Finding` is related to.
+    ///
+    /// `null` if this `Finding` is not related to a concrete `Output`.
+    pub output_id: Option<OutputId>,
+
+    /// Human-readable description of this `Finding`.
+    pub message: String,
+}
+
+impl Finding {
+    /// Creates a new [`Finding`] of the given `severity` out of the given
+    /// `message`.
+    #[inline]
+    #[must_use]
+    fn new<M: Into<String>>(severity: Severity, message: M) -> Self {
+        Self {
+            severity,
+            restream_id: None,
+            output_id: None,
+            message: message.into(),
+        }
+    }
+
+    /// Relates this [`Finding`] to the [`state::Restream`] with the given
+    /// `id`.
+    #[inline]
+    #[must_use]
+    fn restream(mut self, id: RestreamId) -> Self {
+        self.restream_id = Some(id);
+        self
+    }
+
+    /// Relates this [`Finding`] to the [`state::Output`] with the given `id`.
+    #[inline]
+    #[must_use]
+    fn output(mut self, id: OutputId) -> Self {
+        self.output_id = Some(id);
+        self
+    }
+}
+
+/// Severity of a `Finding`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq)]
+pub enum Severity {
+    /// Configured re-streaming will definitely fail.
+    Failure,
+
+    /// Configured re-streaming looks suspicious, and may not work as expected.
+    Suspicion,
+}
+
+/// Checks the given [`State`] against the running environment, returning all
+/// the found inconsistencies.
+///
+/// The given `dvr_dir` is the directory where recorded files of
+/// [`state::Output`]s are written to.
+pub async fn run(state: &State, dvr_dir: &Path) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    let caps = state.ffmpeg_capabilities.get_cloned();
+    if caps.is_none() {
+        findings.push(Finding::new(
+            Severity::Suspicion,
+            "FFmpeg capabilities are not detected, so required protocols and \
+             encoders are not checked",
+        ));
+    }
+    let missing = |reqs: Vec<Requirement>| -> Vec<Requirement> {
+        caps.as_ref().map_or_else(Vec::new, |c| {
+            reqs.into_iter().filter(|r| !c.satisfies(r)).collect()
+        })
+    };
+
+    let restreams = state.restreams.get_cloned();
+
+    let records = restreams
+        .iter()
+        .any(|r| r.outputs.iter().any(|o| o.dst.scheme() == "file"));
+    if records {
+        let writable = fs::metadata(dvr_dir)
+            .await
+            .map_or(false, |m| m.is_dir() && !m.permissions().readonly());
+        if !writable {
+            findings.push(Finding::new(
+                Severity::Failure,
+                format!(
+                    "Recording directory {} doesn't exist or is not writable",
+                    dvr_dir.display(),
+                ),
+            ));
+        }
+    }
+
+    for r in &restreams {
+        for f in check_input(&r.input, &restreams, &missing).await {
+            findings.push(f.restream(r.id));
+        }
+
+        for o in &r.outputs {
+            for f in check_output(o, r, &missing).await {
+                findings.push(f.restream(r.id).output(o.id));
+            }
+        }
+    }
+
+    findings
+}
+
+/// Logs the given [`Finding`]s.
+pub fn report(findings: &[Finding]) {
+    for f in findings {
+        let subject = match (f.restream_id, f.output_id) {
+            (Some(r), Some(o)) => format!(" of output {} in restream {}", o, r),
+            (Some(r), None) => format!(" of restream {}", r),
+            _ => String::new(),
+        };
+        match f.severity {
+            Severity::Failure => {
+                log::error!("Configuration failure{}: {}", subject, f.message)
+            }
+            Severity::Suspicion => {
+                log::warn!("Configuration suspicion{}: {}", subject, f.message)
+            }
+        }
+    }
+}
+
+/// Checks the given [`state::Input`] (along with its failover ones), using the
+/// given `missing` function to detect [`Requirement`]s not satisfied by the
+/// [FFmpeg] binary.
+///
+/// [FFmpeg]: https://ffmpeg.org
+async fn check_input<F>(
+    input: &state::Input,
+    restreams: &[state::Restream],
+    missing: &F,
+) -> Vec<Finding>
+where
+    F: Fn(Vec<Requirement>) -> Vec<Requirement>,
+{
+    let mut findings = vec![];
+    let mut inputs = vec![input];
+    while let Some(input) = inputs.pop() {
+        match input.src.as_ref() {
+            Some(state::InputSrc::Remote(remote)) => {
+                if let Some(id) = remote.url.restream_id() {
+                    if !restreams.iter().any(|r| r.id == id) {
+                        findings.push(Finding::new(
+                            Severity::Failure,
+                            format!(
+                                "Input `{}` pulls from non-existent \
+                                 restream {}",
+                                input.key, id,
+                            ),
+                        ));
+                    }
+                } else {
+                    let reqs = Requirement::protocol_of(&remote.url)
+                        .into_iter()
+                        .collect();
+                    for req in missing(reqs) {
+                        findings.push(Finding::new(
+                            Severity::Failure,
+                            format!(
+                                "FFmpeg lacks {} required by input `{}`",
+                                req, input.key,
+                            ),
+                        ));
+                    }
+                }
+            }
+            Some(state::InputSrc::Failover(s)) => inputs.extend(&s.inputs),
+            Some(state::InputSrc::File(file)) => {
+                let exists = fs::metadata(&file.path)
+                    .await
+                    .map_or(false, |m| m.is_file());
+                if !exists {
+                    findings.push(Finding::new(
+                        Severity::Failure,
+                        format!(
+                            "Media file {} of input `{}` doesn't exist",
+                            file.path.display(),
+                            input.key,
+                        ),
+                    ));
+                }
+            }
+            None => {}
+        }
+    }
+    findings
+}
+
+/// Checks the given [`state::Output`] of the given [`state::Restream`], using
+/// the given `missing` function to detect [`Requirement`]s not satisfied by
+/// the [FFmpeg] binary.
+///
+/// [FFmpeg]: https://ffmpeg.org
+async fn check_output<F>(
+    output: &state::Output,
+    restream: &state::Restream,
+    missing: &F,
+) -> Vec<Finding>
+where
+    F: Fn(Vec<Requirement>) -> Vec<Requirement>,
+{
+    let mut findings = vec![];
+
+    if let Some(overlay) = &output.overlay {
+        let exists = fs::metadata(&overlay.path)
+            .await
+            .map_or(false, |m| m.is_file());
+        if !exists {
+            findings.push(Finding::new(
+                Severity::Failure,
+                format!(
+                    "Overlay image {} doesn't exist",
+                    overlay.path.display(),
+                ),
+            ));
+        }
+        if output.dst.scheme() == "icecast" {
+            findings.push(Finding::new(
+                Severity::Suspicion,
+                "Overlay is ignored for audio-only Icecast destination",
+            ));
+        }
+    }
+
+    if output.max_bitrate_kbps.is_some() && !output.mixins.is_empty() {
+        findings.push(Finding::new(
+            Severity::Suspicion,
+            "Bitrate cap is ignored for output with mixins",
+        ));
+    }
+
+    if output.enabled && !restream.input.enabled {
+        findings.push(Finding::new(
+            Severity::Suspicion,
+            "Output is enabled, but its restream's input is disabled",
+        ));
+    }
+
+    let src = restream.output_src_input(output);
+    match (src, &output.src_stream) {
+        (None, Some(key)) => findings.push(Finding::new(
+            Severity::Failure,
+            format!("Output re-streams unknown `{}` stream", key),
+        )),
+        (Some(i), _)
+            if i.id != restream.input.id && output.delay_secs.is_some() =>
+        {
+            findings.push(Finding::new(
+                Severity::Failure,
+                "Output is delayed, but re-streams not the main stream, so \
+                 never goes live",
+            ));
+        }
+        _ => {}
+    }
+
+    let reqs = src
+        .and_then(|i| restream.input_rtmp_endpoint_url(i).ok())
+        .and_then(|from_url| {
+            let output = state::Output {
+                enabled: true,
+                ..output.clone()
+            };
+            RestreamerKind::from_output(&output, &from_url, None, None)
+        })
+        .map_or_else(Vec::new, |kind| kind.requirements());
+    for req in missing(reqs) {
+        findings.push(Finding::new(
+            Severity::Failure,
+            format!("FFmpeg lacks {} required by output", req),
+        ));
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod run_spec {
+    use std::sync::Arc;
+
+    use actix_web::rt::System;
+    use serde_json::json;
+
+    use crate::{ffmpeg::Capabilities, spec, state::State};
+
+    use super::{run, Severity};
+
+    fn state(restream: serde_json::Value) -> State {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(restream).unwrap(),
+            )
+            .unwrap();
+        state
+    }
+
+    #[test]
+    fn finds_missing_files() {
+        System::new("test").block_on(async {
+            let state = state(json!({
+                "key": "guest",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "src": {"file": {"path": "/nonexistent/video.mp4"}},
+                    "enabled": true,
+                },
+                "outputs": [{
+                    "dst": "rtmp://example.com/live/key",
+                    "overlay": {"path": "/nonexistent/bug.png"},
+                }],
+            }));
+
+            let findings = run(&state, "/nonexistent/dvr".as_ref()).await;
+
+            let failures: Vec<_> = findings
+                .iter()
+                .filter(|f| f.severity == Severity::Failure)
+                .collect();
+            assert_eq!(failures.len(), 2);
+            assert!(failures[0].message.contains("/nonexistent/video.mp4"));
+            assert!(failures[0].output_id.is_none());
+            assert!(failures[1].message.contains("/nonexistent/bug.png"));
+            assert!(failures[1].output_id.is_some());
+        });
+    }
+
+    #[test]
+    fn finds_missing_ffmpeg_capabilities() {
+        System::new("test").block_on(async {
+            let state = state(json!({
+                "key": "guest",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                },
+                "outputs": [{"dst": "srt://example.com:9000"}],
+            }));
+
+            let findings = run(&state, "/nonexistent/dvr".as_ref()).await;
+            assert_eq!(findings.len(), 1);
+            assert_eq!(findings[0].severity, Severity::Suspicion);
+
+            state
+                .ffmpeg_capabilities
+                .set(Some(Arc::new(Capabilities::default())));
+            let findings = run(&state, "/nonexistent/dvr".as_ref()).await;
+            assert_eq!(findings.len(), 2);
+            assert!(findings.iter().all(|f| f.severity == Severity::Failure));
+            assert!(findings.iter().any(|f| f.message.contains("`srt`")));
+        });
+    }
+}
@@ -1,104 +1,1111 @@
 //! CLI (command line interface).
 
-use std::{fmt, net::IpAddr, path::PathBuf, str::FromStr as _};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr as _,
+    time::Duration,
+};
 
 use anyhow::anyhow;
 use ephyr_log::slog;
 use structopt::StructOpt;
+use url::Url;
+
+use crate::{
+    api::graphql::complexity, capacity, cycle, mail::SmtpTls, paths,
+    proxy::IpRange, srs,
+};
 
 /// CLI (command line interface) of the re-streamer server.
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(about = "RTMP re-streamer server")]
+pub struct Cli {
+    /// [`Command`] to be executed.
+    ///
+    /// If [`None`], then the server is run with [`Cli::opts`], as if
+    /// [`Command::Run`] is specified.
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+
+    /// [`Opts`] to run the server with, if no [`Command`] is specified.
+    #[structopt(flatten)]
+    pub opts: Opts,
+}
+
+impl Cli {
+    /// Parses [`Cli`] from command line arguments.
+    ///
+    /// Prints the error message and quits the program in case of failure.
+    #[inline]
+    #[must_use]
+    pub fn from_args() -> Self {
+        <Self as StructOpt>::from_args()
+    }
+}
+
+/// Command of the re-streamer server's [`Cli`].
+#[allow(clippy::large_enum_variant)] // parsed only once on startup
+#[derive(Clone, Debug, StructOpt)]
+pub enum Command {
+    /// Runs the server (default).
+    #[structopt(about = "Runs the server (default)")]
+    Run(Opts),
+
+    /// Hashes a password, so it can be used as [`Settings::password_hash`],
+    /// or checks a password against an existing hash.
+    ///
+    /// [`Settings::password_hash`]: crate::state::Settings::password_hash
+    #[structopt(
+        about = "Hashes a password for pre-seeding the state file",
+        long_about = "Prints the encoded argon2 hash of a password, suitable \
+                      for the `password_hash` of the state file, or checks \
+                      the password against an existing hash with `--check`"
+    )]
+    HashPassword(HashPasswordOpts),
+
+    /// Validates a [`State`] file without running the server.
+    ///
+    /// [`State`]: crate::State
+    #[structopt(
+        about = "Validates a state file without running the server",
+        long_about = "Validates a state file in the same way the server does \
+                      on startup and on importing a spec, printing all the \
+                      found errors. Exits with non-zero code if there are any."
+    )]
+    ValidateState(ValidateStateOpts),
+
+    /// Converts an existing [SRS] configuration file (along with the shell
+    /// scripts running [FFmpeg]) into a [`Spec`].
+    ///
+    /// [`Spec`]: crate::Spec
+    /// [FFmpeg]: https://ffmpeg.org
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        about = "Converts an existing SRS setup into a spec",
+        long_about = "Converts vhost apps of an existing SRS configuration \
+                      file into inputs, and destinations of FFmpeg commands \
+                      found in the `--scripts-dir` shell scripts into \
+                      (disabled) outputs, printing the generated spec to \
+                      STDOUT. The spec can be reviewed and then applied via \
+                      `--initial-spec` or imported. Fragments which cannot be \
+                      converted are reported to STDERR with their line \
+                      numbers."
+    )]
+    ImportSrsConf(ImportSrsConfOpts),
+
+    /// Resets the lost [`Settings::password_hash`] in a [`State`] file, while
+    /// the server is stopped.
+    ///
+    /// [`Settings::password_hash`]: crate::state::Settings::password_hash
+    /// [`State`]: crate::State
+    #[structopt(
+        about = "Resets the lost password in a state file",
+        long_about = "Clears the admin (and viewer) password in the state \
+                      file, or replaces it with the one read from STDIN \
+                      with `--set`. Refuses to run while the server uses \
+                      the state file. Must be run with the same state \
+                      options the server is run with."
+    )]
+    ResetPassword(ResetPasswordOpts),
+}
+
+/// Options of [`Command::HashPassword`].
+#[derive(Clone, Debug, StructOpt)]
+pub struct HashPasswordOpts {
+    /// Password to be hashed or checked.
+    ///
+    /// If [`None`], then it's read from STDIN.
+    #[structopt(
+        help = "Password to hash",
+        long_help = "Password to hash or check (read from STDIN if omitted)"
+    )]
+    pub password: Option<String>,
+
+    /// Encoded [`argon2`] hash to check the password against, instead of
+    /// hashing it.
+    #[structopt(
+        long,
+        value_name = "hash",
+        help = "Checks the password against the hash",
+        long_help = "Checks whether the password matches the given encoded \
+                     argon2 hash, instead of hashing it"
+    )]
+    pub check: Option<String>,
+}
+
+/// Options of [`Command::ValidateState`].
+#[derive(Clone, Debug, StructOpt)]
+pub struct ValidateStateOpts {
+    /// Path to the [`State`] file to be validated.
+    ///
+    /// [`State`]: crate::State
+    #[structopt(help = "Path to the state file to validate")]
+    pub path: PathBuf,
+}
+
+/// Options of [`Command::ImportSrsConf`].
+#[derive(Clone, Debug, StructOpt)]
+pub struct ImportSrsConfOpts {
+    /// Path to the [SRS] configuration file to be converted.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(help = "Path to the SRS configuration file to convert")]
+    pub path: PathBuf,
+
+    /// Path to the directory with shell scripts running [FFmpeg] to push
+    /// live streams onto destinations.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[structopt(
+        long,
+        value_name = "dir",
+        help = "Directory with FFmpeg shell scripts to convert",
+        long_help = "Directory with shell scripts running FFmpeg to push \
+                     live streams from SRS onto destinations, which are \
+                     converted into outputs"
+    )]
+    pub scripts_dir: Option<PathBuf>,
+}
+
+/// Options of [`Command::ResetPassword`].
+#[derive(Clone, Debug, StructOpt)]
+pub struct ResetPasswordOpts {
+    /// Path to the [`State`] file to reset the password in.
+    ///
+    /// [`State`]: crate::State
+    #[structopt(
+        short,
+        long,
+        env = "EPHYR_RESTREAMER_STATE_PATH",
+        default_value = "state.json",
+        help = "Path to the state file",
+        long_help = "Path to the state file the server persists its state in"
+    )]
+    pub state_path: PathBuf,
+
+    /// Indicator whether the [`State`] is persisted as separate files (see
+    /// [`Opts::split_state`]).
+    ///
+    /// [`State`]: crate::State
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SPLIT_STATE",
+        help = "State is persisted as a directory of per-restream files"
+    )]
+    pub split_state: bool,
+
+    /// Path to a file with a secret the [`State`] file is encrypted with (see
+    /// [`Opts::state_encryption_key_file`]).
+    ///
+    /// [`State`]: crate::State
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_STATE_ENCRYPTION_KEY_FILE",
+        help = "Path to a file with a key the state file is encrypted with"
+    )]
+    pub state_encryption_key_file: Option<PathBuf>,
+
+    /// Indicator whether the password should be replaced with the one read
+    /// from STDIN, rather than cleared.
+    #[structopt(
+        long,
+        help = "Replaces the password with the one read from STDIN",
+        long_help = "Replaces the admin password with the one read from \
+                     STDIN, rather than clearing it"
+    )]
+    pub set: bool,
+}
+
+/// Options of running the re-streamer server.
+#[derive(Clone, Debug, StructOpt)]
 pub struct Opts {
     /// Debug mode of the server.
-    #[structopt(short, long, help = "Enables debug mode")]
+    ///
+    /// Only initializes [`State::playground_enabled`], which may be switched
+    /// at runtime afterwards.
+    ///
+    /// [`State::playground_enabled`]: crate::State::playground_enabled
+    #[structopt(
+        short,
+        long,
+        help = "Enables debug mode",
+        long_help = "Enables debug mode, serving GraphQL Playground \
+                     initially (may be switched at runtime via \
+                     `setPlaygroundEnabled` mutation)"
+    )]
     pub debug: bool,
 
-    /// IP address for the server to listen client HTTP requests on.
+    /// Indicator whether the embedded web UI should not be served.
+    ///
+    /// Has no effect if the server is built without `ui` feature, as there is
+    /// no web UI to serve.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NO_UI",
+        help = "Disables serving web UI",
+        long_help = "Disables serving the embedded web UI, so only APIs are \
+                     served (headless mode)"
+    )]
+    pub no_ui: bool,
+
+    /// Indicator whether responses of client HTTP server should not be
+    /// compressed.
+    ///
+    /// Useful for debugging, as uncompressed responses are readable as is.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NO_COMPRESSION",
+        help = "Disables compression of HTTP responses",
+        long_help = "Disables gzip/brotli compression of client HTTP server \
+                     responses, so they're readable as is (useful for \
+                     debugging)"
+    )]
+    pub no_compression: bool,
+
+    /// Indicator whether [GraphQL] schema of the client API should be served
+    /// on `/api/schema.graphql` endpoint without authorization.
+    ///
+    /// [GraphQL]: https://graphql.com
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_PUBLIC_SCHEMA",
+        help = "Serves GraphQL schema without authorization",
+        long_help = "Serves GraphQL schema of the client API on \
+                     `/api/schema.graphql` endpoint without authorization"
+    )]
+    pub public_schema: bool,
+
+    /// Indicator whether a read-only status page of `Restream`s marked as
+    /// public should be served on `/status` endpoint without authorization.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_PUBLIC_STATUS_PAGE",
+        help = "Serves public status page without authorization",
+        long_help = "Serves a read-only status page on `/status` endpoint \
+                     without authorization, listing only labels and \
+                     online statuses of restreams marked as public"
+    )]
+    pub public_status_page: bool,
+
+    /// Secret to derive the key signing browser session cookies from.
+    ///
+    /// If [`None`], then a random key is generated on startup, so sessions
+    /// don't survive restarts.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SESSION_KEY",
+        hide_env_values = true,
+        help = "Secret signing browser session cookies",
+        long_help = "Secret to derive the key signing browser session cookies \
+                     from (random on every startup by default)"
+    )]
+    pub session_key: Option<String>,
+
+    /// Lifetime of browser sessions established via `/api/login` endpoint.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SESSION_LIFETIME",
+        default_value = "7days",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Lifetime of browser sessions",
+        long_help = "Lifetime of browser sessions established via \
+                     `/api/login` endpoint"
+    )]
+    pub session_lifetime: Duration,
+
+    /// Indicator whether the admin password may be cleared without
+    /// authorization by a request from a loopback connection (see
+    /// [`recovery::LOCAL_RESET_HEADER`]), in case it's lost.
+    ///
+    /// __Never enable it behind a reverse proxy running on the same host and
+    /// not setting any forwarding header__ (like `proxy_pass` of [nginx] with
+    /// default configuration): remote requests proxied by it come from a
+    /// loopback connection too, so anyone knowing the confirmation header may
+    /// clear the password.
+    ///
+    /// [`recovery::LOCAL_RESET_HEADER`]: crate::recovery::LOCAL_RESET_HEADER
+    /// [nginx]: https://nginx.org
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_ALLOW_LOCAL_RESET",
+        help = "Allows clearing lost password from loopback",
+        long_help = "Allows clearing the admin password without \
+                     authorization via `POST /api/local-reset` request from \
+                     a loopback connection with \
+                     `X-Ephyr-Local-Reset: clear-password` header, in case \
+                     it's lost. Requests carrying `Forwarded`, \
+                     `X-Forwarded-For` or `X-Real-IP` header are always \
+                     rejected. WARNING: never enable it behind a reverse \
+                     proxy on the same host not setting any of these headers \
+                     (like default nginx `proxy_pass`), as remote requests \
+                     proxied by it look local, so anyone knowing the header \
+                     may clear the password."
+    )]
+    pub allow_local_reset: bool,
+
+    /// Interval of keep-alive messages sent to [GraphQL over WebSocket][1]
+    /// connections of the client API.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_WS_KEEP_ALIVE",
+        default_value = "5s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Keep-alive interval of WebSocket connections",
+        long_help = "Interval of keep-alive messages sent to GraphQL over \
+                     WebSocket connections of the client API"
+    )]
+    pub ws_keep_alive: Duration,
+
+    /// Maximum number of simultaneous [GraphQL over WebSocket][1] connections
+    /// of the client API.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_WS_MAX_CONNECTIONS",
+        default_value = "100",
+        help = "Max number of WebSocket connections",
+        long_help = "Maximum number of simultaneous GraphQL over WebSocket \
+                     connections of the client API. Connections beyond it are \
+                     rejected with `503 Service Unavailable`."
+    )]
+    pub ws_max_connections: usize,
+
+    /// Maximum number of simultaneous operations (like subscriptions) of a
+    /// single [GraphQL over WebSocket][1] connection of the client API.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_WS_MAX_OPERATIONS",
+        default_value = "32",
+        help = "Max number of operations per WebSocket connection",
+        long_help = "Maximum number of simultaneous operations (like \
+                     subscriptions) of a single GraphQL over WebSocket \
+                     connection of the client API. Operations beyond it are \
+                     rejected with an error."
+    )]
+    pub ws_max_operations: usize,
+
+    /// Maximum nesting depth of fields in a single GraphQL operation of the
+    /// client API.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_GRAPHQL_MAX_DEPTH",
+        default_value = "15",
+        help = "Max depth of GraphQL operations",
+        long_help = "Maximum nesting depth of fields in a single GraphQL \
+                     operation of the client API. Top-level fields being \
+                     deeper are rejected without being resolved."
+    )]
+    pub graphql_max_depth: usize,
+
+    /// Maximum complexity (total number of selected fields, with fragments
+    /// expanded) of a single top-level field of a GraphQL operation of the
+    /// client API.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_GRAPHQL_MAX_COMPLEXITY",
+        default_value = "500",
+        help = "Max complexity of GraphQL operations",
+        long_help = "Maximum complexity (total number of selected fields, \
+                     with fragments expanded) of a single top-level field of \
+                     a GraphQL operation of the client API. More complex \
+                     top-level fields are rejected without being resolved."
+    )]
+    pub graphql_max_complexity: usize,
+
+    /// Base path (URL prefix) to serve all the client HTTP endpoints under.
+    ///
+    /// Empty means serving them on the root.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_BASE_PATH",
+        default_value = "",
+        parse(try_from_str = Self::parse_base_path),
+        help = "URL prefix to serve client HTTP under",
+        long_help = "Base path (URL prefix) to serve all the client HTTP \
+                     endpoints under, like `/restreamer`. Empty means serving \
+                     them on the root."
+    )]
+    pub base_path: String,
+
+    /// IP address for the server to listen client HTTP requests on.
+    ///
+    /// May be an IPv6 one, including the `::` any-address.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CLIENT_HTTP_IP",
+        default_value = "0.0.0.0",
+        help = "IP to listen client HTTP on",
+        long_help = "IP address (IPv4 or IPv6) for the server to listen client \
+                     HTTP requests on"
+    )]
+    pub client_http_ip: IpAddr,
+
+    /// Port for the server to listen client HTTP requests on.
+    ///
+    /// Zero port makes the server to pick an ephemeral one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CLIENT_HTTP_PORT",
+        default_value = "80",
+        help = "Port to listen client HTTP on",
+        long_help = "Port for the server to listen client HTTP requests on \
+                     (0 picks an ephemeral one)"
+    )]
+    pub client_http_port: u16,
+
+    /// IP address for the server to listen RTMP callback HTTP requests on.
+    ///
+    /// May be an IPv6 one, including the `::` any-address.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CALLBACK_HTTP_IP",
+        default_value = "127.0.0.1",
+        help = "IP to listen callback HTTP on",
+        long_help = "IP address (IPv4 or IPv6) for the server to listen RTMP \
+                     callback HTTP requests on"
+    )]
+    pub callback_http_ip: IpAddr,
+
+    /// Indicator whether HTTP servers bound to IPv6 addresses should accept
+    /// IPv4 connections too (via IPv4-mapped IPv6 addresses).
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_DUAL_STACK",
+        help = "Binds IPv6 HTTP servers dual-stack",
+        long_help = "Makes HTTP servers bound to IPv6 addresses (like `::`) \
+                     accept IPv4 connections too"
+    )]
+    pub dual_stack: bool,
+
+    /// Port for the server to listen RTMP callback HTTP requests on.
+    ///
+    /// Zero port makes the server to pick an ephemeral one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CALLBACK_HTTP_PORT",
+        default_value = "8081",
+        help = "Port to listen callback HTTP on",
+        long_help = "Port for the server to listen RTMP callback HTTP requests \
+                     on (0 picks an ephemeral one)"
+    )]
+    pub callback_http_port: u16,
+
+    /// Indicator whether [SRS] HTTP callbacks should be served by the client
+    /// HTTP server (under a secret path), rather than by a separate callback
+    /// HTTP server, so the server listens on a single HTTP port.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SINGLE_PORT",
+        help = "Serves SRS callbacks on client HTTP port",
+        long_help = "Serves SRS HTTP callbacks on the client HTTP server \
+                     (under a secret path), rather than on a separate \
+                     callback HTTP server, so only a single HTTP port is used"
+    )]
+    pub single_port: bool,
+
+    /// Path of a Unix socket for the server to listen RTMP callback HTTP
+    /// requests on, instead of the [`Opts::callback_http_ip`] and the
+    /// [`Opts::callback_http_port`], so other local users cannot reach them.
+    ///
+    /// The socket is accessible by its owner only. The bundled [SRS] sends
+    /// HTTP callbacks over TCP only, so the server refuses to start with this
+    /// option (see [`srs::UNIX_SOCKET_CALLBACKS`]).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CALLBACK_HTTP_SOCKET",
+        conflicts_with = "single-port",
+        help = "Unix socket to listen callback HTTP on",
+        long_help = "Path of a Unix socket (accessible by its owner only) for \
+                     the server to listen RTMP callback HTTP requests on, \
+                     instead of TCP. Refused on startup, if SRS cannot send \
+                     HTTP callbacks to it (the bundled SRS 3 cannot)"
+    )]
+    pub callback_http_socket: Option<PathBuf>,
+
+    /// [`IpRange`]s of reverse proxies, which are trusted to report a real
+    /// client IP address via `Forwarded` or `X-Forwarded-For` headers of
+    /// requests to the client HTTP server.
+    ///
+    /// These headers are ignored for requests coming from any other peers.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_TRUSTED_PROXIES",
+        use_delimiter = true,
+        help = "Trusted reverse proxies (CIDR, comma-separated)",
+        long_help = "IP ranges (in CIDR notation, comma-separated) of reverse \
+                     proxies trusted to report a real client IP address via \
+                     `Forwarded` or `X-Forwarded-For` headers"
+    )]
+    pub trusted_proxies: Vec<IpRange>,
+
+    /// Path to a file to persist the server's state in.
+    #[structopt(
+        short,
+        long,
+        env = "EPHYR_RESTREAMER_STATE_PATH",
+        default_value = "state.json",
+        help = "Path to a file to persist state in",
+        long_help = "Path to a file to persist the server's state in"
+    )]
+    pub state_path: PathBuf,
+
+    /// Indicator whether the server's state should be kept in memory only,
+    /// without being persisted into the [`Opts::state_path`] file, so is lost
+    /// on restart.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NO_PERSIST",
+        help = "Keeps state in memory only",
+        long_help = "Keeps the server's state in memory only, without reading \
+                     or writing the state file, so it's lost on restart"
+    )]
+    pub no_persist: bool,
+
+    /// Indicator whether the server's state should be persisted as separate
+    /// files of each [`Restream`] in the `state.d` directory next to the
+    /// [`Opts::state_path`] file, so only the changed ones are rewritten.
+    ///
+    /// The [`Opts::state_path`] file is migrated into the directory on the
+    /// first saving.
+    ///
+    /// [`Restream`]: crate::state::Restream
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SPLIT_STATE",
+        help = "Persists state as a directory of per-restream files",
+        long_help = "Persists the server's state as separate files of each \
+                     restream in the `state.d` directory next to the state \
+                     file, so only the changed ones are rewritten. The state \
+                     file is migrated into the directory on the first saving."
+    )]
+    pub split_state: bool,
+
+    /// Path to a file with a secret to encrypt the [`Opts::state_path`] file
+    /// with.
+    ///
+    /// The state file is not encrypted, if not specified.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_STATE_ENCRYPTION_KEY_FILE",
+        help = "Path to a file with a key to encrypt state file with",
+        long_help = "Path to a file with a random secret (like the output of \
+                     `openssl rand -hex 32`) to encrypt the state file with. \
+                     Not yet encrypted state file is encrypted on the next \
+                     saving."
+    )]
+    pub state_encryption_key_file: Option<PathBuf>,
+
+    /// Base URL of a primary server to replicate the state from, making this
+    /// server a standby one.
+    ///
+    /// This server re-streams nothing and rejects local edits until promoted
+    /// via `promote` GraphQL mutation.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_REPLICATE_FROM",
+        help = "Base URL of a primary server to replicate state from",
+        long_help = "Base URL of a primary server (like \
+                     `http://primary:80/`) to replicate the state from, \
+                     making this server a standby one: its outputs are kept \
+                     disabled and local edits are rejected until it's \
+                     promoted via `promote` GraphQL mutation"
+    )]
+    pub replicate_from: Option<Url>,
+
+    /// Password to authorize on the [`Opts::replicate_from`] primary server
+    /// with.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_REPLICATE_TOKEN",
+        hide_env_values = true,
+        help = "Password to authorize on the primary server with",
+        long_help = "Password (admin or read-only viewer one) to authorize on \
+                     the primary server being replicated from"
+    )]
+    pub replicate_token: Option<String>,
+
+    /// Path to a [`Spec`] file to provision the server's state with on the
+    /// first boot, when the [`Opts::state_path`] file is empty or absent.
+    ///
+    /// Is re-read and applied to the server's state on `SIGHUP`.
+    ///
+    /// [`Spec`]: crate::Spec
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_INITIAL_SPEC",
+        help = "Path to a spec to provision state with on first boot",
+        long_help = "Path to a JSON spec (of the same format as `import` \
+                     mutation accepts) to provision the server's state with, \
+                     when the state file is empty or absent. Is reloaded on \
+                     SIGHUP."
+    )]
+    pub initial_spec: Option<PathBuf>,
+
+    /// Indicator whether the [`Opts::initial_spec`] should be merged into the
+    /// non-empty [`Opts::state_path`] file, rather than being ignored.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_INITIAL_SPEC_MERGE",
+        help = "Merges initial spec into non-empty state",
+        long_help = "Merges the initial spec into the state, even when the \
+                     state file is not empty, rather than ignoring it"
+    )]
+    pub initial_spec_merge: bool,
+
+    /// Indicator whether the [`Opts::initial_spec`] reloaded on `SIGHUP` is
+    /// authoritative, so `Restream`s not described by it are removed.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SPEC_AUTHORITATIVE",
+        help = "Removes restreams not in spec on reload",
+        long_help = "Makes the initial spec reloaded on SIGHUP authoritative, \
+                     so restreams not described by it are removed, rather \
+                     than left untouched"
+    )]
+    pub spec_authoritative: bool,
+
+    /// Path to [SRS] installation directory.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_PATH",
+        default_value = "/usr/local/srs",
+        help = "Path to SRS dir",
+        long_help = "Path to SRS installation directory"
+    )]
+    pub srs_path: PathBuf,
+
+    /// Path to the directory where [SRS] serves public files from via HTTP
+    /// (HLS chunks, etc).
+    ///
+    /// Relative path will use [`Opts::srs_path`] as its base path, not the
+    /// current working directory.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_HTTP_DIR",
+        default_value = "/var/www/srs",
+        help = "Path to SRS public HTTP files",
+        long_help = "Path to the directory where SRS serves public files from \
+                     via HTTP (HLS chunks, etc).\
+                     \n\n\
+                     Relative path will use --srs-path as its base path, not \
+                     the current working directory."
+    )]
+    pub srs_http_dir: PathBuf,
+
+    /// Indicator whether the spawned [SRS] server should not serve live
+    /// streams of `Input`s via [HTTP-FLV] for previewing.
+    ///
+    /// [HTTP-FLV]: https://github.com/ossrs/srs/wiki/v3_EN_DeliveryHttpStream
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NO_HTTP_FLV",
+        help = "Disables HTTP-FLV previews of inputs",
+        long_help = "Disables serving live streams of inputs via HTTP-FLV by \
+                     SRS, which is used for previewing them in a browser"
+    )]
+    pub no_http_flv: bool,
+
+    /// [`srs::LatencyProfile`] of the spawned [SRS] server, providing defaults
+    /// for its [`srs::Tuning`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_LATENCY_PROFILE",
+        default_value = "default",
+        help = "SRS latency profile: default | low",
+        long_help = "Profile of SRS tunables affecting latency of live \
+                     streams: `default` favors smooth playback, while `low` \
+                     minimizes delay. Separate --srs-* tunables override the \
+                     profile's ones."
+    )]
+    pub latency_profile: srs::LatencyProfile,
+
+    /// [`srs::Tuning::chunk_size`] overriding the [`Opts::latency_profile`]
+    /// one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_CHUNK_SIZE",
+        help = "SRS RTMP chunk size (in bytes)",
+        long_help = "Size of RTMP chunks (in bytes) used by SRS, in 128..65536 \
+                     range (the latency profile's one by default)"
+    )]
+    pub srs_chunk_size: Option<u32>,
+
+    /// [`srs::Tuning::queue_length`] overriding the [`Opts::latency_profile`]
+    /// one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_QUEUE_LENGTH",
+        help = "SRS player queue length (in seconds)",
+        long_help = "Maximum length of the queue of a single SRS player (in \
+                     seconds), in 1..300 range (the latency profile's one by \
+                     default)"
+    )]
+    pub srs_queue_length: Option<u32>,
+
+    /// [`srs::Tuning::mr`] overriding the [`Opts::latency_profile`] one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_MR",
+        parse(try_from_str = Self::parse_toggle),
+        help = "SRS merged-read: on | off",
+        long_help = "Enables or disables merged-read of SRS publishers, \
+                     saving CPU at the cost of latency (the latency profile's \
+                     one by default)"
+    )]
+    pub srs_mr: Option<bool>,
+
+    /// [`srs::Tuning::min_latency`] overriding the [`Opts::latency_profile`]
+    /// one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_MIN_LATENCY",
+        parse(try_from_str = Self::parse_toggle),
+        help = "SRS min latency mode: on | off",
+        long_help = "Enables or disables SRS preferring lower latency over \
+                     throughput (the latency profile's one by default)"
+    )]
+    pub srs_min_latency: Option<bool>,
+
+    /// [`srs::Tuning::gop_cache`] overriding the [`Opts::latency_profile`]
+    /// one.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_GOP_CACHE",
+        parse(try_from_str = Self::parse_toggle),
+        help = "SRS GOP cache: on | off",
+        long_help = "Enables or disables caching the last GOP by SRS, so \
+                     players start right away at the cost of latency (the \
+                     latency profile's one by default)"
+    )]
+    pub srs_gop_cache: Option<bool>,
+
+    /// Interval of taking thumbnails of live streams received by `Input`s.
+    ///
+    /// [`None`] disables taking thumbnails.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_THUMBNAILS_INTERVAL",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Interval of taking inputs' thumbnails",
+        long_help = "Interval of taking JPEG thumbnails of live streams \
+                     received by inputs, served on `/preview/{input_id}.jpg` \
+                     endpoint. Thumbnails are disabled if not specified."
+    )]
+    pub thumbnails_interval: Option<Duration>,
+
+    /// Interval of sampling metrics of `Output`s into their history (and
+    /// resources usage of their re-streaming processes).
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_HISTORY_INTERVAL",
+        default_value = "5s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Interval of sampling outputs' metrics",
+        long_help = "Interval of sampling bitrate, frame rate and status of \
+                     outputs into their history, which keeps the most recent \
+                     180 samples, along with CPU and memory usage of their \
+                     re-streaming processes"
+    )]
+    pub history_interval: Duration,
+
+    /// Period to keep removed `Restream`s in trash for, before purging them
+    /// completely.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_TRASH_RETENTION",
+        default_value = "7days",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Period to keep removed restreams in trash for",
+        long_help = "Period to keep removed restreams in trash for (so they \
+                     can be restored), before purging them completely along \
+                     with their recorded files"
+    )]
+    pub trash_retention: Duration,
+
+    /// Interval of persisting the accumulated online time of `Input`s and
+    /// `Output`s (see `Restream.inputOnlineSecs`).
+    ///
+    /// Bounds the online time lost on a server crash.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_USAGE_FLUSH_INTERVAL",
+        default_value = "1m",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Interval of persisting usage counters",
+        long_help = "Interval of persisting the accumulated online time of \
+                     inputs and outputs, used for billing. The online time \
+                     since the last persisting is lost on a server crash."
+    )]
+    pub usage_flush_interval: Duration,
+
+    /// Interval of sweeping leftovers of removed `Restream`s (SRS clients
+    /// still connected to their apps, and files written for them).
+    ///
+    /// [`None`] disables sweeping. Leftovers to be swept may be inspected via
+    /// `Query.leftovers` before enabling it.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SWEEP_INTERVAL",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Interval of sweeping leftovers of removed restreams",
+        long_help = "Interval of kicking SRS clients of removed restreams, \
+                     and removing files written for them (DVR recordings, \
+                     HLS segments, overlay images, rolling buffers) which \
+                     are left behind. Sweeping is disabled if not specified, \
+                     while the leftovers to be swept may be inspected via \
+                     `leftovers` GraphQL query."
+    )]
+    pub sweep_interval: Option<Duration>,
+
+    /// Interval of auditing the running [FFmpeg] re-streaming processes
+    /// against the ones required by the current `State`, repairing any
+    /// divergence between them.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_POOL_AUDIT_INTERVAL",
+        default_value = "30s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Interval of auditing running FFmpeg processes",
+        long_help = "Interval of auditing the running FFmpeg re-streaming \
+                     processes against the ones required by the current \
+                     state. Any divergence (an enabled output without a \
+                     process, or a process left without an output) is \
+                     logged, repaired by spawning/killing processes, and \
+                     counted in `ephyr_pool_repairs_total` metric."
+    )]
+    pub pool_audit_interval: Duration,
+
+    /// Window of coalescing identical log messages of crash-looping [FFmpeg]
+    /// re-streaming processes and rejected [SRS] HTTP callbacks.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_LOG_COALESCE_WINDOW",
+        default_value = "60s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Window of coalescing identical log messages",
+        long_help = "Window of coalescing identical log messages of \
+                     crash-looping FFmpeg processes and rejected SRS \
+                     callbacks. The first message is logged as is, while the \
+                     ones repeated within the window are counted and logged \
+                     once as `message (repeated N times in the last 60s)`. \
+                     Zero disables coalescing."
+    )]
+    pub log_coalesce_window: Duration,
+
+    /// Maximum age (in hours) of [FFmpeg] re-streaming processes of `Output`s,
+    /// after which they're cycled (restarted gracefully) one at a time, within
+    /// the [`Opts::maintenance_window`] (if any).
+    ///
+    /// [`None`] disables cycling.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_CLIENT_HTTP_IP",
-        default_value = "0.0.0.0",
-        help = "IP to listen client HTTP on",
-        long_help = "IP address for the server to listen client HTTP requests \
-                     on"
+        env = "EPHYR_RESTREAMER_FFMPEG_MAX_AGE_HOURS",
+        parse(try_from_str = Self::parse_max_age_hours),
+        help = "Maximum age of FFmpeg processes in hours",
+        long_help = "Maximum age (in hours) of FFmpeg processes of outputs, \
+                     after which they're restarted gracefully one at a time, \
+                     waiting for each one to go online again before the next \
+                     one. Restreams with `noAutoCycle` are skipped. Cycling is \
+                     disabled if not specified."
     )]
-    pub client_http_ip: IpAddr,
+    pub ffmpeg_max_age_hours: Option<u32>,
 
-    /// Port for the server to listen client HTTP requests on.
+    /// Daily window (in UTC) to cycle [FFmpeg] processes older than the
+    /// [`Opts::ffmpeg_max_age_hours`] within.
+    ///
+    /// If [`None`], then processes are cycled at any time.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_CLIENT_HTTP_PORT",
-        default_value = "80",
-        help = "Port to listen client HTTP on",
-        long_help = "Port for the server to listen client HTTP requests on"
+        env = "EPHYR_RESTREAMER_MAINTENANCE_WINDOW",
+        value_name = "HH:MM-HH:MM UTC",
+        help = "Daily window to cycle old FFmpeg processes within",
+        long_help = "Daily window (in UTC) to cycle FFmpeg processes older \
+                     than `--ffmpeg-max-age-hours` within, like \
+                     `03:00-04:00 UTC`. Processes are cycled at any time if \
+                     not specified."
     )]
-    pub client_http_port: u16,
+    pub maintenance_window: Option<cycle::MaintenanceWindow>,
 
-    /// IP address for the server to listen RTMP callback HTTP requests on.
+    /// Maximum number of [FFmpeg] processes re-streaming outputs to run
+    /// concurrently, including the prewarmed ones.
+    ///
+    /// If [`None`], then the number is not limited.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_CALLBACK_HTTP_IP",
-        default_value = "127.0.0.1",
-        help = "IP to listen callback HTTP on",
-        long_help = "IP address for the server to listen RTMP callback HTTP \
-                     requests on"
+        env = "EPHYR_RESTREAMER_FFMPEG_MAX_OUTPUTS",
+        parse(try_from_str = Self::parse_max_outputs),
+        help = "Maximum number of concurrent FFmpeg processes of outputs",
+        long_help = "Maximum number of FFmpeg processes re-streaming outputs \
+                     to run concurrently. Processes of prewarmed outputs are \
+                     counted too, but enabled outputs take precedence over \
+                     them. Not limited if not specified."
     )]
-    pub callback_http_ip: IpAddr,
+    pub ffmpeg_max_outputs: Option<usize>,
 
-    /// Port for the server to listen RTMP callback HTTP requests on.
+    /// Path to the directory where overlay images of `Output`s are uploaded
+    /// to.
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_CALLBACK_HTTP_PORT",
-        default_value = "8081",
-        help = "Port to listen callback HTTP on",
-        long_help = "Port for the server to listen RTMP callback HTTP requests \
-                     on"
+        env = "EPHYR_RESTREAMER_OVERLAYS_DIR",
+        default_value = "overlays",
+        help = "Path to uploaded overlay images",
+        long_help = "Path to the directory where overlay images of outputs \
+                     are uploaded to via `PUT /overlays/{output_id}` endpoint"
     )]
-    pub callback_http_port: u16,
+    pub overlays_dir: PathBuf,
 
-    /// Path to a file to persist the server's state in.
+    /// Path to the directory where rolling buffers of delayed `Output`s are
+    /// written to.
+    ///
+    /// It's wiped out on startup, as buffers are not preserved across restarts.
     #[structopt(
-        short,
         long,
-        env = "EPHYR_RESTREAMER_STATE_PATH",
-        default_value = "state.json",
-        help = "Path to a file to persist state in",
-        long_help = "Path to a file to persist the server's state in"
+        env = "EPHYR_RESTREAMER_BUFFERS_DIR",
+        default_value = "buffers",
+        help = "Path to rolling buffers of delayed outputs",
+        long_help = "Path to the directory where rolling buffers of live \
+                     streams are written to for delayed outputs. It's wiped \
+                     out on startup."
     )]
-    pub state_path: PathBuf,
+    pub buffers_dir: PathBuf,
 
-    /// Path to [SRS] installation directory.
+    /// Path to the directory confining all the media files referred via API
+    /// or spec files (like [`FileInputSrc`]s or [`OutputOverlay`]s).
+    ///
+    /// Any path is allowed, if not specified.
+    ///
+    /// [`FileInputSrc`]: crate::state::FileInputSrc
+    /// [`OutputOverlay`]: crate::state::OutputOverlay
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_MEDIA_ROOT",
+        help = "Path to the only directory media files are allowed from",
+        long_help = "Path to the directory confining all the media files \
+                     referred via API or spec files (like file sources of \
+                     restreams or overlay images of outputs), so the ones \
+                     outside it (even via `..` or symbolic links) are \
+                     rejected. Any path is allowed, if not specified."
+    )]
+    pub media_root: Option<PathBuf>,
+
+    /// Maximum number of [`Restream`]s allowed on this server.
+    ///
+    /// Unlimited, if not specified.
+    ///
+    /// [`Restream`]: crate::state::Restream
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_MAX_RESTREAMS",
+        help = "Maximum number of restreams",
+        long_help = "Maximum number of restreams allowed on this server. \
+                     Adding or importing restreams over it is rejected. \
+                     Unlimited, if not specified."
+    )]
+    pub max_restreams: Option<u32>,
+
+    /// Maximum number of [`Output`]s allowed in a single [`Restream`].
+    ///
+    /// Unlimited, if not specified.
+    ///
+    /// [`Output`]: crate::state::Output
+    /// [`Restream`]: crate::state::Restream
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_MAX_OUTPUTS_PER_RESTREAM",
+        help = "Maximum number of outputs in a single restream",
+        long_help = "Maximum number of outputs allowed in a single restream. \
+                     Adding or importing outputs over it is rejected. \
+                     Unlimited, if not specified."
+    )]
+    pub max_outputs_per_restream: Option<u32>,
+
+    /// Port for the spawned [SRS] server to accept RTMP connections on.
     ///
     /// [SRS]: https://github.com/ossrs/srs
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_SRS_PATH",
-        default_value = "/usr/local/srs",
-        help = "Path to SRS dir",
-        long_help = "Path to SRS installation directory"
+        env = "EPHYR_RESTREAMER_SRS_RTMP_PORT",
+        default_value = "1935",
+        help = "Port to listen RTMP on by SRS",
+        long_help = "Port for the spawned SRS server to accept RTMP \
+                     connections on"
     )]
-    pub srs_path: PathBuf,
+    pub srs_rtmp_port: u16,
 
-    /// Path to the directory where [SRS] serves public files from via HTTP
-    /// (HLS chunks, etc).
+    /// IP address for the spawned [SRS] server to accept RTMP connections on
+    /// from external clients.
     ///
-    /// Relative path will use [`Opts::srs_path`] as its base path, not the
-    /// current working directory.
+    /// If not specified, then RTMP connections are accepted on all network
+    /// interfaces.
     ///
     /// [SRS]: https://github.com/ossrs/srs
     #[structopt(
         long,
-        env = "EPHYR_RESTREAMER_SRS_HTTP_DIR",
-        default_value = "/var/www/srs",
-        help = "Path to SRS public HTTP files",
-        long_help = "Path to the directory where SRS serves public files from \
-                     via HTTP (HLS chunks, etc).\
-                     \n\n\
-                     Relative path will use --srs-path as its base path, not \
-                     the current working directory."
+        env = "EPHYR_RESTREAMER_RTMP_BIND_IP",
+        help = "IP address to listen RTMP on by SRS",
+        long_help = "IP address for the spawned SRS server to accept RTMP \
+                     connections on from external clients (all interfaces, \
+                     if not specified)"
     )]
-    pub srs_http_dir: PathBuf,
+    pub rtmp_bind_ip: Option<IpAddr>,
+
+    /// Port for the spawned [SRS] server to expose its HTTP API on (bound to
+    /// `127.0.0.1` only).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_HTTP_API_PORT",
+        default_value = "8002",
+        help = "Port to listen HTTP API on by SRS",
+        long_help = "Port for the spawned SRS server to expose its HTTP API \
+                     on (bound to 127.0.0.1 only)"
+    )]
+    pub srs_http_api_port: u16,
+
+    /// Port for the spawned [SRS] server to expose its HTTP server on,
+    /// serving [HLS] playlists and segments.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SRS_HTTP_SERVER_PORT",
+        default_value = "8000",
+        help = "Port to listen HTTP server on by SRS",
+        long_help = "Port for the spawned SRS server to expose its HTTP \
+                     server on, serving HLS playlists and segments"
+    )]
+    pub srs_http_server_port: u16,
 
     /// Path to [FFmpeg] binary.
     ///
@@ -113,6 +1120,19 @@ pub struct Opts {
     )]
     pub ffmpeg_path: PathBuf,
 
+    /// Path to [FFprobe] binary.
+    ///
+    /// [FFprobe]: https://ffmpeg.org/ffprobe.html
+    #[structopt(
+        long,
+        env = "FFPROBE_PATH",
+        default_value = "/usr/local/bin/ffprobe",
+        help = "Path to FFprobe binary",
+        long_help = "Path to FFprobe binary, used for probing pull inputs' \
+                     sources"
+    )]
+    pub ffprobe_path: PathBuf,
+
     /// Host to access the re-streamer server in public networks.
     ///
     /// If [`None`], then it will be auto-detected.
@@ -125,6 +1145,294 @@ pub struct Opts {
     )]
     pub public_host: Option<String>,
 
+    /// Indicator whether auto-detection of public IP address via external
+    /// resolvers should be skipped, using the client HTTP bind address (or an
+    /// address of a local network interface, if it's unspecified) instead.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NO_PUBLIC_IP_DETECTION",
+        help = "Disables public IP auto-detection",
+        long_help = "Disables auto-detection of public IP address via \
+                     external resolvers, using the client HTTP bind address \
+                     instead (for LAN-only deployments)"
+    )]
+    pub no_public_ip_detection: bool,
+
+    /// Indicator whether an IPv6 address should be preferred over an IPv4 one
+    /// when auto-detecting public IP address.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_PREFER_IPV6",
+        help = "Prefers IPv6 public IP address",
+        long_help = "Prefers a global-scope IPv6 address of local network \
+                     interfaces over an IPv4 one when auto-detecting public IP \
+                     address"
+    )]
+    pub prefer_ipv6: bool,
+
+    /// Number of worker threads for each HTTP server to handle requests with.
+    ///
+    /// If [`None`], then the number of CPU cores is used.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_HTTP_WORKERS",
+        parse(try_from_str = Self::parse_threads_count),
+        help = "Number of HTTP server workers",
+        long_help = "Number of worker threads for each HTTP server to handle \
+                     requests with (number of CPU cores by default)"
+    )]
+    pub http_workers: Option<usize>,
+
+    /// Maximum number of threads for blocking operations (like password
+    /// hashing or filesystem access) performed by the async runtime.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_BLOCKING_THREADS",
+        default_value = "512",
+        parse(try_from_str = Self::parse_threads_count),
+        help = "Max number of blocking threads",
+        long_help = "Maximum number of threads for blocking operations (like \
+                     password hashing or filesystem access) performed by the \
+                     async runtime"
+    )]
+    pub blocking_threads: usize,
+
+    /// Address (`host[:port]`) of a [SMTP] server to send e-mail
+    /// notifications about outages via.
+    ///
+    /// If [`None`], then no e-mail notifications are sent.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_SERVER",
+        help = "SMTP server to send outage e-mails via",
+        long_help = "Address (`host[:port]`) of SMTP server to send e-mail \
+                     notifications about outages of inputs and outputs via \
+                     (no e-mails are sent by default)"
+    )]
+    pub smtp_server: Option<String>,
+
+    /// [`SmtpTls`] mode of connecting to the [`Opts::smtp_server`].
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_TLS",
+        default_value = "starttls",
+        help = "TLS mode of SMTP server: none | starttls | tls",
+        long_help = "TLS mode of connecting to SMTP server: none | starttls \
+                     | tls (default port is 25, 587 and 465 respectively)"
+    )]
+    pub smtp_tls: SmtpTls,
+
+    /// Username to authenticate on the [`Opts::smtp_server`] with, if any.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_USER",
+        help = "Username for SMTP server",
+        long_help = "Username to authenticate on SMTP server with"
+    )]
+    pub smtp_user: Option<String>,
+
+    /// Password to authenticate on the [`Opts::smtp_server`] with, along with
+    /// the [`Opts::smtp_user`].
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_PASSWORD",
+        hide_env_values = true,
+        help = "Password for SMTP server",
+        long_help = "Password to authenticate on SMTP server with (prefer \
+                     specifying it via environment variable)"
+    )]
+    pub smtp_password: Option<String>,
+
+    /// Address to send e-mail notifications from.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_FROM",
+        help = "Sender of outage e-mails",
+        long_help = "Address to send e-mail notifications about outages from"
+    )]
+    pub smtp_from: Option<String>,
+
+    /// Addresses to send e-mail notifications to.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SMTP_TO",
+        use_delimiter = true,
+        help = "Recipients of outage e-mails (comma-separated)",
+        long_help = "Addresses (comma-separated) to send e-mail notifications \
+                     about outages to"
+    )]
+    pub smtp_to: Vec<String>,
+
+    /// Duration an `Input` or an enabled `Output` should stay not online for,
+    /// to send an e-mail notification about its outage.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NOTIFY_OFFLINE_AFTER",
+        default_value = "5m",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Outage duration to send e-mail after",
+        long_help = "Duration an input or an enabled output should stay not \
+                     online for, to send an e-mail notification about its \
+                     outage (and about its recovery afterwards)"
+    )]
+    pub notify_offline_after: Duration,
+
+    /// Minimum interval between e-mail notifications about outages of the
+    /// same `Input` or `Output`.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_NOTIFY_MIN_INTERVAL",
+        default_value = "30m",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Min interval between outage e-mails",
+        long_help = "Minimum interval between e-mail notifications about \
+                     outages of the same input or output, so a flapping one \
+                     doesn't flood mailboxes"
+    )]
+    pub notify_min_interval: Duration,
+
+    /// URL of a message broker (`mqtt://` or `redis://` one) to publish
+    /// events about status changes of `Input`s and `Output`s to.
+    ///
+    /// Requires `event-broker` Cargo feature to be enabled.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_EVENT_BROKER",
+        help = "URL of MQTT or Redis broker to publish status events to",
+        long_help = "URL of MQTT (`mqtt://[user:pass@]host[:port]`) or Redis \
+                     (`redis://[[user]:pass@]host[:port]`) broker to publish \
+                     JSON events about status changes of inputs and outputs \
+                     to (no events are published by default, TLS brokers are \
+                     not supported, requires `event-broker` feature to be \
+                     compiled in)"
+    )]
+    pub event_broker: Option<Url>,
+
+    /// MQTT topic or Redis channel to publish events to the
+    /// [`Opts::event_broker`] on.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_EVENT_TOPIC",
+        default_value = "ephyr/status",
+        help = "MQTT topic or Redis channel to publish status events on",
+        long_help = "MQTT topic or Redis channel to publish events about \
+                     status changes on"
+    )]
+    pub event_topic: String,
+
+    /// Grace period a transition of an `Input` or an `Output` to offline is
+    /// postponed for, being dropped if it returns online meanwhile.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_STATUS_DEBOUNCE",
+        default_value = "0s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Grace period of going offline",
+        long_help = "Grace period a transition of an input or an output to \
+                     offline status is postponed for, being dropped if it \
+                     returns online meanwhile, so short network blips don't \
+                     flap its status (0s disables debouncing)"
+    )]
+    pub status_debounce: Duration,
+
+    /// Minimum encoding speed (relative to real time) of re-encoding `Output`s
+    /// considered stable.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_MIN_ENCODING_SPEED",
+        default_value = "0.98",
+        help = "Min stable encoding speed",
+        long_help = "Minimum encoding speed (relative to real time) of \
+                     re-encoding outputs considered stable, staying below \
+                     which makes them unstable"
+    )]
+    pub min_encoding_speed: f64,
+
+    /// Duration encoding speed of a re-encoding `Output` should stay below
+    /// the `min_encoding_speed` for, to make it unstable.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_SLOW_ENCODING_WINDOW",
+        default_value = "30s",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Window of slow encoding",
+        long_help = "Duration encoding speed of a re-encoding output should \
+                     stay below the minimum one for, to make it unstable"
+    )]
+    pub slow_encoding_window: Duration,
+
+    /// Number of [FFmpeg] processes this server is sized to run, being its
+    /// full process utilization in the capacity score (see [`capacity`]
+    /// module).
+    ///
+    /// Is not enforced in any way. If not specified, then process utilization
+    /// is not considered by the capacity score.
+    ///
+    /// [`capacity`]: crate::capacity
+    /// [FFmpeg]: https://ffmpeg.org
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CAPACITY_PROCESSES",
+        help = "Number of FFmpeg processes the server is sized for",
+        long_help = "Number of FFmpeg processes this server is sized to run, \
+                     being its full process utilization in the capacity \
+                     score reported to load balancers. Is not enforced. \
+                     Process utilization is not considered, if not \
+                     specified."
+    )]
+    pub capacity_processes: Option<u32>,
+
+    /// [`capacity::Weights`] of the utilizations forming the capacity score of
+    /// this server.
+    ///
+    /// [`capacity::Weights`]: crate::capacity::Weights
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_CAPACITY_WEIGHTS",
+        default_value = "processes=1,load=1,offline=1",
+        help = "Weights of utilizations forming the capacity score",
+        long_help = "Comma-separated weights of the utilizations forming \
+                     the capacity score reported to load balancers: \
+                     `processes` (running FFmpeg processes), `load` (CPU \
+                     load average) and `offline` (enabled outputs not being \
+                     online)"
+    )]
+    pub capacity_weights: capacity::Weights,
+
+    /// Base URL of an OTLP/HTTP collector to export tracing spans to.
+    ///
+    /// Requires `tracing` Cargo feature to be enabled.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_TRACING_OTLP",
+        help = "URL of OTLP/HTTP collector to export tracing spans to",
+        long_help = "Base URL of OTLP/HTTP collector (like \
+                     `http://localhost:4318`) to export tracing spans of \
+                     state mutations, persisting, FFmpeg processes spawning \
+                     and SRS callbacks to (no spans are exported by default, \
+                     requires `tracing` feature to be compiled in)"
+    )]
+    pub tracing_otlp: Option<Url>,
+
+    /// Indicator whether faults may be injected on demand via `injectFault`
+    /// GraphQL mutation, for rehearsing failures handling.
+    ///
+    /// Requires `fault-injection` Cargo feature to be enabled.
+    #[structopt(
+        long,
+        env = "EPHYR_RESTREAMER_ENABLE_FAULT_INJECTION",
+        help = "Enables injecting faults for rehearsals",
+        long_help = "Enables injecting faults (forcing inputs offline, \
+                     killing FFmpeg processes of outputs, delaying SRS \
+                     callbacks) on demand via `injectFault` GraphQL \
+                     mutation, for rehearsing failures handling (requires \
+                     `fault-injection` feature to be compiled in, never use \
+                     in production)"
+    )]
+    pub enable_fault_injection: bool,
+
     /// Verbosity level of the server logs.
     #[structopt(
         short,
@@ -136,14 +1444,197 @@ pub struct Opts {
     pub verbose: Option<slog::Level>,
 }
 
+impl Default for Opts {
+    /// Returns the same [`Opts`] as the ones parsed out of no CLI arguments
+    /// and no environment variables.
+    fn default() -> Self {
+        const DAY: u64 = 24 * 60 * 60;
+
+        Self {
+            debug: false,
+            no_ui: false,
+            no_compression: false,
+            public_schema: false,
+            public_status_page: false,
+            session_key: None,
+            session_lifetime: Duration::from_secs(7 * DAY),
+            allow_local_reset: false,
+            ws_keep_alive: Duration::from_secs(5),
+            ws_max_connections: 100,
+            ws_max_operations: 32,
+            graphql_max_depth: 15,
+            graphql_max_complexity: 500,
+            base_path: String::new(),
+            client_http_ip: [0, 0, 0, 0].into(),
+            client_http_port: 80,
+            callback_http_ip: [127, 0, 0, 1].into(),
+            dual_stack: false,
+            callback_http_port: 8081,
+            single_port: false,
+            callback_http_socket: None,
+            trusted_proxies: vec![],
+            state_path: "state.json".into(),
+            no_persist: false,
+            split_state: false,
+            state_encryption_key_file: None,
+            replicate_from: None,
+            replicate_token: None,
+            initial_spec: None,
+            initial_spec_merge: false,
+            spec_authoritative: false,
+            srs_path: "/usr/local/srs".into(),
+            srs_http_dir: "/var/www/srs".into(),
+            no_http_flv: false,
+            latency_profile: srs::LatencyProfile::default(),
+            srs_chunk_size: None,
+            srs_queue_length: None,
+            srs_mr: None,
+            srs_min_latency: None,
+            srs_gop_cache: None,
+            thumbnails_interval: None,
+            history_interval: Duration::from_secs(5),
+            trash_retention: Duration::from_secs(7 * DAY),
+            usage_flush_interval: Duration::from_secs(60),
+            sweep_interval: None,
+            pool_audit_interval: Duration::from_secs(30),
+            log_coalesce_window: Duration::from_secs(60),
+            ffmpeg_max_age_hours: None,
+            maintenance_window: None,
+            ffmpeg_max_outputs: None,
+            overlays_dir: "overlays".into(),
+            buffers_dir: "buffers".into(),
+            media_root: None,
+            max_restreams: None,
+            max_outputs_per_restream: None,
+            srs_rtmp_port: 1935,
+            rtmp_bind_ip: None,
+            srs_http_api_port: 8002,
+            srs_http_server_port: 8000,
+            ffmpeg_path: "/usr/local/bin/ffmpeg".into(),
+            ffprobe_path: "/usr/local/bin/ffprobe".into(),
+            public_host: None,
+            no_public_ip_detection: false,
+            prefer_ipv6: false,
+            http_workers: None,
+            blocking_threads: 512,
+            smtp_server: None,
+            smtp_tls: SmtpTls::StartTls,
+            smtp_user: None,
+            smtp_password: None,
+            smtp_from: None,
+            smtp_to: vec![],
+            notify_offline_after: Duration::from_secs(5 * 60),
+            notify_min_interval: Duration::from_secs(30 * 60),
+            event_broker: None,
+            event_topic: "ephyr/status".into(),
+            status_debounce: Duration::from_secs(0),
+            min_encoding_speed: 0.98,
+            slow_encoding_window: Duration::from_secs(30),
+            capacity_processes: None,
+            capacity_weights: capacity::Weights::default(),
+            tracing_otlp: None,
+            enable_fault_injection: false,
+            verbose: None,
+        }
+    }
+}
+
 impl Opts {
-    /// Parses CLI [`Opts`] from command line arguments.
+    /// Resolves all the filesystem paths of these [`Opts`] into canonical
+    /// absolute ones (see [`paths::resolve()`]), so they're not affected by
+    /// symbolic links or `..` components anymore.
     ///
-    /// Prints the error message and quits the program in case of failure.
+    /// # Errors
+    ///
+    /// If any of the paths cannot be resolved, or [`Opts::media_root`] is not
+    /// an existing directory.
+    pub fn resolve_paths(&mut self) -> Result<(), paths::PathError> {
+        self.state_path = paths::resolve(&self.state_path, None)?;
+        self.overlays_dir = paths::resolve(&self.overlays_dir, None)?;
+        self.buffers_dir = paths::resolve(&self.buffers_dir, None)?;
+        if let Some(path) = &mut self.state_encryption_key_file {
+            *path = paths::resolve(&*path, None)?;
+        }
+        if let Some(path) = &mut self.initial_spec {
+            *path = paths::resolve(&*path, None)?;
+        }
+        if let Some(root) = &self.media_root {
+            let root = paths::resolve(root, None)?;
+            if !root.is_dir() {
+                return Err(paths::PathError::Root {
+                    root,
+                    reason: "not an existing directory".into(),
+                });
+            }
+            self.media_root = Some(root);
+        }
+        Ok(())
+    }
+
+    /// Returns address for the client HTTP server to listen on.
+    ///
+    /// Once the server is bound, reports the actual address, even if an
+    /// ephemeral port has been requested.
     #[inline]
     #[must_use]
-    pub fn from_args() -> Self {
-        <Self as StructOpt>::from_args()
+    pub fn client_http_addr(&self) -> SocketAddr {
+        (self.client_http_ip, self.client_http_port).into()
+    }
+
+    /// Returns address for the callback HTTP server to listen on, if there is
+    /// a separate one (see [`Opts::single_port`]) listening on TCP (see
+    /// [`Opts::callback_http_socket`]).
+    ///
+    /// Once the server is bound, reports the actual address, even if an
+    /// ephemeral port has been requested.
+    #[inline]
+    #[must_use]
+    pub fn callback_http_addr(&self) -> Option<SocketAddr> {
+        (!self.single_port && self.callback_http_socket.is_none())
+            .then(|| (self.callback_http_ip, self.callback_http_port).into())
+    }
+
+    /// Returns [`srs::Tuning`] of the [`Opts::latency_profile`] with the
+    /// explicitly specified tunables applied on top of it.
+    ///
+    /// Doesn't validate the resulting [`srs::Tuning`].
+    #[must_use]
+    pub fn srs_tuning(&self) -> srs::Tuning {
+        let mut tuning = self.latency_profile.tuning();
+        if let Some(size) = self.srs_chunk_size {
+            tuning.chunk_size = size;
+        }
+        if let Some(len) = self.srs_queue_length {
+            tuning.queue_length = len;
+        }
+        if let Some(mr) = self.srs_mr {
+            tuning.mr = mr;
+        }
+        if let Some(min) = self.srs_min_latency {
+            tuning.min_latency = min;
+        }
+        if let Some(cache) = self.srs_gop_cache {
+            tuning.gop_cache = cache;
+        }
+        tuning
+    }
+
+    /// Returns [`complexity::Limits`] of GraphQL operations of the client API.
+    #[inline]
+    #[must_use]
+    pub fn graphql_limits(&self) -> complexity::Limits {
+        complexity::Limits {
+            max_depth: self.graphql_max_depth,
+            max_complexity: self.graphql_max_complexity,
+        }
+    }
+
+    /// Returns the number of worker threads for each HTTP server to handle
+    /// requests with, resolving the default [`Opts::http_workers`].
+    #[inline]
+    #[must_use]
+    pub fn http_workers_count(&self) -> usize {
+        self.http_workers.unwrap_or_else(num_cpus::get)
     }
 
     /// Parses [`slog::Level`] from the given string.
@@ -168,6 +1659,79 @@ impl Opts {
             )
         })
     }
+
+    /// Parses [`Opts::base_path`] from the given string, normalizing it to
+    /// have a leading slash and no trailing one.
+    ///
+    /// # Errors
+    ///
+    /// If the string contains characters not allowed in URL path, or denotes
+    /// a query or a fragment.
+    pub fn parse_base_path(path: &str) -> Result<String, anyhow::Error> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return Ok(String::new());
+        }
+        if path
+            .chars()
+            .any(|c| c.is_whitespace() || c == '?' || c == '#' || c == '%')
+        {
+            return Err(anyhow!("'{}' is invalid base path", path));
+        }
+        Ok(format!("/{}", path))
+    }
+
+    /// Parses an `on`/`off` toggle from the given string.
+    ///
+    /// # Errors
+    ///
+    /// If the string is neither `on` nor `off`.
+    pub fn parse_toggle(val: &str) -> Result<bool, anyhow::Error> {
+        match val {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(anyhow!("'{}' is invalid toggle, allowed: on | off", val)),
+        }
+    }
+
+    /// Parses [`Opts::ffmpeg_max_age_hours`] from the given string, ensuring
+    /// it's not zero.
+    ///
+    /// # Errors
+    ///
+    /// If the string is not a positive integer.
+    pub fn parse_max_age_hours(n: &str) -> Result<u32, anyhow::Error> {
+        n.parse::<u32>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("'{}' is invalid number of hours", n))
+    }
+
+    /// Parses [`Opts::ffmpeg_max_outputs`] from the given string, ensuring
+    /// it's not zero.
+    ///
+    /// # Errors
+    ///
+    /// If the string is not a positive integer.
+    pub fn parse_max_outputs(n: &str) -> Result<usize, anyhow::Error> {
+        n.parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("'{}' is invalid number of processes", n))
+    }
+
+    /// Parses a number of threads from the given string, ensuring it's not
+    /// zero.
+    ///
+    /// # Errors
+    ///
+    /// If the string is not a positive integer.
+    pub fn parse_threads_count(n: &str) -> Result<usize, anyhow::Error> {
+        n.parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow!("'{}' is invalid number of threads", n))
+    }
 }
 
 /// Error type indicating non-zero process exit code.
@@ -186,3 +1750,46 @@ impl From<()> for Failure {
         Self
     }
 }
+
+#[cfg(test)]
+mod opts_spec {
+    use structopt::StructOpt as _;
+
+    use super::Opts;
+
+    #[test]
+    fn default_matches_parsed_without_args() {
+        assert_eq!(
+            format!("{:?}", Opts::default()),
+            format!("{:?}", Opts::from_iter(&["ephyr-restreamer"])),
+        );
+    }
+
+    #[test]
+    fn rejects_zero_ffmpeg_max_age() {
+        let parse = |hours| {
+            Opts::from_iter_safe(&[
+                "ephyr-restreamer",
+                "--ffmpeg-max-age-hours",
+                hours,
+            ])
+        };
+
+        assert!(parse("0").is_err());
+        assert_eq!(parse("24").unwrap().ffmpeg_max_age_hours, Some(24));
+    }
+
+    #[test]
+    fn rejects_zero_ffmpeg_max_outputs() {
+        let parse = |n| {
+            Opts::from_iter_safe(&[
+                "ephyr-restreamer",
+                "--ffmpeg-max-outputs",
+                n,
+            ])
+        };
+
+        assert!(parse("0").is_err());
+        assert_eq!(parse("10").unwrap().ffmpeg_max_outputs, Some(10));
+    }
+}
@@ -0,0 +1,397 @@
+//! Coalescing of repeated log messages, so a crash-looping [FFmpeg] process or
+//! a misbehaving client doesn't drown out everything else in the logs.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+
+use std::{
+    collections::HashMap,
+    convert::TryInto as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use ephyr_log::{log, slog::Level};
+use tokio::time;
+
+/// Maximum number of distinct messages counted by a [`LogCoalescer`] at once.
+///
+/// Once reached, any new message is emitted as is, without being counted, so
+/// a flood of distinct messages (like a scan from many IP addresses) doesn't
+/// grow the [`LogCoalescer`] unbounded.
+pub const MAX_ENTRIES: usize = 1024;
+
+/// Coalescer of identical log messages.
+///
+/// Messages are identical if they have the same key (a template along with
+/// its key fields) and are emitted in the same scope (like an ID of the failing
+/// process), regardless of their details. The first one is emitted as is,
+/// while the following ones within the [`LogCoalescer::window()`] are only
+/// counted, and emitted once as `message (repeated N times in the last 60s)`.
+///
+/// Clones share the same counted messages.
+#[derive(Clone, Debug)]
+pub struct LogCoalescer {
+    /// Duration (in milliseconds) of the window identical messages are
+    /// counted within.
+    ///
+    /// Zero disables coalescing, so every message is emitted as is.
+    window: Arc<AtomicU64>,
+
+    /// Counted messages, identified by their scopes and keys.
+    entries: Arc<Mutex<HashMap<(String, String), Entry>>>,
+}
+
+/// Counted message in the [`LogCoalescer`].
+#[derive(Clone, Debug)]
+struct Entry {
+    /// Level the message is logged with.
+    level: Level,
+
+    /// The most recent message.
+    message: String,
+
+    /// Moment the current counting window has started at.
+    since: Instant,
+
+    /// Number of messages counted (but not emitted) within the current
+    /// counting window.
+    repeated: u32,
+}
+
+impl Entry {
+    /// Formats the most recent message of this [`Entry`] with the number of
+    /// its repetitions counted till the given moment.
+    fn summary(&self, repeated: u32, now: Instant) -> String {
+        format!(
+            "{} (repeated {} times in the last {}s)",
+            self.message,
+            repeated,
+            now.saturating_duration_since(self.since).as_secs(),
+        )
+    }
+}
+
+impl Default for LogCoalescer {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+impl LogCoalescer {
+    /// Default [`LogCoalescer::window()`].
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+    /// Creates a new [`LogCoalescer`] counting identical messages within the
+    /// given `window`.
+    #[must_use]
+    pub fn new(window: Duration) -> Self {
+        let coalescer = Self {
+            window: Arc::new(AtomicU64::new(0)),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        };
+        coalescer.set_window(window);
+        coalescer
+    }
+
+    /// Returns the window identical messages are counted within.
+    #[inline]
+    #[must_use]
+    pub fn window(&self) -> Duration {
+        Duration::from_millis(self.window.load(Ordering::Relaxed))
+    }
+
+    /// Sets the window identical messages are counted within.
+    ///
+    /// Zero disables coalescing.
+    pub fn set_window(&self, window: Duration) {
+        self.window.store(
+            window.as_millis().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Records the given `message` of the given `key` emitted in the given
+    /// `scope` with the given `level` at the given moment.
+    ///
+    /// Returns the message to be actually logged, if any:
+    /// - the `message` as is, if it's the first one in the current window, or
+    ///   there are [`MAX_ENTRIES`] counted already;
+    /// - [`None`], if it has been counted within the current window;
+    /// - the `message` along with the number of repetitions, if the current
+    ///   window has elapsed (starting a new one).
+    #[must_use]
+    pub fn record(
+        &self,
+        scope: &str,
+        key: &str,
+        level: Level,
+        message: String,
+        now: Instant,
+    ) -> Option<String> {
+        let window = self.window();
+        if window == Duration::from_secs(0) {
+            return Some(message);
+        }
+
+        let mut entries =
+            self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let id = (scope.to_owned(), key.to_owned());
+        let entry = match entries.get_mut(&id) {
+            Some(e) => e,
+            None => {
+                if entries.len() < MAX_ENTRIES {
+                    let _ = entries.insert(
+                        id,
+                        Entry {
+                            level,
+                            message: message.clone(),
+                            since: now,
+                            repeated: 0,
+                        },
+                    );
+                }
+                return Some(message);
+            }
+        };
+
+        entry.message = message;
+        if now.saturating_duration_since(entry.since) < window {
+            entry.repeated += 1;
+            return None;
+        }
+        let emitted = if entry.repeated > 0 {
+            entry.summary(entry.repeated + 1, now)
+        } else {
+            entry.message.clone()
+        };
+        entry.since = now;
+        entry.repeated = 0;
+        Some(emitted)
+    }
+
+    /// Forgets all the messages emitted in the given `scope`, once the
+    /// condition causing them clears.
+    ///
+    /// Returns the messages (along with the number of repetitions) which have
+    /// been counted, but not emitted yet, to be logged finally.
+    #[must_use]
+    pub fn flush(&self, scope: &str, now: Instant) -> Vec<String> {
+        self.drain(now, |(s, _), _| s == scope)
+            .into_iter()
+            .map(|(_, msg)| msg)
+            .collect()
+    }
+
+    /// Forgets all the messages whose counting window has elapsed till the
+    /// given moment, so the ones not repeating anymore don't stay forever.
+    ///
+    /// Returns the messages (along with their levels and the number of
+    /// repetitions) which have been counted, but not emitted yet, to be logged
+    /// finally.
+    #[must_use]
+    pub fn expire(&self, now: Instant) -> Vec<(Level, String)> {
+        let window = self.window();
+        self.drain(now, |_, e| now.saturating_duration_since(e.since) >= window)
+    }
+
+    /// Forgets all the messages matching the given `predicate`, returning the
+    /// ones which have been counted, but not emitted yet.
+    fn drain<F>(&self, now: Instant, predicate: F) -> Vec<(Level, String)>
+    where
+        F: Fn(&(String, String), &Entry) -> bool,
+    {
+        let mut entries =
+            self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.is_empty() {
+            return vec![];
+        }
+        let mut drained = vec![];
+        entries.retain(|id, e| {
+            if !predicate(id, e) {
+                return true;
+            }
+            if e.repeated > 0 {
+                drained.push((e.level, e.summary(e.repeated, now)));
+            }
+            false
+        });
+        drained
+    }
+
+    /// Periodically logs the counted messages whose counting window has
+    /// elapsed (see [`LogCoalescer::expire()`]), so they're not delayed until
+    /// the next identical message, which may never happen.
+    pub async fn expire_periodically(self) {
+        loop {
+            time::delay_for(self.window().max(Duration::from_secs(1))).await;
+            for (level, msg) in self.expire(Instant::now()) {
+                emit(level, &msg);
+            }
+        }
+    }
+}
+
+/// Logs the given `message` with the given `level`.
+fn emit(level: Level, message: &str) {
+    match level {
+        Level::Critical => log::crit!("{}", message),
+        Level::Error => log::error!("{}", message),
+        Level::Warning => log::warn!("{}", message),
+        Level::Info => log::info!("{}", message),
+        Level::Debug => log::debug!("{}", message),
+        Level::Trace => log::trace!("{}", message),
+    }
+}
+
+#[cfg(test)]
+mod log_coalescer_spec {
+    use std::time::{Duration, Instant};
+
+    use ephyr_log::slog::Level;
+
+    use super::{LogCoalescer, MAX_ENTRIES};
+
+    const ERR: Level = Level::Error;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn emits_first_message_as_is() {
+        let c = LogCoalescer::new(secs(60));
+
+        assert_eq!(
+            c.record("a", "exit", ERR, "failed".into(), Instant::now()),
+            Some("failed".into()),
+        );
+    }
+
+    #[test]
+    fn counts_identical_messages_within_window() {
+        let c = LogCoalescer::new(secs(60));
+        let start = Instant::now();
+
+        let _ = c.record("a", "exit", ERR, "failed 1".into(), start);
+        for n in 2..=5 {
+            let msg = format!("failed {}", n);
+            assert!(c.record("a", "exit", ERR, msg, start + secs(n)).is_none());
+        }
+        assert_eq!(
+            c.record("a", "exit", ERR, "failed 6".into(), start + secs(61)),
+            Some("failed 6 (repeated 5 times in the last 61s)".into()),
+        );
+        assert!(c
+            .record("a", "exit", ERR, "failed 7".into(), start + secs(62))
+            .is_none());
+    }
+
+    #[test]
+    fn distinguishes_scopes_and_templates() {
+        let c = LogCoalescer::new(secs(60));
+        let now = Instant::now();
+
+        assert!(c.record("a", "exit", ERR, "failed".into(), now).is_some());
+        assert!(c.record("b", "exit", ERR, "failed".into(), now).is_some());
+        assert!(c.record("a", "setup", ERR, "failed".into(), now).is_some());
+        assert!(c.record("a", "exit", ERR, "failed".into(), now).is_none());
+    }
+
+    #[test]
+    fn emits_sparse_messages_as_is() {
+        let c = LogCoalescer::new(secs(60));
+        let start = Instant::now();
+
+        let _ = c.record("a", "exit", ERR, "failed".into(), start);
+        assert_eq!(
+            c.record("a", "exit", ERR, "failed".into(), start + secs(90)),
+            Some("failed".into()),
+        );
+    }
+
+    #[test]
+    fn flushes_counted_messages_once_cleared() {
+        let c = LogCoalescer::new(secs(60));
+        let start = Instant::now();
+
+        let _ = c.record("a", "exit", ERR, "failed 1".into(), start);
+        let _ = c.record("a", "exit", ERR, "failed 2".into(), start + secs(1));
+        let _ = c.record("a", "exit", ERR, "failed 3".into(), start + secs(2));
+        let _ = c.record("b", "exit", ERR, "failed".into(), start);
+        let _ = c.record("b", "exit", ERR, "failed".into(), start + secs(1));
+
+        assert_eq!(
+            c.flush("a", start + secs(10)),
+            vec!["failed 3 (repeated 2 times in the last 10s)".to_owned()],
+        );
+        assert!(c.flush("a", start + secs(11)).is_empty());
+        assert_eq!(
+            c.record("a", "exit", ERR, "failed 4".into(), start + secs(12)),
+            Some("failed 4".into()),
+        );
+        assert_eq!(c.flush("b", start + secs(12)).len(), 1);
+    }
+
+    #[test]
+    fn flushes_nothing_if_not_repeated() {
+        let c = LogCoalescer::new(secs(60));
+        let now = Instant::now();
+
+        let _ = c.record("a", "exit", ERR, "failed".into(), now);
+
+        assert!(c.flush("a", now).is_empty());
+        assert!(c.record("a", "exit", ERR, "failed".into(), now).is_some());
+    }
+
+    #[test]
+    fn emits_everything_when_disabled() {
+        let c = LogCoalescer::new(Duration::from_secs(0));
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(c.record("a", "exit", ERR, "failed".into(), now).is_some());
+        }
+        assert!(c.flush("a", now).is_empty());
+    }
+
+    #[test]
+    fn expires_elapsed_messages() {
+        let c = LogCoalescer::new(secs(60));
+        let start = Instant::now();
+
+        let _ = c.record("a", "exit", ERR, "failed 1".into(), start);
+        let _ = c.record("a", "exit", ERR, "failed 2".into(), start + secs(1));
+        let _ = c.record("b", "exit", Level::Info, "failed".into(), start);
+        let _ = c.record("c", "exit", ERR, "failed".into(), start + secs(30));
+
+        assert_eq!(
+            c.expire(start + secs(60)),
+            vec![(ERR, "failed 2 (repeated 1 times in the last 60s)".into())],
+        );
+        assert_eq!(
+            c.record("b", "exit", ERR, "failed".into(), start + secs(61)),
+            Some("failed".into()),
+        );
+        assert!(c
+            .record("c", "exit", ERR, "failed".into(), start + secs(61))
+            .is_none());
+    }
+
+    #[test]
+    fn emits_as_is_once_full() {
+        let c = LogCoalescer::new(secs(60));
+        let now = Instant::now();
+
+        for n in 0..MAX_ENTRIES {
+            let _ = c.record("a", &n.to_string(), ERR, "failed".into(), now);
+        }
+        for _ in 0..3 {
+            assert!(c.record("b", "exit", ERR, "failed".into(), now).is_some());
+        }
+        assert!(c.record("a", "0", ERR, "failed".into(), now).is_none());
+    }
+}
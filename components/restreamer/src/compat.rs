@@ -0,0 +1,639 @@
+//! Checking compatibility of live streams with the
+//! [`state::OutputConstraints`] of their downstream destinations.
+//!
+//! Codecs of the live streams received by [`state::Input`]s are polled from
+//! [SRS] periodically, and are checked against the constraints of every
+//! enabled [`state::Output`] re-streaming them, so an incompatible live stream
+//! is reported before its destination drops the connection with a cryptic
+//! error.
+//!
+//! [SRS] 3 doesn't report the resolution of live streams, so the maximum
+//! resolution constraints are checked only with [SRS] 4 or newer.
+//!
+//! [SRS]: https://github.com/ossrs/srs
+
+use std::{collections::HashMap, convert::TryFrom as _, time::Duration};
+
+use ephyr_log::log;
+use tokio::time;
+
+use crate::{
+    api::srs::{self as srs_api, StreamInfo},
+    state::{
+        self, normalize_codec, InputCodecs, InputId, InputSrc, OutputId,
+        ProblemInfo, ProblemKind,
+    },
+    trace, State,
+};
+
+/// Interval of polling [SRS] for codecs of the live streams.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub const CODECS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Spawns a background task renewing [`state::Input::codecs`] in the given
+/// [`State`] with the ones reported by [SRS], once per the given `interval`.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub fn poll_codecs_periodically(interval: Duration, state: State) {
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(interval).await;
+            match srs_api::Client::streams().await {
+                Ok(streams) => renew_codecs(&state, &streams),
+                Err(e) => {
+                    log::debug!("Failed to list SRS streams for codecs: {}", e);
+                }
+            }
+        }
+    }));
+}
+
+/// Renews [`state::Input::codecs`] of all the [`state::Input`]s in the given
+/// [`State`] with the ones of the given [SRS] `streams`.
+///
+/// [`state::Input`]s having no actively published stream have no codecs.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub fn renew_codecs(state: &State, streams: &[StreamInfo]) {
+    /// Collects [`state::Input::codecs`] to be renewed in the given
+    /// [`state::Input`] and all its [`state::FailoverInputSrc::inputs`].
+    fn collect(
+        input: &state::Input,
+        app: &str,
+        codecs: &HashMap<(&str, &str), InputCodecs>,
+        renewed: &mut Vec<(InputId, Option<InputCodecs>)>,
+    ) {
+        let actual = codecs.get(&(app, input.key.as_str()));
+        if input.codecs.as_ref() != actual {
+            renewed.push((input.id, actual.cloned()));
+        }
+        if let Some(InputSrc::Failover(s)) = &input.src {
+            for i in &s.inputs {
+                collect(i, app, codecs, renewed);
+            }
+        }
+    }
+
+    let codecs: HashMap<_, _> = streams
+        .iter()
+        .filter(|s| s.publish.active)
+        .map(|s| ((s.app.as_str(), s.name.as_str()), codecs_of(s)))
+        .collect();
+
+    // Locking for mutation notifies all the `State` subscribers, so is
+    // avoided when nothing has changed.
+    let mut renewed = vec![];
+    for r in state.restreams.lock_ref().iter() {
+        collect(&r.input, r.key.as_str(), &codecs, &mut renewed);
+    }
+    if renewed.is_empty() {
+        return;
+    }
+
+    let mut restreams = trace::lock_mut(&state.restreams, "renew_codecs");
+    for (id, codecs) in renewed {
+        if let Some(i) = restreams.iter_mut().find_map(|r| r.input.find_mut(id))
+        {
+            i.codecs = codecs;
+        }
+    }
+}
+
+/// Extracts [`InputCodecs`] of the given [SRS] stream.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[must_use]
+fn codecs_of(stream: &StreamInfo) -> InputCodecs {
+    let to_i32 = |v: Option<u32>| v.and_then(|v| i32::try_from(v).ok());
+    let video = stream.video.as_ref();
+    let audio = stream.audio.as_ref();
+    InputCodecs {
+        video_codec: video
+            .map(|v| normalize_codec(&v.codec))
+            .filter(|c| !c.is_empty()),
+        width: to_i32(video.and_then(|v| v.width)),
+        height: to_i32(video.and_then(|v| v.height)),
+        audio_codec: audio
+            .map(|a| normalize_codec(&a.codec))
+            .filter(|c| !c.is_empty()),
+        audio_channels: to_i32(audio.and_then(|a| a.channel)),
+    }
+}
+
+/// Watcher of [`state::Restream`]s, renewing the
+/// [`state::Output::incompatibility`] of their [`state::Output`]s whenever
+/// their [`state::Input::codecs`] or [`state::Output::constraints`] change.
+#[derive(Debug)]
+pub struct ConstraintsChecker {
+    /// Application [`State`] to renew [`state::Output`]s in.
+    state: State,
+
+    /// [`Checked`] parameters of the constrained [`state::Output`]s along
+    /// with their [`incompatibility()`], so it's not re-described unless they
+    /// change.
+    checked: HashMap<OutputId, (Checked, Option<String>)>,
+}
+
+impl ConstraintsChecker {
+    /// Creates a new [`ConstraintsChecker`] operating on the given [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            state,
+            checked: HashMap::new(),
+        }
+    }
+
+    /// Renews, in background, the [`state::Output::incompatibility`] of the
+    /// [`state::Output`]s of the given `restreams`, if it has changed for any
+    /// of them.
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let mut checked = HashMap::with_capacity(self.checked.len());
+        let mut changed = false;
+        for r in restreams {
+            for o in &r.outputs {
+                let params = match Checked::of(r, o) {
+                    Some(params) => params,
+                    None => {
+                        changed |= o.incompatibility.is_some();
+                        continue;
+                    }
+                };
+                let reason = match self.checked.remove(&o.id) {
+                    Some((prev, reason)) if prev == params => reason,
+                    _ => incompatibility(r, o),
+                };
+                changed |= o.incompatibility != reason;
+                let _ = checked.insert(o.id, (params, reason));
+            }
+        }
+        self.checked = checked;
+
+        if !changed {
+            return;
+        }
+        let state = self.state.clone();
+        drop(tokio::spawn(async move { renew_incompatibilities(&state) }));
+    }
+}
+
+/// Parameters of a constrained [`state::Output`] its [`incompatibility()`] is
+/// determined by.
+#[derive(Debug, PartialEq)]
+struct Checked {
+    /// [`state::Output::enabled`] indicator.
+    enabled: bool,
+
+    /// [`state::Output::constraints`] themselves.
+    constraints: state::OutputConstraints,
+
+    /// [`state::Input::codecs`] of the live stream being re-streamed.
+    codecs: Option<InputCodecs>,
+
+    /// Scheme of the [`state::Output::dst`].
+    scheme: String,
+
+    /// Indicators whether the [`state::Output`] has an overlay,
+    /// conditioning, loudness normalization and mixins, re-encoding its
+    /// tracks.
+    reencodes: [bool; 4],
+}
+
+impl Checked {
+    /// Captures [`Checked`] parameters of the given [`state::Output`] of the
+    /// given [`state::Restream`].
+    ///
+    /// Returns [`None`] if the [`state::Output`] has no constraints.
+    #[must_use]
+    fn of(restream: &state::Restream, output: &state::Output) -> Option<Self> {
+        Some(Self {
+            enabled: output.enabled,
+            constraints: output.constraints.clone()?,
+            codecs: restream
+                .output_src_input(output)
+                .and_then(|i| i.codecs.clone()),
+            scheme: output.dst.scheme().to_owned(),
+            reencodes: [
+                output.overlay.is_some(),
+                output.conditioning.is_some(),
+                output.loudnorm.is_some(),
+                !output.mixins.is_empty(),
+            ],
+        })
+    }
+}
+
+/// Checks whether the live stream of the given enabled [`state::Output`] of
+/// the given [`state::Restream`] must not be re-streamed at all, as its
+/// [`state::Output::constraints`] are enforced, and it doesn't meet them or
+/// its codecs are not known yet.
+///
+/// Codecs are known only in [`CODECS_POLL_INTERVAL`] after the live stream is
+/// published, and re-streaming it before that could reach the destination
+/// with an incompatible live stream.
+#[must_use]
+pub fn is_blocked(restream: &state::Restream, output: &state::Output) -> bool {
+    let enforced = output
+        .constraints
+        .as_ref()
+        .map_or(false, state::OutputConstraints::enforce);
+    output.enabled
+        && enforced
+        && (output.incompatibility.is_some()
+            || restream
+                .output_src_input(output)
+                .map_or(true, |i| i.codecs.is_none()))
+}
+
+/// Renews the [`state::Output::incompatibility`] of all the
+/// [`state::Output`]s in the given [`State`], reporting it as their
+/// [`ProblemKind::Incompatible`] problem.
+fn renew_incompatibilities(state: &State) {
+    let mut restreams =
+        trace::lock_mut(&state.restreams, "renew_incompatibilities");
+    for r in restreams.iter_mut() {
+        let renewed: Vec<_> = r
+            .outputs
+            .iter()
+            .map(|o| incompatibility(r, o))
+            .enumerate()
+            .filter(|(n, i)| r.outputs[*n].incompatibility != *i)
+            .collect();
+        for (n, incompatibility) in renewed {
+            let output = &mut r.outputs[n];
+            if let Some(reason) = &incompatibility {
+                log::warn!(
+                    "Output {} of {} restream is incompatible: {}",
+                    output.id,
+                    r.key,
+                    reason,
+                );
+                output.problem = Some(ProblemInfo::new(
+                    ProblemKind::Incompatible,
+                    reason.clone(),
+                ));
+            } else if output
+                .problem
+                .as_ref()
+                .map_or(false, |p| p.kind == ProblemKind::Incompatible)
+            {
+                output.problem = None;
+            }
+            output.incompatibility = incompatibility;
+        }
+    }
+}
+
+/// Describes how the live stream re-streamed by the given [`state::Output`]
+/// of the given [`state::Restream`] doesn't meet its
+/// [`state::Output::constraints`].
+///
+/// Tracks re-encoded by the [`state::Output`] are checked as [H.264] video and
+/// [AAC] audio, while their resolution and channels stay the same.
+///
+/// Returns [`None`] if the live stream meets the constraints, or the
+/// [`state::Output`] is disabled, or its live stream codecs are not known.
+///
+/// [AAC]: https://en.wikipedia.org/wiki/Advanced_Audio_Coding
+/// [H.264]: https://en.wikipedia.org/wiki/Advanced_Video_Coding
+#[must_use]
+pub fn incompatibility(
+    restream: &state::Restream,
+    output: &state::Output,
+) -> Option<String> {
+    /// Codec of the video re-encoded by an [`state::Output`].
+    const REENCODED_VIDEO: &str = "h264";
+
+    /// Codec of the audio re-encoded by an [`state::Output`].
+    const REENCODED_AUDIO: &str = "aac";
+
+    if !output.enabled {
+        return None;
+    }
+    let constraints = output.constraints.as_ref()?;
+    let codecs = restream.output_src_input(output)?.codecs.as_ref()?;
+
+    let is_file = output.dst.scheme() == "file";
+    let is_audio_only = output.dst.scheme() == "icecast";
+    let reencodes_video =
+        output.overlay.is_some() || (output.conditioning.is_some() && !is_file);
+    let reencodes_audio = output.conditioning.is_some()
+        || output.loudnorm.is_some()
+        || !output.mixins.is_empty();
+
+    let mut reasons = vec![];
+    let mut transcodable = false;
+
+    if let Some(codec) =
+        codecs.video_codec.as_deref().filter(|_| !is_audio_only)
+    {
+        let codec = if reencodes_video {
+            REENCODED_VIDEO
+        } else {
+            codec
+        };
+        let accepted = constraints.video_codecs();
+        if !accepted.is_empty() && !accepted.contains(&codec) {
+            reasons.push(format!(
+                "video codec `{}` is not accepted (only {})",
+                codec,
+                accepted.join(", "),
+            ));
+            transcodable |= accepted.contains(&REENCODED_VIDEO);
+        }
+
+        let (max_width, max_height) = constraints.max_resolution();
+        let exceeds = |actual: Option<i32>, max: Option<u32>| {
+            let actual = u32::try_from(actual?).ok()?;
+            Some((actual, max?)).filter(|(actual, max)| actual > max)
+        };
+        if let Some((width, max)) = exceeds(codecs.width, max_width) {
+            reasons.push(format!("video width {} exceeds {}", width, max));
+        }
+        if let Some((height, max)) = exceeds(codecs.height, max_height) {
+            reasons.push(format!("video height {} exceeds {}", height, max));
+        }
+    }
+
+    if let Some(codec) = codecs.audio_codec.as_deref() {
+        let codec = if reencodes_audio {
+            REENCODED_AUDIO
+        } else {
+            codec
+        };
+        let accepted = constraints.audio_codecs();
+        if !accepted.is_empty() && !accepted.contains(&codec) {
+            reasons.push(format!(
+                "audio codec `{}` is not accepted (only {})",
+                codec,
+                accepted.join(", "),
+            ));
+            transcodable |= accepted.contains(&REENCODED_AUDIO);
+        }
+
+        let channels =
+            codecs.audio_channels.and_then(|c| u32::try_from(c).ok());
+        if let (Some(channels), Some(max)) =
+            (channels, constraints.max_audio_channels())
+        {
+            if channels > max {
+                reasons.push(format!(
+                    "{} audio channels exceed {}",
+                    channels, max,
+                ));
+            }
+        }
+    }
+
+    if reasons.is_empty() {
+        return None;
+    }
+    let mut reason = reasons.join(", ");
+    if transcodable && output.conditioning.is_none() {
+        reason.push_str(
+            "; attach conditioning to the output to transcode the live stream",
+        );
+    }
+    Some(reason)
+}
+
+#[cfg(test)]
+mod renew_codecs_spec {
+    use serde_json::{json, Value};
+
+    use crate::{api::srs::StreamInfo, spec, state::State};
+
+    use super::renew_codecs;
+
+    fn streams() -> Vec<StreamInfo> {
+        parse(include_str!("api/srs/fixtures/streams/4.0/streams.json"))
+    }
+
+    fn parse(resp: &str) -> Vec<StreamInfo> {
+        let resp: Value = serde_json::from_str(resp).unwrap();
+        serde_json::from_value(resp["streams"].clone()).unwrap()
+    }
+
+    fn restream(key: &str) -> spec::v1::Restream {
+        serde_json::from_value(json!({
+            "key": key,
+            "input": {
+                "key": "origin",
+                "endpoints": [{"kind": "rtmp"}],
+                "enabled": true,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renews_codecs_of_published_inputs_only() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+        state.add_restream(restream("gone")).unwrap();
+
+        renew_codecs(&state, &streams());
+
+        let restreams = state.restreams.lock_ref();
+        let codecs = restreams[0].input.codecs.as_ref().unwrap();
+        assert_eq!(codecs.video_codec.as_deref(), Some("h264"));
+        assert_eq!((codecs.width, codecs.height), (Some(1920), Some(1080)));
+        assert_eq!(codecs.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(codecs.audio_channels, Some(2));
+        assert!(restreams[1].input.codecs.is_none());
+    }
+
+    #[test]
+    fn renews_codecs_without_resolution_on_srs3() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+
+        renew_codecs(
+            &state,
+            &parse(include_str!(
+                "api/srs/fixtures/streams/3.0-r5/streams.json"
+            )),
+        );
+
+        let restreams = state.restreams.lock_ref();
+        let codecs = restreams[0].input.codecs.as_ref().unwrap();
+        assert_eq!(codecs.video_codec.as_deref(), Some("h264"));
+        assert_eq!((codecs.width, codecs.height), (None, None));
+        assert_eq!(codecs.audio_codec.as_deref(), Some("aac"));
+    }
+
+    #[test]
+    fn clears_codecs_of_unpublished_inputs() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+        renew_codecs(&state, &streams());
+
+        renew_codecs(&state, &[]);
+
+        assert!(state.restreams.lock_ref()[0].input.codecs.is_none());
+    }
+}
+
+#[cfg(test)]
+mod incompatibility_spec {
+    use serde_json::json;
+
+    use crate::state::{
+        InputCodecs, OutputConditioning, OutputConditioningPreset, Restream,
+        State,
+    };
+
+    use super::{incompatibility, is_blocked};
+
+    /// Creates a [`State`] with a single enabled output constrained to the
+    /// YouTube preset, optionally `enforce`d.
+    fn state(enforce: bool) -> State {
+        State::with_restream(
+            "main",
+            json!({"outputs": [{
+                "dst": "rtmp://a.rtmp.youtube.com/live2/key",
+                "constraints": {"preset": "youtube", "enforce": enforce},
+                "enabled": true,
+            }]}),
+        )
+    }
+
+    fn codecs(video: &str, audio: &str, channels: i32) -> InputCodecs {
+        InputCodecs {
+            video_codec: Some(video.into()),
+            width: Some(3840),
+            height: Some(2160),
+            audio_codec: Some(audio.into()),
+            audio_channels: Some(channels),
+        }
+    }
+
+    /// Creates a [`Restream`] with a single enabled output constrained to the
+    /// YouTube preset, and its input receiving the given codecs.
+    fn restream(video: &str, audio: &str, channels: i32) -> Restream {
+        let mut restream = state(false).restreams.lock_ref()[0].clone();
+        restream.input.codecs = Some(codecs(video, audio, channels));
+        restream
+    }
+
+    #[test]
+    fn accepts_compatible_stream() {
+        let r = restream("h264", "aac", 2);
+        assert_eq!(incompatibility(&r, &r.outputs[0]), None);
+    }
+
+    #[test]
+    fn reports_mismatches_with_suggestion() {
+        let r = restream("hevc", "opus", 6);
+
+        let reason = incompatibility(&r, &r.outputs[0]).unwrap();
+        assert!(reason.contains("video codec `hevc`"), "{}", reason);
+        assert!(reason.contains("audio codec `opus`"), "{}", reason);
+        assert!(reason.contains("6 audio channels exceed 2"), "{}", reason);
+        assert!(reason.contains("attach conditioning"), "{}", reason);
+    }
+
+    #[test]
+    fn considers_reencoded_tracks() {
+        let mut r = restream("hevc", "opus", 6);
+        r.outputs[0].conditioning = Some(OutputConditioning {
+            preset: OutputConditioningPreset::Youtube,
+            audio_sample_rate: None,
+        });
+
+        let reason = incompatibility(&r, &r.outputs[0]).unwrap();
+        assert!(!reason.contains("codec"), "{}", reason);
+        assert!(!reason.contains("attach conditioning"), "{}", reason);
+        assert!(reason.contains("6 audio channels exceed 2"), "{}", reason);
+    }
+
+    #[test]
+    fn ignores_disabled_outputs() {
+        let mut r = restream("hevc", "aac", 2);
+        r.outputs[0].enabled = false;
+        assert_eq!(incompatibility(&r, &r.outputs[0]), None);
+    }
+
+    #[test]
+    fn blocks_enforced_until_codecs_are_known() {
+        let mut r = state(true).restreams.lock_ref()[0].clone();
+        assert!(is_blocked(&r, &r.outputs[0]));
+
+        r.input.codecs = Some(codecs("h264", "aac", 2));
+        assert!(!is_blocked(&r, &r.outputs[0]));
+
+        r.input.codecs = Some(codecs("hevc", "aac", 2));
+        r.outputs[0].incompatibility = incompatibility(&r, &r.outputs[0]);
+        assert!(is_blocked(&r, &r.outputs[0]));
+    }
+
+    #[test]
+    fn never_blocks_not_enforced() {
+        let mut r = state(false).restreams.lock_ref()[0].clone();
+        assert!(!is_blocked(&r, &r.outputs[0]));
+
+        r.input.codecs = Some(codecs("hevc", "aac", 2));
+        r.outputs[0].incompatibility = incompatibility(&r, &r.outputs[0]);
+        assert!(!is_blocked(&r, &r.outputs[0]));
+    }
+}
+
+#[cfg(test)]
+mod constraints_checker_spec {
+    use std::time::Duration;
+
+    use actix_web::rt::System;
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::state::{InputCodecs, State};
+
+    use super::ConstraintsChecker;
+
+    fn set_video_codec(state: &State, codec: &str) {
+        state.restreams.lock_mut()[0].input.codecs = Some(InputCodecs {
+            video_codec: Some(codec.into()),
+            ..InputCodecs::default()
+        });
+    }
+
+    fn incompatibility(state: &State) -> Option<String> {
+        state.restreams.lock_ref()[0].outputs[0]
+            .incompatibility
+            .clone()
+    }
+
+    #[test]
+    fn renews_on_changes_only() {
+        System::new("test").block_on(async {
+            let state = State::with_restream(
+                "main",
+                json!({"outputs": [{
+                    "dst": "rtmp://a.rtmp.youtube.com/live2/key",
+                    "constraints": {"preset": "youtube"},
+                    "enabled": true,
+                }]}),
+            );
+            let mut checker = ConstraintsChecker::new(state.clone());
+
+            set_video_codec(&state, "hevc");
+            checker.apply(&state.restreams.get_cloned());
+            time::delay_for(Duration::from_millis(10)).await;
+            let reason = incompatibility(&state).unwrap();
+            assert!(reason.contains("video codec `hevc`"), "{}", reason);
+
+            // Stale reason is renewed, even if the parameters are unchanged.
+            state.restreams.lock_mut()[0].outputs[0].incompatibility = None;
+            checker.apply(&state.restreams.get_cloned());
+            time::delay_for(Duration::from_millis(10)).await;
+            assert!(incompatibility(&state).is_some());
+
+            set_video_codec(&state, "h264");
+            checker.apply(&state.restreams.get_cloned());
+            time::delay_for(Duration::from_millis(10)).await;
+            assert_eq!(incompatibility(&state), None);
+        });
+    }
+}
@@ -0,0 +1,392 @@
+//! Cycling of long-running [FFmpeg] re-streaming processes of
+//! [`state::Output`]s within a [`MaintenanceWindow`].
+//!
+//! [FFmpeg]: https://ffmpeg.org
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use ephyr_log::log;
+use tokio::{sync::Mutex, time};
+use uuid::Uuid;
+
+use crate::{
+    ffmpeg,
+    state::{self, State, Statuses},
+};
+
+/// Daily window of time (in UTC) to cycle long-running [FFmpeg] processes
+/// within, like `03:00-04:00`.
+///
+/// May span midnight, like `23:30-00:30`.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MaintenanceWindow {
+    /// Second of the day (in UTC) this [`MaintenanceWindow`] starts at.
+    start: u64,
+
+    /// Second of the day (in UTC) this [`MaintenanceWindow`] ends at
+    /// (exclusively).
+    end: u64,
+}
+
+impl MaintenanceWindow {
+    /// Number of seconds in a single day.
+    const DAY_SECS: u64 = 24 * 60 * 60;
+
+    /// Indicates whether the given moment falls into this
+    /// [`MaintenanceWindow`].
+    #[must_use]
+    pub fn contains(&self, at: SystemTime) -> bool {
+        let secs = at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() % Self::DAY_SECS);
+        if self.start <= self.end {
+            self.start <= secs && secs < self.end
+        } else {
+            secs >= self.start || secs < self.end
+        }
+    }
+
+    /// Parses the given `HH:MM` time of the day into seconds.
+    fn parse_time(s: &str) -> Option<u64> {
+        let mut parts = s.trim().splitn(2, ':');
+        let (h, m) = (parts.next()?, parts.next()?);
+        if h.len() != 2 || m.len() != 2 {
+            return None;
+        }
+        let (h, m) = (h.parse::<u64>().ok()?, m.parse::<u64>().ok()?);
+        (h < 24 && m < 60).then(|| h * 3600 + m * 60)
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            anyhow!(
+                "Invalid maintenance window '{}', should be `HH:MM-HH:MM UTC`",
+                s,
+            )
+        };
+
+        let s = s.trim();
+        let s = s
+            .strip_suffix("UTC")
+            .or_else(|| s.strip_suffix("utc"))
+            .unwrap_or(s);
+        let mut parts = s.splitn(2, '-');
+        let start = parts.next().and_then(Self::parse_time).ok_or_else(err)?;
+        let end = parts.next().and_then(Self::parse_time).ok_or_else(err)?;
+        if start == end {
+            return Err(anyhow!("Maintenance window cannot be empty"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02} UTC",
+            self.start / 3600,
+            self.start % 3600 / 60,
+            self.end / 3600,
+            self.end % 3600 / 60,
+        )
+    }
+}
+
+/// Interval of looking for [FFmpeg] processes to be cycled.
+///
+/// [FFmpeg]: https://ffmpeg.org
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum time to wait for a cycled [FFmpeg] process to become
+/// [`state::Status::Online`] again, before cycling the next one anyway.
+///
+/// [FFmpeg]: https://ffmpeg.org
+const ONLINE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Spawns a background task cycling (restarting gracefully) [FFmpeg]
+/// re-streaming processes of the given `pool` running longer than the given
+/// `max_age`, within the given [`MaintenanceWindow`] only (if any).
+///
+/// Processes are cycled one at a time, the oldest first, waiting for each one
+/// to become [`state::Status::Online`] again before cycling the next one.
+/// Processes of [`state::Restream`]s with [`state::Restream::no_auto_cycle`]
+/// are never cycled, same as the ones not being online (they're restarted
+/// anyway, see [`state::Status::is_online()`]).
+///
+/// Cycling counts as a restart of the process (see
+/// [`ffmpeg::ProcessStats::restarts()`]).
+///
+/// [FFmpeg]: https://ffmpeg.org
+pub fn cycle_periodically(
+    max_age: Duration,
+    window: Option<MaintenanceWindow>,
+    pool: Arc<Mutex<ffmpeg::RestreamersPool>>,
+    state: State,
+) {
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(CHECK_INTERVAL).await;
+
+            let now = SystemTime::now();
+            if state.draining.get() || !window.map_or(true, |w| w.contains(now))
+            {
+                continue;
+            }
+            let candidate = oldest_expired(
+                &state.ffmpeg_pool.snapshot(),
+                &state.restreams.lock_ref(),
+                &state.statuses.lock_ref(),
+                max_age,
+                now,
+            );
+            if let Some((id, age)) = candidate {
+                cycle(&pool, id, age).await;
+            }
+        }
+    }));
+}
+
+/// Cycles the [FFmpeg] process identified by the given `id` in the given
+/// `pool`, waiting for it to become [`state::Status::Online`] again.
+///
+/// [FFmpeg]: https://ffmpeg.org
+async fn cycle(pool: &Mutex<ffmpeg::RestreamersPool>, id: Uuid, age: Duration) {
+    log::info!(
+        "Cycling FFmpeg re-streamer {} running for {}h",
+        id,
+        age.as_secs() / 3600,
+    );
+    let requested_at = SystemTime::now();
+    if !pool.lock().await.cycle(id) {
+        log::info!("FFmpeg re-streamer {} is gone, not cycling it", id);
+        return;
+    }
+
+    let deadline = Instant::now() + ONLINE_TIMEOUT;
+    while Instant::now() < deadline {
+        time::delay_for(Duration::from_secs(1)).await;
+        let online_again = ffmpeg::Stats::global()
+            .get(id)
+            .and_then(|s| s.online_since())
+            .map_or(false, |since| since > requested_at);
+        if online_again {
+            log::info!("Cycled FFmpeg re-streamer {}", id);
+            return;
+        }
+    }
+    log::warn!(
+        "Cycled FFmpeg re-streamer {} hasn't become online in {}s",
+        id,
+        ONLINE_TIMEOUT.as_secs(),
+    );
+}
+
+/// Picks the oldest of the given [FFmpeg] `processes` of [`state::Output`]s
+/// (or consolidating them, see [`state::Restream::consolidate_outputs`])
+/// running longer than the given `max_age` at the given moment, and allowed
+/// to be cycled (according to the given `statuses`).
+///
+/// Returns the ID of the process in the [`ffmpeg::RestreamersPool`], along
+/// with its age.
+///
+/// [FFmpeg]: https://ffmpeg.org
+fn oldest_expired(
+    processes: &[state::PoolProcess],
+    restreams: &[state::Restream],
+    statuses: &Statuses,
+    max_age: Duration,
+    now: SystemTime,
+) -> Option<(Uuid, Duration)> {
+    processes
+        .iter()
+        .filter_map(|p| {
+            let id = Uuid::parse_str(&p.key).ok()?;
+            let age = now
+                .duration_since(p.spawned_at?.into())
+                .ok()
+                .filter(|age| *age >= max_age)?;
+            let cyclable =
+                restreams.iter().filter(|r| !r.no_auto_cycle).any(|r| {
+                    let is_tee =
+                        p.kind == "tee" && Uuid::from(r.input.id) == id;
+                    r.outputs.iter().any(|o| {
+                        (is_tee || Uuid::from(o.id) == id)
+                            && statuses.get(o.id).is_online()
+                    })
+                });
+            cyclable.then(|| (id, age))
+        })
+        .max_by_key(|(_, age)| *age)
+}
+
+#[cfg(test)]
+mod maintenance_window_spec {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::MaintenanceWindow;
+
+    fn window(s: &str) -> MaintenanceWindow {
+        s.parse().unwrap()
+    }
+
+    fn at(h: u64, m: u64) -> std::time::SystemTime {
+        UNIX_EPOCH + Duration::from_secs(10 * 86_400 + h * 3600 + m * 60)
+    }
+
+    #[test]
+    fn parses_with_and_without_timezone() {
+        assert_eq!(window("03:00-04:00 UTC"), window("03:00-04:00"));
+        assert_eq!(window("03:00-04:30").to_string(), "03:00-04:30 UTC");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for s in &["", "03:00", "3:00-4:00", "03:00-24:00", "03:00-03:00"] {
+            assert!(s.parse::<MaintenanceWindow>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn contains_moments_within() {
+        let w = window("03:00-04:00");
+        assert!(w.contains(at(3, 0)));
+        assert!(w.contains(at(3, 59)));
+        assert!(!w.contains(at(4, 0)));
+        assert!(!w.contains(at(2, 59)));
+    }
+
+    #[test]
+    fn spans_midnight() {
+        let w = window("23:30-00:30");
+        assert!(w.contains(at(23, 45)));
+        assert!(w.contains(at(0, 15)));
+        assert!(!w.contains(at(0, 30)));
+        assert!(!w.contains(at(12, 0)));
+    }
+}
+
+#[cfg(test)]
+mod oldest_expired_spec {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::{
+        spec,
+        state::{PoolProcess, Restream, Status, Statuses},
+    };
+
+    use super::oldest_expired;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn restream(key: &str, no_auto_cycle: bool) -> Restream {
+        Restream::new(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": key,
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                "outputs": [{"dst": "rtmp://example.com/live/stream"}],
+                "no_auto_cycle": no_auto_cycle,
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn online(restreams: &[Restream]) -> Statuses {
+        let mut statuses = Statuses::default();
+        for r in restreams {
+            let _ = statuses.set(r.outputs[0].id, Status::Online);
+        }
+        statuses
+    }
+
+    fn process(r: &Restream, age: Duration, now: SystemTime) -> PoolProcess {
+        PoolProcess {
+            key: r.outputs[0].id.to_string(),
+            kind: "copy".into(),
+            command: vec![],
+            pid: Some(42),
+            spawned_at: Some((now - age).into()),
+            restarts: 0,
+            backoff_secs: 0,
+            last_exit: None,
+            limited_by: vec![],
+        }
+    }
+
+    #[test]
+    fn picks_oldest_expired() {
+        let now = SystemTime::now();
+        let restreams = vec![restream("a", false), restream("b", false)];
+        let processes = vec![
+            process(&restreams[0], HOUR * 30, now),
+            process(&restreams[1], HOUR * 50, now),
+        ];
+
+        let statuses = online(&restreams);
+
+        let picked =
+            oldest_expired(&processes, &restreams, &statuses, HOUR * 24, now);
+
+        assert_eq!(
+            picked.map(|(id, _)| id),
+            Some(restreams[1].outputs[0].id.into()),
+        );
+    }
+
+    #[test]
+    fn picks_consolidating_tee() {
+        let now = SystemTime::now();
+        let restreams = vec![restream("a", false), restream("b", true)];
+        let statuses = online(&restreams);
+        let tee = |r: &Restream| PoolProcess {
+            key: r.input.id.to_string(),
+            kind: "tee".into(),
+            ..process(r, HOUR * 30, now)
+        };
+        let processes = vec![tee(&restreams[0]), tee(&restreams[1])];
+
+        let picked =
+            oldest_expired(&processes, &restreams, &statuses, HOUR * 24, now);
+
+        assert_eq!(
+            picked.map(|(id, _)| id),
+            Some(restreams[0].input.id.into()),
+        );
+    }
+
+    #[test]
+    fn skips_young_offline_and_excluded() {
+        let now = SystemTime::now();
+        let restreams = vec![
+            restream("young", false),
+            restream("offline", false),
+            restream("critical", true),
+        ];
+        let mut statuses = online(&restreams);
+        let _ = statuses.set(restreams[1].outputs[0].id, Status::Offline);
+        let processes = vec![
+            process(&restreams[0], HOUR, now),
+            process(&restreams[1], HOUR * 50, now),
+            process(&restreams[2], HOUR * 50, now),
+        ];
+
+        let picked =
+            oldest_expired(&processes, &restreams, &statuses, HOUR * 24, now);
+
+        assert!(picked.is_none());
+    }
+}
@@ -0,0 +1,616 @@
+//! Diagnostic reports of this server, bundling everything needed to
+//! investigate a crash or a misbehaviour into a single `.tar.gz` archive.
+//!
+//! Recent log lines of [FFmpeg] and [SRS] processes and recent
+//! [`StatusEvent`]s are kept in the [`History`] along the way, as there is no
+//! other place to take them from once something has gone wrong.
+//!
+//! Credentials are masked with [`ffmpeg::mask_secrets()`] in every entry of
+//! a [`Report`], so it may be attached to a bug report as is.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+//! [SRS]: https://github.com/ossrs/srs
+
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io::{self, Write as _},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use futures::StreamExt as _;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    api::graphql::DateTime, cli::Opts, extension::StatusEvent, ffmpeg, spec,
+    state::PoolProcess, State,
+};
+
+/// Maximum number of the recent log lines kept in the [`History`].
+pub const LOG_LINES: usize = 1000;
+
+/// Maximum length (in bytes) of a single log line kept in the [`History`],
+/// the exceeding part is cut off.
+pub const MAX_LINE_LEN: usize = 1024;
+
+/// Maximum number of the recent [`StatusEvent`]s kept in the [`History`].
+pub const EVENTS: usize = 500;
+
+/// Maximum size (in bytes) of a single entry of a [`Report`] archive, the
+/// exceeding part is cut off.
+pub const MAX_ENTRY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Maximum duration of generating a [`Report`].
+pub const GENERATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// GraphQL query of the `Info` parameters included into a [`Report`].
+///
+/// Doesn't query `Info.passwordHash`, as it has nothing to diagnose.
+pub const INFO_QUERY: &str = "{
+    info {
+        publicHost srsRtmpPort httpFlv title logLevel draining
+        playgroundEnabled persistenceHealthy persistenceError
+        srs {
+            version running pid uptime restarts httpApiResponds
+            tuning { chunkSize queueLength mr minLatency gopCache }
+        }
+        ffmpeg { version missingCapabilities }
+        specReload { at error added updated removed }
+        replication { source promoted connected syncedAt error failures }
+        wsConnections wsMaxConnections wsMaxOperations
+        graphqlMaxDepth graphqlMaxComplexity httpWorkers blockingThreads
+        clientHttpAddr callbackHttpAddr rejectDstConflicts displayTimezone
+        quota { maxRestreams maxOutputsPerRestream restreams maxOutputs }
+        changeSeq
+    }
+}";
+
+/// Replacement of an entry's part exceeding the [`MAX_ENTRY_SIZE`].
+const TRUNCATED: &[u8] = b"\n... truncated\n";
+
+/// Bounded history of the recent log lines and [`StatusEvent`]s to be
+/// included into a [`Report`].
+///
+/// Log lines are masked on recording already, so credentials never linger in
+/// memory longer than they have to.
+#[derive(Debug, Default)]
+pub struct History {
+    /// Recent log lines, no more than [`LOG_LINES`].
+    logs: Mutex<VecDeque<String>>,
+
+    /// Recent [`StatusEvent`]s, no more than [`EVENTS`].
+    events: Mutex<VecDeque<StatusEvent>>,
+}
+
+impl History {
+    /// Returns the global instance of [`History`].
+    #[inline]
+    #[must_use]
+    pub fn global() -> &'static Self {
+        static HISTORY: Lazy<History> = Lazy::new(History::default);
+        &*HISTORY
+    }
+
+    /// Records every line of the given `text` logged by the given `source`
+    /// (like `srs`), evicting the oldest ones beyond the [`LOG_LINES`].
+    pub fn record_logs(&self, source: &str, text: &str) {
+        let at = DateTime::now();
+        let mut logs = self.logs.lock().unwrap_or_else(PoisonError::into_inner);
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            if logs.len() == LOG_LINES {
+                let _ = logs.pop_front();
+            }
+            let line = mask_text(truncate(line, MAX_LINE_LEN));
+            logs.push_back(format!("{} [{}] {}", at, source, line));
+        }
+    }
+
+    /// Records the given [`StatusEvent`], evicting the oldest ones beyond the
+    /// [`EVENTS`].
+    pub fn record_event(&self, ev: StatusEvent) {
+        let mut events =
+            self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        if events.len() == EVENTS {
+            let _ = events.pop_front();
+        }
+        events.push_back(ev);
+    }
+
+    /// Returns the recorded log lines, from the oldest to the newest.
+    #[must_use]
+    pub fn logs(&self) -> Vec<String> {
+        let logs = self.logs.lock().unwrap_or_else(PoisonError::into_inner);
+        logs.iter().cloned().collect()
+    }
+
+    /// Returns the recorded [`StatusEvent`]s, from the oldest to the newest.
+    #[must_use]
+    pub fn events(&self) -> Vec<StatusEvent> {
+        let events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        events.iter().cloned().collect()
+    }
+}
+
+/// Spawns recording of [`StatusEvent`]s of the given [`State`] into the
+/// global [`History`].
+pub fn record_status_events(state: &State) {
+    let mut events = Box::pin(state.subscribe_status_events());
+    drop(tokio::spawn(async move {
+        while let Some(ev) = events.next().await {
+            History::global().record_event(ev);
+        }
+    }));
+}
+
+/// Diagnostic report of this server, archived as `.tar.gz` with
+/// [`Report::to_tar_gz()`].
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// `Info` parameters of this server, as queried by [`INFO_QUERY`].
+    pub info: Value,
+
+    /// Export of the [`State`], without `Credentials`.
+    pub state: Value,
+
+    /// Snapshot of the pool of [FFmpeg] processes.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub pool: Vec<PoolProcess>,
+
+    /// Recent [`StatusEvent`]s.
+    pub events: Vec<StatusEvent>,
+
+    /// Recent log lines of [FFmpeg] and [SRS] processes.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    /// [SRS]: https://github.com/ossrs/srs
+    pub logs: Vec<String>,
+
+    /// Flags this server has been configured with (see [`config_flags()`]).
+    pub config: Value,
+}
+
+impl Report {
+    /// Collects a new [`Report`] of the given [`State`] and [`Opts`], along
+    /// with the already queried `info`.
+    #[must_use]
+    pub fn collect(state: &State, opts: &Opts, info: Value) -> Self {
+        let mut spec = state.export().into_v1();
+        redact_spec(&mut spec);
+        let exported = serde_json::to_value(spec::Spec::from(spec))
+            .unwrap_or_else(|e| {
+                json!({ "error": format!("Failed to export state: {}", e) })
+            });
+        Self {
+            info,
+            state: exported,
+            pool: state.ffmpeg_pool.snapshot(),
+            events: History::global().events(),
+            logs: History::global().logs(),
+            config: config_flags(opts),
+        }
+    }
+
+    /// Archives this [`Report`] as `.tar.gz`, masking all the credentials.
+    ///
+    /// Contains `info.json`, `state.json`, `pool.json`, `events.json`,
+    /// `logs.txt` and `config.json` entries, each one cut off at the
+    /// [`MAX_ENTRY_SIZE`].
+    ///
+    /// # Errors
+    ///
+    /// If the archive fails to be written.
+    pub fn to_tar_gz(&self) -> io::Result<Vec<u8>> {
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut tar = tar::Builder::new(GzEncoder::new(
+            Vec::new(),
+            Compression::default(),
+        ));
+        let entries = [
+            ("info.json", json_entry(&self.info)?),
+            ("state.json", json_entry(&self.state)?),
+            ("pool.json", json_entry(&self.pool)?),
+            ("events.json", json_entry(&self.events)?),
+            ("logs.txt", text_entry(&self.logs)),
+            ("config.json", json_entry(&self.config)?),
+        ];
+        for (path, data) in &entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            tar.append_data(&mut header, path, data.as_slice())?;
+        }
+        tar.into_inner()?.finish()
+    }
+}
+
+/// Serializes the given `val` as a pretty JSON entry of a [`Report`] archive,
+/// with all the credentials masked.
+///
+/// # Errors
+///
+/// If the given `val` fails to be serialized.
+fn json_entry<T: Serialize>(val: &T) -> io::Result<Vec<u8>> {
+    let mut val = serde_json::to_value(val)?;
+    mask_json(&mut val);
+    Ok(bounded(serde_json::to_vec_pretty(&val)?))
+}
+
+/// Joins the given `lines` into a text entry of a [`Report`] archive, with all
+/// the credentials masked.
+fn text_entry(lines: &[String]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for line in lines {
+        let _ = writeln!(data, "{}", mask_text(line));
+    }
+    bounded(data)
+}
+
+/// Cuts off the part of the given `data` exceeding the [`MAX_ENTRY_SIZE`].
+fn bounded(mut data: Vec<u8>) -> Vec<u8> {
+    if data.len() > MAX_ENTRY_SIZE {
+        data.truncate(MAX_ENTRY_SIZE - TRUNCATED.len());
+        data.extend_from_slice(TRUNCATED);
+    }
+    data
+}
+
+/// Cuts off the part of the given `line` exceeding `max` bytes, at a char
+/// boundary.
+fn truncate(line: &str, max: usize) -> &str {
+    if line.len() <= max {
+        return line;
+    }
+    let end = (0..=max).rev().find(|&i| line.is_char_boundary(i));
+    &line[..end.unwrap_or(0)]
+}
+
+/// Describes flags the given [`Opts`] are configured with, for diagnosing
+/// purposes.
+///
+/// Secrets (like [`Opts::session_key`]) are described by whether they are
+/// specified only.
+#[must_use]
+pub fn config_flags(opts: &Opts) -> Value {
+    let duration = |d: Duration| humantime::format_duration(d).to_string();
+    json!({
+        "debug": opts.debug,
+        "noUi": opts.no_ui,
+        "noCompression": opts.no_compression,
+        "publicSchema": opts.public_schema,
+        "publicStatusPage": opts.public_status_page,
+        "sessionKey": opts.session_key.is_some(),
+        "allowLocalReset": opts.allow_local_reset,
+        "basePath": opts.base_path,
+        "singlePort": opts.single_port,
+        "dualStack": opts.dual_stack,
+        "noPersist": opts.no_persist,
+        "splitState": opts.split_state,
+        "stateEncryption": opts.state_encryption_key_file.is_some(),
+        "replicateFrom": opts.replicate_from.as_ref().map(|u| u.as_str()),
+        "replicateToken": opts.replicate_token.is_some(),
+        "specAuthoritative": opts.spec_authoritative,
+        "noHttpFlv": opts.no_http_flv,
+        "latencyProfile": opts.latency_profile.to_string(),
+        "thumbnailsInterval": opts.thumbnails_interval.map(duration),
+        "sweepInterval": opts.sweep_interval.map(duration),
+        "ffmpegMaxAgeHours": opts.ffmpeg_max_age_hours,
+        "maxRestreams": opts.max_restreams,
+        "maxOutputsPerRestream": opts.max_outputs_per_restream,
+        "capacityProcesses": opts.capacity_processes,
+        "smtpPassword": opts.smtp_password.is_some(),
+        "eventBroker": opts.event_broker.as_ref().map(|u| u.as_str()),
+        "statusDebounce": duration(opts.status_debounce),
+        "minEncodingSpeed": opts.min_encoding_speed,
+        "enableFaultInjection": opts.enable_fault_injection,
+        "verbose": opts.verbose.map(|l| l.as_str()),
+    })
+}
+
+/// Redacts credentials of the given exported [`spec::v1::Spec`], which don't
+/// look like URLs and so are left intact by [`mask_json()`] (like the
+/// [`RtmpPullOptions::token_query`]).
+///
+/// [`RtmpPullOptions::token_query`]: crate::state::RtmpPullOptions::token_query
+pub fn redact_spec(spec: &mut spec::v1::Spec) {
+    for restream in &mut spec.restreams {
+        redact_input(&mut restream.input);
+    }
+}
+
+/// Redacts credentials of the given exported [`spec::v1::Input`] and all its
+/// failover [`spec::v1::Input`]s.
+fn redact_input(input: &mut spec::v1::Input) {
+    if let Some(query) = input
+        .rtmp_options
+        .as_mut()
+        .and_then(|o| o.token_query.as_mut())
+    {
+        *query = ffmpeg::mask_query(query);
+    }
+    if let Some(spec::v1::InputSrc::FailoverInputs(inputs)) = &mut input.src {
+        inputs.iter_mut().for_each(redact_input);
+    }
+}
+
+/// Masks credentials in every string of the given JSON `val` with
+/// [`mask_text()`].
+pub fn mask_json(val: &mut Value) {
+    match val {
+        Value::String(s) => *s = mask_text(s),
+        Value::Array(vals) => vals.iter_mut().for_each(mask_json),
+        Value::Object(map) => map.values_mut().for_each(mask_json),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Masks credentials in every whitespace-separated word of the given free-form
+/// `text` (like a log line) with [`ffmpeg::mask_secrets()`].
+///
+/// URLs are looked up inside words, so the ones quoted or prefixed (like
+/// `'rtmp://...'` or `dst=rtmp://...`) are masked too.
+#[must_use]
+pub fn mask_text(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        masked.push_str(&rest[..word]);
+        rest = &rest[word..];
+
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        masked.push_str(&mask_word(&rest[..end]));
+        rest = &rest[end..];
+    }
+    masked
+}
+
+/// Masks credentials in the given whitespace-free `word` with
+/// [`ffmpeg::mask_secrets()`], ignoring its leading scheme-less part and
+/// trailing punctuation.
+fn mask_word(word: &str) -> Cow<'_, str> {
+    let is_scheme = |c: char| c.is_ascii_alphanumeric() || "+-.".contains(c);
+    let start = word.find("://").map_or(0, |i| {
+        word[..i]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_scheme(c))
+            .map_or(0, |(j, c)| j + c.len_utf8())
+    });
+    let secret =
+        word[start..].trim_end_matches(|c: char| "'\"()[]<>,;:".contains(c));
+    let end = start + secret.len();
+    match ffmpeg::mask_secrets(secret) {
+        Cow::Borrowed(_) => Cow::Borrowed(word),
+        Cow::Owned(masked) => {
+            Cow::Owned(format!("{}{}{}", &word[..start], masked, &word[end..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod mask_text_spec {
+    use super::mask_text;
+
+    #[test]
+    fn masks_quoted_and_prefixed_urls() {
+        assert_eq!(
+            mask_text(
+                "Opening 'rtmp://a.rtmp.youtube.com/live2/abc-def' for \
+                 writing",
+            ),
+            "Opening 'rtmp://a.rtmp.youtube.com/live2/***' for writing",
+        );
+        assert_eq!(
+            mask_text("dst=rtmp://live.example.com/app/abc-def: I/O error"),
+            "dst=rtmp://live.example.com/app/***: I/O error",
+        );
+    }
+
+    #[test]
+    fn masks_query_parameters() {
+        assert_eq!(
+            mask_text("  playpath stream?token=abc-def  "),
+            "  playpath stream?token=***  ",
+        );
+    }
+
+    #[test]
+    fn leaves_text_without_credentials_as_is() {
+        let text = "Stream #0:0: Video: h264, 1920x1080, rtmp://127.0.0.1/a/b";
+        assert_eq!(mask_text(text), text);
+    }
+}
+
+#[cfg(test)]
+mod report_spec {
+    use std::io::Read as _;
+
+    use flate2::read::GzDecoder;
+    use serde_json::json;
+    use structopt::StructOpt as _;
+
+    use crate::{
+        api::graphql::DateTime,
+        cli::Opts,
+        extension::{EntityKind, StatusEvent},
+        spec,
+        state::{PoolProcess, State, Status},
+    };
+
+    use super::Report;
+
+    const KEY: &str = "sEcReT-sTrEaM-kEy";
+
+    const YOUTUBE: &str = "rtmp://a.rtmp.youtube.com/live2/";
+
+    const SRT: &str = "srt://live.example.com:9000?streamid=";
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "main",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "enabled": true,
+                    },
+                    "outputs": [{
+                        "dst": format!("{}{}", YOUTUBE, KEY),
+                        "label": "YouTube",
+                        "enabled": true,
+                    }, {
+                        "dst": format!("{}{}", SRT, KEY),
+                        "enabled": true,
+                    }],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "pulled",
+                    "input": {
+                        "key": "primary",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "src": {"failover_inputs": [{
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "src": {"remote_url": format!(
+                                "rtmp://origin.example.com/live/s?token={}",
+                                KEY,
+                            )},
+                            "enabled": true,
+                        }, {
+                            "key": "cdn",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "src": {
+                                "remote_url": "rtmp://cdn.example.com/live/s",
+                            },
+                            "enabled": true,
+                            "rtmp_options": {
+                                "token_query": format!("token={}&e=1", KEY),
+                            },
+                        }]},
+                        "enabled": true,
+                    },
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+    }
+
+    fn unpack(archive: &[u8]) -> Vec<(String, String)> {
+        let mut tar = tar::Archive::new(GzDecoder::new(archive));
+        tar.entries()
+            .unwrap()
+            .map(|e| {
+                let mut e = e.unwrap();
+                let path = e.path().unwrap().display().to_string();
+                let mut data = String::new();
+                let _ = e.read_to_string(&mut data).unwrap();
+                (path, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn contains_no_unmasked_stream_keys() {
+        let state = state();
+        let restream = state.restreams.get_cloned().remove(0);
+        let mut report = Report::collect(
+            &state,
+            &Opts::from_iter(&["ephyr-restreamer"]),
+            json!({"info": {
+                "replication": {"source": format!("http://x/?t={}", KEY)},
+            }}),
+        );
+        report.pool.push(PoolProcess {
+            key: restream.outputs[0].id.to_string(),
+            kind: "copy".into(),
+            command: vec![
+                "ffmpeg".into(),
+                "-f".into(),
+                "flv".into(),
+                restream.outputs[0].dst.to_string(),
+            ],
+            pid: None,
+            spawned_at: Some(DateTime::now()),
+            restarts: 0,
+            backoff_secs: 0,
+            last_exit: Some(format!(
+                "[rtmp @ 0x1] Server error: {}{}",
+                YOUTUBE, KEY,
+            )),
+            limited_by: vec![],
+        });
+        report.events.push(StatusEvent {
+            kind: EntityKind::Output,
+            id: restream.outputs[1].id.into(),
+            restream_id: restream.id,
+            restream_key: restream.key.clone(),
+            label: None,
+            previous: Status::Offline,
+            status: Status::Online,
+            at: DateTime::now(),
+        });
+        report
+            .logs
+            .push(format!("Opening '{}{}' for writing", YOUTUBE, KEY));
+        report
+            .logs
+            .push(format!("[tee @ 0x2] Slave '{}{}': I/O error", SRT, KEY));
+
+        let entries = unpack(&report.to_tar_gz().unwrap());
+
+        let paths: Vec<_> = entries.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "info.json",
+                "state.json",
+                "pool.json",
+                "events.json",
+                "logs.txt",
+                "config.json",
+            ],
+        );
+        for (path, data) in &entries {
+            assert!(!data.contains(KEY), "unmasked key in {}:\n{}", path, data);
+        }
+        let state = &entries[1].1;
+        assert!(state.contains(&format!("{}***", YOUTUBE)));
+        assert!(state.contains("YouTube"));
+        assert!(state.contains("token=***&e=***"));
+    }
+
+    #[test]
+    fn cuts_off_oversized_entries() {
+        let mut report = Report::collect(
+            &State::new_in_memory(),
+            &Opts::from_iter(&["ephyr-restreamer"]),
+            json!(null),
+        );
+        report.logs = vec!["x".repeat(1024); 5 * 1024];
+
+        let entries = unpack(&report.to_tar_gz().unwrap());
+
+        let logs = &entries[4].1;
+        assert_eq!(logs.len(), super::MAX_ENTRY_SIZE);
+        assert!(logs.ends_with("... truncated\n"));
+    }
+}
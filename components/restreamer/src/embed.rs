@@ -0,0 +1,406 @@
+//! Embedding of this server into another application.
+//!
+//! The server is run on its own dedicated thread with its own async runtime,
+//! so doesn't require the embedding application to use any particular one:
+//! ```no_run
+//! # use ephyr_restreamer::Restreamer;
+//! # use futures::future;
+//! let handle = Restreamer::builder()
+//!     .state_path("/var/lib/ephyr/state.json")
+//!     .ffmpeg_path("/usr/bin/ffmpeg")
+//!     .client_addr(([127, 0, 0, 1], 0).into())
+//!     .build()
+//!     .run(future::pending())
+//!     .expect("Failed to run restreamer");
+//!
+//! println!("Serving on {}", handle.client_addr());
+//! handle.shutdown().expect("Restreamer has failed");
+//! ```
+//!
+//! Logging is up to the embedding application (see [`ephyr_log::init()`]),
+//! all the logs are discarded otherwise.
+//!
+//! Some parts of the server (like [`dvr::Storage`]) are process-global, so
+//! only a single [`Restreamer`] may be run per process.
+//!
+//! [`dvr::Storage`]: crate::dvr::Storage
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use ephyr_log::log;
+use futures::{
+    channel::oneshot,
+    future::{self, FutureExt as _},
+};
+
+use crate::{
+    cli::{Failure, Opts},
+    display_panic,
+    server::{self, Running},
+    State,
+};
+
+/// Ephyr re-streaming server to be embedded into another application.
+///
+/// Use [`Restreamer::builder()`] to configure it.
+#[derive(Clone, Debug)]
+pub struct Restreamer {
+    /// [`Builder`] this [`Restreamer`] has been configured with.
+    cfg: Builder,
+}
+
+impl Restreamer {
+    /// Starts configuring a new [`Restreamer`].
+    #[inline]
+    #[must_use]
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Runs this [`Restreamer`] on a dedicated thread, until the given
+    /// `shutdown_signal` resolves or [`Handle::shutdown()`] is called.
+    ///
+    /// Blocks until all the HTTP servers are bound and ready to accept
+    /// connections.
+    ///
+    /// # Errors
+    ///
+    /// If the server fails to start. The actual error is logged.
+    pub fn run<S>(self, shutdown_signal: S) -> Result<Handle, Failure>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        let cfg = self.cfg.into_opts();
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("ephyr-restreamer".into())
+            .spawn(move || {
+                // Dropped `Handle` shuts the server down as well.
+                let shutdown = future::select(stop_rx, shutdown_signal.boxed())
+                    .map(|_| ())
+                    .boxed_local();
+                server::run_with(cfg, Some(shutdown), move |running| {
+                    let _ = started_tx.send(running);
+                })
+            })
+            .map_err(|e| log::error!("Failed to spawn server thread: {}", e))?;
+
+        match started_rx.recv() {
+            Ok(running) => Ok(Handle {
+                running,
+                stop: stop_tx,
+                thread,
+            }),
+            // Server has exited before being started, so its result explains
+            // why.
+            Err(_) => join(thread).and(Err(Failure)),
+        }
+    }
+}
+
+/// Builder of a [`Restreamer`].
+///
+/// Any option not specified explicitly defaults to the one of
+/// [`Opts::default()`], regardless of environment variables.
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    /// Base [`Opts`] to apply the other options onto.
+    opts: Option<Opts>,
+
+    /// Path to a file to persist the server's state in.
+    state_path: Option<PathBuf>,
+
+    /// Indicator whether the server's state should be kept in memory only.
+    no_persist: Option<bool>,
+
+    /// Path to [FFmpeg] binary.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    ffmpeg_path: Option<PathBuf>,
+
+    /// Path to [FFprobe] binary.
+    ///
+    /// [FFprobe]: https://ffmpeg.org/ffprobe.html
+    ffprobe_path: Option<PathBuf>,
+
+    /// Path to [SRS] installation directory.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    srs_path: Option<PathBuf>,
+
+    /// Path to the directory served by [SRS] HTTP server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    srs_http_dir: Option<PathBuf>,
+
+    /// Path to the directory to store overlay images of outputs in.
+    overlays_dir: Option<PathBuf>,
+
+    /// Path to the directory to store rolling buffers of delayed outputs in.
+    buffers_dir: Option<PathBuf>,
+
+    /// Address for the client HTTP server to listen on.
+    client_addr: Option<SocketAddr>,
+
+    /// Address for the callback HTTP server to listen on.
+    callback_addr: Option<SocketAddr>,
+
+    /// Public host of the server.
+    public_host: Option<String>,
+}
+
+impl Builder {
+    /// Sets the base [`Opts`] for the other options of this [`Builder`] to
+    /// be applied onto, instead of the default ones.
+    #[inline]
+    #[must_use]
+    pub fn opts(mut self, opts: Opts) -> Self {
+        self.opts = Some(opts);
+        self
+    }
+
+    /// Sets path to a file to persist the server's state in.
+    ///
+    /// See [`Opts::state_path`] for details.
+    #[inline]
+    #[must_use]
+    pub fn state_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.state_path = Some(path.into());
+        self
+    }
+
+    /// Sets whether the server's state should be kept in memory only, without
+    /// being persisted into the [`Builder::state_path()`] file.
+    ///
+    /// See [`Opts::no_persist`] for details.
+    #[inline]
+    #[must_use]
+    pub fn no_persist(mut self, no_persist: bool) -> Self {
+        self.no_persist = Some(no_persist);
+        self
+    }
+
+    /// Sets path to [FFmpeg] binary.
+    ///
+    /// See [`Opts::ffmpeg_path`] for details.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[inline]
+    #[must_use]
+    pub fn ffmpeg_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.ffmpeg_path = Some(path.into());
+        self
+    }
+
+    /// Sets path to [FFprobe] binary.
+    ///
+    /// See [`Opts::ffprobe_path`] for details.
+    ///
+    /// [FFprobe]: https://ffmpeg.org/ffprobe.html
+    #[inline]
+    #[must_use]
+    pub fn ffprobe_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.ffprobe_path = Some(path.into());
+        self
+    }
+
+    /// Sets path to [SRS] installation directory.
+    ///
+    /// See [`Opts::srs_path`] for details.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn srs_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.srs_path = Some(path.into());
+        self
+    }
+
+    /// Sets path to the directory served by [SRS] HTTP server.
+    ///
+    /// See [`Opts::srs_http_dir`] for details.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn srs_http_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.srs_http_dir = Some(path.into());
+        self
+    }
+
+    /// Sets path to the directory to store overlay images of outputs in.
+    ///
+    /// See [`Opts::overlays_dir`] for details.
+    #[inline]
+    #[must_use]
+    pub fn overlays_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.overlays_dir = Some(path.into());
+        self
+    }
+
+    /// Sets path to the directory to store rolling buffers of delayed outputs
+    /// in.
+    ///
+    /// See [`Opts::buffers_dir`] for details.
+    #[inline]
+    #[must_use]
+    pub fn buffers_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.buffers_dir = Some(path.into());
+        self
+    }
+
+    /// Sets address for the client HTTP server to listen on.
+    ///
+    /// Zero port requests an ephemeral one, which is reported by
+    /// [`Handle::client_addr()`] then.
+    #[inline]
+    #[must_use]
+    pub fn client_addr(mut self, addr: SocketAddr) -> Self {
+        self.client_addr = Some(addr);
+        self
+    }
+
+    /// Sets address for the callback HTTP server to listen on.
+    ///
+    /// Zero port requests an ephemeral one, which is reported by
+    /// [`Handle::callback_addr()`] then.
+    #[inline]
+    #[must_use]
+    pub fn callback_addr(mut self, addr: SocketAddr) -> Self {
+        self.callback_addr = Some(addr);
+        self
+    }
+
+    /// Sets public host of the server, disabling its detection.
+    ///
+    /// See [`Opts::public_host`] for details.
+    #[inline]
+    #[must_use]
+    pub fn public_host<H: Into<String>>(mut self, host: H) -> Self {
+        self.public_host = Some(host.into());
+        self
+    }
+
+    /// Builds a [`Restreamer`] out of this [`Builder`].
+    #[inline]
+    #[must_use]
+    pub fn build(self) -> Restreamer {
+        Restreamer { cfg: self }
+    }
+
+    /// Resolves the [`Opts`] specified by this [`Builder`].
+    ///
+    /// Unlike the [`Opts`] of the binary, these are never affected by CLI
+    /// arguments or environment variables of the embedding process.
+    fn into_opts(self) -> Opts {
+        let mut opts = self.opts.unwrap_or_default();
+        if let Some(path) = self.state_path {
+            opts.state_path = path;
+        }
+        if let Some(no_persist) = self.no_persist {
+            opts.no_persist = no_persist;
+        }
+        if let Some(path) = self.ffmpeg_path {
+            opts.ffmpeg_path = path;
+        }
+        if let Some(path) = self.ffprobe_path {
+            opts.ffprobe_path = path;
+        }
+        if let Some(path) = self.srs_path {
+            opts.srs_path = path;
+        }
+        if let Some(path) = self.srs_http_dir {
+            opts.srs_http_dir = path;
+        }
+        if let Some(path) = self.overlays_dir {
+            opts.overlays_dir = path;
+        }
+        if let Some(path) = self.buffers_dir {
+            opts.buffers_dir = path;
+        }
+        if let Some(addr) = self.client_addr {
+            opts.client_http_ip = addr.ip();
+            opts.client_http_port = addr.port();
+        }
+        if let Some(addr) = self.callback_addr {
+            opts.callback_http_ip = addr.ip();
+            opts.callback_http_port = addr.port();
+        }
+        if let Some(host) = self.public_host {
+            opts.public_host = Some(host);
+        }
+        opts
+    }
+}
+
+/// Handle to a running [`Restreamer`].
+///
+/// Dropping it shuts the [`Restreamer`] down without waiting for it.
+#[derive(Debug)]
+pub struct Handle {
+    /// Information about the running HTTP servers.
+    running: Running,
+
+    /// Sender of the signal to shut the [`Restreamer`] down.
+    stop: oneshot::Sender<()>,
+
+    /// Thread the [`Restreamer`] is running on.
+    thread: JoinHandle<Result<(), Failure>>,
+}
+
+impl Handle {
+    /// Returns [`State`] of the running [`Restreamer`].
+    #[inline]
+    #[must_use]
+    pub fn state(&self) -> &State {
+        &self.running.state
+    }
+
+    /// Returns address the client HTTP server is actually bound to.
+    #[inline]
+    #[must_use]
+    pub fn client_addr(&self) -> SocketAddr {
+        self.running.client_addr
+    }
+
+    /// Returns address the callback HTTP server is actually bound to, if
+    /// there is a separate one (see [`Opts::single_port`]).
+    #[inline]
+    #[must_use]
+    pub fn callback_addr(&self) -> Option<SocketAddr> {
+        self.running.callback_addr
+    }
+
+    /// Shuts the running [`Restreamer`] down gracefully, waiting for all its
+    /// HTTP servers to finish serving the pending requests.
+    ///
+    /// # Errors
+    ///
+    /// If the [`Restreamer`] has failed while running. The actual error is
+    /// logged.
+    pub fn shutdown(self) -> Result<(), Failure> {
+        // The `Restreamer` may have been shut down by its signal already.
+        let _ = self.stop.send(());
+        join(self.thread)
+    }
+}
+
+/// Waits for the given server `thread` to finish, returning its result.
+///
+/// # Errors
+///
+/// If the server has failed or panicked. The actual error is logged.
+fn join(thread: JoinHandle<Result<(), Failure>>) -> Result<(), Failure> {
+    thread.join().unwrap_or_else(|p| {
+        log::crit!("Server thread has panicked: {}", display_panic(&p));
+        Err(Failure)
+    })
+}
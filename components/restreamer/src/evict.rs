@@ -0,0 +1,346 @@
+//! Evicting [SRS] clients of disabled [`state::Input`]s.
+//!
+//! Disabling a [`state::Input`] forgets the [SRS] clients known to it (kicking
+//! them), but [SRS] may still hold clients which this application hasn't
+//! remembered (being connected in the middle of disabling, for example). Such
+//! clients keep receiving the buffered tail of the live stream, so the
+//! re-streaming processes consuming it don't fail fast and their statuses
+//! linger. That's why [SRS] is asked directly for all the clients of a freshly
+//! disabled [`state::Input`] to kick them.
+//!
+//! [SRS]: https://github.com/ossrs/srs
+
+use std::{collections::HashSet, sync::Arc};
+
+use ephyr_log::log;
+use futures::future::{self, BoxFuture, FutureExt as _};
+
+use crate::{
+    api::srs::{self as srs_api, ClientInfo},
+    state,
+};
+
+/// `app` and `stream` of a live stream served by [SRS].
+///
+/// [SRS]: https://github.com/ossrs/srs
+type AppStream = (String, String);
+
+/// [HTTP API][1] of [SRS] server, used for evicting its clients.
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+pub trait SrsApi: Send + Sync {
+    /// Lists all the clients connected to [SRS] server at the moment.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn clients(&self)
+        -> BoxFuture<'_, Result<Vec<ClientInfo>, srs_api::Error>>;
+
+    /// Kicks off a client connected to [SRS] server by its `id`.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn kickoff_client(
+        &self,
+        id: String,
+    ) -> BoxFuture<'_, Result<(), srs_api::Error>>;
+}
+
+impl SrsApi for srs_api::Client {
+    #[inline]
+    fn clients(
+        &self,
+    ) -> BoxFuture<'_, Result<Vec<ClientInfo>, srs_api::Error>> {
+        Self::clients().boxed()
+    }
+
+    #[inline]
+    fn kickoff_client(
+        &self,
+        id: String,
+    ) -> BoxFuture<'_, Result<(), srs_api::Error>> {
+        Self::kickoff_client(id).boxed()
+    }
+}
+
+/// Watcher of [`state::Input`]s, evicting all the [SRS] clients of the ones
+/// which have become disabled.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Debug)]
+pub struct DisabledInputsEvictor<A> {
+    /// [`SrsApi`] to evict [SRS] clients via.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    api: Arc<A>,
+
+    /// Disabled [`state::Input`]s seen on the previous
+    /// [`DisabledInputsEvictor::apply()`] call.
+    ///
+    /// [`None`] if there was no call yet.
+    disabled: Option<HashSet<AppStream>>,
+}
+
+impl<A: SrsApi + 'static> DisabledInputsEvictor<A> {
+    /// Creates a new [`DisabledInputsEvictor`] evicting [SRS] clients via the
+    /// given [`SrsApi`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn new(api: A) -> Self {
+        Self {
+            api: Arc::new(api),
+            disabled: None,
+        }
+    }
+
+    /// Evicts [SRS] clients of the [`state::Input`]s of the given `restreams`
+    /// which have become disabled since the previous call.
+    ///
+    /// The first call only remembers the disabled [`state::Input`]s, as they
+    /// have been disabled before this application has started.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let mut disabled = HashSet::new();
+        for r in restreams {
+            collect_disabled(&r.key, &r.input, &mut disabled);
+        }
+
+        if let Some(prev) = &self.disabled {
+            let fresh: HashSet<_> =
+                disabled.difference(prev).cloned().collect();
+            if !fresh.is_empty() {
+                let api = Arc::clone(&self.api);
+                drop(tokio::spawn(async move {
+                    let _ = evict(&*api, &fresh).await;
+                }));
+            }
+        }
+        self.disabled = Some(disabled);
+    }
+}
+
+/// Collects `app` and `stream` of the given [`state::Input`] and all its
+/// [`state::FailoverInputSrc::inputs`] being disabled into the given `out`.
+fn collect_disabled(
+    app: &str,
+    input: &state::Input,
+    out: &mut HashSet<AppStream>,
+) {
+    if !input.enabled {
+        let _ = out.insert((app.to_owned(), input.key.to_string()));
+    }
+    if let Some(state::InputSrc::Failover(s)) = &input.src {
+        for i in &s.inputs {
+            collect_disabled(app, i, out);
+        }
+    }
+}
+
+/// Kicks off all the [SRS] clients connected to any of the given `targets`.
+///
+/// Returns the number of the kicked clients.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub async fn evict<A: SrsApi + ?Sized>(
+    api: &A,
+    targets: &HashSet<AppStream>,
+) -> usize {
+    let clients = match api.clients().await {
+        Ok(c) => c,
+        Err(e) => {
+            log::warn!("Failed to list SRS clients for evicting: {}", e);
+            return 0;
+        }
+    };
+    let kicks: Vec<_> = clients
+        .into_iter()
+        .filter(|c| {
+            c.app_stream().map_or(false, |(app, stream)| {
+                targets.contains(&(app.to_owned(), stream.to_owned()))
+            })
+        })
+        .map(|c| async move {
+            let res = api.kickoff_client(c.id.clone()).await;
+            if let Err(e) = &res {
+                log::warn!("Failed to kickoff client {} from SRS: {}", c.id, e);
+            }
+            res.is_ok()
+        })
+        .collect();
+    future::join_all(kicks)
+        .await
+        .into_iter()
+        .filter(|kicked| *kicked)
+        .count()
+}
+
+#[cfg(test)]
+mod evictor_spec {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use actix_web::rt::System;
+    use futures::future::{BoxFuture, FutureExt as _};
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::{
+        api::srs::{self as srs_api, ClientInfo},
+        spec,
+        state::{DisableReason, State},
+    };
+
+    use super::{DisabledInputsEvictor, SrsApi};
+
+    /// Mocked [`SrsApi`], responding with a delay, like a real one does.
+    #[derive(Debug)]
+    struct MockApi {
+        /// Clients connected to the mocked [SRS] server.
+        ///
+        /// [SRS]: https://github.com/ossrs/srs
+        clients: Vec<ClientInfo>,
+
+        /// IDs of the kicked clients.
+        kicked: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// Delay of every response of [`MockApi`].
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    impl SrsApi for MockApi {
+        fn clients(
+            &self,
+        ) -> BoxFuture<'_, Result<Vec<ClientInfo>, srs_api::Error>> {
+            async move {
+                time::delay_for(LATENCY).await;
+                Ok(self.clients.clone())
+            }
+            .boxed()
+        }
+
+        fn kickoff_client(
+            &self,
+            id: String,
+        ) -> BoxFuture<'_, Result<(), srs_api::Error>> {
+            async move {
+                time::delay_for(LATENCY).await;
+                self.kicked.lock().unwrap().push(id);
+                Ok(())
+            }
+            .boxed()
+        }
+    }
+
+    fn client(id: &str, url: &str) -> ClientInfo {
+        ClientInfo {
+            id: id.into(),
+            url: url.into(),
+            publish: false,
+        }
+    }
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        for key in &["guest", "other"] {
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": key,
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "enabled": true,
+                        },
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn kicks_clients_of_disabled_input_promptly() {
+        System::new("test").block_on(async {
+            let state = state();
+            let kicked = Arc::new(Mutex::new(vec![]));
+            let mut evictor = DisabledInputsEvictor::new(MockApi {
+                clients: vec![
+                    client("1", "/guest/origin"),
+                    client("2", "/other/origin"),
+                    client("3", "/guest/origin.flv"),
+                    client("4", "/guest"),
+                ],
+                kicked: Arc::clone(&kicked),
+            });
+            evictor.apply(&state.restreams.lock_ref());
+
+            let (restream_id, input_id) = {
+                let restreams = state.restreams.lock_ref();
+                let r = restreams.iter().find(|r| r.key == *"guest").unwrap();
+                (r.id, r.input.id)
+            };
+            assert_eq!(
+                state.disable_input(
+                    input_id,
+                    restream_id,
+                    DisableReason::default(),
+                ),
+                Some(true)
+            );
+            let disabled_at = Instant::now();
+            evictor.apply(&state.restreams.lock_ref());
+
+            while kicked.lock().unwrap().len() < 2
+                && disabled_at.elapsed() < Duration::from_secs(1)
+            {
+                time::delay_for(Duration::from_millis(5)).await;
+            }
+            let elapsed = disabled_at.elapsed();
+            let mut ids = kicked.lock().unwrap().clone();
+            ids.sort();
+            assert_eq!(ids, vec!["1", "3"]);
+            assert!(
+                elapsed < Duration::from_millis(500),
+                "evicted in {:?}",
+                elapsed,
+            );
+
+            // Nothing is kicked again, unless disabled anew.
+            evictor.apply(&state.restreams.lock_ref());
+            time::delay_for(LATENCY * 4).await;
+            assert_eq!(kicked.lock().unwrap().len(), 2);
+        });
+    }
+
+    #[test]
+    fn ignores_inputs_disabled_before_start() {
+        System::new("test").block_on(async {
+            let state = state();
+            {
+                let mut restreams = state.restreams.lock_mut();
+                let mut statuses = state
+                    .statuses
+                    .lock_mut("ignores_inputs_disabled_before_start");
+                for r in restreams.iter_mut() {
+                    let _ = r
+                        .input
+                        .disable(DisableReason::default(), &mut statuses);
+                }
+            }
+            let kicked = Arc::new(Mutex::new(vec![]));
+            let mut evictor = DisabledInputsEvictor::new(MockApi {
+                clients: vec![client("1", "/guest/origin")],
+                kicked: Arc::clone(&kicked),
+            });
+
+            evictor.apply(&state.restreams.lock_ref());
+            time::delay_for(LATENCY * 4).await;
+            assert!(kicked.lock().unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,255 @@
+//! Enforcing of [`state::Restream::max_session_secs`].
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use ephyr_log::log;
+
+use crate::{
+    state::{
+        self, ConnectionEvent, ConnectionEventKind, DisableReason,
+        DisableReasonKind, EndpointId, InputId, RestreamId, State, Status,
+    },
+    timer::Timers,
+    trace,
+};
+
+/// Watcher of [`state::Restream`]s with [`state::Restream::max_session_secs`]
+/// specified, kicking external clients publishing into their
+/// [`state::Input`]s once their [`state::PublishingSession`]s last longer.
+///
+/// Each [`state::PublishingSession`] has its own [`Timer`] till its
+/// expiration, dropped as soon as the session ends, so a publisher
+/// reconnecting starts a new session with a fresh limit.
+///
+/// [`Timer`]: crate::timer::Timer
+#[derive(Debug)]
+pub struct ExpiringSessionsWatcher {
+    /// Running timers of [`state::PublishingSession`]s, along with the IDs of
+    /// their [`state::Input`]s and the moments they expire at.
+    timers: Timers<EndpointId, (RestreamId, InputId, SystemTime)>,
+}
+
+impl ExpiringSessionsWatcher {
+    /// Creates a new [`ExpiringSessionsWatcher`] operating on the given
+    /// [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            timers: Timers::new(state),
+        }
+    }
+
+    /// Starts timers for the [`state::PublishingSession`]s of the given
+    /// `restreams` which have begun since the previous call, and drops the
+    /// ones of the [`state::PublishingSession`]s which have ended.
+    ///
+    /// Changing [`state::Restream::max_session_secs`] of a running
+    /// [`state::PublishingSession`] restarts its timer.
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let mut running = HashMap::new();
+        for r in restreams {
+            let max = match r.max_session_secs {
+                Some(secs) => Duration::from_secs(secs.into()),
+                None => continue,
+            };
+            let mut sessions = vec![];
+            r.input.collect_sessions(&mut sessions);
+            running.extend(sessions.into_iter().map(|(input, endpoint, s)| {
+                (endpoint, (r.id, input, s.started_at + max))
+            }));
+        }
+
+        self.timers.rearm(
+            running,
+            |(_, _, deadline)| *deadline,
+            |state, endpoint_id, (restream_id, input_id, _)| {
+                expire(state, restream_id, input_id, endpoint_id);
+            },
+        );
+    }
+}
+
+/// Expires the [`state::PublishingSession`] of the [`state::InputEndpoint`]
+/// with the given IDs in the `actual` [`State`]: kicks its publisher, records
+/// a [`ConnectionEventKind::SessionExpiry`], and disables the
+/// [`state::Input`] if [`state::Restream::disable_on_expiry`] is set.
+///
+/// Does nothing if the [`state::PublishingSession`] hasn't exceeded
+/// [`state::Restream::max_session_secs`] (or has ended already).
+fn expire(
+    actual: &State,
+    restream_id: RestreamId,
+    input_id: InputId,
+    endpoint_id: EndpointId,
+) {
+    let mut restreams = trace::lock_mut(&actual.restreams, "expire");
+    let restream = match restreams.iter_mut().find(|r| r.id == restream_id) {
+        Some(r) => r,
+        None => return,
+    };
+    let max = match restream.max_session_secs {
+        Some(secs) => Duration::from_secs(secs.into()),
+        None => return,
+    };
+    let disable = restream.disable_on_expiry;
+    let input = match restream.input.find_mut(input_id) {
+        Some(i) => i,
+        None => return,
+    };
+    let endpoint =
+        match input.endpoints.iter_mut().find(|e| e.id == endpoint_id) {
+            Some(e) => e,
+            None => return,
+        };
+    let session = match endpoint.publishing_session {
+        Some(s) if s.started_at + max <= SystemTime::now() => s,
+        _ => return,
+    };
+
+    let reason =
+        format!("Session exceeded its maximum of {} seconds", max.as_secs());
+    // `srs::ClientId` kicks the client when `Drop`ped.
+    endpoint.srs_publisher_id = None;
+    endpoint.publishing_session = None;
+    endpoint.offline_reason = Some(reason.clone());
+    let mut statuses = actual.statuses.lock_mut("expire");
+    let _ = statuses.set(endpoint_id, Status::Offline);
+    if disable {
+        let _ = input.disable(
+            DisableReason::new(
+                DisableReasonKind::SessionExpiry,
+                reason.clone(),
+                "session expiry watcher",
+            ),
+            &mut statuses,
+        );
+    }
+    drop(statuses);
+    let input_key = input.key.to_string();
+
+    actual.connection_history.record(
+        restream_id,
+        ConnectionEvent::new(
+            ConnectionEventKind::SessionExpiry,
+            session.client_ip,
+            Some(input_key.as_str()),
+            Some(reason),
+        ),
+    );
+    log::info!(
+        "Kicked {} client from `{}` app after publishing for {}s",
+        session.client_ip,
+        restream.key,
+        max.as_secs(),
+    );
+}
+
+#[cfg(test)]
+mod expire_spec {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, SystemTime},
+    };
+
+    use serde_json::json;
+
+    use crate::{
+        spec,
+        state::{
+            ConnectionEventKind, DisableReasonKind, EndpointId, InputId,
+            PublishingSession, RestreamId, State, Status,
+        },
+    };
+
+    use super::expire;
+
+    fn state(disable_on_expiry: bool, elapsed: Duration) -> State {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "guest",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "enabled": true,
+                    },
+                    "max_session_secs": 60,
+                    "disable_on_expiry": disable_on_expiry,
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        {
+            let mut restreams = state.restreams.lock_mut();
+            let endpoint = &mut restreams[0].input.endpoints[0];
+            let _ = state.statuses.set(endpoint.id, Status::Online);
+            endpoint.publishing_session = Some(PublishingSession {
+                started_at: SystemTime::now() - elapsed,
+                client_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            });
+        }
+        state
+    }
+
+    fn ids(state: &State) -> (RestreamId, InputId, EndpointId) {
+        let restreams = state.restreams.lock_ref();
+        let r = &restreams[0];
+        (r.id, r.input.id, r.input.endpoints[0].id)
+    }
+
+    #[test]
+    fn kicks_expired_session() {
+        let state = state(false, Duration::from_secs(61));
+        let (restream_id, input_id, endpoint_id) = ids(&state);
+
+        expire(&state, restream_id, input_id, endpoint_id);
+
+        let restreams = state.restreams.lock_ref();
+        let restream = &restreams[0];
+        let endpoint = &restream.input.endpoints[0];
+        assert_eq!(state.statuses.get(endpoint_id), Status::Offline);
+        assert!(endpoint.publishing_session.is_none());
+        assert!(restream.input.enabled);
+        let history = state.connection_history.recent(restream_id, 1);
+        let event = &history[0];
+        assert_eq!(event.kind, ConnectionEventKind::SessionExpiry);
+        assert_eq!(event.client_ip, "10.0.0.1");
+    }
+
+    #[test]
+    fn disables_input_on_expiry_if_required() {
+        let state = state(true, Duration::from_secs(61));
+        let (restream_id, input_id, endpoint_id) = ids(&state);
+
+        expire(&state, restream_id, input_id, endpoint_id);
+
+        let restreams = state.restreams.lock_ref();
+        assert!(!restreams[0].input.enabled);
+        assert_eq!(
+            restreams[0].input.disabled_reason.as_ref().map(|r| r.kind),
+            Some(DisableReasonKind::SessionExpiry),
+        );
+    }
+
+    #[test]
+    fn keeps_fresh_session() {
+        let state = state(true, Duration::from_secs(10));
+        let (restream_id, input_id, endpoint_id) = ids(&state);
+
+        expire(&state, restream_id, input_id, endpoint_id);
+
+        let restreams = state.restreams.lock_ref();
+        let endpoint = &restreams[0].input.endpoints[0];
+        assert_eq!(state.statuses.get(endpoint_id), Status::Online);
+        assert!(endpoint.publishing_session.is_some());
+        assert!(state
+            .connection_history
+            .recent(restream_id, usize::MAX)
+            .is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+//! Extension point for running custom background tasks along with the server,
+//! without forking it.
+//!
+//! An [`Extension`] is run by [`server::run_with_extensions()`], being given
+//! a [`Context`] with the [`State`], the streams of its changes, and a
+//! [`ShutdownToken`]:
+//! ```no_run
+//! # use ephyr_restreamer::{
+//! #     cli::Opts,
+//! #     extension::{Context, Extension},
+//! #     server,
+//! # };
+//! # use futures::{future::BoxFuture, FutureExt as _, StreamExt as _};
+//! # use structopt::StructOpt as _;
+//! #[derive(Debug)]
+//! struct Notifier;
+//!
+//! impl Extension for Notifier {
+//!     fn name(&self) -> &str {
+//!         "notifier"
+//!     }
+//!
+//!     fn run(self: Box<Self>, ctx: Context) -> BoxFuture<'static, ()> {
+//!         let shutdown = ctx.shutdown.wait();
+//!         let mut events = ctx.status_events.take_until(shutdown);
+//!         async move {
+//!             while let Some(ev) = events.next().await {
+//!                 println!("{:?} {} is {:?} now", ev.kind, ev.id, ev.status);
+//!             }
+//!         }
+//!         .boxed()
+//!     }
+//! }
+//!
+//! server::run_with_extensions(Opts::from_args(), vec![Box::new(Notifier)])
+//!     .expect("Server has failed");
+//! ```
+//!
+//! [`server::run_with_extensions()`]: crate::server::run_with_extensions
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use futures::{
+    future::{self, BoxFuture},
+    stream::{BoxStream, StreamExt as _},
+};
+use futures_signals::signal::{Mutable, SignalExt as _};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    api::graphql::DateTime,
+    state::{self, Label, RestreamId, RestreamKey, Settings, Status, Statuses},
+    State,
+};
+
+/// Maximum duration the server waits for its [`Extension`]s to finish on its
+/// shutdown.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Custom background task run along with the server (see
+/// [`server::run_with_extensions()`]).
+///
+/// [`server::run_with_extensions()`]: crate::server::run_with_extensions
+pub trait Extension: fmt::Debug + Send {
+    /// Returns the name of this [`Extension`], describing it in logs.
+    fn name(&self) -> &str;
+
+    /// Runs this [`Extension`] in background with the given [`Context`].
+    ///
+    /// The returned future should resolve soon after the
+    /// [`Context::shutdown`] is triggered, as the server waits for it no more
+    /// than [`SHUTDOWN_TIMEOUT`] on its shutdown.
+    fn run(self: Box<Self>, ctx: Context) -> BoxFuture<'static, ()>;
+}
+
+/// Everything an [`Extension`] is run with.
+pub struct Context {
+    /// [`State`] the server operates on.
+    pub state: State,
+
+    /// Changes of the [`Context::state`] (see [`State::subscribe_changes()`]
+    /// for their semantics).
+    pub changes: BoxStream<'static, StateSnapshot>,
+
+    /// [`StatusEvent`]s of the [`Context::state`] (see
+    /// [`State::subscribe_status_events()`] for their semantics).
+    pub status_events: BoxStream<'static, StatusEvent>,
+
+    /// [`ShutdownToken`] triggered once the server is shutting down.
+    pub shutdown: ShutdownToken,
+}
+
+impl Context {
+    /// Creates a new [`Context`] subscribed to the given [`State`].
+    ///
+    /// Must be called inside an async runtime, as spawns the subscriptions.
+    #[must_use]
+    pub fn new(state: &State, shutdown: ShutdownToken) -> Self {
+        Self {
+            state: state.clone(),
+            changes: state.subscribe_changes().boxed(),
+            status_events: state.subscribe_status_events().boxed(),
+            shutdown,
+        }
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("state", &self.state)
+            .field("shutdown", &self.shutdown)
+            .finish()
+    }
+}
+
+/// Trigger of [`ShutdownToken`]s, held by the server.
+#[derive(Debug, Default)]
+pub struct ShutdownTrigger(Mutable<bool>);
+
+impl ShutdownTrigger {
+    /// Returns a new [`ShutdownToken`] triggered by this [`ShutdownTrigger`].
+    #[inline]
+    #[must_use]
+    pub fn token(&self) -> ShutdownToken {
+        ShutdownToken(self.0.clone())
+    }
+
+    /// Triggers all the [`ShutdownToken`]s of this [`ShutdownTrigger`].
+    #[inline]
+    pub fn trigger(&self) {
+        self.0.set_neq(true);
+    }
+}
+
+/// Token notifying an [`Extension`] about the server shutting down.
+#[derive(Clone, Debug)]
+pub struct ShutdownToken(Mutable<bool>);
+
+impl ShutdownToken {
+    /// Indicates whether this [`ShutdownToken`] has been triggered already.
+    #[inline]
+    #[must_use]
+    pub fn is_triggered(&self) -> bool {
+        self.0.get()
+    }
+
+    /// Resolves once this [`ShutdownToken`] is triggered.
+    pub async fn wait(self) {
+        let _ = self
+            .0
+            .signal()
+            .to_stream()
+            .filter(|triggered| future::ready(*triggered))
+            .next()
+            .await;
+    }
+}
+
+/// Snapshot of the persisted parts of a [`State`].
+///
+/// Is serialized in the same way the [`State`] is persisted.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct StateSnapshot {
+    /// [`State::settings`] at the moment of this [`StateSnapshot`].
+    pub settings: Settings,
+
+    /// [`State::restreams`] at the moment of this [`StateSnapshot`].
+    pub restreams: Vec<state::Restream>,
+
+    /// [`State::trash`] at the moment of this [`StateSnapshot`].
+    pub trash: Vec<state::Restream>,
+}
+
+/// Event about a status change of a [`state::Input`] or a [`state::Output`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusEvent {
+    /// Kind of the entity whose status has changed.
+    pub kind: EntityKind,
+
+    /// ID of the entity whose status has changed.
+    pub id: Uuid,
+
+    /// ID of the [`state::Restream`] the entity belongs to.
+    pub restream_id: RestreamId,
+
+    /// Key of the [`state::Restream`] the entity belongs to.
+    pub restream_key: RestreamKey,
+
+    /// Label of the entity, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
+
+    /// [`Status`] of the entity before the change.
+    pub previous: Status,
+
+    /// New [`Status`] of the entity.
+    pub status: Status,
+
+    /// Moment of the change.
+    pub at: DateTime,
+}
+
+impl StatusEvent {
+    /// Maximum number of [`StatusEvent`]s queued for a single subscriber (see
+    /// [`State::subscribe_status_events()`]).
+    pub const QUEUE_SIZE: usize = 1024;
+}
+
+/// Kind of an entity a [`StatusEvent`] is about.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    /// [`state::Input`], whose [RTMP] endpoint status is tracked.
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    Input,
+
+    /// [`state::Output`].
+    Output,
+}
+
+/// Tracker of [`Status`]es of [`state::Input`]s and [`state::Output`]s,
+/// producing [`StatusEvent`]s about their changes.
+#[derive(Debug, Default)]
+pub struct StatusTracker {
+    /// [`Status`]es of entities seen on the previous
+    /// [`StatusTracker::diff()`] call, by their IDs.
+    ///
+    /// [`None`] if there was no call yet.
+    statuses: Option<HashMap<Uuid, Status>>,
+}
+
+impl StatusTracker {
+    /// Returns [`StatusEvent`]s about all the [`Status`] changes of the given
+    /// `restreams` (as observed in the given `statuses`) since the previous
+    /// call.
+    ///
+    /// The first call only remembers the [`Status`]es. Entities appeared
+    /// since the previous call are considered being [`Status::Offline`]
+    /// before.
+    pub fn diff(
+        &mut self,
+        restreams: &[state::Restream],
+        statuses: &Statuses,
+    ) -> Vec<StatusEvent> {
+        let (mut curr, mut events) = (HashMap::new(), vec![]);
+        let at = DateTime::now();
+        for r in restreams {
+            let input_status = r
+                .input
+                .endpoints
+                .iter()
+                .find(|e| e.is_rtmp())
+                .map(|e| statuses.get(e.id))
+                .unwrap_or_default();
+            let entities = Some((
+                EntityKind::Input,
+                Uuid::from(r.input.id),
+                None,
+                input_status,
+            ))
+            .into_iter()
+            .chain(r.outputs.iter().map(|o| {
+                let id = Uuid::from(o.id);
+                (EntityKind::Output, id, o.label.as_ref(), statuses.get(o.id))
+            }));
+            for (kind, id, label, status) in entities {
+                let _ = curr.insert(id, status);
+                let previous = match &self.statuses {
+                    Some(prev) => prev.get(&id).copied().unwrap_or_default(),
+                    None => continue,
+                };
+                if previous != status {
+                    events.push(StatusEvent {
+                        kind,
+                        id,
+                        restream_id: r.id,
+                        restream_key: r.key.clone(),
+                        label: label.cloned(),
+                        previous,
+                        status,
+                        at,
+                    });
+                }
+            }
+        }
+        self.statuses = Some(curr);
+        events
+    }
+}
+
+#[cfg(test)]
+mod status_tracker_spec {
+    use serde_json::json;
+
+    use crate::{
+        spec,
+        state::{State, Status},
+    };
+
+    use super::{EntityKind, StatusTracker};
+
+    #[test]
+    fn emits_status_changes_only() {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "main",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "enabled": true,
+                    },
+                    "outputs": [{
+                        "dst": "rtmp://example.com/live/stream",
+                        "label": "YouTube",
+                        "enabled": true,
+                    }],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let mut tracker = StatusTracker::default();
+        let id = state.restreams.lock_ref()[0].outputs[0].id;
+        let diff = |tracker: &mut StatusTracker| {
+            tracker
+                .diff(&state.restreams.lock_ref(), &state.statuses.lock_ref())
+        };
+
+        let _ = state.statuses.set(id, Status::Online);
+        assert!(diff(&mut tracker).is_empty());
+        assert!(diff(&mut tracker).is_empty());
+
+        let _ = state.statuses.set(id, Status::Offline);
+        let events = diff(&mut tracker);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EntityKind::Output);
+        assert_eq!(events[0].previous, Status::Online);
+        assert_eq!(events[0].status, Status::Offline);
+
+        let payload = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(payload["kind"], "output");
+        assert_eq!(payload["restreamKey"], "main");
+        assert_eq!(payload["label"], "YouTube");
+        assert_eq!(payload["status"], "offline");
+    }
+}
+
+#[cfg(test)]
+mod shutdown_token_spec {
+    use futures::FutureExt as _;
+
+    use super::ShutdownTrigger;
+
+    #[test]
+    fn resolves_once_triggered() {
+        let trigger = ShutdownTrigger::default();
+        let token = trigger.token();
+        assert!(!token.is_triggered());
+        assert!(token.clone().wait().now_or_never().is_none());
+
+        trigger.trigger();
+        assert!(token.is_triggered());
+        assert!(token.wait().now_or_never().is_some());
+    }
+}
@@ -0,0 +1,465 @@
+//! Injection of faults on demand, for rehearsing failures handling before big
+//! events.
+//!
+//! Faults are injected only when `fault-injection` feature is compiled in and
+//! the server is started with `--enable-fault-injection` CLI option.
+//! Otherwise, everything in this module compiles down to no-ops: no [`Fault`]
+//! can be injected, so it can never be triggered in production by accident.
+//!
+//! Injected [`Fault`]s are scoped to the [`State`] they're injected into (see
+//! [`State::faults`]), and every [`Fault`] expires automatically once its
+//! duration elapses.
+
+use std::time::Duration;
+#[cfg(feature = "fault-injection")]
+use std::{
+    convert::TryInto as _,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Instant, SystemTime},
+};
+
+use derive_more::{Display, Error};
+#[cfg(feature = "fault-injection")]
+use ephyr_log::log;
+use juniper::{GraphQLEnum, GraphQLObject};
+use uuid::Uuid;
+
+use crate::{api::graphql::DateTime, state::InputId, State};
+#[cfg(feature = "fault-injection")]
+use crate::{ffmpeg, state::Status, trace};
+
+/// Kind of a `Fault`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq)]
+pub enum FaultKind {
+    /// `Input` is forced to be `OFFLINE` for the whole duration of a `Fault`:
+    /// its publisher is kicked, and the new ones are rejected.
+    InputOffline,
+
+    /// Re-streaming [FFmpeg] process of an `Output` is killed once, so is
+    /// restarted as if it has crashed.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    KillOutput,
+
+    /// Processing of [SRS] HTTP callbacks is delayed by `Fault.delayMs` for
+    /// the whole duration of a `Fault`.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    DelayCallbacks,
+}
+
+/// Fault injected into this server for rehearsing failures handling.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct Fault {
+    /// Kind of this `Fault`.
+    pub kind: FaultKind,
+
+    /// ID of the `Input` or `Output` targeted by this `Fault`.
+    ///
+    /// `null` for `DELAY_CALLBACKS` `Fault`s, as they affect the whole server.
+    pub target: Option<String>,
+
+    /// Delay of processing [SRS] HTTP callbacks (in milliseconds), for
+    /// `DELAY_CALLBACKS` `Fault`s only.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub delay_ms: Option<i32>,
+
+    /// Moment of time when this `Fault` has been injected.
+    pub started_at: DateTime,
+
+    /// Moment of time when this `Fault` expires.
+    pub expires_at: DateTime,
+}
+
+/// Error of injecting a [`Fault`].
+#[derive(Clone, Copy, Debug, Display, Eq, Error, PartialEq)]
+pub enum InjectError {
+    /// `fault-injection` feature is not compiled in.
+    #[display(fmt = "Fault injection is not compiled in")]
+    Disabled,
+
+    /// Targeted `Input` or `Output` doesn't exist.
+    #[display(fmt = "Targeted `Input` or `Output` doesn't exist")]
+    UnknownTarget,
+
+    /// Targeted `Output` has no running [FFmpeg] process to be killed.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[display(fmt = "Targeted `Output` has no running FFmpeg process")]
+    NotRunning,
+}
+
+/// [`Fault`] being active at the moment.
+#[cfg(feature = "fault-injection")]
+#[derive(Clone, Debug)]
+struct Active {
+    /// Moment this [`Active`] fault expires at.
+    until: Instant,
+
+    /// ID of the entity targeted by this [`Active`] fault, if any.
+    target: Option<Uuid>,
+
+    /// Injected [`Fault`] itself.
+    fault: Fault,
+}
+
+/// [`Fault`]s injected into a [`State`].
+///
+/// Clones of [`Faults`] refer to the same injected [`Fault`]s.
+#[derive(Clone, Debug, Default)]
+pub struct Faults(#[cfg(feature = "fault-injection")] Arc<Mutex<Vec<Active>>>);
+
+#[cfg(feature = "fault-injection")]
+impl Faults {
+    /// Returns the currently active [`Fault`]s, forgetting the expired ones.
+    fn active(&self) -> MutexGuard<'_, Vec<Active>> {
+        let mut active = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        active.retain(|a| a.until > now);
+        active
+    }
+}
+
+/// Injects a [`Fault`] of the given `kind` into the given [`State`] and its
+/// running [FFmpeg] processes for the given `duration`.
+///
+/// The `target` is an ID of the `Input` for [`FaultKind::InputOffline`], or an
+/// ID of the `Output` for [`FaultKind::KillOutput`]. The `delay` is required
+/// for [`FaultKind::DelayCallbacks`] only.
+///
+/// Doesn't check whether the server is started with
+/// `--enable-fault-injection` CLI option, so the caller should.
+///
+/// # Errors
+///
+/// - If `fault-injection` feature is not compiled in.
+/// - If the `target` doesn't exist.
+/// - If the targeted `Output` has no running [FFmpeg] process to be killed.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[cfg(feature = "fault-injection")]
+pub fn inject(
+    state: &State,
+    kind: FaultKind,
+    target: Option<Uuid>,
+    duration: Duration,
+    delay: Option<Duration>,
+) -> Result<Fault, InjectError> {
+    match kind {
+        FaultKind::InputOffline => {
+            let id = target.ok_or(InjectError::UnknownTarget)?.into();
+            force_offline(state, id)?;
+        }
+        FaultKind::KillOutput => {
+            let id = target.ok_or(InjectError::UnknownTarget)?;
+            let exists = state
+                .restreams
+                .lock_ref()
+                .iter()
+                .any(|r| r.outputs.iter().any(|o| Uuid::from(o.id) == id));
+            if !exists {
+                return Err(InjectError::UnknownTarget);
+            }
+            let pid = ffmpeg::Stats::global()
+                .get(id)
+                .and_then(|s| s.process())
+                .ok_or(InjectError::NotRunning)?
+                .pid;
+            kill(pid);
+        }
+        FaultKind::DelayCallbacks => {}
+    }
+
+    let now = SystemTime::now();
+    let fault = Fault {
+        kind,
+        target: target
+            .filter(|_| kind != FaultKind::DelayCallbacks)
+            .map(|id| id.to_string()),
+        delay_ms: delay
+            .filter(|_| kind == FaultKind::DelayCallbacks)
+            .map(|d| d.as_millis().try_into().unwrap_or(i32::MAX)),
+        started_at: now.into(),
+        expires_at: (now + duration).into(),
+    };
+    log::warn!(
+        "Injected {:?} fault into {} for {}s",
+        kind,
+        fault.target.as_deref().unwrap_or("server"),
+        duration.as_secs(),
+    );
+    state.faults.active().push(Active {
+        until: Instant::now() + duration,
+        target: target.filter(|_| kind != FaultKind::DelayCallbacks),
+        fault: fault.clone(),
+    });
+    Ok(fault)
+}
+
+/// Injects a [`Fault`] of the given `kind` into the given [`State`] and its
+/// running [FFmpeg] processes for the given `duration`.
+///
+/// # Errors
+///
+/// Always, as `fault-injection` feature is not compiled in.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[cfg(not(feature = "fault-injection"))]
+#[inline]
+pub fn inject(
+    _: &State,
+    _: FaultKind,
+    _: Option<Uuid>,
+    _: Duration,
+    _: Option<Duration>,
+) -> Result<Fault, InjectError> {
+    Err(InjectError::Disabled)
+}
+
+/// Forces the `Input` with the given `id` to be [`Status::Offline`], kicking
+/// its publisher.
+///
+/// # Errors
+///
+/// If no such `Input` exists.
+#[cfg(feature = "fault-injection")]
+fn force_offline(state: &State, id: InputId) -> Result<(), InjectError> {
+    let mut restreams = trace::lock_mut(&state.restreams, "inject_fault");
+    let input = restreams
+        .iter_mut()
+        .find_map(|r| r.input.find_mut(id))
+        .ok_or(InjectError::UnknownTarget)?;
+    let mut statuses = state.statuses.lock_mut("force_offline");
+    for e in &mut input.endpoints {
+        // `srs::ClientId` kicks the client when `Drop`ped.
+        e.srs_publisher_id = None;
+        e.publishing_session = None;
+        let _ = statuses.set(e.id, Status::Offline);
+        e.offline_reason = Some("Forced offline by injected fault".into());
+    }
+    Ok(())
+}
+
+/// Kills the OS process with the given `pid` abruptly, as if it has crashed.
+#[cfg(feature = "fault-injection")]
+fn kill(pid: i32) {
+    #[cfg(unix)]
+    {
+        #[allow(unsafe_code)]
+        let _ = unsafe { libc::kill(pid, libc::SIGKILL) };
+    }
+    #[cfg(not(unix))]
+    log::error!("Killing {} process is not supported on this OS", pid);
+}
+
+/// Returns all the [`Fault`]s currently active in the given [`State`].
+#[cfg(feature = "fault-injection")]
+#[must_use]
+pub fn faults(state: &State) -> Vec<Fault> {
+    state
+        .faults
+        .active()
+        .iter()
+        .map(|a| a.fault.clone())
+        .collect()
+}
+
+/// Returns all the [`Fault`]s currently active in the given [`State`].
+#[cfg(not(feature = "fault-injection"))]
+#[inline]
+#[must_use]
+pub fn faults(_: &State) -> Vec<Fault> {
+    vec![]
+}
+
+/// Indicates whether the `Input` with the given `id` is forced to be
+/// [`FaultKind::InputOffline`] in the given [`State`] at the moment, so its
+/// publishers should be rejected.
+#[cfg(feature = "fault-injection")]
+#[must_use]
+pub fn is_input_offline(state: &State, id: InputId) -> bool {
+    let id = Uuid::from(id);
+    state.faults.active().iter().any(|a| {
+        a.fault.kind == FaultKind::InputOffline && a.target == Some(id)
+    })
+}
+
+/// Indicates whether the `Input` with the given `id` is forced to be
+/// [`FaultKind::InputOffline`] in the given [`State`] at the moment, so its
+/// publishers should be rejected.
+#[cfg(not(feature = "fault-injection"))]
+#[inline]
+#[must_use]
+pub fn is_input_offline(_: &State, _: InputId) -> bool {
+    false
+}
+
+/// Returns the delay [SRS] HTTP callbacks should be processed with at the
+/// moment, if any [`FaultKind::DelayCallbacks`] is active in the given
+/// [`State`].
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[cfg(feature = "fault-injection")]
+#[must_use]
+pub fn callbacks_delay(state: &State) -> Option<Duration> {
+    state
+        .faults
+        .active()
+        .iter()
+        .filter_map(|a| a.fault.delay_ms)
+        .max()
+        .map(|ms| Duration::from_millis(ms.try_into().unwrap_or_default()))
+}
+
+/// Returns the delay [SRS] HTTP callbacks should be processed with at the
+/// moment, if any [`FaultKind::DelayCallbacks`] is active in the given
+/// [`State`].
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[cfg(not(feature = "fault-injection"))]
+#[inline]
+#[must_use]
+pub fn callbacks_delay(_: &State) -> Option<Duration> {
+    None
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod inject_spec {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::state::{State, Status};
+
+    use super::{
+        callbacks_delay, faults, inject, is_input_offline, FaultKind,
+        InjectError,
+    };
+
+    fn state() -> State {
+        State::with_restream(
+            "main",
+            json!({"outputs": [{"dst": "rtmp://127.0.0.1/live/out"}]}),
+        )
+    }
+
+    #[test]
+    fn forces_input_offline_until_expired() {
+        let state = state();
+        let (id, endpoint) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].input.id, restreams[0].input.endpoints[0].id)
+        };
+        let _ = state.statuses.set(endpoint, Status::Online);
+
+        let fault = inject(
+            &state,
+            FaultKind::InputOffline,
+            Some(id.into()),
+            Duration::from_secs(60),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(fault.target, Some(id.to_string()));
+        assert!(is_input_offline(&state, id));
+        assert!(faults(&state).contains(&fault));
+        assert_eq!(state.statuses.get(endpoint), Status::Offline);
+    }
+
+    #[test]
+    fn expires_automatically() {
+        let state = state();
+        let id = state.restreams.lock_ref()[0].input.id;
+
+        let fault = inject(
+            &state,
+            FaultKind::InputOffline,
+            Some(id.into()),
+            Duration::from_secs(0),
+            None,
+        )
+        .unwrap();
+
+        assert!(!is_input_offline(&state, id));
+        assert!(!faults(&state).contains(&fault));
+    }
+
+    #[test]
+    fn delays_callbacks() {
+        let state = state();
+
+        let fault = inject(
+            &state,
+            FaultKind::DelayCallbacks,
+            None,
+            Duration::from_secs(60),
+            Some(Duration::from_millis(1500)),
+        )
+        .unwrap();
+
+        assert_eq!(fault.delay_ms, Some(1500));
+        assert_eq!(callbacks_delay(&state), Some(Duration::from_millis(1500)));
+
+        state.faults.0.lock().unwrap().clear();
+        assert_eq!(callbacks_delay(&state), None);
+    }
+
+    #[test]
+    fn scopes_faults_to_state() {
+        let (state, other) = (state(), state());
+        let id = state.restreams.lock_ref()[0].input.id;
+
+        let _ = inject(
+            &state,
+            FaultKind::DelayCallbacks,
+            None,
+            Duration::from_secs(60),
+            Some(Duration::from_millis(1500)),
+        )
+        .unwrap();
+
+        assert!(callbacks_delay(&other).is_none());
+        assert!(faults(&other).is_empty());
+        assert!(!is_input_offline(&other, id));
+
+        state.faults.0.lock().unwrap().clear();
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        let state = state();
+
+        for kind in &[FaultKind::InputOffline, FaultKind::KillOutput] {
+            assert_eq!(
+                inject(
+                    &state,
+                    *kind,
+                    Some(Uuid::new_v4()),
+                    Duration::from_secs(60),
+                    None,
+                ),
+                Err(InjectError::UnknownTarget),
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_killing_not_running_output() {
+        let state = state();
+        let id = state.restreams.lock_ref()[0].outputs[0].id;
+
+        assert_eq!(
+            inject(
+                &state,
+                FaultKind::KillOutput,
+                Some(id.into()),
+                Duration::from_secs(60),
+                None,
+            ),
+            Err(InjectError::NotRunning),
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! [FFprobe] probing of live stream sources.
+//!
+//! [FFprobe]: https://ffmpeg.org/ffprobe.html
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ephyr_log::log;
+use tokio::{process::Command, sync::Semaphore, time};
+use url::Url;
+
+use crate::state::{self, InputId, State, Status};
+
+/// Maximum number of [FFprobe] processes running simultaneously.
+///
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+const MAX_CONCURRENT_PROBES: usize = 4;
+
+/// Minimum interval between two consequent probes of the same
+/// [`state::Input`].
+const MIN_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Timeout of network I/O operations performed by a single [FFprobe] process.
+///
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum time a single [FFprobe] process is allowed to run.
+///
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prober of pull [`state::Input`]s' sources, renewing their [`Status`] in a
+/// [`State`] without waiting for [FFmpeg] re-streaming processes to connect.
+///
+/// Reachable source renews [`Status::Initializing`], while unreachable one
+/// renews [`Status::Offline`]. [`Status::Online`] is never renewed, as it's
+/// reserved for [SRS] `on_publish` callback, so `Output`s start pulling a live
+/// stream only once it's really published. Neither a [`Status`] set by [SRS]
+/// `on_publish` callback is ever overridden.
+///
+/// [FFmpeg]: https://ffmpeg.org
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Debug)]
+pub struct InputsProber {
+    /// Path to a [FFprobe] binary used for spawning processes.
+    ///
+    /// [FFprobe]: https://ffmpeg.org/ffprobe.html
+    ffprobe_path: Arc<PathBuf>,
+
+    /// Permits limiting the number of simultaneously running [FFprobe]
+    /// processes.
+    ///
+    /// [FFprobe]: https://ffmpeg.org/ffprobe.html
+    permits: Arc<Semaphore>,
+
+    /// IDs of enabled pull [`state::Input`]s seen on the previous
+    /// [`InputsProber::apply`] call.
+    enabled: HashSet<InputId>,
+
+    /// Moments when [`state::Input`]s have been probed recently.
+    probed_at: HashMap<InputId, Instant>,
+
+    /// Application [`State`] to renew [`state::Input`]s' [`Status`] in.
+    state: State,
+}
+
+impl InputsProber {
+    /// Creates a new [`InputsProber`] out of the given parameters.
+    #[inline]
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(ffprobe_path: P, state: State) -> Self {
+        Self {
+            ffprobe_path: Arc::new(ffprobe_path.into()),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_PROBES)),
+            enabled: HashSet::new(),
+            probed_at: HashMap::new(),
+            state,
+        }
+    }
+
+    /// Probes, in background, sources of the pull [`state::Input`]s which
+    /// have become enabled since the previous call (so all of them on the
+    /// first call).
+    ///
+    /// The same [`state::Input`] is not probed more often than once per
+    /// [`MIN_PROBE_INTERVAL`].
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let mut srcs = HashMap::new();
+        for r in restreams {
+            collect_srcs(&r.input, &mut srcs);
+        }
+
+        self.probed_at
+            .retain(|_, at| at.elapsed() < MIN_PROBE_INTERVAL);
+
+        for (id, url) in &srcs {
+            if self.enabled.contains(id) || self.probed_at.contains_key(id) {
+                continue;
+            }
+            let _ = self.probed_at.insert(*id, Instant::now());
+
+            let (id, url) = (*id, url.clone());
+            let ffprobe_path = self.ffprobe_path.clone();
+            let permits = self.permits.clone();
+            let state = self.state.clone();
+            drop(tokio::spawn(async move {
+                let _permit = permits.acquire().await;
+                if let Some(reachable) = probe(&ffprobe_path, &url).await {
+                    renew_status(
+                        &state,
+                        id,
+                        if reachable {
+                            Status::Initializing
+                        } else {
+                            Status::Offline
+                        },
+                    );
+                }
+            }));
+        }
+
+        self.enabled = srcs.into_iter().map(|(id, _)| id).collect();
+    }
+}
+
+/// Collects [`Url`]s of the remote sources of the given enabled
+/// [`state::Input`] and all its enabled [`state::FailoverInputSrc::inputs`].
+fn collect_srcs(input: &state::Input, srcs: &mut HashMap<InputId, Url>) {
+    if !input.enabled {
+        return;
+    }
+    match &input.src {
+        Some(state::InputSrc::Remote(s)) if s.url.restream_id().is_none() => {
+            let _ = srcs.insert(input.id, s.url.clone().into());
+        }
+        Some(state::InputSrc::Failover(s)) => {
+            for i in &s.inputs {
+                collect_srcs(i, srcs);
+            }
+        }
+        // Chained `Restream`s and local files are not remote, so their
+        // availability is watched by their re-streaming processes.
+        Some(state::InputSrc::Remote(_))
+        | Some(state::InputSrc::File(_))
+        | None => {}
+    }
+}
+
+/// Probes the given [`Url`] with a [FFprobe] process, checking whether it's
+/// reachable and serves a media.
+///
+/// Returns [`None`] if [FFprobe] process cannot be spawned.
+///
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+async fn probe(ffprobe_path: &Path, url: &Url) -> Option<bool> {
+    let mut cmd = Command::new(ffprobe_path);
+    let _ = cmd
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .args(&["-v", "quiet", "-rw_timeout"])
+        .arg(IO_TIMEOUT.as_micros().to_string())
+        .arg(url.as_str());
+
+    match time::timeout(PROBE_TIMEOUT, cmd.status()).await {
+        Ok(Ok(status)) => Some(status.success()),
+        Ok(Err(e)) => {
+            log::error!("Failed to run FFprobe: {}", e);
+            None
+        }
+        Err(_) => Some(false),
+    }
+}
+
+/// Renews [`Status`] of the RTMP endpoint of a [`state::Input`] with the given
+/// `id` in the `actual` [`State`].
+///
+/// Does nothing if the [`state::Input`] has been disabled meanwhile, or its
+/// endpoint has been already published to [SRS].
+///
+/// [SRS]: https://github.com/ossrs/srs
+fn renew_status(actual: &State, id: InputId, status: Status) {
+    let restreams = actual.restreams.lock_ref();
+    let endpoint = restreams
+        .iter()
+        .find_map(|r| r.input.find(id))
+        .filter(|i| i.enabled)
+        .and_then(|i| i.endpoints.iter().find(|e| e.is_rtmp()))
+        .filter(|e| e.srs_publisher_id.is_none());
+    if let Some(e) = endpoint {
+        let mut statuses = actual.statuses.lock_mut("renew_status");
+        if !statuses.get(e.id).is_online() {
+            let _ = statuses.set(e.id, status);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Auto-disabling of idle [`state::Input`]s.
+
+use std::time::{Duration, SystemTime};
+
+use ephyr_log::log;
+
+use crate::{
+    state::{
+        self, DisableReason, DisableReasonKind, RestreamId, State, Statuses,
+    },
+    timer::Timers,
+    trace,
+};
+
+/// Watcher of [`state::Restream`]s with
+/// [`state::Restream::auto_disable_after_idle_secs`] specified, disabling their
+/// [`state::Input`]s once they stay idle (receiving no live stream)
+/// continuously for that long.
+///
+/// A [`state::Input`] going online or being disabled drops its [`Timer`], so
+/// enabling it again waits the whole idle duration anew.
+///
+/// [`Timer`]: crate::timer::Timer
+#[derive(Debug)]
+pub struct IdleInputsWatcher {
+    /// Running timers of idle [`state::Input`]s, along with the durations they
+    /// have been started with.
+    timers: Timers<RestreamId, Duration>,
+}
+
+impl IdleInputsWatcher {
+    /// Creates a new [`IdleInputsWatcher`] operating on the given [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            timers: Timers::new(state),
+        }
+    }
+
+    /// Starts timers for the [`state::Input`]s of the given `restreams` which
+    /// have become idle (according to the given `statuses`) since the
+    /// previous call, and drops the ones of the
+    /// [`state::Input`]s which are not idle anymore.
+    ///
+    /// Changing [`state::Restream::auto_disable_after_idle_secs`] of an idle
+    /// [`state::Input`] restarts its timer.
+    pub fn apply(
+        &mut self,
+        restreams: &[state::Restream],
+        statuses: &Statuses,
+    ) {
+        let idle = restreams
+            .iter()
+            .filter_map(|r| {
+                let secs = r.auto_disable_after_idle_secs?;
+                is_idle(&r.input, statuses)
+                    .then(|| (r.id, Duration::from_secs(secs.into())))
+            })
+            .collect();
+
+        self.timers.rearm(
+            idle,
+            |after| SystemTime::now() + *after,
+            disable_idle,
+        );
+    }
+}
+
+/// Checks whether the given [`state::Input`] is enabled, but receives no live
+/// stream according to the given `statuses`.
+///
+/// [`state::Input`] being out of its [`state::Input::schedule`] is not idle,
+/// as it's not expected to receive a live stream.
+#[inline]
+#[must_use]
+fn is_idle(input: &state::Input, statuses: &Statuses) -> bool {
+    input.enabled
+        && !input.is_ready_to_serve(statuses)
+        && !input.is_out_of_schedule(SystemTime::now())
+}
+
+/// Disables the [`state::Input`] of the [`state::Restream`] with the given
+/// `id` in the `actual` [`State`], recording the reason of being idle for the
+/// given duration.
+///
+/// Does nothing if the [`state::Input`] is not idle anymore.
+fn disable_idle(actual: &State, id: RestreamId, after: Duration) {
+    let mut restreams = trace::lock_mut(&actual.restreams, "disable_idle");
+    let mut statuses = actual.statuses.lock_mut("disable_idle");
+    let restream = match restreams.iter_mut().find(|r| r.id == id) {
+        Some(r) if is_idle(&r.input, &statuses) => r,
+        _ => return,
+    };
+
+    let _ = restream.input.disable(
+        DisableReason::new(
+            DisableReasonKind::Idle,
+            format!("Received no live stream for {} seconds", after.as_secs()),
+            "idle watcher",
+        ),
+        &mut statuses,
+    );
+    log::info!(
+        "Disabled `{}` app after being idle for {}s",
+        restream.key,
+        after.as_secs(),
+    );
+}
+
+#[cfg(test)]
+mod disable_idle_spec {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::state::{DisableReasonKind, State, Status};
+
+    use super::disable_idle;
+
+    #[test]
+    fn disables_idle_input_with_reason() {
+        let state = State::with_restream(
+            "guest",
+            json!({"auto_disable_after_idle_secs": 60}),
+        );
+        let id = state.restreams.lock_ref()[0].id;
+
+        disable_idle(&state, id, Duration::from_secs(60));
+
+        let restreams = state.restreams.lock_ref();
+        assert!(!restreams[0].input.enabled);
+        assert_eq!(
+            restreams[0].input.disabled_reason.as_ref().map(|r| r.kind),
+            Some(DisableReasonKind::Idle),
+        );
+    }
+
+    #[test]
+    fn keeps_online_input_enabled() {
+        let state = State::with_restream(
+            "guest",
+            json!({"auto_disable_after_idle_secs": 60}),
+        );
+        let id = {
+            let restreams = state.restreams.lock_ref();
+            let _ = state
+                .statuses
+                .set(restreams[0].input.endpoints[0].id, Status::Online);
+            restreams[0].id
+        };
+
+        disable_idle(&state, id, Duration::from_secs(60));
+
+        let restreams = state.restreams.lock_ref();
+        assert!(restreams[0].input.enabled);
+        assert!(restreams[0].input.disabled_reason.is_none());
+    }
+}
@@ -1,5 +1,8 @@
 //! Ephyr [RTMP] re-streaming server.
 //!
+//! Besides being run as a standalone binary (see [`run()`]), it may be
+//! embedded into another application via [`Restreamer`].
+//!
 //! [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
 
 #![deny(
@@ -23,21 +26,59 @@
 )]
 
 pub mod api;
+pub mod capacity;
+pub mod check;
 pub mod cli;
+pub mod coalesce;
+pub mod compat;
+pub mod cycle;
+pub mod diagnostics;
 pub mod dvr;
+pub mod embed;
+#[cfg(feature = "event-broker")]
+pub mod events;
+pub mod evict;
+pub mod expiry;
+pub mod extension;
+pub mod fault;
 pub mod ffmpeg;
+pub mod ffprobe;
+pub mod idle;
+pub mod limits;
+pub mod mail;
+pub mod metrics;
+pub mod migrate;
+pub mod overlay;
+pub mod password;
+pub mod paths;
+pub mod preview;
+pub mod proxy;
+pub mod reachability;
+pub mod reconcile;
+pub mod recovery;
+pub mod replication;
+pub mod rotation;
+pub mod schedule;
 pub mod serde;
 pub mod server;
+pub mod session;
 pub mod spec;
 pub mod srs;
 pub mod state;
+pub mod status_page;
+pub mod sweep;
 pub mod teamspeak;
+pub mod text;
+pub mod timer;
+pub mod trace;
+pub mod usage;
+pub mod workflow;
 
-use std::{any::Any, mem};
+use std::{any::Any, fs, mem};
 
 use ephyr_log::slog;
 
-pub use self::{spec::Spec, state::State};
+pub use self::{embed::Restreamer, spec::Spec, state::State};
 
 /// Runs application.
 ///
@@ -46,7 +87,17 @@ pub use self::{spec::Spec, state::State};
 /// If running has failed and could not be performed. The appropriate error
 /// is logged.
 pub fn run() -> Result<(), cli::Failure> {
-    let mut cfg = cli::Opts::from_args();
+    let cli = cli::Cli::from_args();
+    let mut cfg = match cli.cmd {
+        None => cli.opts,
+        Some(cli::Command::Run(opts)) => opts,
+        Some(cli::Command::HashPassword(opts)) => return password::run(opts),
+        Some(cli::Command::ValidateState(opts)) => {
+            return validate_state(&opts)
+        }
+        Some(cli::Command::ImportSrsConf(opts)) => return migrate::run(&opts),
+        Some(cli::Command::ResetPassword(opts)) => return recovery::run(&opts),
+    };
     cfg.verbose = cfg.verbose.or_else(|| {
         if cfg.debug {
             Some(slog::Level::Debug)
@@ -62,6 +113,28 @@ pub fn run() -> Result<(), cli::Failure> {
     server::run(cfg)
 }
 
+/// Validates the [`State`] file specified by the given
+/// [`cli::ValidateStateOpts`], printing all the found violations to STDERR.
+///
+/// # Errors
+///
+/// If the [`State`] file cannot be read or is invalid.
+fn validate_state(opts: &cli::ValidateStateOpts) -> Result<(), cli::Failure> {
+    let contents = fs::read(&opts.path).map_err(|e| {
+        eprintln!("Failed to read '{}' file: {}", opts.path.display(), e)
+    })?;
+
+    let violations = State::validate(&contents);
+    if violations.is_empty() {
+        println!("'{}' is a valid state file", opts.path.display());
+        return Ok(());
+    }
+    for v in &violations {
+        eprintln!("{}", v);
+    }
+    Err(cli::Failure)
+}
+
 /// Interprets given [panic payload][1] as displayable message.
 ///
 /// [1]: std::panic::PanicInfo::payload
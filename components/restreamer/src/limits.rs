@@ -0,0 +1,279 @@
+//! Enforcing [`OutputLimits`] on [FFmpeg] re-streaming processes.
+//!
+//! Limits are applied by wrapping the spawned program into the limiting
+//! utilities available in OS, each of them `exec`ing the next one, so the
+//! spawned process keeps its PID. Where no utility is available, the limits
+//! are ignored silently.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+
+use std::{
+    convert::TryInto as _,
+    env,
+    ffi::OsString,
+    io, mem,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ephyr_log::log;
+use tokio::{fs, process::Command, time};
+use uuid::Uuid;
+
+use crate::state::OutputLimits;
+
+/// Root of the [cgroups v2] hierarchy.
+///
+/// [cgroups v2]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Name of the [cgroup][1] containing the [cgroups][1] of the limited
+/// processes.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+const CGROUP_NAME: &str = "ephyr";
+
+/// Number of attempts to remove the [cgroup][1] of an exited process.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+const CGROUP_REMOVE_ATTEMPTS: u32 = 10;
+
+/// Interval between attempts to remove the [cgroup][1] of an exited process.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+const CGROUP_REMOVE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Period (in microseconds) of a [cgroup][1] CPU quota.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// [Niceness][1] applied to a process, whose CPU quota cannot be enforced
+/// otherwise, unless its niceness is specified explicitly.
+///
+/// [1]: https://en.wikipedia.org/wiki/Nice_(Unix)
+const FALLBACK_NICE: i32 = 10;
+
+/// Creates a [`Command`] running the given `program` with the given `limits`
+/// applied, preparing the [cgroup][1] of the process with the given `id`, if
+/// required.
+///
+/// Returns the names of the applied mechanisms along (`cgroup`, `taskset`
+/// and/or `nice`), and the prepared [`Cgroup`], which should be kept until
+/// the process exits.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+pub async fn command(
+    program: &Path,
+    id: Uuid,
+    limits: Option<&OutputLimits>,
+) -> (Command, Vec<&'static str>, Option<Cgroup>) {
+    let mut wrappers: Vec<OsString> = vec![];
+    let mut mechanisms = vec![];
+    let mut cgroup = None;
+
+    let cores = num_cpus::get();
+    let mut nice = limits.and_then(OutputLimits::nice);
+    if let Some(quota) = limits
+        .and_then(OutputLimits::cpu_quota)
+        .and_then(|q| enforceable_quota(q, cores))
+    {
+        let prepared = match find_binary("sh") {
+            Some(sh) => prepare_cgroup(id, quota).await.map(|c| (sh, c)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "`sh` binary is not found",
+            )),
+        };
+        match prepared {
+            Ok((sh, prepared)) => {
+                // Failing to join the cgroup shouldn't prevent the process
+                // from running.
+                wrappers.extend(vec![
+                    sh.into(),
+                    "-c".into(),
+                    r#"echo $$ > "$0" 2>/dev/null; exec "$@""#.into(),
+                    prepared.0.join("cgroup.procs").into(),
+                ]);
+                mechanisms.push("cgroup");
+                cgroup = Some(prepared);
+            }
+            Err(e) => {
+                log::debug!("cgroups v2 CPU quota is unavailable: {}", e);
+                if let Some(taskset) = find_binary("taskset") {
+                    wrappers.extend(vec![
+                        taskset.into(),
+                        "-c".into(),
+                        taskset_cpus(quota, cores, spread(id, cores)).into(),
+                    ]);
+                    mechanisms.push("taskset");
+                } else {
+                    nice = nice.or(Some(FALLBACK_NICE));
+                }
+            }
+        }
+    }
+    if let Some(n) = nice {
+        if let Some(bin) = find_binary("nice") {
+            wrappers.extend(vec![
+                bin.into(),
+                "-n".into(),
+                n.to_string().into(),
+            ]);
+            mechanisms.push("nice");
+        }
+    }
+
+    let mut wrappers = wrappers.into_iter();
+    let cmd = match wrappers.next() {
+        Some(first) => {
+            let mut cmd = Command::new(first);
+            let _ = cmd.args(wrappers).arg(program);
+            cmd
+        }
+        None => Command::new(program),
+    };
+    (cmd, mechanisms, cgroup)
+}
+
+/// [cgroup][1] of a limited process, removed once dropped.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+#[derive(Debug)]
+pub struct Cgroup(PathBuf);
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        let dir = mem::take(&mut self.0);
+        // A killed process may still be exiting, and its cgroup cannot be
+        // removed until it has exited completely.
+        drop(tokio::spawn(async move {
+            for _ in 0..CGROUP_REMOVE_ATTEMPTS {
+                match fs::remove_dir(&dir).await {
+                    Ok(()) => return,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+                    Err(_) => time::delay_for(CGROUP_REMOVE_INTERVAL).await,
+                }
+            }
+            log::warn!("Failed to remove '{}' cgroup", dir.display());
+        }));
+    }
+}
+
+/// Prepares a [cgroup][1] with the given CPU `quota` (in percents of a single
+/// CPU core) for the process with the given `id`.
+///
+/// The [cgroup][1] is created inside the one of [`CGROUP_NAME`], which is
+/// the only one having its `cgroup.subtree_control` touched, so the `cpu`
+/// controller is required to be delegated to it by the host already.
+///
+/// # Errors
+///
+/// If [cgroups v2][1] or its `cpu` controller is not available, or the
+/// [cgroup][1] cannot be configured.
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+async fn prepare_cgroup(id: Uuid, quota: u32) -> io::Result<Cgroup> {
+    let root = Path::new(CGROUP_ROOT);
+    let controllers =
+        fs::read_to_string(root.join("cgroup.subtree_control")).await?;
+    if !controllers.split_whitespace().any(|c| c == "cpu") {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "`cpu` controller is not delegated",
+        ));
+    }
+
+    let parent = root.join(CGROUP_NAME);
+    fs::create_dir_all(&parent).await?;
+    // The `cpu` controller may be enabled already, so only writing `cpu.max`
+    // below decides.
+    let _ = fs::write(parent.join("cgroup.subtree_control"), "+cpu").await;
+
+    let cgroup = Cgroup(parent.join(id.to_string()));
+    fs::create_dir_all(&cgroup.0).await?;
+    fs::write(cgroup.0.join("cpu.max"), cpu_max(quota)).await?;
+    Ok(cgroup)
+}
+
+/// Validates the given CPU `quota` (in percents of a single CPU core) against
+/// the given number of `cores` available.
+///
+/// Returns [`None`] if the `quota` covers all the `cores`, so there is
+/// nothing to enforce.
+fn enforceable_quota(quota: u32, cores: usize) -> Option<u32> {
+    let max: u32 = (cores * 100).try_into().unwrap_or(u32::MAX);
+    (quota > 0 && quota < max).then(|| quota)
+}
+
+/// Returns value of the `cpu.max` file of a [cgroup][1], limiting it with the
+/// given CPU `quota` (in percents of a single CPU core).
+///
+/// [1]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+fn cpu_max(quota: u32) -> String {
+    format!(
+        "{} {}",
+        u64::from(quota) * CPU_PERIOD_US / 100,
+        CPU_PERIOD_US,
+    )
+}
+
+/// Returns the [taskset] CPU list, pinning a process to the number of CPU
+/// cores enough for the given CPU `quota` (in percents of a single CPU core),
+/// but not more than the given `cores` available, starting from the `first`
+/// one and wrapping around.
+///
+/// [taskset]: https://man7.org/linux/man-pages/man1/taskset.1.html
+fn taskset_cpus(quota: u32, cores: usize, first: usize) -> String {
+    let cores = cores.max(1);
+    let required: usize = ((quota + 99) / 100).try_into().unwrap_or(cores);
+    (first..first + required.min(cores).max(1))
+        .map(|n| (n % cores).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Picks the first CPU core to pin the process with the given `id` to, out
+/// of the given `cores` available, so pinned processes are spread across all
+/// of them, rather than competing for the first ones.
+fn spread(id: Uuid, cores: usize) -> usize {
+    let cores = cores.max(1);
+    (id.as_u128() % cores as u128)
+        .try_into()
+        .unwrap_or_default()
+}
+
+/// Looks up an executable file with the given `name` in the `PATH`.
+fn find_binary(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod limits_spec {
+    use super::{cpu_max, enforceable_quota, taskset_cpus};
+
+    #[test]
+    fn calculates_cpu_max() {
+        assert_eq!(cpu_max(50), "50000 100000");
+        assert_eq!(cpu_max(250), "250000 100000");
+    }
+
+    #[test]
+    fn calculates_taskset_cpus() {
+        assert_eq!(taskset_cpus(1, 8, 0), "0");
+        assert_eq!(taskset_cpus(100, 8, 5), "5");
+        assert_eq!(taskset_cpus(101, 8, 3), "3,4");
+        assert_eq!(taskset_cpus(101, 8, 7), "7,0");
+        assert_eq!(taskset_cpus(1000, 4, 2), "2,3,0,1");
+    }
+
+    #[test]
+    fn enforces_quota_below_all_cores_only() {
+        assert_eq!(enforceable_quota(150, 4), Some(150));
+        assert_eq!(enforceable_quota(399, 4), Some(399));
+        assert_eq!(enforceable_quota(400, 4), None);
+        assert_eq!(enforceable_quota(0, 4), None);
+    }
+}
@@ -0,0 +1,798 @@
+//! E-mail notifications about prolonged outages of [`state::Input`]s and
+//! [`state::Output`]s, and about failing persisting of the [`State`], sent
+//! via [SMTP].
+//!
+//! [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use ephyr_log::log;
+use tokio::{
+    io::{
+        self, AsyncBufReadExt as _, AsyncRead, AsyncWrite, AsyncWriteExt as _,
+    },
+    net::TcpStream,
+    time,
+};
+use uuid::Uuid;
+
+use crate::{
+    cli::Opts,
+    state::{self, PersistenceHealth, State, Status},
+};
+
+/// Mode of securing connections to a [SMTP] server with [TLS].
+///
+/// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+/// [TLS]: https://en.wikipedia.org/wiki/Transport_Layer_Security
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SmtpTls {
+    /// Plain connection, without any encryption.
+    None,
+
+    /// Plain connection upgraded to [TLS] via `STARTTLS` command.
+    ///
+    /// [TLS]: https://en.wikipedia.org/wiki/Transport_Layer_Security
+    StartTls,
+
+    /// Connection encrypted with [TLS] from the very beginning.
+    ///
+    /// [TLS]: https://en.wikipedia.org/wiki/Transport_Layer_Security
+    Tls,
+}
+
+impl SmtpTls {
+    /// Returns the port of a [SMTP] server conventionally used with this
+    /// [`SmtpTls`] mode.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    #[inline]
+    #[must_use]
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::None => 25,
+            Self::StartTls => 587,
+            Self::Tls => 465,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            _ => Err(anyhow!(
+                "'{}' is invalid SMTP TLS mode, allowed modes are: \
+                 none | starttls | tls",
+                s,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SmtpTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::StartTls => "starttls",
+            Self::Tls => "tls",
+        })
+    }
+}
+
+/// Client sending plain text e-mails via [SMTP].
+///
+/// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+#[derive(Clone)]
+pub struct Mailer {
+    /// Host of the [SMTP] server to send e-mails via.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    host: String,
+
+    /// Port of the [SMTP] server to send e-mails via.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    port: u16,
+
+    /// [`SmtpTls`] mode of connecting to the [SMTP] server.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    tls: SmtpTls,
+
+    /// Username and password to authenticate on the [SMTP] server with, if
+    /// any.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    credentials: Option<(String, String)>,
+
+    /// Address to send e-mails from.
+    from: String,
+
+    /// Addresses to send e-mails to.
+    to: Vec<String>,
+}
+
+impl Mailer {
+    /// Maximum duration of sending a single e-mail.
+    pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Creates a new [`Mailer`] out of the given [`Opts`].
+    ///
+    /// Returns [`None`] if no [`Opts::smtp_server`] is specified.
+    ///
+    /// # Errors
+    ///
+    /// If [`Opts::smtp_server`] is specified without [`Opts::smtp_from`] or
+    /// [`Opts::smtp_to`], or is not a valid `host[:port]`.
+    pub fn new(opts: &Opts) -> Result<Option<Self>, anyhow::Error> {
+        let server = if let Some(s) = &opts.smtp_server {
+            s
+        } else {
+            return Ok(None);
+        };
+
+        let (host, port) = match server.rfind(':') {
+            Some(n) if !server[n + 1..].contains(']') => {
+                let port = server[n + 1..].parse::<u16>().map_err(|e| {
+                    anyhow!("'{}' is invalid SMTP server: {}", server, e)
+                })?;
+                (&server[..n], port)
+            }
+            _ => (server.as_str(), opts.smtp_tls.default_port()),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(anyhow!("'{}' is invalid SMTP server", server));
+        }
+
+        let from = opts
+            .smtp_from
+            .clone()
+            .ok_or_else(|| anyhow!("SMTP sender address is not specified"))?;
+        if opts.smtp_to.is_empty() {
+            return Err(anyhow!("SMTP recipient addresses are not specified"));
+        }
+
+        Ok(Some(Self {
+            host: host.to_owned(),
+            port,
+            tls: opts.smtp_tls,
+            credentials: opts.smtp_user.clone().map(|user| {
+                (user, opts.smtp_password.clone().unwrap_or_default())
+            }),
+            from,
+            to: opts.smtp_to.clone(),
+        }))
+    }
+
+    /// Sends a plain text e-mail with the given `subject` and `body`.
+    ///
+    /// # Errors
+    ///
+    /// If the [SMTP] server cannot be reached, or rejects the e-mail, or it
+    /// takes longer than [`Mailer::TIMEOUT`].
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    pub async fn send(
+        &self,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), anyhow::Error> {
+        time::timeout(Self::TIMEOUT, self.transmit(subject, body))
+            .await
+            .map_err(|_| anyhow!("Timed out"))?
+    }
+
+    /// Performs the whole [SMTP] session of sending an e-mail with the given
+    /// `subject` and `body`.
+    ///
+    /// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+    async fn transmit(
+        &self,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), anyhow::Error> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let mut conn = if self.tls == SmtpTls::Tls {
+            Connection::new(self.secure(tcp).await?)
+        } else {
+            Connection::new(tcp)
+        };
+
+        // Domain of the sender is the most reasonable identity we have.
+        let ehlo = format!(
+            "EHLO {}",
+            self.from.rsplit('@').next().unwrap_or("localhost"),
+        );
+        let _ = conn.reply(2).await?;
+        let exts = conn.command(&ehlo, 2).await?;
+
+        if self.tls == SmtpTls::StartTls {
+            if !exts.iter().any(|e| e.eq_ignore_ascii_case("STARTTLS")) {
+                return Err(anyhow!("SMTP server doesn't support STARTTLS"));
+            }
+            let _ = conn.command("STARTTLS", 2).await?;
+            conn = Connection::new(self.secure(conn.into_inner()).await?);
+            let _ = conn.command(&ehlo, 2).await?;
+        }
+
+        if let Some((user, pass)) = &self.credentials {
+            let token = base64::encode(format!("\0{}\0{}", user, pass));
+            let _ = conn.command(&format!("AUTH PLAIN {}", token), 2).await?;
+        }
+
+        let _ = conn
+            .command(&format!("MAIL FROM:<{}>", self.from), 2)
+            .await?;
+        for to in &self.to {
+            let _ = conn.command(&format!("RCPT TO:<{}>", to), 2).await?;
+        }
+        let _ = conn.command("DATA", 3).await?;
+        let _ = conn
+            .command(&self.message(subject, body, Utc::now().to_rfc2822()), 2)
+            .await?;
+        let _ = conn.command("QUIT", 2).await;
+
+        Ok(())
+    }
+
+    /// Secures the given `stream` with [TLS].
+    ///
+    /// [TLS]: https://en.wikipedia.org/wiki/Transport_Layer_Security
+    async fn secure<S>(
+        &self,
+        stream: S,
+    ) -> Result<tokio_tls::TlsStream<S>, anyhow::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let connector =
+            tokio_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        Ok(connector.connect(&self.host, stream).await?)
+    }
+
+    /// Formats the `DATA` of an e-mail with the given `subject`, `body` and
+    /// `date`, terminated with a single dot line.
+    fn message(&self, subject: &str, body: &str, date: String) -> String {
+        let subject: String =
+            subject.chars().filter(|c| !c.is_control()).collect();
+        let subject = if subject.is_ascii() {
+            subject
+        } else {
+            format!("=?UTF-8?B?{}?=", base64::encode(subject))
+        };
+
+        let mut msg = format!(
+            "From: <{}>\r\n\
+             To: {}\r\n\
+             Subject: {}\r\n\
+             Date: {}\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 8bit\r\n\
+             \r\n",
+            self.from,
+            self.to
+                .iter()
+                .map(|to| format!("<{}>", to))
+                .collect::<Vec<_>>()
+                .join(", "),
+            subject,
+            date,
+        );
+        for line in body.lines() {
+            // Dot-stuffing, so no line of the body terminates the `DATA`.
+            if line.starts_with('.') {
+                msg.push('.');
+            }
+            msg.push_str(line);
+            msg.push_str("\r\n");
+        }
+        msg.push('.');
+        msg
+    }
+}
+
+impl fmt::Debug for Mailer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mailer")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("user", &self.credentials.as_ref().map(|(u, _)| u))
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish()
+    }
+}
+
+/// Bidirectional byte stream that a [`Connection`] may be established over.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// Established connection to a [SMTP] server.
+///
+/// [SMTP]: https://en.wikipedia.org/wiki/Simple_Mail_Transfer_Protocol
+struct Connection(io::BufReader<Box<dyn Stream>>);
+
+impl Connection {
+    /// Wraps the given `stream` into a new [`Connection`].
+    #[inline]
+    fn new<S: Stream + 'static>(stream: S) -> Self {
+        Self(io::BufReader::new(Box::new(stream)))
+    }
+
+    /// Unwraps the underlying stream of this [`Connection`].
+    #[inline]
+    fn into_inner(self) -> Box<dyn Stream> {
+        self.0.into_inner()
+    }
+
+    /// Sends the given `line` and awaits for a reply with the given `class`
+    /// (the first digit of a reply code).
+    ///
+    /// Returns texts of the reply lines, except the first one.
+    ///
+    /// # Errors
+    ///
+    /// If the reply has another class, or the connection fails.
+    async fn command(
+        &mut self,
+        line: &str,
+        class: u8,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        self.0.write_all(line.as_bytes()).await?;
+        self.0.write_all(b"\r\n").await?;
+        self.0.flush().await?;
+        self.reply(class).await.map_err(|e| {
+            let cmd = line.split_whitespace().next().unwrap_or_default();
+            anyhow!("{} failed: {}", cmd, e)
+        })
+    }
+
+    /// Awaits for a (possibly multiline) reply with the given `class` (the
+    /// first digit of a reply code).
+    ///
+    /// Returns texts of the reply lines, except the first one.
+    ///
+    /// # Errors
+    ///
+    /// If the reply has another class, or the connection fails.
+    async fn reply(&mut self, class: u8) -> Result<Vec<String>, anyhow::Error> {
+        let mut lines = vec![];
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.0.read_line(&mut line).await? == 0 {
+                return Err(anyhow!("Connection closed by SMTP server"));
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).unwrap_or_default();
+            if !code.bytes().all(|b| b.is_ascii_digit()) || code.len() < 3 {
+                return Err(anyhow!("Malformed SMTP reply: {}", line));
+            }
+            if code.as_bytes()[0] != b'0' + class {
+                return Err(anyhow!("SMTP server replied: {}", line));
+            }
+            lines.push(line.get(4..).unwrap_or_default().to_owned());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        let _ = lines.remove(0);
+        Ok(lines)
+    }
+}
+
+/// Notifier sending e-mails about [`state::Input`]s and enabled
+/// [`state::Output`]s staying not [`Status::Online`] longer than a
+/// configured threshold, and about their recoveries afterwards.
+///
+/// To not flood mailboxes with flapping entities, an outage of the same
+/// entity is notified not more often than once per a configured interval.
+///
+/// Failing persisting of the [`State`] is notified too, as soon as it becomes
+/// unhealthy (see [`PersistenceHealth::is_healthy()`]), along with its
+/// recovery.
+#[derive(Debug)]
+pub struct OutagesNotifier {
+    /// [`Mailer`] to send e-mails with.
+    mailer: Mailer,
+
+    /// Name of this server to mention in e-mails.
+    server: String,
+
+    /// Duration an entity should stay not [`Status::Online`] for, to be
+    /// notified about.
+    offline_after: Duration,
+
+    /// Minimum interval between outage notifications of the same entity.
+    min_interval: Duration,
+
+    /// Ongoing outages of entities, identified by their IDs.
+    outages: HashMap<Uuid, Outage>,
+
+    /// Moments when the last outage notification has been sent for entities,
+    /// identified by their IDs.
+    last_notified: HashMap<Uuid, Instant>,
+
+    /// Indicator whether failing persisting of the [`State`] has been
+    /// notified about.
+    persistence_failing: bool,
+}
+
+impl OutagesNotifier {
+    /// Interval of checking statuses of entities.
+    pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Creates a new [`OutagesNotifier`] out of the given [`Opts`], if
+    /// [`Mailer`] is configured by them.
+    ///
+    /// # Errors
+    ///
+    /// If [`Mailer`] is misconfigured by the given [`Opts`].
+    pub fn new(opts: &Opts) -> Result<Option<Self>, anyhow::Error> {
+        Ok(Mailer::new(opts)?.map(|mailer| Self {
+            mailer,
+            server: opts.public_host.clone().unwrap_or_default(),
+            offline_after: opts.notify_offline_after,
+            min_interval: opts.notify_min_interval,
+            outages: HashMap::new(),
+            last_notified: HashMap::new(),
+            persistence_failing: false,
+        }))
+    }
+
+    /// Spawns periodical checking of entities in the given [`State`], sending
+    /// e-mails on their outages and recoveries.
+    ///
+    /// Failures of sending e-mails are logged only.
+    pub fn spawn(mut self, state: State) {
+        drop(tokio::spawn(async move {
+            loop {
+                time::delay_for(Self::CHECK_INTERVAL).await;
+
+                let server = state
+                    .settings
+                    .lock_ref()
+                    .title
+                    .clone()
+                    .filter(|t| !t.is_empty())
+                    .unwrap_or_else(|| self.server.clone());
+                let mut mails = self.check(&entities(&state), Instant::now());
+                mails.extend(
+                    self.check_persistence(
+                        &state.persistence_health.lock_ref(),
+                    ),
+                );
+                for (subject, body) in mails {
+                    let subject = format!("[{}] {}", server, subject);
+                    let mailer = self.mailer.clone();
+                    drop(tokio::spawn(async move {
+                        if let Err(e) = mailer.send(&subject, &body).await {
+                            log::error!(
+                                "Failed to send '{}' e-mail: {}",
+                                subject,
+                                e,
+                            );
+                        }
+                    }));
+                }
+            }
+        }));
+    }
+
+    /// Checks the given `entities` at the given moment, returning subjects
+    /// and bodies of e-mails to be sent.
+    fn check(
+        &mut self,
+        entities: &[Entity],
+        now: Instant,
+    ) -> Vec<(String, String)> {
+        let (min_interval, mut mails) = (self.min_interval, vec![]);
+        for e in entities {
+            if e.online {
+                if let Some(outage) = self.outages.remove(&e.id) {
+                    if outage.notified {
+                        mails.push((
+                            format!("{} is back online", e.name),
+                            format!(
+                                "{} is online again after being offline for \
+                                 {}.",
+                                e.name,
+                                humantime::format_duration(round_secs(
+                                    now.duration_since(outage.since),
+                                )),
+                            ),
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            let outage = self.outages.entry(e.id).or_insert(Outage {
+                since: now,
+                notified: false,
+            });
+            let lasts = now.duration_since(outage.since);
+            let is_throttled = self
+                .last_notified
+                .get(&e.id)
+                .map_or(false, |at| now.duration_since(*at) < min_interval);
+            if !outage.notified && lasts >= self.offline_after && !is_throttled
+            {
+                outage.notified = true;
+                let _ = self.last_notified.insert(e.id, now);
+                mails.push((
+                    format!("{} is offline", e.name),
+                    format!(
+                        "{} has been offline for {}.",
+                        e.name,
+                        humantime::format_duration(round_secs(lasts)),
+                    ),
+                ));
+            }
+        }
+
+        // Removed or disabled entities are not tracked anymore.
+        let ids: HashSet<_> = entities.iter().map(|e| e.id).collect();
+        self.outages.retain(|id, _| ids.contains(id));
+        self.last_notified
+            .retain(|_, at| now.duration_since(*at) < min_interval);
+
+        mails
+    }
+
+    /// Checks the given [`PersistenceHealth`], returning subject and body of
+    /// an e-mail to be sent, if it has become unhealthy or recovered.
+    fn check_persistence(
+        &mut self,
+        health: &PersistenceHealth,
+    ) -> Option<(String, String)> {
+        if self.persistence_failing == health.is_healthy() {
+            self.persistence_failing = !health.is_healthy();
+            return Some(if self.persistence_failing {
+                (
+                    "State persisting is failing".into(),
+                    format!(
+                        "Server state has failed to be persisted {} times in \
+                         a row, so changes made since then won't survive a \
+                         restart. The last error: {}",
+                        health.failures,
+                        health.last_error.as_deref().unwrap_or_default(),
+                    ),
+                )
+            } else {
+                (
+                    "State persisting is recovered".into(),
+                    "Server state is persisted successfully again.".into(),
+                )
+            });
+        }
+        None
+    }
+}
+
+/// Ongoing outage of an entity.
+#[derive(Clone, Copy, Debug)]
+struct Outage {
+    /// Moment the entity has stopped being [`Status::Online`] at.
+    since: Instant,
+
+    /// Indicator whether this [`Outage`] has been notified about.
+    notified: bool,
+}
+
+/// Entity whose outages are notified about.
+#[derive(Clone, Debug)]
+struct Entity {
+    /// ID of this [`Entity`].
+    id: Uuid,
+
+    /// Human-readable name of this [`Entity`] to mention in e-mails.
+    name: String,
+
+    /// Indicator whether this [`Entity`] is [`Status::Online`] at the moment.
+    online: bool,
+}
+
+/// Collects enabled [`state::Input`]s and [`state::Output`]s of the given
+/// [`State`] as [`Entity`]s.
+///
+/// [`state::Input`] is considered [`Status::Online`] once it's ready to serve
+/// a live stream (see [`state::Input::is_ready_to_serve()`]), while
+/// [`state::Output`] is considered so being [`Status::Unstable`] too, as it
+/// still delivers media.
+///
+/// [`state::Output`]s' destination URLs are never mentioned, as may contain
+/// secret stream keys.
+fn entities(state: &State) -> Vec<Entity> {
+    let mut entities = vec![];
+    let restreams = state.restreams.lock_ref();
+    let statuses = state.statuses.lock_ref();
+    for r in restreams.iter() {
+        let restream = r
+            .label
+            .as_ref()
+            .map_or_else(|| r.key.to_string(), ToString::to_string);
+        if r.input.enabled {
+            entities.push(Entity {
+                id: r.input.id.into(),
+                name: format!("Input '{}' of '{}'", r.input.key, restream),
+                online: r.input.is_ready_to_serve(&statuses),
+            });
+        }
+        for o in r.outputs.iter().filter(|o| o.enabled) {
+            let output = o
+                .label
+                .as_ref()
+                .map_or_else(|| o.id.to_string(), ToString::to_string);
+            entities.push(Entity {
+                id: o.id.into(),
+                name: format!("Output '{}' of '{}'", output, restream),
+                online: matches!(
+                    statuses.get(o.id),
+                    Status::Online | Status::Unstable,
+                ),
+            });
+        }
+    }
+    entities
+}
+
+/// Rounds the given [`Duration`] to whole seconds, for human-readable output.
+#[inline]
+fn round_secs(d: Duration) -> Duration {
+    Duration::from_secs(d.as_secs())
+}
+
+#[cfg(test)]
+mod outages_notifier_spec {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use uuid::Uuid;
+
+    use crate::state::PersistenceHealth;
+
+    use super::{Entity, Mailer, OutagesNotifier, SmtpTls};
+
+    fn notifier() -> OutagesNotifier {
+        OutagesNotifier {
+            mailer: Mailer {
+                host: "localhost".into(),
+                port: 25,
+                tls: SmtpTls::None,
+                credentials: None,
+                from: "ephyr@example.com".into(),
+                to: vec!["oncall@example.com".into()],
+            },
+            server: "ephyr".into(),
+            offline_after: Duration::from_secs(60),
+            min_interval: Duration::from_secs(600),
+            outages: HashMap::new(),
+            last_notified: HashMap::new(),
+            persistence_failing: false,
+        }
+    }
+
+    fn entity(id: Uuid, online: bool) -> Vec<Entity> {
+        vec![Entity {
+            id,
+            name: "Output 'YouTube' of 'main'".into(),
+            online,
+        }]
+    }
+
+    #[test]
+    fn notifies_prolonged_outage_and_recovery() {
+        let (mut n, id, start) = (notifier(), Uuid::new_v4(), Instant::now());
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(n.check(&entity(id, false), at(0)).is_empty());
+        assert!(n.check(&entity(id, false), at(30)).is_empty());
+
+        let mails = n.check(&entity(id, false), at(61));
+        assert_eq!(mails.len(), 1);
+        assert!(mails[0].0.ends_with("is offline"));
+        assert!(n.check(&entity(id, false), at(120)).is_empty());
+
+        let mails = n.check(&entity(id, true), at(130));
+        assert_eq!(mails.len(), 1);
+        assert!(mails[0].0.ends_with("is back online"));
+        assert!(mails[0].1.contains("2m 10s"));
+    }
+
+    #[test]
+    fn ignores_short_outages() {
+        let (mut n, id, start) = (notifier(), Uuid::new_v4(), Instant::now());
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(n.check(&entity(id, false), at(0)).is_empty());
+        assert!(n.check(&entity(id, true), at(59)).is_empty());
+        assert!(n.check(&entity(id, false), at(60)).is_empty());
+        assert!(n.check(&entity(id, false), at(119)).is_empty());
+    }
+
+    #[test]
+    fn throttles_flapping_entity() {
+        let (mut n, id, start) = (notifier(), Uuid::new_v4(), Instant::now());
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let _ = n.check(&entity(id, false), at(0));
+        assert_eq!(n.check(&entity(id, false), at(60)).len(), 1);
+        assert_eq!(n.check(&entity(id, true), at(70)).len(), 1);
+
+        let _ = n.check(&entity(id, false), at(80));
+        assert!(n.check(&entity(id, false), at(200)).is_empty());
+        assert!(n.check(&entity(id, true), at(210)).is_empty());
+
+        let _ = n.check(&entity(id, false), at(300));
+        assert_eq!(n.check(&entity(id, false), at(660)).len(), 1);
+    }
+
+    #[test]
+    fn notifies_failing_persistence_and_recovery() {
+        let mut n = notifier();
+        let health = |failures| PersistenceHealth {
+            failures,
+            last_error: Some("No space left on device".into()),
+        };
+
+        assert!(n.check_persistence(&health(1)).is_none());
+
+        let (subject, body) = n
+            .check_persistence(&health(PersistenceHealth::MAX_FAILURES))
+            .unwrap();
+        assert!(subject.ends_with("is failing"));
+        assert!(body.contains("No space left on device"), "{}", body);
+        assert!(n.check_persistence(&health(10)).is_none());
+
+        let (subject, _) =
+            n.check_persistence(&PersistenceHealth::default()).unwrap();
+        assert!(subject.ends_with("is recovered"));
+        assert!(n.check_persistence(&PersistenceHealth::default()).is_none());
+    }
+}
+
+#[cfg(test)]
+mod mailer_spec {
+    use super::{Mailer, SmtpTls};
+
+    #[test]
+    fn formats_dot_stuffed_message() {
+        let mailer = Mailer {
+            host: "localhost".into(),
+            port: 25,
+            tls: SmtpTls::None,
+            credentials: None,
+            from: "ephyr@example.com".into(),
+            to: vec!["a@example.com".into(), "b@example.com".into()],
+        };
+
+        let msg = mailer.message(
+            "Output 'Ютуб' is offline",
+            "First line\n.hidden\nLast line",
+            "Tue, 1 Jul 2003 10:52:37 +0000".into(),
+        );
+
+        assert!(msg.contains("To: <a@example.com>, <b@example.com>\r\n"));
+        assert!(msg.contains("Subject: =?UTF-8?B?"));
+        assert!(msg.ends_with("\r\nFirst line\r\n..hidden\r\nLast line\r\n."));
+    }
+}
@@ -0,0 +1,126 @@
+//! [Prometheus] metrics of this server.
+//!
+//! [Prometheus]: https://prometheus.io
+
+use std::fmt::Write as _;
+
+use crate::{
+    ffmpeg,
+    state::{OutputId, OutputProcess, RestreamKey, State},
+};
+
+/// Renders the current [Prometheus] metrics of the given [`State`] in
+/// [text exposition format][1].
+///
+/// [Prometheus]: https://prometheus.io
+/// [1]: https://prometheus.io/docs/instrumenting/exposition_formats
+#[must_use]
+pub fn render(state: &State) -> String {
+    let processes: Vec<_> = state
+        .restreams
+        .lock_ref()
+        .iter()
+        .flat_map(|r| {
+            r.outputs.iter().filter_map(move |o| {
+                let process = ffmpeg::Stats::global().get(o.id)?.process()?;
+                Some((r.key.clone(), o.id, process))
+            })
+        })
+        .collect();
+    let mut out = render_processes(&processes);
+    render_repairs(&mut out, ffmpeg::RestreamersPool::repairs());
+    out
+}
+
+/// Renders the counter of the given number of divergences repaired by
+/// [`ffmpeg::RestreamersPool::audit()`]s into the given `out`put in
+/// [text exposition format][1].
+///
+/// [1]: https://prometheus.io/docs/instrumenting/exposition_formats
+fn render_repairs(out: &mut String, repairs: u64) {
+    let name = "ephyr_pool_repairs_total";
+    let _ = writeln!(
+        out,
+        "# HELP {} Number of divergences between the state and the running \
+         re-streaming processes, repaired by auditing.",
+        name,
+    );
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, repairs);
+}
+
+/// Renders gauges of the given running re-streaming processes of
+/// [`state::Output`]s in [text exposition format][1].
+///
+/// [`state::Output`]: crate::state::Output
+/// [1]: https://prometheus.io/docs/instrumenting/exposition_formats
+fn render_processes(
+    processes: &[(RestreamKey, OutputId, OutputProcess)],
+) -> String {
+    let mut out = String::new();
+    let gauges: [(&str, &str, fn(&OutputProcess) -> Option<f64>); 2] = [
+        (
+            "ephyr_output_process_cpu_percent",
+            "CPU usage of output's re-streaming process, in percents of a \
+             single CPU core.",
+            |p| p.cpu_percent,
+        ),
+        (
+            "ephyr_output_process_memory_bytes",
+            "Resident memory size of output's re-streaming process, in bytes.",
+            |p| p.memory_bytes,
+        ),
+    ];
+    for (name, help, value) in &gauges {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (key, id, process) in processes {
+            if let Some(v) = value(process) {
+                let _ = writeln!(
+                    out,
+                    "{}{{restream=\"{}\",output=\"{}\"}} {}",
+                    name, key, id, v,
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod render_spec {
+    use crate::state::{OutputId, OutputProcess, RestreamKey};
+
+    use super::{render_processes, render_repairs};
+
+    #[test]
+    fn renders_sampled_gauges_only() {
+        let id = OutputId::random();
+        let out = render_processes(&[(
+            RestreamKey::new("guest").unwrap(),
+            id,
+            OutputProcess {
+                pid: 42,
+                cpu_percent: None,
+                memory_bytes: Some(1024.0),
+            },
+        )]);
+
+        assert!(out.contains("# TYPE ephyr_output_process_cpu_percent gauge"));
+        assert!(!out.contains("ephyr_output_process_cpu_percent{"));
+        assert!(out.contains(&format!(
+            "ephyr_output_process_memory_bytes{{restream=\"guest\",\
+             output=\"{}\"}} 1024\n",
+            id,
+        )));
+    }
+
+    #[test]
+    fn renders_repairs_counter() {
+        let mut out = String::new();
+        render_repairs(&mut out, 3);
+
+        assert!(out.contains("# TYPE ephyr_pool_repairs_total counter\n"));
+        assert!(out.ends_with("\nephyr_pool_repairs_total 3\n"));
+    }
+}
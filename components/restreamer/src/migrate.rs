@@ -0,0 +1,980 @@
+//! Migration of a hand-rolled [SRS] setup (its configuration file along with
+//! shell scripts running [FFmpeg] to push live streams onto destinations) into
+//! a [`Spec`] of this application.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+//! [SRS]: https://github.com/ossrs/srs
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, mem,
+    path::Path,
+};
+
+use serde_json::json;
+
+use crate::{
+    cli::{Failure, ImportSrsConfOpts},
+    spec::{self, Spec},
+};
+
+/// Runs [`cli::Command::ImportSrsConf`], printing the generated [`Spec`] to
+/// STDOUT, and every [`Note`] about the fragments which cannot be converted
+/// as is to STDERR.
+///
+/// # Errors
+///
+/// If the SRS configuration file cannot be read, or the generated [`Spec`]
+/// is invalid. The error is printed to STDERR.
+///
+/// [`cli::Command::ImportSrsConf`]: crate::cli::Command::ImportSrsConf
+pub fn run(opts: &ImportSrsConfOpts) -> Result<(), Failure> {
+    let mut migration = Migration::default();
+
+    let conf = fs::read_to_string(&opts.path).map_err(|e| {
+        eprintln!("Failed to read '{}' file: {}", opts.path.display(), e)
+    })?;
+    migration.parse_conf(&opts.path.display().to_string(), &conf);
+
+    if let Some(dir) = &opts.scripts_dir {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .map_err(|e| {
+                eprintln!("Failed to read '{}' dir: {}", dir.display(), e)
+            })?
+            .filter_map(|e| Some(e.ok()?.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        paths.sort();
+        for path in paths {
+            match fs::read_to_string(&path) {
+                Ok(script) => {
+                    migration.parse_script(&path.display().to_string(), &script)
+                }
+                Err(e) => {
+                    eprintln!("Skipped '{}' file: {}", path.display(), e)
+                }
+            }
+        }
+    }
+
+    let (spec, notes) = migration.into_spec();
+    for note in &notes {
+        eprintln!("{}", note);
+    }
+    let violations = spec.validate();
+    if !violations.is_empty() {
+        for v in &violations {
+            eprintln!("{}", v);
+        }
+        return Err(Failure);
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Spec::V1(spec))
+            .map_err(|e| eprintln!("Failed to serialize spec: {}", e))?,
+    );
+    Ok(())
+}
+
+/// Location of a fragment in a migrated file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    /// Name of the file the fragment is located in.
+    pub file: String,
+
+    /// Number of the line the fragment starts at (1-based).
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Note about a fragment of a migrated file, which cannot be converted, or is
+/// converted not as is.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Note {
+    /// [`Location`] of the fragment.
+    pub at: Location,
+
+    /// Human-readable description of this [`Note`].
+    pub message: String,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.at, self.message)
+    }
+}
+
+/// Live stream published into the migrated SRS.
+#[derive(Clone, Debug)]
+struct Stream {
+    /// [`Location`] the live stream is first mentioned at.
+    origin: Location,
+
+    /// URL the live stream is pulled from by SRS, if it's not pushed.
+    src: Option<String>,
+
+    /// Destinations the live stream is pushed onto, along with the
+    /// [`Location`]s they're mentioned at.
+    dsts: Vec<(String, Location)>,
+}
+
+/// Migration of an SRS setup, accumulating the live streams and their
+/// destinations from the parsed files.
+#[derive(Debug, Default)]
+pub struct Migration {
+    /// Live streams mentioned in the parsed files, keyed by their SRS vhost,
+    /// app and stream names.
+    streams: BTreeMap<(String, String, String), Stream>,
+
+    /// SRS apps mentioned without any concrete live stream, keyed by their
+    /// SRS vhost and app names.
+    apps: BTreeMap<(String, String), Location>,
+
+    /// Destinations applying to every live stream of an SRS vhost, with
+    /// `[vhost]`, `[app]` and `[stream]` placeholders, keyed by the vhost name.
+    templates: BTreeMap<String, Vec<(String, Location)>>,
+
+    /// [`Note`]s about the fragments not converted as is.
+    notes: Vec<Note>,
+}
+
+impl Migration {
+    /// Default SRS vhost, used when no other one is specified.
+    const DEFAULT_VHOST: &'static str = "__defaultVhost__";
+
+    /// Name of the [`state::Input`] an SRS app is published into, if none of
+    /// its live streams is known.
+    ///
+    /// [`state::Input`]: crate::state::Input
+    const DEFAULT_STREAM: &'static str = "origin";
+
+    /// Parses the SRS configuration file `contents` of the given `file`,
+    /// collecting the apps and live streams of its vhosts, along with their
+    /// `ingest`ed sources, and `forward` and `exec` destinations.
+    pub fn parse_conf(&mut self, file: &str, contents: &str) {
+        for d in parse_directives(file, contents, &mut self.notes) {
+            match (d.name.as_str(), &d.block) {
+                ("vhost", Some(block)) => {
+                    let vhost = d
+                        .args
+                        .first()
+                        .map_or(Self::DEFAULT_VHOST, String::as_str);
+                    self.parse_vhost(vhost, file, block);
+                }
+                ("vhost", None) => self.note(file, d.line, "vhost without {}"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses the [`Directive`]s of a single SRS `vhost`.
+    fn parse_vhost(
+        &mut self,
+        vhost: &str,
+        file: &str,
+        directives: &[Directive],
+    ) {
+        for d in directives {
+            let block = match &d.block {
+                Some(block) if is_enabled(block) => block,
+                _ => continue,
+            };
+            match d.name.as_str() {
+                "transcode" => {
+                    let scope = d.args.first().map_or("", String::as_str);
+                    let mut parts = scope.splitn(2, '/');
+                    match (parts.next().unwrap_or_default(), parts.next()) {
+                        ("", _) => {
+                            self.note(file, d.line, "transcode without an app");
+                            continue;
+                        }
+                        (app, Some(stream)) => {
+                            let _ =
+                                self.stream(vhost, app, stream, file, d.line);
+                        }
+                        (app, None) => {
+                            let _ = self
+                                .apps
+                                .entry((vhost.into(), app.into()))
+                                .or_insert_with(|| loc(file, d.line));
+                        }
+                    }
+                    self.note(
+                        file,
+                        d.line,
+                        "transcoding is not converted, set up the outputs' \
+                         re-encoding instead",
+                    );
+                }
+                "ingest" => self.parse_ingest(vhost, file, d, block),
+                "forward" => {
+                    let templates =
+                        self.templates.entry(vhost.into()).or_default();
+                    for dst in block
+                        .iter()
+                        .filter(|d| d.name == "destination")
+                        .flat_map(|d| d.args.iter().map(move |a| (a, d.line)))
+                    {
+                        templates.push((
+                            format!("rtmp://{}/[app]/[stream]", dst.0),
+                            loc(file, dst.1),
+                        ));
+                    }
+                }
+                "exec" => {
+                    for cmd in block.iter().filter(|d| d.name == "publish") {
+                        match parse_ffmpeg(&cmd.args) {
+                            Ok((_, dsts)) => {
+                                self.templates
+                                    .entry(vhost.into())
+                                    .or_default()
+                                    .extend(
+                                        dsts.into_iter().map(|dst| {
+                                            (dst, loc(file, cmd.line))
+                                        }),
+                                    );
+                            }
+                            Err(e) => self.note(file, cmd.line, &e),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses the `ingest` [`Directive`] of an SRS `vhost`, pulling a live
+    /// stream from a remote source.
+    fn parse_ingest(
+        &mut self,
+        vhost: &str,
+        file: &str,
+        d: &Directive,
+        block: &[Directive],
+    ) {
+        let url = block
+            .iter()
+            .filter(|d| d.name == "input")
+            .filter_map(|d| d.block.as_ref())
+            .flatten()
+            .find(|d| d.name == "url")
+            .and_then(|d| d.args.first());
+        let output = block
+            .iter()
+            .chain(
+                block
+                    .iter()
+                    .filter(|d| d.name == "engine")
+                    .filter_map(|d| d.block.as_ref())
+                    .flatten(),
+            )
+            .find(|d| d.name == "output")
+            .and_then(|d| d.args.first())
+            .and_then(|o| app_stream(&o.replace("[vhost]", vhost)));
+        match (url, output) {
+            (Some(url), Some((vhost, app, stream))) => {
+                let url = url.clone();
+                self.stream(&vhost, &app, &stream, file, d.line).src =
+                    Some(url);
+            }
+            _ => self.note(
+                file,
+                d.line,
+                "ingest without an input URL or an output RTMP stream",
+            ),
+        }
+    }
+
+    /// Parses the shell `script` of the given `file`, collecting the live
+    /// streams and destinations of every [FFmpeg] command in it.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub fn parse_script(&mut self, file: &str, script: &str) {
+        for (line, cmd) in logical_lines(script) {
+            let words = match split_words(&cmd) {
+                Ok(words) => words,
+                Err(e) => {
+                    self.note(file, line, &e);
+                    continue;
+                }
+            };
+            let is_ffmpeg = |w: &String| {
+                Path::new(w).file_name().map_or(false, |n| n == "ffmpeg")
+            };
+            if !words.iter().any(is_ffmpeg) {
+                continue;
+            }
+            if words.iter().any(|w| w.contains('$')) {
+                self.note(file, line, "shell variables cannot be resolved");
+                continue;
+            }
+            let (src, dsts) = match parse_ffmpeg(&words) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    self.note(file, line, &e);
+                    continue;
+                }
+            };
+            match app_stream(&src) {
+                Some((vhost, app, stream)) => {
+                    let s = self.stream(&vhost, &app, &stream, file, line);
+                    for dst in dsts {
+                        s.dsts.push((dst, loc(file, line)));
+                    }
+                }
+                None => self.note(
+                    file,
+                    line,
+                    &format!("input `{}` is not an RTMP stream", src),
+                ),
+            }
+        }
+    }
+
+    /// Converts this [`Migration`] into a [`spec::v1::Spec`], returning it
+    /// along with the [`Note`]s about the fragments not converted as is.
+    ///
+    /// Each live stream is converted into a [`spec::v1::Restream`] keyed by
+    /// its SRS app (or by its app and stream, if the app has many of them),
+    /// with its destinations converted into the disabled
+    /// [`spec::v1::Output`]s, so nothing goes live before being reviewed.
+    /// Live streams of a non-default SRS vhost have their keys prefixed with
+    /// it, and are grouped by it.
+    #[must_use]
+    pub fn into_spec(mut self) -> (spec::v1::Spec, Vec<Note>) {
+        for ((vhost, app), at) in mem::take(&mut self.apps) {
+            if self
+                .streams
+                .keys()
+                .any(|(v, a, _)| *v == vhost && *a == app)
+            {
+                continue;
+            }
+            self.notes.push(Note {
+                at: at.clone(),
+                message: format!(
+                    "streams of `{}` app are unknown, so it's published as \
+                     `{}/{}`",
+                    app,
+                    app,
+                    Self::DEFAULT_STREAM,
+                ),
+            });
+            let _ = self.stream(
+                &vhost,
+                &app,
+                Self::DEFAULT_STREAM,
+                &at.file,
+                at.line,
+            );
+        }
+
+        let mut per_app = BTreeMap::<(&str, &str), usize>::new();
+        for (vhost, app, _) in self.streams.keys() {
+            *per_app.entry((vhost, app)).or_default() += 1;
+        }
+
+        let mut keys = BTreeSet::new();
+        let mut restreams = vec![];
+        for ((vhost, app, stream), s) in &self.streams {
+            let mut key = if per_app[&(vhost.as_str(), app.as_str())] > 1 {
+                format!("{}-{}", app, stream)
+            } else {
+                app.clone()
+            };
+            let is_default_vhost = vhost == Self::DEFAULT_VHOST;
+            if !is_default_vhost {
+                let prefix: String = vhost
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '_' {
+                            c
+                        } else {
+                            '-'
+                        }
+                    })
+                    .collect();
+                key = format!("{}-{}", prefix, key);
+            }
+            if !keys.insert(key.clone()) {
+                self.notes.push(Note {
+                    at: s.origin.clone(),
+                    message: format!("`{}` restream key is duplicated", key),
+                });
+                continue;
+            }
+
+            let templated =
+                self.templates.get(vhost).into_iter().flatten().map(
+                    |(dst, at)| {
+                        let dst = dst
+                            .replace("[app]", app)
+                            .replace("[stream]", stream)
+                            .replace("[vhost]", vhost);
+                        (dst, at.clone())
+                    },
+                );
+            let mut dsts = BTreeSet::new();
+            let mut outputs = vec![];
+            for (dst, at) in s.dsts.iter().cloned().chain(templated) {
+                if !dsts.insert(dst.clone()) {
+                    continue;
+                }
+                let output = json!({ "dst": dst });
+                match serde_json::from_value::<spec::v1::Output>(output.clone())
+                {
+                    Ok(_) => outputs.push(output),
+                    Err(e) => self.notes.push(Note {
+                        at,
+                        message: format!(
+                            "invalid destination `{}`: {}",
+                            dst, e
+                        ),
+                    }),
+                }
+            }
+
+            let mut input = json!({
+                "key": stream,
+                "endpoints": [{"kind": "rtmp"}],
+                "enabled": true,
+            });
+            if let Some(src) = &s.src {
+                input["src"] = json!({ "remote_url": src });
+            }
+            let mut restream = json!({
+                "key": key,
+                "input": input,
+                "outputs": outputs,
+            });
+            if !is_default_vhost {
+                restream["group"] = json!(vhost);
+            }
+            match serde_json::from_value::<spec::v1::Restream>(restream) {
+                Ok(r) => restreams.push(r),
+                Err(e) => self.notes.push(Note {
+                    at: s.origin.clone(),
+                    message: format!(
+                        "cannot convert `{}/{}` stream of `{}` vhost: {}",
+                        app, stream, vhost, e,
+                    ),
+                }),
+            }
+        }
+
+        let spec = spec::v1::Spec {
+            settings: None,
+            credentials: None,
+            restreams,
+        };
+        (spec, self.notes)
+    }
+
+    /// Returns the [`Stream`] with the given SRS `vhost`, `app` and `stream`
+    /// names, registering it as mentioned at the given `line` of the `file`,
+    /// if it wasn't mentioned before.
+    fn stream(
+        &mut self,
+        vhost: &str,
+        app: &str,
+        stream: &str,
+        file: &str,
+        line: usize,
+    ) -> &mut Stream {
+        self.streams
+            .entry((vhost.into(), app.into(), stream.into()))
+            .or_insert_with(|| Stream {
+                origin: loc(file, line),
+                src: None,
+                dsts: vec![],
+            })
+    }
+
+    /// Adds a [`Note`] about the fragment at the given `line` of the `file`.
+    fn note(&mut self, file: &str, line: usize, message: &str) {
+        self.notes.push(Note {
+            at: loc(file, line),
+            message: message.into(),
+        });
+    }
+}
+
+/// Creates a new [`Location`] of the given `line` in the given `file`.
+fn loc(file: &str, line: usize) -> Location {
+    Location {
+        file: file.into(),
+        line,
+    }
+}
+
+/// Directive of an SRS configuration file, like `listen 1935;` or
+/// `vhost __defaultVhost__ { ... }`.
+#[derive(Clone, Debug)]
+struct Directive {
+    /// Name of this [`Directive`].
+    name: String,
+
+    /// Arguments of this [`Directive`].
+    args: Vec<String>,
+
+    /// Number of the line this [`Directive`] starts at (1-based).
+    line: usize,
+
+    /// Nested [`Directive`]s, if this one is a block.
+    block: Option<Vec<Directive>>,
+}
+
+/// Token of an SRS configuration file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    /// Name or argument of a [`Directive`].
+    Word(String),
+
+    /// `{` opening a block.
+    Open,
+
+    /// `}` closing a block.
+    Close,
+
+    /// `;` ending a [`Directive`].
+    End,
+}
+
+/// Parses the SRS configuration file `contents` of the given `file` into
+/// [`Directive`]s leniently, adding a [`Note`] about every unparseable
+/// fragment instead of failing.
+fn parse_directives(
+    file: &str,
+    contents: &str,
+    notes: &mut Vec<Note>,
+) -> Vec<Directive> {
+    let mut note = |line, message: String| {
+        notes.push(Note {
+            at: loc(file, line),
+            message,
+        });
+    };
+
+    let mut tokens = vec![];
+    for (n, text) in contents.lines().enumerate() {
+        let line = n + 1;
+        let mut chars = text.chars();
+        let mut word = String::new();
+        let flush = |word: &mut String, tokens: &mut Vec<_>| {
+            if !word.is_empty() {
+                tokens.push((Token::Word(mem::take(word)), line));
+            }
+        };
+        while let Some(c) = chars.next() {
+            match c {
+                '#' => break,
+                '{' | '}' | ';' => {
+                    flush(&mut word, &mut tokens);
+                    let token = match c {
+                        '{' => Token::Open,
+                        '}' => Token::Close,
+                        _ => Token::End,
+                    };
+                    tokens.push((token, line));
+                }
+                '"' | '\'' => {
+                    let mut closed = false;
+                    for q in chars.by_ref() {
+                        if q == c {
+                            closed = true;
+                            break;
+                        }
+                        word.push(q);
+                    }
+                    if !closed {
+                        note(line, "unterminated quoted string".into());
+                        word.clear();
+                    }
+                }
+                c if c.is_whitespace() => flush(&mut word, &mut tokens),
+                c => word.push(c),
+            }
+        }
+        flush(&mut word, &mut tokens);
+    }
+
+    let mut current = vec![];
+    let mut opened: Vec<(Directive, Vec<Directive>)> = vec![];
+    let mut words: Vec<String> = vec![];
+    let mut start = 0;
+    let directive = |words: &mut Vec<String>, line| {
+        let mut words = mem::take(words).into_iter();
+        Directive {
+            name: words.next().unwrap_or_default(),
+            args: words.collect(),
+            line,
+            block: None,
+        }
+    };
+    for (token, line) in tokens {
+        match token {
+            Token::Word(w) => {
+                if words.is_empty() {
+                    start = line;
+                }
+                words.push(w);
+            }
+            Token::End => {
+                if !words.is_empty() {
+                    current.push(directive(&mut words, start));
+                }
+            }
+            Token::Open => {
+                if words.is_empty() {
+                    note(line, "block without a name".into());
+                    start = line;
+                }
+                let header = directive(&mut words, start);
+                opened.push((header, mem::take(&mut current)));
+            }
+            Token::Close => {
+                if !words.is_empty() {
+                    note(start, format!("missing `;` after `{}`", words[0]));
+                    words.clear();
+                }
+                match opened.pop() {
+                    Some((mut header, parent)) => {
+                        header.block = Some(mem::replace(&mut current, parent));
+                        current.push(header);
+                    }
+                    None => note(line, "unexpected `}`".into()),
+                }
+            }
+        }
+    }
+    if !words.is_empty() {
+        note(start, format!("missing `;` after `{}`", words[0]));
+    }
+    while let Some((mut header, parent)) = opened.pop() {
+        note(header.line, format!("unclosed `{}` block", header.name));
+        header.block = Some(mem::replace(&mut current, parent));
+        current.push(header);
+    }
+    current
+}
+
+/// Indicates whether the given block of [`Directive`]s is not turned off with
+/// `enabled off;`.
+fn is_enabled(block: &[Directive]) -> bool {
+    !block.iter().any(|d| {
+        d.name == "enabled" && d.args.first().map_or(false, |a| a == "off")
+    })
+}
+
+/// Joins the lines of the given shell `script` continued with `\`, returning
+/// each logical line along with the number of the line it starts at.
+fn logical_lines(script: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut pending: Option<(usize, String)> = None;
+    for (n, text) in script.lines().enumerate() {
+        let (start, mut joined) =
+            pending.take().unwrap_or_else(|| (n + 1, String::new()));
+        match text.strip_suffix('\\') {
+            Some(text) => {
+                joined.push_str(text);
+                joined.push(' ');
+                pending = Some((start, joined));
+            }
+            None => {
+                joined.push_str(text);
+                lines.push((start, joined));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// Splits the given shell command `line` into words, honoring quotes and
+/// escapes, and stopping at a comment or a control operator.
+///
+/// # Errors
+///
+/// If the `line` has an unterminated quoted string.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' if word.is_none() => break,
+            '|' | '&' | ';' | '<' | '>' => break,
+            '\\' => {
+                if let Some(e) = chars.next() {
+                    word.get_or_insert_with(String::new).push(e);
+                }
+            }
+            '"' | '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                let mut closed = false;
+                while let Some(q) = chars.next() {
+                    if q == c {
+                        closed = true;
+                        break;
+                    }
+                    if q == '\\' && c == '"' {
+                        w.extend(chars.next());
+                    } else {
+                        w.push(q);
+                    }
+                }
+                if !closed {
+                    return Err("unterminated quoted string".into());
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    // Redirections like `2>&1` leave a dangling file descriptor number.
+    if words
+        .last()
+        .map_or(false, |w| w.chars().all(|c| c.is_ascii_digit()))
+    {
+        let _ = words.pop();
+    }
+    Ok(words)
+}
+
+/// Parses the given [FFmpeg] command `words`, returning the URL of its input
+/// along with the URLs of its destinations.
+///
+/// # Errors
+///
+/// If the command has no single input or no destinations.
+///
+/// [FFmpeg]: https://ffmpeg.org
+fn parse_ffmpeg(words: &[String]) -> Result<(String, Vec<String>), String> {
+    let inputs: Vec<_> = words
+        .iter()
+        .zip(words.iter().skip(1))
+        .filter(|(opt, _)| *opt == "-i")
+        .map(|(_, src)| src.clone())
+        .collect();
+    let src = match inputs.as_slice() {
+        [src] => src.clone(),
+        [] => return Err("FFmpeg command without an input".into()),
+        _ => return Err("FFmpeg command mixing many inputs".into()),
+    };
+    let dsts: Vec<_> = words
+        .iter()
+        .filter(|w| **w != src)
+        .filter(|w| {
+            ["icecast://", "rtmp://", "rtmps://", "srt://"]
+                .iter()
+                .any(|s| w.starts_with(s))
+        })
+        .cloned()
+        .collect();
+    if dsts.is_empty() {
+        return Err("FFmpeg command without destinations".into());
+    }
+    Ok((src, dsts))
+}
+
+/// Extracts the SRS vhost, app and stream names from the given RTMP `url`,
+/// like `rtmp://127.0.0.1:1935/live?vhost=example.com/stream`.
+///
+/// The vhost is taken from the `vhost` query parameter of either the app or
+/// the stream, defaulting to the [`Migration::DEFAULT_VHOST`].
+fn app_stream(url: &str) -> Option<(String, String, String)> {
+    let rest = url.strip_prefix("rtmp://")?;
+    let mut segments = rest.split('/').skip(1);
+    let mut app = segments.next()?.splitn(2, '?');
+    let mut stream = segments.next()?.splitn(2, '?');
+    if segments.next().is_some() {
+        return None;
+    }
+    let (app, app_query) = (app.next()?, app.next());
+    let (stream, stream_query) = (stream.next()?, stream.next());
+    if app.is_empty() || stream.is_empty() {
+        return None;
+    }
+    let vhost = app_query
+        .into_iter()
+        .chain(stream_query)
+        .flat_map(|q| q.split('&'))
+        .find_map(|p| p.strip_prefix("vhost="))
+        .filter(|v| !v.is_empty())
+        .unwrap_or(Migration::DEFAULT_VHOST);
+    Some((vhost.into(), app.into(), stream.into()))
+}
+
+#[cfg(test)]
+mod migration_spec {
+    use crate::State;
+
+    use super::Migration;
+
+    const CONF: &str = r#"
+listen 1935;
+vhost __defaultVhost__ {
+    forward {
+        enabled on;
+        destination backup.example.com:1935;
+    }
+    exec {
+        enabled off;
+        publish ./ffmpeg -i rtmp://h/[app]/[stream] -f flv rtmp://off/[app];
+    }
+    transcode show {
+        enabled on;
+    }
+    ingest camera {
+        enabled on;
+        input {
+            type stream;
+            url rtmp://camera.example.com/live/main;
+        }
+        engine {
+            enabled off;
+            output rtmp://127.0.0.1:[port]/cams?vhost=[vhost]/main;
+        }
+    }
+}
+"#;
+
+    const SCRIPT: &str = r#"#!/bin/sh
+# Pushes the main stream to YouTube.
+ffmpeg -re -i "rtmp://127.0.0.1/live/main" -c copy \
+    -f flv rtmp://a.rtmp.youtube.com/live2/secret >/dev/null 2>&1 &
+ffmpeg -i rtmp://127.0.0.1/live/extra -c copy -f flv rtmp://b.example.com/x
+echo done
+"#;
+
+    fn migrate(conf: &str, script: &str) -> Migration {
+        let mut migration = Migration::default();
+        migration.parse_conf("srs.conf", conf);
+        migration.parse_script("push.sh", script);
+        migration
+    }
+
+    #[test]
+    fn converts_streams_and_destinations() {
+        let (spec, notes) = migrate(CONF, SCRIPT).into_spec();
+
+        assert!(notes.iter().any(|n| n.message.contains("`show/origin`")));
+        let keys: Vec<_> =
+            spec.restreams.iter().map(|r| r.key.to_string()).collect();
+        assert_eq!(keys, ["cams", "live-extra", "live-main", "show"]);
+
+        let main = &spec.restreams[2];
+        assert_eq!(main.input.key.to_string(), "main");
+        let dsts: Vec<_> =
+            main.outputs.iter().map(|o| o.dst.to_string()).collect();
+        assert_eq!(
+            dsts,
+            [
+                "rtmp://a.rtmp.youtube.com/live2/secret",
+                "rtmp://backup.example.com:1935/live/main",
+            ],
+        );
+        assert!(main.outputs.iter().all(|o| !o.enabled));
+
+        let cams = &spec.restreams[0];
+        assert!(cams.input.src.is_some());
+    }
+
+    #[test]
+    fn reports_unparseable_fragments_with_lines() {
+        let conf = "vhost a {\n    forward { destination x:1935 }\n";
+        let script = "ffmpeg -i rtmp://h/live/s -f flv $DST\n\
+                      ffmpeg -i 'rtmp://h/live/s\n\
+                      ffmpeg -i a.mp4 -f flv rtmp://h/x/y\n";
+
+        let (_, notes) = migrate(conf, script).into_spec();
+
+        let notes: Vec<_> = notes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            notes,
+            [
+                "srs.conf:2: missing `;` after `destination`",
+                "srs.conf:1: unclosed `vhost` block",
+                "push.sh:1: shell variables cannot be resolved",
+                "push.sh:2: unterminated quoted string",
+                "push.sh:3: input `a.mp4` is not an RTMP stream",
+            ],
+        );
+    }
+
+    #[test]
+    fn scopes_destinations_to_vhosts() {
+        let conf = r#"
+vhost __defaultVhost__ {
+    forward {
+        enabled on;
+        destination default.example.com;
+    }
+}
+vhost example.com {
+    exec {
+        enabled on;
+        publish ffmpeg -i rtmp://h/[app]/[stream] -f flv rtmp://e/[vhost];
+    }
+}
+"#;
+        let script = "ffmpeg -i rtmp://h/live/a -f flv rtmp://x/a\n\
+                      ffmpeg -i rtmp://h/live?vhost=example.com/b \
+                      -f flv rtmp://x/b\n";
+
+        let (spec, _) = migrate(conf, script).into_spec();
+
+        let restreams: Vec<_> = spec
+            .restreams
+            .iter()
+            .map(|r| {
+                let dsts: Vec<_> =
+                    r.outputs.iter().map(|o| o.dst.to_string()).collect();
+                (
+                    r.key.to_string(),
+                    r.group.as_ref().map(ToString::to_string),
+                    dsts,
+                )
+            })
+            .collect();
+        assert_eq!(
+            restreams,
+            [
+                (
+                    "live".to_owned(),
+                    None,
+                    vec![
+                        "rtmp://x/a".to_owned(),
+                        "rtmp://default.example.com/live/a".to_owned(),
+                    ],
+                ),
+                (
+                    "example-com-live".to_owned(),
+                    Some("example.com".to_owned()),
+                    vec![
+                        "rtmp://x/b".to_owned(),
+                        "rtmp://e/example.com".to_owned(),
+                    ],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn generates_valid_state() {
+        let (spec, _) = migrate(CONF, SCRIPT).into_spec();
+        assert!(spec.validate().is_empty());
+
+        let state = State::new_in_memory();
+        state.apply(spec, true);
+        let contents = serde_json::to_vec(&state).unwrap();
+
+        assert!(State::validate(&contents).is_empty());
+    }
+}
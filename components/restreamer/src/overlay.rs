@@ -0,0 +1,120 @@
+//! Image overlays (watermarks) of [`state::Output`]s.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use ephyr_log::log;
+use futures::{future, TryFutureExt as _, TryStreamExt as _};
+use tokio::{fs, process::Command, time};
+use uuid::Uuid;
+
+use crate::state::{self, OutputId};
+
+/// Maximum size (in bytes) of an overlay image uploaded via HTTP.
+pub const MAX_UPLOAD_SIZE: usize = 5 * 1024 * 1024;
+
+/// Signature every [PNG] file starts with.
+///
+/// [PNG]: https://en.wikipedia.org/wiki/Portable_Network_Graphics
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Maximum time a single [FFprobe] process is allowed to probe an image.
+///
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns path of the image uploaded as an overlay of the [`state::Output`]
+/// with the given `id` into the given `dir`.
+#[inline]
+#[must_use]
+pub fn uploaded_path(dir: &Path, id: OutputId) -> PathBuf {
+    dir.join(format!("{}.png", id))
+}
+
+/// Checks whether the given bytes represent a [PNG] file.
+///
+/// [PNG]: https://en.wikipedia.org/wiki/Portable_Network_Graphics
+#[inline]
+#[must_use]
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(PNG_SIGNATURE)
+}
+
+/// Probes the given `path` with a [FFprobe] process, checking whether it's an
+/// existing image decodable by [FFmpeg].
+///
+/// # Errors
+///
+/// If [FFprobe] process cannot be spawned.
+///
+/// [FFmpeg]: https://ffmpeg.org
+/// [FFprobe]: https://ffmpeg.org/ffprobe.html
+pub async fn probe(ffprobe_path: &Path, path: &Path) -> io::Result<bool> {
+    if !fs::metadata(path).await.map_or(false, |m| m.is_file()) {
+        return Ok(false);
+    }
+
+    let mut cmd = Command::new(ffprobe_path);
+    let _ = cmd
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .args(&["-v", "error", "-select_streams", "v:0"])
+        .args(&["-show_entries", "stream=codec_type", "-of", "csv=p=0"])
+        .arg(path);
+
+    match time::timeout(PROBE_TIMEOUT, cmd.output()).await {
+        Ok(Ok(out)) => Ok(out.status.success()
+            && String::from_utf8_lossy(&out.stdout).trim() == "video"),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Cleans up any uploaded overlay images in the given `dir` not being
+/// associated with [`state::Output`]s of the given renewed
+/// [`state::Restream`]s.
+pub async fn cleanup(dir: &Path, restreams: &[state::Restream]) {
+    fs::read_dir(dir)
+        .try_flatten_stream()
+        .try_filter(|i| {
+            let path = i.path();
+            future::ready(
+                path.file_stem()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| Uuid::parse_str(n).ok())
+                    .map_or(false, |id| {
+                        let id = OutputId::from(id);
+                        !restreams
+                            .iter()
+                            .any(|r| r.outputs.iter().any(|o| o.id == id))
+                    }),
+            )
+        })
+        .try_for_each_concurrent(4, |i| async move {
+            fs::remove_file(i.path()).await
+        })
+        .await
+        .unwrap_or_else(|e| {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::error!("Failed to cleanup overlay images: {}", e)
+            }
+        })
+}
+
+#[cfg(test)]
+mod is_png_spec {
+    use super::is_png;
+
+    #[test]
+    fn detects_png_signature() {
+        assert!(is_png(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_png(b"\xff\xd8\xff\xe0\0\x10JFIF"));
+        assert!(!is_png(b""));
+    }
+}
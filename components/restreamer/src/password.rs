@@ -0,0 +1,160 @@
+//! Passwords protecting access to the application's public APIs.
+
+use std::{
+    convert::Infallible,
+    io::{self, BufRead as _},
+};
+
+use actix_web::{error::BlockingError, web};
+use once_cell::sync::Lazy;
+use rand::Rng as _;
+
+use crate::cli::{Failure, HashPasswordOpts};
+
+/// Hashes the given `password` with [`argon2`], encoding it as
+/// [`Settings::password_hash`] expects.
+///
+/// [`Settings::password_hash`]: crate::state::Settings::password_hash
+#[must_use]
+pub fn hash(password: &str) -> String {
+    static CFG: Lazy<argon2::Config<'static>> =
+        Lazy::new(argon2::Config::default);
+
+    argon2::hash_encoded(
+        password.as_bytes(),
+        &rand::thread_rng().gen::<[u8; 32]>(),
+        &*CFG,
+    )
+    .unwrap()
+}
+
+/// Checks whether the given `password` matches the given encoded [`argon2`]
+/// `hash`.
+///
+/// Malformed `hash` matches no password.
+#[inline]
+#[must_use]
+pub fn verify(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()) == Ok(true)
+}
+
+/// Same as [`hash()`], but performs the hashing on the blocking threads
+/// pool (see [`cli::Opts::blocking_threads`]), so it doesn't stall the async
+/// executor.
+///
+/// # Errors
+///
+/// If the blocking threads pool fails to perform the hashing.
+///
+/// [`cli::Opts::blocking_threads`]: crate::cli::Opts::blocking_threads
+pub async fn hash_blocking(
+    password: String,
+) -> Result<String, BlockingError<Infallible>> {
+    web::block(move || Ok(hash(&password))).await
+}
+
+/// Same as [`verify()`], but performs the check on the blocking threads pool
+/// (see [`cli::Opts::blocking_threads`]), so it doesn't stall the async
+/// executor.
+///
+/// Matches no password if the blocking threads pool fails to perform the
+/// check.
+///
+/// [`cli::Opts::blocking_threads`]: crate::cli::Opts::blocking_threads
+pub async fn verify_blocking(hash: String, password: String) -> bool {
+    web::block(move || Ok::<_, Infallible>(verify(&hash, &password)))
+        .await
+        .unwrap_or(false)
+}
+
+/// Runs [`cli::Command::HashPassword`], printing the hash of the password to
+/// STDOUT, or checking the password against [`HashPasswordOpts::check`].
+///
+/// # Errors
+///
+/// If the password cannot be read, or doesn't match the checked hash. The
+/// error is printed to STDERR.
+///
+/// [`cli::Command::HashPassword`]: crate::cli::Command::HashPassword
+pub fn run(opts: HashPasswordOpts) -> Result<(), Failure> {
+    let password = match opts.password {
+        Some(pass) => pass,
+        None => read_line().map_err(|e| {
+            eprintln!("Failed to read password from STDIN: {}", e)
+        })?,
+    };
+    if password.is_empty() {
+        eprintln!("Password must not be empty");
+        return Err(Failure);
+    }
+
+    let encoded = match opts.check {
+        Some(encoded) => encoded,
+        None => {
+            println!("{}", hash(&password));
+            return Ok(());
+        }
+    };
+    match argon2::verify_encoded(&encoded, password.as_bytes()) {
+        Ok(true) => {
+            println!("Password matches the hash");
+            Ok(())
+        }
+        Ok(false) => {
+            eprintln!("Password doesn't match the hash");
+            Err(Failure)
+        }
+        Err(e) => {
+            eprintln!("Invalid hash: {}", e);
+            Err(Failure)
+        }
+    }
+}
+
+/// Reads a single line from STDIN, without its trailing line break.
+///
+/// # Errors
+///
+/// If STDIN cannot be read.
+pub fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    let _ = io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+#[cfg(test)]
+mod password_spec {
+    use actix_web::rt::System;
+
+    use super::{hash, hash_blocking, verify, verify_blocking};
+
+    #[test]
+    fn verifies_hashed_password() {
+        let hashed = hash("qwerty");
+
+        assert!(verify(&hashed, "qwerty"));
+        assert!(!verify(&hashed, "qwerty1"));
+        assert!(!verify(&hashed, ""));
+    }
+
+    #[test]
+    fn verifies_on_blocking_pool() {
+        System::new("test").block_on(async {
+            let hashed = hash_blocking("qwerty".into()).await.unwrap();
+
+            assert!(verify_blocking(hashed.clone(), "qwerty".into()).await);
+            assert!(!verify_blocking(hashed, "qwerty1".into()).await);
+        });
+    }
+
+    #[test]
+    fn salts_hashes() {
+        assert_ne!(hash("qwerty"), hash("qwerty"));
+    }
+
+    #[test]
+    fn rejects_malformed_hash() {
+        assert!(!verify("qwerty", "qwerty"));
+        assert!(!verify("", ""));
+    }
+}
@@ -0,0 +1,278 @@
+//! Validation of user-supplied filesystem paths.
+//!
+//! Every path accepted from outside (via CLI options or API) is resolved into
+//! its canonical absolute form, so neither `..` components nor symbolic links
+//! can make it point somewhere else than it looks like. Optionally, the
+//! resolved path is confined to some allowed root directory (see
+//! [`Opts::media_root`]).
+//!
+//! [`Opts::media_root`]: crate::cli::Opts::media_root
+
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use derive_more::{Display, Error};
+
+/// Resolves the given `path` into a canonical absolute one, following all the
+/// symbolic links and `..` components in it.
+///
+/// Unlike [`fs::canonicalize()`], the `path` is not required to exist: its
+/// missing components (and all the following ones) are appended to the
+/// resolved existing part as they are.
+///
+/// Relative `path` is resolved against the given `root` directory, if any, or
+/// against the current working directory otherwise. If the `root` is given,
+/// then the resolved path is required to remain inside it.
+///
+/// # Errors
+///
+/// - If the `path` is empty.
+/// - If the `root` directory cannot be resolved.
+/// - If any component of the `path` cannot be inspected, or is a broken
+///   symbolic link.
+/// - If the resolved path escapes the `root` directory.
+pub fn resolve<P: AsRef<Path>>(
+    path: P,
+    root: Option<&Path>,
+) -> Result<PathBuf, PathError> {
+    let path = path.as_ref();
+    if path.as_os_str().is_empty() {
+        return Err(PathError::Empty);
+    }
+
+    let root = root
+        .map(|r| {
+            fs::canonicalize(r).map_err(|e| PathError::Root {
+                root: r.to_owned(),
+                reason: e.to_string(),
+            })
+        })
+        .transpose()?;
+    let base = match &root {
+        Some(r) => r.clone(),
+        None => env::current_dir().map_err(|e| PathError::Inaccessible {
+            path: path.to_owned(),
+            reason: e.to_string(),
+        })?,
+    };
+
+    let full = base.join(path);
+    let mut resolved = PathBuf::new();
+    for c in full.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                // `resolved` never contains symbolic links, so stepping out of
+                // it lexically is always correct.
+                let _ = resolved.pop();
+            }
+            Component::Normal(name) => {
+                resolved.push(name);
+                resolved = resolve_link(resolved)?;
+            }
+            Component::RootDir | Component::Prefix(_) => resolved.push(c),
+        }
+    }
+
+    if let Some(root) = root {
+        if !resolved.starts_with(&root) {
+            return Err(PathError::OutsideRoot {
+                path: path.to_owned(),
+                root,
+            });
+        }
+    }
+    Ok(resolved)
+}
+
+/// Resolves the last component of the given `path`, if it's a symbolic link,
+/// assuming all the preceding components being resolved already.
+///
+/// # Errors
+///
+/// If the `path` cannot be inspected, or is a broken symbolic link.
+fn resolve_link(path: PathBuf) -> Result<PathBuf, PathError> {
+    let inaccessible = |path: &Path, e: io::Error| PathError::Inaccessible {
+        path: path.to_owned(),
+        reason: e.to_string(),
+    };
+    match fs::symlink_metadata(&path) {
+        Ok(m) if m.file_type().is_symlink() => {
+            fs::canonicalize(&path).map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    PathError::BrokenSymlink { path }
+                } else {
+                    inaccessible(&path, e)
+                }
+            })
+        }
+        Ok(_) => Ok(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(path),
+        Err(e) => Err(inaccessible(&path, e)),
+    }
+}
+
+/// Error of resolving a user-supplied path.
+#[derive(Clone, Debug, Display, Eq, Error, PartialEq)]
+pub enum PathError {
+    /// Path is empty.
+    #[display(fmt = "Path is empty")]
+    Empty,
+
+    /// Root directory to confine the path to cannot be resolved.
+    #[display(
+        fmt = "Failed to resolve '{}' root directory: {}",
+        "root.display()",
+        reason
+    )]
+    Root {
+        /// Root directory as it has been specified.
+        root: PathBuf,
+
+        /// Reason of the failure.
+        reason: String,
+    },
+
+    /// Some component of the path cannot be inspected.
+    #[display(fmt = "Cannot access '{}': {}", "path.display()", reason)]
+    Inaccessible {
+        /// Part of the path which cannot be inspected.
+        path: PathBuf,
+
+        /// Reason of the failure.
+        reason: String,
+    },
+
+    /// Some component of the path is a symbolic link pointing nowhere.
+    #[display(fmt = "'{}' is a broken symbolic link", "path.display()")]
+    BrokenSymlink {
+        /// Part of the path being the broken symbolic link.
+        path: PathBuf,
+    },
+
+    /// Resolved path escapes the root directory it's confined to.
+    #[display(
+        fmt = "'{}' is outside of '{}' directory",
+        "path.display()",
+        "root.display()"
+    )]
+    OutsideRoot {
+        /// Path as it has been specified.
+        path: PathBuf,
+
+        /// Canonical root directory the path should remain inside.
+        root: PathBuf,
+    },
+}
+
+impl PathError {
+    /// Returns unique literal code of the reason of this [`PathError`], for
+    /// reporting it to clients.
+    #[must_use]
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Empty => "EMPTY",
+            Self::Root { .. } => "ROOT",
+            Self::Inaccessible { .. } => "INACCESSIBLE",
+            Self::BrokenSymlink { .. } => "BROKEN_SYMLINK",
+            Self::OutsideRoot { .. } => "OUTSIDE_ROOT",
+        }
+    }
+}
+
+#[cfg(test)]
+mod resolve_spec {
+    use std::{env, fs, os::unix::fs::symlink, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::{resolve, PathError};
+
+    /// Creates a fresh temporary directory with `root/media` and `outside`
+    /// directories inside.
+    fn sandbox() -> PathBuf {
+        let dir = fs::canonicalize(env::temp_dir())
+            .unwrap()
+            .join(format!("ephyr-paths-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("root/media")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        dir
+    }
+
+    fn reason(res: Result<PathBuf, PathError>) -> &'static str {
+        res.unwrap_err().reason()
+    }
+
+    #[test]
+    fn resolves_symlinked_parents() {
+        let dir = sandbox();
+        let root = dir.join("root");
+        symlink(root.join("media"), root.join("link")).unwrap();
+        symlink(dir.join("outside"), root.join("escape")).unwrap();
+
+        assert_eq!(
+            resolve("link/video.mp4", Some(&root)),
+            Ok(root.join("media/video.mp4")),
+        );
+        assert_eq!(
+            resolve(root.join("link/../media/a.mp4"), Some(&root)),
+            Ok(root.join("media/a.mp4")),
+        );
+        assert_eq!(
+            reason(resolve("escape/video.mp4", Some(&root))),
+            "OUTSIDE_ROOT",
+        );
+        // Without root, symbolic links are just followed.
+        assert_eq!(
+            resolve(root.join("escape/video.mp4"), None),
+            Ok(dir.join("outside/video.mp4")),
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_missing_intermediate_dirs() {
+        let dir = sandbox();
+        let root = dir.join("root");
+
+        assert_eq!(
+            resolve("new/nested/video.mp4", Some(&root)),
+            Ok(root.join("new/nested/video.mp4")),
+        );
+        assert_eq!(
+            resolve("new/../media/./video.mp4", Some(&root)),
+            Ok(root.join("media/video.mp4")),
+        );
+        assert_eq!(
+            reason(resolve("new/../../outside/video.mp4", Some(&root))),
+            "OUTSIDE_ROOT",
+        );
+        assert_eq!(reason(resolve("/etc/passwd", Some(&root))), "OUTSIDE_ROOT");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_broken_symlinks_and_bad_roots() {
+        let dir = sandbox();
+        let root = dir.join("root");
+        symlink(dir.join("nowhere"), root.join("broken")).unwrap();
+
+        assert_eq!(
+            resolve("broken/video.mp4", Some(&root)),
+            Err(PathError::BrokenSymlink {
+                path: root.join("broken"),
+            }),
+        );
+        assert_eq!(reason(resolve("", Some(&root))), "EMPTY");
+        assert_eq!(
+            reason(resolve("video.mp4", Some(dir.join("none").as_path()))),
+            "ROOT",
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Previewing of live streams received by [`state::Input`]s.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use ephyr_log::log;
+use tokio::{process::Command, time};
+use url::Url;
+
+use crate::state::{self, InputId, State, Statuses};
+
+/// Maximum time a single [FFmpeg] process is allowed to take a thumbnail.
+///
+/// [FFmpeg]: https://ffmpeg.org
+const TAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Height (in pixels) of taken thumbnails, while their width is scaled
+/// proportionally.
+const THUMBNAIL_HEIGHT: u16 = 180;
+
+/// Thumbnail of a live stream received by a [`state::Input`].
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    /// JPEG image of this [`Thumbnail`].
+    pub jpeg: Arc<[u8]>,
+
+    /// Moment when this [`Thumbnail`] has been taken.
+    pub taken_at: SystemTime,
+}
+
+/// In-memory storage of the recent [`Thumbnail`]s of [`state::Input`]s.
+#[derive(Clone, Debug, Default)]
+pub struct Thumbnails(Arc<RwLock<HashMap<InputId, Thumbnail>>>);
+
+impl Thumbnails {
+    /// Spawns a background task taking [`Thumbnail`]s of all the online (see
+    /// [`state::Status::is_online()`]) [`state::Input`]s of the given
+    /// [`State`] once per the given `interval`, and returns the
+    /// [`Thumbnails`] storage it fills.
+    ///
+    /// [`Thumbnail`]s are taken one by one, so the spawned [FFmpeg] processes
+    /// never overload the server, and those of the [`state::Input`]s being
+    /// not online anymore are removed.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[must_use]
+    pub fn spawn(
+        ffmpeg_path: PathBuf,
+        interval: Duration,
+        state: State,
+    ) -> Self {
+        let thumbnails = Self::default();
+        let storage = thumbnails.clone();
+        drop(tokio::spawn(async move {
+            loop {
+                let mut inputs = HashMap::new();
+                {
+                    let restreams = state.restreams.lock_ref();
+                    let statuses = state.statuses.lock_ref();
+                    for r in restreams.iter() {
+                        collect_online(r, &r.input, &statuses, &mut inputs);
+                    }
+                }
+
+                storage
+                    .0
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .retain(|id, _| inputs.contains_key(id));
+
+                for (id, url) in inputs {
+                    if let Some(jpeg) = take(&ffmpeg_path, &url).await {
+                        let _ = storage
+                            .0
+                            .write()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .insert(
+                                id,
+                                Thumbnail {
+                                    jpeg: jpeg.into(),
+                                    taken_at: SystemTime::now(),
+                                },
+                            );
+                    }
+                }
+
+                time::delay_for(interval).await;
+            }
+        }));
+        thumbnails
+    }
+
+    /// Returns the recent [`Thumbnail`] of a [`state::Input`] with the given
+    /// `id`, if any.
+    #[must_use]
+    pub fn get(&self, id: InputId) -> Option<Thumbnail> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&id)
+            .cloned()
+    }
+
+    /// Stores the given `jpeg` as the recent [`Thumbnail`] of a
+    /// [`state::Input`] with the given `id`.
+    #[cfg(test)]
+    pub fn insert(&self, id: InputId, jpeg: &[u8]) {
+        let _ = self
+            .0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                id,
+                Thumbnail {
+                    jpeg: jpeg.into(),
+                    taken_at: SystemTime::now(),
+                },
+            );
+    }
+}
+
+/// Collects local RTMP [`Url`]s of the given enabled [`state::Input`] and all
+/// its enabled [`state::FailoverInputSrc::inputs`], which are online (see
+/// [`state::Status::is_online()`]) according to the given `statuses`.
+fn collect_online(
+    restream: &state::Restream,
+    input: &state::Input,
+    statuses: &Statuses,
+    urls: &mut HashMap<InputId, Url>,
+) {
+    if !input.enabled {
+        return;
+    }
+    if let Some(e) = input
+        .endpoints
+        .iter()
+        .find(|e| e.is_rtmp() && statuses.get(e.id).is_online())
+    {
+        let _ = urls.insert(input.id, e.kind.rtmp_url(restream, &input.key));
+    }
+    if let Some(state::InputSrc::Failover(s)) = &input.src {
+        for i in &s.inputs {
+            collect_online(restream, i, statuses, urls);
+        }
+    }
+}
+
+/// Takes a single JPEG frame of a live stream served on the given [`Url`] with
+/// a [FFmpeg] process.
+///
+/// Returns [`None`] if the frame cannot be taken.
+///
+/// [FFmpeg]: https://ffmpeg.org
+async fn take(ffmpeg_path: &Path, url: &Url) -> Option<Vec<u8>> {
+    let mut cmd = Command::new(ffmpeg_path);
+    let _ = cmd
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .args(&["-loglevel", "error", "-i", url.as_str()])
+        .args(&["-frames:v", "1", "-an"])
+        .arg("-vf")
+        .arg(format!("scale=-2:{}", THUMBNAIL_HEIGHT))
+        .args(&["-c:v", "mjpeg", "-q:v", "5", "-f", "image2", "pipe:1"]);
+
+    match time::timeout(TAKE_TIMEOUT, cmd.output()).await {
+        Ok(Ok(out)) if out.status.success() && !out.stdout.is_empty() => {
+            Some(out.stdout)
+        }
+        Ok(Ok(_)) | Err(_) => None,
+        Ok(Err(e)) => {
+            log::error!("Failed to run FFmpeg for taking thumbnail: {}", e);
+            None
+        }
+    }
+}
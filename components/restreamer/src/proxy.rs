@@ -0,0 +1,319 @@
+//! IP ranges and resolution of real client IP addresses of requests passed
+//! through trusted reverse proxies.
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use actix_web::http::{header, HeaderMap};
+use anyhow::anyhow;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Range of IP addresses in [CIDR notation][1], like `10.0.0.0/8`.
+///
+/// A bare IP address denotes a range of this single address.
+///
+/// [1]: https://en.wikipedia.org/wiki/Classless_Inter-Domain_Routing
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpRange {
+    /// Network address of this [`IpRange`].
+    addr: IpAddr,
+
+    /// Length of the network prefix of this [`IpRange`] (in bits).
+    prefix: u8,
+}
+
+impl IpRange {
+    /// Checks whether the given `ip` belongs to this [`IpRange`].
+    ///
+    /// IPv4-mapped IPv6 addresses are considered as IPv4 ones.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = unmap(ip);
+        let (addr, ip, bits) = match (self.addr, ip) {
+            (IpAddr::V4(a), IpAddr::V4(i)) => {
+                (u128::from(u32::from(a)), u128::from(u32::from(i)), 32)
+            }
+            (IpAddr::V6(a), IpAddr::V6(i)) => {
+                (u128::from(a), u128::from(i), 128)
+            }
+            _ => return false,
+        };
+        let shift = bits - u32::from(self.prefix);
+        addr.checked_shr(shift).unwrap_or(0)
+            == ip.checked_shr(shift).unwrap_or(0)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, '/');
+        let (addr, prefix) = (parts.next().unwrap_or_default(), parts.next());
+        let addr = unmap(
+            addr.parse::<IpAddr>()
+                .map_err(|e| anyhow!("'{}' is invalid IP range: {}", s, e))?,
+        );
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => {
+                p.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| {
+                    anyhow!(
+                        "'{}' is invalid IP range: prefix length must be \
+                         0..={}",
+                        s,
+                        max,
+                    )
+                })?
+            }
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Serialize for IpRange {
+    #[inline]
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpRange {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl fmt::Display for IpRange {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Resolves the real IP address of a client, who has sent a request with the
+/// given `headers` from the given `peer` address.
+///
+/// If the `peer` belongs to the `trusted` proxies, then the `Forwarded` (or,
+/// if absent, `X-Forwarded-For`) header is walked from the right to the left,
+/// skipping the `trusted` hops, and the first untrusted address is the client
+/// one. Otherwise, the headers are ignored completely, as may be spoofed.
+#[must_use]
+pub fn client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted: &[IpRange],
+) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|r| r.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+
+    let mut chain: Vec<&str> = headers
+        .get_all(header::FORWARDED)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|elem| {
+            elem.split(';').find_map(|pair| {
+                let mut kv = pair.trim().splitn(2, '=');
+                let (key, val) = (kv.next()?, kv.next()?);
+                key.eq_ignore_ascii_case("for").then(|| val)
+            })
+        })
+        .collect();
+    if chain.is_empty() {
+        chain = headers
+            .get_all("x-forwarded-for")
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+    }
+
+    let mut client = peer;
+    for hop in chain.into_iter().rev() {
+        match parse_node(hop) {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            // Obfuscated or malformed hop cannot be trusted to go further.
+            None => break,
+        }
+    }
+    client
+}
+
+/// Parses an IP address out of the given node of `Forwarded` or
+/// `X-Forwarded-For` header, which may be quoted, and may contain a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|n| n.strip_suffix(']'))
+                .and_then(|n| n.parse().ok())
+        })
+        .map(unmap)
+}
+
+/// Converts the given IPv4-mapped IPv6 address into an IPv4 one, leaving any
+/// other address as it is.
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+            v6.to_ipv4().map_or(ip, IpAddr::V4)
+        }
+        _ => ip,
+    }
+}
+
+#[cfg(test)]
+mod client_ip_spec {
+    use std::net::IpAddr;
+
+    use actix_web::http::{HeaderMap, HeaderName, HeaderValue};
+
+    use super::{client_ip, IpRange};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, val) in pairs {
+            headers.append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(val),
+            );
+        }
+        headers
+    }
+
+    fn resolve(peer: &str, pairs: &[(&'static str, &'static str)]) -> String {
+        let trusted: Vec<IpRange> = vec![
+            "127.0.0.1".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+            "fd00::/8".parse().unwrap(),
+        ];
+        client_ip(peer.parse::<IpAddr>().unwrap(), &headers(pairs), &trusted)
+            .to_string()
+    }
+
+    #[test]
+    fn ignores_headers_of_untrusted_peer() {
+        assert_eq!(
+            resolve("203.0.113.7", &[("x-forwarded-for", "198.51.100.1")]),
+            "203.0.113.7",
+        );
+    }
+
+    #[test]
+    fn resolves_chained_x_forwarded_for() {
+        assert_eq!(
+            resolve(
+                "127.0.0.1",
+                &[("x-forwarded-for", "1.1.1.1, 198.51.100.1, 10.1.2.3")],
+            ),
+            "198.51.100.1",
+        );
+        assert_eq!(
+            resolve(
+                "127.0.0.1",
+                &[
+                    ("x-forwarded-for", "1.1.1.1, 198.51.100.1"),
+                    ("x-forwarded-for", "10.1.2.3"),
+                ],
+            ),
+            "198.51.100.1",
+        );
+    }
+
+    #[test]
+    fn resolves_leftmost_when_all_hops_are_trusted() {
+        assert_eq!(
+            resolve("127.0.0.1", &[("x-forwarded-for", "10.0.0.1, 10.0.0.2")]),
+            "10.0.0.1",
+        );
+    }
+
+    #[test]
+    fn stops_on_malformed_hop() {
+        assert_eq!(
+            resolve(
+                "127.0.0.1",
+                &[("x-forwarded-for", "198.51.100.1, unknown, 10.0.0.2")],
+            ),
+            "10.0.0.2",
+        );
+    }
+
+    #[test]
+    fn prefers_forwarded_header() {
+        assert_eq!(
+            resolve(
+                "127.0.0.1",
+                &[
+                    (
+                        "forwarded",
+                        "for=\"[2001:db8::1]:4711\";proto=https, \
+                         For=10.0.0.5",
+                    ),
+                    ("x-forwarded-for", "198.51.100.1"),
+                ],
+            ),
+            "2001:db8::1",
+        );
+    }
+
+    #[test]
+    fn uses_peer_without_headers() {
+        assert_eq!(resolve("10.0.0.1", &[]), "10.0.0.1");
+    }
+}
+
+#[cfg(test)]
+mod ip_range_spec {
+    use std::net::IpAddr;
+
+    use super::IpRange;
+
+    fn contains(range: &str, ip: &str) -> bool {
+        range
+            .parse::<IpRange>()
+            .unwrap()
+            .contains(ip.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn matches_by_prefix() {
+        assert!(contains("10.0.0.0/8", "10.200.0.1"));
+        assert!(!contains("10.0.0.0/8", "11.0.0.1"));
+        assert!(contains("0.0.0.0/0", "203.0.113.7"));
+        assert!(contains("fd00::/8", "fd12::1"));
+        assert!(!contains("fd00::/8", "fe80::1"));
+        assert!(contains("192.0.2.1", "192.0.2.1"));
+        assert!(!contains("192.0.2.1", "192.0.2.2"));
+        assert!(contains("127.0.0.1", "::ffff:127.0.0.1"));
+        assert!(!contains("10.0.0.0/8", "::1"));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("::/129".parse::<IpRange>().is_err());
+        assert!("localhost".parse::<IpRange>().is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Preflight reachability probing of destination URLs, catching most of the
+//! misconfigured `Output`s before they're even added: the ones whose host
+//! doesn't resolve, or whose port is closed.
+//!
+//! Probes perform no protocol handshakes. Host resolution is performed on the
+//! blocking threads pool, and connecting is fully asynchronous, so probes
+//! never occupy HTTP worker threads.
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use derive_more::{Display, Error};
+use once_cell::sync::Lazy;
+use tokio::{
+    net::{self, TcpStream},
+    sync::Semaphore,
+    time,
+};
+use url::{Host, Url};
+
+use crate::state::OutputDstUrl;
+
+/// Maximum duration of probing a single URL.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Maximum number of probes running simultaneously.
+const MAX_CONCURRENT_PROBES: usize = 4;
+
+/// Maximum number of probes allowed to be started within [`RATE_WINDOW`].
+const MAX_PROBES_PER_WINDOW: usize = 30;
+
+/// Sliding window limiting the rate of probes.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Probes reachability of the given `url` by resolving its host and
+/// connecting to its port via TCP.
+///
+/// [SRT] destinations are only resolved, as [SRT] works over UDP, so there is
+/// no connection to establish. Local `file://` destinations are not probed at
+/// all.
+///
+/// Returns the reached address, or [`None`] if there is nothing to probe.
+///
+/// # Errors
+///
+/// If the `url` is unreachable, or too many probes are performed recently
+/// (see [`ProbeError::RateLimited`]).
+///
+/// [SRT]: https://en.wikipedia.org/wiki/Secure_Reliable_Transport
+pub async fn probe(url: &Url) -> Result<Option<SocketAddr>, ProbeError> {
+    static LIMITER: Lazy<RateLimiter> =
+        Lazy::new(|| RateLimiter::new(MAX_PROBES_PER_WINDOW, RATE_WINDOW));
+    static PERMITS: Lazy<Semaphore> =
+        Lazy::new(|| Semaphore::new(MAX_CONCURRENT_PROBES));
+
+    if url.scheme() == "file" {
+        return Ok(None);
+    }
+    let host = match url.host() {
+        Some(Host::Domain(d)) => d.to_owned(),
+        Some(Host::Ipv4(ip)) => ip.to_string(),
+        Some(Host::Ipv6(ip)) => ip.to_string(),
+        None => return Err(ProbeError::NoHost),
+    };
+    let port = url
+        .port()
+        .or_else(|| OutputDstUrl::default_port(url.scheme()))
+        .or_else(|| url.port_or_known_default())
+        .ok_or_else(|| ProbeError::NoPort {
+            scheme: url.scheme().to_owned(),
+        })?;
+
+    if !LIMITER.try_acquire(Instant::now()) {
+        return Err(ProbeError::RateLimited);
+    }
+    let _permit = PERMITS.acquire().await;
+
+    let connect_tcp = url.scheme() != "srt";
+    time::timeout(PROBE_TIMEOUT, reach(host, port, connect_tcp))
+        .await
+        .unwrap_or(Err(ProbeError::TimedOut {
+            timeout: PROBE_TIMEOUT,
+        }))
+}
+
+/// Resolves the given `host` and, if `connect_tcp` is `true`, connects to the
+/// given `port` of any of the resolved addresses via TCP.
+///
+/// # Errors
+///
+/// If the `host` cannot be resolved, or none of its addresses accepts a TCP
+/// connection.
+async fn reach(
+    host: String,
+    port: u16,
+    connect_tcp: bool,
+) -> Result<Option<SocketAddr>, ProbeError> {
+    let unresolved = |reason: String| ProbeError::Unresolved {
+        host: host.clone(),
+        reason,
+    };
+    let addrs: Vec<_> = net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| unresolved(e.to_string()))?
+        .collect();
+    if !connect_tcp {
+        return addrs
+            .first()
+            .copied()
+            .map(Some)
+            .ok_or_else(|| unresolved("no addresses".into()));
+    }
+
+    let mut err = unresolved("no addresses".into());
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(_) => return Ok(Some(addr)),
+            Err(e) => {
+                err = ProbeError::Refused {
+                    addr,
+                    reason: e.to_string(),
+                }
+            }
+        }
+    }
+    Err(err)
+}
+
+/// Error of probing a URL reachability.
+#[derive(Clone, Debug, Display, Eq, Error, PartialEq)]
+pub enum ProbeError {
+    /// URL has no host to probe.
+    #[display(fmt = "URL has no host")]
+    NoHost,
+
+    /// URL has no port, and there is no default one for its scheme.
+    #[display(
+        fmt = "URL has no port, and '{}' scheme has no default one",
+        scheme
+    )]
+    NoPort {
+        /// Scheme of the URL.
+        scheme: String,
+    },
+
+    /// Host of URL cannot be resolved.
+    #[display(fmt = "Failed to resolve '{}' host: {}", host, reason)]
+    Unresolved {
+        /// Host of the URL.
+        host: String,
+
+        /// Reason of the failure.
+        reason: String,
+    },
+
+    /// Resolved address doesn't accept TCP connections.
+    #[display(fmt = "Failed to connect to {}: {}", addr, reason)]
+    Refused {
+        /// Address connection has been tried to.
+        addr: SocketAddr,
+
+        /// Reason of the failure.
+        reason: String,
+    },
+
+    /// Probing hasn't finished in time.
+    #[display(fmt = "No response within {:?}", timeout)]
+    TimedOut {
+        /// Timeout of the probing.
+        timeout: Duration,
+    },
+
+    /// Too many probes have been performed recently.
+    #[display(fmt = "Too many probes are performed, try again later")]
+    RateLimited,
+}
+
+impl ProbeError {
+    /// Returns unique literal code of the reason of this [`ProbeError`], for
+    /// reporting it to clients.
+    #[must_use]
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NoHost => "NO_HOST",
+            Self::NoPort { .. } => "NO_PORT",
+            Self::Unresolved { .. } => "DNS",
+            Self::Refused { .. } => "CONNECT",
+            Self::TimedOut { .. } => "TIMEOUT",
+            Self::RateLimited => "RATE_LIMITED",
+        }
+    }
+}
+
+/// Limiter of a number of operations started within a sliding window.
+#[derive(Debug)]
+struct RateLimiter {
+    /// Maximum number of operations allowed within the
+    /// [`RateLimiter::window`].
+    max: usize,
+
+    /// Duration of the sliding window.
+    window: Duration,
+
+    /// Moments the operations within the current window have been started
+    /// at, in ascending order.
+    started: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Creates a new [`RateLimiter`] allowing `max` operations within the
+    /// given `window`.
+    #[inline]
+    #[must_use]
+    fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            started: Mutex::new(VecDeque::with_capacity(max)),
+        }
+    }
+
+    /// Tries to start a new operation at the given moment.
+    ///
+    /// Returns `false` if the limit is exceeded, so the operation should not
+    /// be started.
+    fn try_acquire(&self, now: Instant) -> bool {
+        let mut started =
+            self.started.lock().unwrap_or_else(PoisonError::into_inner);
+        while started
+            .front()
+            .map_or(false, |at| now.duration_since(*at) >= self.window)
+        {
+            let _ = started.pop_front();
+        }
+        if started.len() >= self.max {
+            return false;
+        }
+        started.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod probe_spec {
+    use std::{
+        net::{Ipv4Addr, TcpListener},
+        time::{Duration, Instant},
+    };
+
+    use actix_web::rt::System;
+    use url::Url;
+
+    use super::{probe, ProbeError, RateLimiter};
+
+    #[test]
+    fn reaches_open_port_only() {
+        System::new("test").block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let url =
+                Url::parse(&format!("rtmp://{}/live/stream", addr)).unwrap();
+
+            assert_eq!(probe(&url).await, Ok(Some(addr)));
+
+            drop(listener);
+            let err = probe(&url).await.unwrap_err();
+            assert_eq!(err.reason(), "CONNECT", "{}", err);
+        });
+    }
+
+    #[test]
+    fn skips_local_files_and_requires_port() {
+        System::new("test").block_on(async {
+            let file = Url::parse("file:///out.flv").unwrap();
+            assert_eq!(probe(&file).await, Ok(None));
+
+            let srt = Url::parse("srt://127.0.0.1/stream").unwrap();
+            assert_eq!(
+                probe(&srt).await,
+                Err(ProbeError::NoPort {
+                    scheme: "srt".into(),
+                }),
+            );
+        });
+    }
+
+    #[test]
+    fn limits_rate_within_window() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(limiter.try_acquire(at(0)));
+        assert!(limiter.try_acquire(at(10)));
+        assert!(!limiter.try_acquire(at(20)));
+        assert!(limiter.try_acquire(at(60)));
+        assert!(!limiter.try_acquire(at(65)));
+        assert!(limiter.try_acquire(at(70)));
+    }
+}
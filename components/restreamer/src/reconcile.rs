@@ -0,0 +1,196 @@
+//! Reconciling [`state::InputEndpoint`]s with the publishers being connected
+//! to [SRS] already.
+//!
+//! [SRS] server may outlive a restart of this application, while its
+//! publishers keep streaming. As no new [SRS] callbacks happen for them, the
+//! restarted application would consider their [`state::InputEndpoint`]s
+//! offline until the publishers reconnect. That's why [SRS] is asked directly
+//! for its publishers on startup.
+//!
+//! [SRS]: https://github.com/ossrs/srs
+
+use ephyr_log::log;
+
+use crate::{
+    api::srs::ClientInfo,
+    evict::SrsApi,
+    state::{self, InputEndpointKind, InputSrc, Status},
+    trace, State,
+};
+
+/// Remembers all the publishers connected to [SRS] server at the moment as
+/// [`state::InputEndpoint::srs_publisher_id`]s of the matching
+/// [`state::InputEndpoint`]s, and marks them as [`Status::Online`].
+///
+/// Only the [`state::InputEndpoint`]s allowed to be published by external
+/// clients are considered (RTMP ones of enabled [`state::Input`]s without
+/// any [`state::Input::src`]), as the publishers pulled by this application
+/// itself are re-spawned anyway. [`state::InputEndpoint`]s having a
+/// publisher already are left untouched.
+///
+/// Returns the number of the reconciled [`state::InputEndpoint`]s.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub async fn srs_publishers<A: SrsApi + ?Sized>(
+    api: &A,
+    state: &State,
+) -> usize {
+    let clients = match api.clients().await {
+        Ok(c) => c,
+        Err(e) => {
+            log::debug!("Failed to list SRS clients for reconciling: {}", e);
+            return 0;
+        }
+    };
+
+    let mut restreams = trace::lock_mut(&state.restreams, "srs_publishers");
+    let mut statuses = state.statuses.lock_mut("srs_publishers");
+    let mut reconciled = 0;
+    for client in clients.iter().filter(|c| c.publish) {
+        if let Some(endpoint) = lookup_endpoint(&mut restreams, client) {
+            // Numeric IDs are required to track clients via SRS callbacks.
+            let id = match client.id.parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            endpoint.srs_publisher_id = Some(id.into());
+            endpoint.offline_reason = None;
+            let _ = state.renew_status(endpoint.id, Status::Online, |_| ());
+            let _ = statuses.set(endpoint.id, Status::Online);
+            reconciled += 1;
+        }
+    }
+    if reconciled > 0 {
+        log::info!("Reconciled {} SRS publishers", reconciled);
+    }
+    reconciled
+}
+
+/// Looks up the [`state::InputEndpoint`] published by the given [SRS]
+/// `client` in the given `restreams`, if it has no publisher yet.
+///
+/// [SRS]: https://github.com/ossrs/srs
+fn lookup_endpoint<'r>(
+    restreams: &'r mut [state::Restream],
+    client: &ClientInfo,
+) -> Option<&'r mut state::InputEndpoint> {
+    /// Traverses the given [`state::Input`] and all its
+    /// [`state::FailoverInputSrc::inputs`] looking for the enabled one with
+    /// the given `key`.
+    fn lookup_input<'i>(
+        input: &'i mut state::Input,
+        key: &str,
+    ) -> Option<&'i mut state::Input> {
+        if input.key == *key {
+            return input.enabled.then(|| input);
+        }
+        if let Some(InputSrc::Failover(s)) = input.src.as_mut() {
+            s.inputs.iter_mut().find_map(|i| lookup_input(i, key))
+        } else {
+            None
+        }
+    }
+
+    let (app, stream) = client.app_stream()?;
+    let restream = restreams.iter_mut().find(|r| r.key == *app)?;
+    if !restream.input.enabled {
+        return None;
+    }
+    let input = lookup_input(&mut restream.input, stream)
+        .filter(|i| i.src.is_none())?;
+    input.endpoints.iter_mut().find(|e| {
+        e.kind == InputEndpointKind::Rtmp && e.srs_publisher_id.is_none()
+    })
+}
+
+#[cfg(test)]
+mod srs_publishers_spec {
+    use actix_web::rt::System;
+    use futures::future::{self, BoxFuture, FutureExt as _};
+    use serde_json::json;
+
+    use crate::{
+        api::srs::{self as srs_api, ClientInfo},
+        evict::SrsApi,
+        spec,
+        state::{State, Status},
+    };
+
+    use super::srs_publishers;
+
+    /// Mocked [`SrsApi`] responding with the recorded [SRS] clients listing.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[derive(Debug)]
+    struct MockApi;
+
+    impl SrsApi for MockApi {
+        fn clients(
+            &self,
+        ) -> BoxFuture<'_, Result<Vec<ClientInfo>, srs_api::Error>> {
+            let resp: serde_json::Value = serde_json::from_str(include_str!(
+                "api/srs/fixtures/clients/4.0/clients.json"
+            ))
+            .unwrap();
+            future::ok(serde_json::from_value(resp["clients"].clone()).unwrap())
+                .boxed()
+        }
+
+        fn kickoff_client(
+            &self,
+            id: String,
+        ) -> BoxFuture<'_, Result<(), srs_api::Error>> {
+            panic!("client {} should not be kicked", id)
+        }
+    }
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        for (key, enabled) in &[("main", true), ("backup", false)] {
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": key,
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}, {"kind": "hls"}],
+                            "enabled": enabled,
+                        },
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn primes_online_publishers() {
+        System::new("test").block_on(async {
+            let state = state();
+
+            assert_eq!(srs_publishers(&MockApi, &state).await, 1);
+
+            let restreams = state.restreams.lock_ref();
+            let main = &restreams[0].input.endpoints;
+            assert_eq!(main[0].srs_publisher_id.as_deref(), Some(&345));
+            assert_eq!(state.statuses.get(main[0].id), Status::Online);
+            assert_eq!(main[1].srs_publisher_id, None);
+            assert_eq!(state.statuses.get(main[1].id), Status::Offline);
+            // Disabled `Input` is not reconciled.
+            let backup = &restreams[1].input.endpoints;
+            assert_eq!(backup[0].srs_publisher_id, None);
+            assert_eq!(state.statuses.get(backup[0].id), Status::Offline);
+        });
+    }
+
+    #[test]
+    fn keeps_already_known_publishers() {
+        System::new("test").block_on(async {
+            let state = state();
+            assert_eq!(srs_publishers(&MockApi, &state).await, 1);
+
+            assert_eq!(srs_publishers(&MockApi, &state).await, 0);
+        });
+    }
+}
@@ -0,0 +1,427 @@
+//! Emergency recovery of access to the application, once its password is
+//! lost.
+//!
+//! The lost password may be reset either via [`cli::Command::ResetPassword`]
+//! while the server is stopped, or via a request from a loopback connection
+//! to the running server, if [`cli::Opts::allow_local_reset`] is specified.
+//! Every reset is recorded as [`Settings::password_reset`] for auditing.
+//!
+//! [`cli::Command::ResetPassword`]: crate::cli::Command::ResetPassword
+//! [`cli::Opts::allow_local_reset`]: crate::cli::Opts::allow_local_reset
+//! [`Settings::password_reset`]: crate::state::Settings::password_reset
+
+use std::{
+    fs,
+    io::{self, Read as _, Seek as _, SeekFrom, Write as _},
+    os::unix::io::AsRawFd as _,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::anyhow;
+use tokio::runtime;
+
+use crate::{
+    cli::{Failure, ResetPasswordOpts},
+    password, paths,
+    state::{self, PasswordResetVia, StatePersistence},
+    State,
+};
+
+/// Name of the HTTP header confirming a request to clear the password from a
+/// loopback connection, if [`cli::Opts::allow_local_reset`] is specified.
+///
+/// Must have [`LOCAL_RESET_CONFIRMATION`] value. Browsers never send custom
+/// headers cross-origin without a preflight, so no web page opened on the
+/// same machine is able to clear the password.
+///
+/// [`cli::Opts::allow_local_reset`]: crate::cli::Opts::allow_local_reset
+pub const LOCAL_RESET_HEADER: &str = "x-ephyr-local-reset";
+
+/// Value of the [`LOCAL_RESET_HEADER`] confirming a request to clear the
+/// password.
+pub const LOCAL_RESET_CONFIRMATION: &str = "clear-password";
+
+/// Lock held by a running server over its [`State`] file, so the file is
+/// not reset via [`cli::Command::ResetPassword`] while being used.
+///
+/// Is an advisory [`flock(2)`][1] over a lock file (containing the PID of the
+/// holding process for diagnostics), so it's released by the kernel once the
+/// holding process exits in any way, even being killed. The lock file itself
+/// is left in place, as removing it would let two processes lock different
+/// files at the same path.
+///
+/// [`cli::Command::ResetPassword`]: crate::cli::Command::ResetPassword
+/// [1]: https://man7.org/linux/man-pages/man2/flock.2.html
+#[derive(Debug)]
+pub struct StateLock {
+    /// Locked file, unlocked once closed.
+    file: fs::File,
+}
+
+impl StateLock {
+    /// Returns the path of the [`StateLock`] file of the [`State`] file
+    /// located by the given `state_path`.
+    #[must_use]
+    pub fn path_of(state_path: &Path) -> PathBuf {
+        let mut path = state_path.as_os_str().to_owned();
+        path.push(".lock");
+        path.into()
+    }
+
+    /// Acquires the [`StateLock`] of the [`State`] file located by the given
+    /// `state_path`.
+    ///
+    /// # Errors
+    ///
+    /// If the [`StateLock`] is held by another process, or its file cannot be
+    /// written.
+    pub fn acquire(state_path: &Path) -> Result<Self, anyhow::Error> {
+        let path = Self::path_of(state_path);
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| {
+                anyhow!("Failed to open '{}' lock file: {}", path.display(), e)
+            })?;
+        let locked = try_flock(&file, libc::LOCK_EX).map_err(|e| {
+            anyhow!("Failed to lock '{}' lock file: {}", path.display(), e)
+        })?;
+        if !locked {
+            return Err(match read_pid(&mut file) {
+                Some(pid) => anyhow!(
+                    "'{}' state file is used by running process {}",
+                    state_path.display(),
+                    pid,
+                ),
+                None => anyhow!(
+                    "'{}' state file is used by another process",
+                    state_path.display(),
+                ),
+            });
+        }
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(file, "{}", process::id()))
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to write '{}' lock file: {}",
+                    path.display(),
+                    e,
+                )
+            })?;
+        Ok(Self { file })
+    }
+
+    /// Returns the PID of the process holding the [`StateLock`] of the
+    /// [`State`] file located by the given `state_path`, if any.
+    ///
+    /// The PID is `0` if it's unknown (the lock file is being written yet).
+    #[must_use]
+    pub fn holder(state_path: &Path) -> Option<u32> {
+        let mut file = fs::File::open(Self::path_of(state_path)).ok()?;
+        if try_flock(&file, libc::LOCK_SH).ok()? {
+            return None;
+        }
+        Some(read_pid(&mut file).unwrap_or(0))
+    }
+}
+
+/// Tries to apply the given [`flock(2)`][1] `operation` to the given `file`
+/// without blocking.
+///
+/// Returns `false` if the `file` is locked by another open file description
+/// already.
+///
+/// # Errors
+///
+/// If [`flock(2)`][1] fails for any other reason.
+///
+/// [1]: https://man7.org/linux/man-pages/man2/flock.2.html
+fn try_flock(file: &fs::File, operation: libc::c_int) -> io::Result<bool> {
+    // SAFETY: The file descriptor is valid as long as the `file` is alive.
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0
+    {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    if e.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+/// Reads the PID of the holding process from the given lock `file`.
+fn read_pid(file: &mut fs::File) -> Option<u32> {
+    let mut pid = String::new();
+    let _ = file.seek(SeekFrom::Start(0)).ok()?;
+    let _ = file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+/// Runs [`cli::Command::ResetPassword`], resetting the password in the
+/// [`State`] file, and reporting the result to STDOUT.
+///
+/// # Errors
+///
+/// If the [`State`] file is used by the server, or cannot be read or written.
+/// The error is printed to STDERR.
+///
+/// [`cli::Command::ResetPassword`]: crate::cli::Command::ResetPassword
+pub fn run(opts: &ResetPasswordOpts) -> Result<(), Failure> {
+    let resolve = |p: &Path| {
+        paths::resolve(p, None).map_err(|e| {
+            eprintln!("Failed to resolve '{}' path: {}", p.display(), e)
+        })
+    };
+    let state_path = resolve(&opts.state_path)?;
+    let key_file = opts
+        .state_encryption_key_file
+        .as_deref()
+        .map(resolve)
+        .transpose()?;
+
+    if !state_path.exists()
+        && !(opts.split_state && state_path.with_file_name("state.d").exists())
+    {
+        eprintln!("'{}' state file doesn't exist", state_path.display());
+        return Err(Failure);
+    }
+    // Held while resetting, so the server cannot start meanwhile.
+    let _lock = StateLock::acquire(&state_path).map_err(|e| {
+        eprintln!("{}, stop the server before resetting the password", e)
+    })?;
+
+    let hash = if opts.set {
+        let pass = password::read_line().map_err(|e| {
+            eprintln!("Failed to read password from STDIN: {}", e)
+        })?;
+        if pass.is_empty() {
+            eprintln!("Password must not be empty");
+            return Err(Failure);
+        }
+        Some(password::hash(&pass))
+    } else {
+        None
+    };
+    let cleared = hash.is_none();
+
+    let mut runtime = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .map_err(|e| eprintln!("Failed to build async runtime: {}", e))?;
+    let reset = runtime
+        .block_on(async {
+            let persistence = state::open_persistence(
+                &state_path,
+                opts.split_state,
+                key_file.as_deref(),
+            )
+            .await?;
+            reset_password(&*persistence, hash).await
+        })
+        .map_err(|e| eprintln!("{}", e))?;
+
+    if !reset {
+        println!("No password is set in '{}' state", state_path.display());
+    } else if cleared {
+        println!("Password is cleared in '{}' state", state_path.display());
+    } else {
+        println!("Password is replaced in '{}' state", state_path.display());
+    }
+    Ok(())
+}
+
+/// Resets the password of the [`State`] persisted in the given `persistence`
+/// to the given `hash` (clearing it, if [`None`]), serializing the [`State`]
+/// in the same way the server does.
+///
+/// Returns `false` if there is no password to be cleared.
+///
+/// # Errors
+///
+/// If nothing is persisted in the `persistence`, or it cannot be loaded or
+/// saved.
+pub async fn reset_password(
+    persistence: &dyn StatePersistence,
+    hash: Option<String>,
+) -> Result<bool, anyhow::Error> {
+    let contents = persistence.load().await?;
+    if contents.is_empty() {
+        return Err(anyhow!("No state is persisted in {}", persistence));
+    }
+    let state: State = serde_json::from_slice(&contents).map_err(|e| {
+        anyhow!("Failed to deserialize state from {}: {}", persistence, e)
+    })?;
+
+    {
+        let mut settings = state.settings.lock_mut();
+        if hash.is_none() && settings.password_hash.is_none() {
+            return Ok(false);
+        }
+        settings.reset_password(hash, PasswordResetVia::Command);
+    }
+
+    let contents = serde_json::to_vec(&state)
+        .map_err(|e| anyhow!("Failed to serialize state: {}", e))?;
+    persistence.save(contents).await.map_err(|e| {
+        anyhow!("Failed to persist state into {}: {}", persistence, e)
+    })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod state_lock_spec {
+    use std::{env, fs, path::PathBuf, process};
+
+    use uuid::Uuid;
+
+    use super::StateLock;
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!("ephyr-state-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn is_held_till_dropped() {
+        let path = temp_path();
+
+        let lock = StateLock::acquire(&path).unwrap();
+        assert_eq!(StateLock::holder(&path), Some(process::id()));
+        assert!(StateLock::acquire(&path).is_err());
+
+        drop(lock);
+        assert_eq!(StateLock::holder(&path), None);
+        drop(StateLock::acquire(&path).unwrap());
+
+        fs::remove_file(StateLock::path_of(&path)).unwrap();
+    }
+
+    #[test]
+    fn ignores_lock_file_left_by_exited_process() {
+        for pid in &[1, process::id(), u32::MAX] {
+            let path = temp_path();
+            fs::write(StateLock::path_of(&path), pid.to_string()).unwrap();
+
+            assert_eq!(StateLock::holder(&path), None);
+            let lock = StateLock::acquire(&path).unwrap();
+            assert_eq!(StateLock::holder(&path), Some(process::id()));
+
+            drop(lock);
+            fs::remove_file(StateLock::path_of(&path)).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod reset_password_spec {
+    use std::{env, fs, path::PathBuf};
+
+    use actix_web::rt::System;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        password,
+        state::{FilePersistence, PasswordResetVia, StatePersistence as _},
+        State,
+    };
+
+    use super::reset_password;
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!("ephyr-state-{}.json", Uuid::new_v4()))
+    }
+
+    fn load(file: &FilePersistence) -> State {
+        System::new("test").block_on(async {
+            serde_json::from_slice(&file.load().await.unwrap()).unwrap()
+        })
+    }
+
+    fn persisted(hash: Option<String>) -> (PathBuf, FilePersistence) {
+        let path = temp_path();
+        let contents = json!({
+            "settings": {
+                "password_hash": hash,
+                "viewer_password_hash": password::hash("viewer"),
+                "title": "test",
+                "delete_confirmation": null,
+                "enable_confirmation": null,
+            },
+            "restreams": [{"id": Uuid::new_v4(), "key": "main"}],
+        });
+        fs::write(&path, serde_json::to_vec(&contents).unwrap()).unwrap();
+        let file = FilePersistence::new(&path);
+        (path, file)
+    }
+
+    #[test]
+    fn clears_password() {
+        let (path, file) = persisted(Some(password::hash("lost")));
+
+        let reset = System::new("test")
+            .block_on(reset_password(&file, None))
+            .unwrap();
+        assert!(reset);
+
+        let state = load(&file);
+        let settings = state.settings.get_cloned();
+        assert_eq!(settings.password_hash, None);
+        assert_eq!(settings.viewer_password_hash, None);
+        assert_eq!(settings.title.as_deref(), Some("test"));
+        let audit = settings.password_reset.unwrap();
+        assert_eq!(audit.via, PasswordResetVia::Command);
+        assert!(audit.cleared);
+        assert_eq!(state.restreams.lock_ref().len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_password() {
+        let (path, file) = persisted(Some(password::hash("lost")));
+
+        let reset = System::new("test")
+            .block_on(reset_password(&file, Some(password::hash("new"))))
+            .unwrap();
+        assert!(reset);
+
+        let settings = load(&file).settings.get_cloned();
+        assert!(password::verify(
+            settings.password_hash.as_deref().unwrap(),
+            "new",
+        ));
+        assert!(!settings.password_reset.unwrap().cleared);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn does_nothing_without_password() {
+        let (path, file) = persisted(None);
+
+        let reset = System::new("test")
+            .block_on(reset_password(&file, None))
+            .unwrap();
+        assert!(!reset);
+        assert_eq!(load(&file).settings.get_cloned().password_reset, None);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_empty_state() {
+        let path = temp_path();
+        let file = FilePersistence::new(&path);
+
+        assert!(System::new("test")
+            .block_on(reset_password(&file, None))
+            .is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,82 @@
+//! Applying of scheduled [`state::Output::pending_dst`]s.
+
+use std::time::SystemTime;
+
+use ephyr_log::log;
+
+use crate::{
+    state::{self, OutputId, RestreamId, State},
+    timer::Timers,
+};
+
+/// Watcher of [`state::Output`]s with [`state::Output::pending_dst`] scheduled
+/// to be applied at [`state::Output::apply_at`], replacing their
+/// [`state::Output::dst`] once the moment comes.
+///
+/// Applying, cancelling or rescheduling a change drops its [`Timer`], so only
+/// the latest scheduled change of an [`state::Output`] is ever applied.
+///
+/// [`Timer`]: crate::timer::Timer
+#[derive(Debug)]
+pub struct PendingDstsWatcher {
+    /// Running timers of [`state::Output`]s, along with the IDs of their
+    /// [`state::Restream`]s and the moments they fire at.
+    timers: Timers<OutputId, (RestreamId, SystemTime)>,
+}
+
+impl PendingDstsWatcher {
+    /// Creates a new [`PendingDstsWatcher`] operating on the given [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            timers: Timers::new(state),
+        }
+    }
+
+    /// Starts timers for the [`state::Output::pending_dst`]s of the given
+    /// `restreams` scheduled since the previous call, and drops the ones not
+    /// scheduled anymore.
+    ///
+    /// Changes scheduled in the past (while the server was down, for example)
+    /// are applied right away.
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let scheduled = restreams
+            .iter()
+            .flat_map(|r| {
+                r.outputs.iter().filter_map(move |o| {
+                    let at = o.apply_at.filter(|_| o.pending_dst.is_some())?;
+                    Some((o.id, (r.id, SystemTime::from(at))))
+                })
+            })
+            .collect();
+
+        self.timers.rearm(
+            scheduled,
+            |(_, at)| *at,
+            |state, output_id, (restream_id, _)| {
+                apply(state, restream_id, output_id);
+            },
+        );
+    }
+}
+
+/// Applies the [`state::Output::pending_dst`] of the [`state::Output`] with
+/// the given IDs in the `actual` [`State`], if its
+/// [`state::Output::apply_at`] moment has come.
+///
+/// Failures are logged only, keeping the [`state::Output::pending_dst`] to be
+/// applied manually.
+fn apply(actual: &State, restream_id: RestreamId, output_id: OutputId) {
+    match actual.apply_pending_output_dst(restream_id, output_id, true) {
+        Ok(Some(true)) => {
+            log::info!("Applied scheduled dst change of {} output", output_id);
+        }
+        Ok(_) => {}
+        Err(e) => log::error!(
+            "Failed to apply scheduled dst change of {} output: {}",
+            output_id,
+            e,
+        ),
+    }
+}
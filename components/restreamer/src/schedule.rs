@@ -0,0 +1,839 @@
+//! Recurring weekly schedules of [`state::Input`]s pulling a live stream,
+//! allowing them to pull within scheduled hours only.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ephyr_log::log;
+use juniper::{graphql_object, GraphQLEnum, GraphQLObject};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    api::graphql::DateTime,
+    state::{self, InputId, State, Status, Statuses},
+    timer::Timers,
+};
+
+/// Number of seconds in a single day.
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Number of seconds in a single week.
+const WEEK_SECS: u64 = 7 * DAY_SECS;
+
+/// Day of a week.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, GraphQLEnum, PartialEq, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    /// Monday.
+    Monday,
+
+    /// Tuesday.
+    Tuesday,
+
+    /// Wednesday.
+    Wednesday,
+
+    /// Thursday.
+    Thursday,
+
+    /// Friday.
+    Friday,
+
+    /// Saturday.
+    Saturday,
+
+    /// Sunday.
+    Sunday,
+}
+
+impl Weekday {
+    /// Returns the number of days passed since the beginning of a week (being
+    /// Monday) till this [`Weekday`].
+    #[inline]
+    #[must_use]
+    fn days_since_monday(self) -> u64 {
+        match self {
+            Self::Monday => 0,
+            Self::Tuesday => 1,
+            Self::Wednesday => 2,
+            Self::Thursday => 3,
+            Self::Friday => 4,
+            Self::Saturday => 5,
+            Self::Sunday => 6,
+        }
+    }
+
+    /// Returns the [`Weekday`] being the given number of `days` since the
+    /// beginning of a week (being Monday), wrapping over the next weeks.
+    #[must_use]
+    fn from_days_since_monday(days: u64) -> Self {
+        match days % 7 {
+            0 => Self::Monday,
+            1 => Self::Tuesday,
+            2 => Self::Wednesday,
+            3 => Self::Thursday,
+            4 => Self::Friday,
+            5 => Self::Saturday,
+            _ => Self::Sunday,
+        }
+    }
+}
+
+/// Range of time (in UTC) on a single [`Weekday`] to pull a live stream
+/// within, like `Monday 19:00-23:00`.
+///
+/// May span midnight (if its end is not after its start), like
+/// `Friday 23:30-01:00`, ending on the next day then.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct ScheduleRange {
+    /// [`Weekday`] (in UTC) this [`ScheduleRange`] starts on.
+    pub weekday: Weekday,
+
+    /// Minute of the day (in UTC) this [`ScheduleRange`] starts at.
+    #[serde(serialize_with = "serialize_time")]
+    start: u16,
+
+    /// Minute of the day (in UTC) this [`ScheduleRange`] ends at
+    /// (exclusively).
+    #[serde(serialize_with = "serialize_time")]
+    end: u16,
+}
+
+impl ScheduleRange {
+    /// Creates a new [`ScheduleRange`] out of the given `HH:MM` times of the
+    /// day (in UTC).
+    ///
+    /// Returns [`None`] if any of the times is not a valid `HH:MM` time, or
+    /// they're equal (so the [`ScheduleRange`] is empty).
+    #[must_use]
+    pub fn new(weekday: Weekday, start: &str, end: &str) -> Option<Self> {
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        (start != end).then(|| Self {
+            weekday,
+            start,
+            end,
+        })
+    }
+
+    /// Converts this [`ScheduleRange`] of a local time having the given
+    /// `offset` from UTC (in seconds, positive to the east of it) into UTC,
+    /// moving it to the previous or next [`Weekday`] if the shift crosses
+    /// midnight.
+    #[must_use]
+    pub fn to_utc(self, offset: i32) -> Self {
+        /// Number of minutes in a single week.
+        const WEEK_MINS: i64 = 7 * 24 * 60;
+
+        let (day, week) = (DAY_SECS / 60, WEEK_SECS / 60);
+        #[allow(clippy::cast_sign_loss)] // `rem_euclid()` is never negative
+        let offset = i64::from(offset / 60).rem_euclid(WEEK_MINS) as u64;
+        let shift = |mins: u64| (mins + week - offset) % week;
+
+        let start = shift(
+            self.weekday.days_since_monday() * day + u64::from(self.start),
+        );
+        #[allow(clippy::cast_possible_truncation)] // less than a day
+        let (start_time, end) = (
+            (start % day) as u16,
+            (shift(u64::from(self.end)) % day) as u16,
+        );
+        Self {
+            weekday: Weekday::from_days_since_monday(start / day),
+            start: start_time,
+            end,
+        }
+    }
+
+    /// Returns the second of a week (since Monday `00:00` UTC) this
+    /// [`ScheduleRange`] starts at.
+    #[inline]
+    #[must_use]
+    fn start_of_week(&self) -> u64 {
+        self.weekday.days_since_monday() * DAY_SECS + u64::from(self.start) * 60
+    }
+
+    /// Returns the duration (in seconds) of this [`ScheduleRange`].
+    #[inline]
+    #[must_use]
+    fn len(&self) -> u64 {
+        let (start, end) = (u64::from(self.start), u64::from(self.end));
+        if start < end {
+            (end - start) * 60
+        } else {
+            DAY_SECS - (start - end) * 60
+        }
+    }
+
+    /// Returns the number of seconds elapsed since the beginning of this
+    /// [`ScheduleRange`] at the given moment (in seconds since [`UNIX_EPOCH`]),
+    /// if it falls into it.
+    #[must_use]
+    fn elapsed(&self, at: u64) -> Option<u64> {
+        let passed =
+            (second_of_week(at) + WEEK_SECS - self.start_of_week()) % WEEK_SECS;
+        (passed < self.len()).then(|| passed)
+    }
+
+    /// Returns the number of seconds left till the nearest beginning of this
+    /// [`ScheduleRange`] at the given moment (in seconds since
+    /// [`UNIX_EPOCH`]).
+    #[inline]
+    #[must_use]
+    fn until(&self, at: u64) -> u64 {
+        (self.start_of_week() + WEEK_SECS - second_of_week(at)) % WEEK_SECS
+    }
+}
+
+/// Range of time (in UTC) on a single day of a week to pull a live stream
+/// within.
+///
+/// May span midnight (if its `end` is not after its `start`), ending on the
+/// next day then.
+#[graphql_object]
+impl ScheduleRange {
+    /// Day of a week (in UTC) this `ScheduleRange` starts on.
+    fn weekday(&self) -> Weekday {
+        self.weekday
+    }
+
+    /// Time of the day (in UTC) this `ScheduleRange` starts at, formatted as
+    /// `HH:MM`.
+    fn start(&self) -> String {
+        format_time(self.start)
+    }
+
+    /// Time of the day (in UTC) this `ScheduleRange` ends at (exclusively),
+    /// formatted as `HH:MM`.
+    fn end(&self) -> String {
+        format_time(self.end)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScheduleRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawScheduleRange {
+            weekday: Weekday,
+            start: String,
+            end: String,
+        }
+
+        let raw = RawScheduleRange::deserialize(deserializer)?;
+        Self::new(raw.weekday, &raw.start, &raw.end).ok_or_else(|| {
+            D::Error::custom(format!(
+                "Not a valid ScheduleRange: {}-{}, should be non-empty \
+                 `HH:MM-HH:MM`",
+                raw.start, raw.end,
+            ))
+        })
+    }
+}
+
+/// Recurring weekly schedule of a [`state::Input`] pulling a live stream,
+/// allowing it to pull within its [`ScheduleRange`]s only.
+///
+/// Overlapping and adjacent [`ScheduleRange`]s are merged into a single
+/// [`ScheduleWindow`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct InputSchedule(Vec<ScheduleRange>);
+
+impl InputSchedule {
+    /// Creates a new [`InputSchedule`] out of the given [`ScheduleRange`]s.
+    ///
+    /// Returns [`None`] if there are no [`ScheduleRange`]s.
+    #[inline]
+    #[must_use]
+    pub fn new(ranges: Vec<ScheduleRange>) -> Option<Self> {
+        (!ranges.is_empty()).then(|| Self(ranges))
+    }
+
+    /// Returns [`ScheduleRange`]s of this [`InputSchedule`].
+    #[inline]
+    #[must_use]
+    pub fn ranges(&self) -> &[ScheduleRange] {
+        &self.0
+    }
+
+    /// Indicates whether the given moment falls into this [`InputSchedule`].
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, at: SystemTime) -> bool {
+        self.contains_secs(unix_secs(at))
+    }
+
+    /// Returns the [`ScheduleWindow`] of this [`InputSchedule`] the given
+    /// moment falls into, if any.
+    #[inline]
+    #[must_use]
+    pub fn active_window(&self, at: SystemTime) -> Option<ScheduleWindow> {
+        self.window_at(unix_secs(at))
+    }
+
+    /// Returns the nearest [`ScheduleWindow`] of this [`InputSchedule`]
+    /// starting after the given moment (and after the active one, if any).
+    #[must_use]
+    pub fn next_window(&self, at: SystemTime) -> Option<ScheduleWindow> {
+        let from = self
+            .active_window(at)
+            .map_or_else(|| unix_secs(at), |w| unix_secs(w.ends_at.into()));
+        // Zero is impossible here, as `from` never falls into this schedule.
+        let wait = self.0.iter().map(|r| r.until(from)).min()?;
+        self.window_at(from + wait)
+    }
+
+    /// Returns the nearest moment after the given one when the given moment
+    /// falls into this [`InputSchedule`] changes, so a pulling should be
+    /// started or stopped.
+    #[must_use]
+    pub fn next_change(&self, at: SystemTime) -> Option<SystemTime> {
+        self.active_window(at)
+            .map(|w| w.ends_at)
+            .or_else(|| self.next_window(at).map(|w| w.starts_at))
+            .map(Into::into)
+    }
+
+    /// Indicates whether the given moment (in seconds since [`UNIX_EPOCH`])
+    /// falls into this [`InputSchedule`].
+    #[inline]
+    #[must_use]
+    fn contains_secs(&self, at: u64) -> bool {
+        self.0.iter().any(|r| r.elapsed(at).is_some())
+    }
+
+    /// Returns the [`ScheduleWindow`] of this [`InputSchedule`] the given
+    /// moment (in seconds since [`UNIX_EPOCH`]) falls into, if any, merging
+    /// all the overlapping and adjacent [`ScheduleRange`]s.
+    ///
+    /// A [`ScheduleWindow`] of an [`InputSchedule`] covering the whole week is
+    /// bounded by its [`ScheduleRange`]s merged once.
+    #[must_use]
+    fn window_at(&self, at: u64) -> Option<ScheduleWindow> {
+        let earliest = |at: u64| {
+            self.0
+                .iter()
+                .filter_map(|r| Some(at - r.elapsed(at)?))
+                .min()
+        };
+        let latest = |at: u64| {
+            self.0
+                .iter()
+                .filter_map(|r| Some(at - r.elapsed(at)? + r.len()))
+                .max()
+        };
+
+        let (mut start, mut end) = (earliest(at)?, latest(at)?);
+        for _ in 0..self.0.len() {
+            match start.checked_sub(1).and_then(earliest) {
+                Some(s) if s < start => start = s,
+                _ => break,
+            }
+        }
+        for _ in 0..self.0.len() {
+            match latest(end) {
+                Some(e) if e > end => end = e,
+                _ => break,
+            }
+        }
+        Some(ScheduleWindow {
+            starts_at: secs_to_time(start).into(),
+            ends_at: secs_to_time(end).into(),
+        })
+    }
+}
+
+/// Continuous period of time of an `InputSchedule` to pull a live stream
+/// within.
+#[derive(Clone, Copy, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct ScheduleWindow {
+    /// Moment this `ScheduleWindow` starts at.
+    pub starts_at: DateTime,
+
+    /// Moment this `ScheduleWindow` ends at (exclusively).
+    pub ends_at: DateTime,
+}
+
+/// Watcher of [`state::Input`]s with [`state::Input::schedule`] specified,
+/// marking their [`state::InputEndpoint`]s as [`Status::OutOfSchedule`] once
+/// they leave their [`ScheduleWindow`]s, and back as [`Status::Offline`] once
+/// they enter them again.
+///
+/// Changing [`Status`] of a [`state::InputEndpoint`] makes the
+/// [`ffmpeg::RestreamersPool`] to stop or start its pulling [FFmpeg] process.
+///
+/// Only the nearest [`InputSchedule::next_change()`] of each enabled
+/// [`state::Input`] has a [`Timer`] running, re-armed for the next one once it
+/// fires, so disabling the [`state::Input`] or changing its
+/// [`state::Input::schedule`] just drops or replaces the [`Timer`].
+///
+/// [`ffmpeg::RestreamersPool`]: crate::ffmpeg::RestreamersPool
+/// [`Timer`]: crate::timer::Timer
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Debug)]
+pub struct ScheduledInputsWatcher {
+    /// Running timers of scheduled [`state::Input`]s, along with the moments
+    /// they fire at.
+    timers: Timers<InputId, SystemTime>,
+
+    /// Application [`State`] to renew [`Status`]es of scheduled
+    /// [`state::Input`]s in.
+    state: State,
+}
+
+impl ScheduledInputsWatcher {
+    /// Creates a new [`ScheduledInputsWatcher`] operating on the given
+    /// [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            timers: Timers::new(state.clone()),
+            state,
+        }
+    }
+
+    /// Starts timers for the scheduled [`state::Input`]s of the given
+    /// `restreams` till their nearest [`InputSchedule::next_change()`], and
+    /// drops the ones not required anymore.
+    ///
+    /// Renews [`Status`]es of the [`state::Input`]s being out of sync (in the
+    /// given `statuses`) with their [`state::Input::schedule`] right away.
+    pub fn apply(
+        &mut self,
+        restreams: &[state::Restream],
+        statuses: &Statuses,
+    ) {
+        let now = SystemTime::now();
+        let mut changes = HashMap::new();
+        let mut stale = false;
+        for r in restreams {
+            visit(&r.input, &mut |i| {
+                stale |= needs_renewal(i, statuses, now);
+                if let Some(at) = i
+                    .schedule
+                    .as_ref()
+                    .filter(|_| i.enabled)
+                    .and_then(|s| s.next_change(now))
+                {
+                    let _ = changes.insert(i.id, at);
+                }
+            });
+        }
+
+        if stale {
+            let state = self.state.clone();
+            drop(tokio::spawn(
+                async move { renew(&state, SystemTime::now()) },
+            ));
+        }
+
+        self.timers.rearm(
+            changes,
+            |at| *at,
+            |state, _, _| {
+                renew(state, SystemTime::now());
+            },
+        );
+    }
+}
+
+/// Calls the given function for the given [`state::Input`] and all its
+/// [`state::FailoverInputSrc::inputs`].
+fn visit<F: FnMut(&state::Input)>(input: &state::Input, f: &mut F) {
+    f(input);
+    if let Some(state::InputSrc::Failover(s)) = &input.src {
+        for i in &s.inputs {
+            visit(i, f);
+        }
+    }
+}
+
+/// Checks whether [`Status`]es of the given [`state::Input`]'s
+/// [`state::InputEndpoint`]s in the given `statuses` don't reflect its
+/// [`state::Input::schedule`] at the given moment.
+#[must_use]
+fn needs_renewal(
+    input: &state::Input,
+    statuses: &Statuses,
+    at: SystemTime,
+) -> bool {
+    let out = input.is_out_of_schedule(at);
+    input
+        .endpoints
+        .iter()
+        .any(|e| (statuses.get(e.id) == Status::OutOfSchedule) != out)
+}
+
+/// Renews [`Status`]es of [`state::InputEndpoint`]s of all the
+/// [`state::Input`]s in the `actual` [`State`] according to their
+/// [`state::Input::schedule`]s at the given moment.
+fn renew(actual: &State, at: SystemTime) {
+    fn renew_input(
+        input: &state::Input,
+        key: &str,
+        statuses: &mut Statuses,
+        at: SystemTime,
+    ) {
+        if needs_renewal(input, statuses, at) {
+            let out = input.is_out_of_schedule(at);
+            for e in &input.endpoints {
+                if out {
+                    let _ = statuses.set(e.id, Status::OutOfSchedule);
+                } else if statuses.get(e.id) == Status::OutOfSchedule {
+                    let _ = statuses.set(e.id, Status::Offline);
+                }
+            }
+            log::info!(
+                "`{}` input of `{}` app {} its schedule",
+                input.key,
+                key,
+                if out { "has left" } else { "has entered" },
+            );
+        }
+        if let Some(state::InputSrc::Failover(s)) = &input.src {
+            for i in &s.inputs {
+                renew_input(i, key, statuses, at);
+            }
+        }
+    }
+
+    let restreams = actual.restreams.lock_ref();
+    let mut statuses = actual.statuses.lock_mut("renew_input");
+    for r in restreams.iter() {
+        renew_input(&r.input, &r.key, &mut statuses, at);
+    }
+}
+
+/// Returns the number of whole seconds passed since [`UNIX_EPOCH`] till the
+/// given moment.
+#[inline]
+#[must_use]
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Converts the given number of seconds passed since [`UNIX_EPOCH`] into a
+/// [`SystemTime`].
+#[inline]
+#[must_use]
+fn secs_to_time(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Returns the second of a week (since Monday `00:00` UTC) the given moment (in
+/// seconds since [`UNIX_EPOCH`]) falls into.
+///
+/// [`UNIX_EPOCH`] itself is Thursday.
+#[inline]
+#[must_use]
+fn second_of_week(at: u64) -> u64 {
+    (at + 3 * DAY_SECS) % WEEK_SECS
+}
+
+/// Parses the given `HH:MM` time of the day into minutes.
+fn parse_time(s: &str) -> Option<u16> {
+    let mut parts = s.trim().splitn(2, ':');
+    let (h, m) = (parts.next()?, parts.next()?);
+    if h.len() != 2 || m.len() != 2 {
+        return None;
+    }
+    let (h, m) = (h.parse::<u16>().ok()?, m.parse::<u16>().ok()?);
+    (h < 24 && m < 60).then(|| h * 60 + m)
+}
+
+/// Formats the given minute of the day as `HH:MM` time.
+#[inline]
+#[must_use]
+fn format_time(minute: u16) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Serializes the given minute of the day as `HH:MM` time.
+#[allow(clippy::trivially_copy_pass_by_ref)] // required by `serde`
+fn serialize_time<S: Serializer>(
+    minute: &u16,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_time(*minute))
+}
+
+#[cfg(test)]
+mod input_schedule_spec {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{InputSchedule, ScheduleRange, Weekday};
+
+    /// Monday, 2021-03-01 00:00:00 UTC.
+    const MONDAY: u64 = 1_614_556_800;
+
+    fn at(day: u64, hh: u64, mm: u64) -> SystemTime {
+        UNIX_EPOCH
+            + Duration::from_secs(MONDAY + day * 86400 + hh * 3600 + mm * 60)
+    }
+
+    fn schedule(ranges: &[(Weekday, &str, &str)]) -> InputSchedule {
+        InputSchedule::new(
+            ranges
+                .iter()
+                .map(|(d, s, e)| ScheduleRange::new(*d, s, e).unwrap())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn converts_local_ranges_to_utc() {
+        // UTC+03:00: Tuesday 02:00-04:00 crosses midnight only in UTC.
+        let range = ScheduleRange::new(Weekday::Tuesday, "02:00", "04:00")
+            .unwrap()
+            .to_utc(3 * 3600);
+        assert_eq!(
+            range,
+            ScheduleRange::new(Weekday::Monday, "23:00", "01:00").unwrap(),
+        );
+        let schedule = InputSchedule::new(vec![range]).unwrap();
+        assert!(!schedule.contains(at(0, 22, 59)));
+        assert!(schedule.contains(at(0, 23, 0)));
+        assert!(schedule.contains(at(1, 0, 59)));
+        assert!(!schedule.contains(at(1, 1, 0)));
+
+        // UTC-05:00: Sunday 22:00-23:30 moves to the next week's Monday.
+        assert_eq!(
+            ScheduleRange::new(Weekday::Sunday, "22:00", "23:30")
+                .unwrap()
+                .to_utc(-5 * 3600),
+            ScheduleRange::new(Weekday::Monday, "03:00", "04:30").unwrap(),
+        );
+        // UTC+05:30: Monday 23:00-01:00 stops crossing midnight.
+        assert_eq!(
+            ScheduleRange::new(Weekday::Monday, "23:00", "01:00")
+                .unwrap()
+                .to_utc(5 * 3600 + 1800),
+            ScheduleRange::new(Weekday::Monday, "17:30", "19:30").unwrap(),
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for (start, end) in &[
+            ("19:00", "19:00"),
+            ("24:00", "01:00"),
+            ("19:60", "20:00"),
+            ("7:00", "20:00"),
+            ("19-00", "20:00"),
+        ] {
+            assert!(
+                ScheduleRange::new(Weekday::Monday, start, end).is_none(),
+                "{}-{}",
+                start,
+                end,
+            );
+        }
+        assert!(InputSchedule::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn contains_range_within_day() {
+        let s = schedule(&[(Weekday::Tuesday, "19:00", "23:00")]);
+
+        assert!(!s.contains(at(1, 18, 59)));
+        assert!(s.contains(at(1, 19, 0)));
+        assert!(s.contains(at(1, 22, 59)));
+        assert!(!s.contains(at(1, 23, 0)));
+        assert!(!s.contains(at(0, 20, 0)));
+        assert!(!s.contains(at(8, 20, 0) + Duration::from_secs(86400)));
+        assert!(s.contains(at(8, 20, 0)));
+    }
+
+    #[test]
+    fn contains_range_crossing_midnight() {
+        let s = schedule(&[(Weekday::Friday, "23:30", "01:00")]);
+
+        assert!(!s.contains(at(4, 23, 29)));
+        assert!(s.contains(at(4, 23, 30)));
+        assert!(s.contains(at(5, 0, 0)));
+        assert!(s.contains(at(5, 0, 59)));
+        assert!(!s.contains(at(5, 1, 0)));
+        // Only the day it starts on matters.
+        assert!(!s.contains(at(3, 23, 45)));
+        assert!(!s.contains(at(4, 0, 30)));
+    }
+
+    #[test]
+    fn contains_range_crossing_week_end() {
+        let s = schedule(&[(Weekday::Sunday, "22:00", "02:00")]);
+
+        assert!(s.contains(at(6, 22, 0)));
+        assert!(s.contains(at(7, 1, 59)));
+        assert!(!s.contains(at(7, 2, 0)));
+        // Recurs weekly, so covers the beginning of the very first week too.
+        assert!(s.contains(at(0, 1, 0)));
+        assert!(!s.contains(at(0, 2, 0)));
+    }
+
+    #[test]
+    fn contains_range_ending_at_midnight() {
+        let s = schedule(&[(Weekday::Monday, "19:00", "00:00")]);
+
+        assert!(s.contains(at(0, 23, 59)));
+        assert!(!s.contains(at(1, 0, 0)));
+    }
+
+    #[test]
+    fn merges_adjacent_ranges_crossing_midnight() {
+        let s = schedule(&[
+            (Weekday::Monday, "22:00", "00:00"),
+            (Weekday::Tuesday, "00:00", "02:00"),
+        ]);
+
+        let w = s.active_window(at(1, 1, 0)).unwrap();
+        assert_eq!(SystemTime::from(w.starts_at), at(0, 22, 0));
+        assert_eq!(SystemTime::from(w.ends_at), at(1, 2, 0));
+        assert_eq!(s.next_change(at(0, 23, 0)), Some(at(1, 2, 0)));
+    }
+
+    #[test]
+    fn finds_next_window() {
+        let s = schedule(&[
+            (Weekday::Monday, "19:00", "23:00"),
+            (Weekday::Saturday, "23:00", "01:00"),
+        ]);
+
+        assert!(s.active_window(at(0, 18, 0)).is_none());
+        let w = s.next_window(at(0, 18, 0)).unwrap();
+        assert_eq!(SystemTime::from(w.starts_at), at(0, 19, 0));
+        assert_eq!(SystemTime::from(w.ends_at), at(0, 23, 0));
+
+        let w = s.next_window(at(0, 20, 0)).unwrap();
+        assert_eq!(SystemTime::from(w.starts_at), at(5, 23, 0));
+        assert_eq!(SystemTime::from(w.ends_at), at(6, 1, 0));
+
+        let w = s.next_window(at(6, 0, 30)).unwrap();
+        assert_eq!(SystemTime::from(w.starts_at), at(7, 19, 0));
+
+        assert_eq!(s.next_change(at(0, 18, 0)), Some(at(0, 19, 0)));
+        assert_eq!(s.next_change(at(0, 19, 0)), Some(at(0, 23, 0)));
+    }
+
+    #[test]
+    fn deserializes_and_serializes_ranges() {
+        let s: InputSchedule = serde_json::from_str(
+            r#"[{"weekday":"friday","start":"23:30","end":"01:00"}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(s, schedule(&[(Weekday::Friday, "23:30", "01:00")]));
+        assert_eq!(
+            serde_json::to_string(&s).unwrap(),
+            r#"[{"weekday":"friday","start":"23:30","end":"01:00"}]"#,
+        );
+        assert!(serde_json::from_str::<InputSchedule>(
+            r#"[{"weekday":"friday","start":"23:30","end":"23:30"}]"#,
+        )
+        .is_err());
+    }
+}
+
+#[cfg(test)]
+mod renew_spec {
+    use std::time::SystemTime;
+
+    use serde_json::json;
+
+    use crate::state::{State, Status};
+
+    use super::renew;
+
+    fn state(schedule: serde_json::Value, enabled: bool) -> State {
+        State::with_restream(
+            "relay",
+            json!({"input": {
+                "endpoints": [{"kind": "rtmp"}, {"kind": "hls"}],
+                "src": {"remote_url": "rtmp://example.com/live/tv"},
+                "enabled": enabled,
+                "schedule": schedule,
+            }}),
+        )
+    }
+
+    /// Schedule repeating the given `HH:MM` ranges every day.
+    fn daily(ranges: &[(&str, &str)]) -> serde_json::Value {
+        let days = [
+            "monday",
+            "tuesday",
+            "wednesday",
+            "thursday",
+            "friday",
+            "saturday",
+            "sunday",
+        ];
+        days.iter()
+            .flat_map(|d| {
+                ranges.iter().map(move |(start, end)| {
+                    json!({"weekday": d, "start": start, "end": end})
+                })
+            })
+            .collect()
+    }
+
+    /// Moment the schedules are renewed at in these tests.
+    fn noon() -> SystemTime {
+        super::secs_to_time(12 * 3600)
+    }
+
+    /// Schedule covering the whole day.
+    fn always() -> serde_json::Value {
+        daily(&[("00:00", "12:00"), ("12:00", "00:00")])
+    }
+
+    /// Schedule not covering the `noon()`.
+    fn off_noon() -> serde_json::Value {
+        daily(&[("13:00", "23:00")])
+    }
+
+    fn statuses(state: &State) -> Vec<Status> {
+        state.restreams.lock_ref()[0]
+            .input
+            .endpoints
+            .iter()
+            .map(|e| state.statuses.get(e.id))
+            .collect()
+    }
+
+    #[test]
+    fn marks_input_out_of_schedule() {
+        let state = state(off_noon(), true);
+
+        renew(&state, noon());
+
+        assert_eq!(statuses(&state), vec![Status::OutOfSchedule; 2]);
+    }
+
+    #[test]
+    fn restores_input_within_schedule() {
+        let state = state(always(), true);
+        for e in &state.restreams.lock_ref()[0].input.endpoints {
+            let _ = state.statuses.set(e.id, Status::OutOfSchedule);
+        }
+
+        renew(&state, noon());
+
+        assert_eq!(statuses(&state), vec![Status::Offline; 2]);
+    }
+
+    #[test]
+    fn keeps_disabled_input_offline() {
+        let state = state(off_noon(), false);
+
+        renew(&state, noon());
+
+        assert_eq!(statuses(&state), vec![Status::Offline; 2]);
+    }
+}
@@ -1,261 +1,3641 @@
 //! HTTP servers.
 
-use std::{net::IpAddr, time::Duration};
+use std::{
+    fs::Permissions,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    os::unix::{
+        fs::{FileTypeExt as _, PermissionsExt as _},
+        net::UnixListener,
+    },
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::{dev::Server, rt::System};
+use anyhow::anyhow;
+use ephyr_log::{log, slog};
+use futures::future::{self, Either, FutureExt, LocalBoxFuture, Shared};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    fs, runtime,
+    signal::{self, unix::SignalKind},
+    sync::Mutex,
+    task::JoinHandle,
+    time,
+};
+
+#[cfg(feature = "event-broker")]
+use crate::events;
+use crate::{
+    api, capacity, check,
+    cli::{Failure, Opts},
+    coalesce, compat, cycle, diagnostics, display_panic, dvr, evict, expiry,
+    extension::{self, Extension, ShutdownTrigger},
+    ffmpeg, ffprobe, idle, mail, overlay, preview, reconcile, recovery,
+    replication, rotation, schedule, spec, srs,
+    state::{
+        self, InMemoryPersistence, PublicHost, Quota, SpecReload,
+        StatePersistence,
+    },
+    sweep, teamspeak, trace, usage, workflow, Spec, State,
+};
+
+/// Runs all application's HTTP servers (see [`serve()`]) on the async runtime
+/// constructed according to the given [`Opts`].
+///
+/// The runtime is constructed explicitly, rather than via
+/// `#[actix_web::main]`, to have its blocking threads pool limited by
+/// [`Opts::blocking_threads`].
+///
+/// # Errors
+///
+/// If the async runtime cannot be constructed, or some [`HttpServer`] cannot
+/// run due to already used port, etc. The actual error is witten to logs.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+pub fn run(cfg: Opts) -> Result<(), Failure> {
+    run_with(cfg, None, |_| ())
+}
+
+/// Same as [`run()`], but invokes the given `on_start` callback with the
+/// [`Running`] servers once they're bound.
+///
+/// If a `shutdown` signal is given, then all the servers are stopped
+/// gracefully once it resolves, and OS signals are not handled by them at
+/// all.
+///
+/// # Errors
+///
+/// If the async runtime cannot be constructed, or some [`HttpServer`] cannot
+/// run due to already used port, etc. The actual error is witten to logs.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+pub fn run_with<F>(
+    cfg: Opts,
+    shutdown: Option<LocalBoxFuture<'static, ()>>,
+    on_start: F,
+) -> Result<(), Failure>
+where
+    F: FnOnce(Running),
+{
+    run_on_runtime(cfg, shutdown, vec![], on_start)
+}
+
+/// Same as [`run()`], but additionally runs the given [`Extension`]s along
+/// with the servers, until they're stopped.
+///
+/// # Errors
+///
+/// If the async runtime cannot be constructed, or some [`HttpServer`] cannot
+/// run due to already used port, etc. The actual error is witten to logs.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+pub fn run_with_extensions(
+    cfg: Opts,
+    extensions: Vec<Box<dyn Extension>>,
+) -> Result<(), Failure> {
+    run_on_runtime(cfg, None, extensions, |_| ())
+}
+
+/// Constructs the async runtime according to the given [`Opts`] and runs
+/// [`serve()`] on it.
+fn run_on_runtime<F>(
+    cfg: Opts,
+    shutdown: Option<LocalBoxFuture<'static, ()>>,
+    extensions: Vec<Box<dyn Extension>>,
+    on_start: F,
+) -> Result<(), Failure>
+where
+    F: FnOnce(Running),
+{
+    let runtime = runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .max_threads(cfg.blocking_threads)
+        .build()
+        .map_err(|e| log::error!("Failed to build async runtime: {}", e))?;
+    System::attach_to_tokio(
+        "main",
+        runtime,
+        serve(cfg, shutdown.map(FutureExt::shared), extensions, on_start),
+    )
+}
+
+/// Signal to stop [`HttpServer`]s gracefully, shared between all of them.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+pub type Shutdown = Shared<LocalBoxFuture<'static, ()>>;
+
+/// Information about application's HTTP servers being run by [`run_with()`].
+#[derive(Clone, Debug)]
+pub struct Running {
+    /// [`State`] the servers operate on.
+    pub state: State,
+
+    /// Address the client HTTP server is actually bound to.
+    ///
+    /// Differs from the configured one, if an ephemeral port has been
+    /// requested.
+    pub client_addr: SocketAddr,
+
+    /// Address the callback HTTP server is actually bound to.
+    ///
+    /// [`None`] in [`Opts::single_port`] mode, as there is no separate
+    /// callback HTTP server.
+    pub callback_addr: Option<SocketAddr>,
+}
+
+/// Initializes and runs all application's HTTP servers along with the given
+/// [`Extension`]s, until the given `shutdown` signal resolves (if any).
+///
+/// # Errors
+///
+/// If some [`HttpServer`] cannot run due to already used port, etc.
+/// The actual error is witten to logs.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+async fn serve<F>(
+    mut cfg: Opts,
+    shutdown: Option<Shutdown>,
+    extensions: Vec<Box<dyn Extension>>,
+    on_start: F,
+) -> Result<(), Failure>
+where
+    F: FnOnce(Running),
+{
+    cfg.resolve_paths()
+        .map_err(|e| log::error!("Failed to resolve paths: {}", e))?;
+
+    #[cfg(feature = "tracing")]
+    if let Some(endpoint) = &cfg.tracing_otlp {
+        trace::otlp::install(endpoint).map_err(|e| {
+            log::error!("Failed to install tracing exporter: {}", e)
+        })?;
+    }
+    #[cfg(not(feature = "tracing"))]
+    if cfg.tracing_otlp.is_some() {
+        return Err(log::error!(
+            "Tracing exporter is configured, but `tracing` feature is not \
+             compiled in"
+        )
+        .into());
+    }
+
+    #[cfg(feature = "fault-injection")]
+    if cfg.enable_fault_injection {
+        log::warn!("Fault injection is enabled, never use it in production");
+    }
+    #[cfg(not(feature = "fault-injection"))]
+    if cfg.enable_fault_injection {
+        return Err(log::error!(
+            "Fault injection is enabled, but `fault-injection` feature is not \
+             compiled in"
+        )
+        .into());
+    }
+
+    cfg.public_host = Some(match cfg.public_host.take() {
+        // Brackets bare IPv6 literals to be usable in URLs.
+        Some(host) => PublicHost::new(host.as_str()).map_or(host, Into::into),
+        None => PublicHost::from(resolve_public_ip(&cfg).await.ok_or_else(
+            || log::error!("Cannot detect server's public IP address"),
+        )?)
+        .into(),
+    });
+
+    let ffmpeg_path =
+        fs::canonicalize(&cfg.ffmpeg_path).await.map_err(|e| {
+            log::error!("Failed to resolve FFmpeg binary path: {}", e)
+        })?;
+    let ffmpeg_caps = ffmpeg::Capabilities::detect(&ffmpeg_path)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "Failed to detect capabilities of FFmpeg binary {}, \
+                 check whether it's a working FFmpeg: {}",
+                ffmpeg_path.display(),
+                e,
+            )
+        })?;
+
+    fs::create_dir_all(&cfg.overlays_dir).await.map_err(|e| {
+        log::error!("Failed to create overlays directory: {}", e)
+    })?;
+
+    // Rolling buffers of delayed `Output`s are useless after restart, as they
+    // have already fallen behind.
+    fs::remove_dir_all(&cfg.buffers_dir)
+        .await
+        .or_else(|e| (e.kind() == io::ErrorKind::NotFound).then(|| ()).ok_or(e))
+        .map_err(|e| {
+            log::error!("Failed to wipe out buffers directory: {}", e)
+        })?;
+    fs::create_dir_all(&cfg.buffers_dir).await.map_err(|e| {
+        log::error!("Failed to create buffers directory: {}", e)
+    })?;
+    let buffers_dir =
+        fs::canonicalize(&cfg.buffers_dir).await.map_err(|e| {
+            log::error!("Failed to resolve buffers directory path: {}", e)
+        })?;
+
+    let initial_spec =
+        if let Some(path) = &cfg.initial_spec {
+            Some(read_spec(path).await.map_err(|e| {
+                log::error!("Failed to load initial spec: {}", e)
+            })?)
+        } else {
+            None
+        };
+    let state_dir = cfg.state_path.with_file_name("state.d");
+    let is_first_boot = cfg.no_persist
+        || (fs::metadata(&cfg.state_path)
+            .await
+            .map_or(true, |m| m.len() == 0)
+            && !(cfg.split_state
+                && fs::metadata(state_dir.join("index.json")).await.is_ok()));
+
+    // Lock is held till the end of the program, so the state is not reset via
+    // `reset-password` command while being used.
+    let state_lock = if cfg.no_persist {
+        None
+    } else {
+        Some(
+            recovery::StateLock::acquire(&cfg.state_path)
+                .map_err(|e| log::error!("{}", e))?,
+        )
+    };
+    let persistence: Arc<dyn StatePersistence> = if cfg.no_persist {
+        Arc::new(InMemoryPersistence)
+    } else {
+        state::open_persistence(
+            &cfg.state_path,
+            cfg.split_state,
+            cfg.state_encryption_key_file.as_deref(),
+        )
+        .await
+        .map_err(|e| log::error!("{}", e))?
+    };
+    let state = State::try_new_with(persistence)
+        .await
+        .map_err(|e| log::error!("Failed to initialize server state: {}", e))?;
+
+    state.quota.set(Quota {
+        max_restreams: cfg.max_restreams,
+        max_outputs_per_restream: cfg.max_outputs_per_restream,
+    });
+    state.media_root.set(cfg.media_root.clone());
+    if let Some(spec) = initial_spec {
+        if is_first_boot || cfg.initial_spec_merge {
+            state.apply_checked(spec, is_first_boot).map_err(|e| {
+                log::error!("Failed to apply initial spec: {}", e);
+            })?;
+            if is_first_boot {
+                log::info!("Provisioned server state with initial spec");
+            } else {
+                log::info!("Merged initial spec into server state");
+            }
+        } else {
+            log::info!(
+                "Ignored initial spec, as '{}' state file is not empty",
+                cfg.state_path.display(),
+            );
+        }
+    }
+    // Already persisted state is never truncated, so only further additions
+    // are rejected.
+    if let Err(e) = state.check_quota() {
+        log::warn!("Server state exceeds quota: {}", e);
+    }
+    // Standby server is replicated before spawning any re-streaming, so never
+    // re-streams the outputs enabled on the primary one.
+    if let Some(source) = &cfg.replicate_from {
+        replication::spawn(source, cfg.replicate_token.clone(), state.clone());
+    }
+    state.status_debouncer.grace.set(cfg.status_debounce);
+    state.log_coalescer.set_window(cfg.log_coalesce_window);
+    drop(tokio::spawn(
+        state.log_coalescer.clone().expire_periodically(),
+    ));
+    state.capacity.set(capacity::Config {
+        processes: cfg.capacity_processes,
+        weights: cfg.capacity_weights,
+    });
+    state.speed_threshold.set(ffmpeg::SpeedThreshold {
+        min_speed: cfg.min_encoding_speed,
+        window: cfg.slow_encoding_window,
+    });
+    // Debug mode only enables the playground initially, so it may be toggled
+    // at runtime.
+    state.playground_enabled.set(cfg.debug);
+    state.ffmpeg_capabilities.set(Some(Arc::new(ffmpeg_caps)));
+    ffmpeg::Capabilities::watch(ffmpeg_path.clone(), state.clone());
+
+    // Verbosity level specified via CLI explicitly has priority over the
+    // persisted one.
+    let log_level = cfg
+        .verbose
+        .or_else(|| state.settings.get_cloned().log_level.map(Into::into));
+    state
+        .log_level
+        .set(log_level.map(Into::into).unwrap_or_default());
+
+    // Callbacks are never served on TCP silently instead of the requested Unix
+    // socket, as it's usually required for not exposing them to other users.
+    if let Some(path) = cfg
+        .callback_http_socket
+        .as_ref()
+        .filter(|_| !srs::UNIX_SOCKET_CALLBACKS)
+    {
+        return Err(log::error!(
+            "SRS cannot send HTTP callbacks to {} Unix socket, as supports \
+             TCP only, so use `--callback-http-ip` with \
+             `--callback-http-port`, or `--single-port` instead",
+            path.display(),
+        )
+        .into());
+    }
+
+    let (client_listener, client_addr) =
+        bind("client", cfg.client_http_addr(), cfg.dual_stack)?;
+    let callback_listener = cfg
+        .callback_http_addr()
+        .map(|addr| bind("callback", addr, cfg.dual_stack))
+        .transpose()?;
+    let callback_addr = callback_listener.as_ref().map(|(_, addr)| *addr);
+    let callback_listener = match &cfg.callback_http_socket {
+        Some(path) => {
+            Some(callback::Listener::Unix(bind_unix("callback", path)?))
+        }
+        None => callback_listener.map(|(l, _)| callback::Listener::Tcp(l)),
+    };
+    // Ephemeral ports are resolved to the actual ones, so SRS is able to reach
+    // the callback endpoints, and clients are reported the real addresses.
+    cfg.client_http_port = client_addr.port();
+    if let Some(addr) = callback_addr {
+        cfg.callback_http_port = addr.port();
+    }
+
+    let callback_token = cfg.single_port.then(callback::Token::generate);
+    let srs_cfg = srs::Config {
+        rtmp_port: cfg.srs_rtmp_port,
+        rtmp_bind_ip: cfg.rtmp_bind_ip,
+        http_api_port: cfg.srs_http_api_port,
+        http_server_port: cfg.srs_http_server_port,
+        callback_url: callback::url(&cfg, callback_token.as_ref()),
+        http_server_dir: cfg.srs_http_dir.clone().into(),
+        http_flv: !cfg.no_http_flv,
+        log_level: log_level.map(Into::into).unwrap_or_default(),
+        tuning: cfg.srs_tuning(),
+        vhosts: srs_vhosts(&state.restreams.lock_ref()),
+    };
+    let srs = srs::Server::try_new(&cfg.srs_path, &srs_cfg)
+        .await
+        .map_err(|e| log::error!("Failed to initialize SRS server: {}", e))?;
+
+    // Inconsistencies are reported once, without preventing the startup, as
+    // they may be fixed while running.
+    check::report(&check::run(&state, &dvr::Storage::global().root_path).await);
+
+    // SRS config is shared by the hooks below, and locked while reloading,
+    // so concurrent reloads don't interfere.
+    let srs_cfg = Arc::new(Mutex::new(srs_cfg));
+    let (srs_for_reload, cfg_for_reload) = (srs.clone(), srs_cfg.clone());
+    let mut curr_log_level = state.log_level.get();
+    State::on_change("apply_log_level", &state.log_level, move |lvl| {
+        ephyr_log::set_level(lvl.into());
+
+        // Avoid rewriting SRS config on the initial value.
+        let changed = lvl != curr_log_level;
+        curr_log_level = lvl;
+        let (srs, srs_cfg) = (srs_for_reload.clone(), cfg_for_reload.clone());
+        async move {
+            if changed {
+                let mut srs_cfg = srs_cfg.lock().await;
+                srs_cfg.log_level = slog::Level::from(lvl).into();
+                srs.reload(&srs_cfg).await.unwrap_or_else(|e| {
+                    log::error!("Failed to reload SRS config: {}", e)
+                });
+            }
+        }
+    });
+    let srs_for_reload = srs.clone();
+    // HLS files of apps not served via HLS anymore are reclaimed by sweeping
+    // (see `sweep` module).
+    State::on_change("apply_srs_vhosts", &state.restreams, move |restreams| {
+        let (srs, srs_cfg) = (srs_for_reload.clone(), srs_cfg.clone());
+        async move {
+            let vhosts = srs_vhosts(&restreams);
+            let mut srs_cfg = srs_cfg.lock().await;
+            // SRS is reloaded only when the set of dedicated vhosts changes,
+            // and its reloading keeps unrelated publishers intact.
+            if vhosts != srs_cfg.vhosts {
+                srs_cfg.vhosts = vhosts;
+                srs.reload(&srs_cfg).await.unwrap_or_else(|e| {
+                    log::error!("Failed to reload SRS config: {}", e)
+                });
+            }
+        }
+    });
+    let srs_state = state.clone();
+    State::on_change(
+        "reset_inputs_on_srs_exit",
+        &srs.status().running,
+        move |running| {
+            // Reset on exit rather than on restart, so stale `srs::ClientId`s
+            // don't kick the clients of a freshly started SRS server.
+            if !running {
+                srs_state.reset_inputs_status();
+            }
+            future::ready(())
+        },
+    );
+    // Files of `Restream`s in trash are kept, as they may be restored, so are
+    // cleaned up once purged from there.
+    for &(name, changes) in &[
+        ("cleanup_files", &state.restreams),
+        ("cleanup_trashed_files", &state.trash),
+    ] {
+        let (restreams, trash) = (state.restreams.clone(), state.trash.clone());
+        let overlays_dir = cfg.overlays_dir.clone();
+        State::on_change(name, changes, move |_| {
+            let mut all = restreams.get_cloned();
+            all.extend(trash.get_cloned());
+            let dir = overlays_dir.clone();
+            async move {
+                // Wait for all the re-streaming processes to release DVR
+                // files.
+                time::delay_for(Duration::from_secs(1)).await;
+                dvr::Storage::global().cleanup(&all).await;
+                overlay::cleanup(&dir, &all).await;
+            }
+        });
+    }
+
+    purge_trash_periodically(cfg.trash_retention, state.clone());
+    recover_output_dsts_periodically(state.clone());
+
+    reload_spec_on_hangup(
+        cfg.initial_spec.clone(),
+        cfg.spec_authoritative,
+        state.clone(),
+    );
+
+    let mut prober =
+        ffprobe::InputsProber::new(cfg.ffprobe_path.clone(), state.clone());
+    State::on_change("probe_pull_inputs", &state.restreams, move |restreams| {
+        prober.apply(&restreams);
+        future::ready(())
+    });
+
+    diagnostics::record_status_events(&state);
+
+    compat::poll_codecs_periodically(
+        compat::CODECS_POLL_INTERVAL,
+        state.clone(),
+    );
+    let mut constraints = compat::ConstraintsChecker::new(state.clone());
+    State::on_change(
+        "check_output_constraints",
+        &state.restreams,
+        move |restreams| {
+            constraints.apply(&restreams);
+            future::ready(())
+        },
+    );
+
+    let mut idle_inputs = idle::IdleInputsWatcher::new(state.clone());
+    state.on_restreams_change(
+        "auto_disable_idle_inputs",
+        move |restreams, statuses| {
+            idle_inputs.apply(&restreams, &statuses);
+            future::ready(())
+        },
+    );
+
+    let rejections = state.callback_rejections.clone();
+    State::on_change(
+        "invalidate_callback_rejections",
+        &state.restreams,
+        move |restreams| {
+            rejections.invalidate(&restreams);
+            future::ready(())
+        },
+    );
+
+    let mut evictor = evict::DisabledInputsEvictor::new(api::srs::Client);
+    State::on_change(
+        "evict_disabled_inputs_clients",
+        &state.restreams,
+        move |restreams| {
+            evictor.apply(&restreams);
+            future::ready(())
+        },
+    );
+
+    let mut removed_apps = sweep::RemovedAppsEvictor::new(api::srs::Client);
+    State::on_change(
+        "evict_removed_apps_clients",
+        &state.restreams,
+        move |restreams| {
+            removed_apps.apply(&restreams);
+            future::ready(())
+        },
+    );
+    if let Some(interval) = cfg.sweep_interval {
+        sweep::sweep_periodically(
+            api::srs::Client,
+            sweep::Dirs::new(&cfg),
+            interval,
+            state.clone(),
+        );
+    }
+
+    let mut sessions = expiry::ExpiringSessionsWatcher::new(state.clone());
+    State::on_change(
+        "expire_publishing_sessions",
+        &state.restreams,
+        move |restreams| {
+            sessions.apply(&restreams);
+            future::ready(())
+        },
+    );
+
+    let confirmations = workflow::InputConfirmationsWatcher::new(state.clone());
+    state.on_restreams_change(
+        "confirm_workflow_inputs",
+        move |restreams, statuses| {
+            confirmations.apply(&restreams, &statuses);
+            future::ready(())
+        },
+    );
+
+    let mut scheduled = schedule::ScheduledInputsWatcher::new(state.clone());
+    state.on_restreams_change(
+        "enforce_input_schedules",
+        move |restreams, statuses| {
+            scheduled.apply(&restreams, &statuses);
+            future::ready(())
+        },
+    );
+
+    let mut pending_dsts = rotation::PendingDstsWatcher::new(state.clone());
+    State::on_change(
+        "apply_pending_output_dsts",
+        &state.restreams,
+        move |restreams| {
+            pending_dsts.apply(&restreams);
+            future::ready(())
+        },
+    );
+
+    let mut usage =
+        usage::UsageMeter::spawn(state.clone(), cfg.usage_flush_interval);
+    state.on_restreams_change(
+        "meter_online_usage",
+        move |restreams, statuses| {
+            usage.apply(&restreams, &statuses);
+            future::ready(())
+        },
+    );
+
+    let thumbnails = cfg.thumbnails_interval.map_or_else(
+        preview::Thumbnails::default,
+        |interval| {
+            preview::Thumbnails::spawn(
+                ffmpeg_path.clone(),
+                interval,
+                state.clone(),
+            )
+        },
+    );
+
+    ffmpeg::Stats::sample_history(cfg.history_interval, state.clone());
+
+    if let Some(notifier) = mail::OutagesNotifier::new(&cfg).map_err(|e| {
+        log::error!("Failed to configure e-mail notifications: {}", e)
+    })? {
+        notifier.spawn(state.clone());
+    }
+
+    #[cfg(feature = "event-broker")]
+    if let Some(publisher) = events::Publisher::new(&cfg)
+        .map_err(|e| log::error!("Failed to configure event broker: {}", e))?
+    {
+        publisher.spawn(state.clone());
+    }
+    #[cfg(not(feature = "event-broker"))]
+    if cfg.event_broker.is_some() {
+        return Err(log::error!(
+            "Event broker is configured, but `event-broker` feature is not \
+             compiled in"
+        )
+        .into());
+    }
+
+    // Publishers which have outlived the restart of this server in SRS are
+    // picked up before spawning any re-streaming, so their `Input`s are not
+    // considered offline.
+    let _ = time::timeout(
+        Duration::from_secs(5),
+        reconcile::srs_publishers(&api::srs::Client, &state),
+    )
+    .await;
+
+    // Pool is shared with its periodic audit, so they don't interfere.
+    let restreamers = Arc::new(Mutex::new(ffmpeg::RestreamersPool::new(
+        ffmpeg_path,
+        buffers_dir,
+        cfg.ffmpeg_max_outputs,
+        state.clone(),
+    )));
+    let pool = Arc::clone(&restreamers);
+    state.on_restreams_change(
+        "spawn_restreamers",
+        move |mut restreams, statuses| {
+            // Iterate in the canonical order to spawn processes
+            // deterministically.
+            state::sort_by_position(&mut restreams);
+            let pool = Arc::clone(&pool);
+            async move {
+                let mut pool = pool.lock().await;
+                trace::pool_apply(restreams.len())
+                    .in_scope(|| pool.apply(&restreams, &statuses));
+            }
+        },
+    );
+    if let Some(hours) = cfg.ffmpeg_max_age_hours {
+        cycle::cycle_periodically(
+            Duration::from_secs(u64::from(hours) * 3600),
+            cfg.maintenance_window,
+            Arc::clone(&restreamers),
+            state.clone(),
+        );
+    } else if let Some(window) = cfg.maintenance_window {
+        log::warn!(
+            "Maintenance window {} has no effect without \
+             `--ffmpeg-max-age-hours`",
+            window,
+        );
+    }
+    audit_restreamers_periodically(
+        cfg.pool_audit_interval,
+        restreamers,
+        state.clone(),
+    );
+
+    let srs_failed = srs.failed().map(|()| {
+        log::crit!("SRS server cannot be restarted, shutting down");
+    });
+    let hook_broken = State::hook_broken().map(|name| {
+        log::crit!("`{}` hook of state is broken, shutting down", name);
+    });
+    let fatal = Box::pin(
+        future::select(Box::pin(srs_failed), Box::pin(hook_broken))
+            .map(|_| Err::<(), Failure>(Failure)),
+    );
+
+    // In single-port mode callbacks are served by the client HTTP server.
+    let callbacks = match callback_listener {
+        None => future::ok::<_, Failure>(()).left_future(),
+        Some(listener) => {
+            self::callback::run(&cfg, listener, state.clone(), shutdown.clone())
+                .right_future()
+        }
+    };
+
+    let extensions_shutdown = ShutdownTrigger::default();
+    let extensions = spawn_extensions(extensions, &state, &extensions_shutdown);
+
+    on_start(Running {
+        state: state.clone(),
+        client_addr,
+        callback_addr,
+    });
+
+    // SRS and hooks of state are watched only while the HTTP servers are
+    // running, so they don't prevent them from being stopped gracefully.
+    let servers = future::try_join(
+        self::client::run(
+            &cfg,
+            client_listener,
+            state,
+            srs.clone(),
+            thumbnails,
+            callback_token,
+            shutdown,
+        ),
+        callbacks,
+    );
+    let served = match future::select(Box::pin(servers), fatal).await {
+        Either::Left((res, _)) => res.map(drop),
+        Either::Right((res, _)) => res,
+    };
+
+    extensions_shutdown.trigger();
+    if time::timeout(extension::SHUTDOWN_TIMEOUT, future::join_all(extensions))
+        .await
+        .is_err()
+    {
+        log::warn!(
+            "Extensions haven't finished in {:?}, abandoning them",
+            extension::SHUTDOWN_TIMEOUT,
+        );
+    }
+    served?;
+
+    drop(srs);
+    // Wait for all the async `Drop`s to proceed well.
+    teamspeak::finish_all_disconnects().await;
+    drop(state_lock);
+
+    Ok(())
+}
+
+/// Spawns the given [`Extension`]s to run in background with the given
+/// [`State`], until the given [`ShutdownTrigger`] is triggered.
+///
+/// Returns handles resolving once the spawned [`Extension`]s finish.
+fn spawn_extensions(
+    extensions: Vec<Box<dyn Extension>>,
+    state: &State,
+    shutdown: &ShutdownTrigger,
+) -> Vec<JoinHandle<()>> {
+    extensions
+        .into_iter()
+        .filter_map(|ext| {
+            let name = ext.name().to_owned();
+            let ctx = extension::Context::new(state, shutdown.token());
+            // Panic may happen on building the future as well.
+            let run =
+                match panic::catch_unwind(AssertUnwindSafe(|| ext.run(ctx))) {
+                    Ok(run) => AssertUnwindSafe(run).catch_unwind(),
+                    Err(e) => {
+                        log::error!(
+                            "Extension `{}` has panicked: {}",
+                            name,
+                            display_panic(&*e),
+                        );
+                        return None;
+                    }
+                };
+            log::info!("Started `{}` extension", name);
+            Some(tokio::spawn(async move {
+                match run.await {
+                    Ok(()) => log::info!("Extension `{}` has finished", name),
+                    Err(e) => log::error!(
+                        "Extension `{}` has panicked: {}",
+                        name,
+                        display_panic(&*e),
+                    ),
+                }
+            }))
+        })
+        .collect()
+}
+
+/// Interval of checking [`State::trash`] for expired [`state::Restream`]s.
+///
+/// [`state::Restream`]: crate::state::Restream
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Returns names of [SRS] vhosts dedicated to the given low-latency
+/// [`state::Restream`]s (see [`state::Restream::srs_vhost()`]), sorted, so
+/// [SRS] configuration is rendered deterministically.
+///
+/// [`state::Restream`]: crate::state::Restream
+/// [`state::Restream::srs_vhost()`]: crate::state::Restream::srs_vhost
+/// [SRS]: https://github.com/ossrs/srs
+fn srs_vhosts(restreams: &[state::Restream]) -> Vec<srs::Vhost> {
+    let mut vhosts: Vec<_> = restreams
+        .iter()
+        .filter_map(state::Restream::srs_vhost_config)
+        .collect();
+    vhosts.sort();
+    vhosts
+}
+
+/// Spawns a background task purging [`state::Restream`]s being in the
+/// [`State::trash`] longer than the given `retention` period.
+///
+/// [`state::Restream`]: crate::state::Restream
+fn purge_trash_periodically(retention: Duration, state: State) {
+    drop(tokio::spawn(async move {
+        loop {
+            let purged = state.purge_expired_trash(retention);
+            if purged > 0 {
+                log::info!("Purged {} expired restream(s) from trash", purged);
+            }
+            time::delay_for(TRASH_PURGE_INTERVAL).await;
+        }
+    }));
+}
+
+/// Spawns a background task recovering health scores of the destinations not
+/// being used by [`state::Output`]s having a backup one, so they're switched
+/// back once recovered.
+///
+/// [`state::Output`]: crate::state::Output
+fn recover_output_dsts_periodically(state: State) {
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(state::OutputDstHealth::RECOVERY_INTERVAL).await;
+            let _ = state.recover_output_dsts();
+        }
+    }));
+}
+
+/// Spawns a background task auditing the given [`ffmpeg::RestreamersPool`]
+/// against the current [`State::restreams`] with the given `interval` (see
+/// [`ffmpeg::RestreamersPool::audit()`]).
+fn audit_restreamers_periodically(
+    interval: Duration,
+    restreamers: Arc<Mutex<ffmpeg::RestreamersPool>>,
+    state: State,
+) {
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(interval).await;
+            let mut pool = restreamers.lock().await;
+            // Snapshot is taken under the lock, so is never older than the
+            // one applied by the `State` hook meanwhile.
+            let mut restreams = state.restreams.get_cloned();
+            let statuses = state.statuses.get_cloned();
+            state::sort_by_position(&mut restreams);
+            let _ = pool.audit(&restreams, &statuses);
+        }
+    }));
+}
+
+/// Reads a [`spec::v1::Spec`] from the file located by the given `path`.
+///
+/// # Errors
+///
+/// If the file cannot be read, or doesn't contain a valid [`Spec`].
+async fn read_spec(path: &Path) -> Result<spec::v1::Spec, anyhow::Error> {
+    let contents = fs::read(path).await.map_err(|e| {
+        anyhow!("Failed to read '{}' file: {}", path.display(), e)
+    })?;
+    let spec = serde_json::from_slice::<Spec>(&contents).map_err(|e| {
+        anyhow!("Failed to parse spec from '{}' file: {}", path.display(), e)
+    })?;
+    Ok(spec.into_v1())
+}
+
+/// Spawns a background task reloading the [`Spec`] from the file located by
+/// the given `path` into the given [`State`] on every `SIGHUP` signal.
+///
+/// See [`State::reload()`] for `authoritative` meaning.
+fn reload_spec_on_hangup(
+    path: Option<PathBuf>,
+    authoritative: bool,
+    state: State,
+) {
+    drop(tokio::spawn(async move {
+        let mut hangups = match signal::unix::signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("Failed to listen to SIGHUP signal: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            let path = match &path {
+                Some(p) => p,
+                None => {
+                    log::warn!(
+                        "Received SIGHUP, but there is no spec file to reload"
+                    );
+                    continue;
+                }
+            };
+
+            let reload = match read_spec(path).await {
+                Ok(spec) => state.reload(spec, authoritative),
+                Err(e) => SpecReload::failed(e),
+            };
+            match &reload.error {
+                None => log::info!(
+                    "Reloaded spec from '{}' file: {} Restream(s) added, \
+                     {} updated, {} removed",
+                    path.display(),
+                    reload.added,
+                    reload.updated,
+                    reload.removed,
+                ),
+                Some(e) => log::error!("Failed to reload spec: {}", e),
+            }
+            state.spec_reload.set(Some(reload));
+        }
+    }));
+}
+
+/// Creates a new [`TcpListener`] bound to the given `addr`.
+///
+/// If the given `addr` is an IPv6 one, then the returned [`TcpListener`]
+/// accepts IPv4 connections too (via IPv4-mapped IPv6 addresses) only if
+/// `dual_stack` is `true`, regardless of the system defaults.
+///
+/// # Errors
+///
+/// If socket cannot be created or bound to the given `addr`.
+fn listen(addr: SocketAddr, dual_stack: bool) -> io::Result<TcpListener> {
+    let domain = if addr.is_ipv6() {
+        Domain::ipv6()
+    } else {
+        Domain::ipv4()
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into_tcp_listener())
+}
+
+/// Creates a new [`TcpListener`] bound to the given `addr` (see [`listen()`])
+/// for the HTTP server of the given `kind`.
+///
+/// Returns the address the [`TcpListener`] is actually bound to along.
+///
+/// # Errors
+///
+/// If the [`TcpListener`] cannot be bound. The actual error is logged.
+fn bind(
+    kind: &str,
+    addr: SocketAddr,
+    dual_stack: bool,
+) -> Result<(TcpListener, SocketAddr), Failure> {
+    let listener = listen(addr, dual_stack).map_err(|e| {
+        log::error!("Failed to bind {} HTTP server: {}", kind, e)
+    })?;
+    let addr = listener.local_addr().map_err(|e| {
+        log::error!("Failed to resolve {} HTTP server address: {}", kind, e)
+    })?;
+    Ok((listener, addr))
+}
+
+/// Binds a [`UnixListener`] for an HTTP server of the given `kind` on the given
+/// `path`, accessible by its owner only.
+///
+/// A stale socket left on the `path` by a previous run is replaced.
+///
+/// # Errors
+///
+/// If the `path` is occupied by something else than a socket, or binding
+/// fails. The actual error is logged.
+fn bind_unix(kind: &str, path: &Path) -> Result<UnixListener, Failure> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(log::error!(
+                "Failed to bind {} HTTP server: {} is not a socket",
+                kind,
+                path.display(),
+            )
+            .into());
+        }
+        std::fs::remove_file(path).map_err(|e| {
+            log::error!(
+                "Failed to remove stale {} HTTP server socket {}: {}",
+                kind,
+                path.display(),
+                e,
+            )
+        })?;
+    }
+    let listener = UnixListener::bind(path).map_err(|e| {
+        log::error!("Failed to bind {} HTTP server: {}", kind, e)
+    })?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o600)).map_err(
+        |e| {
+            log::error!(
+                "Failed to restrict {} HTTP server socket {}: {}",
+                kind,
+                path.display(),
+                e,
+            )
+        },
+    )?;
+    Ok(listener)
+}
+
+/// Stops the given running [`HttpServer`] gracefully once the given
+/// `shutdown` signal resolves.
+///
+/// [`HttpServer`]: actix_web::HttpServer
+fn stop_on(shutdown: Shutdown, server: Server) {
+    actix_web::rt::spawn(async move {
+        shutdown.await;
+        server.stop(true).await;
+    });
+}
+
+/// Client HTTP server responding to client requests.
+pub mod client {
+    use std::{
+        cell::RefCell,
+        convert::{Infallible, TryInto as _},
+        net::{SocketAddr, TcpListener},
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{self, Poll},
+        time::SystemTime,
+    };
+
+    use actix_service::{Service, ServiceFactory, Transform};
+    use actix_web::{
+        cookie::Cookie,
+        dev::{
+            Body, BodyEncoding as _, ResponseBody, ServiceRequest,
+            ServiceResponse,
+        },
+        error, get,
+        http::{
+            header::{
+                self, CacheControl, CacheDirective, ContentEncoding,
+                LastModified,
+            },
+            HeaderMap, HeaderValue, Method, StatusCode,
+        },
+        middleware, post, put, route, web, App, Error, HttpMessage as _,
+        HttpRequest, HttpResponse, HttpServer,
+    };
+    use actix_web_httpauth::{
+        extractors::{
+            basic::{self, BasicAuth},
+            AuthExtractor as _, AuthExtractorConfig, AuthenticationError,
+        },
+        headers::authorization::{Basic, Scheme as _},
+    };
+    #[cfg(feature = "ui")]
+    use actix_web_static_files::ResourceFiles;
+    use ephyr_log::log;
+    use futures::{
+        future::{self, LocalBoxFuture},
+        FutureExt as _, StreamExt as _, TryFutureExt as _,
+    };
+    use juniper::{
+        http::playground::playground_source, DefaultScalarValue,
+        GraphQLTypeAsync, InputValue, RootNode, Variables,
+    };
+    use juniper_actix::{
+        graphql_handler, subscriptions::subscriptions_handler,
+    };
+    use juniper_graphql_ws::ConnectionConfig;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use tokio::{fs, time};
+
+    use crate::{
+        api::{
+            self,
+            graphql::{
+                client::{QueriesRoot, SubscriptionsRoot},
+                complexity::Limited,
+                Role,
+            },
+        },
+        cli::{Failure, Opts},
+        diagnostics, metrics, overlay, password, preview, proxy, recovery,
+        session, srs,
+        state::{InputId, OutputId, PasswordResetVia, Settings},
+        status_page, trace, State,
+    };
+
+    use super::{callback, Shutdown};
+
+    #[cfg(feature = "ui")]
+    pub mod public_dir {
+        #![allow(clippy::must_use_candidate, unused_results)]
+        #![doc(hidden)]
+
+        include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+    }
+
+    /// Runs client HTTP server on the given `listener`, until the given
+    /// `shutdown` signal resolves (if any).
+    ///
+    /// Client HTTP server serves read-only [`api::graphql::client`] on `/api`
+    /// endpoint, the full one on `/api/admin` endpoint (for admins only), its
+    /// schema on `/api/schema.graphql` endpoint, and [`api::rest`] on
+    /// `/api/v1` endpoints.
+    ///
+    /// # Playground
+    ///
+    /// While [`State::playground_enabled`] (initially, if [`cli::Opts::debug`]
+    /// is specified), additionally serves [GraphQL Playground][2] on
+    /// `/api/playground` endpoint for the read-only API, and on
+    /// `/api/admin/playground` endpoint for the full one (for admins only).
+    /// Both are authorized the same way the API itself is.
+    ///
+    /// # Base path
+    ///
+    /// All the endpoints are served under [`cli::Opts::base_path`] prefix, so
+    /// the unprefixed ones respond with `404 Not Found`.
+    ///
+    /// # Overlays
+    ///
+    /// Overlay images of `Output`s are uploaded via `PUT /overlays/{output_id}`
+    /// endpoint.
+    ///
+    /// # Thumbnails
+    ///
+    /// Recent [`preview::Thumbnail`]s of `Input`s are served on
+    /// `/preview/{input_id}.jpg` endpoint, if
+    /// [`cli::Opts::thumbnails_interval`] is specified.
+    ///
+    /// # Diagnostics
+    ///
+    /// A [`diagnostics::Report`] is served on `/api/diagnostics.tar.gz`
+    /// endpoint (for admins only).
+    ///
+    /// # Web UI
+    ///
+    /// Embedded web UI is served on `/` endpoint, unless
+    /// [`cli::Opts::no_ui`] is specified or the server is built without `ui`
+    /// feature.
+    ///
+    /// # Compression
+    ///
+    /// Responses are compressed according to the `Accept-Encoding` header of
+    /// requests, unless [`cli::Opts::no_compression`] is specified. Only the
+    /// textual ones are compressed (see [`is_compressible()`]), so already
+    /// compressed media (like thumbnails) is not compressed twice.
+    ///
+    /// # SRS callbacks
+    ///
+    /// If a `callback_token` is given (in [`cli::Opts::single_port`] mode),
+    /// then [SRS] HTTP callbacks are additionally served on
+    /// `/__srs_callback/{token}` endpoint, outside the base path, authorized
+    /// by the token only.
+    ///
+    /// # Errors
+    ///
+    /// If [`HttpServer`] cannot run due to already used port, etc.
+    /// The actual error is logged.
+    ///
+    /// [`cli::Opts::base_path`]: crate::cli::Opts::base_path
+    /// [`cli::Opts::debug`]: crate::cli::Opts::debug
+    /// [`cli::Opts::no_compression`]: crate::cli::Opts::no_compression
+    /// [`cli::Opts::no_ui`]: crate::cli::Opts::no_ui
+    /// [`cli::Opts::single_port`]: crate::cli::Opts::single_port
+    /// [`cli::Opts::thumbnails_interval`]:
+    ///     crate::cli::Opts::thumbnails_interval
+    /// [2]: https://github.com/graphql/graphql-playground
+    /// [SRS]: https://github.com/ossrs/srs
+    pub async fn run(
+        cfg: &Opts,
+        listener: TcpListener,
+        state: State,
+        srs: srs::Server,
+        thumbnails: preview::Thumbnails,
+        callback_token: Option<callback::Token>,
+        shutdown: Option<Shutdown>,
+    ) -> Result<(), Failure> {
+        let stored_cfg = cfg.clone();
+        let workers = cfg.http_workers_count();
+        let session_key = cfg
+            .session_key
+            .as_deref()
+            .map_or_else(session::Key::generate, session::Key::derive);
+        let status_page_cache = status_page::Cache::default();
+
+        let mut server = HttpServer::new(move || {
+            app(
+                &stored_cfg,
+                &state,
+                &thumbnails,
+                &session_key,
+                &status_page_cache,
+                callback_token.as_ref(),
+            )
+            .app_data(srs.clone())
+        })
+        .workers(workers);
+        if shutdown.is_some() {
+            server = server.disable_signals();
+        }
+        let server = server
+            .listen(listener)
+            .map_err(|e| {
+                log::error!("Failed to bind client HTTP server: {}", e)
+            })?
+            .run();
+        if let Some(shutdown) = shutdown {
+            super::stop_on(shutdown, server.clone());
+        }
+        Ok(server.await.map_err(|e| {
+            log::error!("Failed to run client HTTP server: {}", e)
+        })?)
+    }
+
+    /// Creates the [`App`] of client HTTP server (see [`run()`]) serving the
+    /// given data, shared between its workers.
+    ///
+    /// [`srs::Server`] is not served, so should be added to the returned
+    /// [`App`] additionally.
+    fn app(
+        cfg: &Opts,
+        state: &State,
+        thumbnails: &preview::Thumbnails,
+        session_key: &session::Key,
+        status_page_cache: &status_page::Cache,
+        callback_token: Option<&callback::Token>,
+    ) -> App<
+        impl ServiceFactory<
+            Config = (),
+            Request = ServiceRequest,
+            Response = ServiceResponse<Body>,
+            Error = Error,
+            InitError = (),
+        >,
+        Body,
+    > {
+        let with_compression = !cfg.no_compression;
+        let base_path = &cfg.base_path;
+
+        let mut app = App::new()
+            .app_data(cfg.clone())
+            .app_data(state.clone())
+            .app_data(thumbnails.clone())
+            .app_data(session_key.clone())
+            .app_data(status_page_cache.clone())
+            .app_data(
+                basic::Config::default().realm("Any login is allowed"),
+            )
+            .data(api::graphql::client::read_only_schema())
+            .data(api::graphql::client::schema())
+            .wrap_fn(move |req, srv| {
+                srv.call(req).map_ok(move |mut res| {
+                    restrict_compression(&mut res, with_compression);
+                    res
+                })
+            })
+            .wrap(middleware::Compress::default())
+            .wrap(Authorization)
+            // Wraps `Authorization`, so rejected requests are logged too, and
+            // is wrapped by `resolve_client_ip()`, so logs the real client IP.
+            .wrap(access_logger())
+            .wrap_fn(|mut req, srv| {
+                resolve_client_ip(&mut req);
+                srv.call(req)
+            })
+            // Erases the type of response bodies, so the returned `App` is
+            // nameable.
+            .wrap_fn(|req, srv| {
+                srv.call(req).map_ok(|res| {
+                    res.map_body(|_, b| {
+                        ResponseBody::Other(Body::from_message(b))
+                    })
+                })
+            });
+        if !base_path.is_empty() {
+            // Web UI uses relative URLs, so requires trailing slash.
+            let location = format!("{}/", base_path);
+            app = app.route(
+                base_path,
+                web::get().to(move || {
+                    let location = location.clone();
+                    async move {
+                        HttpResponse::Found()
+                            .header(header::LOCATION, location)
+                            .finish()
+                    }
+                }),
+            );
+        }
+
+        let mut scope = web::scope(base_path)
+            .service(login)
+            .service(logout)
+            .service(graphql)
+            .service(admin_graphql)
+            .service(schema_sdl)
+            .service(prometheus_metrics)
+            .service(diagnostics_report)
+            .service(thumbnail)
+            .service(upload_overlay)
+            .service(playground)
+            .service(admin_playground)
+            .service(api::rest::scope());
+        if cfg.public_status_page {
+            scope = scope.service(public_status);
+        }
+        if cfg.allow_local_reset {
+            scope = scope.service(local_reset);
+        }
+        if cfg!(feature = "ui") && !cfg.no_ui {
+            #[cfg(feature = "ui")]
+            {
+                scope = scope
+                    .service(ResourceFiles::new("/", public_dir::generate()));
+            }
+        } else {
+            scope = scope.service(no_ui);
+        }
+        if let Some(token) = callback_token {
+            app = app
+                .app_data(token.clone())
+                .service(callback::single_port_resource());
+        }
+        app.service(scope)
+    }
+
+    /// [MIME types][1] of responses being worth compressing, additionally to
+    /// `text/*` ones.
+    ///
+    /// [1]: https://developer.mozilla.org/docs/Glossary/MIME_type
+    const COMPRESSIBLE_TYPES: &[&str] = &[
+        "application/graphql",
+        "application/javascript",
+        "application/json",
+        "application/manifest+json",
+        "image/svg+xml",
+    ];
+
+    /// Checks whether a response with the given `headers` is worth compressing
+    /// by its `Content-Type`.
+    ///
+    /// Media (like FLV streams or JPEG thumbnails) is compressed already, so
+    /// compressing it again only wastes CPU.
+    #[must_use]
+    fn is_compressible(headers: &HeaderMap) -> bool {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map_or(false, |mime| {
+                let mime = mime.trim().to_ascii_lowercase();
+                mime.starts_with("text/")
+                    || COMPRESSIBLE_TYPES.contains(&mime.as_str())
+            })
+    }
+
+    /// Forbids [`middleware::Compress`] to compress the given `res`ponse, if
+    /// compression is not `enabled` or the response is not worth compressing
+    /// (see [`is_compressible()`]).
+    fn restrict_compression<B>(res: &mut ServiceResponse<B>, enabled: bool) {
+        if !enabled || !is_compressible(res.headers()) {
+            let _ = res.response_mut().encoding(ContentEncoding::Identity);
+        }
+    }
+
+    /// Returns [`middleware::Logger`] logging requests in its default format,
+    /// but with their first lines rendered by [`request_line()`], so access
+    /// logs don't leak a [`callback::Token`].
+    fn access_logger() -> middleware::Logger {
+        middleware::Logger::new(
+            r#"%a "%{request}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+        )
+        .custom_request_replace("request", request_line)
+    }
+
+    /// Renders the first line of the given `req`uest the same way
+    /// [`middleware::Logger`] does, but with a [`callback::Token`] redacted
+    /// from its path.
+    fn request_line(req: &ServiceRequest) -> String {
+        let path = if callback::single_port_token(req.path()).is_some() {
+            format!("{}/<redacted>", callback::SINGLE_PORT_PATH)
+        } else {
+            req.path().to_owned()
+        };
+        match req.query_string() {
+            "" => format!("{} {} {:?}", req.method(), path, req.version()),
+            q => format!("{} {}?{} {:?}", req.method(), path, q, req.version()),
+        }
+    }
+
+    /// Endpoint serving the read-only [`api::graphql::client`] schema (see
+    /// [`api::graphql::client::ReadOnlySchema`]) to any authorized client.
+    ///
+    /// See [`execute_graphql()`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If GraphQL operation execution errors or fails.
+    #[route("/api", method = "GET", method = "POST")]
+    async fn graphql(
+        req: HttpRequest,
+        payload: web::Payload,
+        schema: web::Data<api::graphql::client::ReadOnlySchema>,
+    ) -> Result<HttpResponse, Error> {
+        execute_graphql(req, payload, schema.into_inner(), false).await
+    }
+
+    /// Endpoint serving the full [`api::graphql::client`] schema (see
+    /// [`api::graphql::client::Schema`]) to [`Role::Admin`] clients only,
+    /// rejecting the [`Role::Viewer`] ones with `403 Forbidden`.
+    ///
+    /// See [`execute_graphql()`] for details.
+    ///
+    /// # Errors
+    ///
+    /// If GraphQL operation execution errors or fails.
+    #[route("/api/admin", method = "GET", method = "POST")]
+    async fn admin_graphql(
+        req: HttpRequest,
+        payload: web::Payload,
+        schema: web::Data<api::graphql::client::Schema>,
+    ) -> Result<HttpResponse, Error> {
+        execute_graphql(req, payload, schema.into_inner(), true).await
+    }
+
+    /// Executes GraphQL operations of the given [`HttpRequest`] against the
+    /// given `schema` of [`api::graphql::client`], rejecting non-admin clients
+    /// if `admin_only` is `true`.
+    ///
+    /// No more than [`cli::Opts::ws_max_connections`] WebSocket connections
+    /// are served simultaneously, the exceeding ones are rejected with
+    /// `503 Service Unavailable`, while each of them runs no more than
+    /// [`cli::Opts::ws_max_operations`] operations simultaneously.
+    ///
+    /// Operations are checked against [`cli::Opts::graphql_limits`] by the
+    /// `schema` itself (see [`Limited`]), regardless of the
+    /// transport they are requested via.
+    ///
+    /// # WebSocket authorization
+    ///
+    /// As browsers cannot reliably send [Basic authorization][1] credentials
+    /// on WebSocket upgrade requests, [GraphQL over WebSocket][2] connections
+    /// are authorized on `connection_init` message instead, by its
+    /// `authorization` payload field (`{"authorization": "Basic ..."}`),
+    /// falling back to the `Authorization` header of the upgrade request.
+    /// Unauthorized connections are rejected with `connection_error` message.
+    ///
+    /// Upgrade requests carrying a valid session cookie (see [`login`]) are
+    /// authorized right away.
+    ///
+    /// # Errors
+    ///
+    /// If GraphQL operation execution errors or fails.
+    ///
+    /// [`cli::Opts::graphql_limits`]: crate::cli::Opts::graphql_limits
+    /// [`cli::Opts::ws_max_connections`]: crate::cli::Opts::ws_max_connections
+    /// [`cli::Opts::ws_max_operations`]: crate::cli::Opts::ws_max_operations
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    /// [2]: https://github.com/apollographql/subscriptions-transport-ws
+    async fn execute_graphql<M>(
+        req: HttpRequest,
+        payload: web::Payload,
+        schema: Arc<
+            RootNode<
+                'static,
+                Limited<QueriesRoot>,
+                M,
+                Limited<SubscriptionsRoot>,
+            >,
+        >,
+        admin_only: bool,
+    ) -> Result<HttpResponse, Error>
+    where
+        M: GraphQLTypeAsync<
+                DefaultScalarValue,
+                Context = api::graphql::Context,
+                TypeInfo = (),
+            > + Send
+            + Sync
+            + 'static,
+    {
+        let ctx = api::graphql::Context::new(req.clone());
+        if req.head().upgrade() {
+            let opts = req.app_data::<Opts>().unwrap();
+            let keep_alive = opts.ws_keep_alive;
+            let max_operations = opts.ws_max_operations;
+            let conn = match WsConnection::try_new(opts.ws_max_connections) {
+                Some(c) => c,
+                None => {
+                    return Ok(HttpResponse::ServiceUnavailable()
+                        .body("Too many WebSocket connections"))
+                }
+            };
+            // Lives as long as the connection holds its `ctx`.
+            req.extensions_mut().insert(conn);
+
+            let state = req.app_data::<State>().unwrap().clone();
+            let session_role = session_role(
+                req.cookie(session::COOKIE_NAME).as_ref().map(Cookie::value),
+                req.app_data::<session::Key>().unwrap(),
+                &state.settings.get_cloned(),
+            );
+            let upgrade_auth = req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned);
+            let init = move |params: Variables| async move {
+                let auth = params
+                    .get("authorization")
+                    .and_then(InputValue::as_string_value)
+                    .map(ToOwned::to_owned)
+                    .or(upgrade_auth);
+                let role = match session_role {
+                    Some(r) => Some(r),
+                    None => authorized_role(&state, auth.as_deref()).await,
+                };
+                let role = match role {
+                    Some(r) => r,
+                    None => {
+                        return Err(api::graphql::Error::new("UNAUTHORIZED")
+                            .status(StatusCode::UNAUTHORIZED)
+                            .message("Unauthorized"))
+                    }
+                };
+                if admin_only && role != Role::Admin {
+                    return Err(api::graphql::Error::new("FORBIDDEN")
+                        .status(StatusCode::FORBIDDEN)
+                        .message("Admin access required"));
+                }
+                Ok(ConnectionConfig::new(ctx.with_role(role))
+                    .with_keep_alive_interval(keep_alive)
+                    .with_max_in_flight_operations(max_operations))
+            };
+            subscriptions_handler(req, payload, schema.into_inner(), init).await
+        } else {
+            if admin_only && ctx.role() != Role::Admin {
+                return Err(error::ErrorForbidden("Admin access required"));
+            }
+            graphql_handler(&*schema, &ctx, req, payload).await
+        }
+    }
+
+    /// Body of a [`login`] request.
+    #[derive(Clone, Debug, Deserialize)]
+    struct LoginRequest {
+        /// Password to verify against [`Settings::password_hash`] or
+        /// [`Settings::viewer_password_hash`].
+        password: String,
+    }
+
+    /// Endpoint establishing a browser session, as an alternative to
+    /// [Basic authorization][1].
+    ///
+    /// Verifies the password given as JSON (`{"password": "..."}`), and sets
+    /// a signed `HttpOnly` session cookie, valid for
+    /// [`cli::Opts::session_lifetime`] or until the password is changed.
+    ///
+    /// The session has the [`Role`] granted by the given password.
+    ///
+    /// Responds with:
+    /// - `204 No Content` if the session has been established (or no password
+    ///   is required at all);
+    /// - `401 Unauthorized` if the password is wrong.
+    ///
+    /// [`cli::Opts::session_lifetime`]: crate::cli::Opts::session_lifetime
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    #[post("/api/login")]
+    async fn login(
+        req: HttpRequest,
+        body: web::Json<LoginRequest>,
+    ) -> HttpResponse {
+        let settings = req.app_data::<State>().unwrap().settings.get_cloned();
+        if settings.password_hash.is_none() {
+            return HttpResponse::NoContent().finish();
+        }
+        let role = blocking_password_role(
+            settings.clone(),
+            body.into_inner().password,
+        )
+        .await;
+        let hash = match role {
+            Some(Role::Admin) => settings.password_hash,
+            Some(Role::Viewer) => settings.viewer_password_hash,
+            None => None,
+        };
+        let hash = match hash {
+            Some(h) => h,
+            None => return HttpResponse::Unauthorized().finish(),
+        };
+
+        let opts = req.app_data::<Opts>().unwrap();
+        let token = req.app_data::<session::Key>().unwrap().sign(
+            &hash,
+            settings.session_generation,
+            SystemTime::now(),
+            opts.session_lifetime,
+        );
+        HttpResponse::NoContent()
+            .header(
+                header::SET_COOKIE,
+                session_cookie(
+                    &token,
+                    &opts.base_path,
+                    opts.session_lifetime.as_secs(),
+                ),
+            )
+            .finish()
+    }
+
+    /// Endpoint ending a browser session established via [`login`], by
+    /// clearing its cookie.
+    ///
+    /// Always responds with `204 No Content`.
+    #[post("/api/logout")]
+    async fn logout(req: HttpRequest) -> HttpResponse {
+        let opts = req.app_data::<Opts>().unwrap();
+        HttpResponse::NoContent()
+            .header(header::SET_COOKIE, session_cookie("", &opts.base_path, 0))
+            .finish()
+    }
+
+    /// Headers set by reverse proxies forwarding requests, so a request
+    /// carrying any of them doesn't originate from a loopback connection,
+    /// regardless of its peer address.
+    const FORWARDING_HEADERS: &[&str] =
+        &["forwarded", "x-forwarded-for", "x-real-ip"];
+
+    /// Endpoint clearing the lost admin password without authorization, if
+    /// [`cli::Opts::allow_local_reset`] is specified.
+    ///
+    /// Is allowed for requests from a loopback connection only, not forwarded
+    /// by any reverse proxy, and carrying the
+    /// [`recovery::LOCAL_RESET_HEADER`] with the
+    /// [`recovery::LOCAL_RESET_CONFIRMATION`] value. Both allowed and rejected
+    /// requests are logged, while the reset is recorded as
+    /// [`Settings::password_reset`].
+    ///
+    /// Forwarded requests are recognized by their forwarding headers only, so
+    /// a reverse proxy on the same host not setting any of them makes remote
+    /// requests indistinguishable from local ones (see
+    /// [`cli::Opts::allow_local_reset`]).
+    ///
+    /// Responds with:
+    /// - `204 No Content` if the password has been cleared (or no password
+    ///   is set at all);
+    /// - `403 Forbidden` if the request is not allowed to clear it.
+    ///
+    /// [`cli::Opts::allow_local_reset`]: crate::cli::Opts::allow_local_reset
+    #[post("/api/local-reset")]
+    async fn local_reset(req: HttpRequest) -> HttpResponse {
+        let ip = req
+            .peer_addr()
+            .map_or_else(|| "unknown".into(), |a| a.ip().to_string());
+        if let Err(reason) = check_local_reset(&req) {
+            log::warn!("Rejected local password reset from {}: {}", ip, reason);
+            return HttpResponse::Forbidden().body(reason);
+        }
+
+        let state = req.app_data::<State>().unwrap();
+        let mut settings = trace::lock_mut(&state.settings, "local_reset");
+        if settings.password_hash.is_some() {
+            settings.reset_password(None, PasswordResetVia::LocalRequest);
+            log::warn!("Password is cleared by local reset from {}", ip);
+        }
+        HttpResponse::NoContent().finish()
+    }
+
+    /// Checks whether the given [`HttpRequest`] is allowed to clear the
+    /// password via [`local_reset`], returning the reason of its rejection
+    /// otherwise.
+    fn check_local_reset(req: &HttpRequest) -> Result<(), &'static str> {
+        if !req.peer_addr().map_or(false, |a| a.ip().is_loopback()) {
+            return Err("not a loopback connection");
+        }
+        let headers = req.headers();
+        if FORWARDING_HEADERS.iter().any(|h| headers.contains_key(*h)) {
+            return Err("forwarded by a reverse proxy");
+        }
+        let confirmed = headers
+            .get(recovery::LOCAL_RESET_HEADER)
+            .map_or(false, |v| {
+                v.as_bytes() == recovery::LOCAL_RESET_CONFIRMATION.as_bytes()
+            });
+        if !confirmed {
+            return Err("no confirmation header");
+        }
+        Ok(())
+    }
+
+    /// Formats a `Set-Cookie` header value for the session cookie with the
+    /// given `token`, expiring in `max_age` seconds.
+    fn session_cookie(token: &str, base_path: &str, max_age: u64) -> String {
+        format!(
+            "{}={}; Path={}/; Max-Age={}; HttpOnly; SameSite=Strict",
+            session::COOKIE_NAME,
+            token,
+            base_path,
+            max_age,
+        )
+    }
+
+    /// Determines the [`Role`] granted by the given `password` according to
+    /// the given [`Settings`].
+    ///
+    /// Returns [`None`] if the `password` matches neither
+    /// [`Settings::password_hash`] nor [`Settings::viewer_password_hash`].
+    fn password_role(settings: &Settings, password: &str) -> Option<Role> {
+        let verifies = |hash: &Option<String>| {
+            hash.as_deref()
+                .map_or(false, |h| password::verify(h, password))
+        };
+        if verifies(&settings.password_hash) {
+            Some(Role::Admin)
+        } else if settings.password_hash.is_some()
+            && verifies(&settings.viewer_password_hash)
+        {
+            Some(Role::Viewer)
+        } else {
+            None
+        }
+    }
+
+    /// Same as [`password_role()`], but performs the check on the blocking
+    /// threads pool, as [`argon2`] hashing is too heavy for the async
+    /// executor.
+    ///
+    /// Grants no [`Role`] if the blocking threads pool fails to perform the
+    /// check.
+    async fn blocking_password_role(
+        settings: Settings,
+        password: String,
+    ) -> Option<Role> {
+        web::block(move || {
+            Ok::<_, Infallible>(password_role(&settings, &password))
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Determines the [`Role`] of the session with the given cookie `token`
+    /// (see [`login`]), if it's signed by the given [`session::Key`] for the
+    /// current [`Settings::password_hash`] or
+    /// [`Settings::viewer_password_hash`], and is not expired yet.
+    ///
+    /// Always [`None`] if [`Settings::password_hash`] is [`None`], as there
+    /// are no sessions to be established.
+    fn session_role(
+        token: Option<&str>,
+        key: &session::Key,
+        settings: &Settings,
+    ) -> Option<Role> {
+        let token = token?;
+        let now = SystemTime::now();
+        let admin_hash = settings.password_hash.as_deref()?;
+        let generation = settings.session_generation;
+        if key.verify(token, admin_hash, generation, now) {
+            return Some(Role::Admin);
+        }
+        settings
+            .viewer_password_hash
+            .as_deref()
+            .filter(|h| key.verify(token, h, generation, now))
+            .map(|_| Role::Viewer)
+    }
+
+    /// Number of currently established [GraphQL over WebSocket][1] connections
+    /// to client HTTP server.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    static WS_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Returns the number of currently established
+    /// [GraphQL over WebSocket][1] connections to client HTTP server.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    #[inline]
+    #[must_use]
+    pub fn ws_connections() -> usize {
+        WS_CONNECTIONS.load(Ordering::SeqCst)
+    }
+
+    /// Guard of an established [GraphQL over WebSocket][1] connection, counted
+    /// in [`ws_connections()`] until dropped.
+    ///
+    /// [1]: https://github.com/apollographql/subscriptions-transport-ws
+    #[derive(Debug)]
+    struct WsConnection;
+
+    impl WsConnection {
+        /// Counts a new [`WsConnection`], unless there are `max` of them
+        /// already.
+        fn try_new(max: usize) -> Option<Self> {
+            WS_CONNECTIONS
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (n < max).then(|| n + 1)
+                })
+                .ok()
+                .map(|_| Self)
+        }
+    }
+
+    impl Drop for WsConnection {
+        fn drop(&mut self) {
+            let _ = WS_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Endpoint serving [`api::graphql::client`] schema in [GraphQL SDL][1].
+    ///
+    /// The served schema is deterministic across restarts, so may be
+    /// committed and diffed.
+    ///
+    /// Requires no authorization if [`cli::Opts::public_schema`] is specified.
+    ///
+    /// [`cli::Opts::public_schema`]: crate::cli::Opts::public_schema
+    /// [1]: https://graphql.org/learn/schema/#type-language
+    #[get("/api/schema.graphql")]
+    async fn schema_sdl(
+        schema: web::Data<api::graphql::client::Schema>,
+    ) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(schema.as_schema_language())
+    }
+
+    /// Number of seconds that browsers and proxies may cache the page served
+    /// by [`public_status`] endpoint for.
+    const STATUS_PAGE_MAX_AGE: u32 = 5;
+
+    /// Endpoint serving the public read-only status page (see
+    /// [`status_page`]).
+    ///
+    /// Requires no authorization, and is served only if
+    /// [`cli::Opts::public_status_page`] is specified.
+    ///
+    /// Responds with an `ETag` header being the current
+    /// [`State::restreams_version()`], and with `304 Not Modified` if it
+    /// matches the `If-None-Match` header of the request. Browsers and proxies
+    /// are allowed to cache the page for [`STATUS_PAGE_MAX_AGE`] seconds.
+    ///
+    /// [`cli::Opts::public_status_page`]: crate::cli::Opts::public_status_page
+    #[get("/status")]
+    async fn public_status(req: HttpRequest) -> HttpResponse {
+        let state = req.app_data::<State>().unwrap();
+        let cache = req.app_data::<status_page::Cache>().unwrap();
+        let (version, page) = cache.get(state);
+
+        let etag = format!("\"{}\"", version);
+        let cache_control = CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(STATUS_PAGE_MAX_AGE),
+        ]);
+        if api::rest::is_none_match_satisfied(req.headers(), &etag) {
+            return HttpResponse::NotModified()
+                .header(header::ETAG, etag)
+                .set(cache_control)
+                .finish();
+        }
+        HttpResponse::Ok()
+            .header(header::ETAG, etag)
+            .set(cache_control)
+            .content_type("text/html; charset=utf-8")
+            .body(page.to_string())
+    }
+
+    /// Endpoint serving [Prometheus] metrics of this server (see
+    /// [`metrics::render()`]).
+    ///
+    /// [Prometheus]: https://prometheus.io
+    #[get("/api/metrics")]
+    async fn prometheus_metrics(req: HttpRequest) -> HttpResponse {
+        let state = req.app_data::<State>().unwrap();
+        HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(metrics::render(state))
+    }
+
+    /// Endpoint serving a [`diagnostics::Report`] of this server as a
+    /// `.tar.gz` archive to [`Role::Admin`] clients only, rejecting the
+    /// [`Role::Viewer`] ones with `403 Forbidden`.
+    ///
+    /// Responds with `503 Service Unavailable` if the report is not generated
+    /// in [`diagnostics::GENERATION_TIMEOUT`].
+    #[get("/api/diagnostics.tar.gz")]
+    async fn diagnostics_report(
+        req: HttpRequest,
+        schema: web::Data<api::graphql::client::Schema>,
+    ) -> HttpResponse {
+        let ctx = api::graphql::Context::new(req.clone());
+        if ctx.role() != Role::Admin {
+            return HttpResponse::Forbidden().body("Admin access required");
+        }
+        let state = req.app_data::<State>().unwrap();
+        let opts = req.app_data::<Opts>().unwrap();
+
+        let generate = async {
+            let info = juniper::execute(
+                diagnostics::INFO_QUERY,
+                None,
+                &schema,
+                &Variables::new(),
+                &ctx,
+            )
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|(info, _)| {
+                serde_json::to_value(info).map_err(|e| e.to_string())
+            })
+            .unwrap_or_else(|e| json!({ "error": e }));
+            let report = diagnostics::Report::collect(state, opts, info);
+            web::block(move || report.to_tar_gz()).await
+        };
+        match time::timeout(diagnostics::GENERATION_TIMEOUT, generate).await {
+            Ok(Ok(archive)) => HttpResponse::Ok()
+                .content_type("application/gzip")
+                .header(
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"diagnostics.tar.gz\"",
+                )
+                .set(CacheControl(vec![CacheDirective::NoStore]))
+                .body(archive),
+            Ok(Err(e)) => {
+                log::error!("Failed to generate diagnostic report: {}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+            Err(_) => {
+                let timeout = diagnostics::GENERATION_TIMEOUT;
+                log::error!(
+                    "Diagnostic report is not generated in {}",
+                    humantime::format_duration(timeout),
+                );
+                HttpResponse::ServiceUnavailable().finish()
+            }
+        }
+    }
+
+    /// Endpoint serving the recent [`preview::Thumbnail`] of an `Input` with
+    /// the given ID as a JPEG image.
+    ///
+    /// Responds with `404 Not Found` if there is no such [`preview::Thumbnail`]
+    /// (the `Input` is not online, or [`cli::Opts::thumbnails_interval`] is not
+    /// specified).
+    ///
+    /// [`cli::Opts::thumbnails_interval`]:
+    ///     crate::cli::Opts::thumbnails_interval
+    #[get("/preview/{id}.jpg")]
+    async fn thumbnail(
+        req: HttpRequest,
+        id: web::Path<InputId>,
+    ) -> HttpResponse {
+        let thumbnail = match req
+            .app_data::<preview::Thumbnails>()
+            .unwrap()
+            .get(id.into_inner())
+        {
+            Some(t) => t,
+            None => return HttpResponse::NotFound().finish(),
+        };
+        let max_age = req
+            .app_data::<Opts>()
+            .unwrap()
+            .thumbnails_interval
+            .map_or(0, |i| i.as_secs());
+        HttpResponse::Ok()
+            .content_type("image/jpeg")
+            .set(CacheControl(vec![
+                CacheDirective::Private,
+                CacheDirective::MaxAge(max_age.try_into().unwrap_or(u32::MAX)),
+            ]))
+            .set(LastModified(thumbnail.taken_at.into()))
+            .body(thumbnail.jpeg.to_vec())
+    }
+
+    /// Endpoint uploading a [PNG] image to be used as an overlay of an
+    /// `Output` with the given ID (see `Mutation.setOutputOverlay`).
+    ///
+    /// Responds with:
+    /// - `204 No Content` if the image has been uploaded;
+    /// - `400 Bad Request` if the image cannot be decoded;
+    /// - `404 Not Found` if there is no such `Output`;
+    /// - `413 Payload Too Large` if the image exceeds
+    ///   [`overlay::MAX_UPLOAD_SIZE`];
+    /// - `415 Unsupported Media Type` if the uploaded file is not a [PNG].
+    ///
+    /// [PNG]: https://en.wikipedia.org/wiki/Portable_Network_Graphics
+    #[put("/overlays/{id}")]
+    async fn upload_overlay(
+        req: HttpRequest,
+        id: web::Path<OutputId>,
+        mut payload: web::Payload,
+    ) -> HttpResponse {
+        let id = id.into_inner();
+        let exists = req
+            .app_data::<State>()
+            .unwrap()
+            .restreams
+            .lock_ref()
+            .iter()
+            .any(|r| r.outputs.iter().any(|o| o.id == id));
+        if !exists {
+            return HttpResponse::NotFound().finish();
+        }
+
+        let mut body = web::BytesMut::new();
+        while let Some(chunk) = payload.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    return HttpResponse::BadRequest().body(e.to_string())
+                }
+            };
+            if body.len() + chunk.len() > overlay::MAX_UPLOAD_SIZE {
+                return HttpResponse::PayloadTooLarge().finish();
+            }
+            body.extend_from_slice(&chunk);
+        }
+        if !overlay::is_png(&body) {
+            return HttpResponse::UnsupportedMediaType().finish();
+        }
+
+        let cfg = req.app_data::<Opts>().unwrap();
+        let path = overlay::uploaded_path(&cfg.overlays_dir, id);
+        // Write to a temporary file first, so an already used image is never
+        // replaced with an undecodable one.
+        let tmp_path = path.with_extension("png.tmp");
+        if let Err(e) = fs::write(&tmp_path, &body).await {
+            log::error!("Failed to write overlay image: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+        match overlay::probe(&cfg.ffprobe_path, &tmp_path).await {
+            Ok(true) => {}
+            Ok(false) => {
+                let _ = fs::remove_file(&tmp_path).await;
+                return HttpResponse::BadRequest()
+                    .body("Uploaded image cannot be decoded");
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path).await;
+                log::error!("Failed to probe overlay image: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+        if let Err(e) = fs::rename(&tmp_path, &path).await {
+            log::error!("Failed to store overlay image: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        HttpResponse::NoContent().finish()
+    }
+
+    /// Endpoint replacing web UI on `/` when it's not served (see
+    /// [`cli::Opts::no_ui`]).
+    ///
+    /// Redirects to [`playground`] while [`State::playground_enabled`], or
+    /// responds with `404 Not Found` otherwise.
+    ///
+    /// [`cli::Opts::no_ui`]: crate::cli::Opts::no_ui
+    #[get("/")]
+    async fn no_ui(req: HttpRequest) -> HttpResponse {
+        let opts = req.app_data::<Opts>().unwrap();
+        if req.app_data::<State>().unwrap().playground_enabled.get() {
+            HttpResponse::Found()
+                .header(
+                    header::LOCATION,
+                    format!("{}/api/playground", opts.base_path),
+                )
+                .finish()
+        } else {
+            HttpResponse::NotFound().finish()
+        }
+    }
+
+    /// Endpoint serving [GraphQL Playground][1] for exploring the read-only
+    /// [`api::graphql::client`] (see [`graphql`]).
+    ///
+    /// Responds with `404 Not Found` unless [`State::playground_enabled`].
+    ///
+    /// [1]: https://github.com/graphql/graphql-playground
+    #[get("/api/playground")]
+    async fn playground(req: HttpRequest) -> HttpResponse {
+        playground_response(&req, false)
+    }
+
+    /// Endpoint serving [GraphQL Playground][1] for exploring the full
+    /// [`api::graphql::client`] (see [`admin_graphql`]).
+    ///
+    /// Responds with `404 Not Found` unless [`State::playground_enabled`], and
+    /// with `403 Forbidden` to non-[`Role::Admin`] clients.
+    ///
+    /// [1]: https://github.com/graphql/graphql-playground
+    #[get("/api/admin/playground")]
+    async fn admin_playground(req: HttpRequest) -> HttpResponse {
+        playground_response(&req, true)
+    }
+
+    /// Renders [GraphQL Playground][1] exploring the API served on the
+    /// current URL without its `/playground` suffix, if it's enabled and
+    /// allowed for the client of the given [`HttpRequest`].
+    ///
+    /// [1]: https://github.com/graphql/graphql-playground
+    fn playground_response(
+        req: &HttpRequest,
+        admin_only: bool,
+    ) -> HttpResponse {
+        if !req.app_data::<State>().unwrap().playground_enabled.get() {
+            return HttpResponse::NotFound().finish();
+        }
+        let role = req.extensions().get::<Role>().copied().unwrap_or_default();
+        if admin_only && role != Role::Admin {
+            return HttpResponse::Forbidden().body("Admin access required");
+        }
+
+        // Constructs API URL relatively to the current HTTP request's scheme
+        // and authority.
+        let html = playground_source("__API_URL__", None).replace(
+            "'__API_URL__'",
+            r"document.URL.replace(/\/playground$/, '')",
+        );
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(html)
+    }
+
+    /// Performs [`HttpRequest`] [Basic authorization][1] as middleware against
+    /// [`Settings::password_hash`] or [`Settings::viewer_password_hash`],
+    /// storing the granted [`Role`] in the request's extensions. Doesn't
+    /// consider username anyhow.
+    ///
+    /// Requests carrying a valid session cookie (see [`login`]) are authorized
+    /// without [Basic authorization][1].
+    ///
+    /// Requests authorized with [`Role::Viewer`] are forbidden to modify
+    /// anything, except via the read-only GraphQL API (see [`graphql`]),
+    /// having no mutations at all.
+    ///
+    /// No-op if [`Settings::password_hash`] is [`None`], or the request is for
+    /// the public schema (see [`cli::Opts::public_schema`]), or the request
+    /// is a WebSocket upgrade (authorized by [`execute_graphql()`] itself), or
+    /// the request is for [`login`] or [`logout`] endpoints.
+    ///
+    /// [`cli::Opts::public_schema`]: crate::cli::Opts::public_schema
+    ///
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    async fn authorize(req: ServiceRequest) -> Result<ServiceRequest, Error> {
+        // SRS callbacks are authorized by their token on their own.
+        if req.app_data::<callback::Token>().is_some()
+            && callback::single_port_token(req.path()).is_some()
+        {
+            return Ok(req);
+        }
+
+        let opts = req.app_data::<Opts>().unwrap();
+        let path = req.path().strip_prefix(opts.base_path.as_str());
+
+        if path == Some("/api/schema.graphql") && opts.public_schema {
+            return Ok(req);
+        }
+        if path == Some("/status") && opts.public_status_page {
+            return Ok(req);
+        }
+        // WebSocket connections are authorized on `connection_init` message.
+        if matches!(path, Some("/api") | Some("/api/admin"))
+            && req.head().upgrade()
+        {
+            return Ok(req);
+        }
+        if path == Some("/api/login") || path == Some("/api/logout") {
+            return Ok(req);
+        }
+        // Local reset is authorized by its connection on its own.
+        if path == Some("/api/local-reset") && opts.allow_local_reset {
+            return Ok(req);
+        }
+        let is_read_only = path == Some("/api")
+            || matches!(*req.method(), Method::GET | Method::HEAD);
+        // Primary server always wins, so a standby one rejects local edits.
+        if !is_read_only
+            && path.map_or(false, |p| p.starts_with("/api/v1/"))
+            && req.app_data::<State>().unwrap().is_replica()
+        {
+            return Err(error::ErrorConflict(
+                "Replica is read-only until promoted",
+            ));
+        }
+
+        let settings = req.app_data::<State>().unwrap().settings.get_cloned();
+        if settings.password_hash.is_none() {
+            return Ok(req);
+        }
+
+        let key = req.app_data::<session::Key>().unwrap();
+        let role = match session_role(
+            req.cookie(session::COOKIE_NAME).as_ref().map(Cookie::value),
+            key,
+            &settings,
+        ) {
+            Some(r) => r,
+            None => {
+                let err = || {
+                    AuthenticationError::new(
+                        req.app_data::<basic::Config>()
+                            .unwrap()
+                            .clone()
+                            .into_inner(),
+                    )
+                };
+                let auth =
+                    BasicAuth::from_service_request(&req).into_inner()?;
+                let pass = auth.password().ok_or_else(err)?.clone();
+                blocking_password_role(settings, pass.into_owned())
+                    .await
+                    .ok_or_else(err)?
+            }
+        };
+
+        if role == Role::Viewer && !is_read_only {
+            return Err(error::ErrorForbidden("Read-only access"));
+        }
+        req.extensions_mut().insert(role);
+
+        Ok(req)
+    }
+
+    /// Middleware performing [`authorize()`] of every [`ServiceRequest`]
+    /// before passing it to the wrapped [`Service`].
+    #[derive(Clone, Copy, Debug)]
+    struct Authorization;
+
+    impl<S, B> Transform<S> for Authorization
+    where
+        S: Service<
+                Request = ServiceRequest,
+                Response = ServiceResponse<B>,
+                Error = Error,
+            > + 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type InitError = ();
+        type Transform = AuthorizationMiddleware<S>;
+        type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            future::ok(AuthorizationMiddleware(Rc::new(RefCell::new(service))))
+        }
+    }
+
+    /// [`Service`] of the [`Authorization`] middleware.
+    ///
+    /// Wrapped [`Service`] is shared, as it's called only once the
+    /// [`authorize()`] completes asynchronously.
+    #[derive(Debug)]
+    struct AuthorizationMiddleware<S>(Rc<RefCell<S>>);
+
+    impl<S, B> Service for AuthorizationMiddleware<S>
+    where
+        S: Service<
+                Request = ServiceRequest,
+                Response = ServiceResponse<B>,
+                Error = Error,
+            > + 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Error>>;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut task::Context<'_>,
+        ) -> Poll<Result<(), Error>> {
+            self.0.borrow_mut().poll_ready(cx)
+        }
+
+        fn call(&mut self, req: ServiceRequest) -> Self::Future {
+            let srv = Rc::clone(&self.0);
+            async move {
+                let req = authorize(req).await?;
+                // Borrow shouldn't be held while the response is awaited.
+                let res = srv.borrow_mut().call(req);
+                res.await
+            }
+            .boxed_local()
+        }
+    }
+
+    /// Replaces the peer address of the given [`ServiceRequest`] with the real
+    /// client one, if the request comes from a trusted reverse proxy (see
+    /// [`cli::Opts::trusted_proxies`]), so access logs and authorization see
+    /// the real client consistently.
+    ///
+    /// [`cli::Opts::trusted_proxies`]: crate::cli::Opts::trusted_proxies
+    fn resolve_client_ip(req: &mut ServiceRequest) {
+        let peer = match req.peer_addr() {
+            Some(addr) => addr.ip(),
+            None => return,
+        };
+        let ip = proxy::client_ip(
+            peer,
+            req.headers(),
+            &req.app_data::<Opts>().unwrap().trusted_proxies,
+        );
+        if ip != peer {
+            req.head_mut().peer_addr = Some(SocketAddr::new(ip, 0));
+        }
+    }
+
+    /// Determines the [`Role`] granted by the given `Authorization` header
+    /// value via [Basic authorization][1]. Doesn't consider username anyhow.
+    ///
+    /// Always [`Role::Admin`] if [`Settings::password_hash`] is [`None`].
+    ///
+    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+    async fn authorized_role(
+        state: &State,
+        auth: Option<&str>,
+    ) -> Option<Role> {
+        let settings = state.settings.get_cloned();
+        if settings.password_hash.is_none() {
+            return Some(Role::Admin);
+        }
+        let password = auth
+            .and_then(|v| HeaderValue::from_str(v).ok())
+            .and_then(|v| Basic::parse(&v).ok())?
+            .password()?
+            .clone();
+        blocking_password_role(settings, password.into_owned()).await
+    }
+
+    #[cfg(test)]
+    mod role_spec {
+        use std::time::{Duration, SystemTime};
+
+        use crate::{api::graphql::Role, password, session, state::Settings};
+
+        use super::{password_role, session_role};
+
+        fn settings() -> Settings {
+            Settings {
+                password_hash: Some(password::hash("admin")),
+                viewer_password_hash: Some(password::hash("viewer")),
+                ..Settings::default()
+            }
+        }
+
+        #[test]
+        fn grants_role_by_password() {
+            let settings = settings();
+
+            assert_eq!(password_role(&settings, "admin"), Some(Role::Admin));
+            assert_eq!(password_role(&settings, "viewer"), Some(Role::Viewer));
+            assert_eq!(password_role(&settings, "unknown"), None);
+        }
+
+        #[test]
+        fn ignores_viewer_password_without_admin_one() {
+            let settings = Settings {
+                password_hash: None,
+                ..settings()
+            };
+
+            assert_eq!(password_role(&settings, "viewer"), None);
+        }
+
+        #[test]
+        fn grants_role_by_session() {
+            let settings = settings();
+            let key = session::Key::generate();
+            let sign = |hash: &Option<String>| {
+                key.sign(
+                    hash.as_deref().unwrap(),
+                    settings.session_generation,
+                    SystemTime::now(),
+                    Duration::from_secs(60),
+                )
+            };
+            let admin = sign(&settings.password_hash);
+            let viewer = sign(&settings.viewer_password_hash);
+
+            assert_eq!(
+                session_role(Some(&admin), &key, &settings),
+                Some(Role::Admin),
+            );
+            assert_eq!(
+                session_role(Some(&viewer), &key, &settings),
+                Some(Role::Viewer),
+            );
+            assert_eq!(session_role(Some("qwerty"), &key, &settings), None);
+            assert_eq!(session_role(None, &key, &settings), None);
+        }
+
+        #[test]
+        fn revokes_sessions_of_previous_generation() {
+            let mut settings = settings();
+            let key = session::Key::generate();
+            let admin = key.sign(
+                settings.password_hash.as_deref().unwrap(),
+                settings.session_generation,
+                SystemTime::now(),
+                Duration::from_secs(60),
+            );
+
+            settings.session_generation += 1;
+
+            assert_eq!(session_role(Some(&admin), &key, &settings), None);
+        }
+    }
+
+    #[cfg(test)]
+    mod resolve_client_ip_spec {
+        use actix_service::Service as _;
+        use actix_web::{rt::System, test, web, App, HttpRequest};
+        use structopt::StructOpt as _;
+
+        use crate::cli::Opts;
+
+        use super::resolve_client_ip;
+
+        fn resolve(peer: &str, xff: &str) -> String {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&[
+                            "ephyr-restreamer",
+                            "--trusted-proxies",
+                            "127.0.0.1,10.0.0.0/8",
+                        ]))
+                        .wrap_fn(|mut req, srv| {
+                            resolve_client_ip(&mut req);
+                            srv.call(req)
+                        })
+                        .route(
+                            "/",
+                            web::get().to(|req: HttpRequest| async move {
+                                req.peer_addr().unwrap().ip().to_string()
+                            }),
+                        ),
+                )
+                .await;
+                let req = test::TestRequest::get()
+                    .peer_addr(peer.parse().unwrap())
+                    .header("x-forwarded-for", xff)
+                    .to_request();
+                let body = test::read_response(&mut app, req).await;
+                String::from_utf8(body.to_vec()).unwrap()
+            })
+        }
+
+        #[test]
+        fn resolves_client_behind_trusted_proxies() {
+            assert_eq!(
+                resolve("127.0.0.1:5000", "198.51.100.1, 10.0.0.2"),
+                "198.51.100.1",
+            );
+        }
+
+        #[test]
+        fn ignores_headers_of_untrusted_peer() {
+            assert_eq!(
+                resolve("203.0.113.7:5000", "198.51.100.1"),
+                "203.0.113.7",
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod single_port_spec {
+        use actix_service::Service as _;
+        use actix_web::{http::StatusCode, rt::System, test, web::Bytes, App};
+        use actix_web_httpauth::extractors::basic;
+        use serde_json::json;
+        use structopt::StructOpt as _;
+
+        use crate::{cli::Opts, password, session, spec, State};
+
+        use super::{callback, request_line, Authorization};
+
+        fn state() -> State {
+            let state = State::new_in_memory();
+            state.settings.lock_mut().password_hash =
+                Some(password::hash("admin"));
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": "guest",
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "enabled": true,
+                        },
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+            state
+        }
+
+        #[test]
+        fn serves_callbacks_authorized_by_token_only() {
+            System::new("test").block_on(async {
+                let token = callback::Token::generate();
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&["ephyr-restreamer"]))
+                        .app_data(state())
+                        .app_data(session::Key::generate())
+                        .app_data(basic::Config::default())
+                        .app_data(token.clone())
+                        .wrap(Authorization)
+                        .service(callback::single_port_resource()),
+                )
+                .await;
+                let body = json!({
+                    "action": "on_connect",
+                    "client_id": 1,
+                    "ip": "127.0.0.1",
+                    "app": "guest",
+                });
+
+                let req = test::TestRequest::post()
+                    .uri(&format!(
+                        "{}/{}",
+                        callback::SINGLE_PORT_PATH,
+                        token.as_str(),
+                    ))
+                    .set_json(&body)
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert_eq!(test::read_body(resp).await, Bytes::from("0"));
+
+                let req = test::TestRequest::post()
+                    .uri(&format!("{}/wrong", callback::SINGLE_PORT_PATH))
+                    .set_json(&body)
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+                for uri in &["/api", "/__srs_callbackx/api"] {
+                    let req = test::TestRequest::get().uri(uri).to_request();
+                    let resp = app.call(req).await;
+                    assert_eq!(
+                        resp.err().map(|e| e.as_response_error().status_code()),
+                        Some(StatusCode::UNAUTHORIZED),
+                        "{}",
+                        uri,
+                    );
+                }
+            });
+        }
+
+        #[test]
+        fn redacts_token_in_access_logs() {
+            let token = callback::Token::generate();
+            let req = test::TestRequest::post()
+                .uri(&format!(
+                    "{}/{}",
+                    callback::SINGLE_PORT_PATH,
+                    token.as_str(),
+                ))
+                .to_srv_request();
+            let line = request_line(&req);
+            assert!(!line.contains(token.as_str()), "{}", line);
+            assert_eq!(line, "POST /__srs_callback/<redacted> HTTP/1.1");
+
+            let req = test::TestRequest::get()
+                .uri("/api?query=1")
+                .to_srv_request();
+            assert_eq!(request_line(&req), "GET /api?query=1 HTTP/1.1");
+        }
+    }
+
+    #[cfg(test)]
+    mod public_status_spec {
+        use actix_service::Service as _;
+        use actix_web::{
+            http::{header, StatusCode},
+            rt::System,
+            test, App,
+        };
+        use actix_web_httpauth::extractors::basic;
+        use structopt::StructOpt as _;
+
+        use crate::{cli::Opts, password, session, status_page, State};
+
+        use super::{public_status, Authorization};
+
+        fn state() -> State {
+            let state = State::new_in_memory();
+            state.settings.lock_mut().password_hash =
+                Some(password::hash("admin"));
+            state
+        }
+
+        #[test]
+        fn serves_page_without_authorization_when_enabled() {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&[
+                            "ephyr-restreamer",
+                            "--public-status-page",
+                        ]))
+                        .app_data(state())
+                        .app_data(session::Key::generate())
+                        .app_data(basic::Config::default())
+                        .app_data(status_page::Cache::default())
+                        .wrap(Authorization)
+                        .service(public_status),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/status").to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert_eq!(
+                    resp.headers().get(header::CACHE_CONTROL).unwrap(),
+                    "public, max-age=5",
+                );
+                let etag = resp.headers().get(header::ETAG).unwrap().clone();
+
+                let req = test::TestRequest::get()
+                    .uri("/status")
+                    .header(header::IF_NONE_MATCH, etag)
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+            });
+        }
+
+        #[test]
+        fn requires_authorization_when_disabled() {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&["ephyr-restreamer"]))
+                        .app_data(state())
+                        .app_data(session::Key::generate())
+                        .app_data(basic::Config::default())
+                        .app_data(status_page::Cache::default())
+                        .wrap(Authorization)
+                        .service(public_status),
+                )
+                .await;
+
+                let req = test::TestRequest::get().uri("/status").to_request();
+                let resp = app.call(req).await;
+                assert_eq!(
+                    resp.err().map(|e| e.as_response_error().status_code()),
+                    Some(StatusCode::UNAUTHORIZED),
+                );
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod local_reset_spec {
+        use actix_service::Service as _;
+        use actix_web::{http::StatusCode, rt::System, test, App};
+        use actix_web_httpauth::extractors::basic;
+        use structopt::StructOpt as _;
+
+        use crate::{
+            cli::Opts, password, recovery, session, state::PasswordResetVia,
+            State,
+        };
+
+        use super::{local_reset, Authorization};
+
+        fn reset(
+            allowed: bool,
+            peer: &str,
+            headers: &[(&str, &str)],
+        ) -> (StatusCode, State) {
+            let state = State::new_in_memory();
+            state.settings.lock_mut().password_hash =
+                Some(password::hash("admin"));
+            let mut args = vec!["ephyr-restreamer"];
+            if allowed {
+                args.push("--allow-local-reset");
+            }
+
+            let status = System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&args))
+                        .app_data(state.clone())
+                        .app_data(session::Key::generate())
+                        .app_data(basic::Config::default())
+                        .wrap(Authorization)
+                        .service(local_reset),
+                )
+                .await;
+                let mut req = test::TestRequest::post()
+                    .uri("/api/local-reset")
+                    .peer_addr(peer.parse().unwrap());
+                for (name, val) in headers {
+                    req = req.header(*name, *val);
+                }
+                match app.call(req.to_request()).await {
+                    Ok(resp) => resp.status(),
+                    Err(e) => e.as_response_error().status_code(),
+                }
+            });
+            (status, state)
+        }
+
+        fn confirmed() -> (&'static str, &'static str) {
+            (
+                recovery::LOCAL_RESET_HEADER,
+                recovery::LOCAL_RESET_CONFIRMATION,
+            )
+        }
+
+        #[test]
+        fn clears_password_from_loopback() {
+            for peer in &["127.0.0.1:5000", "[::1]:5000"] {
+                let (status, state) = reset(true, peer, &[confirmed()]);
+
+                assert_eq!(status, StatusCode::NO_CONTENT);
+                let settings = state.settings.get_cloned();
+                assert_eq!(settings.password_hash, None);
+                assert_eq!(
+                    settings.password_reset.map(|r| r.via),
+                    Some(PasswordResetVia::LocalRequest),
+                );
+            }
+        }
+
+        #[test]
+        fn rejects_without_confirmation() {
+            let (status, state) = reset(
+                true,
+                "127.0.0.1:5000",
+                &[(recovery::LOCAL_RESET_HEADER, "yes")],
+            );
+
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert!(state.settings.get_cloned().password_hash.is_some());
+        }
+
+        #[test]
+        fn rejects_remote_connections() {
+            let (status, state) =
+                reset(true, "203.0.113.7:5000", &[confirmed()]);
+
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert!(state.settings.get_cloned().password_hash.is_some());
+        }
+
+        #[test]
+        fn rejects_forwarded_requests() {
+            let (status, state) = reset(
+                true,
+                "127.0.0.1:5000",
+                &[confirmed(), ("x-forwarded-for", "203.0.113.7")],
+            );
+
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert!(state.settings.get_cloned().password_hash.is_some());
+        }
+
+        #[test]
+        fn requires_authorization_when_disabled() {
+            let (status, state) =
+                reset(false, "127.0.0.1:5000", &[confirmed()]);
+
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert!(state.settings.get_cloned().password_hash.is_some());
+        }
+    }
+
+    #[cfg(test)]
+    mod graphql_spec {
+        use actix_web::{
+            http::StatusCode, rt::System, test, App, HttpMessage as _,
+        };
+        use serde_json::{json, Value};
+        use structopt::StructOpt as _;
+
+        use crate::{api, api::graphql::Role, cli::Opts};
+
+        use super::{admin_graphql, graphql};
+
+        fn post_as(uri: &str, role: Role, query: &str) -> (StatusCode, Value) {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&[
+                            "ephyr-restreamer",
+                            "--graphql-max-depth=2",
+                            "--graphql-max-complexity=3",
+                        ]))
+                        .data(api::graphql::client::read_only_schema())
+                        .data(api::graphql::client::schema())
+                        .service(graphql)
+                        .service(admin_graphql),
+                )
+                .await;
+                let req = test::TestRequest::post()
+                    .uri(uri)
+                    .set_json(&json!({ "query": query }))
+                    .to_request();
+                req.extensions_mut().insert(role);
+                let resp = test::call_service(&mut app, req).await;
+                let status = resp.status();
+                let body = test::read_body(resp).await;
+                (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+            })
+        }
+
+        fn post(query: &str) -> (StatusCode, Value) {
+            post_as("/api", Role::Admin, query)
+        }
+
+        #[test]
+        fn rejects_too_deep_queries() {
+            let (status, body) = post("{ info { srs { version } } }");
+            assert_eq!(status, StatusCode::OK);
+            assert!(body["data"]["info"].is_null());
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"],
+                "QUERY_TOO_DEEP",
+            );
+        }
+
+        #[test]
+        fn rejects_too_complex_queries() {
+            let (status, body) =
+                post("{ info { title publicHost srsRtmpPort } }");
+            assert_eq!(status, StatusCode::OK);
+            assert!(body["data"]["info"].is_null());
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"],
+                "QUERY_TOO_COMPLEX",
+            );
+        }
+
+        #[test]
+        fn read_only_api_cannot_parse_mutations() {
+            for role in &[Role::Admin, Role::Viewer] {
+                let (status, body) = post_as(
+                    "/api",
+                    *role,
+                    r#"mutation { removeDvrFile(path: "a.flv") }"#,
+                );
+                assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", role);
+                assert!(body["data"].is_null(), "{:?}", role);
+            }
+        }
+
+        #[test]
+        fn admin_api_is_for_admins_only() {
+            let (status, _) =
+                post_as("/api/admin", Role::Viewer, "{ info { title } }");
+            assert_eq!(status, StatusCode::FORBIDDEN);
+
+            let (status, body) =
+                post_as("/api/admin", Role::Admin, "{ info { title } }");
+            assert_eq!(status, StatusCode::OK);
+            assert!(body["errors"].is_null());
+        }
+    }
+
+    #[cfg(test)]
+    mod create_spec {
+        use actix_web::{
+            http::StatusCode, rt::System, test, App, HttpMessage as _,
+        };
+        use serde_json::{json, Value};
+        use structopt::StructOpt as _;
+
+        use crate::{
+            api::{self, graphql::Role},
+            cli::Opts,
+            schedule::{InputSchedule, ScheduleRange, Weekday},
+            spec, State,
+        };
+
+        use super::admin_graphql;
+
+        fn post(state: &State, query: &str) -> (StatusCode, Value) {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(Opts::from_iter(&["ephyr-restreamer"]))
+                        .app_data(state.clone())
+                        .data(api::graphql::client::schema())
+                        .service(admin_graphql),
+                )
+                .await;
+                let req = test::TestRequest::post()
+                    .uri("/api/admin")
+                    .set_json(&json!({ "query": query }))
+                    .to_request();
+                req.extensions_mut().insert(Role::Admin);
+                let resp = test::call_service(&mut app, req).await;
+                let status = resp.status();
+                let body = test::read_body(resp).await;
+                (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+            })
+        }
+
+        fn create_push_input(state: &State) -> String {
+            let (_, body) = post(
+                state,
+                r#"mutation { createInput(push: { key: "live" }) { id } }"#,
+            );
+            body["data"]["createInput"]["id"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        }
+
+        #[test]
+        fn creates_push_input_from_partial_object() {
+            let state = State::new_in_memory();
+
+            let (status, body) = post(
+                &state,
+                r#"mutation {
+                    createInput(push: { key: "live", lowLatency: true }) {
+                        key
+                        lowLatency
+                        public
+                        queuePublishers
+                    }
+                }"#,
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            let restream = &body["data"]["createInput"];
+            assert_eq!(restream["key"], "live");
+            assert_eq!(restream["lowLatency"], true);
+            assert_eq!(restream["public"], false);
+            assert_eq!(restream["queuePublishers"], false);
+
+            let restreams = state.restreams.lock_ref();
+            assert_eq!(restreams.len(), 1);
+            assert!(restreams[0].low_latency);
+            assert!(restreams[0].input.src.is_none());
+        }
+
+        #[test]
+        fn creates_pull_input_with_backup() {
+            let state = State::new_in_memory();
+
+            let (status, body) = post(
+                &state,
+                r#"mutation {
+                    createInput(pull: {
+                        key: "pulled"
+                        src: "rtmp://example.com/live/main"
+                        backupSrc: "rtmp://example.com/live/backup"
+                        public: true
+                    }) {
+                        key
+                        public
+                    }
+                }"#,
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            assert_eq!(body["data"]["createInput"]["public"], true);
+
+            let spec = state.restreams.lock_ref()[0].export();
+            assert!(spec.public);
+            match spec.input.src {
+                Some(spec::v1::InputSrc::FailoverInputs(inputs)) => {
+                    assert_eq!(inputs.len(), 2);
+                    assert!(inputs.iter().all(|i| i.src.is_some()));
+                }
+                src => panic!("unexpected input src: {:?}", src),
+            }
+        }
+
+        #[test]
+        fn creates_scheduled_pull_input() {
+            let state = State::new_in_memory();
+
+            let (status, body) = post(
+                &state,
+                r#"mutation {
+                    createInput(pull: {
+                        key: "relay"
+                        src: "rtmp://example.com/live/tv"
+                        schedule: [
+                            {weekday: FRIDAY, start: "23:00", end: "01:00"}
+                        ]
+                    }) {
+                        input { schedule { weekday start end } }
+                    }
+                }"#,
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            assert_eq!(
+                body["data"]["createInput"]["input"]["schedule"],
+                json!([
+                    {"weekday": "FRIDAY", "start": "23:00", "end": "01:00"},
+                ]),
+            );
+            let restream_id = state.restreams.lock_ref()[0].id;
+
+            let (_, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        setInputSchedule(restreamId: "{}", ranges: [])
+                    }}"#,
+                    restream_id,
+                ),
+            );
+            assert_eq!(body["data"]["setInputSchedule"], true, "{}", body);
+            assert!(state.restreams.lock_ref()[0].input.schedule.is_none());
+        }
+
+        #[test]
+        fn converts_schedule_from_timezone() {
+            let state = State::new_in_memory();
+            let (_, body) = post(
+                &state,
+                r#"mutation {
+                    createInput(pull: {
+                        key: "relay"
+                        src: "rtmp://example.com/live/tv"
+                    }) { input { id } }
+                }"#,
+            );
+            assert!(body["errors"].is_null(), "{}", body);
+            let restream_id = state.restreams.lock_ref()[0].id;
+            let set = |timezone: &str| {
+                post(
+                    &state,
+                    &format!(
+                        r#"mutation {{
+                            setInputSchedule(
+                                restreamId: "{}"
+                                ranges: [{{
+                                    weekday: TUESDAY
+                                    start: "02:00"
+                                    end: "04:00"
+                                }}]
+                                timezone: "{}"
+                            )
+                        }}"#,
+                        restream_id, timezone,
+                    ),
+                )
+                .1
+            };
+
+            let body = set("Mars/Olympus_Mons");
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"], "INVALID_TIMEZONE",
+                "{}",
+                body,
+            );
+            assert!(state.restreams.lock_ref()[0].input.schedule.is_none());
+
+            // Always UTC+03:00, as has no DST.
+            let body = set("Etc/GMT-3");
+            assert_eq!(body["data"]["setInputSchedule"], true, "{}", body);
+            assert_eq!(
+                state.restreams.lock_ref()[0].input.schedule,
+                InputSchedule::new(vec![ScheduleRange::new(
+                    Weekday::Monday,
+                    "23:00",
+                    "01:00",
+                )
+                .unwrap()]),
+            );
+        }
+
+        #[test]
+        fn rejects_schedule_of_push_input() {
+            let state = State::new_in_memory();
+            let restream_id = create_push_input(&state);
+
+            let (_, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        setInputSchedule(restreamId: "{}", ranges: [
+                            {{weekday: MONDAY, start: "19:00", end: "23:00"}}
+                        ])
+                    }}"#,
+                    restream_id,
+                ),
+            );
+
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"], "INVALID_SCHEDULE",
+                "{}",
+                body,
+            );
+            assert!(state.restreams.lock_ref()[0].input.schedule.is_none());
+        }
+
+        #[test]
+        fn creates_pull_input_with_rtmp_options() {
+            let state = State::new_in_memory();
+
+            let (status, body) = post(
+                &state,
+                r#"mutation {
+                    createInput(pull: {
+                        key: "relay"
+                        src: "rtmp://cdn.host/app/stream?e=123"
+                        rtmpOptions: {
+                            playpath: "mp4:stream"
+                            tokenQuery: "token=abc"
+                        }
+                    }) {
+                        input {
+                            src { ... on RemoteInputSrc { url } }
+                            rtmpOptions { playpath tokenQuery }
+                        }
+                    }
+                }"#,
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            let input = &body["data"]["createInput"]["input"];
+            assert_eq!(input["src"]["url"], "rtmp://cdn.host/app/stream?e=123");
+            assert_eq!(
+                input["rtmpOptions"],
+                json!({"playpath": "mp4:stream", "tokenQuery": "token=***"}),
+            );
+            let spec = state.restreams.lock_ref()[0].export();
+            assert_eq!(
+                spec.input.rtmp_options.unwrap().token_query.as_deref(),
+                Some("token=abc"),
+            );
+        }
+
+        #[test]
+        fn rejects_rtmp_options_of_push_input() {
+            let state = State::new_in_memory();
+            let restream_id = create_push_input(&state);
+            let input_id = state.restreams.lock_ref()[0].input.id;
+
+            let (_, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        setInputRtmpOptions(
+                            id: "{}"
+                            restreamId: "{}"
+                            options: {{ playpath: "stream" }}
+                        )
+                    }}"#,
+                    input_id, restream_id,
+                ),
+            );
+
+            assert_eq!(
+                body["errors"][0]["extensions"]["code"], "INVALID_RTMP_OPTIONS",
+                "{}",
+                body,
+            );
+            assert!(state.restreams.lock_ref()[0].input.rtmp_options.is_none());
+        }
+
+        #[test]
+        fn rejects_ambiguous_input() {
+            let state = State::new_in_memory();
+
+            for args in &[
+                "",
+                concat!(
+                    r#"(push: { key: "a" }, "#,
+                    r#"pull: { key: "b", src: "rtmp://a/b" })"#,
+                ),
+            ] {
+                let (_, body) = post(
+                    &state,
+                    &format!("mutation {{ createInput{} {{ id }} }}", args),
+                );
+                assert_eq!(
+                    body["errors"][0]["extensions"]["code"], "AMBIGUOUS_INPUT",
+                    "{}",
+                    body,
+                );
+            }
+            assert!(state.restreams.lock_ref().is_empty());
+        }
+
+        #[test]
+        fn creates_output_from_partial_object() {
+            let state = State::new_in_memory();
+            let restream_id = create_push_input(&state);
+
+            let (status, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        createOutput(restreamId: "{}", output: {{
+                            dst: "rtmp://example.com/live/out"
+                            delaySecs: 10
+                            enabled: true
+                        }}) {{
+                            delaySecs
+                            maxBitrateKbps
+                            prewarm
+                            enabled
+                        }}
+                    }}"#,
+                    restream_id,
+                ),
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            let output = &body["data"]["createOutput"];
+            assert_eq!(output["delaySecs"], 10);
+            assert!(output["maxBitrateKbps"].is_null());
+            assert_eq!(output["prewarm"], false);
+            assert_eq!(output["enabled"], true);
+
+            let restreams = state.restreams.lock_ref();
+            assert_eq!(restreams[0].outputs.len(), 1);
+            assert!(restreams[0].outputs[0].enabled);
+            assert_eq!(restreams[0].outputs[0].delay_secs, Some(10));
+        }
+
+        #[test]
+        fn creates_output_with_all_settings() {
+            let state = State::new_in_memory();
+            let restream_id = create_push_input(&state);
+
+            let (status, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        createOutput(restreamId: "{}", output: {{
+                            dst: "rtmp://example.com/live/out"
+                            conditioning: YOUTUBE
+                            audioSampleRate: HZ_44100
+                            metadata: {{ title: "Show" }}
+                            loudnorm: {{ integrated: -23 }}
+                            limits: {{ nice: 10 }}
+                            srcStream: "origin"
+                        }}) {{
+                            conditioning {{ preset audioSampleRate }}
+                            metadata {{ title author }}
+                            loudnorm {{ integrated truePeak }}
+                            limits {{ nice cpuQuota }}
+                            srcStream
+                        }}
+                    }}"#,
+                    restream_id,
+                ),
+            );
+
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            assert_eq!(
+                body["data"]["createOutput"],
+                json!({
+                    "conditioning": {
+                        "preset": "YOUTUBE",
+                        "audioSampleRate": "HZ_44100",
+                    },
+                    "metadata": {"title": "Show", "author": null},
+                    "loudnorm": {"integrated": -23.0, "truePeak": -1.0},
+                    "limits": {"nice": 10, "cpuQuota": null},
+                    "srcStream": null,
+                }),
+            );
+        }
+
+        #[test]
+        fn creates_no_output_if_any_field_is_invalid() {
+            let state = State::new_in_memory();
+            let restream_id = create_push_input(&state);
+
+            for (field, code) in &[
+                ("delaySecs: 1000", "INVALID_OUTPUT_DELAY"),
+                ("maxBitrateKbps: -1", "INVALID_OUTPUT_BITRATE"),
+                (
+                    r#"backupDst: "rtmp://example.com/live/out""#,
+                    "INVALID_BACKUP_DST",
+                ),
+                ("loudnorm: { integrated: 0 }", "INVALID_LOUDNESS_TARGET"),
+                ("limits: { nice: 100 }", "INVALID_OUTPUT_LIMITS"),
+                (r#"srcStream: "unknown""#, "UNKNOWN_STREAM"),
+            ] {
+                let (_, body) = post(
+                    &state,
+                    &format!(
+                        r#"mutation {{
+                            createOutput(restreamId: "{}", output: {{
+                                dst: "rtmp://example.com/live/out"
+                                {}
+                            }}) {{ id }}
+                        }}"#,
+                        restream_id, field,
+                    ),
+                );
+                assert_eq!(
+                    body["errors"][0]["extensions"]["code"], *code,
+                    "{}",
+                    body,
+                );
+            }
+            assert!(state.restreams.lock_ref()[0].outputs.is_empty());
+        }
+
+        #[test]
+        fn deprecated_mutations_still_work() {
+            let state = State::new_in_memory();
+
+            let (_, body) =
+                post(&state, r#"mutation { setRestream(key: "old") }"#);
+            assert_eq!(body["data"]["setRestream"], true, "{}", body);
+
+            let id = state.restreams.lock_ref()[0].id;
+            let (_, body) = post(
+                &state,
+                &format!(
+                    r#"mutation {{
+                        setOutput(restreamId: "{}", dst: "rtmp://a/b/c")
+                    }}"#,
+                    id,
+                ),
+            );
+            assert_eq!(body["data"]["setOutput"], true, "{}", body);
+        }
+    }
+
+    #[cfg(test)]
+    mod playground_spec {
+        use actix_web::{
+            http::StatusCode, rt::System, test, App, HttpMessage as _,
+        };
+
+        use crate::{api::graphql::Role, State};
+
+        use super::{admin_playground, playground};
 
-use ephyr_log::log;
-use futures::future;
-use tokio::{fs, time};
+        fn get_as(uri: &str, role: Role, enabled: bool) -> StatusCode {
+            System::new("test").block_on(async {
+                let state = State::new_in_memory();
+                state.playground_enabled.set(enabled);
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(state)
+                        .service(playground)
+                        .service(admin_playground),
+                )
+                .await;
+                let req = test::TestRequest::get().uri(uri).to_request();
+                req.extensions_mut().insert(role);
+                test::call_service(&mut app, req).await.status()
+            })
+        }
 
-use crate::{
-    cli::{Failure, Opts},
-    dvr, ffmpeg, srs, teamspeak, State,
-};
+        #[test]
+        fn is_not_found_while_disabled() {
+            for uri in &["/api/playground", "/api/admin/playground"] {
+                assert_eq!(
+                    get_as(uri, Role::Admin, false),
+                    StatusCode::NOT_FOUND,
+                    "{}",
+                    uri,
+                );
+                assert_eq!(
+                    get_as(uri, Role::Admin, true),
+                    StatusCode::OK,
+                    "{}",
+                    uri,
+                );
+            }
+        }
 
-/// Initializes and runs all application's HTTP servers.
-///
-/// # Errors
-///
-/// If some [`HttpServer`] cannot run due to already used port, etc.
-/// The actual error is witten to logs.
-///
-/// [`HttpServer`]: actix_web::HttpServer
-#[actix_web::main]
-pub async fn run(mut cfg: Opts) -> Result<(), Failure> {
-    if cfg.public_host.is_none() {
-        cfg.public_host = Some(
-            detect_public_ip()
-                .await
-                .ok_or_else(|| {
-                    log::error!("Cannot detect server's public IP address")
-                })?
-                .to_string(),
-        );
+        #[test]
+        fn admin_playground_is_for_admins_only() {
+            assert_eq!(
+                get_as("/api/playground", Role::Viewer, true),
+                StatusCode::OK,
+            );
+            assert_eq!(
+                get_as("/api/admin/playground", Role::Viewer, true),
+                StatusCode::FORBIDDEN,
+            );
+        }
     }
 
-    let ffmpeg_path =
-        fs::canonicalize(&cfg.ffmpeg_path).await.map_err(|e| {
-            log::error!("Failed to resolve FFmpeg binary path: {}", e)
-        })?;
+    #[cfg(test)]
+    mod compression_spec {
+        use actix_web::{
+            http::{header, StatusCode},
+            rt::System,
+            test,
+        };
+        use serde_json::json;
+        use structopt::StructOpt as _;
 
-    let state = State::try_new(&cfg.state_path)
-        .await
-        .map_err(|e| log::error!("Failed to initialize server state: {}", e))?;
+        use crate::{
+            cli::Opts, preview, session, state::InputId, status_page, State,
+        };
 
-    let srs = srs::Server::try_new(
-        &cfg.srs_path,
-        &srs::Config {
-            callback_port: cfg.callback_http_port,
-            http_server_dir: cfg.srs_http_dir.clone().into(),
-            log_level: cfg.verbose.map(Into::into).unwrap_or_default(),
-        },
-    )
-    .await
-    .map_err(|e| log::error!("Failed to initialize SRS server: {}", e))?;
-    State::on_change(
-        "cleanup_dvr_files",
-        &state.restreams,
-        |restreams| async move {
-            // Wait for all the re-streaming processes to release DVR files.
-            time::delay_for(Duration::from_secs(1)).await;
-            dvr::Storage::global().cleanup(&restreams).await;
-        },
-    );
+        use super::app;
 
-    let mut restreamers =
-        ffmpeg::RestreamersPool::new(ffmpeg_path, state.clone());
-    State::on_change("spawn_restreamers", &state.restreams, move |restreams| {
-        restreamers.apply(&restreams);
-        future::ready(())
-    });
+        /// Requests the given `uri` of the client HTTP server [`app()`]
+        /// accepting `gzip` encoding, and returns the `Content-Encoding` of
+        /// the response, if any.
+        ///
+        /// `{id}` placeholder in the `uri` is replaced with an ID of the
+        /// `Input` having a thumbnail.
+        fn content_encoding(uri: &str, enabled: bool) -> Option<String> {
+            let mut args = vec!["ephyr-restreamer"];
+            if !enabled {
+                args.push("--no-compression");
+            }
+            let id = InputId::random();
+            let thumbnails = preview::Thumbnails::default();
+            thumbnails.insert(id, &[0; 4096]);
+            let uri = uri.replace("{id}", &id.to_string());
 
-    future::try_join(
-        self::client::run(&cfg, state.clone()),
-        self::callback::run(&cfg, state),
-    )
-    .await?;
+            System::new("test").block_on(async {
+                let mut app = test::init_service(app(
+                    &Opts::from_iter(&args),
+                    &State::new_in_memory(),
+                    &thumbnails,
+                    &session::Key::generate(),
+                    &status_page::Cache::default(),
+                    None,
+                ))
+                .await;
+                let req = if uri == "/api" {
+                    test::TestRequest::post()
+                        .set_json(&json!({ "query": "{ __typename }" }))
+                } else {
+                    test::TestRequest::get()
+                }
+                .uri(&uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                assert_eq!(resp.status(), StatusCode::OK);
+                resp.headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap().to_owned())
+            })
+        }
 
-    drop(srs);
-    // Wait for all the async `Drop`s to proceed well.
-    teamspeak::finish_all_disconnects().await;
+        #[test]
+        fn compresses_api_responses() {
+            assert_eq!(content_encoding("/api", true).as_deref(), Some("gzip"));
+        }
 
-    Ok(())
+        #[test]
+        fn skips_media_responses() {
+            assert_eq!(content_encoding("/preview/{id}.jpg", true), None);
+        }
+
+        #[test]
+        fn can_be_disabled() {
+            assert_eq!(content_encoding("/api", false), None);
+        }
+    }
 }
 
-/// Client HTTP server responding to client requests.
-pub mod client {
-    use std::time::Duration;
+/// Callback HTTP server responding to [SRS] HTTP callbacks.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub mod callback {
+    use std::{
+        collections::HashMap,
+        fs,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+        os::unix::net::UnixListener,
+        sync::{Arc, Mutex, PoisonError},
+        time::{Duration, Instant},
+    };
 
-    use actix_service::Service as _;
     use actix_web::{
-        dev::ServiceRequest, get, middleware, route, web, App, Error,
-        HttpRequest, HttpResponse, HttpServer,
-    };
-    use actix_web_httpauth::extractors::{
-        basic::{self, BasicAuth},
-        AuthExtractor as _, AuthExtractorConfig, AuthenticationError,
-    };
-    use actix_web_static_files::ResourceFiles;
-    use ephyr_log::log;
-    use futures::{future, FutureExt as _};
-    use juniper::http::playground::playground_source;
-    use juniper_actix::{
-        graphql_handler, subscriptions::subscriptions_handler,
+        error, http::StatusCode, middleware, web, App, Error, HttpRequest,
+        HttpServer, Resource, ResponseError,
     };
-    use juniper_graphql_ws::ConnectionConfig;
+    use derive_more::Display;
+    use ephyr_log::{log, slog::Level};
+    use rand::Rng as _;
+    use tokio::time;
 
     use crate::{
-        api,
+        api::{graphql::DateTime, srs::callback},
         cli::{Failure, Opts},
-        State,
+        fault,
+        state::{
+            ConnectionEvent, ConnectionEventKind, Input, InputEndpointKind,
+            InputSrc, PublisherPolicy, PublishingSession, Restream, State,
+            Status,
+        },
+        trace,
     };
 
-    pub mod public_dir {
-        #![allow(clippy::must_use_candidate, unused_results)]
-        #![doc(hidden)]
-
-        include!(concat!(env!("OUT_DIR"), "/generated.rs"));
-    }
+    use super::Shutdown;
 
-    /// Runs client HTTP server.
-    ///
-    /// Client HTTP server serves [`api::graphql::client`] on `/` endpoint.
-    ///
-    /// # Playground
-    ///
-    /// If [`cli::Opts::debug`] is specified then additionally serves
-    /// [GraphQL Playground][2] on `/api/playground` endpoint with no
-    /// authorization required.
+    /// Path of the endpoint serving [SRS] HTTP callbacks on the client HTTP
+    /// server in [`Opts::single_port`] mode, followed by a [`Token`].
     ///
-    /// # Errors
-    ///
-    /// If [`HttpServer`] cannot run due to already used port, etc.
-    /// The actual error is logged.
+    /// [SRS]: https://github.com/ossrs/srs
+    pub const SINGLE_PORT_PATH: &str = "/__srs_callback";
+
+    /// Secret token authorizing [SRS] HTTP callbacks served on the client HTTP
+    /// server in [`Opts::single_port`] mode, instead of Basic authorization.
     ///
-    /// [`cli::Opts::debug`]: crate::cli::Opts::debug
-    /// [2]: https://github.com/graphql/graphql-playground
-    pub async fn run(cfg: &Opts, state: State) -> Result<(), Failure> {
-        let in_debug_mode = cfg.debug;
+    /// [SRS]: https://github.com/ossrs/srs
+    #[derive(Clone, Debug)]
+    pub struct Token(String);
 
-        let stored_cfg = cfg.clone();
+    impl Token {
+        /// Generates a new random [`Token`].
+        #[must_use]
+        pub fn generate() -> Self {
+            Self(hex::encode(rand::thread_rng().gen::<[u8; 16]>()))
+        }
 
-        Ok(HttpServer::new(move || {
-            let public_dir_files = public_dir::generate();
-            let mut app = App::new()
-                .app_data(stored_cfg.clone())
-                .app_data(state.clone())
-                .app_data(
-                    basic::Config::default().realm("Any login is allowed"),
-                )
-                .data(api::graphql::client::schema())
-                .wrap(middleware::Logger::default())
-                .wrap_fn(|req, srv| match authorize(req) {
-                    Ok(req) => srv.call(req).left_future(),
-                    Err(e) => future::err(e).right_future(),
-                })
-                .service(graphql);
-            if in_debug_mode {
-                app = app.service(playground);
-            }
-            app.service(ResourceFiles::new("/", public_dir_files))
-        })
-        .bind((cfg.client_http_ip, cfg.client_http_port))
-        .map_err(|e| log::error!("Failed to bind client HTTP server: {}", e))?
-        .run()
-        .await
-        .map_err(|e| log::error!("Failed to run client HTTP server: {}", e))?)
+        /// Returns this [`Token`] as a string.
+        #[inline]
+        #[must_use]
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
     }
 
-    /// Endpoint serving [`api::graphql::client`] directly.
-    ///
-    /// # Errors
+    /// Returns URL of the endpoint serving [SRS] HTTP callbacks according to
+    /// the given [`Opts`], authorized by the given [`Token`] in
+    /// [`Opts::single_port`] mode.
     ///
-    /// If GraphQL operation execution errors or fails.
-    #[route("/api", method = "GET", method = "POST")]
-    async fn graphql(
-        req: HttpRequest,
-        payload: web::Payload,
-        schema: web::Data<api::graphql::client::Schema>,
-    ) -> Result<HttpResponse, Error> {
-        let ctx = api::graphql::Context::new(req.clone());
-        if req.head().upgrade() {
-            let cfg = ConnectionConfig::new(ctx)
-                .with_keep_alive_interval(Duration::from_secs(5));
-            subscriptions_handler(req, payload, schema.into_inner(), cfg).await
-        } else {
-            graphql_handler(&schema, &ctx, req, payload).await
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn url(cfg: &Opts, token: Option<&Token>) -> String {
+        match token.filter(|_| cfg.single_port) {
+            Some(token) => {
+                // SRS runs on the same host, so reaches the client HTTP server
+                // via loopback, unless it's bound to a concrete address.
+                let ip = match cfg.client_http_ip {
+                    IpAddr::V4(ip) if ip.is_unspecified() => {
+                        Ipv4Addr::LOCALHOST.into()
+                    }
+                    IpAddr::V6(ip) if ip.is_unspecified() => {
+                        Ipv6Addr::LOCALHOST.into()
+                    }
+                    ip => ip,
+                };
+                format!(
+                    "http://{}{}/{}",
+                    SocketAddr::from((ip, cfg.client_http_port)),
+                    SINGLE_PORT_PATH,
+                    token.as_str(),
+                )
+            }
+            None => format!("http://127.0.0.1:{}/", cfg.callback_http_port),
         }
     }
 
-    /// Endpoint serving [GraphQL Playground][1] for exploring
-    /// [`api::graphql::client`].
+    /// Returns [`Resource`] serving the whole [HTTP Callback API][1] for [SRS]
+    /// on the given `path`.
     ///
-    /// [1]: https://github.com/graphql/graphql-playground
-    #[get("/api/playground")]
-    async fn playground() -> HttpResponse {
-        // Constructs API URL relatively to the current HTTP request's scheme
-        // and authority.
-        let html = playground_source("__API_URL__", None).replace(
-            "'__API_URL__'",
-            r"document.URL.replace(/\/playground$/, '')",
-        );
-        HttpResponse::Ok()
-            .content_type("text/html; charset=utf-8")
-            .body(html)
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
+    #[must_use]
+    pub fn resource(path: &str) -> Resource {
+        web::resource(path)
+            .app_data(web::PayloadConfig::new(callback::MAX_REQUEST_SIZE))
+            .route(web::post().to(on_callback))
     }
 
-    /// Performs [`HttpRequest`] [Basic authorization][1] as middleware against
-    /// [`State::password_hash`]. Doesn't consider username anyhow.
+    /// Returns [`Resource`] serving the whole [HTTP Callback API][1] for [SRS]
+    /// on the client HTTP server in [`Opts::single_port`] mode.
     ///
-    /// No-op if [`State::password_hash`] is [`None`].
+    /// Requires a [`Token`] to be stored in the application's context.
     ///
-    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
-    fn authorize(req: ServiceRequest) -> Result<ServiceRequest, Error> {
-        let hash = match req
-            .app_data::<State>()
-            .unwrap()
-            .settings
-            .get_cloned()
-            .password_hash
-        {
-            Some(h) => h,
-            None => return Ok(req),
-        };
-
-        let err = || {
-            AuthenticationError::new(
-                req.app_data::<basic::Config>()
-                    .unwrap()
-                    .clone()
-                    .into_inner(),
-            )
-        };
-
-        let auth = BasicAuth::from_service_request(&req).into_inner()?;
-        let pass = auth.password().ok_or_else(err)?;
-        if argon2::verify_encoded(hash.as_str(), pass.as_bytes()) != Ok(true) {
-            return Err(err().into());
-        }
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
+    #[must_use]
+    pub fn single_port_resource() -> Resource {
+        resource(&format!("{}/{{token}}", SINGLE_PORT_PATH))
+    }
 
-        Ok(req)
+    /// Extracts the [`Token`] from the given request `path`, if it addresses
+    /// the [`single_port_resource()`].
+    #[must_use]
+    pub fn single_port_token(path: &str) -> Option<&str> {
+        path.strip_prefix(SINGLE_PORT_PATH)?.strip_prefix('/')
     }
-}
 
-/// Callback HTTP server responding to [SRS] HTTP callbacks.
-///
-/// [SRS]: https://github.com/ossrs/srs
-pub mod callback {
-    use actix_web::{error, middleware, post, web, App, Error, HttpServer};
-    use ephyr_log::log;
+    /// Listener of the callback HTTP server, accepting [SRS] connections.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[derive(Debug)]
+    pub enum Listener {
+        /// Listener on a TCP port (see [`Opts::callback_http_addr()`]).
+        Tcp(TcpListener),
 
-    use crate::{
-        api::srs::callback,
-        cli::{Failure, Opts},
-        state::{Input, InputEndpointKind, InputSrc, State, Status},
-    };
+        /// Listener on a Unix socket (see [`Opts::callback_http_socket`]).
+        Unix(UnixListener),
+    }
 
     /// Runs HTTP server for exposing [SRS] [HTTP Callback API][1] on `/`
     /// endpoint for responding to [SRS] HTTP callbacks.
     ///
+    /// The server is run on the given `listener`, until the given `shutdown`
+    /// signal resolves (if any). The socket of a [`Listener::Unix`] is
+    /// removed once the server stops.
+    ///
     /// # Errors
     ///
     /// If [`HttpServer`] cannot run due to already used port, etc.
@@ -263,86 +3643,436 @@ pub mod callback {
     ///
     /// [SRS]: https://github.com/ossrs/srs
     /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
-    pub async fn run(cfg: &Opts, state: State) -> Result<(), Failure> {
-        Ok(HttpServer::new(move || {
+    pub async fn run(
+        cfg: &Opts,
+        listener: Listener,
+        state: State,
+        shutdown: Option<Shutdown>,
+    ) -> Result<(), Failure> {
+        let mut server = HttpServer::new(move || {
             App::new()
-                .data(state.clone())
+                .app_data(state.clone())
                 .wrap(middleware::Logger::default())
-                .service(on_callback)
+                .service(resource("/"))
         })
-        .bind((cfg.callback_http_ip, cfg.callback_http_port))
+        .workers(cfg.http_workers_count());
+        if shutdown.is_some() {
+            server = server.disable_signals();
+        }
+        let socket = match &listener {
+            Listener::Unix(l) => l
+                .local_addr()
+                .ok()
+                .and_then(|a| a.as_pathname().map(ToOwned::to_owned)),
+            Listener::Tcp(_) => None,
+        };
+        let server = match listener {
+            Listener::Tcp(l) => server.listen(l),
+            Listener::Unix(l) => server.listen_uds(l),
+        }
         .map_err(|e| log::error!("Failed to bind callback HTTP server: {}", e))?
-        .run()
-        .await
-        .map_err(|e| {
+        .run();
+        if let Some(shutdown) = shutdown {
+            super::stop_on(shutdown, server.clone());
+        }
+        let res = server.await;
+        if let Some(path) = socket {
+            let _ = fs::remove_file(path);
+        }
+        Ok(res.map_err(|e| {
             log::error!("Failed to run callback HTTP server: {}", e)
         })?)
     }
 
     /// Endpoint serving the whole [HTTP Callback API][1] for [SRS].
     ///
+    /// Unknown [SRS] events are acknowledged without any reaction.
+    ///
     /// # Errors
     ///
-    /// If [SRS] HTTP callback doesn't succeed.
+    /// - If a [`Token`] is stored in the application's context, but the
+    ///   request doesn't carry it.
+    /// - If [SRS] HTTP callback request cannot be parsed.
+    /// - If [SRS] HTTP callback doesn't succeed.
     ///
     /// [SRS]: https://github.com/ossrs/srs
     /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
-    #[post("/")]
     async fn on_callback(
-        req: web::Json<callback::Request>,
-        state: web::Data<State>,
+        req: HttpRequest,
+        body: web::Bytes,
     ) -> Result<&'static str, Error> {
-        match req.action {
-            callback::Event::OnConnect => on_connect(&req, &*state),
-            callback::Event::OnPublish => on_start(&req, &*state, true),
-            callback::Event::OnUnpublish => on_stop(&req, &*state, true),
-            callback::Event::OnPlay => on_start(&req, &*state, false),
-            callback::Event::OnStop => on_stop(&req, &*state, false),
-            callback::Event::OnHls => on_hls(&req, &*state),
+        if let Some(token) = req.app_data::<Token>() {
+            if req.match_info().get("token") != Some(token.as_str()) {
+                return Err(error::ErrorForbidden("Invalid callback token"));
+            }
+        }
+        let state = req.app_data::<State>().unwrap();
+
+        // Rehearses slow processing of callbacks (see `fault` module).
+        if let Some(delay) = fault::callbacks_delay(state) {
+            time::delay_for(delay).await;
+        }
+
+        let req = match serde_json::from_slice::<callback::Request>(&body) {
+            Ok(req) => req,
+            // Payloads of events this application doesn't react onto differ
+            // between SRS versions too much, so are acknowledged as is.
+            Err(_)
+                if callback::Event::parse(&body)
+                    == Some(callback::Event::Unknown) =>
+            {
+                return Ok("0");
+            }
+            Err(e) => {
+                log::debug!(
+                    "Failed to parse SRS callback request: {}\n{}",
+                    e,
+                    String::from_utf8_lossy(&body),
+                );
+                return Err(error::ErrorBadRequest(e));
+            }
+        };
+        let rejectable = matches!(
+            req.action,
+            callback::Event::OnConnect | callback::Event::OnPublish,
+        );
+        if rejectable {
+            if let Some(r) =
+                state.callback_rejections.check(&req.app, Instant::now())
+            {
+                return Err(r.into());
+            }
+        }
+        trace::callback(req.action)
+            .in_scope(|| match req.action {
+                callback::Event::OnConnect => on_connect(&req, &*state),
+                callback::Event::OnPublish => on_start(&req, &*state, true),
+                callback::Event::OnUnpublish => on_stop(&req, &*state, true),
+                callback::Event::OnPlay => on_start(&req, &*state, false),
+                callback::Event::OnStop => on_stop(&req, &*state, false),
+                callback::Event::OnHls => on_hls(&req, &*state),
+                callback::Event::Unknown => Ok(()),
+            })
+            .map_err(|e| {
+                if let Some(r) = rejectable.then(|| Rejection::of(&e)).flatten()
+                {
+                    state.callback_rejections.record(
+                        &req.app,
+                        r,
+                        Instant::now(),
+                    );
+                }
+                e
+            })
+            .map(|_| "0")
+    }
+
+    /// Rejection of [SRS] HTTP callbacks caused by their `app` as a whole,
+    /// regardless of the client.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+    pub enum Rejection {
+        /// There is no [`Restream`] with such `app` key.
+        #[display(fmt = "Such `app` doesn't exist")]
+        UnknownApp,
+
+        /// [`Restream`] with such `app` key has its [`Input`] disabled.
+        #[display(fmt = "Such `app` is disabled")]
+        DisabledApp,
+    }
+
+    impl Rejection {
+        /// Recognizes the [`Rejection`] in the given error returned by a
+        /// callback handler, if it's caused by the `app` as a whole.
+        #[inline]
+        #[must_use]
+        pub fn of(e: &Error) -> Option<Self> {
+            e.as_error::<Self>().copied()
+        }
+    }
+
+    impl ResponseError for Rejection {
+        fn status_code(&self) -> StatusCode {
+            match self {
+                Self::UnknownApp => StatusCode::NOT_FOUND,
+                Self::DisabledApp => StatusCode::FORBIDDEN,
+            }
+        }
+    }
+
+    /// Negative cache of [`Rejection`]s of [SRS] HTTP callbacks.
+    ///
+    /// Misconfigured clients may retry connecting to a disabled or unknown
+    /// `app` dozens of times per second. Once an `app` is rejected
+    /// [`Rejections::MAX_REJECTIONS`] times within [`Rejections::WINDOW`],
+    /// its callbacks are rejected right away for [`Rejections::COOLDOWN`],
+    /// without looking into the [`State::restreams`] and logging every
+    /// attempt.
+    ///
+    /// Is shared via [`State::callback_rejections`], so clones of
+    /// [`Rejections`] refer to the same cache.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[derive(Clone, Debug, Default)]
+    pub struct Rejections(Arc<Mutex<HashMap<String, RejectionsEntry>>>);
+
+    /// Rejections history of a single `app` in the [`Rejections`] cache.
+    #[derive(Clone, Copy, Debug)]
+    struct RejectionsEntry {
+        /// The most recent [`Rejection`] of the `app`.
+        rejection: Rejection,
+
+        /// Moment the current counting window has started at.
+        since: Instant,
+
+        /// Number of [`Rejection`]s within the current counting window.
+        count: u32,
+
+        /// Moment the cooldown of the `app` ends at, if it's cooling down.
+        cooldown_until: Option<Instant>,
+
+        /// Number of attempts rejected from the cache during the cooldown.
+        suppressed: u32,
+    }
+
+    impl Rejections {
+        /// Number of [`Rejection`]s within [`Rejections::WINDOW`] triggering
+        /// the [`Rejections::COOLDOWN`].
+        pub const MAX_REJECTIONS: u32 = 10;
+
+        /// Duration of the window counting [`Rejection`]s of an `app`.
+        pub const WINDOW: Duration = Duration::from_secs(5);
+
+        /// Duration of rejecting callbacks of an `app` right away.
+        pub const COOLDOWN: Duration = Duration::from_secs(30);
+
+        /// Maximum number of `app`s tracked at once, so clients trying random
+        /// `app`s cannot exhaust memory.
+        const MAX_ENTRIES: usize = 1024;
+
+        /// Returns the cached [`Rejection`] of the given `app`, if it's cooling
+        /// down at the given moment.
+        pub fn check(&self, app: &str, now: Instant) -> Option<Rejection> {
+            let mut entries =
+                self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            let entry = entries.get_mut(app)?;
+            match entry.cooldown_until {
+                Some(until) if now < until => {
+                    entry.suppressed += 1;
+                    Some(entry.rejection)
+                }
+                Some(_) => {
+                    if entry.suppressed > 0 {
+                        log::warn!(
+                            "Rejected {} more callbacks of `{}` app during \
+                             cooldown",
+                            entry.suppressed,
+                            app,
+                        );
+                    }
+                    let _ = entries.remove(app);
+                    None
+                }
+                None => None,
+            }
+        }
+
+        /// Records the given [`Rejection`] of the given `app` happened at the
+        /// given moment, starting its cooldown once there are too many of
+        /// them.
+        pub fn record(&self, app: &str, rejection: Rejection, now: Instant) {
+            let mut entries =
+                self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            if entries.len() >= Self::MAX_ENTRIES && !entries.contains_key(app)
+            {
+                entries.retain(|_, e| {
+                    now.saturating_duration_since(e.since) < Self::WINDOW
+                        || e.cooldown_until.map_or(false, |u| now < u)
+                });
+                if entries.len() >= Self::MAX_ENTRIES {
+                    return;
+                }
+            }
+
+            let entry =
+                entries.entry(app.to_owned()).or_insert(RejectionsEntry {
+                    rejection,
+                    since: now,
+                    count: 0,
+                    cooldown_until: None,
+                    suppressed: 0,
+                });
+            if now.saturating_duration_since(entry.since) >= Self::WINDOW {
+                entry.since = now;
+                entry.count = 0;
+            }
+            entry.rejection = rejection;
+            entry.count += 1;
+            if entry.count >= Self::MAX_REJECTIONS
+                && entry.cooldown_until.is_none()
+            {
+                entry.cooldown_until = Some(now + Self::COOLDOWN);
+                log::warn!(
+                    "Rejecting callbacks of `{}` app for {:?} right away, as \
+                     it's rejected {} times within {:?}",
+                    app,
+                    Self::COOLDOWN,
+                    entry.count,
+                    Self::WINDOW,
+                );
+            }
+        }
+
+        /// Forgets all the [`Rejection`]s of `app`s which are not rejected by
+        /// the given `restreams` anymore (being created or enabled), so their
+        /// clients may connect right away.
+        pub fn invalidate(&self, restreams: &[Restream]) {
+            let mut entries =
+                self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            if entries.is_empty() {
+                return;
+            }
+            entries.retain(|app, e| {
+                match restreams.iter().find(|r| r.key == **app) {
+                    None => e.rejection == Rejection::UnknownApp,
+                    Some(r) => !r.input.enabled && !r.queue_publishers,
+                }
+            });
         }
-        .map(|_| "0")
     }
 
     /// Handles [`callback::Event::OnConnect`].
     ///
     /// Only checks whether the appropriate [`state::Restream`] exists and its
-    /// [`Input`] is enabled.
+    /// [`Input`] is enabled (or [`state::Restream::queue_publishers`] is set).
+    /// The result is recorded into the [`State::connection_history`].
     ///
     /// # Errors
     ///
-    /// If [`callback::Request::app`] matches no existing [`state::Restream`].
+    /// - If [`callback::Request::app`] matches no existing
+    ///   [`state::Restream`].
+    /// - If the matched [`state::Restream`] has its [`Input`] disabled, and
+    ///   doesn't queue publishers.
+    /// - If the server is draining and the matched [`state::Restream`] doesn't
+    ///   serve a live stream already.
     ///
     /// [`state::Restream`]: crate::state::Restream
+    /// [`state::Restream::queue_publishers`]:
+    ///     crate::state::Restream::queue_publishers
     fn on_connect(req: &callback::Request, state: &State) -> Result<(), Error> {
-        state
-            .restreams
-            .get_cloned()
+        let restreams = state.restreams.lock_ref();
+        let restream = restreams
             .iter()
-            .find(|r| r.input.enabled && r.key == *req.app)
-            .ok_or_else(|| error::ErrorNotFound("Such `app` doesn't exist"))
-            .map(|_| ())
+            .find(|r| r.key == *req.app)
+            .ok_or(Rejection::UnknownApp)?;
+
+        let res = if !restream.input.enabled && !restream.queue_publishers {
+            log_rejection(
+                req,
+                state,
+                "connect disabled",
+                format!(
+                    "Rejected connection of {} client to disabled `{}` app",
+                    req.ip, req.app,
+                ),
+            );
+            Err(Rejection::DisabledApp.into())
+        } else if state.draining.get()
+            && !restream.input.is_ready_to_serve(&state.statuses.lock_ref())
+        {
+            log_rejection(
+                req,
+                state,
+                "connect draining",
+                format!(
+                    "Rejected connection of {} client to idle `{}` app while \
+                     draining",
+                    req.ip, req.app,
+                ),
+            );
+            Err(error::ErrorServiceUnavailable("Server is draining"))
+        } else {
+            Ok(())
+        };
+        if res.is_ok() {
+            flush_rejections(&req.app, state);
+        }
+        record_connection(
+            state,
+            restream,
+            ConnectionEventKind::Connect,
+            req,
+            &res,
+        );
+        res
+    }
+
+    /// Handles [`callback::Event::OnPublish`] and [`callback::Event::OnPlay`].
+    ///
+    /// Updates the appropriate [`state::Restream`]'s [`InputEndpoint`] to
+    /// [`Status::Online`] (if [`callback::Event::OnPublish`]) and remembers the
+    /// connected [SRS] client.
+    ///
+    /// The result of [`callback::Event::OnPublish`] is recorded into the
+    /// [`State::connection_history`].
+    ///
+    /// # Errors
+    ///
+    /// - If [`callback::Request::vhost`], [`callback::Request::app`] or
+    ///   [`callback::Request::stream`] matches no existing enabled
+    ///   [`InputEndpoint`].
+    /// - If the matched [`state::Restream`] has its [`Input`] disabled.
+    /// - If [`InputEndpoint`] is not allowed to be published by external
+    ///   client.
+    /// - If [`callback::Event::OnPublish`] occurs from an IP address not
+    ///   allowed by [`state::Restream::publish_allowlist`].
+    /// - If the server is draining and [`callback::Event::OnPublish`] occurs.
+    ///
+    /// [`InputEndpoint`]: crate::state::InputEndpoint
+    /// [`state::Restream`]: crate::state::Restream
+    /// [`state::Restream::publish_allowlist`]:
+    ///     crate::state::Restream::publish_allowlist
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn on_start(
+        req: &callback::Request,
+        state: &State,
+        publishing: bool,
+    ) -> Result<(), Error> {
+        let mut restreams = trace::lock_mut(&state.restreams, "on_start");
+        let restream = restreams
+            .iter_mut()
+            .find(|r| r.key == *req.app)
+            .ok_or(Rejection::UnknownApp)?;
+
+        let res = start(req, restream, state, publishing);
+        if res.is_ok() {
+            flush_rejections(&req.app, state);
+        }
+        if publishing {
+            record_connection(
+                state,
+                restream,
+                ConnectionEventKind::Publish,
+                req,
+                &res,
+            );
+        }
+        res
     }
 
-    /// Handles [`callback::Event::OnPublish`] and [`callback::Event::OnPlay`].
-    ///
-    /// Updates the appropriate [`state::Restream`]'s [`InputEndpoint`] to
-    /// [`Status::Online`] (if [`callback::Event::OnPublish`]) and remembers the
-    /// connected [SRS] client.
+    /// Starts publishing or playing the [`state::Restream`] matched by the
+    /// [`callback::Event::OnPublish`] or [`callback::Event::OnPlay`].
     ///
     /// # Errors
     ///
-    /// - If [`callback::Request::vhost`], [`callback::Request::app`] or
-    ///   [`callback::Request::stream`] matches no existing enabled
-    ///   [`InputEndpoint`].
-    /// - If [`InputEndpoint`] is not allowed to be published by external
-    ///   client.
+    /// See [`on_start()`].
     ///
-    /// [`InputEndpoint`]: crate::state::InputEndpoint
     /// [`state::Restream`]: crate::state::Restream
-    ///
-    /// [SRS]: https://github.com/ossrs/srs
-    fn on_start(
+    fn start(
         req: &callback::Request,
+        restream: &mut Restream,
         state: &State,
         publishing: bool,
     ) -> Result<(), Error> {
@@ -364,16 +4094,57 @@ pub mod callback {
         }
 
         let stream = req.stream.as_deref().unwrap_or_default();
+        // Low-latency `Restream`s are served on their dedicated vhost only, so
+        // the clients using a wrong one are rejected rather than left with
+        // nothing to play.
+        let rtmp_vhost = restream.srs_vhost();
         let kind = match req.vhost.as_str() {
             "hls" => InputEndpointKind::Hls,
-            _ => InputEndpointKind::Rtmp,
+            v if v == rtmp_vhost.as_deref().unwrap_or("__defaultVhost__") => {
+                InputEndpointKind::Rtmp
+            }
+            _ => {
+                return Err(error::ErrorForbidden(
+                    "Such `vhost` is not allowed",
+                ))
+            }
         };
 
-        let mut restreams = state.restreams.lock_mut();
-        let restream = restreams
-            .iter_mut()
-            .find(|r| r.input.enabled && r.key == *req.app)
-            .ok_or_else(|| error::ErrorNotFound("Such `app` doesn't exist"))?;
+        let policy = restream.publisher_policy;
+
+        if !restream.input.enabled {
+            log_rejection(
+                req,
+                state,
+                "start disabled",
+                format!(
+                    "Rejected {} of {} client to disabled `{}` app",
+                    if publishing { "publishing" } else { "playing" },
+                    req.ip,
+                    req.app,
+                ),
+            );
+            return Err(Rejection::DisabledApp.into());
+        }
+
+        if publishing
+            && !req.ip.is_loopback()
+            && !restream.allows_publishing_from(req.ip)
+        {
+            log_rejection(
+                req,
+                state,
+                "publish allowlist",
+                format!(
+                    "Rejected publishing of {} client to `{}` app not allowed \
+                     by its allowlist",
+                    req.ip, req.app,
+                ),
+            );
+            return Err(error::ErrorForbidden(
+                "Publishing from such IP is not allowed",
+            ));
+        }
 
         let input =
             lookup_input(&mut restream.input, stream).ok_or_else(|| {
@@ -397,13 +4168,82 @@ pub mod callback {
                 ));
             }
 
-            if endpoint.srs_publisher_id.as_ref().map(|id| **id)
-                != Some(req.client_id)
-            {
+            if fault::is_input_offline(state, input.id) {
+                log_rejection(
+                    req,
+                    state,
+                    "publish fault",
+                    format!(
+                        "Rejected publishing of {} client to `{}` app forced \
+                         offline by injected fault",
+                        req.ip, req.app,
+                    ),
+                );
+                return Err(error::ErrorServiceUnavailable(
+                    "Such `stream` is forced offline",
+                ));
+            }
+
+            if state.draining.get() {
+                log_rejection(
+                    req,
+                    state,
+                    "publish draining",
+                    format!(
+                        "Rejected publishing of {} client to `{}` app while \
+                         draining",
+                        req.ip, req.app,
+                    ),
+                );
+                return Err(error::ErrorServiceUnavailable(
+                    "Server is draining",
+                ));
+            }
+
+            let publisher_id =
+                endpoint.srs_publisher_id.as_ref().map(|id| **id);
+            if publisher_id != Some(req.client_id) {
+                // Local clients are our own re-streaming processes, which are
+                // always allowed to replace their previous runs.
+                let is_live = publisher_id.is_some()
+                    && state.statuses.get(endpoint.id).is_online();
+                if is_live && !req.ip.is_loopback() {
+                    if policy == PublisherPolicy::RejectNew {
+                        log_rejection(
+                            req,
+                            state,
+                            "publish live",
+                            format!(
+                                "Rejected publishing of {} client to already \
+                                 live `{}` app",
+                                req.ip, req.app,
+                            ),
+                        );
+                        return Err(error::ErrorForbidden(
+                            "Such `stream` is already live",
+                        ));
+                    }
+                    log::info!(
+                        "Replaced publisher of already live `{}` app with {} \
+                         client",
+                        req.app,
+                        req.ip,
+                    );
+                }
+                // Previous publisher is kicked once its `srs::ClientId` is
+                // dropped here.
                 endpoint.srs_publisher_id = Some(req.client_id.into());
+                // Re-publishing always starts a fresh session.
+                endpoint.publishing_session = (!req.ip.is_loopback())
+                    .then(|| PublishingSession::new(req.ip));
             }
 
-            endpoint.status = Status::Online;
+            // `Status::Online` is always committed right away, while renewing
+            // it cancels any postponed `Status::Offline` of the endpoint.
+            let _ = state.renew_status(endpoint.id, Status::Online, |_| ());
+            let _ = state.statuses.set(endpoint.id, Status::Online);
+            endpoint.offline_reason = None;
+            input.problem = None;
         } else {
             // `srs::ClientId` kicks the client when `Drop`ped, so we should be
             // careful here to not accidentally kick the client by creating a
@@ -420,6 +4260,9 @@ pub mod callback {
     /// Updates the appropriate [`state::Restream`]'s [`InputEndpoint`] to
     /// [`Status::Offline`].
     ///
+    /// The result of [`callback::Event::OnUnpublish`] is recorded into the
+    /// [`State::connection_history`].
+    ///
     /// # Errors
     ///
     /// If [`callback::Request::vhost`], [`callback::Request::app`] or
@@ -431,6 +4274,39 @@ pub mod callback {
         req: &callback::Request,
         state: &State,
         publishing: bool,
+    ) -> Result<(), Error> {
+        let mut restreams = trace::lock_mut(&state.restreams, "on_stop");
+        let restream = restreams
+            .iter_mut()
+            .find(|r| r.key == *req.app)
+            .ok_or(Rejection::UnknownApp)?;
+
+        let res = stop(req, restream, state, publishing);
+        if publishing {
+            record_connection(
+                state,
+                restream,
+                ConnectionEventKind::Unpublish,
+                req,
+                &res,
+            );
+        }
+        res
+    }
+
+    /// Stops publishing or playing the [`state::Restream`] matched by the
+    /// [`callback::Event::OnUnpublish`] or [`callback::Event::OnStop`].
+    ///
+    /// # Errors
+    ///
+    /// See [`on_stop()`].
+    ///
+    /// [`state::Restream`]: crate::state::Restream
+    fn stop(
+        req: &callback::Request,
+        restream: &mut Restream,
+        state: &State,
+        publishing: bool,
     ) -> Result<(), Error> {
         /// Traverses the given [`Input`] and all its [`Input::srcs`] looking
         /// for the one matching the specified `stream`.
@@ -455,12 +4331,6 @@ pub mod callback {
             _ => InputEndpointKind::Rtmp,
         };
 
-        let mut restreams = state.restreams.lock_mut();
-        let restream = restreams
-            .iter_mut()
-            .find(|r| r.key == *req.app)
-            .ok_or_else(|| error::ErrorNotFound("Such `app` doesn't exist"))?;
-
         let input =
             lookup_input(&mut restream.input, stream).ok_or_else(|| {
                 error::ErrorNotFound("Such `stream` doesn't exist")
@@ -476,24 +4346,105 @@ pub mod callback {
 
         if publishing {
             endpoint.srs_publisher_id = None;
-            endpoint.status = Status::Offline;
+            endpoint.publishing_session = None;
+
+            let id = endpoint.id;
+            let commit = move |actual: &State| {
+                let restreams = actual.restreams.lock_ref();
+                let unpublished = restreams
+                    .iter()
+                    .find_map(|r| r.input.find_by_endpoint(id))
+                    .and_then(|i| i.endpoints.iter().find(|e| e.id == id))
+                    // Re-published meanwhile, so is not offline anymore.
+                    .map_or(false, |e| e.srs_publisher_id.is_none());
+                if unpublished {
+                    let _ = actual.statuses.set(id, Status::Offline);
+                }
+            };
+            // The `restream` is locked already, so the `commit` cannot be
+            // called right away, while the endpoint is unpublished above.
+            if state.renew_status(id, Status::Offline, commit).is_some() {
+                let _ = state.statuses.set(id, Status::Offline);
+            }
         } else {
             let _ = endpoint.srs_player_ids.remove(&req.client_id);
         }
         Ok(())
     }
 
+    /// Records the given `res`ult of handling the [SRS] HTTP callback `req`
+    /// as a [`ConnectionEvent`] of the given `kind` into the
+    /// [`State::connection_history`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn record_connection(
+        state: &State,
+        restream: &Restream,
+        kind: ConnectionEventKind,
+        req: &callback::Request,
+        res: &Result<(), Error>,
+    ) {
+        state.connection_history.record(
+            restream.id,
+            ConnectionEvent::new(
+                kind,
+                req.ip,
+                req.stream.as_deref(),
+                res.as_ref().err().map(ToString::to_string),
+            ),
+        );
+    }
+
+    /// Logs the given `message` about rejecting the [SRS] HTTP callback `req`
+    /// for the given `reason`, coalescing the ones repeated by the same client
+    /// (see [`State::log_coalescer`]).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    fn log_rejection(
+        req: &callback::Request,
+        state: &State,
+        reason: &str,
+        message: String,
+    ) {
+        if let Some(msg) = state.log_coalescer.record(
+            &format!("`{}` app", req.app),
+            &format!("{} {}", reason, req.ip),
+            Level::Info,
+            message,
+            Instant::now(),
+        ) {
+            log::info!("{}", msg);
+        }
+    }
+
+    /// Logs the rejections of the given `app`, which have been coalesced but
+    /// not emitted yet, once it accepts a client.
+    fn flush_rejections(app: &str, state: &State) {
+        let scope = format!("`{}` app", app);
+        for msg in state.log_coalescer.flush(&scope, Instant::now()) {
+            log::info!("{}", msg);
+        }
+    }
+
     /// Handles [`callback::Event::OnHls`].
     ///
     /// Checks whether the appropriate [`state::Restream`] with an
     /// [`InputEndpointKind::Hls`] exists and its [`Input`] is enabled.
     ///
+    /// For a [`state::Restream`] served via [HLS] by [SRS] itself on its
+    /// dedicated vhost (see [`state::Restream::hls`]), the callback signals a
+    /// new segment being produced, so it's just remembered as
+    /// [`state::Restream::hls_segment_at`].
+    ///
     /// # Errors
     ///
     /// If [`callback::Request::vhost`], [`callback::Request::app`] or
     /// [`callback::Request::stream`] matches no existing [`InputEndpoint`]
-    /// of [`InputEndpointKind::Hls`].
+    /// of [`InputEndpointKind::Hls`], nor a [`state::Restream`] served via
+    /// [HLS].
     ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
     /// [`InputEndpoint`]: crate::state::InputEndpoint
     /// [`state::Restream`]: crate::state::Restream
     fn on_hls(req: &callback::Request, state: &State) -> Result<(), Error> {
@@ -514,6 +4465,22 @@ pub mod callback {
             }
         }
 
+        if req.vhost.as_str() != "hls" {
+            let mut restreams = trace::lock_mut(&state.restreams, "on_hls");
+            let restream = restreams
+                .iter_mut()
+                .find(|r| {
+                    r.key == *req.app
+                        && r.hls
+                        && r.srs_vhost().as_deref() == Some(req.vhost.as_str())
+                })
+                .ok_or_else(|| {
+                    error::ErrorForbidden("Such `vhost` is not allowed")
+                })?;
+            restream.hls_segment_at = Some(DateTime::now());
+            return Ok(());
+        }
+
         let stream = req.stream.as_deref().unwrap_or_default();
         let kind = (req.vhost.as_str() == "hls")
             .then(|| InputEndpointKind::Hls)
@@ -521,11 +4488,11 @@ pub mod callback {
                 error::ErrorForbidden("Such `vhost` is not allowed")
             })?;
 
-        let mut restreams = state.restreams.lock_mut();
+        let mut restreams = trace::lock_mut(&state.restreams, "on_hls");
         let restream = restreams
             .iter_mut()
             .find(|r| r.input.enabled && r.key == *req.app)
-            .ok_or_else(|| error::ErrorNotFound("Such `app` doesn't exist"))?;
+            .ok_or(Rejection::UnknownApp)?;
 
         let endpoint = lookup_input(&mut restream.input, stream)
             .ok_or_else(|| error::ErrorNotFound("Such `stream` doesn't exist"))?
@@ -536,7 +4503,7 @@ pub mod callback {
                 error::ErrorNotFound("Such `stream` doesn't exist")
             })?;
 
-        if endpoint.status != Status::Online {
+        if !state.statuses.get(endpoint.id).is_online() {
             return Err(error::ErrorImATeapot("Not ready to serve"));
         }
 
@@ -548,6 +4515,627 @@ pub mod callback {
         }
         Ok(())
     }
+
+    #[cfg(test)]
+    mod rejections_spec {
+        use std::time::Instant;
+
+        use serde_json::json;
+
+        use crate::{spec, state::State};
+
+        use super::{error, Error, Rejection, Rejections};
+
+        fn reject_many(
+            cache: &Rejections,
+            app: &str,
+            rejection: Rejection,
+            at: Instant,
+        ) {
+            for _ in 0..Rejections::MAX_REJECTIONS {
+                assert_eq!(cache.check(app, at), None);
+                cache.record(app, rejection, at);
+            }
+        }
+
+        #[test]
+        fn recognizes_only_rejections() {
+            for r in &[Rejection::UnknownApp, Rejection::DisabledApp] {
+                let e: Error = (*r).into();
+                assert_eq!(Rejection::of(&e), Some(*r));
+            }
+
+            let e = error::ErrorNotFound(Rejection::UnknownApp.to_string());
+            assert_eq!(Rejection::of(&e), None);
+        }
+
+        #[test]
+        fn cools_down_after_repeated_rejections() {
+            let cache = Rejections::default();
+            let start = Instant::now();
+
+            reject_many(&cache, "main", Rejection::DisabledApp, start);
+            assert_eq!(
+                cache.check("main", start),
+                Some(Rejection::DisabledApp)
+            );
+            assert_eq!(cache.check("other", start), None);
+
+            let after = start + Rejections::COOLDOWN;
+            assert_eq!(cache.check("main", after), None);
+            // Counting starts over after the cooldown.
+            cache.record("main", Rejection::DisabledApp, after);
+            assert_eq!(cache.check("main", after), None);
+        }
+
+        #[test]
+        fn counts_rejections_within_window_only() {
+            let cache = Rejections::default();
+            let start = Instant::now();
+
+            for n in 0..Rejections::MAX_REJECTIONS * 2 {
+                let at = start + Rejections::WINDOW / 2 * n;
+                cache.record("main", Rejection::UnknownApp, at);
+                assert_eq!(cache.check("main", at), None);
+            }
+        }
+
+        #[test]
+        fn invalidates_created_and_enabled_apps() {
+            let cache = Rejections::default();
+            let start = Instant::now();
+            reject_many(&cache, "main", Rejection::DisabledApp, start);
+            reject_many(&cache, "created", Rejection::UnknownApp, start);
+            reject_many(&cache, "unknown", Rejection::UnknownApp, start);
+
+            let state = State::new_in_memory();
+            for (key, enabled) in &[("main", false), ("created", true)] {
+                state
+                    .add_restream(
+                        serde_json::from_value::<spec::v1::Restream>(json!({
+                            "key": key,
+                            "input": {
+                                "key": "origin",
+                                "endpoints": [{"kind": "rtmp"}],
+                                "enabled": enabled,
+                            },
+                        }))
+                        .unwrap(),
+                    )
+                    .unwrap();
+            }
+
+            cache.invalidate(&state.restreams.lock_ref());
+            assert_eq!(
+                cache.check("main", start),
+                Some(Rejection::DisabledApp)
+            );
+            assert_eq!(cache.check("created", start), None);
+            assert_eq!(
+                cache.check("unknown", start),
+                Some(Rejection::UnknownApp),
+            );
+
+            for r in state.restreams.lock_mut().iter_mut() {
+                r.input.enabled = true;
+            }
+            cache.invalidate(&state.restreams.lock_ref());
+            assert_eq!(cache.check("main", start), None);
+        }
+    }
+
+    #[cfg(test)]
+    mod socket_spec {
+        use std::{
+            env, fs,
+            io::{Read as _, Write as _},
+            os::unix::{fs::PermissionsExt as _, net::UnixStream},
+            path::PathBuf,
+            thread,
+        };
+
+        use actix_web::{rt::System, App, HttpServer};
+        use uuid::Uuid;
+
+        use crate::state::State;
+
+        use super::{super::bind_unix, resource};
+
+        fn temp_path() -> PathBuf {
+            env::temp_dir()
+                .join(format!("ephyr-callback-{}.sock", Uuid::new_v4()))
+        }
+
+        #[test]
+        fn serves_callbacks_over_socket() {
+            let path = temp_path();
+            let listener = bind_unix("callback", &path).unwrap();
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            drop(thread::spawn(move || {
+                System::new("test").block_on(async move {
+                    HttpServer::new(|| {
+                        App::new()
+                            .app_data(State::new_in_memory())
+                            .service(resource("/"))
+                    })
+                    .workers(1)
+                    .disable_signals()
+                    .listen_uds(listener)
+                    .unwrap()
+                    .run()
+                    .await
+                })
+            }));
+
+            let body = r#"{"action":"on_dvr","ip":"127.0.0.1","app":"main"}"#;
+            let mut stream = UnixStream::connect(&path).unwrap();
+            write!(
+                stream,
+                "POST / HTTP/1.1\r\n\
+                 Host: localhost\r\n\
+                 Content-Type: application/json\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\
+                 \r\n\
+                 {}",
+                body.len(),
+                body,
+            )
+            .unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+
+            assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+            assert!(resp.ends_with("\r\n\r\n0"), "{}", resp);
+
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn replaces_stale_socket_only() {
+            let path = temp_path();
+            drop(bind_unix("callback", &path).unwrap());
+            assert!(path.exists());
+            drop(bind_unix("callback", &path).unwrap());
+            fs::remove_file(&path).unwrap();
+
+            fs::write(&path, "data").unwrap();
+            assert!(bind_unix("callback", &path).is_err());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[cfg(test)]
+    mod on_callback_spec {
+        use actix_web::{http::StatusCode, rt::System, test, web::Bytes, App};
+        use serde_json::json;
+
+        use crate::state::State;
+
+        use super::resource;
+
+        fn respond(body: &serde_json::Value) -> (StatusCode, Bytes) {
+            System::new("test").block_on(async {
+                let mut app = test::init_service(
+                    App::new()
+                        .app_data(State::new_in_memory())
+                        .service(resource("/")),
+                )
+                .await;
+                let req = test::TestRequest::post()
+                    .uri("/")
+                    .set_json(body)
+                    .to_request();
+                let resp = test::call_service(&mut app, req).await;
+                (resp.status(), test::read_body(resp).await)
+            })
+        }
+
+        #[test]
+        fn acknowledges_unknown_actions_of_any_shape() {
+            for body in &[
+                json!({"action": "on_forward", "tcUrl": "rtmp://127.0.0.1"}),
+                json!({
+                    "action": "on_dvr",
+                    "client_id": "341w361a",
+                    "ip": "127.0.0.1",
+                    "app": "main",
+                }),
+            ] {
+                assert_eq!(
+                    respond(body),
+                    (StatusCode::OK, Bytes::from("0")),
+                    "{}",
+                    body,
+                );
+            }
+        }
+
+        #[test]
+        fn rejects_malformed_known_actions() {
+            let body = json!({"action": "on_publish", "app": "main"});
+            assert_eq!(respond(&body).0, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[cfg(test)]
+    mod on_connect_spec {
+        use actix_web::http::StatusCode;
+        use serde_json::json;
+
+        use crate::{api::srs::callback, spec, state::State};
+
+        use super::on_connect;
+
+        fn state() -> State {
+            let state = State::new_in_memory();
+            for (key, enabled, queue) in &[
+                ("enabled", true, false),
+                ("disabled", false, false),
+                ("queued", false, true),
+            ] {
+                state
+                    .add_restream(
+                        serde_json::from_value::<spec::v1::Restream>(json!({
+                            "key": key,
+                            "input": {
+                                "key": "origin",
+                                "endpoints": [{"kind": "rtmp"}],
+                                "enabled": enabled,
+                            },
+                            "queue_publishers": queue,
+                        }))
+                        .unwrap(),
+                    )
+                    .unwrap();
+            }
+            state
+        }
+
+        fn connect(app: &str) -> Option<StatusCode> {
+            connect_to(app, &state())
+        }
+
+        fn connect_to(app: &str, state: &State) -> Option<StatusCode> {
+            let req = callback::Request {
+                action: callback::Event::OnConnect,
+                client_id: 1,
+                ip: "127.0.0.1".parse().unwrap(),
+                vhost: "__defaultVhost__".into(),
+                app: app.into(),
+                stream: None,
+            };
+            on_connect(&req, state)
+                .err()
+                .map(|e| e.as_response_error().status_code())
+        }
+
+        #[test]
+        fn accepts_enabled_app() {
+            assert_eq!(connect("enabled"), None);
+        }
+
+        #[test]
+        fn forbids_disabled_app() {
+            assert_eq!(connect("disabled"), Some(StatusCode::FORBIDDEN));
+        }
+
+        #[test]
+        fn accepts_disabled_app_queueing_publishers() {
+            assert_eq!(connect("queued"), None);
+        }
+
+        #[test]
+        fn rejects_unknown_app() {
+            assert_eq!(connect("unknown"), Some(StatusCode::NOT_FOUND));
+        }
+
+        #[test]
+        fn rejects_idle_app_while_draining() {
+            let state = state();
+            assert!(state.set_draining(true));
+            assert_eq!(
+                connect_to("enabled", &state),
+                Some(StatusCode::SERVICE_UNAVAILABLE),
+            );
+
+            assert!(state.set_draining(false));
+            assert_eq!(connect_to("enabled", &state), None);
+        }
+    }
+
+    #[cfg(test)]
+    mod on_start_spec {
+        use actix_web::{http::StatusCode, rt::System};
+        use serde_json::json;
+
+        use crate::{
+            api::srs::callback,
+            state::{State, Status},
+        };
+
+        use super::on_start;
+
+        fn state(policy: &str) -> State {
+            State::with_restream("live", json!({"publisher_policy": policy}))
+        }
+
+        fn publish(
+            state: &State,
+            client_id: u32,
+            ip: &str,
+        ) -> Option<StatusCode> {
+            let req = callback::Request {
+                action: callback::Event::OnPublish,
+                client_id,
+                ip: ip.parse().unwrap(),
+                vhost: "__defaultVhost__".into(),
+                app: "live".into(),
+                stream: Some("origin".into()),
+            };
+            on_start(&req, state, true)
+                .err()
+                .map(|e| e.as_response_error().status_code())
+        }
+
+        fn publisher_id(state: &State) -> Option<u32> {
+            let restreams = state.restreams.lock_ref();
+            let endpoint = &restreams[0].input.endpoints[0];
+            assert_eq!(state.statuses.get(endpoint.id), Status::Online);
+            endpoint.srs_publisher_id.as_ref().map(|id| **id)
+        }
+
+        #[test]
+        fn rejects_concurrent_publisher_by_default() {
+            System::new("test").block_on(async {
+                let state = state("reject_new");
+
+                assert_eq!(publish(&state, 1, "10.0.0.1"), None);
+                assert_eq!(
+                    publish(&state, 2, "10.0.0.2"),
+                    Some(StatusCode::FORBIDDEN),
+                );
+                assert_eq!(publisher_id(&state), Some(1));
+
+                // Duplicate callback of the same client is still accepted.
+                assert_eq!(publish(&state, 1, "10.0.0.1"), None);
+                assert_eq!(publisher_id(&state), Some(1));
+            });
+        }
+
+        #[test]
+        fn replaces_existing_publisher_if_required() {
+            System::new("test").block_on(async {
+                let state = state("replace_existing");
+
+                assert_eq!(publish(&state, 1, "10.0.0.1"), None);
+                assert_eq!(publish(&state, 2, "10.0.0.2"), None);
+                assert_eq!(publisher_id(&state), Some(2));
+            });
+        }
+
+        #[test]
+        fn always_replaces_local_publisher() {
+            System::new("test").block_on(async {
+                let state = state("reject_new");
+
+                assert_eq!(publish(&state, 1, "127.0.0.1"), None);
+                assert_eq!(publish(&state, 2, "127.0.0.1"), None);
+                assert_eq!(publisher_id(&state), Some(2));
+            });
+        }
+
+        #[test]
+        fn rejects_publisher_not_in_allowlist() {
+            System::new("test").block_on(async {
+                let state = state("replace_existing");
+                state.restreams.lock_mut()[0].publish_allowlist =
+                    vec!["10.0.0.0/8".parse().unwrap()];
+
+                assert_eq!(
+                    publish(&state, 1, "192.0.2.1"),
+                    Some(StatusCode::FORBIDDEN),
+                );
+                assert_eq!(publish(&state, 2, "10.0.0.1"), None);
+                assert_eq!(publish(&state, 3, "127.0.0.1"), None);
+                assert_eq!(publisher_id(&state), Some(3));
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod on_hls_spec {
+        use actix_web::http::StatusCode;
+        use serde_json::json;
+
+        use crate::{api::srs::callback, state::State};
+
+        use super::on_hls;
+
+        fn segment(state: &State, vhost: &str) -> Option<StatusCode> {
+            let req = callback::Request {
+                action: callback::Event::OnHls,
+                client_id: 1,
+                ip: "127.0.0.1".parse().unwrap(),
+                vhost: vhost.into(),
+                app: "live".into(),
+                stream: Some("origin".into()),
+            };
+            on_hls(&req, state)
+                .err()
+                .map(|e| e.as_response_error().status_code())
+        }
+
+        #[test]
+        fn tracks_segments_on_dedicated_vhost() {
+            let state = State::with_restream("live", json!({"hls": true}));
+            assert_eq!(state.restreams.lock_ref()[0].hls_segment_at, None);
+
+            assert_eq!(segment(&state, "live.hls"), None);
+            assert!(state.restreams.lock_ref()[0].hls_segment_at.is_some());
+        }
+
+        #[test]
+        fn forbids_other_vhosts() {
+            let state = State::with_restream("live", json!({"hls": true}));
+            assert_eq!(
+                segment(&state, "__defaultVhost__"),
+                Some(StatusCode::FORBIDDEN),
+            );
+
+            state.restreams.lock_mut()[0].hls = false;
+            assert_eq!(
+                segment(&state, "live.hls"),
+                Some(StatusCode::FORBIDDEN),
+            );
+            assert_eq!(state.restreams.lock_ref()[0].hls_segment_at, None);
+        }
+    }
+}
+
+/// Maximum time that detecting public IP address via external resolvers is
+/// allowed to take.
+const PUBLIC_IP_DETECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolves IP address to access this server in public with, according to the
+/// given [`Opts`].
+///
+/// Tries [`detect_public_ip()`] first (unless
+/// [`Opts::no_public_ip_detection`] is set), limiting it with
+/// [`PUBLIC_IP_DETECTION_TIMEOUT`], and falls back to the most sensible
+/// address of local network interfaces (see [`pick_local_ip()`]).
+///
+/// Never blocks indefinitely.
+pub async fn resolve_public_ip(cfg: &Opts) -> Option<IpAddr> {
+    if cfg.no_public_ip_detection {
+        if !cfg.client_http_ip.is_unspecified() {
+            log::info!(
+                "Using client HTTP bind address {} as public host",
+                cfg.client_http_ip,
+            );
+            return Some(cfg.client_http_ip);
+        }
+    } else {
+        // Global-scope IPv6 addresses are not NATed, so the one assigned to a
+        // local network interface is the public one.
+        if cfg.prefer_ipv6 {
+            if let Some(ip) = pick_local_ip(&local_ips().await, true)
+                .filter(|ip| ip.is_ipv6() && is_global(ip))
+            {
+                log::info!(
+                    "Detected public IPv6 address {} of local network \
+                     interface",
+                    ip,
+                );
+                return Some(ip);
+            }
+        }
+        match time::timeout(PUBLIC_IP_DETECTION_TIMEOUT, detect_public_ip())
+            .await
+        {
+            Ok(Some(ip)) => {
+                log::info!("Detected public IP address {} via resolvers", ip);
+                return Some(ip);
+            }
+            Ok(None) => {
+                log::warn!("Failed to detect public IP address via resolvers")
+            }
+            Err(_) => log::warn!(
+                "Detecting public IP address via resolvers timed out after {}s",
+                PUBLIC_IP_DETECTION_TIMEOUT.as_secs(),
+            ),
+        }
+    }
+
+    let ip = pick_local_ip(&local_ips().await, cfg.prefer_ipv6);
+    match ip {
+        Some(ip) => log::info!(
+            "Using IP address {} of local network interface as public host",
+            ip,
+        ),
+        None => log::warn!("Found no usable local network interface address"),
+    }
+    ip
+}
+
+/// Lists IP addresses assigned to local network interfaces.
+///
+/// Reads them from `/proc/net/fib_trie` (IPv4) and `/proc/net/if_inet6`
+/// (IPv6), so returns nothing on non-Linux systems.
+async fn local_ips() -> Vec<IpAddr> {
+    let mut ips = fs::read_to_string("/proc/net/fib_trie")
+        .await
+        .map(|s| parse_fib_trie(&s))
+        .unwrap_or_default();
+    ips.extend(
+        fs::read_to_string("/proc/net/if_inet6")
+            .await
+            .map(|s| parse_if_inet6(&s))
+            .unwrap_or_default(),
+    );
+    ips
+}
+
+/// Parses local IPv4 addresses out of `/proc/net/fib_trie` contents.
+fn parse_fib_trie(contents: &str) -> Vec<IpAddr> {
+    let mut ips = vec![];
+    let mut last = None;
+    for line in contents.lines().map(str::trim) {
+        if let Some(addr) = line.strip_prefix("|-- ") {
+            last = addr.parse::<Ipv4Addr>().ok();
+        } else if line == "/32 host LOCAL" {
+            if let Some(ip) = last.take().map(IpAddr::V4) {
+                if !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+    }
+    ips
+}
+
+/// Parses local IPv6 addresses out of `/proc/net/if_inet6` contents.
+fn parse_if_inet6(contents: &str) -> Vec<IpAddr> {
+    contents
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .filter(|hex| hex.len() == 32)
+        .filter_map(|hex| u128::from_str_radix(hex, 16).ok())
+        .map(|n| IpAddr::V6(Ipv6Addr::from(n)))
+        .collect()
+}
+
+/// Picks the most sensible IP address to access this server with out of the
+/// given local ones.
+///
+/// Loopback, link-local and multicast addresses are never picked. Global-scope
+/// addresses are preferred over private ones, and IPv4 over IPv6 (unless
+/// `prefer_ipv6` is `true`).
+fn pick_local_ip(ips: &[IpAddr], prefer_ipv6: bool) -> Option<IpAddr> {
+    ips.iter()
+        .copied()
+        .filter(|ip| {
+            !ip.is_loopback()
+                && !ip.is_unspecified()
+                && !ip.is_multicast()
+                && match ip {
+                    IpAddr::V4(ip) => !ip.is_link_local(),
+                    IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 != 0xfe80,
+                }
+        })
+        .min_by_key(|ip| (!is_global(ip), ip.is_ipv6() != prefer_ipv6))
+}
+
+/// Checks whether the given non-loopback and non-link-local [`IpAddr`] is a
+/// global-scope one (not a private network one).
+fn is_global(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_private(),
+        IpAddr::V6(ip) => ip.segments()[0] & 0xfe00 != 0xfc00,
+    }
 }
 
 /// Tries to detect public IP address of the machine where this application
@@ -566,3 +5154,106 @@ pub async fn detect_public_ip() -> Option<IpAddr> {
     )
     .await
 }
+
+#[cfg(test)]
+mod local_ip_spec {
+    use std::net::IpAddr;
+
+    use super::{parse_fib_trie, parse_if_inet6, pick_local_ip};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_fib_trie() {
+        let contents = "Main:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 0.0.0.0
+        /0 universe UNICAST
+     +-- 127.0.0.0/8 2 0 2
+        +-- 127.0.0.0/31 1 0 0
+           |-- 127.0.0.0
+              /8 host LOCAL
+           |-- 127.0.0.1
+              /32 host LOCAL
+     +-- 192.168.1.0/24 2 0 2
+        |-- 192.168.1.0
+           /24 link UNICAST
+        |-- 192.168.1.17
+           /32 host LOCAL
+Local:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 192.168.1.17
+        /32 host LOCAL
+";
+        assert_eq!(
+            parse_fib_trie(contents),
+            vec![ip("127.0.0.1"), ip("192.168.1.17")],
+        );
+    }
+
+    #[test]
+    fn parses_if_inet6() {
+        let contents = "\
+00000000000000000000000000000001 01 80 10 80       lo
+fe80000000000000020c29fffe1a2b3c 02 40 20 80     eth0
+20010db8000000000000000000000017 02 40 00 00     eth0
+";
+        assert_eq!(
+            parse_if_inet6(contents),
+            vec![
+                ip("::1"),
+                ip("fe80::20c:29ff:fe1a:2b3c"),
+                ip("2001:db8::17")
+            ],
+        );
+    }
+
+    #[test]
+    fn prefers_global_ipv4() {
+        let ips = [
+            ip("127.0.0.1"),
+            ip("fe80::1"),
+            ip("192.168.1.17"),
+            ip("2001:db8::17"),
+            ip("203.0.113.5"),
+        ];
+        assert_eq!(pick_local_ip(&ips, false), Some(ip("203.0.113.5")));
+        assert_eq!(pick_local_ip(&ips[..4], false), Some(ip("2001:db8::17")));
+        assert_eq!(pick_local_ip(&ips[..3], false), Some(ip("192.168.1.17")));
+        assert_eq!(pick_local_ip(&ips[..2], false), None);
+    }
+
+    #[test]
+    fn prefers_global_ipv6_if_requested() {
+        let ips = [
+            ip("192.168.1.17"),
+            ip("fd00::17"),
+            ip("203.0.113.5"),
+            ip("2001:db8::17"),
+        ];
+        assert_eq!(pick_local_ip(&ips, true), Some(ip("2001:db8::17")));
+        assert_eq!(pick_local_ip(&ips[..3], true), Some(ip("203.0.113.5")));
+        assert_eq!(pick_local_ip(&ips[..2], true), Some(ip("fd00::17")));
+    }
+}
+
+#[cfg(test)]
+mod bind_spec {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::bind;
+
+    #[test]
+    fn reports_ephemeral_port() {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let (first, first_addr) = bind("client", addr, false).unwrap();
+        let (_second, second_addr) = bind("callback", addr, false).unwrap();
+
+        assert_eq!(first_addr.ip(), addr.ip());
+        assert_ne!(first_addr.port(), 0);
+        assert_ne!(first_addr.port(), second_addr.port());
+        assert_eq!(first.local_addr().unwrap(), first_addr);
+    }
+}
@@ -0,0 +1,205 @@
+//! Browser sessions authorizing access to the application's public APIs via a
+//! signed cookie, as an alternative to [Basic authorization][1].
+//!
+//! [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rand::Rng as _;
+
+/// Name of the cookie holding a session token.
+pub const COOKIE_NAME: &str = "ephyr_session";
+
+/// Secret key signing session tokens.
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Generates a new random [`Key`].
+    ///
+    /// Sessions signed with it don't survive the application restart.
+    #[must_use]
+    pub fn generate() -> Self {
+        Self(rand::thread_rng().gen())
+    }
+
+    /// Derives a [`Key`] from the given `secret`, so sessions signed with it
+    /// survive the application restart.
+    #[must_use]
+    pub fn derive(secret: &str) -> Self {
+        let mut key = [0; 32];
+        key.copy_from_slice(
+            blake2b_simd::Params::new()
+                .hash_length(32)
+                .personal(b"ephyr-session")
+                .hash(secret.as_bytes())
+                .as_bytes(),
+        );
+        Self(key)
+    }
+
+    /// Signs a new session token, valid for the given `lifetime` since the
+    /// given `now` moment, and only while [`Settings::password_hash`] is the
+    /// given `password_hash` and [`Settings::session_generation`] is the given
+    /// `generation`.
+    ///
+    /// [`Settings::password_hash`]: crate::state::Settings::password_hash
+    /// [`Settings::session_generation`]:
+    ///     crate::state::Settings::session_generation
+    #[must_use]
+    pub fn sign(
+        &self,
+        password_hash: &str,
+        generation: u64,
+        now: SystemTime,
+        lifetime: Duration,
+    ) -> String {
+        let expires_at = (now + lifetime)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        format!(
+            "{}.{}",
+            expires_at,
+            self.mac(password_hash, generation, expires_at).to_hex(),
+        )
+    }
+
+    /// Checks whether the given session `token` is signed by this [`Key`] for
+    /// the given `password_hash` and `generation`, and is not expired at the
+    /// given `now` moment.
+    ///
+    /// Malformed `token` is never valid.
+    #[must_use]
+    pub fn verify(
+        &self,
+        token: &str,
+        password_hash: &str,
+        generation: u64,
+        now: SystemTime,
+    ) -> bool {
+        let mut parts = token.splitn(2, '.');
+        let expires_at = match parts.next().and_then(|s| s.parse::<u64>().ok())
+        {
+            Some(t) => t,
+            None => return false,
+        };
+        let mac = match parts.next().and_then(|s| hex::decode(s).ok()) {
+            Some(m) => m,
+            None => return false,
+        };
+        let not_expired = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(false, |d| d.as_secs() < expires_at);
+        // Comparison of `blake2b_simd::Hash`es is constant-time.
+        not_expired
+            && self.mac(password_hash, generation, expires_at) == mac[..]
+    }
+
+    /// Calculates the keyed [BLAKE2b] MAC of a session token.
+    ///
+    /// Mixing the `password_hash` in invalidates all the existing sessions
+    /// once the password is changed, and mixing the `generation` in allows to
+    /// invalidate them explicitly.
+    ///
+    /// [BLAKE2b]: https://www.blake2.net
+    fn mac(
+        &self,
+        password_hash: &str,
+        generation: u64,
+        expires_at: u64,
+    ) -> blake2b_simd::Hash {
+        blake2b_simd::Params::new()
+            .hash_length(32)
+            .key(&self.0)
+            .to_state()
+            .update(&expires_at.to_be_bytes())
+            .update(&generation.to_be_bytes())
+            .update(password_hash.as_bytes())
+            .finalize()
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+#[cfg(test)]
+mod key_spec {
+    use std::time::{Duration, SystemTime};
+
+    use super::Key;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn verifies_signed_token() {
+        let key = Key::generate();
+        let now = SystemTime::now();
+        let token = key.sign("hash", 0, now, DAY);
+
+        assert!(key.verify(&token, "hash", 0, now));
+        assert!(key.verify(&token, "hash", 0, now + DAY / 2));
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let key = Key::generate();
+        let now = SystemTime::now();
+        let token = key.sign("hash", 0, now, DAY);
+
+        assert!(!key.verify(&token, "hash", 0, now + DAY * 2));
+    }
+
+    #[test]
+    fn rejects_token_after_password_change() {
+        let key = Key::generate();
+        let now = SystemTime::now();
+        let token = key.sign("hash", 0, now, DAY);
+
+        assert!(!key.verify(&token, "new_hash", 0, now));
+    }
+
+    #[test]
+    fn rejects_token_of_another_generation() {
+        let key = Key::generate();
+        let now = SystemTime::now();
+        let token = key.sign("hash", 0, now, DAY);
+
+        assert!(!key.verify(&token, "hash", 1, now));
+    }
+
+    #[test]
+    fn rejects_token_of_another_key() {
+        let now = SystemTime::now();
+        let token = Key::generate().sign("hash", 0, now, DAY);
+
+        assert!(!Key::generate().verify(&token, "hash", 0, now));
+    }
+
+    #[test]
+    fn rejects_tampered_token() {
+        let key = Key::generate();
+        let now = SystemTime::now();
+        let token = key.sign("hash", 0, now, DAY);
+        let (expires_at, mac) = token.split_at(token.find('.').unwrap());
+        let tampered = format!("{}0{}", expires_at, mac);
+
+        assert!(!key.verify(&tampered, "hash", 0, now));
+        assert!(!key.verify("", "hash", 0, now));
+        assert!(!key.verify("123.qwerty", "hash", 0, now));
+    }
+
+    #[test]
+    fn derives_same_key_from_same_secret() {
+        let now = SystemTime::now();
+        let token = Key::derive("secret").sign("hash", 0, now, DAY);
+
+        assert!(Key::derive("secret").verify(&token, "hash", 0, now));
+        assert!(!Key::derive("another").verify(&token, "hash", 0, now));
+    }
+}
@@ -3,12 +3,14 @@
 //!
 //! [`State`]: state::State
 
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt, time::Duration};
 
+use derive_more::Display;
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 
-use crate::{serde::is_false, state};
+use crate::{proxy::IpRange, schedule::InputSchedule, serde::is_false, state};
 use url::Url;
+use uuid::Uuid;
 
 /// Shareable (exportable and importable) specification of a [`State`].
 ///
@@ -18,30 +20,149 @@ pub struct Spec {
     /// [`Settings`] to be performed.
     pub settings: Option<Settings>,
 
+    /// [`Credentials`] protecting access to the server.
+    ///
+    /// Never exported, unless explicitly requested for a full migration of
+    /// the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<Credentials>,
+
     /// [`Restream`]s to be performed.
     #[serde(deserialize_with = "Spec::deserialize_restreams")]
     pub restreams: Vec<Restream>,
 }
 
 impl Spec {
+    /// Validates all the invariants of this [`Spec`], returning every found
+    /// [`Violation`].
+    ///
+    /// These are the same invariants being ensured on deserialization, so this
+    /// method is useful for a [`Spec`] obtained in other ways (exported from a
+    /// [`state::State`], for example), or for reporting all the
+    /// [`Violation`]s at once.
+    #[must_use]
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        Violation::collect(
+            &mut violations,
+            "$.restreams",
+            Self::check_restreams(&self.restreams),
+        );
+        for (n, r) in self.restreams.iter().enumerate() {
+            let path = format!("$.restreams[{}]", n);
+            r.input
+                .validate_into(&format!("{}.input", path), &mut violations);
+            Violation::collect(
+                &mut violations,
+                &format!("{}.input.schedule", path),
+                r.input.check_schedule(),
+            );
+            Violation::collect(
+                &mut violations,
+                &format!("{}.outputs", path),
+                Restream::check_outputs(&r.outputs),
+            );
+            for (m, o) in r.outputs.iter().enumerate() {
+                Violation::collect(
+                    &mut violations,
+                    &format!("{}.outputs[{}].mixins", path, m),
+                    Output::check_mixins(&o.mixins),
+                );
+            }
+        }
+        violations
+    }
+
+    /// Deserializes [`Spec::restreams`] ensuring its invariants preserved.
     fn deserialize_restreams<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Vec<Restream>, D::Error> {
         let restreams = <Vec<Restream>>::deserialize(deserializer)?;
+        Self::check_restreams(&restreams).map_err(D::Error::custom)?;
+        Ok(restreams)
+    }
 
-        if !restreams.is_empty() {
-            let mut unique = HashSet::with_capacity(restreams.len());
-            for r in &restreams {
-                if let Some(key) = unique.replace(&r.key) {
-                    return Err(D::Error::custom(format!(
-                        "Duplicate Restream.key in Spec.restreams: {}",
-                        key,
-                    )));
-                }
+    /// Checks invariants of [`Spec::restreams`].
+    ///
+    /// # Errors
+    ///
+    /// If [`Restream::key`]s are not unique, or IDs of any entities are not
+    /// unique.
+    fn check_restreams(restreams: &[Restream]) -> Result<(), String> {
+        let mut unique = HashSet::with_capacity(restreams.len());
+        for r in restreams {
+            if let Some(key) = unique.replace(&r.key) {
+                return Err(format!(
+                    "Duplicate Restream.key in Spec.restreams: {}",
+                    key,
+                ));
             }
         }
 
-        Ok(restreams)
+        let mut ids = vec![];
+        for r in restreams {
+            r.collect_ids(&mut ids);
+        }
+        let mut unique = HashSet::with_capacity(ids.len());
+        for id in ids {
+            if !unique.insert(id) {
+                return Err(format!("Duplicate ID in Spec.restreams: {}", id));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Violation of a [`Spec`] invariant.
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+#[display(fmt = "{}: {}", path, message)]
+pub struct Violation {
+    /// [JSONPath] to the value violating the invariant.
+    ///
+    /// [JSONPath]: https://goessner.net/articles/JsonPath
+    pub path: String,
+
+    /// Human-readable description of this [`Violation`].
+    pub message: String,
+}
+
+impl Violation {
+    /// Pushes a new [`Violation`] of the value at the given `path` into the
+    /// given `violations`, if the given check `result` is an error.
+    fn collect(
+        violations: &mut Vec<Self>,
+        path: &str,
+        result: Result<(), String>,
+    ) {
+        if let Err(message) = result {
+            violations.push(Self {
+                path: path.to_owned(),
+                message,
+            });
+        }
+    }
+}
+
+/// Credentials protecting access to the server's public APIs.
+///
+/// Contains [`argon2`] hashes only, never plain passwords. Still, these are
+/// secrets, so are redacted in [`Debug`] output to not leak into logs.
+///
+/// [`Debug`]: fmt::Debug
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Credentials {
+    /// [`argon2`] hash of the password granting full access.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+
+    /// [`argon2`] hash of the password granting read-only access.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewer_password_hash: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Credentials(..)")
     }
 }
 
@@ -68,6 +189,13 @@ pub struct Settings {
 /// [`state::Restream`].
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Restream {
+    /// ID of this [`Restream`] to be used verbatim, instead of a random one.
+    ///
+    /// Always exported, so the same [`Spec`] provisions entities with the same
+    /// IDs on different servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<state::RestreamId>,
+
     /// Unique key of this [`Restream`] identifying it, and used to form its
     /// endpoints URLs.
     pub key: state::RestreamKey,
@@ -76,6 +204,10 @@ pub struct Restream {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub label: Option<state::Label>,
 
+    /// Optional group this [`Restream`] belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<state::RestreamGroup>,
+
     /// [`Input`] that a live stream is received from.
     pub input: Input,
 
@@ -86,34 +218,139 @@ pub struct Restream {
         skip_serializing_if = "Vec::is_empty"
     )]
     pub outputs: Vec<Output>,
+
+    /// Indicator whether publishers of this [`Restream`] should be allowed to
+    /// connect even when its [`Input`] is disabled.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub queue_publishers: bool,
+
+    /// Policy of resolving concurrent publishing of a live stream into the
+    /// same [`Input`] of this [`Restream`].
+    #[serde(
+        default,
+        skip_serializing_if = "state::PublisherPolicy::is_default"
+    )]
+    pub publisher_policy: state::PublisherPolicy,
+
+    /// Host to be used in public URLs of this [`Restream`] instead of the
+    /// globally configured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_host_override: Option<state::PublicHost>,
+
+    /// Number of seconds the [`Input`] of this [`Restream`] should stay idle
+    /// (receiving no live stream) continuously for, to be disabled
+    /// automatically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_disable_after_idle_secs: Option<u32>,
+
+    /// Maximum number of seconds a single session of an external client
+    /// publishing a live stream into the [`Input`] of this [`Restream`] may
+    /// last for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session_secs: Option<u32>,
+
+    /// Indicator whether the [`Input`] of this [`Restream`] should be disabled
+    /// once a publishing session exceeds [`Restream::max_session_secs`].
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disable_on_expiry: bool,
+
+    /// [`IpRange`]s that external clients are allowed to publish a live
+    /// stream into the [`Input`] of this [`Restream`] from.
+    ///
+    /// If empty, then publishing is allowed from anywhere.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publish_allowlist: Vec<IpRange>,
+
+    /// Indicator whether the live stream of this [`Restream`] should be served
+    /// with as low latency as possible.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub low_latency: bool,
+
+    /// Indicator whether the live stream of this [`Restream`] should be served
+    /// via [HLS] by [SRS] itself.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hls: bool,
+
+    /// Indicator whether this [`Restream`] is listed on the public status
+    /// page.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub public: bool,
+
+    /// Indicator whether the re-streaming processes of this [`Restream`] are
+    /// never cycled automatically within the maintenance window.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_auto_cycle: bool,
+
+    /// Indicator whether the copy-only [`Output`]s of this [`Restream`] should
+    /// be re-streamed by a single [FFmpeg] process.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub consolidate_outputs: bool,
 }
 
 impl Restream {
+    /// Collects IDs of this [`Restream`] and all its entities specified
+    /// explicitly into the given `ids`.
+    pub fn collect_ids(&self, ids: &mut Vec<Uuid>) {
+        ids.extend(self.id.map(Uuid::from));
+        self.input.collect_ids(ids);
+        for o in &self.outputs {
+            o.collect_ids(ids);
+        }
+    }
+
+    /// Forgets IDs of this [`Restream`] and all its entities, so it can be
+    /// compared with a [`Restream`] specified without them.
+    pub fn forget_ids(&mut self) {
+        self.id = None;
+        self.input.forget_ids();
+        for o in &mut self.outputs {
+            o.id = None;
+            for m in &mut o.mixins {
+                m.id = None;
+            }
+        }
+    }
+
     /// Deserializes [`Restream::outputs`] ensuring its invariants preserved.
     fn deserialize_outputs<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Vec<Output>, D::Error> {
         let outputs = <Vec<Output>>::deserialize(deserializer)?;
+        Self::check_outputs(&outputs).map_err(D::Error::custom)?;
+        Ok(outputs)
+    }
 
-        if !outputs.is_empty() {
-            let mut unique = HashSet::with_capacity(outputs.len());
-            for o in &outputs {
-                if let Some(dst) = unique.replace(&o.dst) {
-                    return Err(D::Error::custom(format!(
-                        "Duplicate Output.dst in Restream.outputs: {}",
-                        dst,
-                    )));
-                }
+    /// Checks invariants of [`Restream::outputs`].
+    ///
+    /// # Errors
+    ///
+    /// If [`Output::dst`]s are not unique.
+    fn check_outputs(outputs: &[Output]) -> Result<(), String> {
+        let mut unique = HashSet::with_capacity(outputs.len());
+        for o in outputs {
+            if let Some(dst) = unique.replace(&o.dst) {
+                return Err(format!(
+                    "Duplicate Output.dst in Restream.outputs: {}",
+                    dst,
+                ));
             }
         }
-
-        Ok(outputs)
+        Ok(())
     }
 }
 
 /// Shareable (exportable and importable) specification of a [`state::Input`].
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct Input {
+    /// ID of this [`Input`] to be used verbatim, instead of a random one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<state::InputId>,
+
     /// Key of this [`Input`] to expose its [`InputEndpoint`]s with for
     /// accepting and serving a live stream.
     pub key: state::InputKey,
@@ -132,6 +369,37 @@ pub struct Input {
     /// live stream from its upstream sources.
     #[serde(default, skip_serializing_if = "is_false")]
     pub enabled: bool,
+
+    /// Maximum lifetime of a connection pulling a live stream from a remote
+    /// [`Input::src`], after which it's proactively re-established.
+    #[serde(
+        default,
+        with = "serde_humantime",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_pull_lifetime: Option<Duration>,
+
+    /// Timeout (in seconds) of establishing a connection for pulling a live
+    /// stream from a remote [`Input::src`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u32>,
+
+    /// Timeout (in seconds) of a connection pulling a live stream from a
+    /// remote [`Input::src`] receiving no data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u32>,
+
+    /// Recurring weekly schedule allowing this [`Input`] to pull a live
+    /// stream from its [`Input::src`] within the scheduled hours only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<InputSchedule>,
+
+    /// Additional options of pulling a live stream from a remote [RTMP]
+    /// [`Input::src`].
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtmp_options: Option<state::RtmpPullOptions>,
 }
 
 impl<'de> Deserialize<'de> for Input {
@@ -141,81 +409,208 @@ impl<'de> Deserialize<'de> for Input {
     {
         #[derive(Deserialize)]
         struct RawInput {
+            #[serde(default)]
+            id: Option<state::InputId>,
             key: state::InputKey,
             endpoints: Vec<InputEndpoint>,
             #[serde(default)]
             src: Option<InputSrc>,
             #[serde(default)]
             enabled: bool,
+            #[serde(default, with = "serde_humantime")]
+            max_pull_lifetime: Option<Duration>,
+            #[serde(default)]
+            connect_timeout_secs: Option<u32>,
+            #[serde(default)]
+            idle_timeout_secs: Option<u32>,
+            #[serde(default)]
+            schedule: Option<InputSchedule>,
+            #[serde(default)]
+            rtmp_options: Option<state::RtmpPullOptions>,
         }
 
         let raw = RawInput::deserialize(deserializer)?;
 
-        let mut unique_endpoints = HashSet::with_capacity(raw.endpoints.len());
-        for e in &raw.endpoints {
-            if let Some(kind) = unique_endpoints.replace(e.kind) {
-                return Err(D::Error::custom(format!(
+        Self::check_endpoints(&raw.endpoints).map_err(D::Error::custom)?;
+        if let Some(src) = &raw.src {
+            Self::check_src(&raw.key, src).map_err(D::Error::custom)?;
+        }
+        let rtmp_options = raw
+            .rtmp_options
+            .map(|o| {
+                state::RtmpPullOptions::new(
+                    o.playpath,
+                    o.swf_url,
+                    o.page_url,
+                    o.token_query,
+                )
+            })
+            .transpose()
+            .map_err(D::Error::custom)?
+            .flatten();
+
+        Ok(Self {
+            id: raw.id,
+            key: raw.key,
+            endpoints: raw.endpoints,
+            src: raw.src,
+            enabled: raw.enabled,
+            max_pull_lifetime: raw.max_pull_lifetime,
+            connect_timeout_secs: raw.connect_timeout_secs,
+            idle_timeout_secs: raw.idle_timeout_secs,
+            schedule: raw.schedule,
+            rtmp_options,
+        })
+    }
+}
+
+impl Input {
+    /// Collects IDs of this [`Input`] and all its [`InputSrc::FailoverInputs`]
+    /// specified explicitly into the given `ids`.
+    fn collect_ids(&self, ids: &mut Vec<Uuid>) {
+        ids.extend(self.id.map(Uuid::from));
+        if let Some(InputSrc::FailoverInputs(inputs)) = &self.src {
+            for i in inputs {
+                i.collect_ids(ids);
+            }
+        }
+    }
+
+    /// Forgets IDs of this [`Input`] and all its
+    /// [`InputSrc::FailoverInputs`].
+    fn forget_ids(&mut self) {
+        self.id = None;
+        if let Some(InputSrc::FailoverInputs(inputs)) = &mut self.src {
+            for i in inputs {
+                i.forget_ids();
+            }
+        }
+    }
+
+    /// Validates all the invariants of this [`Input`] located by the given
+    /// `path`, collecting every found [`Violation`] into the given
+    /// `violations`.
+    fn validate_into(&self, path: &str, violations: &mut Vec<Violation>) {
+        Violation::collect(
+            violations,
+            &format!("{}.endpoints", path),
+            Self::check_endpoints(&self.endpoints),
+        );
+        if let Some(src) = &self.src {
+            let path = format!("{}.src", path);
+            Violation::collect(
+                violations,
+                &path,
+                Self::check_src(&self.key, src),
+            );
+            if let InputSrc::FailoverInputs(inputs) = src {
+                for (n, i) in inputs.iter().enumerate() {
+                    i.validate_into(
+                        &format!("{}.failover_inputs[{}]", path, n),
+                        violations,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks invariants of [`Input::endpoints`].
+    ///
+    /// # Errors
+    ///
+    /// If [`InputEndpoint::kind`]s are not unique, or there is no
+    /// [`state::InputEndpointKind::Rtmp`] one.
+    fn check_endpoints(endpoints: &[InputEndpoint]) -> Result<(), String> {
+        let mut unique = HashSet::with_capacity(endpoints.len());
+        for e in endpoints {
+            if let Some(kind) = unique.replace(e.kind) {
+                return Err(format!(
                     "Duplicate InputEndpoint.kind in Input.endpoints: {}",
                     kind,
-                )));
+                ));
             }
         }
-        if !unique_endpoints.contains(&state::InputEndpointKind::Rtmp) {
-            return Err(D::Error::custom(format!(
+        if !unique.contains(&state::InputEndpointKind::Rtmp) {
+            return Err(format!(
                 "Input.endpoints should contain at least one {} endpoint",
                 state::InputEndpointKind::Rtmp,
-            )));
+            ));
         }
+        Ok(())
+    }
 
-        if let Some(src) = &raw.src {
-            fn ensure_srcs_unique<'i>(
-                src: &'i InputSrc,
-                unique_urls: &mut HashSet<&'i state::InputSrcUrl>,
-                unique_keys: &mut HashSet<&'i state::InputKey>,
-            ) -> Result<(), String> {
-                match src {
-                    InputSrc::RemoteUrl(url) => {
-                        if let Some(url) = unique_urls.replace(url) {
+    /// Checks invariants of [`Input::schedule`].
+    ///
+    /// Only a top-level [`Input`] is checked, as [`InputSrc::FailoverInputs`]
+    /// inherit the [`InputSchedule`] of their parent, whatever they pull.
+    ///
+    /// # Errors
+    ///
+    /// If the [`InputSchedule`] has no ranges, or this [`Input`] doesn't pull
+    /// a live stream.
+    fn check_schedule(&self) -> Result<(), String> {
+        let schedule = match &self.schedule {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if schedule.ranges().is_empty() {
+            return Err(
+                "Input.schedule should contain at least one range".to_owned()
+            );
+        }
+        if !matches!(
+            self.src,
+            Some(InputSrc::RemoteUrl(_)) | Some(InputSrc::FailoverInputs(_)),
+        ) {
+            return Err("Only a pulling Input may have a schedule".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Checks invariants of [`Input::src`] of an [`Input`] with the given
+    /// `key`.
+    ///
+    /// # Errors
+    ///
+    /// If the remote URLs or [`Input::key`]s are not unique across the whole
+    /// `src` tree.
+    fn check_src(key: &state::InputKey, src: &InputSrc) -> Result<(), String> {
+        fn ensure_srcs_unique<'i>(
+            src: &'i InputSrc,
+            unique_urls: &mut HashSet<&'i state::InputSrcUrl>,
+            unique_keys: &mut HashSet<&'i state::InputKey>,
+        ) -> Result<(), String> {
+            match src {
+                InputSrc::RemoteUrl(url) => {
+                    if let Some(url) = unique_urls.replace(url) {
+                        return Err(format!(
+                            "Duplicate RemoteInputSrc.url in Input.src: {}",
+                            url,
+                        ));
+                    }
+                }
+                InputSrc::File(_) => {}
+                InputSrc::FailoverInputs(inputs) => {
+                    for i in inputs {
+                        if let Some(key) = unique_keys.replace(&i.key) {
                             return Err(format!(
-                                "Duplicate RemoteInputSrc.url in Input.src: {}",
-                                url,
+                                "Duplicate Input.key in Input.srcs: {}",
+                                key,
                             ));
                         }
-                    }
-                    InputSrc::FailoverInputs(inputs) => {
-                        for i in inputs {
-                            if let Some(key) = unique_keys.replace(&i.key) {
-                                return Err(format!(
-                                    "Duplicate Input.key in Input.srcs: {}",
-                                    key,
-                                ));
-                            }
-                            if let Some(s) = i.src.as_ref() {
-                                ensure_srcs_unique(
-                                    s,
-                                    unique_urls,
-                                    unique_keys,
-                                )?;
-                            }
+                        if let Some(s) = i.src.as_ref() {
+                            ensure_srcs_unique(s, unique_urls, unique_keys)?;
                         }
                     }
                 }
-                Ok(())
             }
-
-            let mut unique_urls = HashSet::new();
-            let mut unique_keys = HashSet::with_capacity(1);
-            let _ = unique_keys.insert(&raw.key);
-            ensure_srcs_unique(&src, &mut unique_urls, &mut unique_keys)
-                .map_err(D::Error::custom)?;
+            Ok(())
         }
 
-        Ok(Self {
-            key: raw.key,
-            endpoints: raw.endpoints,
-            src: raw.src,
-            enabled: raw.enabled,
-        })
+        let mut unique_urls = HashSet::new();
+        let mut unique_keys = HashSet::with_capacity(1);
+        let _ = unique_keys.insert(key);
+        ensure_srcs_unique(src, &mut unique_urls, &mut unique_keys)
     }
 }
 
@@ -237,14 +632,26 @@ pub enum InputSrc {
 
     /// Multiple [`Input`]s forming a failover source.
     FailoverInputs(Vec<Input>),
+
+    /// Local media file.
+    File(state::FileInputSrc),
 }
 
 /// Shareable (exportable and importable) specification of a [`state::Output`].
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Output {
+    /// ID of this [`Output`] to be used verbatim, instead of a random one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<state::OutputId>,
+
     /// Downstream URL to re-stream a live stream onto.
     pub dst: state::OutputDstUrl,
 
+    /// Backup downstream URL to re-stream a live stream onto while the
+    /// [`Output::dst`] keeps failing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_dst: Option<state::OutputDstUrl>,
+
     /// Optional label of this [`Output`].
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub label: Option<state::Label>,
@@ -269,48 +676,153 @@ pub struct Output {
     )]
     pub mixins: Vec<Mixin>,
 
+    /// Metadata to be injected into the live stream re-streamed by this
+    /// [`Output`].
+    #[serde(
+        default,
+        deserialize_with = "Output::deserialize_metadata",
+        skip_serializing_if = "state::OutputMetadata::is_empty"
+    )]
+    pub metadata: state::OutputMetadata,
+
+    /// Conditioning to re-encode the live stream with before re-streaming it
+    /// to the downstream destination of this [`Output`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditioning: Option<state::OutputConditioning>,
+
+    /// Target loudness to normalize the audio of this [`Output`] to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudnorm: Option<state::LoudnessTarget>,
+
+    /// Image to be drawn over the video of this [`Output`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<state::OutputOverlay>,
+
+    /// Number of seconds to delay the live stream re-streamed by this
+    /// [`Output`] for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_secs: Option<u32>,
+
+    /// Maximum bitrate (in kbit/s) to re-stream the live stream of this
+    /// [`Output`] with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bitrate_kbps: Option<u32>,
+
+    /// Limits of OS resources the re-streaming process of this [`Output`] is
+    /// allowed to consume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<state::OutputLimits>,
+
+    /// Constraints of the downstream destination of this [`Output`] on the
+    /// live stream it accepts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<state::OutputConstraints>,
+
+    /// Indicator whether the re-streaming process of this [`Output`] is kept
+    /// connected to its downstream destination while being disabled, so it
+    /// goes live near-instantly once enabled.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub prewarm: bool,
+
+    /// Strategy of keeping this [`Output`] connected to its downstream
+    /// destination while being prewarmed.
+    #[serde(
+        default,
+        skip_serializing_if = "state::PrewarmStrategy::is_default"
+    )]
+    pub prewarm_strategy: state::PrewarmStrategy,
+
+    /// Key of the [`Input`] of the [`Restream`] to re-stream the live stream
+    /// of (one of its [`InputSrc::FailoverInputs`], like a clean feed).
+    ///
+    /// If [`None`], then the live stream of the main [`Input`] is re-streamed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src_stream: Option<state::InputKey>,
+
+    /// Indicator whether the re-streaming process of this [`Output`] should
+    /// bound its send buffer and drop frames once its downstream destination
+    /// cannot keep up, rather than stalling.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub drop_frames_when_behind: bool,
+
     /// Indicator whether this [`Output`]  is enabled, so is allowed to perform
     /// a live stream re-streaming to its downstream destination.
     #[serde(default, skip_serializing_if = "is_false")]
     pub enabled: bool,
+
+    /// Policy of enabling this [`Output`] after the server restart.
+    #[serde(
+        default,
+        skip_serializing_if = "state::OutputBootPolicy::is_default"
+    )]
+    pub on_boot: state::OutputBootPolicy,
 }
 
 impl Output {
+    /// Collects IDs of this [`Output`] and its [`Output::mixins`] specified
+    /// explicitly into the given `ids`.
+    pub fn collect_ids(&self, ids: &mut Vec<Uuid>) {
+        ids.extend(self.id.map(Uuid::from));
+        ids.extend(self.mixins.iter().filter_map(|m| m.id.map(Uuid::from)));
+    }
+
+    /// Deserializes [`Output::metadata`] sanitizing its values.
+    fn deserialize_metadata<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<state::OutputMetadata, D::Error> {
+        let meta = state::OutputMetadata::deserialize(deserializer)?;
+        state::OutputMetadata::new(meta.title, meta.author, meta.comment)
+            .map_err(D::Error::custom)
+    }
+
     /// Deserializes [`Output::mixins`] ensuring its invariants preserved.
     fn deserialize_mixins<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Vec<Mixin>, D::Error> {
         let mixins = <Vec<Mixin>>::deserialize(deserializer)?;
+        Self::check_mixins(&mixins).map_err(D::Error::custom)?;
+        Ok(mixins)
+    }
 
-        if !mixins.is_empty() {
-            let mut unique = HashSet::with_capacity(mixins.len());
-            let mut has_ts = false;
-            for m in &mixins {
-                if let Some(src) = unique.replace(&m.src) {
-                    return Err(D::Error::custom(format!(
-                        "Duplicate Mixin.src in Output.mixins: {}",
-                        src,
-                    )));
-                }
-                if m.src.scheme() == "ts" {
-                    if has_ts {
-                        return Err(D::Error::custom(format!(
-                            "Second TeamSpeak Mixin.src in Output.mixins: {}",
-                            m.src,
-                        )));
-                    }
-                    has_ts = true;
+    /// Checks invariants of [`Output::mixins`].
+    ///
+    /// # Errors
+    ///
+    /// If [`Mixin::src`]s are not unique, or there is more than one
+    /// [TeamSpeak] [`Mixin`].
+    ///
+    /// [TeamSpeak]: https://teamspeak.com
+    fn check_mixins(mixins: &[Mixin]) -> Result<(), String> {
+        let mut unique = HashSet::with_capacity(mixins.len());
+        let mut has_ts = false;
+        for m in mixins {
+            if let Some(src) = unique.replace(&m.src) {
+                return Err(format!(
+                    "Duplicate Mixin.src in Output.mixins: {}",
+                    src,
+                ));
+            }
+            if m.src.scheme() == "ts" {
+                if has_ts {
+                    return Err(format!(
+                        "Second TeamSpeak Mixin.src in Output.mixins: {}",
+                        m.src,
+                    ));
                 }
+                has_ts = true;
             }
         }
-
-        Ok(mixins)
+        Ok(())
     }
 }
 
 /// Shareable (exportable and importable) specification of a [`state::Mixin`].
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Mixin {
+    /// ID of this [`Mixin`] to be used verbatim, instead of a random one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<state::MixinId>,
+
     /// URL of the source to be mixed with an [`Output`].
     pub src: state::MixinSrcUrl,
 
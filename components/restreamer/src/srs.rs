@@ -4,11 +4,17 @@
 
 use std::{
     borrow::Borrow,
-    ops::Deref,
+    net::{IpAddr, SocketAddr},
+    ops::{Deref, RangeInclusive},
     panic::AssertUnwindSafe,
     path::{Path, PathBuf},
     process::Stdio,
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
@@ -16,16 +22,128 @@ use askama::Template;
 use derive_more::{AsRef, Deref, Display, From, Into};
 use ephyr_log::{log, slog};
 use futures::future::{self, FutureExt as _, TryFutureExt as _};
+use futures_signals::signal::{Mutable, SignalExt as _};
 use smart_default::SmartDefault;
-use tokio::{fs, process::Command};
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt as _, AsyncRead},
+    process::Command,
+    time,
+};
+
+use crate::{api, diagnostics, display_panic, dvr};
+
+/// Port that the spawned [SRS] server accepts RTMP connections on.
+///
+/// [SRS]: https://github.com/ossrs/srs
+static RTMP_PORT: AtomicU16 = AtomicU16::new(1935);
+
+/// Port that the spawned [SRS] server exposes its [HTTP API][1] on.
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+static HTTP_API_PORT: AtomicU16 = AtomicU16::new(8002);
+
+/// Port that the spawned [SRS] server exposes its HTTP server on, serving
+/// [HLS] playlists and segments.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [SRS]: https://github.com/ossrs/srs
+static HTTP_SERVER_PORT: AtomicU16 = AtomicU16::new(8000);
 
-use crate::{api, display_panic, dvr};
+/// Indicator whether the spawned [SRS] server is able to send its
+/// [HTTP callbacks][1] to a Unix socket (see
+/// [`cli::Opts::callback_http_socket`]).
+///
+/// [SRS] 3 connects to the hosts of `http_hooks` URLs over TCP only, having no
+/// URL format to refer a Unix socket.
+///
+/// [`cli::Opts::callback_http_socket`]: crate::cli::Opts::callback_http_socket
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
+pub const UNIX_SOCKET_CALLBACKS: bool = false;
+
+/// Directory inside the [`Config::http_server_dir`] where [SRS] writes [HLS]
+/// playlists and segments of the apps served on [`hls_vhost()`]s.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [SRS]: https://github.com/ossrs/srs
+pub const HLS_DIR: &str = "live";
+
+/// Returns port that the spawned [SRS] server accepts RTMP connections on.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[inline]
+#[must_use]
+pub fn rtmp_port() -> u16 {
+    // TODO: Inject `srs::Config` normally as dependency rather than use
+    //       global values.
+    RTMP_PORT.load(Ordering::SeqCst)
+}
+
+/// Returns port that the spawned [SRS] server exposes its [HTTP API][1] on.
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+#[inline]
+#[must_use]
+pub fn http_api_port() -> u16 {
+    HTTP_API_PORT.load(Ordering::SeqCst)
+}
+
+/// Returns port that the spawned [SRS] server exposes its HTTP server on,
+/// serving [HLS] playlists and segments.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [SRS]: https://github.com/ossrs/srs
+#[inline]
+#[must_use]
+pub fn http_server_port() -> u16 {
+    HTTP_SERVER_PORT.load(Ordering::SeqCst)
+}
+
+/// Returns name of the [SRS] vhost dedicated to live streams of the given
+/// low-latency `app`.
+///
+/// [SRS] configures GOP cache per vhost only, so low-latency apps cannot share
+/// the default vhost with others.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[inline]
+#[must_use]
+pub fn low_latency_vhost(app: &str) -> String {
+    format!("{}.lowlatency", app)
+}
+
+/// Returns name of the [SRS] vhost dedicated to live streams of the given
+/// `app` served via [HLS] by [SRS] itself.
+///
+/// [SRS] enables [HLS] per vhost only, so such apps cannot share the default
+/// vhost with others.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [SRS]: https://github.com/ossrs/srs
+#[inline]
+#[must_use]
+pub fn hls_vhost(app: &str) -> String {
+    format!("{}.hls", app)
+}
 
 /// [SRS] server spawnable as a separate process.
 ///
 /// [SRS]: https://github.com/ossrs/srs
 #[derive(Clone, Debug)]
 pub struct Server {
+    /// Path to the [SRS] binary.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    bin_path: PathBuf,
+
+    /// Working directory of the [SRS] process.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    workdir: PathBuf,
+
     /// Path where [SRS] configuration file should be created.
     ///
     /// [SRS]: https://github.com/ossrs/srs
@@ -35,20 +153,60 @@ pub struct Server {
     ///
     /// [SRS]: https://github.com/ossrs/srs
     process: Arc<ServerProcess>,
+
+    /// Version of the [SRS] binary, if it has been detected successfully.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    version: Option<String>,
+
+    /// Runtime [`Status`] of the spawned [SRS] process.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    status: Status,
+
+    /// [`Tuning`] of the [SRS] vhost used by this [`Server`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    tuning: Tuning,
 }
 
 impl Server {
+    /// Minimal time that [SRS] process should run for, so its exit is not
+    /// considered as a crash right after start.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const MIN_HEALTHY_UPTIME: Duration = Duration::from_secs(30);
+
+    /// Number of consecutive crashes right after start, after which [SRS]
+    /// process is not restarted anymore.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const MAX_RAPID_CRASHES: u32 = 5;
+
+    /// Initial delay before restarting the exited [SRS] process.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const MIN_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+    /// Maximal delay before restarting the exited [SRS] process.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
     /// Tries to create and run a new [SRS] server process.
     ///
     /// # Errors
     ///
-    /// If [SRS] configuration file fails to be created.
+    /// - If [`Config::tuning`] is invalid.
+    /// - If [SRS] configuration file fails to be created.
     ///
     /// [SRS]: https://github.com/ossrs/srs
     pub async fn try_new<P: AsRef<Path>>(
         workdir: P,
         cfg: &Config,
     ) -> Result<Self, anyhow::Error> {
+        cfg.tuning.validate()?;
+
         let workdir = workdir.as_ref();
         let mut bin_path = workdir.to_path_buf();
         bin_path.push("objs/srs");
@@ -75,35 +233,60 @@ impl Server {
             )
         })?;
 
+        RTMP_PORT.store(cfg.rtmp_port, Ordering::SeqCst);
+        HTTP_API_PORT.store(cfg.http_api_port, Ordering::SeqCst);
+        HTTP_SERVER_PORT.store(cfg.http_server_port, Ordering::SeqCst);
+
         // Set directory for dvr::Storage served by this SRS instance.
         let mut dvr_dir = http_dir.clone();
         dvr_dir.push("dvr");
         dvr::Storage { root_path: dvr_dir }.set_global()?;
 
-        let mut cmd = Command::new(bin_path);
+        let mut cmd = Command::new(&bin_path);
         let _ = cmd
             .stdin(Stdio::null())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .kill_on_drop(true)
             .current_dir(workdir)
             .arg("-c")
             .arg(&conf_path);
 
+        let version = Self::detect_version(&bin_path).await;
+        if version.is_none() {
+            log::warn!("Failed to detect SRS server version");
+        }
+
+        let status = Status::default();
+        let supervised = status.clone();
         let (spawner, abort_handle) = future::abortable(async move {
+            let status = supervised;
+            let mut backoff = Self::MIN_RESTART_BACKOFF;
+            let mut rapid_crashes = 0;
             loop {
                 let cmd = &mut cmd;
+                let (running, pid, started) =
+                    (&status.running, &status.pid, &status.started_at);
+                let started_at = Instant::now();
                 let _ = AssertUnwindSafe(async move {
-                    let process = cmd.spawn().map_err(|e| {
+                    let mut process = cmd.spawn().map_err(|e| {
                         log::crit!("Cannot start SRS server: {}", e)
                     })?;
-                    let out =
-                        process.wait_with_output().await.map_err(|e| {
-                            log::crit!("Failed to observe SRS server: {}", e)
-                        })?;
+                    running.set(true);
+                    pid.set(Some(process.id()));
+                    started.set(Some(started_at));
+                    if let Some(out) = process.stdout.take() {
+                        drop(tokio::spawn(forward_log(out)));
+                    }
+                    if let Some(err) = process.stderr.take() {
+                        drop(tokio::spawn(forward_log(err)));
+                    }
+                    let exit_status = process.await.map_err(|e| {
+                        log::crit!("Failed to observe SRS server: {}", e)
+                    })?;
                     log::crit!(
                         "SRS server stopped with exit code: {}",
-                        out.status,
+                        exit_status,
                     );
                     Ok(())
                 })
@@ -116,16 +299,45 @@ impl Server {
                         display_panic(&p),
                     );
                 });
+                status.running.set(false);
+                status.pid.set(None);
+                status.started_at.set(None);
+
+                if started_at.elapsed() >= Self::MIN_HEALTHY_UPTIME {
+                    rapid_crashes = 0;
+                    backoff = Self::MIN_RESTART_BACKOFF;
+                } else {
+                    rapid_crashes += 1;
+                    if rapid_crashes >= Self::MAX_RAPID_CRASHES {
+                        log::crit!(
+                            "SRS server crashed {} times in a row right after \
+                             start, giving up restarting it",
+                            rapid_crashes,
+                        );
+                        status.failed.set(true);
+                        break;
+                    }
+                }
+
+                log::info!("Restarting SRS server in {:?}", backoff);
+                time::delay_for(backoff).await;
+                backoff = (backoff * 2).min(Self::MAX_RESTART_BACKOFF);
+                *status.restarts.lock_mut() += 1;
             }
         });
 
         let srv = Self {
+            bin_path,
+            workdir: workdir.to_path_buf(),
             conf_path,
             process: Arc::new(ServerProcess(abort_handle)),
+            version,
+            status,
+            tuning: cfg.tuning,
         };
 
         // Pre-create SRS conf file.
-        srv.refresh(cfg).await?;
+        cfg.write_to(&srv.conf_path).await?;
 
         // Start SRS server as a child process.
         drop(tokio::spawn(spawner));
@@ -133,24 +345,235 @@ impl Server {
         Ok(srv)
     }
 
-    /// Updates [SRS] configuration file and reloads the spawned [SRS] server
-    /// to catch up the changes.
+    /// Regenerates [SRS] configuration file from the given [`Config`] and
+    /// reloads the spawned [SRS] server to catch up the changes, without
+    /// dropping any of its clients.
+    ///
+    /// The new configuration is written aside and verified by [SRS] binary
+    /// before replacing the actual one, so the running [SRS] server never
+    /// observes a partially written or malformed configuration file.
+    ///
+    /// If [SRS] server is not running at the moment, then the new
+    /// configuration will be picked up on its restart.
     ///
     /// # Errors
     ///
-    /// If [SRS] configuration file fails to be created.
+    /// - If [SRS] configuration file fails to be created.
+    /// - If [SRS] rejects the new configuration (the old one remains in use
+    ///   then).
+    /// - If [SRS] server fails to be signaled for reloading.
     ///
     /// [SRS]: https://github.com/ossrs/srs
-    pub async fn refresh(&self, cfg: &Config) -> anyhow::Result<()> {
-        // SRS server reloads automatically on its conf file changes.
-        fs::write(
-            &self.conf_path,
-            cfg.render().map_err(|e| {
-                anyhow!("Failed to render SRS config from template: {}", e)
-            })?,
-        )
-        .await
-        .map_err(|e| anyhow!("Failed to write SRS config file: {}", e))
+    pub async fn reload(&self, cfg: &Config) -> anyhow::Result<()> {
+        let mut new_conf_path = self.conf_path.clone();
+        let _ = new_conf_path.set_extension("conf.new");
+        cfg.write_to(&new_conf_path).await?;
+
+        let out = Command::new(&self.bin_path)
+            .stdin(Stdio::null())
+            .current_dir(&self.workdir)
+            .arg("-t")
+            .arg("-c")
+            .arg(&new_conf_path)
+            .output()
+            .await
+            .map_err(|e| anyhow!("Failed to verify new SRS config: {}", e))?;
+        if !out.status.success() {
+            let _ = fs::remove_file(&new_conf_path).await;
+            return Err(anyhow!(
+                "SRS rejected new config: {}{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr),
+            ));
+        }
+
+        fs::rename(&new_conf_path, &self.conf_path)
+            .await
+            .map_err(|e| anyhow!("Failed to replace SRS config file: {}", e))?;
+
+        let pid = if let Some(pid) = self.status.pid.get() {
+            pid
+        } else {
+            log::debug!("SRS server is not running, so its reload is skipped");
+            return Ok(());
+        };
+        let out = Command::new("kill")
+            .stdin(Stdio::null())
+            .arg("-HUP")
+            .arg(pid.to_string())
+            .output()
+            .await
+            .map_err(|e| anyhow!("Failed to signal SRS server: {}", e))?;
+        if !out.status.success() {
+            return Err(anyhow!(
+                "Failed to signal SRS server: {}",
+                String::from_utf8_lossy(&out.stderr),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns path of the configuration file of this [SRS] server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn conf_path(&self) -> &Path {
+        &self.conf_path
+    }
+
+    /// Returns version of this [SRS] server's binary, if it has been detected
+    /// successfully.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Detects version of the [SRS] binary located by the given `bin_path`.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    async fn detect_version(bin_path: &Path) -> Option<String> {
+        let out = Command::new(bin_path)
+            .stdin(Stdio::null())
+            .arg("-v")
+            .output()
+            .await
+            .ok()?;
+        // SRS prints its version to STDERR.
+        let ver = String::from_utf8_lossy(if out.stderr.is_empty() {
+            &out.stdout
+        } else {
+            &out.stderr
+        })
+        .trim()
+        .to_owned();
+        (out.status.success() && !ver.is_empty()).then(|| ver)
+    }
+
+    /// Returns [`Tuning`] of the vhost of this [SRS] server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    /// Returns runtime [`Status`] of this [SRS] server.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Resolves once this [SRS] server has failed unrecoverably, so is not
+    /// restarted anymore.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub async fn failed(&self) {
+        let _ = self.status.failed.signal().wait_for(true).await;
+    }
+}
+
+/// Runtime status of a spawned [SRS] server process.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    /// Indicator whether [SRS] process is running at the moment.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub running: Mutable<bool>,
+
+    /// ID of the running [SRS] process, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub pid: Mutable<Option<u32>>,
+
+    /// Moment when the running [SRS] process has been started, if any.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub started_at: Mutable<Option<Instant>>,
+
+    /// Number of times [SRS] process has been restarted after its unexpected
+    /// exit.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub restarts: Mutable<u32>,
+
+    /// Indicator whether [SRS] process keeps crashing right after start, so
+    /// is not restarted anymore.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub failed: Mutable<bool>,
+}
+
+/// Reads [SRS] log lines from the given `src` and re-emits them via
+/// [`ephyr_log`] with `srs` target, until `src` is exhausted.
+///
+/// [SRS]: https://github.com/ossrs/srs
+async fn forward_log<R: AsyncRead + Unpin>(src: R) {
+    let logger = log::logger().new(slog::o!("target" => "srs"));
+    let mut src = io::BufReader::new(src);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match src.read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Failed to read SRS server log: {}", e);
+                break;
+            }
+        }
+        diagnostics::History::global().record_logs("srs", &line);
+        let (lvl, msg) = parse_log_line(line.trim_end());
+        if msg.is_empty() {
+            continue;
+        }
+        match lvl {
+            slog::Level::Critical => slog::crit!(logger, "{}", msg),
+            slog::Level::Error => slog::error!(logger, "{}", msg),
+            slog::Level::Warning => slog::warn!(logger, "{}", msg),
+            slog::Level::Info => slog::info!(logger, "{}", msg),
+            slog::Level::Debug => slog::debug!(logger, "{}", msg),
+            slog::Level::Trace => slog::trace!(logger, "{}", msg),
+        }
+    }
+}
+
+/// Parses the given [SRS] log `line` into its severity and message.
+///
+/// [SRS] log line looks like `[<timestamp>][<level>][<pid>][<cid>] <msg>`.
+/// The timestamp is omitted, as our logger has its own one. Lines of unknown
+/// format are considered informational.
+///
+/// Note, that chatty [SRS] levels are mapped down, so [SRS]'s `Trace` becomes
+/// [`slog::Level::Debug`], and anything more verbose becomes
+/// [`slog::Level::Trace`] (see [`LogLevel`] for the reverse mapping).
+///
+/// [SRS]: https://github.com/ossrs/srs
+fn parse_log_line(line: &str) -> (slog::Level, &str) {
+    let mut parts = line.splitn(3, ']');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(ts), Some(lvl), Some(msg))
+            if ts.starts_with('[') && lvl.starts_with('[') =>
+        {
+            let lvl = match &lvl[1..] {
+                "Error" => slog::Level::Error,
+                "Warn" => slog::Level::Warning,
+                "Trace" => slog::Level::Debug,
+                _ => slog::Level::Trace,
+            };
+            (lvl, msg.trim_start())
+        }
+        _ => (slog::Level::Info, line),
     }
 }
 
@@ -225,20 +648,305 @@ impl Drop for ClientId {
 #[derive(Clone, Debug, Template)]
 #[template(path = "restreamer.srs.conf.j2", escape = "none")]
 pub struct Config {
-    /// Port that [HTTP Callback API][1] is exposed on.
+    /// Port that [SRS] accepts RTMP connections on.
     ///
-    /// [1]: https://en.wikipedia.org/wiki/Basic_access_authentication
-    pub callback_port: u16,
+    /// [SRS]: https://github.com/ossrs/srs
+    pub rtmp_port: u16,
+
+    /// IP address that [SRS] accepts RTMP connections on, in addition to the
+    /// `127.0.0.1` one always used by local re-streaming processes.
+    ///
+    /// If [`None`], then RTMP connections are accepted on all interfaces.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub rtmp_bind_ip: Option<IpAddr>,
+
+    /// Port that [SRS] [HTTP API][1] is exposed on.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPApi
+    pub http_api_port: u16,
+
+    /// Port that [SRS] HTTP server is exposed on.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub http_server_port: u16,
+
+    /// URL that [HTTP Callback API][1] is exposed on.
+    ///
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
+    pub callback_url: String,
 
     /// Path to the directory served by [SRS] HTTP server (HLS chunks, etc).
     ///
     /// [SRS]: https://github.com/ossrs/srs
     pub http_server_dir: DisplayablePath,
 
+    /// Indicator whether [SRS] should serve live streams via [HTTP-FLV] on its
+    /// HTTP server.
+    ///
+    /// [HTTP-FLV]: https://github.com/ossrs/srs/wiki/v3_EN_DeliveryHttpStream
+    /// [SRS]: https://github.com/ossrs/srs
+    pub http_flv: bool,
+
     /// Severity of [SRS] server logs.
     ///
     /// [SRS]: https://github.com/ossrs/srs
     pub log_level: LogLevel,
+
+    /// [`Tuning`] of [SRS] vhost serving live streams.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub tuning: Tuning,
+
+    /// [SRS] vhosts dedicated to separate apps (see [`low_latency_vhost()`]
+    /// and [`hls_vhost()`]).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub vhosts: Vec<Vhost>,
+}
+
+/// [SRS] vhost dedicated to a single app.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Vhost {
+    /// Name of this [`Vhost`].
+    ///
+    /// Should be a valid [SRS] configuration token.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub name: String,
+
+    /// Indicator whether this [`Vhost`] is served with
+    /// [`Config::low_latency_tuning()`].
+    pub low_latency: bool,
+
+    /// Indicator whether [SRS] serves live streams of this [`Vhost`] via
+    /// [HLS] in the [`HLS_DIR`].
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    pub hls: bool,
+}
+
+impl Config {
+    /// Returns value of the `listen` directive of [SRS] configuration,
+    /// accepting RTMP connections on [`Config::rtmp_port`] and, if specified,
+    /// [`Config::rtmp_bind_ip`] only.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn rtmp_listen(&self) -> String {
+        let port = self.rtmp_port;
+        match self.rtmp_bind_ip.filter(|ip| !ip.is_unspecified()) {
+            Some(ip) if ip.is_loopback() => {
+                SocketAddr::new(ip, port).to_string()
+            }
+            Some(ip) => {
+                format!("127.0.0.1:{} {}", port, SocketAddr::new(ip, port))
+            }
+            None => port.to_string(),
+        }
+    }
+
+    /// Returns [`Tuning`] of [SRS] vhosts dedicated to low-latency apps: the
+    /// [`Config::tuning`] without GOP cache and with
+    /// [`Tuning::min_latency`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn low_latency_tuning(&self) -> Tuning {
+        Tuning {
+            mr: false,
+            min_latency: true,
+            gop_cache: false,
+            ..self.tuning
+        }
+    }
+
+    /// Returns [`Tuning`] of the given dedicated [`Vhost`].
+    #[inline]
+    #[must_use]
+    pub fn vhost_tuning(&self, vhost: &Vhost) -> Tuning {
+        if vhost.low_latency {
+            self.low_latency_tuning()
+        } else {
+            self.tuning
+        }
+    }
+
+    /// Returns name of the directory inside the [`Config::http_server_dir`]
+    /// where [SRS] writes [HLS] files (see [`HLS_DIR`]).
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[allow(clippy::unused_self)]
+    #[inline]
+    #[must_use]
+    pub fn hls_dir_name(&self) -> &'static str {
+        HLS_DIR
+    }
+
+    /// Returns path to the directory where [SRS] writes [HLS] playlists and
+    /// segments of the given `app` served on its [`hls_vhost()`].
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn hls_dir(&self, app: &str) -> PathBuf {
+        self.http_server_dir.join(HLS_DIR).join(app)
+    }
+
+    /// Renders this [`Config`] into the given [SRS] configuration file.
+    ///
+    /// Running [SRS] server doesn't catch up the changes automatically, so
+    /// should be [reloaded][`Server::reload`] explicitly.
+    ///
+    /// # Errors
+    ///
+    /// If [SRS] configuration file fails to be rendered or written.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub async fn write_to<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> anyhow::Result<()> {
+        fs::write(
+            path,
+            self.render().map_err(|e| {
+                anyhow!("Failed to render SRS config from template: {}", e)
+            })?,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to write SRS config file: {}", e))
+    }
+}
+
+/// Tunables of [SRS] vhost, affecting latency of live streams passing
+/// through it.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tuning {
+    /// Size (in bytes) of [RTMP chunks][1].
+    ///
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_LowLatency
+    pub chunk_size: u32,
+
+    /// Maximum length (in seconds) of the queue of a single player, exceeding
+    /// which drops the queued media.
+    pub queue_length: u32,
+
+    /// Indicator whether [merged-read][1] of publishers is enabled, saving
+    /// CPU at the cost of latency.
+    ///
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_LowLatency#merged-read
+    pub mr: bool,
+
+    /// Indicator whether [SRS] prefers lower latency over throughput.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub min_latency: bool,
+
+    /// Indicator whether the last GOP is cached, so players start right
+    /// away from a keyframe, at the cost of latency.
+    pub gop_cache: bool,
+}
+
+impl Tuning {
+    /// Range of allowed [`Tuning::chunk_size`]s (as [SRS] accepts).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub const CHUNK_SIZES: RangeInclusive<u32> = 128..=65536;
+
+    /// Range of allowed [`Tuning::queue_length`]s.
+    pub const QUEUE_LENGTHS: RangeInclusive<u32> = 1..=300;
+
+    /// Validates this [`Tuning`] to contain values in the allowed ranges.
+    ///
+    /// # Errors
+    ///
+    /// If any value is out of its allowed range.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !Self::CHUNK_SIZES.contains(&self.chunk_size) {
+            return Err(anyhow!(
+                "SRS chunk size should be in {}..={} range, but is {}",
+                Self::CHUNK_SIZES.start(),
+                Self::CHUNK_SIZES.end(),
+                self.chunk_size,
+            ));
+        }
+        if !Self::QUEUE_LENGTHS.contains(&self.queue_length) {
+            return Err(anyhow!(
+                "SRS queue length should be in {}..={} range, but is {}",
+                Self::QUEUE_LENGTHS.start(),
+                Self::QUEUE_LENGTHS.end(),
+                self.queue_length,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Profile of [SRS] vhost [`Tuning`], for not configuring each tunable
+/// separately.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq, SmartDefault)]
+pub enum LatencyProfile {
+    /// Defaults of [SRS], favoring smooth playback and fast start of players.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[default]
+    #[display(fmt = "default")]
+    Default,
+
+    /// [Low latency][1] setup, favoring minimal delay over throughput.
+    ///
+    /// [1]: https://github.com/ossrs/srs/wiki/v3_EN_LowLatency
+    #[display(fmt = "low")]
+    Low,
+}
+
+impl LatencyProfile {
+    /// Returns the [`Tuning`] of this [`LatencyProfile`].
+    #[must_use]
+    pub fn tuning(self) -> Tuning {
+        match self {
+            Self::Default => Tuning {
+                chunk_size: 60000,
+                queue_length: 30,
+                mr: false,
+                min_latency: false,
+                gop_cache: true,
+            },
+            Self::Low => Tuning {
+                chunk_size: 4096,
+                queue_length: 5,
+                mr: false,
+                min_latency: true,
+                gop_cache: false,
+            },
+        }
+    }
+}
+
+impl FromStr for LatencyProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "low" => Ok(Self::Low),
+            _ => Err(anyhow!(
+                "'{}' is invalid latency profile, allowed profiles are: \
+                 default | low",
+                s,
+            )),
+        }
+    }
 }
 
 /// Severity of [SRS] [server logs][1].
@@ -299,3 +1007,197 @@ impl From<slog::Level> for LogLevel {
 #[as_ref(forward)]
 #[display(fmt = "{}", "_0.display()")]
 pub struct DisplayablePath(PathBuf);
+
+#[cfg(test)]
+mod tuning_spec {
+    use super::{LatencyProfile, Tuning};
+
+    #[test]
+    fn profiles_are_valid() {
+        for profile in &[LatencyProfile::Default, LatencyProfile::Low] {
+            assert!(profile.tuning().validate().is_ok(), "for: {}", profile);
+            let parsed: LatencyProfile = profile.to_string().parse().unwrap();
+            assert_eq!(parsed, *profile);
+        }
+        assert!("lowest".parse::<LatencyProfile>().is_err());
+    }
+
+    #[test]
+    fn validates_ranges() {
+        let valid = LatencyProfile::Default.tuning();
+        for invalid in &[
+            Tuning {
+                chunk_size: 127,
+                ..valid
+            },
+            Tuning {
+                chunk_size: 65537,
+                ..valid
+            },
+            Tuning {
+                queue_length: 0,
+                ..valid
+            },
+            Tuning {
+                queue_length: 301,
+                ..valid
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "for: {:?}", invalid);
+        }
+        assert!(Tuning {
+            chunk_size: 128,
+            queue_length: 300,
+            ..valid
+        }
+        .validate()
+        .is_ok());
+    }
+}
+
+#[cfg(test)]
+mod config_spec {
+    use std::path::PathBuf;
+
+    use askama::Template as _;
+    use serde_json::json;
+
+    use crate::{spec, state};
+
+    use super::{Config, LatencyProfile, LogLevel, Vhost};
+
+    fn config(vhosts: Vec<Vhost>) -> Config {
+        Config {
+            rtmp_port: 1935,
+            rtmp_bind_ip: None,
+            http_api_port: 8002,
+            http_server_port: 8000,
+            callback_url: "http://127.0.0.1:8081/".into(),
+            http_server_dir: PathBuf::from("/var/www/srs").into(),
+            http_flv: true,
+            log_level: LogLevel::default(),
+            tuning: LatencyProfile::Default.tuning(),
+            vhosts,
+        }
+    }
+
+    fn vhost(name: &str, low_latency: bool, hls: bool) -> Vhost {
+        Vhost {
+            name: name.into(),
+            low_latency,
+            hls,
+        }
+    }
+
+    /// Returns the block of the given `vhost` in the rendered `conf`.
+    fn vhost_block<'c>(conf: &'c str, vhost: &str) -> &'c str {
+        let start = conf.find(&format!("vhost {} {{", vhost)).unwrap();
+        &conf[start..start + conf[start..].find("\n}\n").unwrap()]
+    }
+
+    #[test]
+    fn renders_low_latency_vhosts() {
+        let conf = config(vec![vhost("live.lowlatency", true, false)])
+            .render()
+            .unwrap();
+        let default = &conf[..conf.find("vhost live.lowlatency {").unwrap()];
+        let vhost = vhost_block(&conf, "live.lowlatency");
+
+        assert!(default.contains("gop_cache       on;"), "{}", default);
+        assert!(vhost.contains("gop_cache       off;"), "{}", vhost);
+        assert!(vhost.contains("min_latency    on;"), "{}", vhost);
+        assert!(vhost.contains("on_publish      http://127.0.0.1:8081/;"));
+        assert!(!vhost.contains("hls"), "{}", vhost);
+
+        assert!(!config(vec![]).render().unwrap().contains("lowlatency"));
+    }
+
+    #[test]
+    fn renders_hls_vhosts() {
+        let conf = config(vec![
+            vhost("live.hls", false, true),
+            vhost("news.lowlatency", true, true),
+        ])
+        .render()
+        .unwrap();
+
+        let vhost = vhost_block(&conf, "live.hls");
+        assert!(vhost.contains("gop_cache       on;"), "{}", vhost);
+        assert!(vhost.contains("on_hls          http://127.0.0.1:8081/;"));
+        assert!(vhost.contains("hls_path           /var/www/srs/live;"));
+
+        let vhost = vhost_block(&conf, "news.lowlatency");
+        assert!(vhost.contains("gop_cache       off;"), "{}", vhost);
+        assert!(vhost.contains("hls_path           /var/www/srs/live;"));
+    }
+
+    #[test]
+    fn cannot_be_corrupted_by_labels() {
+        let restream = |label: &str| {
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "guest",
+                "label": label,
+                "low_latency": true,
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+            }))
+        };
+
+        let err = restream("guest;\n}\nvhost evil {\n  enabled on;")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Not a valid Label"), "{}", err);
+
+        let restream =
+            state::Restream::new(restream("guest; } vhost evil {").unwrap());
+        let conf = config(restream.srs_vhost_config().into_iter().collect())
+            .render()
+            .unwrap();
+        assert!(!conf.contains("evil"), "{}", conf);
+        assert_eq!(conf.matches("\nvhost ").count(), 2, "{}", conf);
+        assert!(vhost_block(&conf, "guest.lowlatency")
+            .contains("min_latency    on;"));
+    }
+}
+
+#[cfg(test)]
+mod parse_log_line_spec {
+    use ephyr_log::slog;
+
+    use super::parse_log_line;
+
+    #[test]
+    fn parses_level_and_message() {
+        for (input, expected) in &[
+            (
+                "[2021-03-11 10:00:00.123][Error][1][42] Oops",
+                (slog::Level::Error, "[1][42] Oops"),
+            ),
+            (
+                "[2021-03-11 10:00:00.123][Warn][1][42] Hmm",
+                (slog::Level::Warning, "[1][42] Hmm"),
+            ),
+            (
+                "[2021-03-11 10:00:00.123][Trace][1][42] Ok",
+                (slog::Level::Debug, "[1][42] Ok"),
+            ),
+            (
+                "[2021-03-11 10:00:00.123][Verb][1][42] Ok",
+                (slog::Level::Trace, "[1][42] Ok"),
+            ),
+        ] {
+            assert_eq!(parse_log_line(input), *expected, "for: {}", input);
+        }
+    }
+
+    #[test]
+    fn considers_unknown_format_informational() {
+        for input in &["", "XCORE-SRS/4.0.0", "[not a level"] {
+            assert_eq!(
+                parse_log_line(input),
+                (slog::Level::Info, *input),
+                "for: {}",
+                input,
+            );
+        }
+    }
+}
@@ -1,32 +1,172 @@
 //! Application state.
 
 use std::{
-    borrow::Cow, collections::HashSet, convert::TryInto, future::Future, mem,
-    panic::AssertUnwindSafe, path::Path, time::Duration,
+    any::Any,
+    borrow::Cow,
+    collections::{
+        hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque,
+    },
+    convert::TryInto,
+    fmt,
+    fs::Permissions,
+    future::Future,
+    hash::Hasher as _,
+    io, iter, mem,
+    net::{IpAddr, Ipv6Addr},
+    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+use aes::Aes256;
 use anyhow::anyhow;
-use derive_more::{Deref, Display, From, Into};
-use ephyr_log::log;
+use backoff::{backoff::Backoff as _, ExponentialBackoff};
+use chrono::{LocalResult, NaiveDateTime, Offset as _, TimeZone as _, Utc};
+use chrono_tz::Tz;
+use derive_more::{Deref, Display, Error, From, Into};
+use eax::{
+    aead::{generic_array::GenericArray, Aead as _, NewAead as _},
+    Eax,
+};
+use ephyr_log::{log, slog};
 use futures::{
-    future::TryFutureExt as _,
-    sink,
-    stream::{StreamExt as _, TryStreamExt as _},
+    future::{self, BoxFuture, FutureExt as _},
+    stream::{Stream, StreamExt as _, TryStreamExt as _},
+};
+use futures_signals::{
+    map_ref,
+    signal::{Mutable, MutableLockRef, Signal, SignalExt as _},
 };
-use futures_signals::signal::{Mutable, SignalExt as _};
 use juniper::{
-    graphql_scalar, GraphQLEnum, GraphQLObject, GraphQLScalarValue,
-    GraphQLUnion, ParseScalarResult, ParseScalarValue, ScalarValue, Value,
+    graphql_object, graphql_scalar, GraphQLEnum, GraphQLObject,
+    GraphQLScalarValue, GraphQLUnion, ParseScalarResult, ParseScalarValue,
+    ScalarValue, Value,
 };
 use once_cell::sync::Lazy;
+use rand::Rng as _;
 use regex::Regex;
-use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Deserialize, Deserializer, Serialize,
+};
 use smart_default::SmartDefault;
-use tokio::{fs, io::AsyncReadExt as _};
+use tokio::{
+    fs,
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    sync::mpsc,
+    time,
+};
 use url::Url;
 use uuid::Uuid;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{
+    api::graphql::{Context, DateTime},
+    capacity,
+    coalesce::LogCoalescer,
+    display_panic,
+    extension::{StateSnapshot, StatusEvent, StatusTracker},
+    fault, ffmpeg, paths,
+    proxy::IpRange,
+    schedule::{InputSchedule, ScheduleRange, ScheduleWindow},
+    serde::is_false,
+    server, spec, srs, text, trace,
+    workflow::Workflow,
+    Spec,
+};
+
+/// Result of reloading a [`Spec`] into a [`State`].
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct SpecReload {
+    /// Moment of time when the reloading happened.
+    pub at: DateTime,
+
+    /// Error the reloading has failed with, if any.
+    pub error: Option<String>,
+
+    /// Number of `Restream`s added by the reloading.
+    pub added: i32,
+
+    /// Number of existing `Restream`s updated by the reloading.
+    pub updated: i32,
+
+    /// Number of `Restream`s removed by the reloading.
+    pub removed: i32,
+}
+
+impl SpecReload {
+    /// Creates a new [`SpecReload`] failed with the given `error`.
+    #[inline]
+    #[must_use]
+    pub fn failed<E: ToString>(error: E) -> Self {
+        Self {
+            at: DateTime::now(),
+            error: Some(error.to_string()),
+            added: 0,
+            updated: 0,
+            removed: 0,
+        }
+    }
+}
+
+/// Status of replicating a [`State`] from a primary server, being a standby
+/// one.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct Replication {
+    /// URL of the primary server being replicated from, without credentials.
+    pub source: String,
+
+    /// Indicator whether this server has been promoted to a primary one, so
+    /// doesn't replicate anymore and accepts local edits.
+    pub promoted: bool,
+
+    /// Indicator whether the last attempt to reach the primary server has
+    /// succeeded.
+    pub connected: bool,
+
+    /// Moment of time when the state of the primary server has been applied
+    /// the last time.
+    ///
+    /// `null` if it hasn't been applied yet.
+    pub synced_at: Option<DateTime>,
+
+    /// Error the last attempt to reach the primary server has failed with, if
+    /// any.
+    pub error: Option<String>,
 
-use crate::{display_panic, serde::is_false, spec, srs, Spec};
+    /// Number of consecutive failed attempts to reach the primary server.
+    pub failures: i32,
+
+    /// IDs of the [`Output`]s being enabled on the primary server, which are
+    /// enabled once this server is promoted (see [`State::promote()`]).
+    #[graphql(skip)]
+    pub enabled_outputs: Vec<OutputId>,
+}
+
+impl Replication {
+    /// Creates a new not yet connected [`Replication`] from the given
+    /// `source` URL.
+    #[must_use]
+    pub fn new(source: &Url) -> Self {
+        let mut source = source.clone();
+        let _ = source.set_username("");
+        let _ = source.set_password(None);
+        Self {
+            source: source.to_string(),
+            promoted: false,
+            connected: false,
+            synced_at: None,
+            error: None,
+            failures: 0,
+            enabled_outputs: vec![],
+        }
+    }
+}
 
 /// Server's settings.
 ///
@@ -37,6 +177,13 @@ pub struct Settings {
     /// public APIs.
     pub password_hash: Option<String>,
 
+    /// [`argon2`] hash of password which grants read-only access to this
+    /// application's public APIs.
+    ///
+    /// Has no effect if [`Settings::password_hash`] is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewer_password_hash: Option<String>,
+
     /// Title for the server
     /// It is used for differentiating servers on UI side if multiple servers
     /// are used.
@@ -49,6 +196,41 @@ pub struct Settings {
     /// Whether do we need to confirm enabling/disabling of inputs or outputs
     /// If `true` we should confirm, `false` - do not confirm
     pub enable_confirmation: Option<bool>,
+
+    /// Verbosity level of the server logs explicitly persisted to survive
+    /// restarts.
+    ///
+    /// If [`None`], then the one specified via CLI (or the default one) is
+    /// used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LogLevel>,
+
+    /// Generation of browser sessions, which their tokens are bound to.
+    ///
+    /// Incrementing it invalidates all the existing browser sessions.
+    #[serde(default)]
+    pub session_generation: u64,
+
+    /// Indicator whether adding (or editing) an [`Output`] with an
+    /// [`Output::dst`] equivalent to the one of an [`Output`] in another
+    /// [`Restream`] should be rejected, rather than just reported via
+    /// [`Output::conflicts_with`].
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub reject_dst_conflicts: bool,
+
+    /// [`TimeZone`] that clients should display timestamps and schedules in.
+    ///
+    /// It affects displaying only, as all the timestamps are stored in UTC.
+    /// If [`None`], then UTC is displayed too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_timezone: Option<TimeZone>,
+
+    /// The last emergency [`PasswordReset`] of [`Settings::password_hash`],
+    /// if any.
+    ///
+    /// Is kept for auditing only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_reset: Option<PasswordReset>,
 }
 
 impl Settings {
@@ -70,15 +252,161 @@ impl Settings {
         self.delete_confirmation = new.delete_confirmation;
         self.enable_confirmation = new.enable_confirmation;
     }
+
+    /// Exports the credentials of this [`Settings`] as
+    /// [`spec::v1::Credentials`].
+    #[inline]
+    #[must_use]
+    pub fn export_credentials(&self) -> spec::v1::Credentials {
+        spec::v1::Credentials {
+            password_hash: self.password_hash.clone(),
+            viewer_password_hash: self.viewer_password_hash.clone(),
+        }
+    }
+
+    /// Applies the given [`spec::v1::Credentials`] to this [`Settings`],
+    /// invalidating all the existing browser sessions.
+    pub fn apply_credentials(&mut self, new: spec::v1::Credentials) {
+        self.password_hash = new.password_hash;
+        // Viewer password has no effect without the admin one.
+        self.viewer_password_hash = new
+            .viewer_password_hash
+            .filter(|_| self.password_hash.is_some());
+        self.session_generation = self.session_generation.wrapping_add(1);
+    }
+
+    /// Resets [`Settings::password_hash`] to the given `hash` in emergency
+    /// (clearing it, if [`None`]), recording the [`PasswordReset`] performed
+    /// `via` the given way.
+    ///
+    /// [`Settings::viewer_password_hash`] is cleared too, and all the existing
+    /// browser sessions are invalidated.
+    pub fn reset_password(
+        &mut self,
+        hash: Option<String>,
+        via: PasswordResetVia,
+    ) {
+        let cleared = hash.is_none();
+        self.apply_credentials(spec::v1::Credentials {
+            password_hash: hash,
+            viewer_password_hash: None,
+        });
+        self.password_reset = Some(PasswordReset {
+            at: DateTime::now(),
+            via,
+            cleared,
+        });
+    }
+}
+
+/// Emergency reset of a lost [`Settings::password_hash`], bypassing the usual
+/// verification of the old password.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PasswordReset {
+    /// Moment this [`PasswordReset`] has been performed at.
+    pub at: DateTime,
+
+    /// Way this [`PasswordReset`] has been performed via.
+    pub via: PasswordResetVia,
+
+    /// Indicator whether the password has been cleared, rather than replaced.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cleared: bool,
+}
+
+/// Way a [`PasswordReset`] is performed via.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordResetVia {
+    /// `reset-password` CLI command run while the server is stopped.
+    #[display(fmt = "`reset-password` command")]
+    Command,
+
+    /// HTTP request from a loopback connection, allowed by
+    /// [`cli::Opts::allow_local_reset`].
+    ///
+    /// [`cli::Opts::allow_local_reset`]: crate::cli::Opts::allow_local_reset
+    #[display(fmt = "local HTTP request")]
+    LocalRequest,
 }
 
 impl Default for Settings {
     fn default() -> Settings {
         Settings {
             password_hash: None,
+            viewer_password_hash: None,
             title: None,
             delete_confirmation: Some(true),
             enable_confirmation: Some(true),
+            log_level: None,
+            session_generation: 0,
+            reject_dst_conflicts: false,
+            display_timezone: None,
+            password_reset: None,
+        }
+    }
+}
+
+/// Verbosity level of the server logs.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    PartialEq,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Only critical errors are logged.
+    Critical,
+
+    /// Errors are logged.
+    Error,
+
+    /// Warnings and errors are logged.
+    Warning,
+
+    /// Informational messages, warnings and errors are logged.
+    #[default]
+    Info,
+
+    /// Debug messages are logged additionally.
+    Debug,
+
+    /// Everything is logged, including very verbose tracing messages.
+    Trace,
+}
+
+impl From<slog::Level> for LogLevel {
+    #[inline]
+    fn from(lvl: slog::Level) -> Self {
+        match lvl {
+            slog::Level::Critical => Self::Critical,
+            slog::Level::Error => Self::Error,
+            slog::Level::Warning => Self::Warning,
+            slog::Level::Info => Self::Info,
+            slog::Level::Debug => Self::Debug,
+            slog::Level::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for slog::Level {
+    #[inline]
+    fn from(lvl: LogLevel) -> Self {
+        match lvl {
+            LogLevel::Critical => Self::Critical,
+            LogLevel::Error => Self::Error,
+            LogLevel::Warning => Self::Warning,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
         }
     }
 }
@@ -93,6 +421,184 @@ pub struct State {
 
     /// All [`Restream`]s performed by this application.
     pub restreams: Mutable<Vec<Restream>>,
+
+    /// Removed [`Restream`]s, kept (not performed) for being restored until
+    /// purged.
+    ///
+    /// See [`Restream::deleted_at`].
+    #[serde(default)]
+    pub trash: Mutable<Vec<Restream>>,
+
+    /// Current verbosity level of the server logs.
+    ///
+    /// Is not persisted, unless is explicitly saved into
+    /// [`Settings::log_level`].
+    #[serde(skip)]
+    pub log_level: Mutable<LogLevel>,
+
+    /// Indicator whether this server is draining, so rejects new publishers
+    /// and doesn't spawn new re-streaming processes, while letting the
+    /// running ones continue.
+    ///
+    /// Is never persisted, so the server always starts in normal mode.
+    #[serde(skip)]
+    pub draining: Mutable<bool>,
+
+    /// Indicator whether [GraphQL Playground][1] is served by this server.
+    ///
+    /// Is never persisted, as is initialized with [`cli::Opts::debug`] on
+    /// every server start.
+    ///
+    /// [`cli::Opts::debug`]: crate::cli::Opts::debug
+    /// [1]: https://github.com/graphql/graphql-playground
+    #[serde(skip)]
+    pub playground_enabled: Mutable<bool>,
+
+    /// [`ffmpeg::Capabilities`] of the used [FFmpeg] binary, once detected.
+    ///
+    /// Is never persisted, as is re-detected on every server start.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(skip)]
+    pub ffmpeg_capabilities: Mutable<Option<Arc<ffmpeg::Capabilities>>>,
+
+    /// [`ffmpeg::Requirement`]s of the current re-streaming, missing in the
+    /// [`State::ffmpeg_capabilities`], so preventing the correspondent
+    /// [FFmpeg] processes from being spawned.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(skip)]
+    pub missing_ffmpeg_capabilities: Mutable<Vec<ffmpeg::Requirement>>,
+
+    /// Result of the last [`Spec`] reloading (see [`State::reload()`]), if
+    /// any.
+    ///
+    /// Is never persisted.
+    #[serde(skip)]
+    pub spec_reload: Mutable<Option<SpecReload>>,
+
+    /// [`Replication`] of this [`State`] from a primary server, if this server
+    /// is a standby one.
+    ///
+    /// Is never persisted, as is configured on every server start.
+    #[serde(skip)]
+    pub replication: Mutable<Option<Replication>>,
+
+    /// Indicator whether this server is connected to the event broker it
+    /// publishes status changes to (see [`cli::Opts::event_broker`]).
+    ///
+    /// [`None`] if no event broker is configured. Is never persisted.
+    ///
+    /// [`cli::Opts::event_broker`]: crate::cli::Opts::event_broker
+    #[serde(skip)]
+    pub event_broker_connected: Mutable<Option<bool>>,
+
+    /// [`Quota`] of [`Restream`]s and [`Output`]s allowed in this [`State`].
+    ///
+    /// Is not persisted, as is configured on every server start.
+    #[serde(skip)]
+    pub quota: Mutable<Quota>,
+
+    /// Directory confining all the media files referred by [`Spec`]s applied
+    /// via [`State::apply_checked()`], if any (see [`cli::Opts::media_root`]).
+    ///
+    /// Is not persisted, as is configured on every server start.
+    ///
+    /// [`cli::Opts::media_root`]: crate::cli::Opts::media_root
+    #[serde(skip)]
+    pub media_root: Mutable<Option<PathBuf>>,
+
+    /// [`StatusDebouncer`] of [`Output`]s and [`InputEndpoint`]s.
+    ///
+    /// Its [`StatusDebouncer::grace`] is not persisted, as is configured on
+    /// every server start.
+    #[serde(skip)]
+    pub status_debouncer: StatusDebouncer,
+
+    /// [`StatusRegistry`] of [`Output`]s and [`InputEndpoint`]s.
+    ///
+    /// Is never persisted, as is runtime only.
+    #[serde(skip)]
+    pub statuses: StatusRegistry,
+
+    /// [`ffmpeg::SpeedThreshold`] of re-encoding [FFmpeg] processes.
+    ///
+    /// Is not persisted, as is configured on every server start.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(skip)]
+    pub speed_threshold: Mutable<ffmpeg::SpeedThreshold>,
+
+    /// [`ffmpeg::PoolView`] of [FFmpeg] processes managed by this server at
+    /// the moment.
+    ///
+    /// Is diagnostic only, so is never persisted.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(skip)]
+    pub ffmpeg_pool: ffmpeg::PoolView,
+
+    /// Cached [`RestreamsVersion`] of [`State::restreams`].
+    ///
+    /// Is never persisted, as is recalculated on demand.
+    #[serde(skip)]
+    pub restreams_version: RestreamsVersion,
+
+    /// Cached [`capacity::Counts`] of [`State::restreams`].
+    ///
+    /// Is never persisted, as is recalculated on demand.
+    #[serde(skip)]
+    pub restreams_counts: capacity::CountsCache,
+
+    /// [`ChangeFeed`] of [`State::restreams`].
+    ///
+    /// Is never persisted, so cursors older than the server start require a
+    /// full resync.
+    #[serde(skip)]
+    pub changes: ChangeFeed,
+
+    /// [`capacity::Config`] of calculating the capacity score of this server.
+    ///
+    /// Is not persisted, as is configured on every server start.
+    #[serde(skip)]
+    pub capacity: Mutable<capacity::Config>,
+
+    /// [`PersistenceHealth`] of persisting this [`State`].
+    ///
+    /// Is never persisted, as is diagnostic only.
+    #[serde(skip)]
+    pub persistence_health: Mutable<PersistenceHealth>,
+
+    /// [`LogCoalescer`] of repeated log messages of this server.
+    ///
+    /// Its [`LogCoalescer::window()`] is not persisted, as is configured on
+    /// every server start.
+    #[serde(skip)]
+    pub log_coalescer: LogCoalescer,
+
+    /// Negative cache of [SRS] HTTP callbacks rejected because of their
+    /// `app`.
+    ///
+    /// Is never persisted, as is relevant for the running server only.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(skip)]
+    pub callback_rejections: server::callback::Rejections,
+
+    /// [`Fault`]s injected into this [`State`] for rehearsing failures
+    /// handling.
+    ///
+    /// Is never persisted, as every [`Fault`] is short-living.
+    ///
+    /// [`Fault`]: fault::Fault
+    #[serde(skip)]
+    pub faults: fault::Faults,
+
+    /// [`ConnectionHistory`] of [`State::restreams`].
+    ///
+    /// Is never persisted, so the history is reset on the server restart.
+    #[serde(skip)]
+    pub connection_history: ConnectionHistory,
 }
 
 impl State {
@@ -105,2019 +611,14471 @@ impl State {
     pub async fn try_new<P: AsRef<Path>>(
         file: P,
     ) -> Result<Self, anyhow::Error> {
-        let file = file.as_ref();
-
-        let mut contents = vec![];
-        let _ = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .read(true)
-            .open(&file)
-            .await
-            .map_err(|e| {
-                anyhow!("Failed to open '{}' file: {}", file.display(), e)
-            })?
-            .read_to_end(&mut contents)
-            .await
-            .map_err(|e| {
-                anyhow!("Failed to read '{}' file: {}", file.display(), e)
-            })?;
+        Self::try_new_with(Arc::new(FilePersistence::new(file.as_ref()))).await
+    }
+
+    /// Instantiates a new [`State`] loading it from the given `persistence`
+    /// (if anything has been persisted there) and performing all the required
+    /// inner subscriptions.
+    ///
+    /// # Errors
+    ///
+    /// If [`State`] fails to be loaded from the `persistence`, or to be
+    /// parsed.
+    pub async fn try_new_with(
+        persistence: Arc<dyn StatePersistence>,
+    ) -> Result<Self, anyhow::Error> {
+        let contents = persistence.load().await?;
 
         let state = if contents.is_empty() {
             State::default()
         } else {
             serde_json::from_slice(&contents).map_err(|e| {
                 anyhow!(
-                    "Failed to deserialize state from '{}' file: {}",
-                    file.display(),
+                    "Failed to deserialize state from {}: {}",
+                    persistence,
                     e,
                 )
             })?
         };
 
-        let (file, persisted_state) = (file.to_owned(), state.clone());
-        let persist_state1 = move || {
-            fs::write(
-                file.clone(),
-                serde_json::to_vec(&persisted_state)
-                    .expect("Failed to serialize server state"),
-            )
-            .map_err(|e| log::error!("Failed to persist server state: {}", e))
-        };
-        let persist_state2 = persist_state1.clone();
-        Self::on_change("persist_restreams", &state.restreams, move |_| {
-            persist_state1()
+        for o in state
+            .restreams
+            .lock_mut()
+            .iter_mut()
+            .flat_map(|r| r.outputs.iter_mut())
+        {
+            o.apply_boot_policy();
+        }
+        // Trashed `Restream`s keep their positions to be restored into, while
+        // being ordered by their removal.
+        normalize_positions(&mut state.restreams.lock_mut());
+        for restreams in &[&state.restreams, &state.trash] {
+            for r in restreams.lock_mut().iter_mut() {
+                normalize_positions(&mut r.outputs);
+            }
+        }
+
+        // Changes are coalesced, as every persisting takes the latest snapshot
+        // of the `State` anyway.
+        let (subscribed, persisted) = (state.clone(), state.clone());
+        Self::spawn_hook(
+            "persist",
+            move || subscribed.subscribe_changes(),
+            move |snapshot| {
+                persisted
+                    .clone()
+                    .persist(Arc::clone(&persistence), snapshot)
+            },
+        );
+
+        state.changes.start(&state.restreams.lock_ref());
+        // Digests are calculated once per change, being both deduplicated
+        // and recorded.
+        let (restreams, changes) =
+            (state.restreams.clone(), state.changes.clone());
+        Self::spawn_hook(
+            "journal_restreams",
+            move || {
+                restreams
+                    .signal_ref(|r| ChangeFeed::digests(r))
+                    .dedupe_cloned()
+                    .to_stream()
+            },
+            move |digests| {
+                changes.record(&digests);
+                future::ready(())
+            },
+        );
+        let changes = state.changes.clone();
+        Self::on_persisted_change(
+            "journal_settings",
+            &state.settings,
+            move || {
+                changes.bump();
+                future::ready(())
+            },
+        );
+        let changes = state.changes.clone();
+        Self::on_persisted_change("journal_trash", &state.trash, move || {
+            changes.bump();
+            future::ready(())
+        });
+
+        // Removed `Restream`s are forgotten, so their history doesn't pile up.
+        let (restreams, history) =
+            (state.restreams.clone(), state.connection_history.clone());
+        Self::spawn_hook(
+            "prune_connection_history",
+            move || {
+                restreams
+                    .signal_ref(|r| r.iter().map(|r| r.id).collect())
+                    .dedupe_cloned()
+                    .to_stream()
+            },
+            move |ids: HashSet<_>| {
+                history.retain(&ids);
+                future::ready(())
+            },
+        );
+
+        // Removed entities are forgotten, so their raw statuses don't pile up.
+        let (restreams, debouncer) =
+            (state.restreams.clone(), state.status_debouncer.clone());
+        Self::spawn_hook(
+            "prune_status_debouncer",
+            move || {
+                restreams
+                    .signal_ref(|restreams| {
+                        let mut ids = HashSet::new();
+                        for r in restreams {
+                            r.collect_status_ids(&mut ids);
+                        }
+                        ids
+                    })
+                    .dedupe_cloned()
+                    .to_stream()
+            },
+            move |ids| {
+                debouncer.retain(&ids);
+                future::ready(())
+            },
+        );
+
+        let refreshed_state = state.clone();
+        Self::on_change("refresh_dst_conflicts", &state.restreams, move |_| {
+            refreshed_state.refresh_dst_conflicts();
+            future::ready(())
         });
-        Self::on_change("persist_settings", &state.settings, move |_| {
-            persist_state2()
+
+        let (version, counts) = (
+            state.restreams_version.clone(),
+            state.restreams_counts.clone(),
+        );
+        state.on_restreams_change("invalidate_version", move |_, _| {
+            version.invalidate();
+            counts.invalidate();
+            future::ready(())
         });
 
         Ok(state)
     }
 
-    /// Applies the given [`Spec`] to this [`State`].
+    /// Persists the given [`StateSnapshot`] (emitted by
+    /// [`State::subscribe_changes()`]) into the given `persistence`.
     ///
-    /// If `replace` is `true` then all the [`Restream`]s, [`Restream::outputs`]
-    /// and [`Output::mixins`] will be replaced with new ones, otherwise new
-    /// ones will be merged with already existing ones.
-    pub fn apply(&self, new: spec::v1::Spec, replace: bool) {
-        let mut restreams = self.restreams.lock_mut();
-        if replace {
-            let mut olds = mem::replace(
-                &mut *restreams,
-                Vec::with_capacity(new.restreams.len()),
-            );
-            for new in new.restreams {
-                if let Some(mut old) = olds
-                    .iter()
-                    .enumerate()
-                    .find_map(|(n, o)| (o.key == new.key).then(|| n))
-                    .map(|n| olds.swap_remove(n))
-                {
-                    old.apply(new, replace);
-                    restreams.push(old);
-                } else {
-                    restreams.push(Restream::new(new));
+    /// Failed persisting (including failed serialization) is retried endlessly
+    /// with an [`ExponentialBackoff`], taking the latest [`StateSnapshot`] of
+    /// this [`State`] on every retry, so the latest one is flushed as soon as
+    /// the `persistence` recovers.
+    /// Failures are reported via [`State::persistence_health`].
+    async fn persist(
+        self,
+        persistence: Arc<dyn StatePersistence>,
+        snapshot: StateSnapshot,
+    ) {
+        let mut backoff = ExponentialBackoff {
+            max_interval: PersistenceHealth::MAX_RETRY_INTERVAL,
+            max_elapsed_time: None,
+            ..ExponentialBackoff::default()
+        };
+        let mut snapshot = Some(snapshot);
+        loop {
+            let snapshot = snapshot.take().unwrap_or_else(|| self.snapshot());
+            // Serialization failure is retried too, as the taken snapshot
+            // could be fine once the offending value is edited.
+            let res = match serde_json::to_vec(&snapshot) {
+                Ok(contents) => {
+                    trace::persist(contents.len())
+                        .instrument(persistence.save(contents))
+                        .await
                 }
-            }
-        } else {
-            for new in new.restreams {
-                if let Some(old) =
-                    restreams.iter_mut().find(|o| o.key == new.key)
-                {
-                    old.apply(new, replace);
-                } else {
-                    restreams.push(Restream::new(new));
+                Err(e) => Err(anyhow!("Failed to serialize: {}", e)),
+            };
+            let e = match res {
+                Ok(()) => {
+                    let mut health = self.persistence_health.lock_mut();
+                    if !health.is_healthy() {
+                        log::info!(
+                            "Server state is persisted into {} again",
+                            persistence,
+                        );
+                    }
+                    if health.failures > 0 {
+                        *health = PersistenceHealth::default();
+                    }
+                    break;
                 }
-            }
-        }
-
-        let mut settings = self.settings.lock_mut();
-        if new.settings.is_some() || replace {
-            settings.apply(
-                new.settings.unwrap_or_else(|| Settings::default().export()),
+                Err(e) => e,
+            };
+
+            let delay = backoff.next_backoff().unwrap_or(backoff.max_interval);
+            log::error!(
+                "Failed to persist server state into {}, retrying in \
+                 {:?}: {}",
+                persistence,
+                delay,
+                e,
             );
+            {
+                let mut health = self.persistence_health.lock_mut();
+                health.failures = health.failures.saturating_add(1);
+                health.last_error = Some(e.to_string());
+            }
+            time::delay_for(delay).await;
         }
     }
 
-    /// Exports this [`State`] as a [`spec::v1::Spec`].
+    /// Instantiates a new empty [`State`], not persisted anywhere.
+    ///
+    /// Unlike [`State::try_new_with()`] with [`InMemoryPersistence`], doesn't
+    /// perform any inner subscriptions, so doesn't require an async runtime,
+    /// which makes it handy for unit-testing mutations.
     #[inline]
     #[must_use]
-    pub fn export(&self) -> Spec {
-        spec::v1::Spec {
-            settings: Some(self.settings.get_cloned().export()),
-            restreams: self
-                .restreams
-                .get_cloned()
-                .iter()
-                .map(Restream::export)
-                .collect(),
-        }
-        .into()
+    pub fn new_in_memory() -> Self {
+        Self::default()
     }
 
-    /// Subscribes the specified `hook` to changes of the [`Mutable`] `val`ue.
+    /// Instantiates a new [`State`] via [`State::new_in_memory()`] having a
+    /// single [`Restream`] added, as described in [`State::and_restream()`].
     ///
-    /// `name` is just a convenience for describing the `hook` in logs.
-    pub fn on_change<F, Fut, T>(name: &'static str, val: &Mutable<T>, hook: F)
-    where
-        F: FnMut(T) -> Fut + Send + 'static,
-        Fut: Future + Send + 'static,
-        T: Clone + PartialEq + Send + Sync + 'static,
-    {
-        drop(tokio::spawn(
-            AssertUnwindSafe(
-                val.signal_cloned().dedupe_cloned().to_stream().then(hook),
-            )
-            .catch_unwind()
-            .map_err(move |p| {
-                log::crit!(
-                    "Panicked executing `{}` hook of state: {}",
-                    name,
-                    display_panic(&p),
-                )
-            })
-            .map(|_| Ok(()))
-            .forward(sink::drain()),
-        ));
+    /// # Panics
+    ///
+    /// If the described [`Restream`] is invalid or cannot be added.
+    #[cfg(test)]
+    #[must_use]
+    pub fn with_restream(key: &str, fields: serde_json::Value) -> Self {
+        Self::new_in_memory().and_restream(key, fields)
     }
 
-    /// Adds a new [`Restream`] by the given `spec` to this [`State`].
+    /// Adds to this [`State`] a [`Restream`] with the given `key` and an
+    /// enabled `origin` [`Input`] accepting RTMP, having the given JSON
+    /// [`spec::v1::Restream`] `fields` merged over them, so a test spells out
+    /// only the fields it's about.
     ///
-    /// # Errors
+    /// JSON objects are merged recursively, while any other values (including
+    /// arrays) replace the default ones.
     ///
-    /// If this [`State`] has a [`Restream`] with such `key` already.
-    pub fn add_restream(&self, spec: spec::v1::Restream) -> anyhow::Result<()> {
-        let mut restreams = self.restreams.lock_mut();
-
-        if restreams.iter().any(|r| r.key == spec.key) {
-            return Err(anyhow!("Restream.key '{}' is used already", spec.key));
+    /// # Panics
+    ///
+    /// If the described [`Restream`] is invalid or cannot be added.
+    #[cfg(test)]
+    #[must_use]
+    pub fn and_restream(self, key: &str, fields: serde_json::Value) -> Self {
+        fn merge(into: &mut serde_json::Value, from: serde_json::Value) {
+            match (into, from) {
+                (
+                    serde_json::Value::Object(into),
+                    serde_json::Value::Object(from),
+                ) => {
+                    for (k, v) in from {
+                        merge(
+                            into.entry(k).or_insert(serde_json::Value::Null),
+                            v,
+                        );
+                    }
+                }
+                (into, from) => *into = from,
+            }
         }
 
-        restreams.push(Restream::new(spec));
-        Ok(())
+        let mut restream = serde_json::json!({
+            "key": key,
+            "input": {
+                "key": "origin",
+                "endpoints": [{"kind": "rtmp"}],
+                "enabled": true,
+            },
+        });
+        merge(&mut restream, fields);
+        self.add_restream(
+            serde_json::from_value::<spec::v1::Restream>(restream).unwrap(),
+        )
+        .unwrap();
+        self
     }
 
-    /// Edits a [`Restream`] with the given `spec` identified by the given `id`
-    /// in this [`State`].
-    ///
-    /// Returns [`None`] if there is no [`Restream`] with such `id` in this
-    /// [`State`].
-    ///
-    /// # Errors
+    /// Validates the given JSON `contents` of a [`State`] file, deserializing
+    /// it in the same way [`State::try_new()`] does, and checking the same
+    /// invariants importing a [`Spec`] does.
     ///
-    /// If this [`State`] has a [`Restream`] with such `key` already.
-    pub fn edit_restream(
-        &self,
-        id: RestreamId,
-        spec: spec::v1::Restream,
-    ) -> anyhow::Result<Option<()>> {
-        let mut restreams = self.restreams.lock_mut();
+    /// Returns all the found [`spec::v1::Violation`]s, located as precisely as
+    /// possible.
+    #[must_use]
+    pub fn validate(contents: &[u8]) -> Vec<spec::v1::Violation> {
+        if contents.is_empty() {
+            return vec![];
+        }
+        let json = match serde_json::from_slice::<serde_json::Value>(contents) {
+            Ok(json) => json,
+            Err(e) => {
+                return vec![spec::v1::Violation {
+                    path: "$".into(),
+                    message: e.to_string(),
+                }]
+            }
+        };
+        match serde_json::from_value::<Self>(json.clone()) {
+            Ok(state) => state.export().into_v1().validate(),
+            Err(e) => Self::locate_invalid(&json, &e),
+        }
+    }
 
-        if restreams.iter().any(|r| r.key == spec.key && r.id != id) {
-            return Err(anyhow!("Restream.key '{}' is used already", spec.key));
+    /// Locates values of the given [`State`] `json` failing to deserialize as
+    /// precisely as possible, falling back to the given top-level `err`.
+    fn locate_invalid(
+        json: &serde_json::Value,
+        err: &serde_json::Error,
+    ) -> Vec<spec::v1::Violation> {
+        fn check<T: DeserializeOwned>(
+            json: &serde_json::Value,
+            path: String,
+            violations: &mut Vec<spec::v1::Violation>,
+        ) {
+            if let Err(e) = serde_json::from_value::<T>(json.clone()) {
+                violations.push(spec::v1::Violation {
+                    path,
+                    message: e.to_string(),
+                });
+            }
         }
 
-        #[allow(clippy::manual_find_map)] // due to consuming `spec`
-        Ok(restreams
-            .iter_mut()
-            .find(|r| r.id == id)
-            .map(|r| r.apply(spec, false)))
+        let mut violations = vec![];
+        if let Some(settings) = json.get("settings") {
+            check::<Settings>(settings, "$.settings".into(), &mut violations);
+        }
+        let restreams = json
+            .get("restreams")
+            .and_then(serde_json::Value::as_array)
+            .into_iter()
+            .flatten();
+        for (n, r) in restreams.enumerate() {
+            let path = format!("$.restreams[{}]", n);
+            let found = violations.len();
+            if let Some(input) = r.get("input") {
+                check::<Input>(
+                    input,
+                    format!("{}.input", path),
+                    &mut violations,
+                );
+            }
+            let outputs = r
+                .get("outputs")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten();
+            for (m, o) in outputs.enumerate() {
+                check::<Output>(
+                    o,
+                    format!("{}.outputs[{}]", path, m),
+                    &mut violations,
+                );
+            }
+            if violations.len() == found {
+                check::<Restream>(r, path, &mut violations);
+            }
+        }
+        if violations.is_empty() {
+            violations.push(spec::v1::Violation {
+                path: "$".into(),
+                message: err.to_string(),
+            });
+        }
+        violations
     }
 
-    /// Removes a [`Restream`] with the given `id` from this [`State`].
+    /// Applies the given [`Spec`] to this [`State`].
     ///
-    /// Returns [`None`] if there is no [`Restream`] with such `id` in this
-    /// [`State`].
-    #[allow(clippy::must_use_candidate)]
-    pub fn remove_restream(&self, id: RestreamId) -> Option<()> {
-        let mut restreams = self.restreams.lock_mut();
-        let prev_len = restreams.len();
-        restreams.retain(|r| r.id != id);
-        (restreams.len() != prev_len).then(|| ())
+    /// If `replace` is `true` then all the [`Restream`]s, [`Restream::outputs`]
+    /// and [`Output::mixins`] will be replaced with new ones, otherwise new
+    /// ones will be merged with already existing ones.
+    pub fn apply(&self, new: spec::v1::Spec, replace: bool) {
+        apply_restreams(
+            &mut trace::lock_mut(&self.restreams, "apply"),
+            new.restreams,
+            replace,
+        );
+        self.apply_settings(new.settings, replace);
     }
 
-    /// Enables a [`Restream`] with the given `id` in this [`State`].
+    /// Applies the given [`Spec`] to this [`State`] the same way
+    /// [`State::apply()`] does, but only if all its media file paths resolve
+    /// inside the [`State::media_root`] (see [`resolve_media_paths()`]), and
+    /// the result fits the [`State::quota`] and has no duplicate IDs.
     ///
-    /// Returns `true` if it has been enabled, or `false` if it already has been
-    /// enabled, or [`None`] if it doesn't exist.
-    #[must_use]
-    pub fn enable_restream(&self, id: RestreamId) -> Option<bool> {
-        self.restreams
-            .lock_mut()
-            .iter_mut()
-            .find_map(|r| (r.id == id).then(|| r.input.enable()))
-    }
-
-    /// Disables a [`Restream`] with the given `id` in this [`State`].
+    /// Intended for [`Spec`]s coming from outside (imported, provisioned or
+    /// reloaded ones), as opposed to the ones of trusted sources (like
+    /// replication or migration).
     ///
-    /// Returns `true` if it has been disabled, or `false` if it already has
-    /// been disabled, or [`None`] if it doesn't exist.
-    #[must_use]
-    pub fn disable_restream(&self, id: RestreamId) -> Option<bool> {
-        self.restreams
-            .lock_mut()
-            .iter_mut()
-            .find_map(|r| (r.id == id).then(|| r.input.disable()))
-    }
-
-    /// Enables an [`Input`] with the given `id` in the specified [`Restream`]
-    /// of this [`State`].
+    /// # Errors
     ///
-    /// Returns `true` if it has been enabled, or `false` if it already has been
-    /// enabled, or [`None`] if it doesn't exist.
-    #[must_use]
-    pub fn enable_input(
+    /// If any media file path of the [`Spec`] cannot be resolved, or applying
+    /// it exceeds the [`State::quota`] or duplicates IDs of other entities, in
+    /// which case nothing is applied.
+    pub fn apply_checked(
         &self,
-        id: InputId,
-        restream_id: RestreamId,
-    ) -> Option<bool> {
-        self.restreams
-            .lock_mut()
-            .iter_mut()
-            .find(|r| r.id == restream_id)?
-            .input
-            .find_mut(id)
-            .map(Input::enable)
-    }
+        mut new: spec::v1::Spec,
+        replace: bool,
+    ) -> Result<(), Rejected> {
+        let root = self.media_root.get_cloned();
+        for r in &mut new.restreams {
+            resolve_media_paths(r, root.as_deref())?;
+        }
 
-    /// Disables an [`Input`] with the given `id` in the specified [`Restream`]
-    /// of this [`State`].
-    ///
-    /// Returns `true` if it has been disabled, or `false` if it already has
-    /// been disabled, or [`None`] if it doesn't exist.
-    #[must_use]
-    pub fn disable_input(
-        &self,
-        id: InputId,
-        restream_id: RestreamId,
-    ) -> Option<bool> {
-        self.restreams
-            .lock_mut()
-            .iter_mut()
-            .find(|r| r.id == restream_id)?
-            .input
-            .find_mut(id)
-            .map(Input::disable)
+        let restreams = new.restreams;
+        self.transaction("apply_checked", |tx| {
+            tx.apply_restreams(restreams, replace);
+        })?;
+        self.apply_settings(new.settings, replace);
+        Ok(())
     }
 
-    /// Adds a new [`Output`] to the specified [`Restream`] of this [`State`].
+    /// Applies the given [`spec::v1::Restream`] to the [`Restream`] with the
+    /// given `id` (see [`Restream::apply()`]), checking it the same way
+    /// [`State::apply_checked()`] does.
     ///
     /// Returns [`None`] if there is no [`Restream`] with such `id` in this
     /// [`State`].
     ///
     /// # Errors
     ///
-    /// If the [`Restream`] has an [`Output`] with such `dst` already.
-    pub fn add_output(
+    /// If any media file path of the `spec` cannot be resolved, or applying
+    /// it exceeds the [`State::quota`] or duplicates IDs of other entities, in
+    /// which case nothing is applied.
+    pub fn apply_restream_checked(
         &self,
-        restream_id: RestreamId,
-        spec: spec::v1::Output,
-    ) -> anyhow::Result<Option<()>> {
-        let mut restreams = self.restreams.lock_mut();
-
-        let outputs = if let Some(r) =
-            restreams.iter_mut().find(|r| r.id == restream_id)
-        {
-            &mut r.outputs
-        } else {
-            return Ok(None);
-        };
-
-        if let Some(o) = outputs.iter().find(|o| o.dst == spec.dst) {
-            return Err(anyhow!("Output.dst '{}' is used already", o.dst));
-        }
-
-        outputs.push(Output::new(spec));
-        Ok(Some(()))
+        id: RestreamId,
+        mut spec: spec::v1::Restream,
+        replace: bool,
+    ) -> Result<Option<()>, Rejected> {
+        let root = self.media_root.get_cloned();
+        resolve_media_paths(&mut spec, root.as_deref())?;
+
+        self.transaction("apply_restream_checked", |tx| {
+            tx.apply_restream(id, spec, replace)
+        })
     }
 
-    /// Edits an [`Output`] with the given `spec` identified by the given `id`
-    /// in the specified [`Restream`] of this [`State`].
+    /// Performs the given batch of mutations of [`State::restreams`] under a
+    /// single lock of them, so their subscribers (like the re-streaming pool)
+    /// are notified only once, after the whole batch is done.
     ///
-    /// Returns [`None`] if there is no [`Restream`] with such `restream_id` in
-    /// this [`State`], or there is no [`Output`] with such `id`.
+    /// The batch is performed on a copy of [`State::restreams`], which is
+    /// committed only if it differs. If the batch applies any [`Spec`]s, the
+    /// copy is committed only if it fits the [`State::quota`], has no IDs used
+    /// by multiple entities (including the ones in the [`State::trash`]) and
+    /// has no cycles of chained [`Restream`]s.
+    ///
+    /// `op` is just a convenience for describing the batch in traces.
     ///
     /// # Errors
     ///
-    /// If the [`Restream`] has an [`Output`] with such `dst` already.
-    pub fn edit_output(
+    /// If the batch applies [`Spec`]s, and the result exceeds the
+    /// [`State::quota`], duplicates IDs or chains [`Restream`]s into a cycle,
+    /// in which case nothing is committed.
+    pub fn transaction<F, R>(
         &self,
-        restream_id: RestreamId,
-        id: OutputId,
-        spec: spec::v1::Output,
-    ) -> anyhow::Result<Option<()>> {
-        let mut restreams = self.restreams.lock_mut();
+        op: &'static str,
+        f: F,
+    ) -> Result<R, Rejected>
+    where
+        F: FnOnce(&mut Transaction) -> R,
+    {
+        let mut restreams = trace::lock_mut(&self.restreams, op);
 
-        let outputs = if let Some(r) =
-            restreams.iter_mut().find(|r| r.id == restream_id)
-        {
-            &mut r.outputs
-        } else {
-            return Ok(None);
+        let mut tx = Transaction {
+            restreams: restreams.clone(),
+            applied: false,
         };
-
-        if outputs.iter().any(|o| o.dst == spec.dst && o.id != id) {
-            return Err(anyhow!("Output.dst '{}' is used already", spec.dst));
+        let out = f(&mut tx);
+        if tx.applied {
+            self.quota.get().check_all(&tx.restreams)?;
+            if let Some(id) = find_duplicate_id(
+                tx.restreams.iter().chain(&*self.trash.lock_ref()),
+            ) {
+                return Err(Duplicate::Id { id }.into());
+            }
+            if let Some(id) = find_chain_cycle(&tx.restreams) {
+                return Err(Rejected::ChainCycle(id));
+            }
         }
+        if tx.restreams != *restreams {
+            *restreams = tx.restreams;
+        }
+        Ok(out)
+    }
 
-        #[allow(clippy::manual_find_map)] // due to consuming `spec`
-        Ok(outputs
-            .iter_mut()
-            .find(|o| o.id == id)
-            .map(|o| o.apply(spec, true)))
+    /// Applies the given [`spec::v1::Settings`] to this [`State`] (see
+    /// [`State::apply()`]).
+    fn apply_settings(&self, new: Option<spec::v1::Settings>, replace: bool) {
+        let mut settings = self.settings.lock_mut();
+        if new.is_some() || replace {
+            settings.apply(new.unwrap_or_else(|| Settings::default().export()));
+        }
     }
 
-    /// Removes an [`Output`] with the given `id` from the specified
-    /// [`Restream`] of this [`State`].
+    /// Reloads this [`State`] with the given [`spec::v1::Spec`] via
+    /// [`State::apply()`], so only the differences are applied.
     ///
-    /// Returns [`None`] if there is no [`Restream`] with such `restream_id` or
-    /// no [`Output`] with such `id` in this [`State`].
+    /// If `authoritative`, then [`Restream`]s not described by the `spec` are
+    /// removed, otherwise they're left untouched.
     #[must_use]
-    pub fn remove_output(
+    pub fn reload(
         &self,
-        id: OutputId,
-        restream_id: RestreamId,
-    ) -> Option<()> {
-        let mut restreams = self.restreams.lock_mut();
-        let outputs =
-            &mut restreams.iter_mut().find(|r| r.id == restream_id)?.outputs;
+        spec: spec::v1::Spec,
+        authoritative: bool,
+    ) -> SpecReload {
+        let (mut added, mut updated, mut removed) = (0, 0, 0);
+        {
+            let restreams = self.restreams.lock_ref();
+            for new in &spec.restreams {
+                match restreams.iter().find(|r| r.key == new.key) {
+                    None => added += 1,
+                    Some(old) => {
+                        // Specs written by hand usually have no IDs, so
+                        // shouldn't be considered changed because of them.
+                        let mut old = old.export();
+                        if new.id.is_none() {
+                            old.forget_ids();
+                        }
+                        if old != *new {
+                            updated += 1;
+                        }
+                    }
+                }
+            }
+            if authoritative {
+                removed = restreams
+                    .iter()
+                    .filter(|r| spec.restreams.iter().all(|n| n.key != r.key))
+                    .count();
+            }
+        }
 
-        let prev_len = outputs.len();
-        outputs.retain(|o| o.id != id);
-        (outputs.len() != prev_len).then(|| ())
+        if let Err(e) = self.apply_checked(spec, authoritative) {
+            return SpecReload::failed(e);
+        }
+
+        let count = |n: usize| n.try_into().unwrap_or(i32::MAX);
+        SpecReload {
+            at: DateTime::now(),
+            error: None,
+            added: count(added),
+            updated: count(updated),
+            removed: count(removed),
+        }
     }
 
-    /// Enables an [`Output`] with the given `id` in the specified [`Restream`]
-    /// of this [`State`].
-    ///
-    /// Returns `true` if it has been enabled, or `false` if it already has been
-    /// enabled, or [`None`] if it doesn't exist.
+    /// Exports this [`State`] as a [`spec::v1::Spec`].
+    #[inline]
     #[must_use]
-    pub fn enable_output(
-        &self,
-        id: OutputId,
-        restream_id: RestreamId,
-    ) -> Option<bool> {
-        let mut restreams = self.restreams.lock_mut();
-        let output = restreams
-            .iter_mut()
-            .find(|r| r.id == restream_id)?
-            .outputs
-            .iter_mut()
-            .find(|o| o.id == id)?;
-
-        if output.enabled {
-            return Some(false);
+    pub fn export(&self) -> Spec {
+        spec::v1::Spec {
+            settings: Some(self.settings.get_cloned().export()),
+            credentials: None,
+            restreams: self
+                .restreams
+                .get_cloned()
+                .iter()
+                .map(Restream::export)
+                .collect(),
         }
+        .into()
+    }
 
-        output.enabled = true;
-        Some(true)
+    /// Indicates whether this [`State`] is a standby replica of a primary
+    /// server at the moment (see [`State::replication`]), so rejects local
+    /// edits.
+    #[must_use]
+    pub fn is_replica(&self) -> bool {
+        self.replication
+            .lock_ref()
+            .as_ref()
+            .map_or(false, |r| !r.promoted)
     }
 
-    /// Disables an [`Output`] with the given `id` in the specified [`Restream`]
-    /// of this [`State`].
+    /// Applies the given [`spec::v1::Spec`] of a primary server to this
+    /// [`State`] being its standby replica, keeping all the [`Output`]s
+    /// disabled until [`State::promote()`] is called.
     ///
-    /// Returns `true` if it has been disabled, or `false` if it already has
-    /// been disabled, or [`None`] if it doesn't exist.
-    #[must_use]
-    pub fn disable_output(
-        &self,
-        id: OutputId,
-        restream_id: RestreamId,
-    ) -> Option<bool> {
-        let mut restreams = self.restreams.lock_mut();
-        let output = restreams
-            .iter_mut()
-            .find(|r| r.id == restream_id)?
-            .outputs
-            .iter_mut()
-            .find(|o| o.id == id)?;
+    /// Returns `false` if this [`State`] is not a replica (anymore), so
+    /// nothing has been applied.
+    pub fn apply_replicated(&self, mut spec: spec::v1::Spec) -> bool {
+        let mut replication = self.replication.lock_mut();
+        let replication = match replication.as_mut() {
+            Some(r) if !r.promoted => r,
+            _ => return false,
+        };
 
-        if !output.enabled {
+        let mut enabled = vec![];
+        for o in spec.restreams.iter_mut().flat_map(|r| r.outputs.iter_mut()) {
+            if o.enabled {
+                enabled.extend(o.id);
+            }
+            o.enabled = false;
+        }
+        spec.credentials = None;
+        self.apply(spec, true);
+        // Existing `Output`s keep their `enabled` on applying.
+        let _ = self.disable_all_outputs_of_restreams();
+
+        replication.enabled_outputs = enabled;
+        replication.synced_at = Some(DateTime::now());
+        true
+    }
+
+    /// Promotes this [`State`] being a standby replica to a primary one, so it
+    /// stops replicating, accepts local edits, and enables the [`Output`]s
+    /// which were enabled on the primary server at the last replication.
+    ///
+    /// Returns `true` if promoted, `false` if promoted already, or [`None`] if
+    /// this [`State`] is not a replica at all.
+    pub fn promote(&self) -> Option<bool> {
+        let mut replication = self.replication.lock_mut();
+        let replication = replication.as_mut()?;
+        if replication.promoted {
             return Some(false);
         }
+        replication.promoted = true;
 
-        output.enabled = false;
+        let enabled = mem::take(&mut replication.enabled_outputs);
+        let mut restreams = trace::lock_mut(&self.restreams, "promote");
+        for o in restreams.iter_mut().flat_map(|r| r.outputs.iter_mut()) {
+            if enabled.contains(&o.id) {
+                o.enabled = true;
+            }
+        }
         Some(true)
     }
 
-    /// Enables all [`Output`]s in the specified [`Restream`] of this [`State`].
+    /// Subscribes the specified `hook` to changes of the [`Mutable`] `val`ue.
     ///
-    /// Returns `true` if at least one [`Output`] has been enabled, or `false`
-    /// if all of them already have been enabled, or [`None`] if no [`Restream`]
-    /// with such `restream_id` exists.
-    #[must_use]
-    pub fn enable_all_outputs(&self, restream_id: RestreamId) -> Option<bool> {
-        self.set_state_of_all_outputs(restream_id, true)
-    }
-
-    /// Disables all [`Output`]s in the specified [`Restream`] of this
-    /// [`State`].
+    /// A panic of the `hook` doesn't unsubscribe it, so it keeps being called
+    /// on further changes (see [`HookPanics`] for the limit).
     ///
-    /// Returns `true` if at least one [`Output`] has been disabled, or `false`
-    /// if all of them already have been disabled, or [`None`] if no
-    /// [`Restream`] with such `restream_id` exists.
-    #[must_use]
-    pub fn disable_all_outputs(&self, restream_id: RestreamId) -> Option<bool> {
-        self.set_state_of_all_outputs(restream_id, false)
+    /// `name` is just a convenience for describing the `hook` in logs.
+    pub fn on_change<F, Fut, T>(name: &'static str, val: &Mutable<T>, hook: F)
+    where
+        F: FnMut(T) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        let val = val.clone();
+        Self::spawn_hook(
+            name,
+            move || val.signal_cloned().dedupe_cloned().to_stream(),
+            hook,
+        );
     }
 
-    /// Enables all [`Output`]s in all [`Restream`]s of this [`State`].
+    /// Subscribes the specified `hook` to changes of the [`State::restreams`]
+    /// along with their [`State::statuses`], calling it with both on a change
+    /// of any of them.
     ///
-    /// Returns `true` if at least one [`Output`] has been enabled, or `false`
-    /// if all of them already have been enabled or there are no outputs
-    #[must_use]
-    pub fn enable_all_outputs_of_restreams(&self) -> bool {
-        self.set_state_of_all_outputs_of_restreams(true)
+    /// A panic of the `hook` doesn't unsubscribe it, the same way it doesn't
+    /// for [`State::on_change()`].
+    ///
+    /// `name` is just a convenience for describing the `hook` in logs.
+    pub fn on_restreams_change<F, Fut>(&self, name: &'static str, mut hook: F)
+    where
+        F: FnMut(Vec<Restream>, Statuses) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+    {
+        let state = self.clone();
+        Self::spawn_hook(
+            name,
+            move || state.restreams_with_statuses(),
+            move |(restreams, statuses)| hook(restreams, statuses),
+        );
     }
 
-    /// Disables all [`Output`]s in ALL [`Restream`]s of this [`State`].
+    /// Returns a [`Stream`] of the [`State::restreams`] along with their
+    /// [`State::statuses`], emitting both on a change of any of them.
+    fn restreams_with_statuses(
+        &self,
+    ) -> impl Stream<Item = (Vec<Restream>, Statuses)> + Send + 'static {
+        map_ref! {
+            let restreams = self.restreams.signal_cloned().dedupe_cloned(),
+            let statuses = self.statuses.signal() =>
+            (restreams.clone(), statuses.clone())
+        }
+        .to_stream()
+    }
+
+    /// Subscribes the specified `hook` to changes of the persisted
+    /// representation of the [`Mutable`] `val`ue.
     ///
-    /// Returns `true` if at least one [`Output`] has been disabled, or `false`
-    /// if all of them already have been disabled or there are no outputs
+    /// Unlike [`State::on_change()`], doesn't clone the `val`ue on its every
+    /// change, and doesn't call the `hook` on changes of its non-persisted
+    /// fields only (like [`Output::problem`]).
+    ///
+    /// A panic of the `hook` doesn't unsubscribe it, the same way it doesn't
+    /// for [`State::on_change()`].
+    ///
+    /// `name` is just a convenience for describing the `hook` in logs.
+    pub fn on_persisted_change<F, Fut, T>(
+        name: &'static str,
+        val: &Mutable<T>,
+        mut hook: F,
+    ) where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        T: Serialize + Send + Sync + 'static,
+    {
+        let val = val.clone();
+        Self::spawn_hook(
+            name,
+            move || val.signal_ref(persisted_digest::<T>).dedupe().to_stream(),
+            move |_| hook(),
+        );
+    }
+
+    /// Returns the current [`StateSnapshot`] of this [`State`].
     #[must_use]
-    pub fn disable_all_outputs_of_restreams(&self) -> bool {
-        self.set_state_of_all_outputs_of_restreams(false)
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            settings: self.settings.get_cloned(),
+            restreams: self.restreams.get_cloned(),
+            trash: self.trash.get_cloned(),
+        }
     }
 
-    /// Tunes a [`Volume`] rate of the specified [`Output`] or its [`Mixin`] in
-    /// this [`State`].
+    /// Subscribes to changes of this [`State`], returning a [`Stream`] of its
+    /// [`StateSnapshot`]s.
     ///
-    /// Returns `true` if a [`Volume`] rate has been changed, or `false` if it
-    /// has the same value already.
+    /// # Semantics
     ///
-    /// Returns [`None`] if no such [`Restream`]/[`Output`]/[`Mixin`] exists.
-    #[must_use]
-    pub fn tune_volume(
+    /// - The first [`StateSnapshot`] is the current one.
+    /// - [`StateSnapshot`]s are deduplicated: a new one is emitted only once
+    ///   the persisted representation of [`State::settings`],
+    ///   [`State::restreams`] or [`State::trash`] changes, so changes of
+    ///   runtime [`Status`]es emit nothing (see
+    ///   [`State::subscribe_status_events()`] for them).
+    /// - Latest wins: changes are never queued, so a slow consumer never
+    ///   blocks mutations, but skips intermediate [`StateSnapshot`]s, always
+    ///   receiving the latest one (taken once it polls the [`Stream`]).
+    pub fn subscribe_changes(
         &self,
-        restream_id: RestreamId,
-        output_id: OutputId,
-        mixin_id: Option<MixinId>,
-        volume: Volume,
-    ) -> Option<bool> {
-        let mut restreams = self.restreams.lock_mut();
-        let output = restreams
-            .iter_mut()
-            .find(|r| r.id == restream_id)?
-            .outputs
-            .iter_mut()
-            .find(|o| o.id == output_id)?;
+    ) -> impl Stream<Item = StateSnapshot> + Send + 'static {
+        let state = self.clone();
+        map_ref! {
+            let settings =
+                self.settings.signal_ref(persisted_digest::<Settings>),
+            let restreams = self
+                .restreams
+                .signal_ref(persisted_digest::<Vec<Restream>>),
+            let trash =
+                self.trash.signal_ref(persisted_digest::<Vec<Restream>>) =>
+            (*settings, *restreams, *trash)
+        }
+        .dedupe()
+        .to_stream()
+        .map(move |_| state.snapshot())
+    }
 
-        let curr_volume = if let Some(id) = mixin_id {
-            &mut output.mixins.iter_mut().find(|m| m.id == id)?.volume
-        } else {
-            &mut output.volume
-        };
+    /// Subscribes to [`Status`] changes of [`Input`]s and [`Output`]s of this
+    /// [`State`], returning a [`Stream`] of [`StatusEvent`]s about them.
+    ///
+    /// Must be called inside an async runtime, as spawns the subscription.
+    ///
+    /// # Semantics
+    ///
+    /// - Only changes happened after the subscription are emitted.
+    /// - [`StatusEvent`]s are deduplicated: an entity changed several times
+    ///   between two observations of [`State::statuses`] emits a single
+    ///   [`StatusEvent`] from its previously observed [`Status`] to the
+    ///   current one, or nothing, if it's the same.
+    /// - [`StatusEvent`]s are queued in order, so a slow consumer never
+    ///   blocks mutations, while no more than [`StatusEvent::QUEUE_SIZE`] of
+    ///   them are kept, and the exceeding ones are dropped (with a warning).
+    /// - The subscription is cancelled once the [`Stream`] is dropped.
+    pub fn subscribe_status_events(
+        &self,
+    ) -> impl Stream<Item = StatusEvent> + Send + 'static {
+        let (mut tx, rx) = mpsc::channel(StatusEvent::QUEUE_SIZE);
+        // Statuses are remembered right away, as the spawned subscription
+        // observes them later.
+        let mut tracker = StatusTracker::default();
+        let _ =
+            tracker.diff(&self.restreams.lock_ref(), &self.statuses.lock_ref());
+
+        // Subscription ends on the next change once the `Stream` is dropped.
+        let closed = Arc::new(AtomicBool::new(false));
+        let (state, is_closed) = (self.clone(), closed.clone());
+        Self::spawn_hook(
+            "status_events",
+            move || {
+                let is_closed = is_closed.clone();
+                state.restreams_with_statuses().take_while(move |_| {
+                    future::ready(!is_closed.load(Ordering::Relaxed))
+                })
+            },
+            move |(restreams, statuses)| {
+                for ev in tracker.diff(&restreams, &statuses) {
+                    match tx.try_send(ev) {
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            log::warn!(
+                                "Dropped status event, as its subscriber \
+                                 doesn't keep up",
+                            );
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            closed.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+                future::ready(())
+            },
+        );
+        rx
+    }
 
-        if *curr_volume == volume {
-            return Some(false);
-        }
+    /// Spawns the given `hook` of [`State`] to be driven in background,
+    /// calling it on every value of the [`Stream`] returned by `subscribe`.
+    ///
+    /// Panics of the `hook` are caught and logged on each its invocation, so
+    /// it keeps receiving further values. If the [`Stream`] itself panics, it's
+    /// re-created via `subscribe`. Too frequent panics stop the `hook` and
+    /// resolve [`State::hook_broken()`] (see [`HookPanics`]).
+    ///
+    /// `name` is just a convenience for describing the `hook` in logs.
+    fn spawn_hook<N, S, F, Fut>(
+        name: &'static str,
+        mut subscribe: N,
+        mut hook: F,
+    ) where
+        N: FnMut() -> S + Send + 'static,
+        S: Stream + Send + 'static,
+        F: FnMut(S::Item) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+    {
+        drop(tokio::spawn(async move {
+            let mut panics = HookPanics::default();
+            loop {
+                let mut values = Box::pin(subscribe());
+                loop {
+                    let panic = match AssertUnwindSafe(values.next())
+                        .catch_unwind()
+                        .await
+                    {
+                        Ok(Some(val)) => {
+                            match panic::catch_unwind(AssertUnwindSafe(|| {
+                                hook(val)
+                            })) {
+                                Ok(fut) => AssertUnwindSafe(fut)
+                                    .catch_unwind()
+                                    .await
+                                    .err(),
+                                Err(p) => Some(p),
+                            }
+                        }
+                        Ok(None) => return,
+                        // Panicked `Stream` cannot be polled anymore, so
+                        // should be re-subscribed.
+                        Err(p) => {
+                            if panics.record(name, &*p) {
+                                return;
+                            }
+                            break;
+                        }
+                    };
+                    if let Some(p) = panic {
+                        if panics.record(name, &*p) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }));
+    }
 
-        *curr_volume = volume;
-        Some(true)
+    /// Resolves once any hook of [`State`] is broken by panicking too
+    /// frequently (see [`HookPanics`]), returning its name.
+    ///
+    /// The process is not expected to keep running without such a hook, so
+    /// should be shut down.
+    pub async fn hook_broken() -> &'static str {
+        let broken = BROKEN_HOOK.signal().to_stream().filter_map(future::ready);
+        Box::pin(broken).next().await.unwrap_or_default()
     }
 
-    /// Tunes a [`Delay`] of the specified [`Mixin`] in this [`State`].
+    /// Adds a new [`Restream`] by the given `spec` to this [`State`].
     ///
-    /// Returns `true` if a [`Delay`] has been changed, or `false` if it has the
-    /// same value already.
+    /// # Errors
     ///
-    /// Returns [`None`] if no such [`Restream`]/[`Output`]/[`Mixin`] exists.
-    #[must_use]
-    pub fn tune_delay(
+    /// If this [`State`] has a [`Restream`] with such `key` already, or adding
+    /// it exceeds the [`State::quota`].
+    pub fn add_restream(
         &self,
-        input_id: RestreamId,
-        output_id: OutputId,
-        mixin_id: MixinId,
-        delay: Delay,
-    ) -> Option<bool> {
-        let mut restreams = self.restreams.lock_mut();
-        let mixin = restreams
-            .iter_mut()
-            .find(|r| r.id == input_id)?
-            .outputs
-            .iter_mut()
-            .find(|o| o.id == output_id)?
-            .mixins
-            .iter_mut()
-            .find(|m| m.id == mixin_id)?;
+        spec: spec::v1::Restream,
+    ) -> Result<(), Rejected> {
+        let mut restreams = trace::lock_mut(&self.restreams, "add_restream");
 
-        if mixin.delay == delay {
-            return Some(false);
+        if let Some(r) =
+            restreams.iter().find(|r| r.key.eq_ignore_case(&spec.key))
+        {
+            return Err(Duplicate::restream(r));
+        }
+        let mut ids = vec![];
+        spec.collect_ids(&mut ids);
+        if let Some(id) =
+            find_used_id(restreams.iter().chain(&*self.trash.lock_ref()), &ids)
+        {
+            return Err(Duplicate::Id { id }.into());
         }
+        let quota = self.quota.get();
+        quota.check_restreams(restreams.len() + 1)?;
+        quota.check_outputs(&spec.key, spec.outputs.len())?;
 
-        mixin.delay = delay;
-        Some(true)
+        push_positioned(&mut restreams, Restream::new(spec));
+        Ok(())
+    }
+
+    /// Checks whether the current [`Restream`]s of this [`State`] fit its
+    /// [`State::quota`].
+    ///
+    /// # Errors
+    ///
+    /// If the [`State::quota`] is exceeded already.
+    pub fn check_quota(&self) -> Result<(), QuotaExceeded> {
+        self.quota.get().check_all(&self.restreams.lock_ref())
     }
 
-    /// Disables/Enables all [`Output`]s in the specified [`Restream`] of this
+    /// Edits a [`Restream`] with the given `spec` identified by the given `id`
+    /// in this [`State`].
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `id` in this
     /// [`State`].
-    #[must_use]
-    fn set_state_of_all_outputs(
+    ///
+    /// # Errors
+    ///
+    /// If this [`State`] has a [`Restream`] with such `key` already, or the
+    /// `spec` specifies IDs used by other entities already.
+    pub fn edit_restream(
         &self,
-        restream_id: RestreamId,
-        enabled: bool,
-    ) -> Option<bool> {
-        let mut restreams = self.restreams.lock_mut();
-        Some(
-            restreams
-                .iter_mut()
-                .find(|r| r.id == restream_id)?
-                .outputs
-                .iter_mut()
-                .filter(|o| o.enabled != enabled)
-                .fold(false, |_, o| {
-                    o.enabled = enabled;
-                    true
-                }),
-        )
+        id: RestreamId,
+        spec: spec::v1::Restream,
+    ) -> Result<Option<()>, Duplicate> {
+        let mut restreams = trace::lock_mut(&self.restreams, "edit_restream");
+
+        if let Some(r) = restreams
+            .iter()
+            .find(|r| r.key.eq_ignore_case(&spec.key) && r.id != id)
+        {
+            return Err(Duplicate::restream(r));
+        }
+
+        let n = match restreams.iter().position(|r| r.id == id) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        let mut edited = restreams[n].clone();
+        edited.apply(spec, false);
+        check_ids_unique(&restreams, n, &edited, &self.trash.lock_ref())?;
+
+        restreams[n] = edited;
+        Ok(Some(()))
     }
 
-    /// Disables/Enables all [`Output`]s in ALL [`Restream`]s of this [`State`].
-    #[must_use]
-    fn set_state_of_all_outputs_of_restreams(&self, enabled: bool) -> bool {
-        let mut restreams = self.restreams.lock_mut();
-        restreams
-            .iter_mut()
-            .flat_map(|r| r.outputs.iter_mut())
-            .filter(|o| o.enabled != enabled)
-            .fold(false, |_, o| {
-                o.enabled = enabled;
-                true
-            })
+    /// Removes a [`Restream`] with the given `id` from this [`State`] into
+    /// its [`State::trash`], so it stops being performed, but may be restored
+    /// later (see [`State::restore_restream()`]).
+    ///
+    /// Unless `force` is `true`, refuses to remove the [`Restream`] being live
+    /// (see [`Restream::is_live()`]). The check is performed under the same
+    /// lock as the removal, so a publisher connecting at the same moment
+    /// cannot be missed.
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `id` in this
+    /// [`State`].
+    ///
+    /// # Errors
+    ///
+    /// If the [`Restream`] is live and `force` is `false`.
+    pub fn remove_restream(
+        &self,
+        id: RestreamId,
+        force: bool,
+    ) -> anyhow::Result<Option<()>> {
+        let mut restreams = trace::lock_mut(&self.restreams, "remove_restream");
+        let n = match restreams.iter().position(|r| r.id == id) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        let mut statuses = self.statuses.lock_mut("remove_restream");
+        if !force && restreams[n].is_live(&statuses) {
+            return Err(anyhow!("Restream is live at the moment"));
+        }
+        let mut restream = restreams.remove(n);
+        restream.input.reset_status(&mut statuses);
+        for o in &restream.outputs {
+            let _ = statuses.set(o.id, Status::Offline);
+        }
+        drop(statuses);
+        for o in &mut restream.outputs {
+            o.paused = false;
+            o.test = None;
+            o.conflicts_with.clear();
+        }
+        restream.deleted_at = Some(DateTime::now());
+        self.trash.lock_mut().push(restream);
+        Ok(Some(()))
     }
-}
 
-/// Re-stream of a live stream from one `Input` to many `Output`s.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct Restream {
-    /// Unique ID of this `Input`.
+    /// Restores a [`Restream`] with the given `id` from the [`State::trash`]
+    /// of this [`State`], so it's performed again.
     ///
-    /// Once assigned, it never changes.
-    pub id: RestreamId,
+    /// Returns [`None`] if there is no [`Restream`] with such `id` in the
+    /// [`State::trash`].
+    ///
+    /// # Errors
+    ///
+    /// If this [`State`] has a [`Restream`] with the same `key` already, or
+    /// restoring it exceeds the [`State::quota`].
+    pub fn restore_restream(
+        &self,
+        id: RestreamId,
+    ) -> Result<Option<()>, Rejected> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "restore_restream");
+        let mut trash = self.trash.lock_mut();
+
+        let n = match trash.iter().position(|r| r.id == id) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        if let Some(r) = restreams
+            .iter()
+            .find(|r| r.key.eq_ignore_case(&trash[n].key))
+        {
+            return Err(Duplicate::restream(r).into());
+        }
+        let quota = self.quota.get();
+        quota.check_restreams(restreams.len() + 1)?;
+        quota.check_outputs(&trash[n].key, trash[n].outputs.len())?;
 
-    /// Unique key of this `Restream` identifying it, and used to form its
-    /// endpoints URLs.
-    pub key: RestreamKey,
+        let mut restream = trash.remove(n);
+        restream.deleted_at = None;
+        insert_positioned(&mut restreams, restream);
+        Ok(Some(()))
+    }
 
-    /// Optional label of this `Restream`.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub label: Option<Label>,
+    /// Purges a [`Restream`] with the given `id` from the [`State::trash`] of
+    /// this [`State`], so it cannot be restored anymore.
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `id` in the
+    /// [`State::trash`].
+    #[must_use]
+    pub fn purge_restream(&self, id: RestreamId) -> Option<()> {
+        let mut trash = self.trash.lock_mut();
+        let n = trash.iter().position(|r| r.id == id)?;
+        let _ = trash.remove(n);
+        Some(())
+    }
 
-    /// `Input` that a live stream is received from.
-    pub input: Input,
+    /// Purges all the [`Restream`]s being in the [`State::trash`] of this
+    /// [`State`] longer than the given `retention` period.
+    ///
+    /// Returns the number of purged [`Restream`]s.
+    pub fn purge_expired_trash(&self, retention: Duration) -> usize {
+        let expired_before = match SystemTime::now().checked_sub(retention) {
+            Some(at) => DateTime::from(at),
+            None => return 0,
+        };
+        let is_expired = |r: &Restream| {
+            r.deleted_at.map_or(false, |at| at <= expired_before)
+        };
+        if !self.trash.lock_ref().iter().any(is_expired) {
+            return 0;
+        }
 
-    /// `Output`s that a live stream is re-streamed to.
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub outputs: Vec<Output>,
-}
+        let mut trash = self.trash.lock_mut();
+        let len = trash.len();
+        trash.retain(|r| !is_expired(r));
+        len - trash.len()
+    }
 
-impl Restream {
-    /// Creates a new [`Restream`] out of the given [`spec::v1::Restream`].
+    /// Checks whether making the [`Restream`] with the given `id` to pull a
+    /// live stream from the given `upstream` [`Restream`] forms a cycle of
+    /// chained [`Restream`]s.
     #[inline]
     #[must_use]
-    pub fn new(spec: spec::v1::Restream) -> Self {
-        Self {
-            id: RestreamId::random(),
-            key: spec.key,
-            label: spec.label,
-            input: Input::new(spec.input),
-            outputs: spec.outputs.into_iter().map(Output::new).collect(),
-        }
+    pub fn forms_chain_cycle(
+        &self,
+        id: RestreamId,
+        upstream: RestreamId,
+    ) -> bool {
+        forms_chain_cycle(&self.restreams.lock_ref(), id, upstream)
     }
 
-    /// Applies the given [`spec::v1::Restream`] to this [`Restream`].
+    /// Enables a [`Restream`] with the given `id` in this [`State`].
     ///
-    /// If `replace` is `true` then all the [`Restream::outputs`] will be
-    /// replaced with new ones, otherwise new ones will be merged with already
-    /// existing [`Restream::outputs`].
-    pub fn apply(&mut self, new: spec::v1::Restream, replace: bool) {
-        self.key = new.key;
-        self.label = new.label;
-        self.input.apply(new.input);
-        if replace {
-            let mut olds = mem::replace(
-                &mut self.outputs,
-                Vec::with_capacity(new.outputs.len()),
-            );
-            for new in new.outputs {
-                if let Some(mut old) = olds
-                    .iter()
-                    .enumerate()
-                    .find_map(|(n, o)| (o.dst == new.dst).then(|| n))
-                    .map(|n| olds.swap_remove(n))
-                {
-                    old.apply(new, replace);
-                    self.outputs.push(old);
-                } else {
-                    self.outputs.push(Output::new(new));
-                }
-            }
-        } else {
-            for new in new.outputs {
-                if let Some(old) =
-                    self.outputs.iter_mut().find(|o| o.dst == new.dst)
-                {
-                    old.apply(new, replace);
+    /// Returns `true` if it has been enabled, or `false` if it already has been
+    /// enabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn enable_restream(&self, id: RestreamId) -> Option<bool> {
+        trace::lock_mut(&self.restreams, "enable_restream")
+            .iter_mut()
+            .find_map(|r| (r.id == id).then(|| r.input.enable()))
+    }
+
+    /// Disables a [`Restream`] with the given `id` in this [`State`] for the
+    /// given [`DisableReason`].
+    ///
+    /// Returns `true` if it has been disabled, or `false` if it already has
+    /// been disabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn disable_restream(
+        &self,
+        id: RestreamId,
+        reason: DisableReason,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "disable_restream");
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+        Some(
+            restream.input.disable(
+                reason,
+                &mut self.statuses.lock_mut("disable_restream"),
+            ),
+        )
+    }
+
+    /// Enables an [`Input`] with the given `id` in the specified [`Restream`]
+    /// of this [`State`].
+    ///
+    /// Returns `true` if it has been enabled, or `false` if it already has been
+    /// enabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn enable_input(
+        &self,
+        id: InputId,
+        restream_id: RestreamId,
+    ) -> Option<bool> {
+        trace::lock_mut(&self.restreams, "enable_input")
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .input
+            .find_mut(id)
+            .map(Input::enable)
+    }
+
+    /// Disables an [`Input`] with the given `id` in the specified [`Restream`]
+    /// of this [`State`] for the given [`DisableReason`].
+    ///
+    /// Use [`DisableReason::default()`] for disabling it manually by an
+    /// operator.
+    ///
+    /// Returns `true` if it has been disabled, or `false` if it already has
+    /// been disabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn disable_input(
+        &self,
+        id: InputId,
+        restream_id: RestreamId,
+        reason: DisableReason,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(&self.restreams, "disable_input");
+        let input = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .input
+            .find_mut(id)?;
+        Some(
+            input.disable(reason, &mut self.statuses.lock_mut("disable_input")),
+        )
+    }
+
+    /// Resets the [`Restream::input_online_secs`] and the
+    /// [`Output::online_secs`] of the [`Restream`] owning the [`Input`] with
+    /// the given `id`, so they're counted since now, recording the
+    /// [`UsageReset`] into its [`Restream::usage_resets`].
+    ///
+    /// Returns [`None`] if there is no [`Input`] with such `id`.
+    #[must_use]
+    pub fn reset_usage_counters(&self, id: InputId) -> Option<UsageReset> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "reset_usage_counters");
+        let restream =
+            restreams.iter_mut().find(|r| r.input.find(id).is_some())?;
+        let reset = UsageReset {
+            at: DateTime::now(),
+            input_online_secs: mem::take(&mut restream.input_online_secs),
+            outputs_online_secs: restream
+                .outputs
+                .iter_mut()
+                .map(|o| mem::take(&mut o.online_secs))
+                .fold(0, u64::saturating_add),
+        };
+        restream.usage_since = Some(reset.at);
+        restream.usage_resets.push(reset.clone());
+        Some(reset)
+    }
+
+    /// Marks all the [`InputEndpoint`]s of this [`State`] as
+    /// [`Status::Offline`], forgetting all their [SRS] clients.
+    ///
+    /// Intended to be used once [SRS] server process has exited, so all its
+    /// clients are gone and must reconnect.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn reset_inputs_status(&self) {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "reset_inputs_status");
+        let mut statuses = self.statuses.lock_mut("reset_inputs_status");
+        for r in restreams.iter_mut() {
+            r.input.reset_status(&mut statuses);
+        }
+    }
+
+    /// Adds a new [`Output`] to the specified [`Restream`] of this [`State`].
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `id` in this
+    /// [`State`].
+    ///
+    /// # Errors
+    ///
+    /// If the [`Restream`] has an [`Output`] with such `dst` already, or
+    /// another [`Restream`] has it while [`Settings::reject_dst_conflicts`] is
+    /// enabled, or adding it exceeds the [`State::quota`], or its
+    /// [`Output::src_stream`] is not an [`Input`] of the [`Restream`].
+    pub fn add_output(
+        &self,
+        restream_id: RestreamId,
+        mut spec: spec::v1::Output,
+    ) -> Result<Option<()>, Rejected> {
+        let reject_conflicts = self.settings.lock_ref().reject_dst_conflicts;
+        let mut restreams = trace::lock_mut(&self.restreams, "add_output");
+
+        let conflict = reject_conflicts
+            .then(|| {
+                Duplicate::foreign_output(&restreams, restream_id, &spec.dst)
+            })
+            .flatten();
+        let mut ids = vec![];
+        spec.collect_ids(&mut ids);
+        let used_id =
+            find_used_id(restreams.iter().chain(&*self.trash.lock_ref()), &ids);
+
+        let restream = if let Some(r) =
+            restreams.iter_mut().find(|r| r.id == restream_id)
+        {
+            r
+        } else {
+            return Ok(None);
+        };
+
+        if let Some(o) = restream
+            .outputs
+            .iter()
+            .find(|o| o.dst.is_equivalent(&spec.dst))
+        {
+            return Err(Duplicate::output(o).into());
+        }
+        if let Some(dup) = conflict {
+            return Err(dup.into());
+        }
+        if let Some(id) = used_id {
+            return Err(Duplicate::Id { id }.into());
+        }
+        if let Some(k) = &spec.src_stream {
+            if restream.input.find_by_key(k).is_none() {
+                return Err(Rejected::SrcStream(k.clone()));
+            }
+        }
+        spec.src_stream = spec.src_stream.filter(|k| *k != restream.input.key);
+        self.quota
+            .get()
+            .check_outputs(&restream.key, restream.outputs.len() + 1)?;
+
+        push_positioned(&mut restream.outputs, Output::new(spec));
+        let _ = refresh_dst_conflicts(&mut restreams);
+        Ok(Some(()))
+    }
+
+    /// Edits an [`Output`] with the given `spec` identified by the given `id`
+    /// in the specified [`Restream`] of this [`State`].
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `restream_id` in
+    /// this [`State`], or there is no [`Output`] with such `id`.
+    ///
+    /// # Errors
+    ///
+    /// If the [`Restream`] has an [`Output`] with such `dst` already, or
+    /// another [`Restream`] has it while [`Settings::reject_dst_conflicts`] is
+    /// enabled (and the edited [`Output`] has another `dst`), or the `spec`
+    /// specifies IDs used by other entities already.
+    pub fn edit_output(
+        &self,
+        restream_id: RestreamId,
+        id: OutputId,
+        spec: spec::v1::Output,
+    ) -> Result<Option<()>, Duplicate> {
+        let reject_conflicts = self.settings.lock_ref().reject_dst_conflicts;
+        let mut restreams = trace::lock_mut(&self.restreams, "edit_output");
+
+        check_output_dst(
+            &restreams,
+            restream_id,
+            id,
+            &spec.dst,
+            reject_conflicts,
+        )?;
+        let n = match restreams.iter().position(|r| r.id == restream_id) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        let mut edited = restreams[n].clone();
+        let output = match edited.outputs.iter_mut().find(|o| o.id == id) {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+        output.apply(spec, true);
+        check_ids_unique(&restreams, n, &edited, &self.trash.lock_ref())?;
+
+        restreams[n] = edited;
+        let _ = refresh_dst_conflicts(&mut restreams);
+        Ok(Some(()))
+    }
+
+    /// Returns all the [`DstConflict`]s between [`Output`]s of different
+    /// [`Restream`]s of this [`State`] present at the moment.
+    #[must_use]
+    pub fn dst_conflicts(&self) -> Vec<DstConflict> {
+        let mut conflicts: Vec<DstConflict> = vec![];
+        for r in self.restreams.lock_ref().iter() {
+            for o in &r.outputs {
+                let dst = o.dst.normalized();
+                let output = OutputRef::new(r, o);
+                if let Some(c) = conflicts.iter_mut().find(|c| c.dst == dst) {
+                    c.outputs.push(output);
                 } else {
-                    self.outputs.push(Output::new(new));
+                    conflicts.push(DstConflict {
+                        dst,
+                        outputs: vec![output],
+                    });
                 }
             }
         }
+        conflicts.retain(|c| {
+            c.outputs
+                .iter()
+                .any(|o| o.restream_id != c.outputs[0].restream_id)
+        });
+        conflicts
+    }
+
+    /// Returns the current [`RestreamsVersion`] of [`State::restreams`] and
+    /// [`State::statuses`], calculating it only if it's not cached yet.
+    #[must_use]
+    pub fn restreams_version(&self) -> String {
+        let restreams = self.restreams.lock_ref();
+        self.restreams_version
+            .get(&restreams, &self.statuses.lock_ref())
+    }
+
+    /// Refreshes [`Output::conflicts_with`] of all the [`Output`]s in this
+    /// [`State`], touching [`State::restreams`] only if any has changed.
+    pub fn refresh_dst_conflicts(&self) {
+        let is_fresh = {
+            let restreams = self.restreams.lock_ref();
+            restreams
+                .iter()
+                .flat_map(|r| r.outputs.iter().map(|o| &o.conflicts_with))
+                .eq(collect_dst_conflicts(&restreams).iter())
+        };
+        if !is_fresh {
+            let _ = refresh_dst_conflicts(&mut trace::lock_mut(
+                &self.restreams,
+                "refresh_dst_conflicts",
+            ));
+        }
+    }
+
+    /// Indicates whether the [`Output`] with the given `id` in this [`State`]
+    /// is enabled, but idles only because the [`Input`] of its [`Restream`]
+    /// doesn't serve a live stream at the moment (see
+    /// [`Output::is_awaiting_input()`]).
+    ///
+    /// Is derived from the current [`State::statuses`] along with the
+    /// [`State::restreams`] they belong to, so is never stored and never
+    /// outdated.
+    #[must_use]
+    pub fn is_awaiting_input(&self, id: OutputId) -> bool {
+        let restreams = self.restreams.lock_ref();
+        let statuses = self.statuses.lock_ref();
+        restreams
+            .iter()
+            .find_map(|r| {
+                let output = r.outputs.iter().find(|o| o.id == id)?;
+                Some(output.is_awaiting_input(&r.input, &statuses))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Removes an [`Output`] with the given `id` from the specified
+    /// [`Restream`] of this [`State`].
+    ///
+    /// Unless `force` is `true`, refuses to remove the [`Output`] being
+    /// [`Status::Online`]. The check is performed under the same lock as the
+    /// removal.
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `restream_id` or
+    /// no [`Output`] with such `id` in this [`State`].
+    ///
+    /// # Errors
+    ///
+    /// If the [`Output`] is [`Status::Online`] and `force` is `false`.
+    pub fn remove_output(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+        force: bool,
+    ) -> anyhow::Result<Option<()>> {
+        let mut restreams = trace::lock_mut(&self.restreams, "remove_output");
+        let outputs = match restreams.iter_mut().find(|r| r.id == restream_id) {
+            Some(r) => &mut r.outputs,
+            None => return Ok(None),
+        };
+        let n = match outputs.iter().position(|o| o.id == id) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        if !force && self.statuses.get(id).is_online() {
+            return Err(anyhow!("Output is live at the moment"));
+        }
+        let _ = outputs.remove(n);
+        let _ = self.statuses.set(id, Status::Offline);
+        Ok(Some(()))
+    }
+
+    /// Enables an [`Output`] with the given `id` in the specified [`Restream`]
+    /// of this [`State`].
+    ///
+    /// Returns `true` if it has been enabled, or `false` if it already has been
+    /// enabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn enable_output(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(&self.restreams, "enable_output");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == id)?;
+
+        if output.enabled {
+            return Some(false);
+        }
+
+        output.enabled = true;
+        output.disabled_reason = None;
+        Some(true)
+    }
+
+    /// Disables an [`Output`] with the given `id` in the specified [`Restream`]
+    /// of this [`State`].
+    ///
+    /// Returns `true` if it has been disabled, or `false` if it already has
+    /// been disabled, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn disable_output(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(&self.restreams, "disable_output");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == id)?;
+
+        if !output.enabled {
+            return Some(false);
+        }
+
+        output.enabled = false;
+        output.disabled_reason = None;
+        Some(true)
+    }
+
+    /// Pauses or resumes an [`Output`] with the given `id` in the specified
+    /// [`Restream`] of this [`State`].
+    ///
+    /// Returns `true` if it has been paused (or resumed), or `false` if it
+    /// already has been so, or [`None`] if it doesn't exist.
+    #[must_use]
+    pub fn set_output_paused(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+        paused: bool,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_paused");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == id)?;
+
+        if output.paused == paused {
+            return Some(false);
+        }
+
+        output.paused = paused;
+        Some(true)
+    }
+
+    /// Renews [`Status`] of the [`Output`] or [`InputEndpoint`] with the given
+    /// `id` to the given one, debouncing it with the
+    /// [`State::status_debouncer`].
+    ///
+    /// The given `commit` function should apply the `status` to the
+    /// [`State::statuses`] of this [`State`]. If the `status` should be
+    /// committed right away, the `commit` function is returned back to the
+    /// caller, which may already hold the [`State::restreams`] lock, so
+    /// should either apply the `status` on its own or call the `commit`
+    /// function once the lock is released. Otherwise, it's called once the
+    /// grace period passes, unless the entity has renewed its [`Status`]
+    /// meanwhile.
+    #[must_use]
+    pub fn renew_status<Id, F>(
+        &self,
+        id: Id,
+        status: Status,
+        commit: F,
+    ) -> Option<F>
+    where
+        Id: Into<Uuid>,
+        F: FnOnce(&Self) + Send + 'static,
+    {
+        let id = id.into();
+        let (revision, delay) =
+            match self.status_debouncer.record(id, status, Instant::now()) {
+                Some(postponed) => postponed,
+                None => return Some(commit),
+            };
+        let state = self.clone();
+        drop(tokio::spawn(async move {
+            time::delay_for(delay).await;
+            if state.status_debouncer.complete(id, revision) {
+                commit(&state);
+            }
+        }));
+        None
+    }
+
+    /// Records the given [`ProblemInfo`] as the most recent failure of an
+    /// [`Output`] with the given `id`.
+    ///
+    /// Returns `false` if there is no such [`Output`] in this [`State`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn set_output_problem(
+        &self,
+        id: OutputId,
+        problem: ProblemInfo,
+    ) -> bool {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_problem");
+        restreams
+            .iter_mut()
+            .find_map(|r| r.outputs.iter_mut().find(|o| o.id == id))
+            .map(|o| o.problem = Some(problem))
+            .is_some()
+    }
+
+    /// Clears the most recent failure of an [`Output`] with the given `id`.
+    ///
+    /// [`ProblemKind::Incompatible`] is not cleared, as it doesn't depend on
+    /// whether the [`Output`] re-streams successfully.
+    ///
+    /// Returns `true` if it has been cleared, or `false` if there was none, or
+    /// no such [`Output`] exists in this [`State`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn clear_output_problem(&self, id: OutputId) -> bool {
+        let is_clearable = |o: &Output| {
+            o.id == id
+                && o.problem
+                    .as_ref()
+                    .map_or(false, |p| p.kind != ProblemKind::Incompatible)
+        };
+        // Checked without modifying first, so the `State::restreams` are not
+        // touched on every renewal of `Status::Online`.
+        if !self
+            .restreams
+            .lock_ref()
+            .iter()
+            .any(|r| r.outputs.iter().any(is_clearable))
+        {
+            return false;
+        }
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "clear_output_problem");
+        restreams
+            .iter_mut()
+            .find_map(|r| r.outputs.iter_mut().find(|o| is_clearable(o)))
+            .and_then(|o| o.problem.take())
+            .is_some()
+    }
+
+    /// Records the given [`ProblemInfo`] as the most recent failure of an
+    /// [`Input`] owning an [`InputEndpoint`] with the given `id`.
+    ///
+    /// Returns `false` if there is no such [`Input`] in this [`State`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn set_input_problem(
+        &self,
+        endpoint_id: EndpointId,
+        problem: ProblemInfo,
+    ) -> bool {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_input_problem");
+        restreams
+            .iter_mut()
+            .find_map(|r| r.input.find_by_endpoint_mut(endpoint_id))
+            .map(|i| i.problem = Some(problem))
+            .is_some()
+    }
+
+    /// Clears the most recent failure of an [`Input`] owning an
+    /// [`InputEndpoint`] with the given `id`.
+    ///
+    /// Returns `true` if it has been cleared, or `false` if there was none, or
+    /// no such [`Input`] exists in this [`State`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn clear_input_problem(&self, endpoint_id: EndpointId) -> bool {
+        // Checked without modifying first, so the `State::restreams` are not
+        // touched on every renewal of `Status::Online`.
+        if !self.restreams.lock_ref().iter().any(|r| {
+            r.input
+                .find_by_endpoint(endpoint_id)
+                .map_or(false, |i| i.problem.is_some())
+        }) {
+            return false;
+        }
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "clear_input_problem");
+        restreams
+            .iter_mut()
+            .find_map(|r| r.input.find_by_endpoint_mut(endpoint_id))
+            .and_then(|i| i.problem.take())
+            .is_some()
+    }
+
+    /// Starts an [`OutputTest`] of the given `duration_secs` for an [`Output`]
+    /// with the given `id` in the specified [`Restream`] of this [`State`].
+    ///
+    /// Returns `true` if it has been started, or `false` if another one is
+    /// running already, or [`None`] if the [`Output`] doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// If the [`Output`] is enabled, so its re-streaming process is active.
+    pub fn start_output_test(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+        duration_secs: u32,
+    ) -> anyhow::Result<Option<bool>> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "start_output_test");
+        let output = match restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter_mut().find(|o| o.id == id))
+        {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+
+        if output.enabled {
+            return Err(anyhow!("Output is active, disable it first"));
+        }
+        if output.test.is_some() {
+            return Ok(Some(false));
+        }
+
+        output.test = Some(OutputTest {
+            duration_secs: duration_secs.try_into().unwrap_or(i32::MAX),
+            started_at: DateTime::now(),
+        });
+        let _ = self.statuses.set(id, Status::Testing);
+        Ok(Some(true))
+    }
+
+    /// Stops the running [`OutputTest`] of an [`Output`] with the given `id`
+    /// in the specified [`Restream`] of this [`State`].
+    ///
+    /// Returns `true` if it has been stopped, or `false` if there was none
+    /// running, or [`None`] if the [`Output`] doesn't exist.
+    #[must_use]
+    pub fn stop_output_test(
+        &self,
+        id: OutputId,
+        restream_id: RestreamId,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "stop_output_test");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == id)?;
+
+        if output.test.take().is_none() {
+            return Some(false);
+        }
+        if self.statuses.get(id) == Status::Testing {
+            let _ = self.statuses.set(id, Status::Offline);
+        }
+        Some(true)
+    }
+
+    /// Finishes the [`OutputTest`] of an [`Output`] with the given `id`,
+    /// started at the given moment, recording its `result` (if any) into the
+    /// [`Output::last_test`], and the failure into the [`Output::problem`].
+    ///
+    /// Does nothing if such [`OutputTest`] is not running anymore.
+    pub fn finish_output_test(
+        &self,
+        id: OutputId,
+        started_at: &DateTime,
+        result: Option<Result<(), String>>,
+    ) {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "finish_output_test");
+        let output = match restreams
+            .iter_mut()
+            .find_map(|r| r.outputs.iter_mut().find(|o| o.id == id))
+        {
+            Some(o) => o,
+            None => return,
+        };
+        if output.test.as_ref().map(|t| &t.started_at) != Some(started_at) {
+            return;
+        }
+
+        output.test = None;
+        if self.statuses.get(id) == Status::Testing {
+            let _ = self.statuses.set(id, Status::Offline);
+        }
+        if let Some(res) = result {
+            if let Err(e) = &res {
+                output.problem =
+                    Some(ProblemInfo::new(ProblemKind::Test, e.clone()));
+            }
+            output.last_test = Some(OutputTestResult {
+                succeeded: res.is_ok(),
+                failure: res.err(),
+                at: DateTime::now(),
+            });
+        }
+    }
+
+    /// Enables all [`Output`]s in the specified [`Restream`] of this [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been enabled, or `false`
+    /// if all of them already have been enabled, or [`None`] if no [`Restream`]
+    /// with such `restream_id` exists.
+    #[must_use]
+    pub fn enable_all_outputs(&self, restream_id: RestreamId) -> Option<bool> {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("enable_all_outputs", |tx| {
+            tx.set_state_of_all_outputs(restream_id, true)
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Disables all [`Output`]s in the specified [`Restream`] of this
+    /// [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been disabled, or `false`
+    /// if all of them already have been disabled, or [`None`] if no
+    /// [`Restream`] with such `restream_id` exists.
+    #[must_use]
+    pub fn disable_all_outputs(&self, restream_id: RestreamId) -> Option<bool> {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("disable_all_outputs", |tx| {
+            tx.set_state_of_all_outputs(restream_id, false)
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Enables all [`Output`]s in all [`Restream`]s of this [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been enabled, or `false`
+    /// if all of them already have been enabled or there are no outputs
+    #[must_use]
+    pub fn enable_all_outputs_of_restreams(&self) -> bool {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("enable_all_outputs_of_restreams", |tx| {
+            tx.set_state_of_all_outputs_of_restreams(true)
+        })
+        .ok()
+        .unwrap_or_default()
+    }
+
+    /// Disables all [`Output`]s in ALL [`Restream`]s of this [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been disabled, or `false`
+    /// if all of them already have been disabled or there are no outputs
+    #[must_use]
+    pub fn disable_all_outputs_of_restreams(&self) -> bool {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("disable_all_outputs_of_restreams", |tx| {
+            tx.set_state_of_all_outputs_of_restreams(false)
+        })
+        .ok()
+        .unwrap_or_default()
+    }
+
+    /// Enables all [`Output`]s in all [`Restream`]s of the given `group` in
+    /// this [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been enabled, or `false`
+    /// if all of them already have been enabled, or [`None`] if no
+    /// [`Restream`] belongs to such `group`.
+    #[must_use]
+    pub fn enable_group_outputs(&self, group: &RestreamGroup) -> Option<bool> {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("enable_group_outputs", |tx| {
+            tx.set_state_of_group_outputs(group, true)
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Disables all [`Output`]s in all [`Restream`]s of the given `group` in
+    /// this [`State`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been disabled, or `false`
+    /// if all of them already have been disabled, or [`None`] if no
+    /// [`Restream`] belongs to such `group`.
+    #[must_use]
+    pub fn disable_group_outputs(&self, group: &RestreamGroup) -> Option<bool> {
+        // Switching `Output`s applies no `Spec`s, so is never rejected.
+        self.transaction("disable_group_outputs", |tx| {
+            tx.set_state_of_group_outputs(group, false)
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Renames the `from` group of [`Restream`]s in this [`State`] to the `to`
+    /// one, rewriting [`Restream::group`] of all its members at once.
+    ///
+    /// Renaming into an already existing group merges them.
+    ///
+    /// Returns `true` if the group has been renamed, or `false` if it has the
+    /// same name already, or [`None`] if no [`Restream`] belongs to the `from`
+    /// group.
+    #[must_use]
+    pub fn rename_restream_group(
+        &self,
+        from: &RestreamGroup,
+        to: RestreamGroup,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "rename_restream_group");
+        let mut members = restreams
+            .iter_mut()
+            .filter(|r| r.group.as_ref() == Some(from))
+            .peekable();
+        let _ = members.peek()?;
+
+        if *from == to {
+            return Some(false);
+        }
+        for r in members {
+            r.group = Some(to.clone());
+        }
+        Some(true)
+    }
+
+    /// Tunes a [`Volume`] rate of the specified [`Output`] or its [`Mixin`] in
+    /// this [`State`].
+    ///
+    /// Returns `true` if a [`Volume`] rate has been changed, or `false` if it
+    /// has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`]/[`Mixin`] exists.
+    #[must_use]
+    pub fn tune_volume(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        mixin_id: Option<MixinId>,
+        volume: Volume,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(&self.restreams, "tune_volume");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        let curr_volume = if let Some(id) = mixin_id {
+            &mut output.mixins.iter_mut().find(|m| m.id == id)?.volume
+        } else {
+            &mut output.volume
+        };
+
+        if *curr_volume == volume {
+            return Some(false);
+        }
+
+        *curr_volume = volume;
+        Some(true)
+    }
+
+    /// Sets [`OutputMetadata`] of the specified [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if [`OutputMetadata`] has been changed, or `false` if it
+    /// has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_metadata(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        metadata: OutputMetadata,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_metadata");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.metadata == metadata {
+            return Some(false);
+        }
+
+        output.metadata = metadata;
+        Some(true)
+    }
+
+    /// Sets [`OutputConditioning`] of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// Returns `true` if [`OutputConditioning`] has been changed, or `false`
+    /// if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_conditioning(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        conditioning: Option<OutputConditioning>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_conditioning");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.conditioning == conditioning {
+            return Some(false);
+        }
+
+        output.conditioning = conditioning;
+        Some(true)
+    }
+
+    /// Sets [`LoudnessTarget`] of the specified [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if [`LoudnessTarget`] has been changed, or `false` if it
+    /// has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_loudnorm(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        loudnorm: Option<LoudnessTarget>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_loudnorm");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.loudnorm == loudnorm {
+            return Some(false);
+        }
+
+        output.loudnorm = loudnorm;
+        Some(true)
+    }
+
+    /// Sets [`OutputLimits`] of the specified [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if [`OutputLimits`] have been changed, or `false` if
+    /// they have the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_limits(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        limits: Option<OutputLimits>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_limits");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.limits == limits {
+            return Some(false);
+        }
+
+        output.limits = limits;
+        Some(true)
+    }
+
+    /// Sets [`OutputConstraints`] of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// Returns `true` if [`OutputConstraints`] have been changed, or `false`
+    /// if they have the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_constraints(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        constraints: Option<OutputConstraints>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_constraints");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.constraints == constraints {
+            return Some(false);
+        }
+
+        output.constraints = constraints;
+        Some(true)
+    }
+
+    /// Sets [`Output::prewarm`] indicator of the specified [`Output`] in this
+    /// [`State`], along with its [`PrewarmStrategy`].
+    ///
+    /// Returns `true` if [`Output::prewarm`] or [`Output::prewarm_strategy`]
+    /// has been changed, or `false` if they have the same values already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_prewarm(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        prewarm: bool,
+        strategy: PrewarmStrategy,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_prewarm");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.prewarm == prewarm && output.prewarm_strategy == strategy {
+            return Some(false);
+        }
+
+        output.prewarm = prewarm;
+        output.prewarm_strategy = strategy;
+        Some(true)
+    }
+
+    /// Sets [`Restream::consolidate_outputs`] indicator of the specified
+    /// [`Restream`] in this [`State`].
+    ///
+    /// Returns `true` if [`Restream::consolidate_outputs`] has been changed,
+    /// or `false` if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    #[must_use]
+    pub fn set_restream_consolidate_outputs(
+        &self,
+        id: RestreamId,
+        consolidate: bool,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(
+            &self.restreams,
+            "set_restream_consolidate_outputs",
+        );
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+
+        if restream.consolidate_outputs == consolidate {
+            return Some(false);
+        }
+
+        restream.consolidate_outputs = consolidate;
+        Some(true)
+    }
+
+    /// Sets [`RtmpPullOptions`] of the specified [`Input`] in this [`State`].
+    ///
+    /// Returns `true` if [`Input::rtmp_options`] have been changed, or `false`
+    /// if they have the same value already.
+    ///
+    /// Returns [`None`] if no such [`Input`] exists.
+    ///
+    /// # Errors
+    ///
+    /// With [`NotRtmpPull`] if [`RtmpPullOptions`] are set to an [`Input`],
+    /// which doesn't pull a live stream from a remote [RTMP] [`InputSrcUrl`].
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    pub fn set_input_rtmp_options(
+        &self,
+        id: InputId,
+        restream_id: RestreamId,
+        options: Option<RtmpPullOptions>,
+    ) -> Result<Option<bool>, NotRtmpPull> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_input_rtmp_options");
+        let input = match restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.input.find_mut(id))
+        {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        let pulls_rtmp = matches!(
+            &input.src,
+            Some(InputSrc::Remote(s)) if s.url.is_rtmp(),
+        );
+        if options.is_some() && !pulls_rtmp {
+            return Err(NotRtmpPull);
+        }
+
+        if input.rtmp_options == options {
+            return Ok(Some(false));
+        }
+
+        input.rtmp_options = options;
+        Ok(Some(true))
+    }
+
+    /// Sets [`Restream::hls`] indicator of the specified [`Restream`] in this
+    /// [`State`], forgetting its [`Restream::hls_segment_at`] on a change.
+    ///
+    /// Returns `true` if [`Restream::hls`] has been changed, or `false` if it
+    /// has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    #[must_use]
+    pub fn set_restream_hls(&self, id: RestreamId, hls: bool) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_restream_hls");
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+
+        if restream.hls == hls {
+            return Some(false);
+        }
+
+        restream.hls = hls;
+        restream.hls_segment_at = None;
+        Some(true)
+    }
+
+    /// Sets [`Restream::no_auto_cycle`] indicator of the specified
+    /// [`Restream`] in this [`State`].
+    ///
+    /// Returns `true` if [`Restream::no_auto_cycle`] has been changed, or
+    /// `false` if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    #[must_use]
+    pub fn set_restream_no_auto_cycle(
+        &self,
+        id: RestreamId,
+        no_auto_cycle: bool,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_restream_no_auto_cycle");
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+
+        if restream.no_auto_cycle == no_auto_cycle {
+            return Some(false);
+        }
+
+        restream.no_auto_cycle = no_auto_cycle;
+        Some(true)
+    }
+
+    /// Advances the [`Workflow`] of the specified [`Restream`] in this
+    /// [`State`] to its next [`WorkflowStage`] by an operator.
+    ///
+    /// Returns `true` if the [`Workflow`] has been advanced, or `false` if it
+    /// is [`WorkflowStage::Wrapped`] already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    ///
+    /// [`WorkflowStage`]: crate::workflow::WorkflowStage
+    /// [`WorkflowStage::Wrapped`]: crate::workflow::WorkflowStage::Wrapped
+    #[must_use]
+    pub fn advance_restream_workflow(&self, id: RestreamId) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "advance_restream_workflow");
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+        Some(restream.workflow.advance(false))
+    }
+
+    /// Resets the [`Workflow`] of the specified [`Restream`] in this [`State`]
+    /// back to [`WorkflowStage::Planned`].
+    ///
+    /// Returns `true` if the [`Workflow`] has been reset, or `false` if it has
+    /// been reset already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    ///
+    /// [`WorkflowStage::Planned`]: crate::workflow::WorkflowStage::Planned
+    #[must_use]
+    pub fn reset_restream_workflow(&self, id: RestreamId) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "reset_restream_workflow");
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+        Some(restream.workflow.reset())
+    }
+
+    /// Sets [`Workflow::auto_confirm_input`] indicator of the specified
+    /// [`Restream`] in this [`State`].
+    ///
+    /// Returns `true` if [`Workflow::auto_confirm_input`] has been changed, or
+    /// `false` if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`] exists.
+    #[must_use]
+    pub fn set_restream_workflow_auto_confirm(
+        &self,
+        id: RestreamId,
+        auto_confirm_input: bool,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(
+            &self.restreams,
+            "set_restream_workflow_auto_confirm",
+        );
+        let restream = restreams.iter_mut().find(|r| r.id == id)?;
+
+        if restream.workflow.auto_confirm_input == auto_confirm_input {
+            return Some(false);
+        }
+
+        restream.workflow.auto_confirm_input = auto_confirm_input;
+        Some(true)
+    }
+
+    /// Sets [`Output::drop_frames_when_behind`] indicator of the specified
+    /// [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if [`Output::drop_frames_when_behind`] has been changed,
+    /// or `false` if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_drop_frames_when_behind(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        drop: bool,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(
+            &self.restreams,
+            "set_output_drop_frames_when_behind",
+        );
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.drop_frames_when_behind == drop {
+            return Some(false);
+        }
+
+        output.drop_frames_when_behind = drop;
+        Some(true)
+    }
+
+    /// Sets [`Output::src_stream`] of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// The key of the main [`Restream::input`] is normalized to [`None`].
+    ///
+    /// Returns `true` if [`Output::src_stream`] has been changed, or `false`
+    /// if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    ///
+    /// # Errors
+    ///
+    /// If the given `key` doesn't match any [`Input`] of the [`Restream`].
+    pub fn set_output_src_stream(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        key: Option<InputKey>,
+    ) -> anyhow::Result<Option<bool>> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_src_stream");
+        let restream = match restreams.iter_mut().find(|r| r.id == restream_id)
+        {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        if let Some(k) = &key {
+            if restream.input.find_by_key(k).is_none() {
+                return Err(anyhow!(
+                    "No `{}` stream in `{}` Restream",
+                    k,
+                    restream.key,
+                ));
+            }
+        }
+        let key = key.filter(|k| *k != restream.input.key);
+        let output =
+            match restream.outputs.iter_mut().find(|o| o.id == output_id) {
+                Some(o) => o,
+                None => return Ok(None),
+            };
+
+        if output.src_stream == key {
+            return Ok(Some(false));
+        }
+
+        output.src_stream = key;
+        Ok(Some(true))
+    }
+
+    /// Sets [`OutputOverlay`] of the specified [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if [`OutputOverlay`] has been changed, or `false` if it
+    /// has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_overlay(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        overlay: Option<OutputOverlay>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_overlay");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.overlay == overlay {
+            return Some(false);
+        }
+
+        output.overlay = overlay;
+        Some(true)
+    }
+
+    /// Sets [`Output::delay_secs`] of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// Returns `true` if [`Output::delay_secs`] has been changed, or `false`
+    /// if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_delay(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        delay_secs: Option<u32>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_delay");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        let delay_secs = Output::normalize_delay(delay_secs);
+        if output.delay_secs == delay_secs {
+            return Some(false);
+        }
+
+        output.delay_secs = delay_secs;
+        Some(true)
+    }
+
+    /// Sets [`Output::max_bitrate_kbps`] of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// Returns `true` if [`Output::max_bitrate_kbps`] has been changed, or
+    /// `false` if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_max_bitrate(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        kbps: Option<u32>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_max_bitrate");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        let kbps = kbps.filter(|b| *b > 0);
+        if output.max_bitrate_kbps == kbps {
+            return Some(false);
+        }
+
+        output.max_bitrate_kbps = kbps;
+        Some(true)
+    }
+
+    /// Sets [`Output::backup_dst`] of the specified [`Output`] in this
+    /// [`State`], resetting its [`Output::dst_health`].
+    ///
+    /// Returns `true` if [`Output::backup_dst`] has been changed, or `false`
+    /// if it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_backup_dst(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: Option<OutputDstUrl>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_backup_dst");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.backup_dst == dst {
+            return Some(false);
+        }
+
+        output.backup_dst = dst;
+        output.dst_health = OutputDstHealth::default();
+        Some(true)
+    }
+
+    /// Schedules the specified [`Output`] in this [`State`] to replace its
+    /// [`Output::dst`] with the given `dst`, either at the given `apply_at`
+    /// moment, or manually (see [`State::apply_pending_output_dst()`]).
+    ///
+    /// Returns `true` if the scheduled change has been changed, or `false` if
+    /// it's the same already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    ///
+    /// # Errors
+    ///
+    /// - If the given `dst` is the same as the current [`Output::dst`].
+    /// - If the [`Restream`] has another [`Output`] with such `dst` already,
+    ///   or another [`Restream`] has it while
+    ///   [`Settings::reject_dst_conflicts`] is enabled.
+    pub fn schedule_output_dst_change(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: OutputDstUrl,
+        apply_at: Option<DateTime>,
+    ) -> Result<Option<bool>, PendingDstError> {
+        let reject_conflicts = self.settings.lock_ref().reject_dst_conflicts;
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "schedule_output_dst_change");
+
+        check_output_dst(
+            &restreams,
+            restream_id,
+            output_id,
+            &dst,
+            reject_conflicts,
+        )?;
+        let output = match restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter_mut().find(|o| o.id == output_id))
+        {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+        if output.dst == dst {
+            return Err(PendingDstError::SameDst);
+        }
+
+        if output.pending_dst.as_ref() == Some(&dst)
+            && output.apply_at == apply_at
+        {
+            return Ok(Some(false));
+        }
+        output.pending_dst = Some(dst);
+        output.apply_at = apply_at;
+        Ok(Some(true))
+    }
+
+    /// Cancels the scheduled change of [`Output::dst`] of the specified
+    /// [`Output`] in this [`State`].
+    ///
+    /// Returns `true` if the scheduled change has been cancelled, or `false`
+    /// if there is no one.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn cancel_output_dst_change(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "cancel_output_dst_change");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.pending_dst.is_none() {
+            return Some(false);
+        }
+        output.pending_dst = None;
+        output.apply_at = None;
+        Some(true)
+    }
+
+    /// Replaces [`Output::dst`] of the specified [`Output`] in this [`State`]
+    /// with its [`Output::pending_dst`], clearing the latter, so the
+    /// re-streaming process of the [`Output`] is restarted.
+    ///
+    /// If `due_only` is `true`, then the [`Output::pending_dst`] is applied
+    /// only if its [`Output::apply_at`] moment has come already.
+    ///
+    /// Returns `true` if [`Output::dst`] has been replaced, or `false` if
+    /// there is nothing to apply.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    ///
+    /// # Errors
+    ///
+    /// If the [`Output::pending_dst`] is used by another [`Output`] at the
+    /// moment, in the same way [`State::schedule_output_dst_change()`]
+    /// checks. The [`Output::pending_dst`] is kept then.
+    pub fn apply_pending_output_dst(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        due_only: bool,
+    ) -> Result<Option<bool>, Duplicate> {
+        let reject_conflicts = self.settings.lock_ref().reject_dst_conflicts;
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "apply_pending_output_dst");
+
+        let pending = match restreams
+            .iter()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter().find(|o| o.id == output_id))
+        {
+            Some(o) => o.pending_dst.clone().filter(|_| {
+                !due_only
+                    || o.apply_at.map_or(false, |at| at <= DateTime::now())
+            }),
+            None => return Ok(None),
+        };
+        let dst = match pending {
+            Some(dst) => dst,
+            None => return Ok(Some(false)),
+        };
+        check_output_dst(
+            &restreams,
+            restream_id,
+            output_id,
+            &dst,
+            reject_conflicts,
+        )?;
+
+        if let Some(o) = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)
+            .and_then(|r| r.outputs.iter_mut().find(|o| o.id == output_id))
+        {
+            if o.backup_dst.as_ref() == Some(&dst) {
+                o.backup_dst = None;
+            }
+            o.dst = dst;
+            o.pending_dst = None;
+            o.apply_at = None;
+            o.dst_health = OutputDstHealth::default();
+        }
+        let _ = refresh_dst_conflicts(&mut restreams);
+        Ok(Some(true))
+    }
+
+    /// Switches the specified [`Output`] in this [`State`] to re-stream to the
+    /// given [`OutputDestination`], overriding the automatic switching, or
+    /// returns it back to the automatic switching if [`None`] is given.
+    ///
+    /// Returns `true` if the [`Output`] has been switched, or `false` if it
+    /// has the same [`OutputDstHealth::overridden`] destination already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists, or it has
+    /// no [`Output::backup_dst`].
+    #[must_use]
+    pub fn switch_output_dst(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        dst: Option<OutputDestination>,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "switch_output_dst");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id && o.backup_dst.is_some())?;
+
+        if output.dst_health.overridden == dst {
+            return Some(false);
+        }
+
+        output.dst_health.overridden = dst;
+        let _ = output.dst_health.decide(Instant::now());
+        Some(true)
+    }
+
+    /// Records a failure (if not `healthy`) or a success of re-streaming the
+    /// [`Output`] with the given `id` to the given `dst` URL into its
+    /// [`Output::dst_health`], switching its destination if required.
+    ///
+    /// Returns `true` if the destination has been switched.
+    ///
+    /// Returns [`None`] if no such [`Output`] exists, or it has no
+    /// [`Output::backup_dst`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn renew_output_dst_health(
+        &self,
+        id: OutputId,
+        dst: &Url,
+        healthy: bool,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "renew_output_dst_health");
+        let output = restreams
+            .iter_mut()
+            .find_map(|r| r.outputs.iter_mut().find(|o| o.id == id))
+            .filter(|o| o.backup_dst.is_some())?;
+
+        let kind = if output.backup_dst.as_deref() == Some(dst) {
+            OutputDestination::Backup
+        } else {
+            OutputDestination::Primary
+        };
+        output.dst_health.record(kind, healthy);
+        let switched = output.dst_health.decide(Instant::now());
+        if switched {
+            log::info!(
+                "Switched output {} to its {:?} destination",
+                output.id,
+                output.dst_health.active,
+            );
+        }
+        Some(switched)
+    }
+
+    /// Recovers scores of the destinations not being used by all the
+    /// [`Output`]s having an [`Output::backup_dst`] (see
+    /// [`OutputDstHealth::recover()`]), switching them back if required.
+    ///
+    /// Returns the number of the switched [`Output`]s.
+    #[allow(clippy::must_use_candidate)]
+    pub fn recover_output_dsts(&self) -> usize {
+        let has_backups = self
+            .restreams
+            .lock_ref()
+            .iter()
+            .any(|r| r.outputs.iter().any(|o| o.backup_dst.is_some()));
+        if !has_backups {
+            return 0;
+        }
+
+        let now = Instant::now();
+        let mut switched = 0;
+        for o in trace::lock_mut(&self.restreams, "recover_output_dsts")
+            .iter_mut()
+            .flat_map(|r| r.outputs.iter_mut())
+            .filter(|o| o.backup_dst.is_some())
+        {
+            o.dst_health.recover();
+            if o.dst_health.decide(now) {
+                log::info!(
+                    "Switched output {} to its {:?} destination",
+                    o.id,
+                    o.dst_health.active,
+                );
+                switched += 1;
+            }
+        }
+        switched
+    }
+
+    /// Sets [`Output::on_boot`] policy of the specified [`Output`] in this
+    /// [`State`].
+    ///
+    /// Returns `true` if [`Output::on_boot`] has been changed, or `false` if
+    /// it has the same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`] exists.
+    #[must_use]
+    pub fn set_output_on_boot(
+        &self,
+        restream_id: RestreamId,
+        output_id: OutputId,
+        on_boot: OutputBootPolicy,
+    ) -> Option<bool> {
+        let mut restreams =
+            trace::lock_mut(&self.restreams, "set_output_on_boot");
+        let output = restreams
+            .iter_mut()
+            .find(|r| r.id == restream_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?;
+
+        if output.on_boot == on_boot {
+            return Some(false);
+        }
+
+        output.on_boot = on_boot;
+        Some(true)
+    }
+
+    /// Tunes a [`Delay`] of the specified [`Mixin`] in this [`State`].
+    ///
+    /// Returns `true` if a [`Delay`] has been changed, or `false` if it has the
+    /// same value already.
+    ///
+    /// Returns [`None`] if no such [`Restream`]/[`Output`]/[`Mixin`] exists.
+    #[must_use]
+    pub fn tune_delay(
+        &self,
+        input_id: RestreamId,
+        output_id: OutputId,
+        mixin_id: MixinId,
+        delay: Delay,
+    ) -> Option<bool> {
+        let mut restreams = trace::lock_mut(&self.restreams, "tune_delay");
+        let mixin = restreams
+            .iter_mut()
+            .find(|r| r.id == input_id)?
+            .outputs
+            .iter_mut()
+            .find(|o| o.id == output_id)?
+            .mixins
+            .iter_mut()
+            .find(|m| m.id == mixin_id)?;
+
+        if mixin.delay == delay {
+            return Some(false);
+        }
+
+        mixin.delay = delay;
+        Some(true)
+    }
+
+    /// Switches drain mode of this [`State`] (see [`State::draining`]).
+    ///
+    /// Returns `true` if drain mode has been switched, or `false` if it's in
+    /// the requested mode already.
+    #[must_use]
+    pub fn set_draining(&self, enabled: bool) -> bool {
+        if self.draining.replace(enabled) == enabled {
+            return false;
+        }
+        if !enabled {
+            // Touch `Restream`s, so their re-streaming processes, not spawned
+            // while draining, are spawned now.
+            let _ = &mut *trace::lock_mut(&self.restreams, "set_draining");
+        }
+        true
+    }
+
+    /// Disables all [`Input`]s and [`Output`]s of all [`Restream`]s in this
+    /// [`State`] in a single pass, stopping all the re-streaming.
+    ///
+    /// [`Input`]s are disabled for the given [`DisableReason`].
+    ///
+    /// Returns `true` if at least one [`Input`] or [`Output`] has been
+    /// disabled, or `false` if all of them already have been disabled.
+    #[must_use]
+    pub fn stop_everything(&self, reason: &DisableReason) -> bool {
+        let mut restreams = trace::lock_mut(&self.restreams, "stop_everything");
+        let mut statuses = self.statuses.lock_mut("stop_everything");
+        let mut changed = false;
+        for r in restreams.iter_mut() {
+            changed |= r.input.disable(reason.clone(), &mut statuses);
+            for o in &mut r.outputs {
+                changed |= mem::replace(&mut o.enabled, false);
+                o.disabled_reason = None;
+            }
+        }
+        changed
+    }
+}
+
+/// Disables/Enables all the given [`Output`]s.
+///
+/// Returns `true` if at least one [`Output`] has been switched.
+fn switch_outputs<'o, I>(outputs: I, enabled: bool) -> bool
+where
+    I: Iterator<Item = &'o mut Output>,
+{
+    outputs
+        .filter(|o| o.enabled != enabled)
+        .fold(false, |_, o| {
+            o.enabled = enabled;
+            o.disabled_reason = None;
+            true
+        })
+}
+
+/// Batch of mutations of [`State::restreams`], performed atomically via
+/// [`State::transaction()`].
+#[derive(Debug)]
+pub struct Transaction {
+    /// Working copy of [`State::restreams`] being mutated.
+    restreams: Vec<Restream>,
+
+    /// Indicator whether any [`Spec`]s have been applied in this
+    /// [`Transaction`], so its result should be checked before committing.
+    applied: bool,
+}
+
+impl Transaction {
+    /// Applies the given [`spec::v1::Restream`]s the same way
+    /// [`State::apply()`] does.
+    pub fn apply_restreams(
+        &mut self,
+        new: Vec<spec::v1::Restream>,
+        replace: bool,
+    ) {
+        apply_restreams(&mut self.restreams, new, replace);
+        self.applied = true;
+    }
+
+    /// Applies the given [`spec::v1::Restream`] to the [`Restream`] with the
+    /// given `id` (see [`Restream::apply()`]).
+    ///
+    /// Returns [`None`] if there is no [`Restream`] with such `id`.
+    pub fn apply_restream(
+        &mut self,
+        id: RestreamId,
+        spec: spec::v1::Restream,
+        replace: bool,
+    ) -> Option<()> {
+        let restream = self.restreams.iter_mut().find(|r| r.id == id)?;
+        restream.apply(spec, replace);
+        self.applied = true;
+        Some(())
+    }
+
+    /// Disables/Enables all [`Output`]s in the specified [`Restream`].
+    ///
+    /// Returns `true` if at least one [`Output`] has been switched, or
+    /// [`None`] if no [`Restream`] with such `restream_id` exists.
+    pub fn set_state_of_all_outputs(
+        &mut self,
+        restream_id: RestreamId,
+        enabled: bool,
+    ) -> Option<bool> {
+        Some(switch_outputs(
+            self.restreams
+                .iter_mut()
+                .find(|r| r.id == restream_id)?
+                .outputs
+                .iter_mut(),
+            enabled,
+        ))
+    }
+
+    /// Disables/Enables all [`Output`]s in all [`Restream`]s of the given
+    /// `group`.
+    ///
+    /// Returns `true` if at least one [`Output`] has been switched, or
+    /// [`None`] if no [`Restream`] belongs to such `group`.
+    pub fn set_state_of_group_outputs(
+        &mut self,
+        group: &RestreamGroup,
+        enabled: bool,
+    ) -> Option<bool> {
+        let mut members = self
+            .restreams
+            .iter_mut()
+            .filter(|r| r.group.as_ref() == Some(group))
+            .peekable();
+        let _ = members.peek()?;
+
+        Some(switch_outputs(
+            members.flat_map(|r| r.outputs.iter_mut()),
+            enabled,
+        ))
+    }
+
+    /// Disables/Enables all [`Output`]s in ALL [`Restream`]s.
+    ///
+    /// Returns `true` if at least one [`Output`] has been switched.
+    pub fn set_state_of_all_outputs_of_restreams(
+        &mut self,
+        enabled: bool,
+    ) -> bool {
+        switch_outputs(
+            self.restreams.iter_mut().flat_map(|r| r.outputs.iter_mut()),
+            enabled,
+        )
+    }
+}
+
+/// Error of adding (or editing) an entity in a [`State`], which duplicates an
+/// already existing one.
+#[derive(Clone, Debug, Display, Error)]
+pub enum Duplicate {
+    /// [`Restream`] with the same [`Restream::key`] exists already.
+    #[display(fmt = "Restream.key '{}' is used already", key)]
+    Restream {
+        /// ID of the existing [`Restream`].
+        id: RestreamId,
+
+        /// Key of the existing [`Restream`].
+        key: RestreamKey,
+
+        /// Label of the existing [`Restream`], if any.
+        label: Option<Label>,
+    },
+
+    /// [`Output`] with an equivalent [`Output::dst`] exists already in the
+    /// same [`Restream`].
+    #[display(fmt = "Output.dst '{}' is used already", dst)]
+    Output {
+        /// ID of the existing [`Output`].
+        id: OutputId,
+
+        /// Destination URL of the existing [`Output`].
+        dst: OutputDstUrl,
+
+        /// Label of the existing [`Output`], if any.
+        label: Option<Label>,
+    },
+
+    /// [`Output`] with an equivalent [`Output::dst`] exists already in
+    /// another [`Restream`], while [`Settings::reject_dst_conflicts`] is
+    /// enabled.
+    #[display(
+        fmt = "Output.dst '{}' is used already by another Restream",
+        dst
+    )]
+    ForeignOutput {
+        /// ID of the [`Restream`] the existing [`Output`] belongs to.
+        restream_id: RestreamId,
+
+        /// ID of the existing [`Output`].
+        id: OutputId,
+
+        /// Destination URL of the existing [`Output`].
+        dst: OutputDstUrl,
+
+        /// Label of the existing [`Output`], if any.
+        label: Option<Label>,
+    },
+
+    /// Entity with the same ID exists already.
+    #[display(fmt = "ID '{}' is used already", id)]
+    Id {
+        /// The used ID.
+        id: Uuid,
+    },
+}
+
+impl Duplicate {
+    /// Creates a new [`Duplicate::Restream`] pointing to the given existing
+    /// [`Restream`].
+    #[inline]
+    #[must_use]
+    pub fn restream(existing: &Restream) -> Self {
+        Self::Restream {
+            id: existing.id,
+            key: existing.key.clone(),
+            label: existing.label.clone(),
+        }
+    }
+
+    /// Creates a new [`Duplicate::Output`] pointing to the given existing
+    /// [`Output`].
+    #[inline]
+    #[must_use]
+    pub fn output(existing: &Output) -> Self {
+        Self::Output {
+            id: existing.id,
+            dst: existing.dst.clone(),
+            label: existing.label.clone(),
+        }
+    }
+
+    /// Looks for an [`Output`] with an [`Output::dst`] equivalent to the given
+    /// `dst` in the given `restreams` other than the one with the given
+    /// `restream_id`, and creates a new [`Duplicate::ForeignOutput`] pointing
+    /// to it, if any.
+    #[must_use]
+    pub fn foreign_output(
+        restreams: &[Restream],
+        restream_id: RestreamId,
+        dst: &OutputDstUrl,
+    ) -> Option<Self> {
+        let normalized = dst.normalized();
+        restreams
+            .iter()
+            .filter(|r| r.id != restream_id)
+            .find_map(|r| {
+                r.outputs
+                    .iter()
+                    .find(|o| o.dst.normalized() == normalized)
+                    .map(|o| Self::ForeignOutput {
+                        restream_id: r.id,
+                        id: o.id,
+                        dst: o.dst.clone(),
+                        label: o.label.clone(),
+                    })
+            })
+    }
+}
+
+/// Error of adding (or applying) entities to a [`State`].
+#[derive(Clone, Debug, Display, Error, From)]
+pub enum Rejected {
+    /// Entity duplicates an already existing one.
+    #[display(fmt = "{}", _0)]
+    Duplicate(Duplicate),
+
+    /// Adding the entity exceeds the [`State::quota`].
+    #[display(fmt = "{}", _0)]
+    Quota(QuotaExceeded),
+
+    /// File source of an [`Input`] cannot be resolved inside the
+    /// [`State::media_root`].
+    #[display(fmt = "Invalid file source: {}", _0)]
+    #[from(ignore)]
+    FileSrc(paths::PathError),
+
+    /// Image of an [`OutputOverlay`] cannot be resolved inside the
+    /// [`State::media_root`].
+    #[display(fmt = "Invalid overlay image: {}", _0)]
+    #[from(ignore)]
+    OverlayImage(paths::PathError),
+
+    /// [`Restream`] with the given ID pulls a live stream from itself via a
+    /// cycle of chained [`Restream`]s (see [`State::forms_chain_cycle()`]).
+    #[display(fmt = "Restream {} is chained in a cycle", _0)]
+    #[from(ignore)]
+    ChainCycle(#[error(not(source))] RestreamId),
+
+    /// [`Output::src_stream`] is not an [`Input`] of its [`Restream`].
+    #[display(fmt = "No `{}` stream in the Restream", _0)]
+    #[from(ignore)]
+    SrcStream(#[error(not(source))] InputKey),
+}
+
+/// Error of scheduling a change of [`Output::dst`] (see
+/// [`State::schedule_output_dst_change()`]).
+#[derive(Clone, Debug, Display, Error, From)]
+pub enum PendingDstError {
+    /// Scheduled destination duplicates an already existing one.
+    #[display(fmt = "{}", _0)]
+    Duplicate(Duplicate),
+
+    /// Scheduled destination is the same as the current [`Output::dst`].
+    #[display(fmt = "Pending destination must differ from dst")]
+    SameDst,
+}
+
+/// Quota of [`Restream`]s and [`Output`]s allowed in a [`State`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Quota {
+    /// Maximum number of [`Restream`]s allowed.
+    ///
+    /// [`None`] means unlimited.
+    pub max_restreams: Option<u32>,
+
+    /// Maximum number of [`Output`]s allowed in a single [`Restream`].
+    ///
+    /// [`None`] means unlimited.
+    pub max_outputs_per_restream: Option<u32>,
+}
+
+impl Quota {
+    /// Indicates whether this [`Quota`] imposes no limits at all.
+    #[inline]
+    #[must_use]
+    pub fn is_unlimited(&self) -> bool {
+        self.max_restreams.is_none() && self.max_outputs_per_restream.is_none()
+    }
+
+    /// Checks whether the given `count` of [`Restream`]s fits this [`Quota`].
+    ///
+    /// # Errors
+    ///
+    /// If [`Quota::max_restreams`] is exceeded.
+    pub fn check_restreams(&self, count: usize) -> Result<(), QuotaExceeded> {
+        match self.max_restreams.and_then(|limit| excess(limit, count)) {
+            Some((limit, excess)) => {
+                Err(QuotaExceeded::Restreams { limit, excess })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether the given `count` of [`Output`]s of the [`Restream`]
+    /// with the given `key` fits this [`Quota`].
+    ///
+    /// # Errors
+    ///
+    /// If [`Quota::max_outputs_per_restream`] is exceeded.
+    pub fn check_outputs(
+        &self,
+        key: &RestreamKey,
+        count: usize,
+    ) -> Result<(), QuotaExceeded> {
+        match self
+            .max_outputs_per_restream
+            .and_then(|limit| excess(limit, count))
+        {
+            Some((limit, excess)) => Err(QuotaExceeded::Outputs {
+                key: key.clone(),
+                limit,
+                excess,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether all the given `restreams` fit this [`Quota`].
+    ///
+    /// # Errors
+    ///
+    /// If this [`Quota`] is exceeded by any of the `restreams`.
+    pub fn check_all(
+        &self,
+        restreams: &[Restream],
+    ) -> Result<(), QuotaExceeded> {
+        self.check_restreams(restreams.len())?;
+        restreams
+            .iter()
+            .try_for_each(|r| self.check_outputs(&r.key, r.outputs.len()))
+    }
+}
+
+/// Returns the given `limit` along with the amount the given `count` exceeds
+/// it by, if it does.
+fn excess(limit: u32, count: usize) -> Option<(u32, u32)> {
+    let count: u32 = count.try_into().unwrap_or(u32::MAX);
+    (count > limit).then(|| (limit, count - limit))
+}
+
+/// Error of exceeding a [`Quota`].
+#[derive(Clone, Debug, Display, Eq, Error, PartialEq)]
+pub enum QuotaExceeded {
+    /// [`Quota::max_restreams`] is exceeded.
+    #[display(fmt = "Quota of {} Restreams is exceeded by {}", limit, excess)]
+    Restreams {
+        /// Maximum number of [`Restream`]s allowed.
+        limit: u32,
+
+        /// Number of [`Restream`]s over the `limit`.
+        excess: u32,
+    },
+
+    /// [`Quota::max_outputs_per_restream`] is exceeded.
+    #[display(
+        fmt = "Quota of {} Outputs in '{}' Restream is exceeded by {}",
+        limit,
+        key,
+        excess
+    )]
+    Outputs {
+        /// Key of the [`Restream`] exceeding the `limit`.
+        key: RestreamKey,
+
+        /// Maximum number of [`Output`]s allowed in a single [`Restream`].
+        limit: u32,
+
+        /// Number of [`Output`]s over the `limit`.
+        excess: u32,
+    },
+}
+
+impl QuotaExceeded {
+    /// Returns the limit being exceeded.
+    #[inline]
+    #[must_use]
+    pub fn limit(&self) -> u32 {
+        match self {
+            Self::Restreams { limit, .. } | Self::Outputs { limit, .. } => {
+                *limit
+            }
+        }
+    }
+
+    /// Returns the amount the limit is exceeded by.
+    #[inline]
+    #[must_use]
+    pub fn excess(&self) -> u32 {
+        match self {
+            Self::Restreams { excess, .. } | Self::Outputs { excess, .. } => {
+                *excess
+            }
+        }
+    }
+}
+
+/// Returns the first of the given `ids` being used already by any entity of
+/// the given `restreams`.
+fn find_used_id<'r, I>(restreams: I, ids: &[Uuid]) -> Option<Uuid>
+where
+    I: IntoIterator<Item = &'r Restream>,
+{
+    if ids.is_empty() {
+        return None;
+    }
+    let mut used = vec![];
+    for r in restreams {
+        r.collect_ids(&mut used);
+    }
+    let used: HashSet<_> = used.into_iter().collect();
+    ids.iter().copied().find(|id| used.contains(id))
+}
+
+/// Checks whether the given `edited` version of the `n`th of the given
+/// `restreams` uses no IDs of other entities of these `restreams` and the
+/// `trash`.
+///
+/// # Errors
+///
+/// With the [`Duplicate::Id`] used by multiple entities.
+fn check_ids_unique(
+    restreams: &[Restream],
+    n: usize,
+    edited: &Restream,
+    trash: &[Restream],
+) -> Result<(), Duplicate> {
+    let others = restreams
+        .iter()
+        .enumerate()
+        .filter_map(|(i, r)| (i != n).then(|| r));
+    match find_duplicate_id(others.chain(iter::once(edited)).chain(trash)) {
+        Some(id) => Err(Duplicate::Id { id }),
+        None => Ok(()),
+    }
+}
+
+/// Checks whether making the [`Restream`] with the given `id` to pull a live
+/// stream from the given `upstream` one forms a cycle of chained `restreams`.
+fn forms_chain_cycle(
+    restreams: &[Restream],
+    id: RestreamId,
+    upstream: RestreamId,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut queue = vec![upstream];
+    while let Some(curr) = queue.pop() {
+        if curr == id {
+            return true;
+        }
+        if !visited.insert(curr) {
+            continue;
+        }
+        if let Some(r) = restreams.iter().find(|r| r.id == curr) {
+            r.input.collect_upstreams(&mut queue);
+        }
+    }
+    false
+}
+
+/// Returns ID of the first of the given `restreams` pulling a live stream from
+/// itself via a cycle of chained `restreams`.
+fn find_chain_cycle(restreams: &[Restream]) -> Option<RestreamId> {
+    restreams.iter().find_map(|r| {
+        let mut upstreams = vec![];
+        r.input.collect_upstreams(&mut upstreams);
+        upstreams
+            .into_iter()
+            .any(|up| forms_chain_cycle(restreams, r.id, up))
+            .then(|| r.id)
+    })
+}
+
+/// Returns the first ID being used by multiple entities of the given
+/// `restreams`.
+fn find_duplicate_id<'r, I>(restreams: I) -> Option<Uuid>
+where
+    I: IntoIterator<Item = &'r Restream>,
+{
+    let mut ids = vec![];
+    for r in restreams {
+        r.collect_ids(&mut ids);
+    }
+    let mut seen = HashSet::with_capacity(ids.len());
+    ids.into_iter().find(|id| !seen.insert(*id))
+}
+
+/// Collects [`Output::conflicts_with`] of all the [`Output`]s of the given
+/// `restreams`, in the order of their iteration.
+///
+/// [`Output::dst`]s are compared by their [`OutputDstUrl::normalized`] forms.
+fn collect_dst_conflicts(restreams: &[Restream]) -> Vec<Vec<OutputRef>> {
+    let dsts: Vec<_> = restreams
+        .iter()
+        .flat_map(|r| r.outputs.iter().map(move |o| (r, o, o.dst.normalized())))
+        .collect();
+    dsts.iter()
+        .map(|(r, _, dst)| {
+            dsts.iter()
+                .filter(|(other, _, d)| other.id != r.id && d == dst)
+                .map(|(other, o, _)| OutputRef::new(other, o))
+                .collect()
+        })
+        .collect()
+}
+
+/// Checks whether the [`Output`] with the given `id` of the specified
+/// [`Restream`] may re-stream to the given `dst`, being not used by any other
+/// [`Output`] of the same [`Restream`], nor by [`Output`]s of other
+/// [`Restream`]s if `reject_conflicts` is `true`.
+///
+/// # Errors
+///
+/// With the [`Duplicate`] using the `dst` already.
+fn check_output_dst(
+    restreams: &[Restream],
+    restream_id: RestreamId,
+    id: OutputId,
+    dst: &OutputDstUrl,
+    reject_conflicts: bool,
+) -> Result<(), Duplicate> {
+    let outputs = match restreams.iter().find(|r| r.id == restream_id) {
+        Some(r) => &r.outputs,
+        None => return Ok(()),
+    };
+    if let Some(o) = outputs
+        .iter()
+        .find(|o| o.dst.is_equivalent(dst) && o.id != id)
+    {
+        return Err(Duplicate::output(o));
+    }
+    // Already existing conflicts shouldn't prevent editing.
+    let keeps_dst = outputs
+        .iter()
+        .find(|o| o.id == id)
+        .map_or(false, |o| o.dst.is_equivalent(dst));
+    if reject_conflicts && !keeps_dst {
+        if let Some(dup) =
+            Duplicate::foreign_output(restreams, restream_id, dst)
+        {
+            return Err(dup);
+        }
+    }
+    Ok(())
+}
+
+/// Refreshes [`Output::conflicts_with`] of all the [`Output`]s of the given
+/// `restreams`, returning whether any of them has changed.
+fn refresh_dst_conflicts(restreams: &mut [Restream]) -> bool {
+    let conflicts = collect_dst_conflicts(restreams);
+    let mut changed = false;
+    for (o, c) in restreams
+        .iter_mut()
+        .flat_map(|r| r.outputs.iter_mut())
+        .zip(conflicts)
+    {
+        if o.conflicts_with != c {
+            o.conflicts_with = c;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Gap between positions of [`Restream`]s (or [`Output`]s) assigned by
+/// renumbering them, so new ones may be inserted between the existing ones
+/// without renumbering all of them.
+const POSITION_GAP: u32 = 1024;
+
+/// Element of a [`State`] collection ordered by its explicit position, rather
+/// than by its index in the collection.
+trait Positioned {
+    /// Returns position of this element.
+    ///
+    /// `0` means that no position has been assigned yet.
+    fn position(&self) -> u32;
+
+    /// Sets position of this element.
+    fn set_position(&mut self, position: u32);
+}
+
+impl Positioned for Restream {
+    #[inline]
+    fn position(&self) -> u32 {
+        self.position
+    }
+
+    #[inline]
+    fn set_position(&mut self, position: u32) {
+        self.position = position;
+    }
+}
+
+impl Positioned for Output {
+    #[inline]
+    fn position(&self) -> u32 {
+        self.position
+    }
+
+    #[inline]
+    fn set_position(&mut self, position: u32) {
+        self.position = position;
+    }
+}
+
+/// Renumbers the given `items` according to their current order, spreading
+/// their positions with [`POSITION_GAP`]s.
+fn renumber_positions<T: Positioned>(items: &mut [T]) {
+    let mut position = 0_u32;
+    for item in items {
+        position = position.saturating_add(POSITION_GAP);
+        item.set_position(position);
+    }
+}
+
+/// Collects the given `items` assigning them positions according to their
+/// order.
+fn positioned<T, I>(items: I) -> Vec<T>
+where
+    T: Positioned,
+    I: IntoIterator<Item = T>,
+{
+    let mut items: Vec<_> = items.into_iter().collect();
+    renumber_positions(&mut items);
+    items
+}
+
+/// Appends the given `item` to the end of the given `items` ordered by their
+/// positions.
+///
+/// Renumbers all the `items` only if there is no position left after the
+/// last one.
+fn push_positioned<T: Positioned>(items: &mut Vec<T>, mut item: T) {
+    let position = items.last().map_or(Some(POSITION_GAP), |l| {
+        l.position().checked_add(POSITION_GAP)
+    });
+    item.set_position(position.unwrap_or_default());
+    items.push(item);
+    if position.is_none() {
+        renumber_positions(items);
+    }
+}
+
+/// Resolves all the media file paths referred by the given
+/// [`spec::v1::Restream`] (see [`paths::resolve()`]), confining them to the
+/// given `root` directory, if any.
+///
+/// # Errors
+///
+/// If any of the paths cannot be resolved, or escapes the `root` directory.
+fn resolve_media_paths(
+    restream: &mut spec::v1::Restream,
+    root: Option<&Path>,
+) -> Result<(), Rejected> {
+    fn resolve_input(
+        input: &mut spec::v1::Input,
+        root: Option<&Path>,
+    ) -> Result<(), Rejected> {
+        match &mut input.src {
+            Some(spec::v1::InputSrc::File(src)) => {
+                src.path = paths::resolve(&src.path, root)
+                    .map_err(Rejected::FileSrc)?;
+            }
+            Some(spec::v1::InputSrc::FailoverInputs(inputs)) => {
+                for i in inputs {
+                    resolve_input(i, root)?;
+                }
+            }
+            Some(spec::v1::InputSrc::RemoteUrl(_)) | None => {}
+        }
+        Ok(())
+    }
+
+    resolve_input(&mut restream.input, root)?;
+    for o in &mut restream.outputs {
+        if let Some(overlay) = &mut o.overlay {
+            overlay.path = paths::resolve(&overlay.path, root)
+                .map_err(Rejected::OverlayImage)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the given [`spec::v1::Restream`] `specs` to the given `restreams`.
+///
+/// If `replace` is `true` then all the [`Restream`]s, [`Restream::outputs`]
+/// and [`Output::mixins`] will be replaced with new ones, otherwise new ones
+/// will be merged with already existing ones.
+fn apply_restreams(
+    restreams: &mut Vec<Restream>,
+    specs: Vec<spec::v1::Restream>,
+    replace: bool,
+) {
+    if replace {
+        let mut olds = mem::replace(restreams, Vec::with_capacity(specs.len()));
+        for new in specs {
+            // Explicit IDs identify entities even if their keys change.
+            if let Some(mut old) = olds
+                .iter()
+                .enumerate()
+                .find_map(|(n, o)| {
+                    (Some(o.id) == new.id || o.key == new.key).then(|| n)
+                })
+                .map(|n| olds.swap_remove(n))
+            {
+                old.apply(new, replace);
+                restreams.push(old);
+            } else {
+                restreams.push(Restream::new(new));
+            }
+        }
+        // Replacing defines the order of `Restream`s completely.
+        renumber_positions(restreams);
+    } else {
+        for new in specs {
+            if let Some(old) = restreams
+                .iter_mut()
+                .find(|o| Some(o.id) == new.id || o.key == new.key)
+            {
+                old.apply(new, replace);
+            } else {
+                push_positioned(restreams, Restream::new(new));
+            }
+        }
+    }
+}
+
+/// Inserts the given `item` into the given `items` ordered by their positions
+/// according to its own position, if it has any, or appends it otherwise.
+///
+/// If the position of the `item` is occupied already, then it's placed right
+/// after the occupying element, renumbering all the `items` only if there is
+/// no free position between the neighbours.
+fn insert_positioned<T: Positioned>(items: &mut Vec<T>, mut item: T) {
+    let n = items
+        .iter()
+        .position(|i| i.position() > item.position())
+        .unwrap_or(items.len());
+    if item.position() == 0 || n == items.len() {
+        push_positioned(items, item);
+        return;
+    }
+
+    let (prev, next) = (
+        n.checked_sub(1).map_or(0, |p| items[p].position()),
+        items[n].position(),
+    );
+    let is_occupied = prev == item.position();
+    if is_occupied && next - prev >= 2 {
+        item.set_position(prev + (next - prev) / 2);
+    }
+    items.insert(n, item);
+    if is_occupied && next - prev < 2 {
+        renumber_positions(items);
+    }
+}
+
+/// Sorts the given `items` by their positions, assigning them positions
+/// according to their current order if they're missing or ambiguous (as in
+/// state files persisted before positions were introduced).
+fn normalize_positions<T: Positioned>(items: &mut [T]) {
+    items.sort_by_key(Positioned::position);
+    let is_ambiguous = items.first().map_or(false, |i| i.position() == 0)
+        || items.windows(2).any(|w| w[0].position() == w[1].position());
+    if is_ambiguous {
+        renumber_positions(items);
+    }
+}
+
+/// Sorts the given [`Restream`]s and their [`Restream::outputs`] by their
+/// positions, being the canonical order of them.
+pub fn sort_by_position(restreams: &mut [Restream]) {
+    restreams.sort_by_key(|r| r.position);
+    for r in restreams {
+        r.outputs.sort_by_key(|o| o.position);
+    }
+}
+
+/// Re-stream of a live stream from one `Input` to many `Output`s.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Restream {
+    /// Unique ID of this `Input`.
+    ///
+    /// Once assigned, it never changes.
+    pub id: RestreamId,
+
+    /// Unique key of this `Restream` identifying it, and used to form its
+    /// endpoints URLs.
+    #[serde(deserialize_with = "RestreamKey::deserialize_persisted")]
+    pub key: RestreamKey,
+
+    /// Optional label of this `Restream`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
+
+    /// Optional group this `Restream` belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<RestreamGroup>,
+
+    /// `Input` that a live stream is received from.
+    pub input: Input,
+
+    /// `Output`s that a live stream is re-streamed to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Output>,
+
+    /// Indicator whether publishers of this `Restream` should be allowed to
+    /// connect even when its `Input` is disabled, being rejected only on
+    /// publishing, so their retries pick the live stream up as soon as the
+    /// `Input` is enabled.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub queue_publishers: bool,
+
+    /// `PublisherPolicy` resolving concurrent publishing of a live stream
+    /// into the same `Input` of this `Restream`.
+    #[serde(default, skip_serializing_if = "PublisherPolicy::is_default")]
+    pub publisher_policy: PublisherPolicy,
+
+    /// Host to be used in public URLs of this `Restream` instead of the
+    /// globally configured one.
+    ///
+    /// Affects only URLs shown to clients, and never anything done locally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_host_override: Option<PublicHost>,
+
+    /// Number of seconds the `Input` of this `Restream` should stay idle
+    /// (receiving no live stream) continuously for, to be disabled
+    /// automatically.
+    ///
+    /// If [`None`], then the `Input` is never disabled automatically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_disable_after_idle_secs: Option<u32>,
+
+    /// Maximum number of seconds a single session of an external client
+    /// publishing a live stream into the `Input` of this `Restream` may last
+    /// for, before the client is kicked.
+    ///
+    /// If [`None`], then publishing sessions are not limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_session_secs: Option<u32>,
+
+    /// Indicator whether the `Input` of this `Restream` should be disabled once
+    /// a publishing session exceeds [`Restream::max_session_secs`], so the
+    /// kicked client cannot start a fresh one.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disable_on_expiry: bool,
+
+    /// [`IpRange`]s that external clients are allowed to publish a live
+    /// stream into the `Input` of this `Restream` from.
+    ///
+    /// If empty, then publishing is allowed from anywhere.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publish_allowlist: Vec<IpRange>,
+
+    /// Indicator whether the live stream of this `Restream` should be served
+    /// with as low latency as possible, having no GOP cache, at the cost of
+    /// slower start of its players.
+    ///
+    /// See [`Restream::srs_vhost()`] for details.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub low_latency: bool,
+
+    /// Indicator whether the live stream of this `Restream` should be served
+    /// via [HLS] by [SRS] itself.
+    ///
+    /// See [`Restream::srs_vhost()`] for details.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hls: bool,
+
+    /// Moment when [SRS] has produced the last [HLS] segment of the live
+    /// stream of this `Restream`, as reported by its `on_hls` callback.
+    ///
+    /// Kept in memory only.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(skip)]
+    pub hls_segment_at: Option<DateTime>,
+
+    /// Indicator whether this `Restream` is listed on the public status page
+    /// of this server (see [`cli::Opts::public_status_page`]).
+    ///
+    /// [`cli::Opts::public_status_page`]: crate::cli::Opts::public_status_page
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub public: bool,
+
+    /// Indicator whether the re-streaming processes of the `Output`s of this
+    /// `Restream` are never cycled automatically within the maintenance
+    /// window (see [`cycle::cycle_periodically()`]), as any interruption is
+    /// critical for it.
+    ///
+    /// [`cycle::cycle_periodically()`]: crate::cycle::cycle_periodically
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_auto_cycle: bool,
+
+    /// Indicator whether the copy-only `Output`s of this `Restream` are
+    /// re-streamed by a single [FFmpeg] process via its `tee` muxer, rather
+    /// than by a separate process each (see
+    /// [`ffmpeg::TeeRestreamer::is_teeable()`]).
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub consolidate_outputs: bool,
+
+    /// Total number of seconds the `Input` of this `Restream` has been serving
+    /// a live stream since the [`Restream::usage_since`] moment.
+    ///
+    /// Is accumulated by [`usage::UsageMeter`] and persisted periodically, so
+    /// the time since the last persisting is lost on a server crash.
+    ///
+    /// [`usage::UsageMeter`]: crate::usage::UsageMeter
+    #[serde(default)]
+    pub input_online_secs: u64,
+
+    /// Moment since which the [`Restream::input_online_secs`] and the
+    /// [`Output::online_secs`] of this `Restream` are counted: either its
+    /// creation, or the last reset of the counters.
+    ///
+    /// [`None`] if this `Restream` has been created before the usage started
+    /// to be counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_since: Option<DateTime>,
+
+    /// All the [`UsageReset`]s of the counters of this `Restream`, the oldest
+    /// first.
+    ///
+    /// Is kept for auditing only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage_resets: Vec<UsageReset>,
+
+    /// Moment when this `Restream` has been removed into the trash.
+    ///
+    /// [`None`] if this `Restream` is not removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+
+    /// Advisory "go live" [`Workflow`] of this `Restream`.
+    ///
+    /// Never affects re-streaming in any way.
+    #[serde(default, skip_serializing_if = "Workflow::is_default")]
+    pub workflow: Workflow,
+
+    /// Position of this `Restream` among others, being the canonical key
+    /// they're ordered by.
+    ///
+    /// Is kept when this `Restream` is removed into the trash, so it's
+    /// restored into the same place.
+    #[serde(default)]
+    pub position: u32,
+}
+
+/// Re-stream of a live stream from one `Input` to many `Output`s.
+#[graphql_object(context = Context)]
+impl Restream {
+    /// Unique ID of this `Input`.
+    ///
+    /// Once assigned, it never changes.
+    fn id(&self) -> RestreamId {
+        self.id
+    }
+
+    /// Unique key of this `Restream` identifying it, and used to form its
+    /// endpoints URLs.
+    fn key(&self) -> &RestreamKey {
+        &self.key
+    }
+
+    /// Optional label of this `Restream`.
+    fn label(&self) -> Option<&Label> {
+        self.label.as_ref()
+    }
+
+    /// Optional group this `Restream` belongs to.
+    fn group(&self) -> Option<&RestreamGroup> {
+        self.group.as_ref()
+    }
+
+    /// `Input` that a live stream is received from.
+    fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// `Output`s that a live stream is re-streamed to.
+    fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Indicator whether publishers of this `Restream` should be allowed to
+    /// connect even when its `Input` is disabled, being rejected only on
+    /// publishing, so their retries pick the live stream up as soon as the
+    /// `Input` is enabled.
+    fn queue_publishers(&self) -> bool {
+        self.queue_publishers
+    }
+
+    /// `PublisherPolicy` resolving concurrent publishing of a live stream
+    /// into the same `Input` of this `Restream`.
+    fn publisher_policy(&self) -> PublisherPolicy {
+        self.publisher_policy
+    }
+
+    /// Host to be used in public URLs of this `Restream` instead of the
+    /// globally configured one (`Info.publicHost`).
+    ///
+    /// Affects only URLs shown to clients, and never anything done locally.
+    fn public_host_override(&self) -> Option<&PublicHost> {
+        self.public_host_override.as_ref()
+    }
+
+    /// Number of seconds the `Input` of this `Restream` should stay idle
+    /// (receiving no live stream) continuously for, to be disabled
+    /// automatically.
+    ///
+    /// The reason of disabling is reported via `Input.disabledReason`.
+    ///
+    /// If `null`, then the `Input` is never disabled automatically.
+    fn auto_disable_after_idle_secs(&self) -> Option<i32> {
+        self.auto_disable_after_idle_secs
+            .map(|s| s.try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Maximum number of seconds a single session of an external client
+    /// publishing a live stream into the `Input` of this `Restream` may last
+    /// for, before the client is kicked.
+    ///
+    /// If `null`, then publishing sessions are not limited.
+    fn max_session_secs(&self) -> Option<i32> {
+        self.max_session_secs
+            .map(|s| s.try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Indicator whether the `Input` of this `Restream` should be disabled once
+    /// a publishing session exceeds `Restream.maxSessionSecs`, so the kicked
+    /// client cannot start a fresh one.
+    fn disable_on_expiry(&self) -> bool {
+        self.disable_on_expiry
+    }
+
+    /// IP ranges (in CIDR notation) that external clients are allowed to
+    /// publish a live stream into the `Input` of this `Restream` from.
+    ///
+    /// If empty, then publishing is allowed from anywhere.
+    fn publish_allowlist(&self) -> Vec<String> {
+        self.publish_allowlist
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Indicator whether the live stream of this `Restream` is served with as
+    /// low latency as possible, having no GOP cache, at the cost of slower
+    /// start of its players.
+    ///
+    /// Low-latency `Restream`s are served on a dedicated SRS vhost, so their
+    /// publishers should specify it (see `Restream.srsVhost`).
+    fn low_latency(&self) -> bool {
+        self.low_latency
+    }
+
+    /// Indicator whether the live stream of this `Restream` is served via HLS
+    /// by SRS itself (see `Restream.hlsUrl`).
+    ///
+    /// HLS `Restream`s are served on a dedicated SRS vhost, so their
+    /// publishers should specify it (see `Restream.srsVhost`).
+    fn hls(&self) -> bool {
+        self.hls
+    }
+
+    /// URL of the HLS playlist of the live stream of this `Restream`, served
+    /// by SRS HTTP server.
+    ///
+    /// `null` if this `Restream` is not served via HLS.
+    #[graphql(name = "hlsUrl")]
+    fn hls_url_field(&self, context: &Context) -> Option<String> {
+        self.hls_url(
+            self.public_host_override
+                .as_ref()
+                .map(|h| h.as_str())
+                .or_else(|| context.config().public_host.as_deref())?,
+        )
+    }
+
+    /// Moment when SRS has produced the last HLS segment of the live stream of
+    /// this `Restream`, signaling the HLS playlist being alive.
+    ///
+    /// `null` if no segments have been produced since this server start.
+    fn hls_segment_at(&self) -> Option<DateTime> {
+        self.hls_segment_at
+    }
+
+    /// Indicator whether this `Restream` is listed on the public status page
+    /// of this server, showing only its `label` and whether it's online.
+    fn public(&self) -> bool {
+        self.public
+    }
+
+    /// Indicator whether the re-streaming processes of the `Output`s of this
+    /// `Restream` are never cycled automatically within the maintenance
+    /// window of this server, as any interruption is critical for it.
+    fn no_auto_cycle(&self) -> bool {
+        self.no_auto_cycle
+    }
+
+    /// Indicator whether the copy-only `Output`s of this `Restream` are
+    /// re-streamed by a single FFmpeg process, rather than by a separate
+    /// process each.
+    ///
+    /// `Output`s re-encoding, mixing, delaying or pausing their live stream
+    /// are always re-streamed by their own processes.
+    fn consolidate_outputs(&self) -> bool {
+        self.consolidate_outputs
+    }
+
+    /// SRS vhost dedicated to the live stream of this `Restream`, which its
+    /// publishers should specify in the publishing URL (like
+    /// `rtmp://host/app?vhost=<srsVhost>/stream`).
+    ///
+    /// `null` if the default SRS vhost is used.
+    #[graphql(name = "srsVhost")]
+    fn srs_vhost_field(&self) -> Option<String> {
+        self.srs_vhost()
+    }
+
+    /// Moment when the earliest running publishing session of this `Restream`
+    /// will exceed `Restream.maxSessionSecs`.
+    ///
+    /// `null` if there is no running publishing session, or sessions are not
+    /// limited.
+    fn session_expires_at(&self) -> Option<DateTime> {
+        self.session_deadline().map(Into::into)
+    }
+
+    /// Number of seconds remaining until the earliest running publishing
+    /// session of this `Restream` exceeds `Restream.maxSessionSecs`.
+    ///
+    /// `null` if there is no running publishing session, or sessions are not
+    /// limited.
+    fn session_remaining_secs(&self) -> Option<i32> {
+        let remaining = self
+            .session_deadline()?
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        Some(remaining.as_secs().try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Recent `ConnectionEvent`s of clients of this `Restream`, the latest
+    /// first.
+    ///
+    /// Only the last 50 events are kept, in memory only, so the history is
+    /// reset on the server restart.
+    #[graphql(arguments(limit(
+        description = "Maximum number of `ConnectionEvent`s to return. \
+                       If not specified, then all of them are returned."
+    )))]
+    fn connection_history(
+        &self,
+        limit: Option<i32>,
+        context: &Context,
+    ) -> Vec<ConnectionEvent> {
+        let limit = limit.map_or(usize::MAX, |l| l.try_into().unwrap_or(0));
+        context.state().connection_history.recent(self.id, limit)
+    }
+
+    /// Moment when this `Restream` has been removed into the trash.
+    ///
+    /// `null` if this `Restream` is not removed.
+    fn deleted_at(&self) -> Option<DateTime> {
+        self.deleted_at
+    }
+
+    /// Total number of bytes sent by all the `Output`s of this `Restream`
+    /// since the server start (or the last `Mutation.resetBytesSent`).
+    ///
+    /// Represented as `Float`, so is exact up to 2^53 bytes (~9 PB).
+    #[allow(clippy::cast_precision_loss)] // intended, see description
+    fn bytes_sent(&self) -> f64 {
+        ffmpeg::Stats::global().bytes_sent(self.outputs.iter().map(|o| o.id))
+            as f64
+    }
+
+    /// Total number of seconds the `Input` of this `Restream` has been serving
+    /// a live stream since the `usageSince` moment, for billing.
+    ///
+    /// Survives server restarts, but is persisted periodically only, so the
+    /// time since the last persisting may be lost on a server crash.
+    ///
+    /// Represented as `Float`, so is exact up to 2^53 seconds.
+    #[allow(clippy::cast_precision_loss)] // intended, see description
+    fn input_online_secs(&self) -> f64 {
+        self.input_online_secs as f64
+    }
+
+    /// Moment since which the `inputOnlineSecs` of this `Restream` and the
+    /// `onlineSecs` of its `Output`s are counted: either the creation of this
+    /// `Restream`, or the last `Mutation.resetUsageCounters`.
+    ///
+    /// `null` if this `Restream` has been created before the usage started to
+    /// be counted.
+    fn usage_since(&self) -> Option<DateTime> {
+        self.usage_since
+    }
+
+    /// Advisory "go live" `Workflow` of this `Restream`, tracking the progress
+    /// of operators.
+    ///
+    /// Never affects re-streaming in any way.
+    fn workflow(&self) -> &Workflow {
+        &self.workflow
+    }
+}
+
+impl Restream {
+    /// Creates a new [`Restream`] out of the given [`spec::v1::Restream`].
+    #[inline]
+    #[must_use]
+    pub fn new(spec: spec::v1::Restream) -> Self {
+        Self {
+            id: spec.id.unwrap_or_else(RestreamId::random),
+            key: spec.key,
+            label: spec.label,
+            group: spec.group,
+            input: Input::new(spec.input),
+            outputs: positioned(spec.outputs.into_iter().map(Output::new)),
+            queue_publishers: spec.queue_publishers,
+            publisher_policy: spec.publisher_policy,
+            public_host_override: spec.public_host_override,
+            auto_disable_after_idle_secs: spec.auto_disable_after_idle_secs,
+            max_session_secs: spec.max_session_secs,
+            disable_on_expiry: spec.disable_on_expiry,
+            publish_allowlist: spec.publish_allowlist,
+            low_latency: spec.low_latency,
+            hls: spec.hls,
+            hls_segment_at: None,
+            public: spec.public,
+            no_auto_cycle: spec.no_auto_cycle,
+            consolidate_outputs: spec.consolidate_outputs,
+            input_online_secs: 0,
+            usage_since: Some(DateTime::now()),
+            usage_resets: Vec::new(),
+            deleted_at: None,
+            workflow: Workflow::default(),
+            position: 0,
+        }
+    }
+
+    /// Indicates whether an external client with the given `ip` is allowed to
+    /// publish a live stream into the [`Input`] of this [`Restream`] according
+    /// to its [`Restream::publish_allowlist`].
+    #[must_use]
+    pub fn allows_publishing_from(&self, ip: IpAddr) -> bool {
+        self.publish_allowlist.is_empty()
+            || self.publish_allowlist.iter().any(|r| r.contains(ip))
+    }
+
+    /// Indicates whether this [`Restream`] is live at the moment, according to
+    /// the given `statuses`: either its [`Input`] serves a live stream, or any
+    /// of its [`Output`]s is online (see [`Status::is_online()`]).
+    #[must_use]
+    pub fn is_live(&self, statuses: &Statuses) -> bool {
+        self.input.is_ready_to_serve(statuses)
+            || self.outputs.iter().any(|o| statuses.get(o.id).is_online())
+    }
+
+    /// Returns the moment when the earliest running [`PublishingSession`] of
+    /// this [`Restream`] exceeds [`Restream::max_session_secs`].
+    ///
+    /// Returns [`None`] if there is no running [`PublishingSession`], or they
+    /// are not limited.
+    #[must_use]
+    pub fn session_deadline(&self) -> Option<SystemTime> {
+        let max = Duration::from_secs(self.max_session_secs?.into());
+        let mut sessions = vec![];
+        self.input.collect_sessions(&mut sessions);
+        sessions
+            .into_iter()
+            .map(|(_, _, s)| s.started_at + max)
+            .min()
+    }
+
+    /// Collects IDs of this [`Restream`] and all its entities (its
+    /// [`Input`]s, [`Output`]s and their [`Mixin`]s) into the given `ids`.
+    fn collect_ids(&self, ids: &mut Vec<Uuid>) {
+        ids.push(self.id.into());
+        self.input.collect_ids(ids);
+        for o in &self.outputs {
+            ids.push(o.id.into());
+            ids.extend(o.mixins.iter().map(|m| Uuid::from(m.id)));
+        }
+    }
+
+    /// Collects IDs of all the entities of this [`Restream`] having a
+    /// [`Status`] (its [`InputEndpoint`]s, [`Output`]s and their [`Mixin`]s)
+    /// into the given `ids`.
+    fn collect_status_ids(&self, ids: &mut HashSet<Uuid>) {
+        self.input.collect_endpoint_ids(ids);
+        for o in &self.outputs {
+            let _ = ids.insert(o.id.into());
+            ids.extend(o.mixins.iter().map(|m| Uuid::from(m.id)));
+        }
+    }
+
+    /// Applies the given [`spec::v1::Restream`] to this [`Restream`].
+    ///
+    /// If `replace` is `true` then all the [`Restream::outputs`] will be
+    /// replaced with new ones, otherwise new ones will be merged with already
+    /// existing [`Restream::outputs`].
+    pub fn apply(&mut self, new: spec::v1::Restream, replace: bool) {
+        if let Some(id) = new.id {
+            self.id = id;
+        }
+        self.key = new.key;
+        self.label = new.label;
+        self.group = new.group;
+        self.queue_publishers = new.queue_publishers;
+        self.publisher_policy = new.publisher_policy;
+        self.public_host_override = new.public_host_override;
+        self.auto_disable_after_idle_secs = new.auto_disable_after_idle_secs;
+        self.max_session_secs = new.max_session_secs;
+        self.disable_on_expiry = new.disable_on_expiry;
+        self.publish_allowlist = new.publish_allowlist;
+        self.low_latency = new.low_latency;
+        if self.hls != new.hls {
+            self.hls_segment_at = None;
+        }
+        self.hls = new.hls;
+        self.public = new.public;
+        self.no_auto_cycle = new.no_auto_cycle;
+        self.consolidate_outputs = new.consolidate_outputs;
+        self.input.apply(new.input);
+        if replace {
+            let mut olds = mem::replace(
+                &mut self.outputs,
+                Vec::with_capacity(new.outputs.len()),
+            );
+            for new in new.outputs {
+                if let Some(mut old) = olds
+                    .iter()
+                    .enumerate()
+                    .find_map(|(n, o)| {
+                        (Some(o.id) == new.id || o.dst.is_equivalent(&new.dst))
+                            .then(|| n)
+                    })
+                    .map(|n| olds.swap_remove(n))
+                {
+                    old.apply(new, replace);
+                    self.outputs.push(old);
+                } else {
+                    self.outputs.push(Output::new(new));
+                }
+            }
+            // Replacing defines the order of `Output`s completely.
+            renumber_positions(&mut self.outputs);
+        } else {
+            for new in new.outputs {
+                if let Some(old) = self.outputs.iter_mut().find(|o| {
+                    Some(o.id) == new.id || o.dst.is_equivalent(&new.dst)
+                }) {
+                    old.apply(new, replace);
+                } else {
+                    push_positioned(&mut self.outputs, Output::new(new));
+                }
+            }
+        }
+    }
+
+    /// Exports this [`Restream`] as a [`spec::v1::Restream`].
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> spec::v1::Restream {
+        spec::v1::Restream {
+            id: Some(self.id),
+            key: self.key.clone(),
+            label: self.label.clone(),
+            group: self.group.clone(),
+            input: self.input.export(),
+            outputs: self.outputs.iter().map(Output::export).collect(),
+            queue_publishers: self.queue_publishers,
+            publisher_policy: self.publisher_policy,
+            public_host_override: self.public_host_override.clone(),
+            auto_disable_after_idle_secs: self.auto_disable_after_idle_secs,
+            max_session_secs: self.max_session_secs,
+            disable_on_expiry: self.disable_on_expiry,
+            publish_allowlist: self.publish_allowlist.clone(),
+            low_latency: self.low_latency,
+            hls: self.hls,
+            public: self.public,
+            no_auto_cycle: self.no_auto_cycle,
+            consolidate_outputs: self.consolidate_outputs,
+        }
+    }
+
+    /// Returns the [SRS] vhost dedicated to the live stream of this
+    /// [`Restream`], if it's [`Restream::low_latency`] or [`Restream::hls`].
+    ///
+    /// [SRS] configures GOP cache and [HLS] per vhost only, so such
+    /// [`Restream`] is served on its own vhost (see
+    /// [`srs::low_latency_vhost()`] and [`srs::hls_vhost()`]), used both by
+    /// its publishers and local re-streaming processes. [`Restream`]s with
+    /// keys persisted by older versions and violating the current rules are
+    /// never served so, as cannot be put into [SRS] configuration safely.
+    ///
+    /// [`None`] means the default [SRS] vhost.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn srs_vhost(&self) -> Option<String> {
+        if RestreamKey::validate(&self.key).is_err() {
+            return None;
+        }
+        if self.low_latency {
+            Some(srs::low_latency_vhost(&self.key))
+        } else if self.hls {
+            Some(srs::hls_vhost(&self.key))
+        } else {
+            None
+        }
+    }
+
+    /// Returns configuration of the [SRS] vhost dedicated to the live stream
+    /// of this [`Restream`], if any (see [`Restream::srs_vhost()`]).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn srs_vhost_config(&self) -> Option<srs::Vhost> {
+        self.srs_vhost().map(|name| srs::Vhost {
+            name,
+            low_latency: self.low_latency,
+            hls: self.hls,
+        })
+    }
+
+    /// Returns URL of the [HLS] playlist of the live stream of this
+    /// [`Restream`] on the given `public_host`, if it's [`Restream::hls`].
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    #[must_use]
+    pub fn hls_url(&self, public_host: &str) -> Option<String> {
+        self.srs_vhost().filter(|_| self.hls)?;
+        // Public host may have a port already, which is replaced then.
+        let mut url = Url::parse(&format!("http://{}", public_host)).ok()?;
+        url.set_port(Some(srs::http_server_port())).ok()?;
+        url.set_path(&format!(
+            "{}/{}/{}.m3u8",
+            srs::HLS_DIR,
+            self.key,
+            self.input.key,
+        ));
+        Some(url.into())
+    }
+
+    /// Returns an URL on a local [SRS] server of the endpoint representing a
+    /// main [`Input`] in this [`Restream`].
+    ///
+    /// # Errors
+    ///
+    /// If not found any RTMP [`Input`] endpoint
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn main_input_rtmp_endpoint_url(&self) -> anyhow::Result<Url> {
+        self.input_rtmp_endpoint_url(&self.input)
+    }
+
+    /// Returns an URL on a local [SRS] server of the endpoint representing the
+    /// given [`Input`] of this [`Restream`] (the main one, or any of its
+    /// [`FailoverInputSrc::inputs`]).
+    ///
+    /// # Errors
+    ///
+    /// If not found any RTMP [`Input`] endpoint
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn input_rtmp_endpoint_url(
+        &self,
+        input: &Input,
+    ) -> anyhow::Result<Url> {
+        match input.endpoints.iter().find(|e| e.is_rtmp()) {
+            Some(e) => Ok(e.kind.rtmp_url(self, &input.key)),
+            None => Err(anyhow!("Not found any RTMP endpoint")),
+        }
+    }
+
+    /// Returns the [`Input`] of this [`Restream`] whose live stream the given
+    /// [`Output`] re-streams, according to its [`Output::src_stream`].
+    ///
+    /// Returns [`None`] if the [`Output::src_stream`] doesn't match any
+    /// [`Input`] of this [`Restream`] anymore.
+    #[must_use]
+    pub fn output_src_input(&self, output: &Output) -> Option<&Input> {
+        match &output.src_stream {
+            None => Some(&self.input),
+            Some(key) => self.input.find_by_key(key),
+        }
+    }
+}
+
+/// Reset of the usage counters of a [`Restream`] (see
+/// [`State::reset_usage_counters()`]).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct UsageReset {
+    /// Moment this [`UsageReset`] has been performed at.
+    pub at: DateTime,
+
+    /// [`Restream::input_online_secs`] discarded by this [`UsageReset`].
+    pub input_online_secs: u64,
+
+    /// Sum of [`Output::online_secs`] discarded by this [`UsageReset`].
+    pub outputs_online_secs: u64,
+}
+
+/// Activity of a client of a `Restream`, reported by [SRS] HTTP callbacks.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct ConnectionEvent {
+    /// Kind of this `ConnectionEvent`.
+    pub kind: ConnectionEventKind,
+
+    /// Moment of time when this `ConnectionEvent` has happened.
+    pub at: DateTime,
+
+    /// IP address of the client this `ConnectionEvent` is related to.
+    pub client_ip: String,
+
+    /// Key of the `Input` the client has addressed, if any.
+    pub input_key: Option<String>,
+
+    /// Reason of the client being rejected.
+    ///
+    /// `null` if the client has been accepted.
+    pub rejection_reason: Option<String>,
+}
+
+impl ConnectionEvent {
+    /// Creates a new [`ConnectionEvent`] of the given `kind`, happened just
+    /// now with the client of the given `ip`, and rejected with the given
+    /// `reason` (if any).
+    #[inline]
+    #[must_use]
+    pub fn new(
+        kind: ConnectionEventKind,
+        ip: IpAddr,
+        input_key: Option<&str>,
+        rejection_reason: Option<String>,
+    ) -> Self {
+        Self {
+            kind,
+            at: DateTime::now(),
+            client_ip: ip.to_string(),
+            input_key: input_key.map(ToOwned::to_owned),
+            rejection_reason,
+        }
+    }
+}
+
+/// Registry of recent [`ConnectionEvent`]s of [`Restream`]s, identified by
+/// their IDs, the oldest first.
+///
+/// Is kept apart from the [`State::restreams`], so recording a
+/// [`ConnectionEvent`] doesn't wake the subscribers of their configuration.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionHistory(
+    Mutable<HashMap<RestreamId, VecDeque<ConnectionEvent>>>,
+);
+
+impl ConnectionHistory {
+    /// Maximum number of [`ConnectionEvent`]s kept for a single [`Restream`].
+    pub const MAX_EVENTS: usize = 50;
+
+    /// Records the given [`ConnectionEvent`] of the [`Restream`] with the
+    /// given `id`, evicting its oldest one if [`ConnectionHistory::MAX_EVENTS`]
+    /// is reached.
+    pub fn record(&self, id: RestreamId, event: ConnectionEvent) {
+        let mut history = self.0.lock_mut();
+        let events = history.entry(id).or_default();
+        if events.len() >= Self::MAX_EVENTS {
+            let _ = events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Returns at most `limit` recent [`ConnectionEvent`]s of the [`Restream`]
+    /// with the given `id`, the latest first.
+    #[must_use]
+    pub fn recent(&self, id: RestreamId, limit: usize) -> Vec<ConnectionEvent> {
+        self.0.lock_ref().get(&id).map_or_else(Vec::new, |events| {
+            events.iter().rev().take(limit).cloned().collect()
+        })
+    }
+
+    /// Forgets the [`ConnectionEvent`]s of all the [`Restream`]s, except the
+    /// ones with the given `ids`.
+    pub fn retain(&self, ids: &HashSet<RestreamId>) {
+        let history = self.0.lock_ref();
+        if history.keys().all(|id| ids.contains(id)) {
+            return;
+        }
+        drop(history);
+        self.0.lock_mut().retain(|id, _| ids.contains(id));
+    }
+}
+
+/// Kind of a `ConnectionEvent`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq)]
+pub enum ConnectionEventKind {
+    /// Client has connected to a `Restream`.
+    Connect,
+
+    /// Client has started publishing a live stream into an `Input`.
+    Publish,
+
+    /// Client has stopped publishing a live stream into an `Input`.
+    Unpublish,
+
+    /// Client has been kicked for exceeding `Restream.maxSessionSecs`.
+    SessionExpiry,
+}
+
+/// Policy of resolving concurrent publishing of a live stream into the same
+/// `Input` by different clients.
+///
+/// Applies to external clients only.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    PartialEq,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PublisherPolicy {
+    /// New client is rejected while the `Input` is live already.
+    #[default]
+    RejectNew,
+
+    /// Existing client is kicked, and the new one is accepted instead.
+    ReplaceExisting,
+}
+
+impl PublisherPolicy {
+    /// Indicates whether this [`PublisherPolicy`] is the default one.
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required for `serde`
+    #[inline]
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// ID of a `Restream`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLScalarValue,
+    Hash,
+    Into,
+    PartialEq,
+    Serialize,
+)]
+pub struct RestreamId(Uuid);
+
+impl RestreamId {
+    /// Generates a new random [`RestreamId`].
+    #[inline]
+    #[must_use]
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Key of a [`Restream`] identifying it, and used to form its endpoints URLs.
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct RestreamKey(String);
+
+impl RestreamKey {
+    /// Maximum length of a [`RestreamKey`].
+    pub const MAX_LEN: usize = 64;
+
+    /// Prefixes of [`RestreamKey`]s reserved for [SRS] applications generated
+    /// by this application itself (for pulling live streams, for example).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub const RESERVED_PREFIXES: &'static [&'static str] = &["pull_"];
+
+    /// Creates a new [`RestreamKey`] if the given value meets its invariants.
+    #[must_use]
+    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
+        let val = val.into();
+        Self::validate(&val).ok().map(|_| Self(val.into_owned()))
+    }
+
+    /// Validates the given value to represent a valid [`RestreamKey`], being
+    /// usable as an [SRS] application name and a part of URLs.
+    ///
+    /// # Errors
+    ///
+    /// With an [`InvalidRestreamKey`] describing the violated rule.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn validate(val: &str) -> Result<(), InvalidRestreamKey> {
+        static REGEX: Lazy<Regex> =
+            Lazy::new(|| Regex::new("^[a-zA-Z0-9_-]*$").unwrap());
+
+        if val.is_empty() || val.len() > Self::MAX_LEN {
+            return Err(InvalidRestreamKey::Length);
+        }
+        if !REGEX.is_match(val) {
+            return Err(InvalidRestreamKey::Charset);
+        }
+        let lowercased = val.to_ascii_lowercase();
+        if let Some(prefix) = Self::RESERVED_PREFIXES
+            .iter()
+            .find(|p| lowercased.starts_with(*p))
+        {
+            return Err(InvalidRestreamKey::ReservedPrefix(prefix));
+        }
+        Ok(())
+    }
+
+    /// Checks whether this [`RestreamKey`] is the same as the `other` one,
+    /// ignoring ASCII case.
+    #[inline]
+    #[must_use]
+    pub fn eq_ignore_case(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+
+    /// Deserializes a [`RestreamKey`] persisted in a [`State`], accepting the
+    /// non-empty values violating [`RestreamKey::validate()`] with a warning
+    /// only, so [`Restream`]s persisted by older versions still load after
+    /// upgrading.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not a string, or is empty.
+    pub fn deserialize_persisted<'de, D>(
+        deserializer: D,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = <Cow<'_, str>>::deserialize(deserializer)?;
+        if val.is_empty() {
+            return Err(D::Error::custom("Not a valid Restream.key"));
+        }
+        if let Err(e) = Self::validate(&val) {
+            log::warn!(
+                "Restream.key '{}' is not valid ({}), consider changing it",
+                val,
+                e,
+            );
+        }
+        Ok(Self(val.into_owned()))
+    }
+}
+
+impl<'de> Deserialize<'de> for RestreamKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = <Cow<'_, str>>::deserialize(deserializer)?;
+        Self::validate(&val).map_err(|e| {
+            D::Error::custom(format!("Not a valid Restream.key: {}", e))
+        })?;
+        Ok(Self(val.into_owned()))
+    }
+}
+
+/// Type of `Restream`'s `key` identifying it, and used to form its endpoints
+/// URLs.
+///
+/// It should meet `[a-zA-Z0-9_-]{1,64}` format, and should not start with
+/// `pull_` prefix.
+#[graphql_scalar]
+impl<S> GraphQLScalar for RestreamKey
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        // Any string is accepted, leaving the validation to resolvers, so
+        // they can describe the violated rule in a structured error.
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .map(|s| Self(s.to_owned()))
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Reason of a value not being a valid [`RestreamKey`].
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum InvalidRestreamKey {
+    /// Value is empty or longer than [`RestreamKey::MAX_LEN`].
+    #[display(fmt = "must be 1 to {} characters long", RestreamKey::MAX_LEN)]
+    Length,
+
+    /// Value contains characters other than ASCII letters, digits, `_` and
+    /// `-`.
+    #[display(fmt = "may contain only ASCII letters, digits, `_` and `-`")]
+    Charset,
+
+    /// Value starts with one of [`RestreamKey::RESERVED_PREFIXES`].
+    #[display(fmt = "must not start with reserved `{}` prefix", _0)]
+    ReservedPrefix(&'static str),
+}
+
+impl PartialEq<str> for RestreamKey {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+/// Upstream source that a `Restream` receives a live stream from.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Input {
+    /// Unique ID of this `Input`.
+    ///
+    /// Once assigned, it never changes.
+    pub id: InputId,
+
+    /// Key of this `Input` to expose its `InputEndpoint`s with for accepting
+    /// and serving a live stream.
+    pub key: InputKey,
+
+    /// Endpoints of this `Input` serving a live stream for `Output`s and
+    /// clients.
+    pub endpoints: Vec<InputEndpoint>,
+
+    /// Source to pull a live stream from.
+    ///
+    /// If specified, then this `Input` will pull a live stream from it (pull
+    /// kind), otherwise this `Input` will await a live stream to be pushed
+    /// (push kind).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src: Option<InputSrc>,
+
+    /// Indicator whether this `Input` is enabled, so is allowed to receive a
+    /// live stream from its upstream sources.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub enabled: bool,
+
+    /// Maximum lifetime of a connection pulling a live stream from a
+    /// [`RemoteInputSrc`], after which it's proactively re-established (so
+    /// the remote host is re-resolved).
+    ///
+    /// [`None`] means the connection is kept as long as it's alive.
+    #[serde(
+        default,
+        with = "serde_humantime",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_pull_lifetime: Option<Duration>,
+
+    /// Timeout (in seconds) of establishing a connection for pulling a live
+    /// stream from a [`RemoteInputSrc`].
+    ///
+    /// [`None`] means [`Input::DEFAULT_CONNECT_TIMEOUT_SECS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u32>,
+
+    /// Timeout (in seconds) of a connection pulling a live stream from a
+    /// [`RemoteInputSrc`] receiving no data, after which it's considered
+    /// stalled.
+    ///
+    /// [`None`] means [`Input::DEFAULT_IDLE_TIMEOUT_SECS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u32>,
+
+    /// Recurring weekly [`InputSchedule`] allowing this [`Input`] to pull a
+    /// live stream within the scheduled hours only.
+    ///
+    /// [`None`] means pulling at any time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<InputSchedule>,
+
+    /// Additional [`RtmpPullOptions`] of pulling a live stream from a remote
+    /// [RTMP] [`RemoteInputSrc`].
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtmp_options: Option<RtmpPullOptions>,
+
+    /// `DisableReason` of this `Input` being disabled last time.
+    ///
+    /// Is reset once this `Input` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<DisableReason>,
+
+    /// Most recent failure of this `Input`, explaining why it doesn't receive
+    /// a live stream.
+    ///
+    /// Is cleared once this `Input` becomes `Status.ONLINE`.
+    #[serde(skip)]
+    pub problem: Option<ProblemInfo>,
+
+    /// `InputCodecs` of the live stream received by this `Input` at the
+    /// moment.
+    ///
+    /// Is renewed periodically by the [`compat`] module.
+    ///
+    /// [`compat`]: crate::compat
+    #[serde(skip)]
+    pub codecs: Option<InputCodecs>,
+}
+
+/// Upstream source that a `Restream` receives a live stream from.
+#[graphql_object(context = Context)]
+impl Input {
+    /// Unique ID of this `Input`.
+    ///
+    /// Once assigned, it never changes.
+    fn id(&self) -> InputId {
+        self.id
+    }
+
+    /// Key of this `Input` to expose its `InputEndpoint`s with for accepting
+    /// and serving a live stream.
+    fn key(&self) -> &InputKey {
+        &self.key
+    }
+
+    /// Endpoints of this `Input` serving a live stream for `Output`s and
+    /// clients.
+    fn endpoints(&self) -> &[InputEndpoint] {
+        &self.endpoints
+    }
+
+    /// Source to pull a live stream from.
+    ///
+    /// If specified, then this `Input` will pull a live stream from it (pull
+    /// kind), otherwise this `Input` will await a live stream to be pushed
+    /// (push kind).
+    fn src(&self) -> Option<&InputSrc> {
+        self.src.as_ref()
+    }
+
+    /// Indicator whether this `Input` is enabled, so is allowed to receive a
+    /// live stream from its upstream sources.
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// `DisableReason` of this `Input` being disabled last time, explaining
+    /// why and by whom it has been disabled.
+    ///
+    /// `null` if this `Input` is enabled, or has never been disabled since
+    /// being created.
+    fn disabled_reason(&self) -> Option<&DisableReason> {
+        self.disabled_reason.as_ref()
+    }
+
+    /// Most recent failure of this `Input`, explaining why it doesn't receive
+    /// a live stream.
+    ///
+    /// Is cleared once this `Input` becomes `Status.ONLINE`.
+    fn problem(&self) -> Option<&ProblemInfo> {
+        self.problem.as_ref()
+    }
+
+    /// `InputCodecs` of the live stream received by this `Input` at the
+    /// moment.
+    ///
+    /// `null` if this `Input` receives no live stream, or its codecs are not
+    /// known yet.
+    fn codecs(&self) -> Option<&InputCodecs> {
+        self.codecs.as_ref()
+    }
+
+    /// Maximum lifetime (in seconds) of a connection pulling a live stream
+    /// from a `RemoteInputSrc`, after which it's proactively re-established.
+    ///
+    /// `null` means the connection is kept as long as it's alive.
+    fn max_pull_lifetime(&self) -> Option<f64> {
+        self.max_pull_lifetime.map(|d| d.as_secs_f64())
+    }
+
+    /// Timeout (in seconds) of establishing a connection for pulling a live
+    /// stream from a `RemoteInputSrc`.
+    fn connect_timeout_secs(&self) -> i32 {
+        self.connect_timeout()
+            .as_secs()
+            .try_into()
+            .unwrap_or(i32::MAX)
+    }
+
+    /// Timeout (in seconds) of a connection pulling a live stream from a
+    /// `RemoteInputSrc` receiving no data, after which it's considered
+    /// stalled.
+    fn idle_timeout_secs(&self) -> i32 {
+        self.idle_timeout().as_secs().try_into().unwrap_or(i32::MAX)
+    }
+
+    /// `ScheduleRange`s (in UTC) of a recurring weekly schedule allowing this
+    /// `Input` to pull a live stream within them only.
+    ///
+    /// Outside them, its `InputEndpoint`s have `Status.OUT_OF_SCHEDULE`,
+    /// unless this `Input` is disabled.
+    ///
+    /// `null` means pulling at any time.
+    fn schedule(&self) -> Option<&[ScheduleRange]> {
+        self.schedule.as_ref().map(InputSchedule::ranges)
+    }
+
+    /// `ScheduleWindow` of the `Input.schedule` this `Input` is allowed to
+    /// pull a live stream within at the moment.
+    ///
+    /// `null` if there is no `Input.schedule`, or this `Input` is out of it.
+    fn active_window(&self) -> Option<ScheduleWindow> {
+        self.schedule.as_ref()?.active_window(SystemTime::now())
+    }
+
+    /// Nearest `ScheduleWindow` of the `Input.schedule` starting after the
+    /// current moment (and after the `Input.activeWindow`, if any).
+    ///
+    /// `null` if there is no `Input.schedule`.
+    fn next_window(&self) -> Option<ScheduleWindow> {
+        self.schedule.as_ref()?.next_window(SystemTime::now())
+    }
+
+    /// Additional options of pulling a live stream from a remote RTMP
+    /// `RemoteInputSrc`.
+    ///
+    /// `null` means no additional options.
+    fn rtmp_options(&self) -> Option<&RtmpPullOptions> {
+        self.rtmp_options.as_ref()
+    }
+}
+
+impl Input {
+    /// Default value of [`Input::connect_timeout_secs`].
+    pub const DEFAULT_CONNECT_TIMEOUT_SECS: u32 = 10;
+
+    /// Default value of [`Input::idle_timeout_secs`].
+    pub const DEFAULT_IDLE_TIMEOUT_SECS: u32 = 10;
+
+    /// Creates a new [`Input`] out of the given [`spec::v1::Input`].
+    #[must_use]
+    pub fn new(spec: spec::v1::Input) -> Self {
+        Self {
+            id: spec.id.unwrap_or_else(InputId::random),
+            key: spec.key,
+            endpoints: spec
+                .endpoints
+                .into_iter()
+                .map(InputEndpoint::new)
+                .collect(),
+            src: spec.src.map(InputSrc::new),
+            enabled: spec.enabled,
+            max_pull_lifetime: spec.max_pull_lifetime,
+            connect_timeout_secs: spec.connect_timeout_secs,
+            idle_timeout_secs: spec.idle_timeout_secs,
+            schedule: spec.schedule,
+            rtmp_options: spec.rtmp_options,
+            disabled_reason: None,
+            problem: None,
+            codecs: None,
+        }
+    }
+
+    /// Returns timeout of establishing a connection for pulling a live stream
+    /// from a [`RemoteInputSrc`].
+    #[inline]
+    #[must_use]
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.connect_timeout_secs
+                .unwrap_or(Self::DEFAULT_CONNECT_TIMEOUT_SECS)
+                .into(),
+        )
+    }
+
+    /// Returns timeout of a connection pulling a live stream from a
+    /// [`RemoteInputSrc`] receiving no data, after which it's considered
+    /// stalled.
+    #[inline]
+    #[must_use]
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.idle_timeout_secs
+                .unwrap_or(Self::DEFAULT_IDLE_TIMEOUT_SECS)
+                .into(),
+        )
+    }
+
+    /// Indicates whether this [`Input`] pulls a live stream and is enabled,
+    /// but is not allowed to pull at the given moment by its
+    /// [`Input::schedule`].
+    #[inline]
+    #[must_use]
+    pub fn is_out_of_schedule(&self, at: SystemTime) -> bool {
+        self.enabled
+            && self.src.is_some()
+            && self.schedule.as_ref().map_or(false, |s| !s.contains(at))
+    }
+
+    /// Sets the given [`InputSchedule`] to this [`Input`] and all its
+    /// [`FailoverInputSrc::inputs`].
+    ///
+    /// Returns `false` if it has been set already.
+    #[must_use]
+    pub fn set_schedule(&mut self, schedule: Option<InputSchedule>) -> bool {
+        let mut changed = self.schedule != schedule;
+
+        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
+            for i in &mut s.inputs {
+                changed |= i.set_schedule(schedule.clone());
+            }
+        }
+        self.schedule = schedule;
+
+        changed
+    }
+
+    /// Applies the given [`spec::v1::Input`] to this [`Input`].
+    pub fn apply(&mut self, new: spec::v1::Input) {
+        if self.key != new.key
+            || !new.enabled
+            || (self.src.is_none() && new.src.is_some())
+            || (self.src.is_some() && new.src.is_none())
+        {
+            // SRS endpoints have changed, disabled, or push/pull type has been
+            // switched, so we should kick the publisher and all the players.
+            for e in &mut self.endpoints {
+                e.srs_publisher_id = None;
+                e.srs_player_ids.clear();
+            }
+        }
+
+        if let Some(id) = new.id {
+            self.id = id;
+        }
+        self.key = new.key;
+        // Temporary omit changing existing `enabled` value to avoid unexpected
+        // breakages of ongoing re-streams.
+        //self.enabled = new.enabled;
+        self.max_pull_lifetime = new.max_pull_lifetime;
+        self.connect_timeout_secs = new.connect_timeout_secs;
+        self.idle_timeout_secs = new.idle_timeout_secs;
+        self.schedule = new.schedule;
+        self.rtmp_options = new.rtmp_options;
+
+        let mut olds = mem::replace(
+            &mut self.endpoints,
+            Vec::with_capacity(new.endpoints.len()),
+        );
+        for new in new.endpoints {
+            if let Some(mut old) = olds
+                .iter()
+                .enumerate()
+                .find_map(|(n, o)| (o.kind == new.kind).then(|| n))
+                .map(|n| olds.swap_remove(n))
+            {
+                old.apply(new);
+                self.endpoints.push(old);
+            } else {
+                self.endpoints.push(InputEndpoint::new(new));
+            }
+        }
+
+        match (self.src.as_mut(), new.src) {
+            (Some(old), Some(new)) => old.apply(new),
+            (None, Some(new)) => self.src = Some(InputSrc::new(new)),
+            _ => self.src = None,
+        }
+    }
+
+    /// Exports this [`Input`] as a [`spec::v1::Input`].
+    #[must_use]
+    pub fn export(&self) -> spec::v1::Input {
+        spec::v1::Input {
+            id: Some(self.id),
+            key: self.key.clone(),
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(InputEndpoint::export)
+                .collect(),
+            src: self.src.as_ref().map(InputSrc::export),
+            enabled: self.enabled,
+            max_pull_lifetime: self.max_pull_lifetime,
+            connect_timeout_secs: self.connect_timeout_secs,
+            idle_timeout_secs: self.idle_timeout_secs,
+            schedule: self.schedule.clone(),
+            rtmp_options: self.rtmp_options.clone(),
+        }
+    }
+
+    /// Enables this [`Input`].
+    ///
+    /// Returns `false` if it has been enabled already.
+    #[must_use]
+    pub fn enable(&mut self) -> bool {
+        let mut changed = !self.enabled;
+
+        self.enabled = true;
+        self.disabled_reason = None;
+
+        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
+            for i in &mut s.inputs {
+                changed |= i.enable();
+            }
+        }
+
+        changed
+    }
+
+    /// Disables this [`Input`] for the given [`DisableReason`], marking its
+    /// [`InputEndpoint`]s as [`Status::Offline`] in the given `statuses`.
+    ///
+    /// The [`DisableReason`] is recorded only if this [`Input`] has been
+    /// enabled, so the original one of an already disabled [`Input`] is
+    /// preserved.
+    ///
+    /// Returns `false` if it has been disabled already.
+    #[must_use]
+    pub fn disable(
+        &mut self,
+        reason: DisableReason,
+        statuses: &mut Statuses,
+    ) -> bool {
+        let mut changed = self.enabled;
+
+        if self.enabled {
+            self.enabled = false;
+            self.disabled_reason = Some(reason.clone());
+        }
+
+        for e in &mut self.endpoints {
+            e.srs_publisher_id = None;
+            e.srs_player_ids.clear();
+            e.publishing_session = None;
+            // Do not rely only on SRS to set status, as it sporadically races.
+            let _ = statuses.set(e.id, Status::Offline);
+        }
+
+        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
+            for i in &mut s.inputs {
+                changed |= i.disable(reason.clone(), statuses);
+            }
+        }
+
+        changed
+    }
+
+    /// Marks all the [`InputEndpoint`]s of this [`Input`] (and its
+    /// [`FailoverInputSrc::inputs`]) as [`Status::Offline`] in the given
+    /// `statuses`, forgetting all their [SRS] clients.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn reset_status(&mut self, statuses: &mut Statuses) {
+        for e in &mut self.endpoints {
+            e.srs_publisher_id = None;
+            e.srs_player_ids.clear();
+            e.publishing_session = None;
+            let _ = statuses.set(e.id, Status::Offline);
+        }
+
+        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
+            for i in &mut s.inputs {
+                i.reset_status(statuses);
+            }
+        }
+    }
+
+    /// Lookups for an [`Input`] with the given `key` inside this [`Input`] or
+    /// its [`FailoverInputSrc::inputs`].
+    #[must_use]
+    pub fn find_by_key(&self, key: &InputKey) -> Option<&Self> {
+        if self.key == *key {
+            return Some(self);
+        }
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            s.inputs.iter().find_map(|i| i.find_by_key(key))
+        } else {
+            None
+        }
+    }
+
+    /// Lookups for an [`Input`] with the given `id` inside this [`Input`] or
+    /// its [`FailoverInputSrc::inputs`].
+    #[must_use]
+    pub fn find(&self, id: InputId) -> Option<&Self> {
+        if self.id == id {
+            return Some(self);
+        }
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            s.inputs.iter().find_map(|i| i.find(id))
+        } else {
+            None
+        }
+    }
+
+    /// Collects IDs of this [`Input`] and all its
+    /// [`FailoverInputSrc::inputs`] into the given `ids`.
+    fn collect_ids(&self, ids: &mut Vec<Uuid>) {
+        ids.push(self.id.into());
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            for i in &s.inputs {
+                i.collect_ids(ids);
+            }
+        }
+    }
+
+    /// Collects IDs of [`InputEndpoint`]s of this [`Input`] and all its
+    /// [`FailoverInputSrc::inputs`] into the given `ids`.
+    fn collect_endpoint_ids(&self, ids: &mut HashSet<Uuid>) {
+        ids.extend(self.endpoints.iter().map(|e| Uuid::from(e.id)));
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            for i in &s.inputs {
+                i.collect_endpoint_ids(ids);
+            }
+        }
+    }
+
+    /// Lookups for an [`Input`] with the given `id` inside this [`Input`] or
+    /// its [`FailoverInputSrc::inputs`].
+    #[must_use]
+    pub fn find_mut(&mut self, id: InputId) -> Option<&mut Self> {
+        if self.id == id {
+            return Some(self);
+        }
+        if let Some(InputSrc::Failover(s)) = &mut self.src {
+            s.inputs.iter_mut().find_map(|i| i.find_mut(id))
+        } else {
+            None
+        }
+    }
+
+    /// Lookups for an [`Input`] owning an [`InputEndpoint`] with the given `id`
+    /// inside this [`Input`] or its [`FailoverInputSrc::inputs`].
+    #[must_use]
+    pub fn find_by_endpoint(&self, id: EndpointId) -> Option<&Self> {
+        if self.endpoints.iter().any(|e| e.id == id) {
+            return Some(self);
+        }
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            s.inputs.iter().find_map(|i| i.find_by_endpoint(id))
+        } else {
+            None
+        }
+    }
+
+    /// Lookups for an [`Input`] owning an [`InputEndpoint`] with the given `id`
+    /// inside this [`Input`] or its [`FailoverInputSrc::inputs`].
+    #[must_use]
+    pub fn find_by_endpoint_mut(
+        &mut self,
+        id: EndpointId,
+    ) -> Option<&mut Self> {
+        if self.endpoints.iter().any(|e| e.id == id) {
+            return Some(self);
+        }
+        if let Some(InputSrc::Failover(s)) = &mut self.src {
+            s.inputs.iter_mut().find_map(|i| i.find_by_endpoint_mut(id))
+        } else {
+            None
+        }
+    }
+
+    /// Collects [`RestreamId`]s of the chained [`Restream`]s this [`Input`]
+    /// (or its [`FailoverInputSrc::inputs`]) pulls a live stream from.
+    pub fn collect_upstreams(&self, ids: &mut Vec<RestreamId>) {
+        match &self.src {
+            Some(InputSrc::Remote(s)) => ids.extend(s.url.restream_id()),
+            Some(InputSrc::Failover(s)) => {
+                for i in &s.inputs {
+                    i.collect_upstreams(ids);
+                }
+            }
+            Some(InputSrc::File(_)) | None => {}
+        }
+    }
+
+    /// Collects running [`PublishingSession`]s of the [`InputEndpoint`]s of
+    /// this [`Input`] (and its [`FailoverInputSrc::inputs`]), along with IDs
+    /// of their [`Input`]s and [`InputEndpoint`]s.
+    pub fn collect_sessions(
+        &self,
+        sessions: &mut Vec<(InputId, EndpointId, PublishingSession)>,
+    ) {
+        sessions.extend(
+            self.endpoints.iter().filter_map(|e| {
+                e.publishing_session.map(|s| (self.id, e.id, s))
+            }),
+        );
+        if let Some(InputSrc::Failover(s)) = &self.src {
+            for i in &s.inputs {
+                i.collect_sessions(sessions);
+            }
+        }
+    }
+
+    /// Indicates whether this [`Input`] is ready to serve a live stream for
+    /// [`Output`]s, according to the given `statuses`.
+    #[must_use]
+    pub fn is_ready_to_serve(&self, statuses: &Statuses) -> bool {
+        let mut is_online = self
+            .endpoints
+            .iter()
+            .any(|e| e.is_rtmp() && statuses.get(e.id).is_online());
+
+        if !is_online {
+            if let Some(InputSrc::Failover(s)) = &self.src {
+                is_online = s.inputs.iter().any(|i| {
+                    i.endpoints
+                        .iter()
+                        .any(|e| e.is_rtmp() && statuses.get(e.id).is_online())
+                });
+            }
+        }
+
+        is_online
+    }
+}
+
+/// Reason of an `Input` being disabled.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct DisableReason {
+    /// Kind of this `DisableReason`.
+    pub kind: DisableReasonKind,
+
+    /// Human-readable details of this `DisableReason`.
+    pub detail: String,
+
+    /// Moment of time when the `Input` has been disabled.
+    pub at: DateTime,
+
+    /// Actor who has disabled the `Input` (an API it has been disabled via,
+    /// or a watcher of this server, for example).
+    pub actor: String,
+}
+
+impl DisableReason {
+    /// Creates a new [`DisableReason`] of disabling an [`Input`] right now.
+    #[inline]
+    #[must_use]
+    pub fn new<D, A>(kind: DisableReasonKind, detail: D, actor: A) -> Self
+    where
+        D: Into<String>,
+        A: Into<String>,
+    {
+        Self {
+            kind,
+            detail: detail.into(),
+            at: DateTime::now(),
+            actor: actor.into(),
+        }
+    }
+
+    /// Creates a new [`DisableReasonKind::Manual`] [`DisableReason`] of
+    /// disabling an [`Input`] right now by the given `actor`.
+    #[inline]
+    #[must_use]
+    pub fn manual<A: Into<String>>(actor: A) -> Self {
+        Self::new(DisableReasonKind::Manual, "Disabled manually", actor)
+    }
+}
+
+impl Default for DisableReason {
+    /// Creates a new [`DisableReasonKind::Manual`] [`DisableReason`] of
+    /// disabling an [`Input`] right now by an operator.
+    #[inline]
+    fn default() -> Self {
+        Self::manual("operator")
+    }
+}
+
+impl<'de> Deserialize<'de> for DisableReason {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Full {
+                kind: DisableReasonKind,
+                detail: String,
+                at: DateTime,
+                actor: String,
+            },
+            // Previously, only a bare detail of being disabled automatically
+            // has been persisted, so the moment it happened at is unknown.
+            Legacy(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Full {
+                kind,
+                detail,
+                at,
+                actor,
+            } => Self {
+                kind,
+                detail,
+                at,
+                actor,
+            },
+            Raw::Legacy(detail) => {
+                let kind = if detail.starts_with("Session") {
+                    DisableReasonKind::SessionExpiry
+                } else {
+                    DisableReasonKind::Idle
+                };
+                Self::new(kind, detail, "server")
+            }
+        })
+    }
+}
+
+/// Kind of a `DisableReason`.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, GraphQLEnum, PartialEq, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DisableReasonKind {
+    /// `Input` has been disabled manually by an operator.
+    Manual,
+
+    /// `Input` has been disabled automatically after receiving no live stream
+    /// for `Restream.autoDisableAfterIdleSecs`.
+    Idle,
+
+    /// `Input` has been disabled automatically after its publishing session
+    /// has exceeded `Restream.maxSessionSecs` (see
+    /// `Restream.disableOnExpiry`).
+    SessionExpiry,
+}
+
+/// Endpoint of an [`Input`] serving a live stream for [`Output`]s and
+/// clients.
+///
+/// Its [`Status`] is kept in the [`State::statuses`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InputEndpoint {
+    /// Unique ID of this [`InputEndpoint`].
+    ///
+    /// Once assigned, it never changes.
+    pub id: EndpointId,
+
+    /// Kind of this [`InputEndpoint`].
+    pub kind: InputEndpointKind,
+
+    /// ID of [SRS] client who publishes a live stream to this [`InputEndpoint`]
+    /// (either an external client or a local process).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(skip)]
+    pub srs_publisher_id: Option<srs::ClientId>,
+
+    /// IDs of [SRS] clients who play a live stream from this [`InputEndpoint`]
+    /// (either an external clients or a local processes).
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[serde(skip)]
+    pub srs_player_ids: HashSet<srs::ClientId>,
+
+    /// Reason of this [`InputEndpoint`] having become [`Status::Offline`]
+    /// last time, if known.
+    #[serde(skip)]
+    pub offline_reason: Option<String>,
+
+    /// Session of an external client publishing a live stream into this
+    /// [`InputEndpoint`] at the moment, if any.
+    #[serde(skip)]
+    pub publishing_session: Option<PublishingSession>,
+}
+
+/// Endpoint of an `Input` serving a live stream for `Output`s and clients.
+#[graphql_object(context = Context)]
+impl InputEndpoint {
+    /// Unique ID of this `InputEndpoint`.
+    ///
+    /// Once assigned, it never changes.
+    fn id(&self) -> EndpointId {
+        self.id
+    }
+
+    /// Kind of this `InputEndpoint`.
+    fn kind(&self) -> InputEndpointKind {
+        self.kind
+    }
+
+    /// `Status` of this `InputEndpoint` indicating whether it actually serves a
+    /// live stream ready to be consumed by `Output`s and clients.
+    fn status(&self, context: &Context) -> Status {
+        context.state().statuses.get(self.id)
+    }
+
+    /// Reason of this `InputEndpoint` having become `Status.OFFLINE` last
+    /// time, if known (a timed out connection to a pulled source, for
+    /// example).
+    ///
+    /// Is cleared once this `InputEndpoint` becomes `Status.ONLINE`.
+    fn offline_reason(&self) -> Option<&str> {
+        self.offline_reason.as_deref()
+    }
+}
+
+impl InputEndpoint {
+    /// Creates a new [`InputEndpoint`] out of the given
+    /// [`spec::v1::InputEndpoint`].
+    #[inline]
+    #[must_use]
+    pub fn new(spec: spec::v1::InputEndpoint) -> Self {
+        Self {
+            id: EndpointId::random(),
+            kind: spec.kind,
+            srs_publisher_id: None,
+            srs_player_ids: HashSet::new(),
+            offline_reason: None,
+            publishing_session: None,
+        }
+    }
+
+    /// Applies the given [`spec::v1::InputEndpoint`] to this [`InputEndpoint`].
+    #[inline]
+    pub fn apply(&mut self, new: spec::v1::InputEndpoint) {
+        self.kind = new.kind;
+    }
+
+    /// Exports this [`InputEndpoint`] as a [`spec::v1::InputEndpoint`].
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> spec::v1::InputEndpoint {
+        spec::v1::InputEndpoint { kind: self.kind }
+    }
+
+    /// Indicates whether this [`InputEndpoint`] is an
+    /// [`InputEndpointKind::Rtmp`].
+    #[inline]
+    #[must_use]
+    pub fn is_rtmp(&self) -> bool {
+        matches!(self.kind, InputEndpointKind::Rtmp)
+    }
+}
+
+/// Session of an external client publishing a live stream into an
+/// [`InputEndpoint`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct PublishingSession {
+    /// Moment when the client has started publishing.
+    pub started_at: SystemTime,
+
+    /// IP address of the publishing client.
+    pub client_ip: IpAddr,
+}
+
+impl PublishingSession {
+    /// Creates a new [`PublishingSession`] of the client with the given `ip`,
+    /// started just now.
+    #[inline]
+    #[must_use]
+    pub fn new(ip: IpAddr) -> Self {
+        Self {
+            started_at: SystemTime::now(),
+            client_ip: ip,
+        }
+    }
+}
+
+/// Possible kinds of an `InputEndpoint`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLEnum,
+    Hash,
+    PartialEq,
+    Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEndpointKind {
+    /// [RTMP] endpoint.
+    ///
+    /// Can accept a live stream and serve it for playing.
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    #[display(fmt = "RTMP")]
+    Rtmp,
+
+    /// [HLS] endpoint.
+    ///
+    /// Only serves a live stream for playing and is not able to accept one.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    #[display(fmt = "HLS")]
+    Hls,
+}
+
+impl InputEndpointKind {
+    /// Returns RTMP URL on a local [SRS] server of this [`InputEndpointKind`]
+    /// for the given `restream` and `input`.
+    ///
+    /// RTMP endpoints use the [`Restream::srs_vhost()`].
+    ///
+    /// [SRS] app is named after the [`Restream::key`] only, and never after
+    /// the [`InputSrc`] a live stream is pulled from, so editing the latter
+    /// keeps the app (and anything pinned to it) intact.
+    ///
+    /// # Panics
+    /// No panics, because [`InputKey`] is validated, while [`RestreamKey`] is
+    /// never empty and its unusual characters are percent-encoded.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[must_use]
+    pub fn rtmp_url(self, restream: &Restream, input: &InputKey) -> Url {
+        Url::parse(&format!(
+            "rtmp://127.0.0.1:{}/{}{}/{}",
+            srs::rtmp_port(),
+            restream.key,
+            match self {
+                Self::Rtmp => restream
+                    .srs_vhost()
+                    .map(|v| format!("?vhost={}", v))
+                    .unwrap_or_default(),
+                Self::Hls => "?vhost=hls".into(),
+            },
+            input,
+        ))
+        .unwrap()
+    }
+}
+
+/// ID of an `InputEndpoint`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLScalarValue,
+    Into,
+    PartialEq,
+    Serialize,
+)]
+pub struct EndpointId(Uuid);
+
+impl EndpointId {
+    /// Generates a new random [`EndpointId`].
+    #[inline]
+    #[must_use]
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Source to pull a live stream by an `Input` from.
+#[derive(
+    Clone, Debug, Deserialize, Eq, From, GraphQLUnion, PartialEq, Serialize,
+)]
+#[graphql(context = Context)]
+#[serde(rename_all = "lowercase")]
+pub enum InputSrc {
+    /// Remote endpoint.
+    Remote(RemoteInputSrc),
+
+    /// Multiple local endpoints forming a failover source.
+    Failover(FailoverInputSrc),
+
+    /// Local media file.
+    File(FileInputSrc),
+}
+
+impl InputSrc {
+    /// Creates a new [`InputSrc`] out of the given [`spec::v1::InputSrc`].
+    #[inline]
+    #[must_use]
+    pub fn new(spec: spec::v1::InputSrc) -> Self {
+        match spec {
+            spec::v1::InputSrc::RemoteUrl(url) => {
+                Self::Remote(RemoteInputSrc { url })
+            }
+            spec::v1::InputSrc::FailoverInputs(inputs) => {
+                Self::Failover(FailoverInputSrc {
+                    inputs: inputs.into_iter().map(Input::new).collect(),
+                })
+            }
+            spec::v1::InputSrc::File(file) => Self::File(file),
+        }
+    }
+
+    /// Applies the given [`spec::v1::InputSrc`] to this [`InputSrc`].
+    ///
+    /// Replaces all the [`FailoverInputSrc::inputs`] with new ones.
+    pub fn apply(&mut self, new: spec::v1::InputSrc) {
+        match (self, new) {
+            (Self::Remote(old), spec::v1::InputSrc::RemoteUrl(new_url)) => {
+                old.url = new_url
+            }
+            (Self::Failover(src), spec::v1::InputSrc::FailoverInputs(news)) => {
+                let mut olds = mem::replace(
+                    &mut src.inputs,
+                    Vec::with_capacity(news.len()),
+                );
+                for new in news {
+                    if let Some(mut old) = olds
+                        .iter()
+                        .enumerate()
+                        .find_map(|(n, o)| {
+                            (Some(o.id) == new.id || o.key == new.key)
+                                .then(|| n)
+                        })
+                        .map(|n| olds.swap_remove(n))
+                    {
+                        old.apply(new);
+                        src.inputs.push(old);
+                    } else {
+                        src.inputs.push(Input::new(new));
+                    }
+                }
+            }
+            (old, new) => *old = Self::new(new),
+        }
+    }
+
+    /// Exports this [`InputSrc`] as a [`spec::v1::InputSrc`].
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> spec::v1::InputSrc {
+        match self {
+            Self::Remote(i) => spec::v1::InputSrc::RemoteUrl(i.url.clone()),
+            Self::Failover(src) => spec::v1::InputSrc::FailoverInputs(
+                src.inputs.iter().map(Input::export).collect(),
+            ),
+            Self::File(file) => spec::v1::InputSrc::File(file.clone()),
+        }
+    }
+}
+
+/// Remote upstream source to pull a live stream by an `Input` from.
+#[derive(
+    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+pub struct RemoteInputSrc {
+    /// URL of this `RemoteInputSrc`.
+    pub url: InputSrcUrl,
+}
+
+/// Failover source of multiple `Input`s to pull a live stream by an `Input`
+/// from.
+#[derive(
+    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+#[graphql(context = Context)]
+pub struct FailoverInputSrc {
+    /// `Input`s forming this `FailoverInputSrc`.
+    ///
+    /// Failover is implemented by attempting to pull the first `Input` falling
+    /// back to the second one, and so on. Once the first source is restored,
+    /// we pool from it once again.
+    pub inputs: Vec<Input>,
+}
+
+/// Local media file to pull a live stream by an `Input` from.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FileInputSrc {
+    /// Absolute path to the media file.
+    pub path: PathBuf,
+
+    /// Indicator whether the media file should be looped infinitely, rather
+    /// than streamed once.
+    #[serde(default, rename = "loop", skip_serializing_if = "is_false")]
+    pub looped: bool,
+}
+
+/// Local media file to pull a live stream by an `Input` from.
+#[graphql_object]
+impl FileInputSrc {
+    /// Absolute path to the media file on the server.
+    fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Indicator whether the media file is looped infinitely, rather than
+    /// streamed once.
+    #[graphql(name = "loop")]
+    fn looped(&self) -> bool {
+        self.looped
+    }
+}
+
+/// Additional options of pulling a live stream from a remote [RTMP]
+/// [`InputSrcUrl`], required by some CDNs.
+///
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtmpPullOptions {
+    /// Stream name to play, overriding the one in the path of the
+    /// [`InputSrcUrl`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playpath: Option<String>,
+
+    /// URL of the SWF player to report to the remote server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swf_url: Option<Url>,
+
+    /// URL of the web page the live stream is played on to report to the
+    /// remote server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<Url>,
+
+    /// Query string with token parameters (like `token=...&e=...`) to be
+    /// appended to the [`RtmpPullOptions::playpath`], or to the
+    /// [`InputSrcUrl`] if there is none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_query: Option<String>,
+}
+
+impl RtmpPullOptions {
+    /// Creates new [`RtmpPullOptions`] out of the given values, if they meet
+    /// their invariants.
+    ///
+    /// Empty values are omitted, and [`None`] is returned if all of them are
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// With a description of the violated invariant, if any of the given
+    /// values is invalid.
+    pub fn new(
+        playpath: Option<String>,
+        swf_url: Option<Url>,
+        page_url: Option<Url>,
+        token_query: Option<String>,
+    ) -> Result<Option<Self>, &'static str> {
+        let playpath = playpath.filter(|p| !p.is_empty());
+        if playpath
+            .as_deref()
+            .map_or(false, |p| p.contains(char::is_whitespace))
+        {
+            return Err("RTMP playpath should not contain whitespaces");
+        }
+
+        for url in swf_url.iter().chain(&page_url) {
+            if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+                return Err("RTMP SWF and page URLs should be HTTP(S) URLs");
+            }
+        }
+
+        let token_query = token_query
+            .map(|q| q.trim_start_matches('?').to_owned())
+            .filter(|q| !q.is_empty());
+        if token_query.as_deref().map_or(false, |q| {
+            q.contains(|c: char| c.is_whitespace() || c == '#')
+                || q.split('&').any(|p| p.is_empty() || p.starts_with('='))
+        }) {
+            return Err("RTMP token query should be a `key=value&...` string");
+        }
+
+        let opts = Self {
+            playpath,
+            swf_url,
+            page_url,
+            token_query,
+        };
+        Ok((opts != Self::default()).then(|| opts))
+    }
+}
+
+/// Error of setting [`RtmpPullOptions`] to an [`Input`], which doesn't pull a
+/// live stream from a remote [RTMP] [`InputSrcUrl`].
+///
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[derive(Clone, Copy, Debug, Display, Eq, Error, PartialEq)]
+#[display(
+    fmt = "Only an Input pulling from a remote RTMP URL may have RTMP options"
+)]
+pub struct NotRtmpPull;
+
+/// Additional options of pulling a live stream from a remote [RTMP]
+/// `RemoteInputSrc.url`, required by some CDNs.
+///
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[graphql_object]
+impl RtmpPullOptions {
+    /// Stream name to play, overriding the one in the path of the
+    /// `RemoteInputSrc.url`.
+    fn playpath(&self) -> Option<&str> {
+        self.playpath.as_deref()
+    }
+
+    /// URL of the SWF player reported to the remote server.
+    fn swf_url(&self) -> Option<&Url> {
+        self.swf_url.as_ref()
+    }
+
+    /// URL of the web page the live stream is played on reported to the remote
+    /// server.
+    fn page_url(&self) -> Option<&Url> {
+        self.page_url.as_ref()
+    }
+
+    /// Query string with token parameters appended to the `playpath`, or to
+    /// the `RemoteInputSrc.url` if there is none.
+    ///
+    /// Values of the parameters are masked.
+    fn token_query(&self) -> Option<String> {
+        self.token_query.as_deref().map(ffmpeg::mask_query)
+    }
+}
+
+/// ID of an `Input`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLScalarValue,
+    Hash,
+    Into,
+    PartialEq,
+    Serialize,
+)]
+pub struct InputId(Uuid);
+
+impl InputId {
+    /// Generates a new random [`InputId`].
+    #[inline]
+    #[must_use]
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Key of an [`Input`] used to form its endpoint URL.
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct InputKey(String);
+
+impl InputKey {
+    /// Creates a new [`InputKey`] if the given value meets its invariants.
+    #[must_use]
+    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
+        static REGEX: Lazy<Regex> =
+            Lazy::new(|| Regex::new("^[a-z0-9_-]{1,50}$").unwrap());
+
+        let val = val.into();
+        (!val.is_empty() && REGEX.is_match(&val))
+            .then(|| Self(val.into_owned()))
+    }
+}
+
+impl<'de> Deserialize<'de> for InputKey {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
+            .ok_or_else(|| D::Error::custom("Not a valid Input.key"))
+    }
+}
+
+/// Type of `Input`'s `key` used to form its endpoint URL.
+///
+/// It should meet `[a-z0-9_-]{1,50}` format.
+#[graphql_scalar]
+impl<S> GraphQLScalar for InputKey
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(Self::new)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+impl PartialEq<str> for InputKey {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+/// [`Url`] of a [`RemoteInputSrc`].
+///
+/// Only the following URLs are allowed at the moment:
+/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
+///   host);
+/// - [HLS] URL (starting with `http://` or `https://` scheme, having a host,
+///   and with `.m3u8` extension in its path);
+/// - chained [`Restream`] URL (`ephyr://restream/<id>`, where `<id>` is a
+///   [`RestreamId`]), pulled locally without any external hop.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct InputSrcUrl(Url);
+
+impl InputSrcUrl {
+    /// Creates a new [`InputSrcUrl`] if the given [`Url`] is suitable for that.
+    ///
+    /// # Errors
+    ///
+    /// Returns the given [`Url`] back if it doesn't represent a valid
+    /// [`InputSrcUrl`].
+    #[inline]
+    pub fn new(url: Url) -> Result<Self, Url> {
+        if Self::validate(&url) {
+            Ok(Self(url))
+        } else {
+            Err(url)
+        }
+    }
+
+    /// Validates the given [`Url`] to represent a valid [`InputSrcUrl`].
+    #[must_use]
+    pub fn validate(url: &Url) -> bool {
+        match url.scheme() {
+            "rtmp" | "rtmps" => url.has_host(),
+            "http" | "https" => {
+                url.has_host()
+                    && Path::new(url.path()).extension()
+                        == Some("m3u8".as_ref())
+            }
+            "ephyr" => Self::parse_restream_id(url).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Indicates whether this [`InputSrcUrl`] refers to a remote [RTMP]
+    /// endpoint.
+    ///
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    #[inline]
+    #[must_use]
+    pub fn is_rtmp(&self) -> bool {
+        matches!(self.0.scheme(), "rtmp" | "rtmps")
+    }
+
+    /// Returns [`RestreamId`] of the chained [`Restream`] this
+    /// [`InputSrcUrl`] refers to, if any.
+    #[inline]
+    #[must_use]
+    pub fn restream_id(&self) -> Option<RestreamId> {
+        Self::parse_restream_id(&self.0)
+    }
+
+    /// Parses [`RestreamId`] out of the given `ephyr://restream/<id>` [`Url`].
+    #[must_use]
+    fn parse_restream_id(url: &Url) -> Option<RestreamId> {
+        (url.scheme() == "ephyr" && url.host_str() == Some("restream"))
+            .then(|| Uuid::parse_str(url.path().strip_prefix('/')?).ok())
+            .flatten()
+            .map(RestreamId)
+    }
+}
+
+impl<'de> Deserialize<'de> for InputSrcUrl {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
+            D::Error::custom(format!("Not a valid RemoteInputSrc.url: {}", url))
+        })
+    }
+}
+
+/// Type of a `RemoteInputSrc.url`.
+///
+/// Only the following URLs are allowed at the moment:
+/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
+///   host);
+/// - [HLS] URL (starting with `http://` or `https://` scheme, having a host,
+///   and with `.m3u8` extension in its path);
+/// - chained `Restream` URL (`ephyr://restream/<id>`, where `<id>` is a
+///   `RestreamId`), pulled locally without any external hop.
+///
+/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[graphql_scalar]
+impl<S> GraphQLScalar for InputSrcUrl
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(|s| Url::parse(s).ok())
+            .and_then(|url| Self::new(url).ok())
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Downstream destination that a `Restream` re-streams a live stream to.
+///
+/// Its [`Status`] is kept in the [`State::statuses`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Output {
+    /// Unique ID of this `Output`.
+    ///
+    /// Once assigned, it never changes.
+    pub id: OutputId,
+
+    /// Downstream URL to re-stream a live stream onto.
+    ///
+    /// At the moment only [RTMP] and [Icecast] are supported.
+    ///
+    /// [Icecast]: https://icecast.org
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    pub dst: OutputDstUrl,
+
+    /// Backup downstream URL to re-stream a live stream onto instead of the
+    /// [`Output::dst`] while the latter keeps failing.
+    ///
+    /// See [`OutputDstHealth`] for details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_dst: Option<OutputDstUrl>,
+
+    /// Optional label of this `Output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
+
+    /// Url of stream preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_url: Option<Url>,
+
+    /// Volume rate of this `Output`'s audio tracks when mixed with
+    /// `Output.mixins`.
+    ///
+    /// Has no effect when there is no `Output.mixins`.
+    #[serde(default, skip_serializing_if = "Volume::is_origin")]
+    pub volume: Volume,
+
+    /// `Mixin`s to mix this `Output` with before re-streaming it to its
+    /// downstream destination.
+    ///
+    /// If empty, then no mixing is performed and re-streaming is as cheap as
+    /// possible (just copies bytes "as is").
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mixins: Vec<Mixin>,
+
+    /// `OutputMetadata` to be injected into the live stream re-streamed by
+    /// this `Output`.
+    #[serde(default, skip_serializing_if = "OutputMetadata::is_empty")]
+    pub metadata: OutputMetadata,
+
+    /// `OutputConditioning` to re-encode the live stream with before
+    /// re-streaming it to the downstream destination of this `Output`.
+    ///
+    /// If [`None`], then the live stream is re-streamed "as is".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditioning: Option<OutputConditioning>,
+
+    /// `LoudnessTarget` to normalize the audio of this `Output` to.
+    ///
+    /// If `Output.mixins` are present, then the mixed audio is normalized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudnorm: Option<LoudnessTarget>,
+
+    /// `OutputOverlay` image to be drawn over the video of this `Output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<OutputOverlay>,
+
+    /// Number of seconds to delay the live stream re-streamed by this
+    /// `Output` for, so it's time-shifted behind the live edge.
+    ///
+    /// Cannot exceed `Output::MAX_DELAY_SECS`. If [`None`], then the live
+    /// stream is re-streamed without any delay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_secs: Option<u32>,
+
+    /// Maximum bitrate (in kbit/s) to re-stream the live stream of this
+    /// `Output` with, smoothing out bursts for constrained destinations.
+    ///
+    /// Has no effect when there are `Output.mixins`. If [`None`], then the
+    /// bitrate is not capped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bitrate_kbps: Option<u32>,
+
+    /// `OutputLimits` of OS resources the re-streaming process of this
+    /// `Output` is allowed to consume.
+    ///
+    /// If [`None`], then the process is not limited anyhow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<OutputLimits>,
+
+    /// `OutputConstraints` of the downstream destination of this `Output` on
+    /// the live stream it accepts.
+    ///
+    /// If [`None`], then any live stream is considered being accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<OutputConstraints>,
+
+    /// Indicator whether the re-streaming process of this `Output` is kept
+    /// connected to its downstream destination while this `Output` is
+    /// disabled and its `Input` is online (in the way defined by its
+    /// [`Output::prewarm_strategy`]), so it goes live near-instantly once
+    /// enabled.
+    ///
+    /// Takes effect only for an `Output` switchable to a slate at runtime
+    /// (see [`Output::detect_pause_mode()`]).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub prewarm: bool,
+
+    /// `PrewarmStrategy` of keeping this `Output` connected to its downstream
+    /// destination while being prewarmed (see [`Output::prewarm`]).
+    #[serde(default, skip_serializing_if = "PrewarmStrategy::is_default")]
+    pub prewarm_strategy: PrewarmStrategy,
+
+    /// Key of the `Input` of its `Restream` to re-stream the live stream of:
+    /// one of the `FailoverInputSrc.inputs` published separately (like a
+    /// clean feed along with the program one).
+    ///
+    /// If [`None`], then the live stream of the main `Restream.input` is
+    /// re-streamed (see [`Restream::output_src_input()`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src_stream: Option<InputKey>,
+
+    /// Indicator whether the re-streaming process of this `Output` bounds its
+    /// send buffer and drops frames once its downstream destination cannot
+    /// keep up with the live stream, rather than stalling and dropping the
+    /// connection eventually (see [`ffmpeg::backpressure_args()`]).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub drop_frames_when_behind: bool,
+
+    /// Indicator whether this `Output` is enabled, so is allowed to perform a
+    /// live stream re-streaming to its downstream destination.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub enabled: bool,
+
+    /// `OutputBootPolicy` of this `Output`, defining whether it's enabled
+    /// after the server restart.
+    #[serde(default, skip_serializing_if = "OutputBootPolicy::is_default")]
+    pub on_boot: OutputBootPolicy,
+
+    /// Position of this `Output` among others of its `Restream`, being the
+    /// canonical key they're ordered by.
+    #[serde(default)]
+    pub position: u32,
+
+    /// Total number of seconds this `Output` has been `Status::Online` since
+    /// the [`Restream::usage_since`] moment of its `Restream`.
+    ///
+    /// Is accumulated by [`usage::UsageMeter`] and persisted periodically, so
+    /// the time since the last persisting is lost on a server crash.
+    ///
+    /// [`usage::UsageMeter`]: crate::usage::UsageMeter
+    #[serde(default)]
+    pub online_secs: u64,
+
+    /// Reason of this `Output` being disabled automatically, rather than
+    /// manually.
+    ///
+    /// Is reset once this `Output` is enabled or disabled manually.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<String>,
+
+    /// Downstream URL to replace the [`Output::dst`] with once it's applied
+    /// (see [`State::apply_pending_output_dst()`]), like a fresh stream key
+    /// of a scheduled event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_dst: Option<OutputDstUrl>,
+
+    /// Moment to apply the [`Output::pending_dst`] at automatically.
+    ///
+    /// If [`None`], then the [`Output::pending_dst`] is applied manually only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apply_at: Option<DateTime>,
+
+    /// Indicator whether this `Output` is paused, so re-streams a slate
+    /// (black frame with silent audio) instead of its live stream, while
+    /// staying connected to its downstream destination.
+    ///
+    /// Is not persisted, so is reset on the server restart.
+    #[serde(skip)]
+    pub paused: bool,
+
+    /// Most recent failure of this `Output`, explaining why it doesn't
+    /// re-stream a live stream.
+    ///
+    /// Is cleared once this `Output` becomes `Status.ONLINE`.
+    #[serde(skip)]
+    pub problem: Option<ProblemInfo>,
+
+    /// Description of how the live stream of this [`Output`] doesn't meet its
+    /// [`Output::constraints`], if it doesn't.
+    ///
+    /// Is renewed by [`compat::ConstraintsChecker`].
+    ///
+    /// [`compat::ConstraintsChecker`]: crate::compat::ConstraintsChecker
+    #[serde(skip)]
+    pub incompatibility: Option<String>,
+
+    /// [`OutputDstHealth`] of this [`Output`], choosing the destination to
+    /// re-stream to, if it has an [`Output::backup_dst`].
+    ///
+    /// Is not persisted, so the [`Output::dst`] is tried first after the
+    /// server restart.
+    #[serde(skip)]
+    pub dst_health: OutputDstHealth,
+
+    /// `OutputTest` re-streaming a test signal to the downstream destination
+    /// of this `Output` at the moment, if any.
+    #[serde(skip)]
+    pub test: Option<OutputTest>,
+
+    /// Result of the most recently finished `OutputTest` of this `Output`, if
+    /// any.
+    #[serde(skip)]
+    pub last_test: Option<OutputTestResult>,
+
+    /// `Output`s of other `Restream`s re-streaming to the same downstream
+    /// destination as this `Output` does.
+    #[serde(skip)]
+    pub conflicts_with: Vec<OutputRef>,
+}
+
+/// Downstream destination that a `Restream` re-streams a live stream to.
+#[graphql_object(context = Context)]
+impl Output {
+    /// Unique ID of this `Output`.
+    ///
+    /// Once assigned, it never changes.
+    fn id(&self) -> OutputId {
+        self.id
+    }
+
+    /// Downstream URL to re-stream a live stream onto.
+    ///
+    /// At the moment only [RTMP] and [Icecast] are supported.
+    ///
+    /// [Icecast]: https://icecast.org
+    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+    fn dst(&self) -> &OutputDstUrl {
+        &self.dst
+    }
+
+    /// Backup downstream URL to re-stream a live stream onto instead of the
+    /// `Output.dst` while the latter keeps failing (for platforms providing
+    /// primary and backup ingest URLs for the same stream key).
+    ///
+    /// Only one of them is re-streamed to at a time (see
+    /// `Output.activeDst`).
+    fn backup_dst(&self) -> Option<&OutputDstUrl> {
+        self.backup_dst.as_ref()
+    }
+
+    /// Downstream URL scheduled to replace the `Output.dst` (see
+    /// `Mutation.scheduleOutputDstChange`), like a fresh stream key of an
+    /// upcoming event.
+    ///
+    /// `null` if no change is pending.
+    fn pending_dst(&self) -> Option<&OutputDstUrl> {
+        self.pending_dst.as_ref()
+    }
+
+    /// Moment the `Output.pendingDst` is applied at automatically.
+    ///
+    /// `null` if it's applied manually only (see `Mutation.applyPendingDst`),
+    /// or no change is pending.
+    fn apply_at(&self) -> Option<DateTime> {
+        self.apply_at
+    }
+
+    /// Optional label of this `Output`.
+    fn label(&self) -> Option<&Label> {
+        self.label.as_ref()
+    }
+
+    /// Url of stream preview.
+    fn preview_url(&self) -> Option<&Url> {
+        self.preview_url.as_ref()
+    }
+
+    /// Volume rate of this `Output`'s audio tracks when mixed with
+    /// `Output.mixins`.
+    ///
+    /// Has no effect when there is no `Output.mixins`.
+    fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    /// `Mixin`s to mix this `Output` with before re-streaming it to its
+    /// downstream destination.
+    ///
+    /// If empty, then no mixing is performed and re-streaming is as cheap as
+    /// possible (just copies bytes "as is").
+    fn mixins(&self) -> &[Mixin] {
+        &self.mixins
+    }
+
+    /// `OutputMetadata` to be injected into the live stream re-streamed by
+    /// this `Output`.
+    fn metadata(&self) -> &OutputMetadata {
+        &self.metadata
+    }
+
+    /// `OutputConditioning` to re-encode the live stream with before
+    /// re-streaming it to the downstream destination of this `Output`.
+    ///
+    /// If `null`, then the live stream is re-streamed "as is".
+    fn conditioning(&self) -> Option<OutputConditioning> {
+        self.conditioning
+    }
+
+    /// `LoudnessTarget` to normalize the audio of this `Output` to.
+    ///
+    /// If `Output.mixins` are present, then the mixed audio is normalized.
+    fn loudnorm(&self) -> Option<LoudnessTarget> {
+        self.loudnorm
+    }
+
+    /// `OutputOverlay` image to be drawn over the video of this `Output`.
+    ///
+    /// Forces the video of this `Output` to be re-encoded.
+    fn overlay(&self) -> Option<&OutputOverlay> {
+        self.overlay.as_ref()
+    }
+
+    /// Number of seconds the live stream re-streamed by this `Output` is
+    /// delayed for, so it's time-shifted behind the live edge.
+    ///
+    /// If `null`, then the live stream is re-streamed without any delay.
+    fn delay_secs(&self) -> Option<i32> {
+        self.delay_secs.map(|d| d.try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Maximum bitrate (in kbit/s) to re-stream the live stream of this
+    /// `Output` with, smoothing out bursts for constrained destinations.
+    ///
+    /// If the video is re-encoded anyway (see `Output.conditioning` or
+    /// `Output.overlay`), then its encoder is constrained to this bitrate.
+    /// Otherwise, the copied live stream is paced at its native rate, so is
+    /// not sent in bursts (the exact mechanism is shown by
+    /// `Query.outputCommand`).
+    ///
+    /// Has no effect when there are `Output.mixins`. If `null`, then the
+    /// bitrate is not capped.
+    fn max_bitrate_kbps(&self) -> Option<i32> {
+        self.max_bitrate_kbps
+            .map(|b| b.try_into().unwrap_or(i32::MAX))
+    }
+
+    /// `OutputLimits` of OS resources the re-streaming process of this
+    /// `Output` is allowed to consume.
+    ///
+    /// If `null`, then the process is not limited anyhow.
+    fn limits(&self) -> Option<OutputLimits> {
+        self.limits
+    }
+
+    /// `OutputConstraints` of the downstream destination of this `Output` on
+    /// the live stream it accepts, checked against the `Input.codecs`.
+    ///
+    /// If `null`, then any live stream is considered being accepted.
+    fn constraints(&self) -> Option<&OutputConstraints> {
+        self.constraints.as_ref()
+    }
+
+    /// Indicator whether the re-streaming process of this `Output` is kept
+    /// connected to its downstream destination while this `Output` is
+    /// disabled and its `Input` is online (in the way defined by its
+    /// `Output.prewarmStrategy`), so it goes live near-instantly once enabled.
+    ///
+    /// Takes effect only for an `Output` having `LIVE` `Output.pauseMode`,
+    /// being ignored otherwise.
+    ///
+    /// Prewarmed process reports `PREWARMED` `Output.status` once connected,
+    /// and `ONLINE` once this `Output` is enabled.
+    fn prewarm(&self) -> bool {
+        self.prewarm
+    }
+
+    /// `PrewarmStrategy` of keeping this `Output` connected to its downstream
+    /// destination while being prewarmed (see `Output.prewarm`).
+    fn prewarm_strategy(&self) -> PrewarmStrategy {
+        self.prewarm_strategy
+    }
+
+    /// Key of the `Input` of its `Restream` this `Output` re-streams the live
+    /// stream of: one of the `FailoverInputSrc.inputs`, published separately
+    /// (like a clean feed along with the program one).
+    ///
+    /// `null` if the live stream of the main `Restream.input` is re-streamed.
+    fn src_stream(&self) -> Option<&InputKey> {
+        self.src_stream.as_ref()
+    }
+
+    /// Indicator whether the re-streaming process of this `Output` bounds its
+    /// send buffer and drops frames once its downstream destination cannot
+    /// keep up with the live stream, rather than stalling and dropping the
+    /// connection eventually.
+    fn drop_frames_when_behind(&self) -> bool {
+        self.drop_frames_when_behind
+    }
+
+    /// Indicator whether this `Output` is enabled, so is allowed to perform a
+    /// live stream re-streaming to its downstream destination.
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// `OutputBootPolicy` of this `Output`, defining whether it's enabled
+    /// after the server restart.
+    fn on_boot(&self) -> OutputBootPolicy {
+        self.on_boot
+    }
+
+    /// Reason of this `Output` being disabled automatically (see
+    /// `Output.onBoot`), rather than manually.
+    ///
+    /// `null` if this `Output` is enabled, or has been disabled manually.
+    fn disabled_reason(&self) -> Option<&str> {
+        self.disabled_reason.as_deref()
+    }
+
+    /// Indicator whether this `Output` is paused, so re-streams a slate
+    /// (black frame with silent audio) instead of its live stream, while
+    /// staying connected to its downstream destination.
+    ///
+    /// Unlike `Output.enabled`, it's not persisted, so is reset on the server
+    /// restart.
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// `PauseMode` this `Output` is paused (and resumed) with.
+    fn pause_mode(&self) -> PauseMode {
+        self.detect_pause_mode()
+    }
+
+    /// `Status` of this `Output` indicating whether it actually re-streams a
+    /// live stream to its downstream destination.
+    fn status(&self, context: &Context) -> Status {
+        context.state().statuses.get(self.id)
+    }
+
+    /// Indicator whether this `Output` is enabled and not `Status.ONLINE` only
+    /// because its `Restream.input` has no live stream to re-stream at the
+    /// moment (has no publisher, for example), rather than because of any
+    /// problem with its downstream destination.
+    ///
+    /// Becomes `false` as soon as the `Restream.input` is online again.
+    fn awaiting_input(&self, context: &Context) -> bool {
+        context.state().is_awaiting_input(self.id)
+    }
+
+    /// Most recent failure of this `Output`, explaining why it doesn't
+    /// re-stream a live stream.
+    ///
+    /// Is cleared once this `Output` becomes `Status.ONLINE`.
+    fn problem(&self) -> Option<&ProblemInfo> {
+        self.problem.as_ref()
+    }
+
+    /// `OutputDestination` this `Output` re-streams to at the moment.
+    ///
+    /// Always `PRIMARY` if there is no `Output.backupDst`.
+    fn active_dst(&self) -> OutputDestination {
+        self.active_dst_kind()
+    }
+
+    /// `OutputDestination` this `Output` is switched to manually (see
+    /// `Mutation.switchOutputDestination`), overriding the automatic
+    /// switching.
+    ///
+    /// `null` if the destination is chosen automatically.
+    fn dst_override(&self) -> Option<OutputDestination> {
+        self.dst_health.overridden
+    }
+
+    /// Health scores of the destinations of this `Output`, driving the
+    /// automatic switching between them.
+    ///
+    /// `null` if there is no `Output.backupDst`.
+    fn dst_health(&self) -> Option<OutputDstScores> {
+        self.backup_dst.as_ref().map(|_| OutputDstScores {
+            primary: self.dst_health.primary.into(),
+            backup: self.dst_health.backup.into(),
+        })
+    }
+
+    /// `OutputTest` re-streaming a test signal to the downstream destination
+    /// of this `Output` at the moment, if any.
+    fn test(&self) -> Option<&OutputTest> {
+        self.test.as_ref()
+    }
+
+    /// Result of the most recently finished `OutputTest` of this `Output`, if
+    /// any.
+    fn last_test(&self) -> Option<&OutputTestResult> {
+        self.last_test.as_ref()
+    }
+
+    /// `Output`s of other `Restream`s re-streaming to the same downstream
+    /// destination as this `Output` does, which makes the destination
+    /// platform flap between them.
+    ///
+    /// `dst`s are compared in their normalized forms, so different spellings
+    /// of the same destination conflict too.
+    fn conflicts_with(&self) -> &[OutputRef] {
+        &self.conflicts_with
+    }
+
+    /// Total number of bytes sent by this `Output` to its downstream
+    /// destination since the server start (or the last
+    /// `Mutation.resetBytesSent`).
+    ///
+    /// Accumulated across restarts of the re-streaming process, and preserved
+    /// while this `Output` is disabled.
+    ///
+    /// Represented as `Float`, so is exact up to 2^53 bytes (~9 PB).
+    #[allow(clippy::cast_precision_loss)] // intended, see description
+    fn bytes_sent(&self) -> f64 {
+        ffmpeg::Stats::global().bytes_sent(Some(self.id)) as f64
+    }
+
+    /// Total number of seconds this `Output` has been `Status.ONLINE` since
+    /// the `Restream.usageSince` moment, for billing.
+    ///
+    /// Survives server restarts, but is persisted periodically only, so the
+    /// time since the last persisting may be lost on a server crash.
+    ///
+    /// Represented as `Float`, so is exact up to 2^53 seconds.
+    #[allow(clippy::cast_precision_loss)] // intended, see description
+    fn online_secs(&self) -> f64 {
+        self.online_secs as f64
+    }
+
+    /// Moment when this `Output` has become `Status.ONLINE`.
+    ///
+    /// `null` if it's not `Status.ONLINE` at the moment.
+    fn online_since(&self) -> Option<DateTime> {
+        ffmpeg::Stats::global()
+            .get(self.id)?
+            .online_since()
+            .map(Into::into)
+    }
+
+    /// Number of seconds this `Output` is continuously `Status.ONLINE` for.
+    ///
+    /// `null` if it's not `Status.ONLINE` at the moment.
+    fn online_for(&self) -> Option<i32> {
+        ffmpeg::Stats::global()
+            .get(self.id)?
+            .online_since()?
+            .elapsed()
+            .ok()
+            .map(|d| d.as_secs().try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Number of unexpected restarts of this `Output`'s re-streaming process
+    /// since it has been enabled last time.
+    fn restarts(&self) -> i32 {
+        ffmpeg::Stats::global()
+            .get(self.id)
+            .map_or(0, |s| s.restarts().try_into().unwrap_or(i32::MAX))
+    }
+
+    /// Total number of seconds this `Output` has been `Status.ONLINE` today
+    /// (in UTC).
+    fn online_today(&self) -> i32 {
+        ffmpeg::Stats::global().get(self.id).map_or(0, |s| {
+            s.online_today().as_secs().try_into().unwrap_or(i32::MAX)
+        })
+    }
+
+    /// Recent `OutputSample`s of this `Output`, the oldest first.
+    ///
+    /// Sampled periodically while the re-streaming process is running only, so
+    /// may have gaps. Kept in memory only, and bounded by the most recent 180
+    /// `OutputSample`s (15 minutes with the default sampling interval).
+    fn history(&self) -> Vec<OutputSample> {
+        ffmpeg::Stats::global()
+            .get(self.id)
+            .map_or_else(Vec::new, |s| s.history())
+    }
+
+    /// Re-streaming process of this `Output` running at the moment, along
+    /// with its resources usage.
+    ///
+    /// Resources usage is sampled with the same interval as the `history`
+    /// (and on Linux only).
+    ///
+    /// `null` if the re-streaming process is not running at the moment.
+    fn process(&self) -> Option<OutputProcess> {
+        ffmpeg::Stats::global().get(self.id)?.process()
+    }
+
+    /// Encoding statistics of the re-streaming process of this `Output`
+    /// running at the moment.
+    ///
+    /// `null` if the re-streaming process is not running at the moment, or
+    /// only copies the media without re-encoding it.
+    fn encoding_stats(&self) -> Option<OutputEncodingStats> {
+        ffmpeg::Stats::global().get(self.id)?.encoding()
+    }
+}
+
+impl Output {
+    /// Maximum allowed value of [`Output::delay_secs`] (5 minutes).
+    pub const MAX_DELAY_SECS: u32 = 300;
+
+    /// Creates a new [`Output`] out of the given [`spec::v1::Output`].
+    #[inline]
+    #[must_use]
+    pub fn new(spec: spec::v1::Output) -> Self {
+        Self {
+            id: spec.id.unwrap_or_else(OutputId::random),
+            backup_dst: spec.backup_dst.filter(|b| *b != spec.dst),
+            dst: spec.dst,
+            label: spec.label,
+            preview_url: spec.preview_url,
+            volume: spec.volume,
+            mixins: spec.mixins.into_iter().map(Mixin::new).collect(),
+            metadata: spec.metadata,
+            conditioning: spec.conditioning,
+            loudnorm: spec.loudnorm,
+            overlay: spec.overlay,
+            delay_secs: Self::normalize_delay(spec.delay_secs),
+            max_bitrate_kbps: spec.max_bitrate_kbps.filter(|b| *b > 0),
+            limits: spec.limits,
+            constraints: spec.constraints,
+            prewarm: spec.prewarm,
+            prewarm_strategy: spec.prewarm_strategy,
+            src_stream: spec.src_stream,
+            drop_frames_when_behind: spec.drop_frames_when_behind,
+            enabled: spec.enabled,
+            on_boot: spec.on_boot,
+            position: 0,
+            online_secs: 0,
+            disabled_reason: None,
+            pending_dst: None,
+            apply_at: None,
+            paused: false,
+            problem: None,
+            incompatibility: None,
+            dst_health: OutputDstHealth::default(),
+            test: None,
+            last_test: None,
+            conflicts_with: vec![],
+        }
+    }
+
+    /// Indicates whether this [`Output`] is enabled, but idles only because
+    /// the given [`Input`] of its [`Restream`] doesn't serve a live stream at
+    /// the moment (according to the given `statuses`), so
+    /// [`ffmpeg::RestreamersPool`] doesn't spawn its process.
+    ///
+    /// Disabled [`Input`] is an intentional idling, so is not considered.
+    #[inline]
+    #[must_use]
+    pub fn is_awaiting_input(
+        &self,
+        input: &Input,
+        statuses: &Statuses,
+    ) -> bool {
+        self.enabled
+            && !statuses.get(self.id).is_online()
+            && input.enabled
+            && !input.is_ready_to_serve(statuses)
+    }
+
+    /// Returns the [`OutputDestination`] this [`Output`] re-streams to at the
+    /// moment.
+    #[inline]
+    #[must_use]
+    pub fn active_dst_kind(&self) -> OutputDestination {
+        if self.backup_dst.is_some() {
+            self.dst_health.active
+        } else {
+            OutputDestination::Primary
+        }
+    }
+
+    /// Returns the downstream URL this [`Output`] re-streams to at the moment:
+    /// either the [`Output::dst`] or the [`Output::backup_dst`].
+    #[inline]
+    #[must_use]
+    pub fn active_dst(&self) -> &OutputDstUrl {
+        match (self.active_dst_kind(), &self.backup_dst) {
+            (OutputDestination::Backup, Some(backup)) => backup,
+            _ => &self.dst,
+        }
+    }
+
+    /// Applies [`Output::on_boot`] policy to this [`Output`] being loaded on
+    /// the server start.
+    ///
+    /// [`OutputBootPolicy::StayOff`] disables this [`Output`] regardless of
+    /// its persisted [`Output::enabled`] value, so it's never re-streamed
+    /// until enabled manually.
+    pub fn apply_boot_policy(&mut self) {
+        if self.on_boot == OutputBootPolicy::StayOff && self.enabled {
+            self.enabled = false;
+            self.disabled_reason = Some(
+                "Disabled on server restart by `STAY_OFF` boot policy".into(),
+            );
+        }
+    }
+
+    /// Returns [`PauseMode`] this [`Output`] is paused (and resumed) with.
+    ///
+    /// Only an [`Output`] being already mixed via a filter graph, which either
+    /// is audio-only or re-encodes its video, may be switched to a slate at
+    /// runtime. Any other one copies its live stream "as is", so has to be
+    /// restarted.
+    #[must_use]
+    pub fn detect_pause_mode(&self) -> PauseMode {
+        let is_file = self.dst.scheme() == "file";
+        let is_audio_only = self.dst.scheme() == "icecast";
+        let reencodes_video =
+            self.overlay.is_some() || (self.conditioning.is_some() && !is_file);
+        if !self.mixins.is_empty() && (is_audio_only || reencodes_video) {
+            PauseMode::Live
+        } else {
+            PauseMode::Restart
+        }
+    }
+
+    /// Indicates whether this [`Output`] is disabled, but should be prewarmed
+    /// (see [`Output::prewarm`]).
+    ///
+    /// Only an [`Output`] switchable to a slate at runtime (see
+    /// [`Output::detect_pause_mode()`]) and not being tested at the moment (as
+    /// its test occupies the same destination) is prewarmed. Recording
+    /// [`Output`]s are never prewarmed, so don't record the slate.
+    #[inline]
+    #[must_use]
+    pub fn is_prewarmed(&self) -> bool {
+        !self.enabled
+            && self.prewarm
+            && self.test.is_none()
+            && self.dst.scheme() != "file"
+            && self.detect_pause_mode() == PauseMode::Live
+    }
+
+    /// Indicates whether this [`Output`] is prewarmed (see
+    /// [`Output::is_prewarmed()`]) with its re-streaming process being
+    /// suspended once connected (see [`PrewarmStrategy::Handshake`]).
+    #[inline]
+    #[must_use]
+    pub fn is_held_at_handshake(&self) -> bool {
+        self.is_prewarmed()
+            && self.prewarm_strategy == PrewarmStrategy::Handshake
+    }
+
+    /// Applies the given [`spec::v1::Output`] to this [`Output`].
+    ///
+    /// If `replace` is `true` then all the [`Output::mixins`] will be replaced
+    /// with new ones, otherwise new ones will be merged with already existing
+    /// [`Output::mixins`].
+    pub fn apply(&mut self, new: spec::v1::Output, replace: bool) {
+        if let Some(id) = new.id {
+            self.id = id;
+        }
+        let backup_dst = new.backup_dst.filter(|b| *b != new.dst);
+        if self.dst != new.dst || self.backup_dst != backup_dst {
+            self.dst_health = OutputDstHealth::default();
+        }
+        self.dst = new.dst;
+        self.backup_dst = backup_dst;
+        self.label = new.label;
+        self.preview_url = new.preview_url;
+        self.volume = new.volume;
+        self.metadata = new.metadata;
+        self.conditioning = new.conditioning;
+        self.loudnorm = new.loudnorm;
+        self.overlay = new.overlay;
+        self.delay_secs = Self::normalize_delay(new.delay_secs);
+        self.max_bitrate_kbps = new.max_bitrate_kbps.filter(|b| *b > 0);
+        self.limits = new.limits;
+        self.constraints = new.constraints;
+        self.prewarm = new.prewarm;
+        self.prewarm_strategy = new.prewarm_strategy;
+        self.src_stream = new.src_stream;
+        self.drop_frames_when_behind = new.drop_frames_when_behind;
+        self.on_boot = new.on_boot;
+        // Temporary omit changing existing `enabled` value to avoid unexpected
+        // breakages of ongoing re-streams.
+        //self.enabled = new.enabled;
+        if replace {
+            let mut olds = mem::replace(
+                &mut self.mixins,
+                Vec::with_capacity(new.mixins.len()),
+            );
+            for new in new.mixins {
+                if let Some(mut old) = olds
+                    .iter()
+                    .enumerate()
+                    .find_map(|(n, o)| {
+                        (Some(o.id) == new.id || o.src == new.src).then(|| n)
+                    })
+                    .map(|n| olds.swap_remove(n))
+                {
+                    old.apply(new);
+                    self.mixins.push(old);
+                } else {
+                    self.mixins.push(Mixin::new(new));
+                }
+            }
+        } else {
+            for new in new.mixins {
+                if let Some(old) = self
+                    .mixins
+                    .iter_mut()
+                    .find(|o| Some(o.id) == new.id || o.src == new.src)
+                {
+                    old.apply(new);
+                } else {
+                    self.mixins.push(Mixin::new(new));
+                }
+            }
+        }
+    }
+
+    /// Exports this [`Output`] as a [`spec::v1::Output`].
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> spec::v1::Output {
+        spec::v1::Output {
+            id: Some(self.id),
+            dst: self.dst.clone(),
+            backup_dst: self.backup_dst.clone(),
+            label: self.label.clone(),
+            preview_url: self.preview_url.clone(),
+            volume: self.volume,
+            mixins: self.mixins.iter().map(Mixin::export).collect(),
+            metadata: self.metadata.clone(),
+            conditioning: self.conditioning,
+            loudnorm: self.loudnorm,
+            overlay: self.overlay.clone(),
+            delay_secs: self.delay_secs,
+            max_bitrate_kbps: self.max_bitrate_kbps,
+            limits: self.limits,
+            constraints: self.constraints.clone(),
+            prewarm: self.prewarm,
+            prewarm_strategy: self.prewarm_strategy,
+            src_stream: self.src_stream.clone(),
+            drop_frames_when_behind: self.drop_frames_when_behind,
+            enabled: self.enabled,
+            on_boot: self.on_boot,
+        }
+    }
+
+    /// Normalizes the given [`Output::delay_secs`] value, capping it with
+    /// [`Output::MAX_DELAY_SECS`] and treating zero delay as no delay at all.
+    #[inline]
+    #[must_use]
+    pub fn normalize_delay(secs: Option<u32>) -> Option<u32> {
+        secs.filter(|s| *s > 0).map(|s| s.min(Self::MAX_DELAY_SECS))
+    }
+
+    /// Returns [`Output::delay_secs`] as a [`Duration`], if any.
+    #[inline]
+    #[must_use]
+    pub fn delay(&self) -> Option<Duration> {
+        self.delay_secs.map(|s| Duration::from_secs(s.into()))
+    }
+}
+
+/// Metadata (like title or author) injected into a live stream re-streamed by
+/// an `Output`, so is displayed by its downstream destination.
+///
+/// For [RTMP] destinations it's delivered in the `onMetaData` message.
+///
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+#[derive(
+    Clone, Debug, Default, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+pub struct OutputMetadata {
+    /// Title of the live stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Author of the live stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// Arbitrary comment to the live stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl OutputMetadata {
+    /// Creates a new [`OutputMetadata`] out of the given values, omitting the
+    /// blank ones.
+    ///
+    /// # Errors
+    ///
+    /// If any of the given values violates the rules of [`text::sanitize()`].
+    pub fn new(
+        title: Option<String>,
+        author: Option<String>,
+        comment: Option<String>,
+    ) -> Result<Self, text::Violation> {
+        let non_blank = |field: &'static str, v: Option<String>| {
+            v.map(|v| text::sanitize(field, v.trim(), text::MAX_METADATA_LEN))
+                .transpose()
+                .map(|v| v.filter(|v| !v.is_empty()))
+        };
+        Ok(Self {
+            title: non_blank("title", title)?,
+            author: non_blank("author", author)?,
+            comment: non_blank("comment", comment)?,
+        })
+    }
+
+    /// Indicates whether this [`OutputMetadata`] has no values at all.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries().next().is_none()
+    }
+
+    /// Returns the non-blank values of this [`OutputMetadata`] along with
+    /// their [FFmpeg] metadata keys.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub fn entries(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        vec![
+            ("title", self.title.as_deref()),
+            ("author", self.author.as_deref()),
+            ("comment", self.comment.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| Some((k, v.filter(|v| !v.trim().is_empty())?)))
+    }
+}
+
+/// Conditioning of a live stream re-streamed by an `Output`, re-encoding it
+/// to meet the requirements of a specific streaming platform.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+pub struct OutputConditioning {
+    /// `OutputConditioningPreset` of the streaming platform to meet the
+    /// requirements of.
+    pub preset: OutputConditioningPreset,
+
+    /// `AudioSampleRate` to re-encode audio with.
+    ///
+    /// If [`None`], then the default one of the `preset` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_sample_rate: Option<AudioSampleRate>,
+}
+
+impl OutputConditioning {
+    /// Returns the [`AudioSampleRate`] to re-encode audio with, falling back
+    /// to the default one of the [`OutputConditioning::preset`].
+    #[inline]
+    #[must_use]
+    pub fn audio_sample_rate(&self) -> AudioSampleRate {
+        self.audio_sample_rate
+            .unwrap_or_else(|| self.preset.audio_sample_rate())
+    }
+}
+
+/// Preset of a streaming platform, whose requirements a live stream should be
+/// conditioned to meet.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    GraphQLEnum,
+    Hash,
+    PartialEq,
+    Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputConditioningPreset {
+    /// [YouTube] requirements: keyframe every 2 seconds and 48 kHz AAC audio.
+    ///
+    /// [YouTube]: https://support.google.com/youtube/answer/2853702
+    #[display(fmt = "YouTube")]
+    Youtube,
+
+    /// [Twitch] requirements: keyframe every 2 seconds and 44.1 kHz AAC
+    /// audio.
+    ///
+    /// [Twitch]: https://stream.twitch.tv/encoding
+    #[display(fmt = "Twitch")]
+    Twitch,
+
+    /// [Facebook] requirements: keyframe every 2 seconds, 48 kHz AAC audio and
+    /// 30 FPS video.
+    ///
+    /// [Facebook]: https://www.facebook.com/help/1534561009906955
+    #[display(fmt = "Facebook")]
+    Facebook,
+}
+
+impl OutputConditioningPreset {
+    /// Returns the maximum interval between keyframes required by this
+    /// [`OutputConditioningPreset`].
+    #[inline]
+    #[must_use]
+    pub fn keyframe_interval(self) -> Duration {
+        match self {
+            Self::Youtube | Self::Twitch | Self::Facebook => {
+                Duration::from_secs(2)
+            }
+        }
+    }
+
+    /// Returns the default [`AudioSampleRate`] of this
+    /// [`OutputConditioningPreset`].
+    #[inline]
+    #[must_use]
+    pub fn audio_sample_rate(self) -> AudioSampleRate {
+        match self {
+            Self::Youtube | Self::Facebook => AudioSampleRate::Hz48000,
+            Self::Twitch => AudioSampleRate::Hz44100,
+        }
+    }
+
+    /// Returns the frame rate the video should be normalized to, if this
+    /// [`OutputConditioningPreset`] requires it.
+    #[inline]
+    #[must_use]
+    pub fn fps(self) -> Option<u16> {
+        match self {
+            Self::Youtube | Self::Twitch => None,
+            Self::Facebook => Some(30),
+        }
+    }
+
+    /// Returns video codecs accepted by the streaming platform of this
+    /// [`OutputConditioningPreset`] (see [`normalize_codec()`]).
+    #[inline]
+    #[must_use]
+    pub fn video_codecs(self) -> &'static [&'static str] {
+        match self {
+            Self::Youtube | Self::Twitch | Self::Facebook => &["h264"],
+        }
+    }
+
+    /// Returns audio codecs accepted by the streaming platform of this
+    /// [`OutputConditioningPreset`] (see [`normalize_codec()`]).
+    #[inline]
+    #[must_use]
+    pub fn audio_codecs(self) -> &'static [&'static str] {
+        match self {
+            Self::Youtube => &["aac", "mp3"],
+            Self::Twitch | Self::Facebook => &["aac"],
+        }
+    }
+
+    /// Returns maximum number of audio channels accepted by the streaming
+    /// platform of this [`OutputConditioningPreset`].
+    #[inline]
+    #[must_use]
+    pub fn max_audio_channels(self) -> u32 {
+        match self {
+            Self::Youtube | Self::Twitch | Self::Facebook => 2,
+        }
+    }
+
+    /// Returns maximum width and height (in pixels) of the video accepted by
+    /// the streaming platform of this [`OutputConditioningPreset`].
+    #[inline]
+    #[must_use]
+    pub fn max_resolution(self) -> (u32, u32) {
+        match self {
+            Self::Youtube => (3840, 2160),
+            Self::Twitch | Self::Facebook => (1920, 1080),
+        }
+    }
+}
+
+/// Sample rate of an audio track.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    GraphQLEnum,
+    Hash,
+    PartialEq,
+    Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioSampleRate {
+    /// 44.1 kHz.
+    #[display(fmt = "44100")]
+    #[graphql(name = "HZ_44100")]
+    Hz44100,
+
+    /// 48 kHz.
+    #[display(fmt = "48000")]
+    #[graphql(name = "HZ_48000")]
+    Hz48000,
+}
+
+/// Target loudness to normalize audio to, as per [EBU R128].
+///
+/// [EBU R128]: https://tech.ebu.ch/publications/r128
+#[derive(Clone, Copy, Debug, GraphQLObject, PartialEq, Serialize)]
+pub struct LoudnessTarget {
+    /// Integrated loudness in LUFS.
+    integrated: f64,
+
+    /// Maximum true peak in dBTP.
+    true_peak: f64,
+}
+
+// Values are always finite, as checked by `LoudnessTarget::new()`.
+impl Eq for LoudnessTarget {}
+
+impl LoudnessTarget {
+    /// Default maximum true peak (in dBTP) of a [`LoudnessTarget`].
+    pub const DEFAULT_TRUE_PEAK: f64 = -1.0;
+
+    /// Creates a new [`LoudnessTarget`] if the given values are within the
+    /// ranges supported by [FFmpeg]'s `loudnorm` filter:
+    /// - `integrated` loudness within `-70.0..=-5.0` LUFS;
+    /// - `true_peak` within `-9.0..=0.0` dBTP.
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    #[must_use]
+    pub fn new(integrated: f64, true_peak: f64) -> Option<Self> {
+        ((-70.0..=-5.0).contains(&integrated)
+            && (-9.0..=0.0).contains(&true_peak))
+        .then(|| Self {
+            integrated,
+            true_peak,
+        })
+    }
+
+    /// Returns integrated loudness (in LUFS) of this [`LoudnessTarget`].
+    #[inline]
+    #[must_use]
+    pub fn integrated(&self) -> f64 {
+        self.integrated
+    }
+
+    /// Returns maximum true peak (in dBTP) of this [`LoudnessTarget`].
+    #[inline]
+    #[must_use]
+    pub fn true_peak(&self) -> f64 {
+        self.true_peak
+    }
+}
+
+impl<'de> Deserialize<'de> for LoudnessTarget {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            integrated: f64,
+            #[serde(default = "default_true_peak")]
+            true_peak: f64,
+        }
+
+        fn default_true_peak() -> f64 {
+            LoudnessTarget::DEFAULT_TRUE_PEAK
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        Self::new(raw.integrated, raw.true_peak)
+            .ok_or_else(|| D::Error::custom("Not a valid LoudnessTarget"))
+    }
+}
+
+/// Limits of OS resources a re-streaming process of an `Output` is allowed to
+/// consume, so a heavy process doesn't starve the others on the same server.
+///
+/// Limits are enforced on a best-effort basis, depending on the mechanisms
+/// available in OS (see `PoolProcess.limitedBy`), and are ignored silently
+/// where none is.
+#[derive(Clone, Copy, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct OutputLimits {
+    /// [Niceness] of the process, within `0..=19` range.
+    ///
+    /// The higher it is, the less CPU time the process gets when competing
+    /// with others.
+    ///
+    /// [Niceness]: https://en.wikipedia.org/wiki/Nice_(Unix)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nice: Option<i32>,
+
+    /// Maximum CPU usage of the process, in percents of a single CPU core.
+    ///
+    /// Enforced via [cgroups v2] CPU quota on Linux, falling back to pinning
+    /// the process to the required number of CPU cores with [taskset].
+    ///
+    /// [cgroups v2]: https://docs.kernel.org/admin-guide/cgroup-v2.html
+    /// [taskset]: https://man7.org/linux/man-pages/man1/taskset.1.html
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_quota: Option<i32>,
+}
+
+impl OutputLimits {
+    /// Maximum allowed [`OutputLimits::nice()`] value.
+    pub const MAX_NICE: i32 = 19;
+
+    /// Creates new [`OutputLimits`] if the given values are valid:
+    /// - `nice` within `0..=19` range (negative values require privileges);
+    /// - `cpu_quota` is positive.
+    ///
+    /// Returns [`None`] if no limits are specified at all.
+    #[must_use]
+    pub fn new(nice: Option<i32>, cpu_quota: Option<i32>) -> Option<Self> {
+        ((nice.is_some() || cpu_quota.is_some())
+            && nice.map_or(true, |n| (0..=Self::MAX_NICE).contains(&n))
+            && cpu_quota.map_or(true, |q| q > 0))
+        .then(|| Self { nice, cpu_quota })
+    }
+
+    /// Returns [niceness] of the process, if any.
+    ///
+    /// [niceness]: https://en.wikipedia.org/wiki/Nice_(Unix)
+    #[inline]
+    #[must_use]
+    pub fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    /// Returns maximum CPU usage of the process (in percents of a single CPU
+    /// core), if any.
+    #[inline]
+    #[must_use]
+    pub fn cpu_quota(&self) -> Option<u32> {
+        self.cpu_quota.and_then(|q| q.try_into().ok())
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputLimits {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            #[serde(default)]
+            nice: Option<i32>,
+            #[serde(default)]
+            cpu_quota: Option<i32>,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        Self::new(raw.nice, raw.cpu_quota)
+            .ok_or_else(|| D::Error::custom("Not a valid OutputLimits"))
+    }
+}
+
+/// Constraints of a downstream destination of an `Output` on the live stream
+/// it accepts (like the codecs), so an incompatible live stream is detected
+/// before being re-streamed (see `ProblemKind.INCOMPATIBLE`).
+///
+/// Values not specified explicitly are taken from the `preset`, if any.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct OutputConstraints {
+    /// `OutputConditioningPreset` of the streaming platform to take the
+    /// constraints not specified explicitly from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<OutputConditioningPreset>,
+
+    /// Video codecs (like `h264` or `hevc`) accepted by the destination.
+    ///
+    /// Empty means the ones of the `preset`, or any if there is none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    video_codecs: Vec<String>,
+
+    /// Audio codecs (like `aac` or `mp3`) accepted by the destination.
+    ///
+    /// Empty means the ones of the `preset`, or any if there is none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    audio_codecs: Vec<String>,
+
+    /// Maximum number of audio channels accepted by the destination.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_audio_channels: Option<i32>,
+
+    /// Maximum width (in pixels) of the video accepted by the destination.
+    ///
+    /// Is checked with SRS 4 or newer only, as SRS 3 doesn't report it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_width: Option<i32>,
+
+    /// Maximum height (in pixels) of the video accepted by the destination.
+    ///
+    /// Is checked with SRS 4 or newer only, as SRS 3 doesn't report it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_height: Option<i32>,
+
+    /// Indicator whether an incompatible live stream is not re-streamed to
+    /// the destination at all, rather than being reported only.
+    ///
+    /// Live stream is not re-streamed until its codecs are known either.
+    #[serde(skip_serializing_if = "is_false")]
+    enforce: bool,
+}
+
+impl OutputConstraints {
+    /// Creates new [`OutputConstraints`] out of the given values, if they
+    /// meet their invariants.
+    ///
+    /// Codec names are normalized (see [`normalize_codec()`]). [`None`] is
+    /// returned if no constraints are specified at all.
+    ///
+    /// # Errors
+    ///
+    /// With a description of the violated invariant, if any of the given
+    /// values is invalid.
+    pub fn new(
+        preset: Option<OutputConditioningPreset>,
+        video_codecs: Vec<String>,
+        audio_codecs: Vec<String>,
+        max_audio_channels: Option<i32>,
+        max_width: Option<i32>,
+        max_height: Option<i32>,
+        enforce: bool,
+    ) -> Result<Option<Self>, &'static str> {
+        let normalize = |codecs: Vec<String>| {
+            let mut codecs: Vec<_> = codecs
+                .iter()
+                .map(|c| normalize_codec(c))
+                .filter(|c| !c.is_empty())
+                .collect();
+            if codecs.iter().any(|c| {
+                !c.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }) {
+                return Err("Codec names should be alphanumeric");
+            }
+            codecs.sort();
+            codecs.dedup();
+            Ok(codecs)
+        };
+        let video_codecs = normalize(video_codecs)?;
+        let audio_codecs = normalize(audio_codecs)?;
+
+        if [max_audio_channels, max_width, max_height]
+            .iter()
+            .any(|v| v.map_or(false, |v| v <= 0))
+        {
+            return Err("Maximum audio channels and resolution should be \
+                        positive");
+        }
+
+        let has_any = preset.is_some()
+            || !video_codecs.is_empty()
+            || !audio_codecs.is_empty()
+            || max_audio_channels.is_some()
+            || max_width.is_some()
+            || max_height.is_some();
+        Ok(has_any.then(|| Self {
+            preset,
+            video_codecs,
+            audio_codecs,
+            max_audio_channels,
+            max_width,
+            max_height,
+            enforce,
+        }))
+    }
+
+    /// Returns video codecs accepted by the destination, falling back to the
+    /// ones of the [`OutputConstraints::preset`].
+    ///
+    /// Empty means any.
+    #[must_use]
+    pub fn video_codecs(&self) -> Vec<&str> {
+        if !self.video_codecs.is_empty() {
+            return self.video_codecs.iter().map(String::as_str).collect();
+        }
+        self.preset
+            .map(|p| p.video_codecs().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Returns audio codecs accepted by the destination, falling back to the
+    /// ones of the [`OutputConstraints::preset`].
+    ///
+    /// Empty means any.
+    #[must_use]
+    pub fn audio_codecs(&self) -> Vec<&str> {
+        if !self.audio_codecs.is_empty() {
+            return self.audio_codecs.iter().map(String::as_str).collect();
+        }
+        self.preset
+            .map(|p| p.audio_codecs().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Returns maximum number of audio channels accepted by the destination,
+    /// falling back to the one of the [`OutputConstraints::preset`].
+    #[must_use]
+    pub fn max_audio_channels(&self) -> Option<u32> {
+        self.max_audio_channels
+            .and_then(|c| c.try_into().ok())
+            .or_else(|| self.preset.map(|p| p.max_audio_channels()))
+    }
+
+    /// Returns maximum width and height (in pixels) of the video accepted by
+    /// the destination, falling back to the ones of the
+    /// [`OutputConstraints::preset`].
+    #[must_use]
+    pub fn max_resolution(&self) -> (Option<u32>, Option<u32>) {
+        let preset = self.preset.map(OutputConditioningPreset::max_resolution);
+        (
+            self.max_width
+                .and_then(|w| w.try_into().ok())
+                .or_else(|| preset.map(|(w, _)| w)),
+            self.max_height
+                .and_then(|h| h.try_into().ok())
+                .or_else(|| preset.map(|(_, h)| h)),
+        )
+    }
+
+    /// Indicates whether an incompatible live stream should not be
+    /// re-streamed to the destination at all.
+    #[inline]
+    #[must_use]
+    pub fn enforce(&self) -> bool {
+        self.enforce
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputConstraints {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            #[serde(default)]
+            preset: Option<OutputConditioningPreset>,
+            #[serde(default)]
+            video_codecs: Vec<String>,
+            #[serde(default)]
+            audio_codecs: Vec<String>,
+            #[serde(default)]
+            max_audio_channels: Option<i32>,
+            #[serde(default)]
+            max_width: Option<i32>,
+            #[serde(default)]
+            max_height: Option<i32>,
+            #[serde(default)]
+            enforce: bool,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        Self::new(
+            raw.preset,
+            raw.video_codecs,
+            raw.audio_codecs,
+            raw.max_audio_channels,
+            raw.max_width,
+            raw.max_height,
+            raw.enforce,
+        )
+        .map_err(D::Error::custom)?
+        .ok_or_else(|| D::Error::custom("OutputConstraints cannot be empty"))
+    }
+}
+
+/// Normalizes the given codec name, as different tools name the same codec
+/// differently (like `H264` reported by [SRS] or `h265` for `hevc`).
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[must_use]
+pub fn normalize_codec(name: &str) -> String {
+    let name = name.trim().to_ascii_lowercase();
+    match name.as_str() {
+        "avc" | "h.264" => "h264".into(),
+        "h265" | "h.265" => "hevc".into(),
+        _ => name,
+    }
+}
+
+/// Image (watermark, for example) drawn over the video of an `Output`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OutputOverlay {
+    /// Absolute path to the image file.
+    pub path: PathBuf,
+
+    /// Position of the image in the video.
+    #[serde(default)]
+    pub position: OverlayPosition,
+
+    /// Opacity of the image in percents.
+    #[serde(default = "OutputOverlay::default_opacity")]
+    pub opacity: u8,
+}
+
+impl OutputOverlay {
+    /// Maximum possible value of [`OutputOverlay::opacity`], meaning a fully
+    /// opaque image.
+    pub const MAX_OPACITY: u8 = 100;
+
+    /// Returns the default value of [`OutputOverlay::opacity`].
+    #[inline]
+    #[must_use]
+    pub fn default_opacity() -> u8 {
+        Self::MAX_OPACITY
+    }
+
+    /// Displays [`OutputOverlay::opacity`] as a fraction of `1`, i.e. `100%`
+    /// as `1.00`, `50%` as `0.50`, and so on.
+    #[must_use]
+    pub fn opacity_as_fraction(&self) -> String {
+        let opacity = self.opacity.min(Self::MAX_OPACITY);
+        format!("{}.{:02}", opacity / 100, opacity % 100)
+    }
+}
+
+/// Image (watermark, for example) drawn over the video of an `Output`.
+#[graphql_object]
+impl OutputOverlay {
+    /// Absolute path to the image file on the server.
+    fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Position of the image in the video.
+    fn position(&self) -> OverlayPosition {
+        self.position
+    }
+
+    /// Opacity of the image in percents.
+    fn opacity(&self) -> i32 {
+        self.opacity.into()
+    }
+}
+
+/// Position of an `OutputOverlay` image in a video.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    PartialEq,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    /// Top left corner.
+    TopLeft,
+
+    /// Top right corner.
+    #[default]
+    TopRight,
+
+    /// Bottom left corner.
+    BottomLeft,
+
+    /// Bottom right corner.
+    BottomRight,
+}
+
+/// ID of an `Output`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLScalarValue,
+    Hash,
+    Into,
+    PartialEq,
+    Serialize,
+)]
+pub struct OutputId(Uuid);
+
+impl OutputId {
+    /// Generates a new random [`OutputId`].
+    #[inline]
+    #[must_use]
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// [`Url`] of an [`Output::dst`].
+///
+/// Only the following URLs are allowed at the moment:
+/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
+///   host);
+/// - [SRT] URL (starting with `srt://` scheme and having a host);
+/// - [Icecast] URL (starting with `icecast://` scheme and having a host);
+/// - [FLV] file URL (starting with `file:///` scheme, without host and
+///   subdirectories, and with `.flv` extension in its path).
+///
+/// [FLV]: https://en.wikipedia.org/wiki/Flash_Video
+/// [Icecast]: https://icecast.org
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+/// [SRT]: https://en.wikipedia.org/wiki/Secure_Reliable_Transport
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct OutputDstUrl(Url);
+
+impl OutputDstUrl {
+    /// Creates a new [`OutputDstUrl`] if the given [`Url`] is suitable for
+    /// that.
+    ///
+    /// # Errors
+    ///
+    /// Returns the given [`Url`] back if it doesn't represent a valid
+    /// [`OutputDstUrl`].
+    #[inline]
+    pub fn new(url: Url) -> Result<Self, Url> {
+        if Self::validate(&url) {
+            Ok(Self(url))
+        } else {
+            Err(url)
+        }
+    }
+
+    /// Validates the given [`Url`] to represent a valid [`OutputDstUrl`].
+    #[must_use]
+    pub fn validate(url: &Url) -> bool {
+        match url.scheme() {
+            "icecast" | "rtmp" | "rtmps" | "srt" => url.has_host(),
+            "file" => {
+                let path = Path::new(url.path());
+                !url.has_host()
+                    && path.is_absolute()
+                    && path.extension() == Some("flv".as_ref())
+                    && path.parent() == Some("/".as_ref())
+                    && !url.path().contains("/../")
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the port conventionally used by destinations of the given
+    /// `scheme`, if there is any.
+    #[must_use]
+    pub fn default_port(scheme: &str) -> Option<u16> {
+        match scheme {
+            "rtmp" => Some(1935),
+            "rtmps" => Some(443),
+            "icecast" => Some(8000),
+            _ => None,
+        }
+    }
+
+    /// Returns a normalized form of this [`OutputDstUrl`], so different
+    /// spellings of the same destination become equal: lowercased host,
+    /// omitted default port, no trailing slashes in path, and no
+    /// percent-encoded unreserved characters.
+    ///
+    /// The original [`OutputDstUrl`] is left untouched, so is still used "as
+    /// is" for re-streaming and persisting.
+    #[must_use]
+    pub fn normalized(&self) -> Url {
+        let mut url = self.0.clone();
+        if url.scheme() == "file" {
+            return url;
+        }
+
+        if let Some(host) = url.host_str().map(str::to_lowercase) {
+            let _ = url.set_host(Some(&host));
+        }
+
+        let default_port = Self::default_port(url.scheme());
+        if url.port().is_some() && url.port() == default_port {
+            let _ = url.set_port(None);
+        }
+
+        let path = decode_unreserved(url.path().trim_end_matches('/'));
+        url.set_path(&path);
+
+        url
+    }
+
+    /// Checks whether this [`OutputDstUrl`] points to the same destination as
+    /// the `other` one, comparing their [`OutputDstUrl::normalized`] forms.
+    #[inline]
+    #[must_use]
+    pub fn is_equivalent(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+/// Decodes percent-encoded unreserved characters (as defined by
+/// [RFC 3986][1]) in the given URL component, and uppercases hex digits of the
+/// ones left encoded.
+///
+/// [1]: https://tools.ietf.org/html/rfc3986#section-2.3
+fn decode_unreserved(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('%') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        match rest
+            .get(1..3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(b) if b.is_ascii_alphanumeric() || b"-._~".contains(&b) => {
+                out.push(char::from(b));
+            }
+            Some(b) => out.push_str(&format!("%{:02X}", b)),
+            None => {
+                out.push('%');
+                rest = &rest[1..];
+                continue;
+            }
+        }
+        rest = &rest[3..];
+    }
+    out.push_str(rest);
+    out
+}
+
+impl<'de> Deserialize<'de> for OutputDstUrl {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
+            D::Error::custom(format!("Not a valid Output.src URL: {}", url))
+        })
+    }
+}
+
+/// Type of an `Output.dst` URL.
+///
+/// Only the following URLs are allowed at the moment:
+/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
+///   host);
+/// - [SRT] URL (starting with `srt://` scheme and having a host);
+/// - [Icecast] URL (starting with `icecast://` scheme and having a host);
+/// - [FLV] file URL (starting with `file:///` scheme, without host and
+///   subdirectories, and with `.flv` extension in its path).
+///
+/// [FLV]: https://en.wikipedia.org/wiki/Flash_Video
+/// [Icecast]: https://icecast.org
+/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
+/// [SRT]: https://en.wikipedia.org/wiki/Secure_Reliable_Transport
+#[graphql_scalar]
+impl<S> GraphQLScalar for OutputDstUrl
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(|s| Url::parse(s).ok())
+            .and_then(|url| Self::new(url).ok())
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Additional source for an `Output` to be mixed with before re-streaming to
+/// the destination.
+#[derive(
+    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+pub struct Mixin {
+    /// Unique ID of this `Mixin`.
+    ///
+    /// Once assigned, it never changes.
+    pub id: MixinId,
+
+    /// URL of the source to be mixed with an `Output`.
+    ///
+    /// At the moment, only [TeamSpeak] is supported.
+    ///
+    /// [TeamSpeak]: https://teamspeak.com
+    pub src: MixinSrcUrl,
+
+    /// Volume rate of this `Mixin`'s audio tracks to mix them with.
+    #[serde(default, skip_serializing_if = "Volume::is_origin")]
+    pub volume: Volume,
+
+    /// Delay that this `Mixin` should wait before being mixed with an `Output`.
+    ///
+    /// Very useful to fix de-synchronization issues and correct timings between
+    /// a `Mixin` and its `Output`.
+    #[serde(default, skip_serializing_if = "Delay::is_zero")]
+    pub delay: Delay,
+
+    /// `Status` of this `Mixin` indicating whether it provides an actual media
+    /// stream to be mixed with its `Output`.
+    #[serde(skip)]
+    pub status: Status,
+}
+
+impl Mixin {
+    /// Creates a new [`Mixin`] out of the given [`spec::v1::Mixin`].
+    #[inline]
+    #[must_use]
+    pub fn new(spec: spec::v1::Mixin) -> Self {
+        Self {
+            id: spec.id.unwrap_or_else(MixinId::random),
+            src: spec.src,
+            volume: spec.volume,
+            delay: spec.delay,
+            status: Status::Offline,
+        }
+    }
+
+    /// Applies the given [`spec::v1::Mixin`] to this [`Mixin`].
+    #[inline]
+    pub fn apply(&mut self, new: spec::v1::Mixin) {
+        if let Some(id) = new.id {
+            self.id = id;
+        }
+        self.src = new.src;
+        self.volume = new.volume;
+        self.delay = new.delay;
+    }
+
+    /// Exports this [`Mixin`] as a [`spec::v1::Mixin`].
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> spec::v1::Mixin {
+        spec::v1::Mixin {
+            id: Some(self.id),
+            src: self.src.clone(),
+            volume: self.volume,
+            delay: self.delay,
+        }
+    }
+}
+
+/// ID of a `Mixin`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    Eq,
+    From,
+    GraphQLScalarValue,
+    Into,
+    PartialEq,
+    Serialize,
+)]
+pub struct MixinId(Uuid);
+
+impl MixinId {
+    /// Generates a new random [`MixinId`].
+    #[inline]
+    #[must_use]
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// [`Url`] of a [`Mixin::src`].
+///
+/// Only the following URLs are allowed at the moment:
+/// - [TeamSpeak] URL (starting with `ts://` scheme and having a host);
+/// - [MP3] HTTP URL (starting with `http://` or `https://` scheme, having a
+///   host and `.mp3` extension in its path).
+///
+/// [MP3]: https://en.wikipedia.org/wiki/MP3
+/// [TeamSpeak]: https://teamspeak.com
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct MixinSrcUrl(Url);
+
+impl MixinSrcUrl {
+    /// Creates a new [`MixinSrcUrl`] if the given [`Url`] is suitable for that.
+    ///
+    /// # Errors
+    ///
+    /// Returns the given [`Url`] back if it doesn't represent a valid
+    /// [`MixinSrcUrl`].
+    #[inline]
+    pub fn new(url: Url) -> Result<Self, Url> {
+        if Self::validate(&url) {
+            Ok(Self(url))
+        } else {
+            Err(url)
+        }
+    }
+
+    /// Validates the given [`Url`] to represent a valid [`MixinSrcUrl`].
+    #[must_use]
+    pub fn validate(url: &Url) -> bool {
+        url.has_host()
+            && match url.scheme() {
+                "ts" => true,
+                "http" | "https" => {
+                    Path::new(url.path()).extension() == Some("mp3".as_ref())
+                }
+                _ => false,
+            }
+    }
+}
+
+impl<'de> Deserialize<'de> for MixinSrcUrl {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
+            D::Error::custom(format!("Not a valid Mixin.src URL: {}", url))
+        })
+    }
+}
+
+/// Type of a `Mixin.src` URL.
+///
+/// Only the following URLs are allowed at the moment:
+/// - [TeamSpeak] URL (starting with `ts://` scheme and having a host);
+/// - [MP3] HTTP URL (starting with `http://` or `https://` scheme, having a
+///   host and `.mp3` extension in its path).
+///
+/// [MP3]: https://en.wikipedia.org/wiki/MP3
+/// [TeamSpeak]: https://teamspeak.com
+#[graphql_scalar]
+impl<S> GraphQLScalar for MixinSrcUrl
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(|s| Url::parse(s).ok())
+            .and_then(|url| Self::new(url).ok())
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Mode of pausing (and resuming) an `Output`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq)]
+pub enum PauseMode {
+    /// Re-streaming process is kept alive and switched to a slate at runtime,
+    /// so the downstream destination sees no reconnection.
+    ///
+    /// Used for an `Output` having `Output.mixins` and either being
+    /// audio-only or re-encoding its video.
+    Live,
+
+    /// Re-streaming process is quickly restarted to re-stream a slate (and
+    /// back), so the downstream destination sees a short reconnection.
+    ///
+    /// Used for an `Output` copying its video "as is", as such video cannot
+    /// be switched at runtime.
+    Restart,
+}
+
+/// Policy of enabling an `Output` after the server restart.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    PartialEq,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBootPolicy {
+    /// `Output` keeps its `Output.enabled` value, so resumes re-streaming
+    /// right away if it has been enabled before the restart.
+    #[default]
+    Resume,
+
+    /// `Output` is always disabled after the restart, so should be enabled
+    /// manually to go live again.
+    StayOff,
+}
+
+impl OutputBootPolicy {
+    /// Indicates whether this [`OutputBootPolicy`] is the default one.
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required for `serde`
+    #[inline]
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Strategy of keeping a disabled `Output` connected to its downstream
+/// destination while being prewarmed (see `Output.prewarm`).
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    PartialEq,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PrewarmStrategy {
+    /// Slate (black frame with silent audio) is re-streamed, so the
+    /// downstream destination keeps receiving media all the time.
+    #[default]
+    Slate,
+
+    /// Re-streaming process is suspended once connected to the downstream
+    /// destination, so nothing is sent until the `Output` is enabled.
+    ///
+    /// Suits only the destinations tolerating a connection without any media
+    /// for a long time.
+    Handshake,
+}
+
+impl PrewarmStrategy {
+    /// Indicates whether this [`PrewarmStrategy`] is the default one.
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required for `serde`
+    #[inline]
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Downstream destination of an `Output` having a `Output.backupDst`.
+#[derive(
+    Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq, Serialize, SmartDefault,
+)]
+pub enum OutputDestination {
+    /// `Output.dst` is re-streamed to.
+    #[default]
+    Primary,
+
+    /// `Output.backupDst` is re-streamed to.
+    Backup,
+}
+
+/// Health scores of the destinations of an `Output` having a
+/// `Output.backupDst`.
+///
+/// Each score is within `0..=100` range, where `100` means fully healthy. It
+/// drops on every failure of the re-streaming process, grows back once the
+/// process goes `Status.ONLINE`, and slowly recovers while the destination is
+/// not used.
+#[derive(Clone, Copy, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct OutputDstScores {
+    /// Health score of the `Output.dst`.
+    pub primary: i32,
+
+    /// Health score of the `Output.backupDst`.
+    pub backup: i32,
+}
+
+/// Health of the destinations of an [`Output`] having an
+/// [`Output::backup_dst`], choosing the one to re-stream to.
+///
+/// The [`Output::dst`] is re-streamed to, until its score drops below
+/// [`OutputDstHealth::SWITCH_THRESHOLD`]. Then the [`Output::backup_dst`] is
+/// switched to, and is kept for at least [`OutputDstHealth::MIN_DWELL`], until
+/// the score of the [`Output::dst`] recovers to
+/// [`OutputDstHealth::RECOVERY_THRESHOLD`]. The gap between these thresholds
+/// prevents ping-ponging between the destinations.
+///
+/// Manual [`OutputDstHealth::overridden`] destination disables the automatic
+/// switching.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, SmartDefault)]
+pub struct OutputDstHealth {
+    /// Health score of the [`Output::dst`].
+    #[default(Self::MAX_SCORE)]
+    pub primary: u8,
+
+    /// Health score of the [`Output::backup_dst`].
+    #[default(Self::MAX_SCORE)]
+    pub backup: u8,
+
+    /// [`OutputDestination`] being re-streamed to at the moment.
+    pub active: OutputDestination,
+
+    /// [`OutputDestination`] chosen manually, if any.
+    pub overridden: Option<OutputDestination>,
+
+    /// Moment of the last switching of the [`OutputDstHealth::active`]
+    /// destination.
+    #[serde(skip)]
+    pub switched_at: Option<Instant>,
+}
+
+impl OutputDstHealth {
+    /// Score of a fully healthy destination.
+    pub const MAX_SCORE: u8 = 100;
+
+    /// Score a destination loses on every failure.
+    pub const FAILURE_PENALTY: u8 = 25;
+
+    /// Score a destination gains once being re-streamed to successfully.
+    pub const SUCCESS_BONUS: u8 = 20;
+
+    /// Score a destination not being used regains every
+    /// [`OutputDstHealth::RECOVERY_INTERVAL`].
+    pub const IDLE_RECOVERY: u8 = 10;
+
+    /// Interval of recovering scores of the destinations not being used.
+    pub const RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Score of the active destination, below which it's switched from.
+    pub const SWITCH_THRESHOLD: u8 = 50;
+
+    /// Score of the [`Output::dst`] required to switch back to it from the
+    /// [`Output::backup_dst`].
+    pub const RECOVERY_THRESHOLD: u8 = 90;
+
+    /// Minimum duration of staying on the [`Output::backup_dst`] before
+    /// switching back to the [`Output::dst`].
+    pub const MIN_DWELL: Duration = Duration::from_secs(120);
+
+    /// Returns the score of the given [`OutputDestination`].
+    #[inline]
+    #[must_use]
+    pub fn score(&self, dst: OutputDestination) -> u8 {
+        match dst {
+            OutputDestination::Primary => self.primary,
+            OutputDestination::Backup => self.backup,
+        }
+    }
+
+    /// Records a failure (if not `healthy`) or a success of re-streaming to
+    /// the given [`OutputDestination`].
+    pub fn record(&mut self, dst: OutputDestination, healthy: bool) {
+        let score = match dst {
+            OutputDestination::Primary => &mut self.primary,
+            OutputDestination::Backup => &mut self.backup,
+        };
+        *score = if healthy {
+            score
+                .saturating_add(Self::SUCCESS_BONUS)
+                .min(Self::MAX_SCORE)
+        } else {
+            score.saturating_sub(Self::FAILURE_PENALTY)
+        };
+    }
+
+    /// Recovers the score of the destination not being used at the moment.
+    pub fn recover(&mut self) {
+        let score = match self.active {
+            OutputDestination::Primary => &mut self.backup,
+            OutputDestination::Backup => &mut self.primary,
+        };
+        *score = score
+            .saturating_add(Self::IDLE_RECOVERY)
+            .min(Self::MAX_SCORE);
+    }
+
+    /// Chooses the [`OutputDestination`] to re-stream to at the given moment,
+    /// according to the current scores.
+    ///
+    /// Returns `true` if the [`OutputDstHealth::active`] destination has been
+    /// switched.
+    pub fn decide(&mut self, now: Instant) -> bool {
+        let next = if let Some(dst) = self.overridden {
+            dst
+        } else {
+            match self.active {
+                OutputDestination::Primary
+                    if self.primary < Self::SWITCH_THRESHOLD
+                        && self.backup > self.primary =>
+                {
+                    OutputDestination::Backup
+                }
+                OutputDestination::Backup
+                    if (self.primary >= Self::RECOVERY_THRESHOLD
+                        && self.switched_at.map_or(true, |at| {
+                            now.saturating_duration_since(at) >= Self::MIN_DWELL
+                        }))
+                        || (self.backup < Self::SWITCH_THRESHOLD
+                            && self.primary > self.backup) =>
+                {
+                    OutputDestination::Primary
+                }
+                dst => dst,
+            }
+        };
+        if next == self.active {
+            return false;
+        }
+        self.active = next;
+        self.switched_at = Some(now);
+        true
+    }
+}
+
+/// Codecs of a live stream received by an `Input`, as reported by [SRS].
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Clone, Debug, Default, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct InputCodecs {
+    /// Codec of the video track (like `h264` or `hevc`).
+    ///
+    /// `null` if there is no video track.
+    pub video_codec: Option<String>,
+
+    /// Width of the video in pixels, if known.
+    pub width: Option<i32>,
+
+    /// Height of the video in pixels, if known.
+    pub height: Option<i32>,
+
+    /// Codec of the audio track (like `aac` or `mp3`).
+    ///
+    /// `null` if there is no audio track.
+    pub audio_codec: Option<String>,
+
+    /// Number of the audio channels, if known.
+    pub audio_channels: Option<i32>,
+}
+
+/// Most recent failure of an `Input` or an `Output`, explaining why it's not
+/// `Status.ONLINE`.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct ProblemInfo {
+    /// Kind of this `ProblemInfo`.
+    pub kind: ProblemKind,
+
+    /// Human-readable description of this `ProblemInfo`.
+    pub message: String,
+
+    /// Moment of time when this `ProblemInfo` has happened.
+    pub at: DateTime,
+}
+
+impl ProblemInfo {
+    /// Creates a new [`ProblemInfo`] of the given `kind`, happened just now.
+    #[inline]
+    #[must_use]
+    pub fn new<M: Into<String>>(kind: ProblemKind, message: M) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            at: DateTime::now(),
+        }
+    }
+}
+
+/// Kind of a `ProblemInfo`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq, Serialize)]
+pub enum ProblemKind {
+    /// Failed to connect to a remote endpoint (an upstream source or a
+    /// downstream destination).
+    Connect,
+
+    /// Re-streaming process has failed for other reasons.
+    Process,
+
+    /// Probing of a media has failed.
+    Probe,
+
+    /// Client has been rejected by [SRS].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    Rejection,
+
+    /// Retention of recorded files has failed.
+    Retention,
+
+    /// Re-encoding doesn't keep up with real time (see
+    /// `OutputEncodingStats`).
+    SlowEncoding,
+
+    /// Downstream destination doesn't keep up with the live stream, so its
+    /// re-streaming process falls behind real time (see
+    /// `Output.dropFramesWhenBehind`).
+    FallingBehind,
+
+    /// Re-streaming of a test signal has failed (see `OutputTest`).
+    Test,
+
+    /// Live stream doesn't meet the `Output.constraints` of its downstream
+    /// destination (see `Input.codecs`).
+    ///
+    /// Unlike other kinds, it's not cleared once the `Output` becomes
+    /// `Status.ONLINE`, but only once the live stream meets the constraints.
+    Incompatible,
+}
+
+/// Test of an `Output`, re-streaming a test signal (SMPTE color bars with a
+/// tone) directly to its downstream destination for a limited time, so the
+/// destination can be verified before going live.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct OutputTest {
+    /// Number of seconds the test signal is re-streamed for.
+    pub duration_secs: i32,
+
+    /// Moment of time when this `OutputTest` has started.
+    pub started_at: DateTime,
+}
+
+impl OutputTest {
+    /// Maximum allowed [`OutputTest::duration_secs`] (5 minutes).
+    pub const MAX_DURATION_SECS: u32 = 300;
+
+    /// Returns duration of this [`OutputTest`].
+    #[inline]
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_secs.try_into().unwrap_or(0))
+    }
+}
+
+/// Result of a finished `OutputTest`.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct OutputTestResult {
+    /// Indicator whether the downstream destination has sustained the
+    /// connection for the whole `OutputTest.durationSecs`.
+    pub succeeded: bool,
+
+    /// Reason of the `OutputTest` failure.
+    ///
+    /// `null` if it has succeeded.
+    pub failure: Option<String>,
+
+    /// Moment of time when the `OutputTest` has finished.
+    pub at: DateTime,
+}
+
+/// Reference to an `Output` of a `Restream`.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq, Serialize)]
+pub struct OutputRef {
+    /// ID of the `Restream` the referred `Output` belongs to.
+    pub restream_id: RestreamId,
+
+    /// Key of the `Restream` the referred `Output` belongs to.
+    pub restream_key: RestreamKey,
+
+    /// ID of the referred `Output`.
+    pub output_id: OutputId,
+
+    /// Label of the referred `Output`, if any.
+    pub output_label: Option<Label>,
+
+    /// Downstream URL of the referred `Output`.
+    pub dst: OutputDstUrl,
+}
+
+impl OutputRef {
+    /// Creates a new [`OutputRef`] referring the given [`Output`] of the given
+    /// [`Restream`].
+    #[inline]
+    #[must_use]
+    pub fn new(restream: &Restream, output: &Output) -> Self {
+        Self {
+            restream_id: restream.id,
+            restream_key: restream.key.clone(),
+            output_id: output.id,
+            output_label: output.label.clone(),
+            dst: output.dst.clone(),
+        }
+    }
+}
+
+/// Conflict of `Output`s of different `Restream`s re-streaming to the same
+/// downstream destination.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct DstConflict {
+    /// Normalized downstream URL the conflicting `Output`s re-stream to.
+    pub dst: Url,
+
+    /// Conflicting `Output`s, at least two of them belonging to different
+    /// `Restream`s.
+    pub outputs: Vec<OutputRef>,
+}
+
+/// Sample of an `Output`'s re-streaming metrics, taken periodically while its
+/// re-streaming process is running.
+#[derive(Clone, Debug, GraphQLObject, PartialEq)]
+pub struct OutputSample {
+    /// Moment of time when this `OutputSample` has been taken.
+    pub at: DateTime,
+
+    /// Bitrate (in kbit/s) that the `Output` has been sending its downstream
+    /// destination with since the previous `OutputSample`.
+    ///
+    /// `null` for the first `OutputSample` after the re-streaming process has
+    /// been started.
+    pub kbps: Option<f64>,
+
+    /// Frame rate of the re-streaming process, as reported by [FFmpeg].
+    ///
+    /// `null` if not reported (for audio-only `Output`s, for example).
+    ///
+    /// [FFmpeg]: https://ffmpeg.org
+    pub fps: Option<f64>,
+
+    /// `Status` of the `Output` at the moment of this `OutputSample`.
+    pub status: Status,
+}
+
+/// Re-streaming process of an `Output` running in OS, along with its resources
+/// usage.
+#[derive(Clone, Debug, GraphQLObject, PartialEq)]
+pub struct OutputProcess {
+    /// ID of the re-streaming process in OS.
+    pub pid: i32,
+
+    /// CPU usage of the re-streaming process (in percents of a single CPU
+    /// core), averaged since its previous sampling.
+    ///
+    /// `null` if not sampled yet, or cannot be sampled on this platform.
+    pub cpu_percent: Option<f64>,
+
+    /// Resident memory size (in bytes) of the re-streaming process.
+    ///
+    /// `null` if not sampled yet, or cannot be sampled on this platform.
+    pub memory_bytes: Option<f64>,
+}
+
+/// Encoding statistics of a re-streaming process of an `Output`, re-encoding
+/// its media.
+#[derive(Clone, Debug, GraphQLObject, PartialEq)]
+pub struct OutputEncodingStats {
+    /// Encoding speed relative to real time (`1.0` means real time).
+    ///
+    /// Staying below the configured threshold for a while makes the `Output`
+    /// `Status.UNSTABLE`.
+    ///
+    /// `null` if not reported yet.
+    pub speed: Option<f64>,
+
+    /// Number of frames dropped since the re-streaming process has been
+    /// spawned.
+    pub drop_frames: i32,
+
+    /// Number of frames duplicated since the re-streaming process has been
+    /// spawned.
+    pub dup_frames: i32,
+}
+
+/// [FFmpeg] process managed by the server's pool of re-streaming processes,
+/// as the pool sees it.
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Clone, Debug, GraphQLObject, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolProcess {
+    /// ID of the element this process is reconciled under: an `Output`, an
+    /// `InputEndpoint`, a `Restream` (for its delay buffer), or an `Input`
+    /// (for its consolidated `Output`s).
+    pub key: String,
+
+    /// Kind of the re-streaming performed by this process: `copy`,
+    /// `transcoding`, `mixing`, `buffering` or `tee`.
+    pub kind: String,
+
+    /// Command line this process has been spawned with most recently, with
+    /// credentials masked.
+    ///
+    /// Empty if it hasn't been spawned yet.
+    pub command: Vec<String>,
+
+    /// ID of this process in OS.
+    ///
+    /// `null` if it's not running at the moment.
+    pub pid: Option<i32>,
+
+    /// Moment when this process has been spawned most recently.
+    ///
+    /// `null` if it hasn't been spawned yet.
+    pub spawned_at: Option<DateTime>,
+
+    /// Number of unexpected restarts of this process.
+    pub restarts: i32,
+
+    /// Current delay (in seconds) before restarting this process once it
+    /// exits.
+    pub backoff_secs: i32,
+
+    /// Status this process has exited with most recently.
+    ///
+    /// `null` if it hasn't exited yet.
+    pub last_exit: Option<String>,
+
+    /// Mechanisms enforcing `OutputLimits` of this process when it has been
+    /// spawned most recently: `cgroup`, `taskset` and/or `nice`.
+    ///
+    /// Empty if the process is not limited, or no mechanism is available.
+    pub limited_by: Vec<String>,
+}
+
+/// Status indicating availability of an `Input`, `Output`, or a `Mixin`.
+#[derive(
+    Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq, Serialize, SmartDefault,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Inactive, no operations are performed and no media traffic is flowed.
+    #[default]
+    Offline,
+
+    /// Initializing, media traffic doesn't yet flow as expected.
+    Initializing,
+
+    /// Active, all operations are performing successfully and media traffic
+    /// flows as expected.
+    Online,
+
+    /// Active, but media traffic doesn't flow as expected (re-encoding doesn't
+    /// keep up with real time, for example).
+    Unstable,
+
+    /// Re-streaming a test signal instead of a live stream (see `OutputTest`).
+    Testing,
+
+    /// Enabled, but not pulling a live stream outside the scheduled hours (see
+    /// `Input.schedule`).
+    OutOfSchedule,
+
+    /// Disabled, but connected to the downstream destination, so goes live
+    /// near-instantly once enabled (see `Output.prewarm`).
+    Prewarmed,
+}
+
+impl Status {
+    /// Indicates whether this [`Status`] means that media traffic flows, even
+    /// if not as expected ([`Status::Online`] or [`Status::Unstable`]).
+    #[inline]
+    #[must_use]
+    pub fn is_online(self) -> bool {
+        matches!(self, Self::Online | Self::Unstable)
+    }
+}
+
+/// Runtime [`Status`]es of [`Output`]s and [`InputEndpoint`]s, identified by
+/// their IDs.
+///
+/// Entities missing here are [`Status::Offline`].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Statuses(BTreeMap<Uuid, Status>);
+
+impl Statuses {
+    /// Returns the [`Status`] of the entity with the given `id`.
+    #[inline]
+    #[must_use]
+    pub fn get<Id: Into<Uuid>>(&self, id: Id) -> Status {
+        self.0.get(&id.into()).copied().unwrap_or_default()
+    }
+
+    /// Sets the given [`Status`] of the entity with the given `id`.
+    ///
+    /// Returns `false` if the entity has had this [`Status`] already.
+    pub fn set<Id: Into<Uuid>>(&mut self, id: Id, status: Status) -> bool {
+        let id = id.into();
+        if self.get(id) == status {
+            return false;
+        }
+        // Offline entities are not kept, so removed ones don't pile up.
+        if status == Status::Offline {
+            let _ = self.0.remove(&id);
+        } else {
+            let _ = self.0.insert(id, status);
+        }
+        true
+    }
+}
+
+/// Registry of runtime [`Statuses`] of [`Output`]s and [`InputEndpoint`]s.
+///
+/// Is kept apart from the [`State::restreams`], so [`Status`] changes (much
+/// more frequent than anything else) neither clone nor re-serialize all the
+/// [`Restream`]s, and don't wake the subscribers interested in their
+/// configuration only (like persistence). Subscribers interested in both
+/// should use [`State::on_restreams_change()`].
+///
+/// If locked along with the [`State::restreams`], should be locked after
+/// them.
+#[derive(Clone, Debug, Default)]
+pub struct StatusRegistry(Mutable<Statuses>);
+
+impl StatusRegistry {
+    /// Returns the current [`Status`] of the entity with the given `id`.
+    #[inline]
+    #[must_use]
+    pub fn get<Id: Into<Uuid>>(&self, id: Id) -> Status {
+        self.0.lock_ref().get(id)
+    }
+
+    /// Sets the given [`Status`] of the entity with the given `id`, notifying
+    /// subscribers only if it has changed.
+    ///
+    /// Returns `false` if the entity has had this [`Status`] already.
+    ///
+    /// The [`Status`] is compared and set under the same lock, so concurrent
+    /// calls never act on a stale [`Status`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn set<Id: Into<Uuid>>(&self, id: Id, status: Status) -> bool {
+        let id = id.into();
+        let mut statuses = trace::lock_mut(&self.0, "set_status");
+        // Borrowed mutably only on a change, as that's what notifies the
+        // subscribers once the lock is released.
+        if statuses.get(id) == status {
+            return false;
+        }
+        statuses.set(id, status)
+    }
+
+    /// Returns the current [`Statuses`], locked for reading.
+    #[inline]
+    #[must_use]
+    pub fn lock_ref(&self) -> MutableLockRef<'_, Statuses> {
+        self.0.lock_ref()
+    }
+
+    /// Returns the current [`Statuses`], locked for modifying by the
+    /// operation named as `op` (see [`trace::lock_mut()`]).
+    ///
+    /// Subscribers are notified once the lock is released, even if nothing
+    /// has changed, so [`StatusRegistry::set()`] should be preferred for a
+    /// single entity.
+    #[inline]
+    #[must_use]
+    pub fn lock_mut(&self, op: &'static str) -> trace::LockMut<'_, Statuses> {
+        trace::lock_mut(&self.0, op)
+    }
+
+    /// Returns a copy of the current [`Statuses`].
+    #[inline]
+    #[must_use]
+    pub fn get_cloned(&self) -> Statuses {
+        self.0.get_cloned()
+    }
+
+    /// Returns a [`Signal`] of the [`Statuses`] changes, deduplicated.
+    #[inline]
+    pub fn signal(&self) -> impl Signal<Item = Statuses> {
+        self.0.signal_cloned().dedupe_cloned()
+    }
+}
+
+/// Debouncer of [`Status`] transitions of [`Output`]s and [`InputEndpoint`]s,
+/// preventing short network blips from flapping their [`Status`]es.
+///
+/// A transition away from being online (see [`Status::is_online()`]) is
+/// committed into the [`State`] only if the entity doesn't go online again
+/// within the [`StatusDebouncer::grace`] period. Raw (not committed yet)
+/// [`Status`]es are tracked separately, and are never broadcast to
+/// subscribers.
+#[derive(Clone, Debug, Default)]
+pub struct StatusDebouncer {
+    /// Grace period a transition away from being online (see
+    /// [`Status::is_online()`]) is postponed for.
+    ///
+    /// Zero means no debouncing at all, so any transition is committed right
+    /// away.
+    pub grace: Mutable<Duration>,
+
+    /// Raw [`Status`]es of the debounced entities, identified by their IDs.
+    raw: Arc<Mutex<HashMap<Uuid, RawStatus>>>,
+}
+
+impl StatusDebouncer {
+    /// Returns the raw [`Status`] of the entity with the given `id`, if its
+    /// transition is postponed and not committed into the [`State`] yet.
+    ///
+    /// [`None`] means that the committed [`Status`] is the raw one.
+    #[must_use]
+    pub fn raw_status<Id: Into<Uuid>>(&self, id: Id) -> Option<Status> {
+        self.raw
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&id.into())
+            .filter(|r| r.pending_since.is_some())
+            .map(|r| r.status)
+    }
+
+    /// Records the given raw `status` of the entity with the given `id`.
+    ///
+    /// Returns the revision of the recorded `status` along with the duration
+    /// its committing should be postponed for, or [`None`] if it should be
+    /// committed right away.
+    fn record(
+        &self,
+        id: Uuid,
+        status: Status,
+        now: Instant,
+    ) -> Option<(u64, Duration)> {
+        let grace = self.grace.get();
+        let mut raw = self.raw.lock().unwrap_or_else(PoisonError::into_inner);
+        let raw = raw.entry(id).or_default();
+
+        let was_online = raw.status.is_online();
+        raw.status = status;
+        raw.revision = raw.revision.wrapping_add(1);
+
+        if status.is_online() || grace == Duration::from_secs(0) {
+            raw.pending_since = None;
+            return None;
+        }
+        // Subsequent transitions don't prolong the grace period, so an entity
+        // failing to come back online is committed as such in time.
+        let since = match raw.pending_since {
+            Some(since) => since,
+            None if was_online => now,
+            None => return None,
+        };
+        match (since + grace).checked_duration_since(now) {
+            Some(left) if left > Duration::from_secs(0) => {
+                raw.pending_since = Some(since);
+                Some((raw.revision, left))
+            }
+            _ => {
+                raw.pending_since = None;
+                None
+            }
+        }
+    }
+
+    /// Forgets the raw [`Status`]es of all the entities, except the ones with
+    /// the given `ids`.
+    pub fn retain(&self, ids: &HashSet<Uuid>) {
+        self.raw
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|id, _| ids.contains(id));
+    }
+
+    /// Completes the postponed transition of the entity with the given `id`.
+    ///
+    /// Returns `false` if the transition of the given `revision` has been
+    /// superseded by another one meanwhile, so shouldn't be committed.
+    fn complete(&self, id: Uuid, revision: u64) -> bool {
+        let mut raw = self.raw.lock().unwrap_or_else(PoisonError::into_inner);
+        match raw.get_mut(&id) {
+            Some(r) if r.revision == revision => {
+                r.pending_since = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Raw [`Status`] of an entity, tracked by a [`StatusDebouncer`].
+#[derive(Clone, Copy, Debug, Default)]
+struct RawStatus {
+    /// Last renewed [`Status`].
+    status: Status,
+
+    /// Revision of the last renewed [`Status`], increased on every renewal.
+    revision: u64,
+
+    /// Moment when the entity has stopped being online (see
+    /// [`Status::is_online()`]), if its transition is not committed yet.
+    pending_since: Option<Instant>,
+}
+
+/// Calculates a digest of the persisted (serialized) representation of the
+/// given `val`ue, without allocating the representation itself.
+///
+/// # Panics
+///
+/// If the `val`ue fails to be serialized.
+fn persisted_digest<T: Serialize + ?Sized>(val: &T) -> u64 {
+    let mut digest = Digest(DefaultHasher::new());
+    serde_json::to_writer(&mut digest, val)
+        .expect("Failed to serialize server state");
+    digest.0.finish()
+}
+
+/// [`io::Write`]r feeding all the written bytes into a [`Hasher`].
+///
+/// [`Hasher`]: std::hash::Hasher
+struct Digest<H>(H);
+
+impl<H: std::hash::Hasher> io::Write for Digest<H> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Name of the first [`State`] hook broken by [`HookPanics`], if any.
+static BROKEN_HOOK: Lazy<Mutable<Option<&'static str>>> =
+    Lazy::new(Mutable::default);
+
+/// Circuit breaker of a [`State`] hook (see [`State::on_change()`]), stopping
+/// the hook once it panics too frequently.
+///
+/// Such a hook is considered broken for good, so the whole process is better
+/// to be shut down (see [`State::hook_broken()`]) and restarted from scratch
+/// by a process supervisor (like [systemd]), rather than silently keep
+/// running without it.
+///
+/// [systemd]: https://systemd.io
+#[derive(Debug, Default)]
+struct HookPanics(VecDeque<Instant>);
+
+impl HookPanics {
+    /// Maximum number of panics of a hook tolerated during a
+    /// [`HookPanics::WINDOW`].
+    const MAX: usize = 10;
+
+    /// Sliding window of time [`HookPanics::MAX`] is counted in.
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    /// Records and logs the given `panic` of the `name`d hook.
+    ///
+    /// Returns `true` if [`HookPanics::MAX`] is exceeded, so the hook is
+    /// broken and should be stopped.
+    fn record(
+        &mut self,
+        name: &'static str,
+        panic: &(dyn Any + Send + 'static),
+    ) -> bool {
+        log::crit!(
+            "Panicked executing `{}` hook of state: {}",
+            name,
+            display_panic(panic),
+        );
+        if !self.exceeded(Instant::now()) {
+            return false;
+        }
+        log::crit!("`{}` hook of state panics too frequently, stopping", name);
+        let mut broken = BROKEN_HOOK.lock_mut();
+        if broken.is_none() {
+            *broken = Some(name);
+        }
+        true
+    }
+
+    /// Counts a panic happened at the given moment, and checks whether
+    /// [`HookPanics::MAX`] is exceeded.
+    fn exceeded(&mut self, now: Instant) -> bool {
+        while self
+            .0
+            .front()
+            .map_or(false, |at| now.duration_since(*at) >= Self::WINDOW)
+        {
+            let _ = self.0.pop_front();
+        }
+        self.0.push_back(now);
+        self.0.len() > Self::MAX
+    }
+}
+
+/// Health of persisting a [`State`] into its [`StatePersistence`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PersistenceHealth {
+    /// Number of consecutive failures of persisting a [`State`].
+    pub failures: u32,
+
+    /// Error of the last failed persisting, if the last one has failed.
+    pub last_error: Option<String>,
+}
+
+impl PersistenceHealth {
+    /// Number of consecutive failures of persisting a [`State`] to consider
+    /// it unhealthy.
+    ///
+    /// A single failure may be a transient one, so is not reported.
+    pub const MAX_FAILURES: u32 = 3;
+
+    /// Maximum interval between retries of a failed persisting.
+    pub const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Indicates whether a [`State`] is persisted successfully, or failed less
+    /// than [`PersistenceHealth::MAX_FAILURES`] times in a row.
+    #[inline]
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.failures < Self::MAX_FAILURES
+    }
+
+    /// Returns error of the last failed persisting, if it's unhealthy.
+    #[inline]
+    #[must_use]
+    pub fn error(&self) -> Option<&str> {
+        if self.is_healthy() {
+            None
+        } else {
+            self.last_error.as_deref()
+        }
+    }
+}
+
+/// Storage persisting a [`State`] between restarts.
+///
+/// Its [`Display`] implementation describes the storage in error messages.
+///
+/// [`Display`]: fmt::Display
+pub trait StatePersistence: fmt::Debug + fmt::Display + Send + Sync {
+    /// Loads the serialized [`State`] persisted previously, being empty if
+    /// nothing has been persisted yet.
+    ///
+    /// # Errors
+    ///
+    /// If the persisted [`State`] cannot be loaded.
+    fn load(&self) -> BoxFuture<'_, Result<Vec<u8>, anyhow::Error>>;
+
+    /// Persists the given serialized [`State`] `contents`, replacing the
+    /// previously persisted ones.
+    ///
+    /// # Errors
+    ///
+    /// If the `contents` cannot be persisted.
+    fn save(
+        &self,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>>;
+}
+
+/// Opens the [`StatePersistence`] of the [`State`] file located by the given
+/// `path` in the same way the server does: as a [`DirPersistence`] in the
+/// `state.d` directory next to the file, if `split`, and encrypted with the
+/// [`EncryptionKey`] read from the given `key_file`, if any.
+///
+/// # Errors
+///
+/// If the [`EncryptionKey`] cannot be read.
+pub async fn open_persistence(
+    path: &Path,
+    split: bool,
+    key_file: Option<&Path>,
+) -> Result<Arc<dyn StatePersistence>, anyhow::Error> {
+    let key = match key_file {
+        Some(f) => Some(EncryptionKey::from_file(f).await?),
+        None => None,
+    };
+    Ok(if split {
+        let dir = DirPersistence::new(path.with_file_name("state.d"), path);
+        Arc::new(match key {
+            Some(key) => dir.encrypted(key),
+            None => dir,
+        })
+    } else {
+        let file = FilePersistence::new(path);
+        Arc::new(match key {
+            Some(key) => file.encrypted(key),
+            None => file,
+        })
+    })
+}
+
+/// [`StatePersistence`] in a JSON file on the filesystem.
+///
+/// The file is accessible by its owner only, as contains secrets (stream keys,
+/// for example), and may be encrypted with an [`EncryptionKey`].
+#[derive(Clone, Debug)]
+pub struct FilePersistence {
+    /// Path of the file.
+    path: PathBuf,
+
+    /// [`EncryptionKey`] to encrypt the file contents with, if any.
+    key: Option<EncryptionKey>,
+}
+
+impl FilePersistence {
+    /// Creates a new [`FilePersistence`] in the file located by the given
+    /// `path`.
+    ///
+    /// The file is created on loading, if it doesn't exist.
+    #[inline]
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            key: None,
+        }
+    }
+
+    /// Makes this [`FilePersistence`] to encrypt the file contents with the
+    /// given [`EncryptionKey`].
+    ///
+    /// Not yet encrypted file is still loaded, being encrypted on the next
+    /// saving.
+    #[inline]
+    #[must_use]
+    pub fn encrypted(mut self, key: EncryptionKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Decrypts the given `contents` loaded from the file, if required.
+    ///
+    /// # Errors
+    ///
+    /// If the `contents` are encrypted, but no [`EncryptionKey`] is provided,
+    /// or it's a wrong one.
+    fn decrypt(&self, contents: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
+        if !contents.starts_with(EncryptionKey::MAGIC) {
+            if self.key.is_some() && !contents.is_empty() {
+                log::warn!(
+                    "{} is not encrypted yet, it will be encrypted on the next \
+                     saving",
+                    self,
+                );
+            }
+            return Ok(contents);
+        }
+        let key = self.key.as_ref().ok_or_else(|| {
+            anyhow!(
+                "{} is encrypted, but no state encryption key is provided",
+                self,
+            )
+        })?;
+        key.decrypt(&contents[EncryptionKey::MAGIC.len()..])
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to decrypt {}: wrong state encryption key or \
+                     corrupted file",
+                    self,
+                )
+            })
+    }
+}
+
+impl fmt::Display for FilePersistence {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' file", self.path.display())
+    }
+}
+
+impl StatePersistence for FilePersistence {
+    fn load(&self) -> BoxFuture<'_, Result<Vec<u8>, anyhow::Error>> {
+        async move {
+            // Nothing is created on loading, as a missing file just means that
+            // nothing has been persisted yet.
+            let mut file = match fs::File::open(&self.path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Ok(vec![]);
+                }
+                Err(e) => {
+                    return Err(anyhow!("Failed to open {}: {}", self, e));
+                }
+            };
+
+            let mode = file
+                .metadata()
+                .await
+                .map_err(|e| anyhow!("Failed to stat {}: {}", self, e))?
+                .permissions()
+                .mode();
+            if mode & 0o077 != 0 {
+                log::warn!(
+                    "{} is accessible by others (mode {:o}), restricting it to \
+                     its owner only",
+                    self,
+                    mode & 0o777,
+                );
+                fs::set_permissions(
+                    &self.path,
+                    Permissions::from_mode(PRIVATE_FILE_MODE),
+                )
+                .await
+                .map_err(|e| {
+                    anyhow!("Failed to restrict permissions of {}: {}", self, e)
+                })?;
+            }
+
+            let mut contents = vec![];
+            let _ = file
+                .read_to_end(&mut contents)
+                .await
+                .map_err(|e| anyhow!("Failed to read {}: {}", self, e))?;
+            self.decrypt(contents)
+        }
+        .boxed()
+    }
+
+    fn save(
+        &self,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>> {
+        let contents = match &self.key {
+            Some(key) => key.encrypt(&contents),
+            None => contents,
+        };
+        let path = self.path.clone();
+        async move {
+            // Written into a temporary file first and renamed then, so a crash
+            // never leaves the file half-written.
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(format!(".{}.tmp", Uuid::new_v4()));
+            let mut opts = private_file_options();
+            let _ = opts.write(true).truncate(true);
+            let mut file = fs::OpenOptions::from(opts).open(&tmp).await?;
+            file.write_all(&contents).await?;
+            file.sync_all().await?;
+            drop(file);
+            fs::rename(&tmp, &path).await.map_err(|e| {
+                let _ = std::fs::remove_file(&tmp);
+                e
+            })?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// [`StatePersistence`] in a directory of JSON files on the filesystem, so
+/// only the changed parts of a [`State`] are rewritten on its saving.
+///
+/// Each [`Restream`] is persisted into its own `<id>.json` file (by
+/// [`RestreamId`]), while the rest of the [`State`] (along with the order of
+/// its [`Restream`]s) is persisted into the `index.json` file, being written
+/// the last. Each file is written in the same way [`FilePersistence`] does, so
+/// is replaced atomically.
+///
+/// A legacy [`FilePersistence`] file is loaded, if the directory has no index
+/// yet, and is migrated on the first saving (renamed with `.migrated` suffix).
+#[derive(Clone, Debug)]
+pub struct DirPersistence {
+    /// Path of the directory.
+    dir: PathBuf,
+
+    /// Path of the legacy [`FilePersistence`] file.
+    legacy: PathBuf,
+
+    /// [`EncryptionKey`] to encrypt the files contents with, if any.
+    key: Option<EncryptionKey>,
+
+    /// Digests of the files contents written by this [`DirPersistence`]
+    /// already, by file names.
+    ///
+    /// Locked for the whole saving, so concurrent savings don't interleave.
+    written: Arc<tokio::sync::Mutex<HashMap<String, u64>>>,
+}
+
+impl DirPersistence {
+    /// Name of the file persisting everything of a [`State`] except its
+    /// [`Restream`]s.
+    const INDEX: &'static str = "index.json";
+
+    /// Creates a new [`DirPersistence`] in the directory located by the given
+    /// `dir` path, migrating the `legacy` [`FilePersistence`] file into it.
+    ///
+    /// The directory is created on saving, if it doesn't exist.
+    #[inline]
+    #[must_use]
+    pub fn new<D: Into<PathBuf>, L: Into<PathBuf>>(dir: D, legacy: L) -> Self {
+        Self {
+            dir: dir.into(),
+            legacy: legacy.into(),
+            key: None,
+            written: Arc::default(),
+        }
+    }
+
+    /// Makes this [`DirPersistence`] to encrypt the files contents with the
+    /// given [`EncryptionKey`].
+    ///
+    /// Not yet encrypted files are still loaded, being encrypted on the next
+    /// saving.
+    #[inline]
+    #[must_use]
+    pub fn encrypted(mut self, key: EncryptionKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Returns [`FilePersistence`] of the given `file` in this
+    /// [`DirPersistence`], or of the legacy file, if [`None`].
+    fn file(&self, file: Option<&str>) -> FilePersistence {
+        let path =
+            file.map_or_else(|| self.legacy.clone(), |f| self.dir.join(f));
+        let file = FilePersistence::new(path);
+        match &self.key {
+            Some(key) => file.encrypted(key.clone()),
+            None => file,
+        }
+    }
+
+    /// Splits the given serialized [`State`] `contents` into the files of this
+    /// [`DirPersistence`], with the index being the last.
+    ///
+    /// # Errors
+    ///
+    /// If the `contents` don't represent a [`State`].
+    fn split(contents: &[u8]) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+        let mut index: serde_json::Value = serde_json::from_slice(contents)?;
+        let restreams = index
+            .get_mut("restreams")
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+        let restreams = match restreams {
+            serde_json::Value::Array(r) => r,
+            serde_json::Value::Null => vec![],
+            _ => return Err(anyhow!("`restreams` is not an array")),
+        };
+
+        let mut files = Vec::with_capacity(restreams.len() + 1);
+        let mut ids = Vec::with_capacity(restreams.len());
+        for r in restreams {
+            let id = r
+                .get("id")
+                .and_then(serde_json::Value::as_str)
+                .ok_or_else(|| anyhow!("`Restream.id` is not a string"))?
+                .to_owned();
+            files.push((format!("{}.json", id), serde_json::to_vec(&r)?));
+            ids.push(serde_json::Value::String(id));
+        }
+        index["restreams"] = serde_json::Value::Array(ids);
+        files.push((Self::INDEX.to_owned(), serde_json::to_vec(&index)?));
+        Ok(files)
+    }
+}
+
+impl fmt::Display for DirPersistence {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' directory", self.dir.display())
+    }
+}
+
+impl StatePersistence for DirPersistence {
+    fn load(&self) -> BoxFuture<'_, Result<Vec<u8>, anyhow::Error>> {
+        async move {
+            if fs::metadata(self.dir.join(Self::INDEX)).await.is_err() {
+                if fs::metadata(&self.legacy).await.is_err() {
+                    return Ok(vec![]);
+                }
+                return self.file(None).load().await;
+            }
+
+            let mut index: serde_json::Value = serde_json::from_slice(
+                &self.file(Some(Self::INDEX)).load().await?,
+            )
+            .map_err(|e| anyhow!("Failed to parse index of {}: {}", self, e))?;
+            let ids = index
+                .get("restreams")
+                .and_then(serde_json::Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            let mut restreams = Vec::with_capacity(ids.len());
+            for id in ids {
+                let id = id
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Malformed index of {}", self))?;
+                let file = self.file(Some(&format!("{}.json", id)));
+                restreams.push(
+                    serde_json::from_slice::<serde_json::Value>(
+                        &file.load().await?,
+                    )
+                    .map_err(|e| anyhow!("Failed to parse {}: {}", file, e))?,
+                );
+            }
+            index["restreams"] = serde_json::Value::Array(restreams);
+            Ok(serde_json::to_vec(&index)?)
+        }
+        .boxed()
+    }
+
+    fn save(
+        &self,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>> {
+        let this = self.clone();
+        async move {
+            let files = Self::split(&contents)?;
+            let mut written = this.written.lock().await;
+            fs::create_dir_all(&this.dir).await?;
+            fs::set_permissions(&this.dir, Permissions::from_mode(0o700))
+                .await?;
+
+            for (name, contents) in &files {
+                let digest = {
+                    let mut hasher = DefaultHasher::new();
+                    hasher.write(contents);
+                    hasher.finish()
+                };
+                if written.get(name) == Some(&digest) {
+                    continue;
+                }
+                this.file(Some(name)).save(contents.clone()).await?;
+                let _ = written.insert(name.clone(), digest);
+            }
+
+            // Files of removed `Restream`s are cleaned up only once the index
+            // doesn't refer them anymore.
+            let mut entries = fs::read_dir(&this.dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.ends_with(".json")
+                    && files.iter().all(|(f, _)| *f != name)
+                {
+                    fs::remove_file(entry.path()).await?;
+                    let _ = written.remove(&name);
+                }
+            }
+
+            if fs::metadata(&this.legacy).await.is_ok() {
+                let mut migrated = this.legacy.clone().into_os_string();
+                migrated.push(".migrated");
+                fs::rename(&this.legacy, &migrated).await?;
+                log::info!("Migrated {} into {}", this.file(None), this,);
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Mode of a [`FilePersistence`] file, making it readable and writable by its
+/// owner only.
+const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Returns [`std::fs::OpenOptions`] creating a [`FilePersistence`] file with
+/// the [`PRIVATE_FILE_MODE`], if it doesn't exist.
+fn private_file_options() -> std::fs::OpenOptions {
+    let mut opts = std::fs::OpenOptions::new();
+    let _ = opts.create(true).mode(PRIVATE_FILE_MODE);
+    opts
+}
+
+/// Secret key to encrypt a [`State`] persisted by [`FilePersistence`] with.
+///
+/// Encrypts with AES-256 in [EAX] mode, which authenticates the encrypted
+/// contents, so a wrong key is detected rather than producing garbage.
+///
+/// [EAX]: https://en.wikipedia.org/wiki/EAX_mode
+#[derive(Clone)]
+pub struct EncryptionKey(Arc<Eax<Aes256>>);
+
+impl EncryptionKey {
+    /// Prefix of the contents encrypted with an [`EncryptionKey`],
+    /// distinguishing them from the not encrypted ones.
+    const MAGIC: &'static [u8] = b"ephyr-encrypted:v1\n";
+
+    /// Size of a random nonce prepended to the encrypted contents.
+    const NONCE_SIZE: usize = 16;
+
+    /// Derives a new [`EncryptionKey`] from the given `secret`, ignoring its
+    /// surrounding whitespaces.
+    ///
+    /// The `secret` is expected to be a random one (like the output of
+    /// `openssl rand -hex 32`), as no password stretching is applied.
+    ///
+    /// Returns [`None`] if the `secret` is empty.
+    #[must_use]
+    pub fn derive(secret: &[u8]) -> Option<Self> {
+        let secret = String::from_utf8_lossy(secret);
+        let secret = secret.trim();
+        if secret.is_empty() {
+            return None;
+        }
+        let hash = blake2b_simd::Params::new()
+            .hash_length(32)
+            .personal(b"ephyr-state-key")
+            .hash(secret.as_bytes());
+        Some(Self(Arc::new(Eax::new(GenericArray::from_slice(
+            hash.as_bytes(),
+        )))))
+    }
+
+    /// Reads a new [`EncryptionKey`] from the file located by the given
+    /// `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, or is empty.
+    pub async fn from_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let secret = fs::read(path).await.map_err(|e| {
+            anyhow!(
+                "Failed to read state encryption key from '{}' file: {}",
+                path.display(),
+                e,
+            )
+        })?;
+        Self::derive(&secret).ok_or_else(|| {
+            anyhow!("State encryption key '{}' file is empty", path.display(),)
+        })
+    }
+
+    /// Encrypts the given `plain` contents, prepending them with a random
+    /// nonce and [`EncryptionKey::MAGIC`].
+    #[must_use]
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        let mut nonce = [0_u8; Self::NONCE_SIZE];
+        rand::thread_rng().fill(&mut nonce);
+        let sealed = self
+            .0
+            .encrypt(GenericArray::from_slice(&nonce), plain)
+            .expect("EAX encryption never fails");
+
+        let mut out =
+            Vec::with_capacity(Self::MAGIC.len() + nonce.len() + sealed.len());
+        out.extend_from_slice(Self::MAGIC);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        out
+    }
+
+    /// Decrypts the given `sealed` contents (without [`EncryptionKey::MAGIC`])
+    /// encrypted previously with [`EncryptionKey::encrypt()`].
+    ///
+    /// Returns [`None`] if this [`EncryptionKey`] is a wrong one, or the
+    /// contents are corrupted.
+    #[must_use]
+    pub fn decrypt(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < Self::NONCE_SIZE {
+            return None;
+        }
+        let (nonce, sealed) = sealed.split_at(Self::NONCE_SIZE);
+        self.0.decrypt(GenericArray::from_slice(nonce), sealed).ok()
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(***)")
+    }
+}
+
+/// No-op [`StatePersistence`], so the [`State`] lives in memory only and is
+/// lost on restart.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InMemoryPersistence;
+
+impl fmt::Display for InMemoryPersistence {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory")
+    }
+}
+
+impl StatePersistence for InMemoryPersistence {
+    #[inline]
+    fn load(&self) -> BoxFuture<'_, Result<Vec<u8>, anyhow::Error>> {
+        future::ok(vec![]).boxed()
+    }
+
+    #[inline]
+    fn save(
+        &self,
+        _: Vec<u8>,
+    ) -> BoxFuture<'static, Result<(), anyhow::Error>> {
+        future::ok(()).boxed()
+    }
+}
+
+/// Version of [`State::restreams`] along with their [`State::statuses`],
+/// allowing polling clients to detect whether they have changed cheaply.
+///
+/// It's a [XXH3] hash of the serialized [`Restream`]s, their runtime fields
+/// exposed to clients (omitted by the serialization), and their [`Statuses`].
+/// Once calculated, it's cached until [`RestreamsVersion::invalidate()`] is
+/// called.
+///
+/// [XXH3]: https://github.com/Cyan4973/xxHash
+#[derive(Clone, Debug, Default)]
+pub struct RestreamsVersion(Arc<Mutex<Option<String>>>);
+
+impl RestreamsVersion {
+    /// Returns the cached version, calculating it for the given `restreams`
+    /// and their `statuses` if there is none.
+    ///
+    /// If the calculation fails, logs the error and returns a random version
+    /// (not cached), so clients never consider their data as up-to-date.
+    #[must_use]
+    pub fn get(&self, restreams: &[Restream], statuses: &Statuses) -> String {
+        let mut version = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(v) = &*version {
+            return v.clone();
+        }
+        match Self::calculate(restreams, statuses) {
+            Ok(v) => version.get_or_insert(v).clone(),
+            Err(e) => {
+                log::error!("Failed to calculate version of Restreams: {}", e);
+                Uuid::new_v4().to_simple().to_string()
+            }
+        }
+    }
+
+    /// Drops the cached version, so the next [`RestreamsVersion::get()`]
+    /// calculates it anew.
+    pub fn invalidate(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Calculates the version of the given `restreams` and their `statuses`.
+    ///
+    /// # Errors
+    ///
+    /// If the `restreams` or their `statuses` fail to be serialized.
+    pub fn calculate(
+        restreams: &[Restream],
+        statuses: &Statuses,
+    ) -> Result<String, serde_json::Error> {
+        /// Feeds the runtime fields of the given [`Input`] and all its
+        /// [`FailoverInputSrc::inputs`] into the given `digest`.
+        fn feed_input(
+            digest: &mut Digest<Xxh3>,
+            input: &Input,
+        ) -> Result<(), serde_json::Error> {
+            serde_json::to_writer(
+                &mut *digest,
+                &(&input.problem, &input.codecs),
+            )?;
+            for e in &input.endpoints {
+                serde_json::to_writer(
+                    &mut *digest,
+                    &(&e.offline_reason, &e.publishing_session),
+                )?;
+            }
+            if let Some(InputSrc::Failover(s)) = &input.src {
+                for i in &s.inputs {
+                    feed_input(digest, i)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut digest = Digest(Xxh3::new());
+        serde_json::to_writer(&mut digest, restreams)?;
+        serde_json::to_writer(&mut digest, statuses)?;
+        for r in restreams {
+            serde_json::to_writer(&mut digest, &r.hls_segment_at)?;
+            feed_input(&mut digest, &r.input)?;
+            for o in &r.outputs {
+                serde_json::to_writer(
+                    &mut digest,
+                    &(
+                        o.paused,
+                        &o.problem,
+                        &o.incompatibility,
+                        &o.dst_health,
+                        &o.test,
+                        &o.last_test,
+                        &o.conflicts_with,
+                    ),
+                )?;
+                for m in &o.mixins {
+                    serde_json::to_writer(&mut digest, &m.status)?;
+                }
+            }
+        }
+        Ok(format!("{:016x}", digest.0.digest()))
+    }
+}
+
+/// Journal of changes of [`State::restreams`], allowing external systems to
+/// sync them incrementally (see [`ChangeFeed::since()`]).
+///
+/// Its sequence number is bumped on every persisted change of the [`State`].
+/// It starts from the boot time of the server (in microseconds since UNIX
+/// epoch), so keeps increasing monotonically across restarts, while the
+/// journal itself is kept in memory only, being bounded by
+/// [`ChangeFeed::CAPACITY`].
+#[derive(Clone, Debug, Default)]
+pub struct ChangeFeed(Arc<Mutex<ChangeJournal>>);
+
+impl ChangeFeed {
+    /// Maximum number of [`RestreamChange`]s kept in a [`ChangeFeed`].
+    pub const CAPACITY: usize = 1000;
+
+    /// Returns the current sequence number of this [`ChangeFeed`].
+    #[must_use]
+    pub fn seq(&self) -> ChangeSeq {
+        ChangeSeq(self.lock().seq)
+    }
+
+    /// Remembers the given `restreams` as the ones every further change is
+    /// recorded against, without recording any change.
+    pub fn start(&self, restreams: &[Restream]) {
+        self.lock().digests = Self::digests(restreams).into_iter().collect();
+    }
+
+    /// Bumps the sequence number of this [`ChangeFeed`], recording the
+    /// [`RestreamChange`]s happened since the previous call, given the
+    /// current [`ChangeFeed::digests()`] of [`State::restreams`].
+    pub fn record(&self, digests: &[(RestreamId, u64)]) {
+        self.lock().record(digests);
+    }
+
+    /// Calculates digests of the persisted representation of the given
+    /// `restreams`, preserving their order.
+    ///
+    /// These digests are what [`ChangeFeed::record()`] detects changes by, so
+    /// may be calculated just once per change, and deduplicated by itself.
+    #[must_use]
+    pub fn digests(restreams: &[Restream]) -> Vec<(RestreamId, u64)> {
+        restreams
+            .iter()
+            .map(|r| (r.id, persisted_digest(r)))
+            .collect()
+    }
+
+    /// Bumps the sequence number of this [`ChangeFeed`] on a persisted change
+    /// not touching [`State::restreams`].
+    pub fn bump(&self) {
+        self.lock().seq += 1;
+    }
+
+    /// Returns the current sequence number of this [`ChangeFeed`], along with
+    /// the [`RestreamChange`]s happened after the given `cursor` (a sequence
+    /// number seen previously), merged to a single one per [`Restream`].
+    ///
+    /// Returns [`None`] if the `cursor` is older than the horizon of this
+    /// [`ChangeFeed`] (or is unknown to it), so a full resync is required.
+    #[must_use]
+    pub fn since(
+        &self,
+        cursor: ChangeSeq,
+    ) -> Option<(ChangeSeq, Vec<RestreamChange>)> {
+        let journal = self.lock();
+        let cursor = cursor.0;
+        if cursor < journal.horizon || cursor > journal.seq {
+            return None;
+        }
+        let mut merged: Vec<RestreamChange> = vec![];
+        for c in journal.changes.iter().filter(|c| c.seq.0 > cursor) {
+            match merged.iter().position(|m| m.restream_id == c.restream_id) {
+                Some(i) => {
+                    let prev = merged.remove(i);
+                    merged.push(RestreamChange {
+                        kind: prev.kind.merge(c.kind),
+                        ..*c
+                    });
+                }
+                None => merged.push(*c),
+            }
+        }
+        Some((ChangeSeq(journal.seq), merged))
+    }
+
+    /// Locks the [`ChangeJournal`] of this [`ChangeFeed`].
+    fn lock(&self) -> MutexGuard<'_, ChangeJournal> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Inner journal of a [`ChangeFeed`].
+#[derive(Debug)]
+struct ChangeJournal {
+    /// Current sequence number.
+    seq: u64,
+
+    /// Sequence number the recorded [`RestreamChange`]s are complete after.
+    horizon: u64,
+
+    /// Digests of the persisted representation of the [`Restream`]s recorded
+    /// the last time.
+    digests: HashMap<RestreamId, u64>,
+
+    /// Recorded [`RestreamChange`]s, ordered by their sequence numbers.
+    changes: VecDeque<RestreamChange>,
+}
+
+impl Default for ChangeJournal {
+    fn default() -> Self {
+        #[allow(clippy::cast_possible_truncation)] // never happens
+        let boot = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        Self {
+            seq: boot,
+            horizon: boot,
+            digests: HashMap::new(),
+            changes: VecDeque::new(),
+        }
+    }
+}
+
+impl ChangeJournal {
+    /// Bumps the sequence number, recording the [`RestreamChange`]s of the
+    /// given [`Restream`]s' `digests` against the previously recorded ones.
+    fn record(&mut self, digests: &[(RestreamId, u64)]) {
+        self.seq += 1;
+        let seq = ChangeSeq(self.seq);
+
+        for (restream_id, digest) in digests {
+            let kind = match self.digests.get(restream_id) {
+                None => ChangeKind::Created,
+                Some(d) if d != digest => ChangeKind::Updated,
+                Some(_) => continue,
+            };
+            self.push(RestreamChange {
+                seq,
+                restream_id: *restream_id,
+                kind,
+            });
+        }
+        let digests: HashMap<_, _> = digests.iter().copied().collect();
+        let mut deleted: Vec<_> = self
+            .digests
+            .keys()
+            .filter(|id| !digests.contains_key(id))
+            .copied()
+            .collect();
+        deleted.sort_by_key(|id| id.to_string());
+        for restream_id in deleted {
+            self.push(RestreamChange {
+                seq,
+                restream_id,
+                kind: ChangeKind::Deleted,
+            });
+        }
+        self.digests = digests;
+    }
+
+    /// Appends the given [`RestreamChange`], evicting the oldest one and
+    /// advancing the horizon if there are too many of them.
+    fn push(&mut self, change: RestreamChange) {
+        self.changes.push_back(change);
+        while self.changes.len() > ChangeFeed::CAPACITY {
+            if let Some(evicted) = self.changes.pop_front() {
+                self.horizon = evicted.seq.0;
+            }
+        }
+    }
+}
+
+/// Sequence number of a [`ChangeFeed`].
+#[derive(
+    Clone, Copy, Debug, Display, Eq, From, Into, Ord, PartialEq, PartialOrd,
+)]
+pub struct ChangeSeq(pub u64);
+
+/// Sequence number of persisted changes of a server, used as a cursor of
+/// `Query.changesSince`.
+///
+/// Represented as a string of decimal digits, as exceeds the range of
+/// integers safely representable in JavaScript.
+#[graphql_scalar]
+impl<S> GraphQLScalar for ChangeSeq
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.to_string())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(|s| s.parse().ok())
+            .map(Self)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Change of a [`Restream`] recorded by a [`ChangeFeed`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RestreamChange {
+    /// Sequence number of the [`ChangeFeed`] this change happened at.
+    pub seq: ChangeSeq,
+
+    /// ID of the changed [`Restream`].
+    pub restream_id: RestreamId,
+
+    /// [`ChangeKind`] of this change.
+    pub kind: ChangeKind,
+}
+
+/// Kind of a `RestreamChange`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq)]
+pub enum ChangeKind {
+    /// `Restream` has been created (or restored from the trash).
+    Created,
+
+    /// `Restream` has been updated.
+    Updated,
+
+    /// `Restream` has been deleted (or moved into the trash).
+    Deleted,
+}
+
+impl ChangeKind {
+    /// Merges this [`ChangeKind`] with the `next` one happened after it.
+    #[must_use]
+    pub fn merge(self, next: Self) -> Self {
+        match (self, next) {
+            (Self::Created, Self::Updated) => Self::Created,
+            (Self::Deleted, Self::Created) => Self::Updated,
+            (_, next) => next,
+        }
+    }
+}
+
+/// Label of a [`Restream`] or an [`Output`].
+#[derive(Clone, Debug, Deref, Display, Eq, Into, PartialEq, Serialize)]
+pub struct Label(String);
+
+impl Label {
+    /// Maximum allowed length (in characters) of a [`Label`].
+    pub const MAX_LEN: usize = 70;
+
+    /// Creates a new [`Label`] if the given value meets its invariants.
+    ///
+    /// The value is normalized to [Unicode NFC][1].
+    ///
+    /// # Errors
+    ///
+    /// If the given value is empty, contains a comma, or violates the rules of
+    /// [`text::sanitize()`].
+    ///
+    /// [1]: https://unicode.org/reports/tr15
+    pub fn new<'s, S: Into<Cow<'s, str>>>(
+        val: S,
+    ) -> Result<Self, text::Violation> {
+        let val = text::sanitize("label", &val.into(), Self::MAX_LEN)?;
+        if val.is_empty() {
+            return Err(text::Violation::Empty("label"));
+        }
+        if val.contains(',') {
+            return Err(text::Violation::ForbiddenChar("label", ','));
+        }
+        Ok(Self(val))
+    }
+}
+
+impl<'de> Deserialize<'de> for Label {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
+            .map_err(|e| D::Error::custom(format!("Not a valid Label: {}", e)))
+    }
+}
+
+/// Name of a time zone in the [IANA time zone database][1], like
+/// `Europe/Kyiv`.
+///
+/// [1]: https://www.iana.org/time-zones
+#[derive(Clone, Debug, Deref, Display, Eq, Into, PartialEq, Serialize)]
+pub struct TimeZone(String);
+
+impl TimeZone {
+    /// Maximum allowed length (in characters) of a [`TimeZone`] name.
+    pub const MAX_LEN: usize = 64;
+
+    /// Creates a new [`TimeZone`] if the given value looks like a valid
+    /// [IANA time zone database][1] name.
+    ///
+    /// Doesn't check whether such time zone exists in the database (see
+    /// [`TimeZone::is_known()`]), so the persisted [`TimeZone`]s remain
+    /// readable even if the database changes.
+    ///
+    /// [1]: https://www.iana.org/time-zones
+    #[must_use]
+    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
+        static FORMAT: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^[A-Za-z][A-Za-z0-9_+\-]*(/[A-Za-z0-9_+\-]+)*$")
+                .unwrap()
+        });
+
+        let val = val.into();
+        (val.len() <= Self::MAX_LEN && FORMAT.is_match(&val))
+            .then(|| Self(val.into_owned()))
+    }
+
+    /// Returns the [`Tz`] of this [`TimeZone`] from the
+    /// [IANA time zone database][1] compiled into this application, if it
+    /// exists there.
+    ///
+    /// [1]: https://www.iana.org/time-zones
+    #[must_use]
+    pub fn tz(&self) -> Option<Tz> {
+        self.0.parse().ok()
+    }
+
+    /// Indicates whether this [`TimeZone`] exists in the
+    /// [IANA time zone database][1] compiled into this application.
+    ///
+    /// [1]: https://www.iana.org/time-zones
+    #[inline]
+    #[must_use]
+    pub fn is_known(&self) -> bool {
+        self.tz().is_some()
+    }
+
+    /// Returns the offset of this [`TimeZone`] from UTC (in seconds, positive
+    /// to the east of it) at the given moment, if this [`TimeZone`] is known
+    /// (see [`TimeZone::is_known()`]).
+    #[must_use]
+    pub fn utc_offset(&self, at: SystemTime) -> Option<i32> {
+        let at = chrono::DateTime::<Utc>::from(at).naive_utc();
+        Some(
+            self.tz()?
+                .offset_from_utc_datetime(&at)
+                .fix()
+                .local_minus_utc(),
+        )
+    }
+
+    /// Converts the given wall-clock `local` date and time in this
+    /// [`TimeZone`] into the moment it denotes.
+    ///
+    /// # Errors
+    ///
+    /// - If this [`TimeZone`] is not known (see [`TimeZone::is_known()`]).
+    /// - If the `local` time is skipped or repeated by a DST transition, so
+    ///   denotes no moment or two of them.
+    pub fn to_utc(
+        &self,
+        local: NaiveDateTime,
+    ) -> Result<DateTime, LocalTimeError> {
+        match self
+            .tz()
+            .ok_or(LocalTimeError::UnknownTimeZone)?
+            .from_local_datetime(&local)
+        {
+            LocalResult::Single(dt) => Ok(dt.with_timezone(&Utc).into()),
+            LocalResult::None => Err(LocalTimeError::Skipped),
+            LocalResult::Ambiguous(..) => Err(LocalTimeError::Ambiguous),
+        }
+    }
+}
+
+/// Error of converting a wall-clock time in a [`TimeZone`] into a moment (see
+/// [`TimeZone::to_utc()`]).
+#[derive(Clone, Copy, Debug, Display, Eq, Error, PartialEq)]
+pub enum LocalTimeError {
+    /// [`TimeZone`] doesn't exist in the [IANA time zone database][1].
+    ///
+    /// [1]: https://www.iana.org/time-zones
+    #[display(fmt = "Unknown IANA time zone")]
+    UnknownTimeZone,
+
+    /// Wall-clock time is skipped by a DST transition, so never happens.
+    #[display(fmt = "Local time is skipped by a DST transition")]
+    Skipped,
+
+    /// Wall-clock time is repeated by a DST transition, so happens twice.
+    #[display(fmt = "Local time is ambiguous due to a DST transition")]
+    Ambiguous,
+}
+
+impl LocalTimeError {
+    /// Returns unique literal code of this [`LocalTimeError`] for reporting it
+    /// in a GraphQL error.
+    #[inline]
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownTimeZone => "INVALID_TIMEZONE",
+            Self::Skipped => "NONEXISTENT_LOCAL_TIME",
+            Self::Ambiguous => "AMBIGUOUS_LOCAL_TIME",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
+            .ok_or_else(|| D::Error::custom("Not a valid TimeZone"))
+    }
+}
+
+/// Name of a group of [`Restream`]s.
+#[derive(
+    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
+)]
+pub struct RestreamGroup(String);
+
+impl RestreamGroup {
+    /// Maximum allowed length (in characters) of a [`RestreamGroup`].
+    pub const MAX_LEN: usize = 100;
+
+    /// Creates a new [`RestreamGroup`] if the given value meets its
+    /// invariants.
+    #[must_use]
+    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
+        let val = text::sanitize("group", &val.into(), Self::MAX_LEN).ok()?;
+        (!val.trim().is_empty()).then(|| Self(val))
+    }
+}
+
+impl<'de> Deserialize<'de> for RestreamGroup {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
+            .ok_or_else(|| D::Error::custom("Not a valid RestreamGroup"))
+    }
+}
+
+/// Type of a `Restream` group name.
+///
+/// It should be a free-form non-blank string of at most 100 characters,
+/// without control characters.
+#[graphql_scalar]
+impl<S> GraphQLScalar for RestreamGroup
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(Self::new)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Type of a `Restream` or an `Output` label.
+///
+/// It should meet `[^,\n\t\r\f\v]{1,70}` format.
+#[graphql_scalar]
+impl<S> GraphQLScalar for Label
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(|v| Self::new(v).ok())
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Public host (hostname or IP address with an optional port) that clients
+/// reach this application by.
+#[derive(Clone, Debug, Deref, Display, Eq, Into, PartialEq, Serialize)]
+pub struct PublicHost(String);
+
+impl PublicHost {
+    /// Creates a new [`PublicHost`] if the given value meets its invariants.
+    ///
+    /// Bare IPv6 literals are enclosed into brackets to be usable in URLs.
+    #[must_use]
+    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
+        let val = val.into();
+        if let Ok(ip) = val.parse::<Ipv6Addr>() {
+            return Some(IpAddr::V6(ip).into());
+        }
+        if val.is_empty() || val.contains(char::is_whitespace) {
+            return None;
+        }
+        let url = Url::parse(&format!("rtmp://{}", val)).ok()?;
+        (url.has_host()
+            && url.username().is_empty()
+            && url.password().is_none()
+            && url.path().is_empty()
+            && url.query().is_none()
+            && url.fragment().is_none())
+        .then(|| Self(val.into_owned()))
+    }
+}
+
+impl From<IpAddr> for PublicHost {
+    #[inline]
+    fn from(ip: IpAddr) -> Self {
+        Self(match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicHost {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
+            .ok_or_else(|| D::Error::custom("Not a valid PublicHost"))
+    }
+}
+
+/// Type of a public host (hostname or IP address with an optional port) that
+/// clients reach this application by.
+///
+/// It should be a bare host, like `example.com`, `10.0.0.1:1935` or
+/// `[::1]:1935`, without any scheme, path or credentials.
+#[graphql_scalar]
+impl<S> GraphQLScalar for PublicHost
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.as_str().to_owned())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_str)
+            .and_then(Self::new)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Volume rate of an audio track in percents.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    SmartDefault,
+)]
+pub struct Volume(#[default(Self::ORIGIN.0)] u16);
+
+impl Volume {
+    /// Maximum possible value of a [`Volume`] rate.
+    pub const MAX: Volume = Volume(1000);
+
+    /// Value of a [`Volume`] rate corresponding to the original one of an audio
+    /// track.
+    pub const ORIGIN: Volume = Volume(100);
+
+    /// Minimum possible value of a [`Volume`] rate. Actually, disables audio.
+    pub const OFF: Volume = Volume(0);
+
+    /// Creates a new [`Volume`] rate value if it satisfies the required
+    /// invariants:
+    /// - within [`Volume::OFF`] and [`Volume::MAX`] values.
+    #[must_use]
+    pub fn new<N: TryInto<u16>>(num: N) -> Option<Self> {
+        let num = num.try_into().ok()?;
+        if (Self::OFF.0..=Self::MAX.0).contains(&num) {
+            Some(Self(num))
+        } else {
+            None
+        }
+    }
+
+    /// Displays this [`Volume`] as a fraction of `1`, i.e. `100%` as `1`, `50%`
+    /// as `0.50`, and so on.
+    #[must_use]
+    pub fn display_as_fraction(self) -> String {
+        format!("{}.{:02}", self.0 / 100, self.0 % 100)
+    }
+
+    /// Indicates whether this [`Volume`] rate value corresponds is the
+    /// [`Volume::ORIGIN`]al one.
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required for `serde`
+    #[inline]
+    #[must_use]
+    pub fn is_origin(&self) -> bool {
+        *self == Self::ORIGIN
+    }
+}
+
+/// Type a volume rate of audio track in percents.
+///
+/// It's values are always within range of `0` and `1000` (inclusively).
+///
+/// `0` means disabled audio.
+#[graphql_scalar]
+impl<S> GraphQLScalar for Volume
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(i32::from(self.0))
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_int)
+            .and_then(Self::new)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+/// Delay of a [`Mixin`] being mixed with an [`Output`].
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Default,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub struct Delay(#[serde(with = "serde_humantime")] Duration);
+
+impl Delay {
+    /// Creates a new [`Delay`] out of the given milliseconds.
+    #[inline]
+    #[must_use]
+    pub fn from_millis<N: TryInto<u64>>(millis: N) -> Option<Self> {
+        millis
+            .try_into()
+            .ok()
+            .map(|m| Self(Duration::from_millis(m)))
+    }
+
+    /// Returns milliseconds of this [`Delay`].
+    #[inline]
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn as_millis(&self) -> i32 {
+        self.0.as_millis().try_into().unwrap()
+    }
+
+    /// Indicates whether this [`Delay`] introduces no actual delay.
+    #[inline]
+    #[must_use]
+    pub fn is_zero(&self) -> bool {
+        self.0 == Duration::default()
+    }
+}
+
+/// Type of a `Mixin` delay in milliseconds.
+///
+/// Negative values are not allowed.
+#[graphql_scalar]
+impl<S> GraphQLScalar for Delay
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.as_millis())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Self> {
+        v.as_scalar()
+            .and_then(ScalarValue::as_int)
+            .and_then(Self::from_millis)
+    }
+
+    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
+        <String as ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+#[cfg(test)]
+mod volume_spec {
+    use super::Volume;
+
+    #[test]
+    fn displays_as_fraction() {
+        for (input, expected) in &[
+            (1, "0.01"),
+            (10, "0.10"),
+            (200, "2.00"),
+            (107, "1.07"),
+            (170, "1.70"),
+            (1000, "10.00"),
+        ] {
+            let actual = Volume::new(*input).unwrap().display_as_fraction();
+            assert_eq!(&actual, *expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod output_dst_url_spec {
+    use url::Url;
+
+    use super::OutputDstUrl;
+
+    fn dst(url: &str) -> OutputDstUrl {
+        OutputDstUrl::new(Url::parse(url).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn treats_different_spellings_as_equivalent() {
+        for (a, b) in &[
+            ("rtmp://host/app/stream", "rtmp://host:1935/app/stream/"),
+            ("rtmp://HOST.com/app/stream", "rtmp://host.com/app/stream"),
+            ("rtmp://host/app/stream", "rtmp://host/app/stream///"),
+            ("rtmp://host/app/stream", "rtmp://host/%61pp/str%65am"),
+            ("rtmps://host/app/stream", "rtmps://host:443/app/stream"),
+            ("icecast://host/mount", "icecast://host:8000/mount"),
+            ("srt://host:4000?streamid=a", "srt://HOST:4000?streamid=a"),
+        ] {
+            assert!(dst(a).is_equivalent(&dst(b)), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn distinguishes_different_destinations() {
+        for (a, b) in &[
+            ("rtmp://host/app/stream", "rtmp://host:1936/app/stream"),
+            ("rtmp://host/app/stream", "rtmps://host/app/stream"),
+            ("rtmp://host/app/stream", "rtmp://host/app/Stream"),
+            ("rtmp://host/app/a%2Fb", "rtmp://host/app/a/b"),
+            ("srt://host:4000?streamid=a", "srt://host:4000?streamid=b"),
+            ("file:///a.flv", "file:///b.flv"),
+        ] {
+            assert!(!dst(a).is_equivalent(&dst(b)), "{} == {}", a, b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod rtmp_pull_options_spec {
+    use serde_json::json;
+    use url::Url;
+
+    use super::{RestreamId, RtmpPullOptions, State};
+
+    fn options(
+        playpath: Option<&str>,
+        page_url: Option<&str>,
+        token_query: Option<&str>,
+    ) -> Result<Option<RtmpPullOptions>, &'static str> {
+        RtmpPullOptions::new(
+            playpath.map(Into::into),
+            None,
+            page_url.map(|u| Url::parse(u).unwrap()),
+            token_query.map(Into::into),
+        )
+    }
+
+    #[test]
+    fn normalizes_values() {
+        assert_eq!(options(None, None, None), Ok(None));
+        assert_eq!(options(Some(""), None, Some("?")), Ok(None));
+
+        let opts = options(Some("mp4:stream"), None, Some("?token=a&e=1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(opts.playpath.as_deref(), Some("mp4:stream"));
+        assert_eq!(opts.token_query.as_deref(), Some("token=a&e=1"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for (playpath, page_url, token_query) in &[
+            (Some("my stream"), None, None),
+            (None, Some("rtmp://host/page"), None),
+            (None, None, Some("token=a b")),
+            (None, None, Some("token=a#b")),
+            (None, None, Some("token=a&&e=1")),
+            (None, None, Some("=a")),
+        ] {
+            assert!(
+                options(*playpath, *page_url, *token_query).is_err(),
+                "{:?} {:?} {:?}",
+                playpath,
+                page_url,
+                token_query,
+            );
+        }
+    }
+
+    #[test]
+    fn sets_to_remote_rtmp_inputs_only() {
+        let state = State::with_restream(
+            "pulled",
+            json!({"input": {"src": {"remote_url": "rtmp://example.com/a/b"}}}),
+        )
+        .and_restream("published", json!({}));
+        let ids: Vec<_> = state
+            .restreams
+            .get_cloned()
+            .iter()
+            .map(|r| (r.id, r.input.id))
+            .collect();
+        let opts = options(Some("mp4:stream"), None, Some("token=a")).unwrap();
+
+        let (pulled, input) = ids[0];
+        assert_eq!(
+            state.set_input_rtmp_options(input, pulled, opts.clone()),
+            Ok(Some(true)),
+        );
+        assert_eq!(
+            state.set_input_rtmp_options(input, pulled, opts.clone()),
+            Ok(Some(false)),
+        );
+        let stored = state.restreams.get_cloned()[0].input.rtmp_options.clone();
+        assert_eq!(stored, opts);
+        assert_eq!(
+            state.set_input_rtmp_options(input, RestreamId::random(), None),
+            Ok(None),
+        );
+
+        let (published, input) = ids[1];
+        assert!(state
+            .set_input_rtmp_options(input, published, opts)
+            .is_err());
+        assert_eq!(
+            state.set_input_rtmp_options(input, published, None),
+            Ok(Some(false)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod restream_key_spec {
+    use serde_json::json;
+
+    use super::{InvalidRestreamKey, RestreamKey};
+
+    #[test]
+    fn accepts_valid_keys() {
+        for key in &["a", "Live_1", "my-stream", &*"a".repeat(64)] {
+            assert_eq!(RestreamKey::validate(key), Ok(()), "{}", key);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for (key, err) in &[
+            ("", InvalidRestreamKey::Length),
+            (&*"a".repeat(65), InvalidRestreamKey::Length),
+            ("my stream", InvalidRestreamKey::Charset),
+            ("app/stream", InvalidRestreamKey::Charset),
+            ("стрим", InvalidRestreamKey::Charset),
+            ("pull_abc", InvalidRestreamKey::ReservedPrefix("pull_")),
+            ("Pull_abc", InvalidRestreamKey::ReservedPrefix("pull_")),
+        ] {
+            assert_eq!(RestreamKey::validate(key), Err(*err), "{}", key);
+        }
+    }
+
+    #[test]
+    fn validates_on_deserialization() {
+        let key: RestreamKey = serde_json::from_str(r#""my-stream""#).unwrap();
+        assert_eq!(&*key, "my-stream");
+
+        for val in &[r#""""#, r#""my stream""#, r#""pull_abc""#] {
+            assert!(
+                serde_json::from_str::<RestreamKey>(val).is_err(),
+                "{}",
+                val,
+            );
+        }
+    }
+
+    #[test]
+    fn deserializes_invalid_persisted_keys() {
+        let key =
+            RestreamKey::deserialize_persisted(json!("my stream")).unwrap();
+        assert_eq!(&*key, "my stream");
+
+        assert!(RestreamKey::deserialize_persisted(json!("")).is_err());
+    }
+}
+
+#[cfg(test)]
+mod restream_group_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{RestreamGroup, State};
+
+    fn add(state: &State, key: &str, group: Option<&str>) {
+        let dst = format!("rtmp://127.0.0.1/{}/out", key);
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": key,
+                    "group": group,
+                    "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                    "outputs": [{"dst": dst}],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+    }
+
+    fn group(name: &str) -> RestreamGroup {
+        RestreamGroup::new(name).unwrap()
+    }
+
+    #[test]
+    fn validates_names() {
+        assert!(RestreamGroup::new("Match day / Court 1").is_some());
+        assert!(RestreamGroup::new("Финал").is_some());
+        assert!(RestreamGroup::new("a".repeat(100)).is_some());
+
+        assert!(RestreamGroup::new("").is_none());
+        assert!(RestreamGroup::new("  ").is_none());
+        assert!(RestreamGroup::new("a".repeat(101)).is_none());
+        assert!(RestreamGroup::new("line\nbreak").is_none());
+    }
+
+    #[test]
+    fn toggles_outputs_of_group_only() {
+        let state = State::new_in_memory();
+        add(&state, "a", Some("event"));
+        add(&state, "b", Some("event"));
+        add(&state, "c", None);
+
+        assert_eq!(state.enable_group_outputs(&group("event")), Some(true));
+        assert_eq!(state.enable_group_outputs(&group("event")), Some(false));
+        assert_eq!(state.enable_group_outputs(&group("unknown")), None);
+
+        let enabled: Vec<_> = state
+            .restreams
+            .lock_ref()
+            .iter()
+            .map(|r| r.outputs.iter().all(|o| o.enabled))
+            .collect();
+        assert_eq!(enabled, vec![true, true, false]);
+
+        assert_eq!(state.disable_group_outputs(&group("event")), Some(true));
+    }
+
+    #[test]
+    fn renames_all_members() {
+        let state = State::new_in_memory();
+        add(&state, "a", Some("old"));
+        add(&state, "b", Some("old"));
+        add(&state, "c", Some("other"));
+
+        assert_eq!(
+            state.rename_restream_group(&group("old"), group("new")),
+            Some(true),
+        );
+        assert_eq!(
+            state.rename_restream_group(&group("old"), group("new")),
+            None,
+        );
+        assert_eq!(
+            state.rename_restream_group(&group("new"), group("new")),
+            Some(false),
+        );
+
+        let groups: Vec<_> = state
+            .restreams
+            .lock_ref()
+            .iter()
+            .map(|r| r.group.as_ref().map(|g| g.to_string()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                Some("new".to_owned()),
+                Some("new".to_owned()),
+                Some("other".to_owned()),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod public_host_spec {
+    use std::net::IpAddr;
+
+    use url::Url;
+
+    use super::PublicHost;
+
+    #[test]
+    fn accepts_bare_hosts() {
+        for host in &["example.com", "vpn.local", "10.0.0.1:1935", "[::1]:1935"]
+        {
+            assert!(PublicHost::new(*host).is_some(), "{}", host);
+        }
+    }
+
+    #[test]
+    fn rejects_non_bare_hosts() {
+        for host in &[
+            "",
+            "my host",
+            "rtmp://example.com",
+            "example.com/live",
+            "user@example.com",
+            "example.com?a=b",
+            "example.com:99999",
+        ] {
+            assert!(PublicHost::new(*host).is_none(), "{}", host);
+        }
+    }
+
+    #[test]
+    fn brackets_ipv6_literals() {
+        for (host, expected) in &[
+            ("2001:db8::1", "[2001:db8::1]"),
+            ("::1", "[::1]"),
+            ("[2001:db8::1]", "[2001:db8::1]"),
+            ("[2001:db8::1]:1935", "[2001:db8::1]:1935"),
+        ] {
+            assert_eq!(
+                PublicHost::new(*host).map(Into::<String>::into).as_deref(),
+                Some(*expected),
+                "{}",
+                host,
+            );
+        }
+
+        let ip = "2001:db8::1".parse::<IpAddr>().unwrap();
+        assert_eq!(*PublicHost::from(ip), "[2001:db8::1]");
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        assert_eq!(*PublicHost::from(ip), "10.0.0.1");
+    }
+
+    #[test]
+    fn generates_valid_urls_with_ipv6_host() {
+        let host = PublicHost::from("2001:db8::1".parse::<IpAddr>().unwrap());
+        let url = format!("rtmp://{}:1935/app/in", host);
+
+        assert_eq!(url, "rtmp://[2001:db8::1]:1935/app/in");
+        let url = Url::parse(&url).unwrap();
+        assert_eq!(url.host_str(), Some("[2001:db8::1]"));
+        assert_eq!(url.port(), Some(1935));
+    }
+}
+
+#[cfg(test)]
+mod output_metadata_spec {
+    use super::OutputMetadata;
+
+    #[test]
+    fn omits_blank_values() {
+        let meta = OutputMetadata::new(
+            Some(" My Show ".into()),
+            Some("   ".into()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(meta.title.as_deref(), Some("My Show"));
+        assert_eq!(meta.author, None);
+        assert_eq!(meta.entries().collect::<Vec<_>>(), [("title", "My Show")]);
+
+        assert!(OutputMetadata::new(Some(String::new()), None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn keeps_special_characters_as_is() {
+        let val = "Tom & \"Jerry\"; $(rm -rf /) = 'live'";
+        let meta = OutputMetadata::new(None, None, Some(val.into())).unwrap();
+        assert_eq!(meta.entries().collect::<Vec<_>>(), [("comment", val)]);
+    }
+
+    #[test]
+    fn names_offending_field() {
+        let err = OutputMetadata::new(
+            Some("My Show".into()),
+            Some("me\r\nX-Injected: 1".into()),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.field(), "author");
+
+        let err =
+            OutputMetadata::new(None, None, Some("a".repeat(257))).unwrap_err();
+        assert_eq!(err.field(), "comment");
+    }
+}
+
+#[cfg(test)]
+mod time_zone_spec {
+    use chrono::NaiveDateTime;
+
+    use super::{DateTime, LocalTimeError, TimeZone};
+
+    #[test]
+    fn validates_format() {
+        for name in
+            &["UTC", "Europe/Kyiv", "America/Port-au-Prince", "Etc/GMT+3"]
+        {
+            assert!(TimeZone::new(*name).is_some(), "{}", name);
+        }
+        for name in
+            &["", "/etc/passwd", "Europe/../../etc", "Europe//Kyiv", "+3"]
+        {
+            assert!(TimeZone::new(*name).is_none(), "{}", name);
+        }
+    }
+
+    fn local(val: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(val, "%Y-%m-%dT%H:%M").unwrap()
+    }
+
+    #[test]
+    fn knows_database_zones_only() {
+        for name in &["UTC", "Europe/Kiev", "America/New_York"] {
+            assert!(TimeZone::new(*name).unwrap().is_known(), "{}", name);
+        }
+        assert!(!TimeZone::new("Mars/Olympus_Mons").unwrap().is_known());
+        assert!(!TimeZone::new("zone.tab").unwrap().is_known());
+    }
+
+    #[test]
+    fn converts_local_time_to_utc() {
+        let tz = TimeZone::new("Europe/Kiev").unwrap();
+
+        assert_eq!(
+            tz.to_utc(local("2021-03-28T01:30")),
+            Ok(DateTime::parse("2021-03-27T23:30:00Z").unwrap()),
+        );
+        assert_eq!(
+            tz.to_utc(local("2021-07-01T12:00")),
+            Ok(DateTime::parse("2021-07-01T09:00:00Z").unwrap()),
+        );
+        assert_eq!(
+            TimeZone::new("Mars/Olympus_Mons")
+                .unwrap()
+                .to_utc(local("2021-07-01T12:00")),
+            Err(LocalTimeError::UnknownTimeZone),
+        );
+    }
+
+    #[test]
+    fn follows_dst_in_utc_offset() {
+        let tz = TimeZone::new("Europe/Kiev").unwrap();
+        let at = |val| DateTime::parse(val).unwrap().into();
+
+        assert_eq!(tz.utc_offset(at("2021-01-01T12:00:00Z")), Some(2 * 3600));
+        assert_eq!(tz.utc_offset(at("2021-07-01T12:00:00Z")), Some(3 * 3600));
+    }
+
+    #[test]
+    fn rejects_spring_forward_gap() {
+        let tz = TimeZone::new("Europe/Kiev").unwrap();
+
+        // Clocks jump from 03:00 to 04:00.
+        assert_eq!(
+            tz.to_utc(local("2021-03-28T03:30")),
+            Err(LocalTimeError::Skipped),
+        );
+        assert_eq!(LocalTimeError::Skipped.code(), "NONEXISTENT_LOCAL_TIME");
+    }
+
+    #[test]
+    fn rejects_fall_back_overlap() {
+        let tz = TimeZone::new("America/New_York").unwrap();
+
+        // Clocks fall back from 02:00 to 01:00.
+        assert_eq!(
+            tz.to_utc(local("2021-11-07T01:30")),
+            Err(LocalTimeError::Ambiguous),
+        );
+        assert_eq!(LocalTimeError::Ambiguous.code(), "AMBIGUOUS_LOCAL_TIME");
+    }
+}
+
+#[cfg(test)]
+mod label_spec {
+    use crate::text::Violation;
+
+    use super::Label;
+
+    #[test]
+    fn reports_violations() {
+        assert!(Label::new("Main stage").is_ok());
+        assert_eq!(
+            Label::new("Main\nstage"),
+            Err(Violation::ControlChars("label")),
+        );
+        assert!(Label::new("Main\u{85}stage").is_err());
+        assert_eq!(
+            Label::new("a".repeat(Label::MAX_LEN + 1)),
+            Err(Violation::TooLong("label", Label::MAX_LEN)),
+        );
+        assert_eq!(Label::new(""), Err(Violation::Empty("label")));
+        assert_eq!(
+            Label::new("Main, stage"),
+            Err(Violation::ForbiddenChar("label", ',')),
+        );
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        assert_eq!(&*Label::new("Cafe\u{301}").unwrap(), "Café");
+    }
+}
+
+#[cfg(test)]
+mod output_limits_spec {
+    use super::OutputLimits;
+
+    #[test]
+    fn accepts_valid_values() {
+        let limits = OutputLimits::new(Some(10), Some(150)).unwrap();
+        assert_eq!(limits.nice(), Some(10));
+        assert_eq!(limits.cpu_quota(), Some(150));
+
+        assert!(OutputLimits::new(Some(0), None).is_some());
+        assert!(OutputLimits::new(None, Some(1)).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(OutputLimits::new(None, None).is_none());
+        assert!(OutputLimits::new(Some(-5), None).is_none());
+        assert!(OutputLimits::new(Some(20), None).is_none());
+        assert!(OutputLimits::new(Some(10), Some(0)).is_none());
+        let json = r#"{"nice": 42}"#;
+        assert!(serde_json::from_str::<OutputLimits>(json).is_err());
+    }
+}
+
+#[cfg(test)]
+mod output_constraints_spec {
+    use super::{OutputConditioningPreset, OutputConstraints};
+
+    #[test]
+    fn falls_back_to_preset() {
+        let constraints = OutputConstraints::new(
+            Some(OutputConditioningPreset::Twitch),
+            vec![],
+            vec!["AAC".into(), "MP3".into()],
+            None,
+            Some(1280),
+            None,
+            true,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(constraints.video_codecs(), ["h264"]);
+        assert_eq!(constraints.audio_codecs(), ["aac", "mp3"]);
+        assert_eq!(constraints.max_audio_channels(), Some(2));
+        assert_eq!(constraints.max_resolution(), (Some(1280), Some(1080)));
+        assert!(constraints.enforce());
+    }
+
+    #[test]
+    fn normalizes_codecs() {
+        let constraints = OutputConstraints::new(
+            None,
+            vec![" H.265".into(), "hevc".into(), "AVC".into()],
+            vec![],
+            None,
+            None,
+            None,
+            false,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(constraints.video_codecs(), ["h264", "hevc"]);
+        assert!(constraints.audio_codecs().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let new = |codecs: Vec<String>, channels| {
+            OutputConstraints::new(
+                None,
+                codecs,
+                vec![],
+                channels,
+                None,
+                None,
+                false,
+            )
+        };
+        assert_eq!(new(vec![], None), Ok(None));
+        assert!(new(vec!["h 264".into()], None).is_err());
+        assert!(new(vec![], Some(0)).is_err());
+        let json = r#"{"enforce": true}"#;
+        assert!(serde_json::from_str::<OutputConstraints>(json).is_err());
+    }
+}
+
+#[cfg(test)]
+mod loudness_target_spec {
+    use super::LoudnessTarget;
+
+    #[test]
+    fn accepts_supported_values() {
+        for (i, tp) in
+            &[(-23.0, -1.0), (-14.0, 0.0), (-70.0, -9.0), (-5.0, -1.5)]
+        {
+            assert!(LoudnessTarget::new(*i, *tp).is_some(), "{} {}", i, tp);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_values() {
+        for (i, tp) in &[
+            (-71.0, -1.0),
+            (-4.0, -1.0),
+            (-23.0, 1.0),
+            (-23.0, -10.0),
+            (f64::NAN, -1.0),
+            (-23.0, f64::INFINITY),
+        ] {
+            assert!(LoudnessTarget::new(*i, *tp).is_none(), "{} {}", i, tp);
+        }
+    }
+
+    #[test]
+    fn deserializes_with_default_true_peak() {
+        let target: LoudnessTarget =
+            serde_json::from_str(r#"{"integrated":-23}"#).unwrap();
+        assert_eq!(target, LoudnessTarget::new(-23.0, -1.0).unwrap());
+
+        assert!(serde_json::from_str::<LoudnessTarget>(
+            r#"{"integrated":0,"true_peak":-1}"#
+        )
+        .is_err());
+    }
+}
+
+#[cfg(test)]
+mod duplicate_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{Duplicate, Rejected, State};
+
+    fn restream(key: &str) -> spec::v1::Restream {
+        serde_json::from_value(json!({
+            "key": key,
+            "label": "Main stage",
+            "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+            "outputs": [{
+                "dst": "rtmp://example.com/live/key",
+                "label": "YouTube",
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn points_to_conflicting_restream() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+        let existing = state.restreams.lock_ref()[0].id;
+
+        match state.add_restream(restream("MAIN")).unwrap_err() {
+            Rejected::Duplicate(Duplicate::Restream { id, label, .. }) => {
+                assert_eq!(id, existing);
+                assert_eq!(label.unwrap().to_string(), "Main stage");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        state.add_restream(restream("other")).unwrap();
+        let other = state.restreams.lock_ref()[1].id;
+        match state.edit_restream(other, restream("main")).unwrap_err() {
+            Duplicate::Restream { id, .. } => assert_eq!(id, existing),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn points_to_conflicting_output() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+        let (restream_id, existing) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
+
+        let output = serde_json::from_value::<spec::v1::Output>(json!({
+            "dst": "rtmp://example.com/live/key",
+        }))
+        .unwrap();
+        match state.add_output(restream_id, output).unwrap_err() {
+            Rejected::Duplicate(Duplicate::Output { id, label, .. }) => {
+                assert_eq!(id, existing);
+                assert_eq!(label.unwrap().to_string(), "YouTube");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod output_dst_health_spec {
+    use std::time::Instant;
+
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{Output, OutputDestination, OutputDstHealth};
+
+    fn fail(health: &mut OutputDstHealth, times: usize, now: Instant) {
+        for _ in 0..times {
+            health.record(health.active, false);
+            let _ = health.decide(now);
+        }
+    }
+
+    #[test]
+    fn switches_to_backup_on_repeated_failures() {
+        let mut health = OutputDstHealth::default();
+        let now = Instant::now();
+
+        fail(&mut health, 2, now);
+        assert_eq!(health.active, OutputDestination::Primary);
+
+        fail(&mut health, 1, now);
+        assert_eq!(health.active, OutputDestination::Backup);
+        assert_eq!(health.primary, 25);
+        assert_eq!(health.backup, OutputDstHealth::MAX_SCORE);
+    }
+
+    #[test]
+    fn switches_back_with_hysteresis() {
+        let mut health = OutputDstHealth::default();
+        let start = Instant::now();
+        fail(&mut health, 3, start);
+        assert_eq!(health.active, OutputDestination::Backup);
+
+        // Recovered, but not dwelled on backup enough yet.
+        for _ in 0..7 {
+            health.recover();
+        }
+        assert_eq!(health.primary, 95);
+        assert!(!health.decide(start));
+        assert_eq!(health.active, OutputDestination::Backup);
+
+        assert!(health.decide(start + OutputDstHealth::MIN_DWELL));
+        assert_eq!(health.active, OutputDestination::Primary);
+    }
+
+    #[test]
+    fn returns_to_healthier_primary_if_backup_fails() {
+        let mut health = OutputDstHealth::default();
+        let now = Instant::now();
+        fail(&mut health, 3, now);
+        health.recover();
+        health.recover();
+
+        fail(&mut health, 3, now);
+        assert_eq!(health.active, OutputDestination::Primary);
+    }
+
+    #[test]
+    fn manual_override_disables_switching() {
+        let mut health = OutputDstHealth {
+            overridden: Some(OutputDestination::Backup),
+            ..OutputDstHealth::default()
+        };
+        let now = Instant::now();
+        assert!(health.decide(now));
+        assert_eq!(health.active, OutputDestination::Backup);
+
+        fail(&mut health, 4, now);
+        assert_eq!(health.backup, 0);
+        assert_eq!(health.active, OutputDestination::Backup);
+    }
+
+    #[test]
+    fn output_re_streams_to_active_dst() {
+        let mut output = Output::new(
+            serde_json::from_value::<spec::v1::Output>(json!({
+                "dst": "rtmp://primary.example.com/live/key",
+                "backup_dst": "rtmp://backup.example.com/live/key",
+            }))
+            .unwrap(),
+        );
+        assert_eq!(output.active_dst(), &output.dst);
+
+        output.dst_health.active = OutputDestination::Backup;
+        assert_eq!(output.active_dst_kind(), OutputDestination::Backup);
+        assert_eq!(output.active_dst(), output.backup_dst.as_ref().unwrap());
+
+        output.backup_dst = None;
+        assert_eq!(output.active_dst_kind(), OutputDestination::Primary);
+        assert_eq!(output.active_dst(), &output.dst);
+    }
+}
+
+#[cfg(test)]
+mod quota_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{Quota, QuotaExceeded, Rejected, RestreamKey, State};
+
+    fn restream(key: &str, outputs: usize) -> serde_json::Value {
+        json!({
+            "key": key,
+            "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+            "outputs": (0..outputs)
+                .map(|n| json!({"dst": format!("rtmp://example.com/{}", n)}))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn restream_spec(key: &str, outputs: usize) -> spec::v1::Restream {
+        serde_json::from_value(restream(key, outputs)).unwrap()
+    }
+
+    fn output_spec(n: usize) -> spec::v1::Output {
+        serde_json::from_value(
+            json!({"dst": format!("rtmp://example.com/{}", n)}),
+        )
+        .unwrap()
+    }
+
+    fn state(max_restreams: u32, max_outputs: u32) -> State {
+        let state = State::new_in_memory();
+        state.quota.set(Quota {
+            max_restreams: Some(max_restreams),
+            max_outputs_per_restream: Some(max_outputs),
+        });
+        state
+    }
+
+    fn parse(restreams: Vec<serde_json::Value>) -> spec::v1::Spec {
+        serde_json::from_value::<spec::Spec>(json!({
+            "version": "v1",
+            "restreams": restreams,
+        }))
+        .unwrap()
+        .into_v1()
+    }
+
+    #[test]
+    fn rejects_additions_over_limits() {
+        let state = state(1, 2);
+        let key = RestreamKey::new("main").unwrap();
+
+        match state.add_restream(restream_spec("main", 3)).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(
+                e,
+                QuotaExceeded::Outputs {
+                    key,
+                    limit: 2,
+                    excess: 1,
+                },
+            ),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(state.restreams.lock_ref().is_empty());
+
+        state.add_restream(restream_spec("main", 2)).unwrap();
+        let id = state.restreams.lock_ref()[0].id;
+
+        match state.add_restream(restream_spec("second", 0)).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(
+                e,
+                QuotaExceeded::Restreams {
+                    limit: 1,
+                    excess: 1,
+                },
+            ),
+            e => panic!("unexpected error: {}", e),
+        }
+        match state.add_output(id, output_spec(3)).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(e.excess(), 1),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.restreams.lock_ref().len(), 1);
+        assert_eq!(state.restreams.lock_ref()[0].outputs.len(), 2);
+        assert_eq!(state.check_quota(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_restoring_over_limits() {
+        let state = state(1, 2);
+        state.add_restream(restream_spec("main", 1)).unwrap();
+        let id = state.restreams.lock_ref()[0].id;
+        let _ = state.remove_restream(id, true).unwrap();
+        state.add_restream(restream_spec("second", 1)).unwrap();
+
+        match state.restore_restream(id).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(e.limit(), 1),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.trash.lock_ref().len(), 1);
+    }
+
+    #[test]
+    fn rejects_applying_over_limits() {
+        let state = state(2, 2);
+        state
+            .apply_checked(parse(vec![restream("main", 1)]), true)
+            .unwrap();
+        let id = state.restreams.lock_ref()[0].id;
+
+        let merged = parse(vec![restream("second", 1), restream("third", 1)]);
+        match state.apply_checked(merged.clone(), false).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(
+                e,
+                QuotaExceeded::Restreams {
+                    limit: 2,
+                    excess: 1,
+                },
+            ),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.restreams.lock_ref().len(), 1);
+
+        let single = parse(vec![restream("main", 5)]).restreams.remove(0);
+        match state.apply_restream_checked(id, single, true).unwrap_err() {
+            Rejected::Quota(e) => assert_eq!(
+                e,
+                QuotaExceeded::Outputs {
+                    key: RestreamKey::new("main").unwrap(),
+                    limit: 2,
+                    excess: 3,
+                },
+            ),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.restreams.lock_ref()[0].outputs.len(), 1);
+
+        state.apply_checked(merged, true).unwrap();
+        assert_eq!(state.restreams.lock_ref().len(), 2);
+    }
+
+    #[test]
+    fn fails_reload_over_limits() {
+        let state = state(1, 2);
+        let reloaded = state.reload(
+            parse(vec![restream("main", 1), restream("second", 1)]),
+            true,
+        );
+
+        assert!(reloaded.error.is_some());
+        assert!(state.restreams.lock_ref().is_empty());
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let state = State::new_in_memory();
+        let many = (0..10).map(|n| restream(&format!("r{}", n), 10)).collect();
+
+        assert!(state.quota.get().is_unlimited());
+        state.apply_checked(parse(many), true).unwrap();
+        assert_eq!(state.restreams.lock_ref().len(), 10);
+    }
+}
+
+#[cfg(test)]
+mod media_root_spec {
+    use std::{env, fs};
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::spec;
+
+    use super::{InputSrc, Rejected, State};
+
+    fn parse(file: &str) -> spec::v1::Spec {
+        serde_json::from_value::<spec::Spec>(json!({
+            "version": "v1",
+            "restreams": [{
+                "key": "main",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "src": {"file": {"path": file}},
+                },
+            }],
+        }))
+        .unwrap()
+        .into_v1()
+    }
+
+    #[test]
+    fn confines_applied_spec() {
+        let root =
+            env::temp_dir().join(format!("ephyr-media-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let state = State::new_in_memory();
+        state.media_root.set(Some(root.clone()));
+
+        match state
+            .apply_checked(parse("../outside.mp4"), true)
+            .unwrap_err()
+        {
+            Rejected::FileSrc(e) => assert_eq!(e.reason(), "OUTSIDE_ROOT"),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(state.reload(parse("/etc/passwd"), true).error.is_some());
+        assert!(state.restreams.lock_ref().is_empty());
+
+        state.apply_checked(parse("video.mp4"), true).unwrap();
+        match &state.restreams.lock_ref()[0].input.src {
+            Some(InputSrc::File(src)) => assert_eq!(
+                src.path,
+                fs::canonicalize(&root).unwrap().join("video.mp4"),
+            ),
+            src => panic!("unexpected source: {:?}", src),
+        }
+    }
+}
+
+#[cfg(test)]
+mod ids_spec {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::spec;
+
+    use super::{Duplicate, Rejected, State};
+
+    const RESTREAM_ID: &str = "6b4b1e0e-3c36-4a5b-9c5e-0d8f8b1c7a01";
+    const INPUT_ID: &str = "6b4b1e0e-3c36-4a5b-9c5e-0d8f8b1c7a02";
+    const OUTPUT_ID: &str = "6b4b1e0e-3c36-4a5b-9c5e-0d8f8b1c7a03";
+
+    fn spec(restream_id: &str) -> serde_json::Value {
+        json!({
+            "version": "v1",
+            "restreams": [{
+                "id": restream_id,
+                "key": "main",
+                "input": {
+                    "id": INPUT_ID,
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                },
+                "outputs": [{
+                    "id": OUTPUT_ID,
+                    "dst": "rtmp://example.com/live/key",
+                }],
+            }],
+        })
+    }
+
+    fn parse(json: serde_json::Value) -> Result<spec::v1::Spec, String> {
+        serde_json::from_value::<spec::Spec>(json)
+            .map(spec::Spec::into_v1)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn provisions_same_ids_on_different_servers() {
+        let (active, standby) =
+            (State::new_in_memory(), State::new_in_memory());
+        active.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+        standby.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+
+        for state in &[active, standby] {
+            let restreams = state.restreams.lock_ref();
+            assert_eq!(restreams[0].id.to_string(), RESTREAM_ID);
+            assert_eq!(restreams[0].input.id.to_string(), INPUT_ID);
+            assert_eq!(restreams[0].outputs[0].id.to_string(), OUTPUT_ID);
+        }
+    }
+
+    #[test]
+    fn round_trips_ids_via_export() {
+        let state = State::new_in_memory();
+        state.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+
+        let exported = serde_json::to_value(&state.export()).unwrap();
+        assert_eq!(exported["restreams"][0]["id"], RESTREAM_ID);
+        assert_eq!(exported["restreams"][0]["input"]["id"], INPUT_ID);
+        assert_eq!(exported["restreams"][0]["outputs"][0]["id"], OUTPUT_ID);
+
+        let copy = State::new_in_memory();
+        copy.apply(parse(exported).unwrap(), true);
+        assert_eq!(copy.export().into_v1(), state.export().into_v1());
+    }
+
+    #[test]
+    fn adopts_ids_of_already_existing_entities() {
+        let state = State::new_in_memory();
+        let mut spec_without_ids = spec(RESTREAM_ID);
+        let r = spec_without_ids["restreams"][0].as_object_mut().unwrap();
+        let _ = r.remove("id");
+        let _ = r["input"].as_object_mut().unwrap().remove("id");
+        let _ = r["outputs"][0].as_object_mut().unwrap().remove("id");
+        state.apply(parse(spec_without_ids.clone()).unwrap(), true);
+        assert_ne!(state.restreams.lock_ref()[0].id.to_string(), RESTREAM_ID);
+
+        let reloaded = state.reload(parse(spec_without_ids).unwrap(), false);
+        assert_eq!(reloaded.updated, 0);
+
+        state.apply(parse(spec(RESTREAM_ID)).unwrap(), false);
+        let restreams = state.restreams.lock_ref();
+        assert_eq!(restreams.len(), 1);
+        assert_eq!(restreams[0].id.to_string(), RESTREAM_ID);
+    }
+
+    #[test]
+    fn rejects_invalid_or_duplicate_ids() {
+        let mut json = spec(RESTREAM_ID);
+        json["restreams"][0]["outputs"][0]["id"] = json!("not-a-uuid");
+        assert!(parse(json).is_err());
+
+        let err = parse(spec(OUTPUT_ID)).unwrap_err();
+        assert!(err.contains("Duplicate ID"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_adding_with_used_id() {
+        let state = State::new_in_memory();
+        state.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+        let restream_id = state.restreams.lock_ref()[0].id;
+
+        let restream = serde_json::from_value::<spec::v1::Restream>(json!({
+            "id": RESTREAM_ID,
+            "key": "other",
+            "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+        }))
+        .unwrap();
+        match state.add_restream(restream).unwrap_err() {
+            Rejected::Duplicate(Duplicate::Id { id }) => {
+                assert_eq!(id.to_string(), RESTREAM_ID)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let output = serde_json::from_value::<spec::v1::Output>(json!({
+            "id": INPUT_ID,
+            "dst": "rtmp://example.com/live/other",
+        }))
+        .unwrap();
+        match state.add_output(restream_id, output).unwrap_err() {
+            Rejected::Duplicate(Duplicate::Id { id }) => {
+                assert_eq!(id.to_string(), INPUT_ID)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let id = Uuid::new_v4();
+        let output = serde_json::from_value::<spec::v1::Output>(json!({
+            "id": id,
+            "dst": "rtmp://example.com/live/other",
+        }))
+        .unwrap();
+        assert_eq!(state.add_output(restream_id, output).unwrap(), Some(()));
+        assert_eq!(
+            state.restreams.lock_ref()[0].outputs[1].id,
+            super::OutputId::from(id),
+        );
+    }
+
+    #[test]
+    fn rejects_importing_used_ids() {
+        let state = State::new_in_memory();
+        state.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+        let before = state.restreams.get_cloned();
+
+        let mut other = spec(RESTREAM_ID);
+        let r = other["restreams"][0].as_object_mut().unwrap();
+        let _ = r.remove("id");
+        let _ = r["input"].as_object_mut().unwrap().remove("id");
+        r["key"] = json!("other");
+        r["outputs"][0]["dst"] = json!("rtmp://example.com/live/other");
+        match state
+            .apply_checked(parse(other).unwrap(), false)
+            .unwrap_err()
+        {
+            Rejected::Duplicate(Duplicate::Id { id }) => {
+                assert_eq!(id.to_string(), OUTPUT_ID)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.restreams.get_cloned(), before);
+    }
+
+    #[test]
+    fn rejects_editing_to_used_ids() {
+        let state = State::new_in_memory();
+        state.apply(parse(spec(RESTREAM_ID)).unwrap(), true);
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "other",
+                    "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                    "outputs": [{"dst": "rtmp://example.com/live/other"}],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let (other_id, output_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[1].id, restreams[1].outputs[0].id)
+        };
+
+        let edited = serde_json::from_value::<spec::v1::Restream>(json!({
+            "id": RESTREAM_ID,
+            "key": "other",
+            "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+        }))
+        .unwrap();
+        assert!(matches!(
+            state.edit_restream(other_id, edited),
+            Err(Duplicate::Id { .. }),
+        ));
+
+        let edited = serde_json::from_value::<spec::v1::Output>(json!({
+            "id": INPUT_ID,
+            "dst": "rtmp://example.com/live/other",
+        }))
+        .unwrap();
+        assert!(matches!(
+            state.edit_output(other_id, output_id, edited),
+            Err(Duplicate::Id { .. }),
+        ));
+
+        let restreams = state.restreams.lock_ref();
+        assert_eq!(restreams[1].id, other_id);
+        assert_eq!(restreams[1].outputs[0].id, output_id);
+    }
+}
+
+#[cfg(test)]
+mod dst_conflicts_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{Duplicate, Rejected, State};
+
+    fn restream(key: &str) -> spec::v1::Restream {
+        serde_json::from_value(json!({
+            "key": key,
+            "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+        }))
+        .unwrap()
+    }
+
+    fn output(dst: &str) -> spec::v1::Output {
+        serde_json::from_value(json!({"dst": dst})).unwrap()
+    }
+
+    #[test]
+    fn reports_outputs_of_other_restreams() {
+        let state = State::new_in_memory();
+        state.add_restream(restream("main")).unwrap();
+        state.add_restream(restream("backup")).unwrap();
+        let (main, backup) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[1].id)
+        };
+
+        state
+            .add_output(main, output("rtmp://a.rtmp.youtube.com/live2/key"))
+            .unwrap();
+        state
+            .add_output(
+                backup,
+                output("rtmp://A.rtmp.youtube.com:1935/live2/key/"),
+            )
+            .unwrap();
+        state
+            .add_output(backup, output("rtmp://example.com/live/key"))
+            .unwrap();
+
+        {
+            let restreams = state.restreams.lock_ref();
+            let conflicts = &restreams[0].outputs[0].conflicts_with;
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].restream_id, backup);
+            assert_eq!(conflicts[0].output_id, restreams[1].outputs[0].id);
+            assert_eq!(restreams[1].outputs[0].conflicts_with.len(), 1);
+            assert!(restreams[1].outputs[1].conflicts_with.is_empty());
+        }
+
+        let conflicts = state.dst_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].outputs.len(), 2);
+
+        state.remove_restream(backup, false).unwrap();
+        state.refresh_dst_conflicts();
+        assert!(state.restreams.lock_ref()[0].outputs[0]
+            .conflicts_with
+            .is_empty());
+        assert!(state.dst_conflicts().is_empty());
+    }
+
+    #[test]
+    fn rejects_conflicts_when_configured() {
+        let state = State::new_in_memory();
+        state.settings.lock_mut().reject_dst_conflicts = true;
+        state.add_restream(restream("main")).unwrap();
+        state.add_restream(restream("backup")).unwrap();
+        let (main, backup) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[1].id)
+        };
+        let dst = "rtmp://example.com/live/key";
+
+        state.add_output(main, output(dst)).unwrap();
+        let existing = state.restreams.lock_ref()[0].outputs[0].id;
+
+        match state.add_output(backup, output(dst)).unwrap_err() {
+            Rejected::Duplicate(Duplicate::ForeignOutput {
+                restream_id,
+                id,
+                ..
+            }) => {
+                assert_eq!(restream_id, main);
+                assert_eq!(id, existing);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(state.restreams.lock_ref()[1].outputs.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod awaiting_input_spec {
+    use serde_json::json;
+
+    use super::{State, Status};
+
+    fn awaiting(state: &State) -> Vec<bool> {
+        let ids: Vec<_> = state.restreams.lock_ref()[0]
+            .outputs
+            .iter()
+            .map(|o| o.id)
+            .collect();
+        ids.into_iter()
+            .map(|id| state.is_awaiting_input(id))
+            .collect()
+    }
+
+    #[test]
+    fn follows_input_status() {
+        let state = State::with_restream(
+            "main",
+            json!({"outputs": [
+                {"dst": "rtmp://a/live", "enabled": true},
+                {"dst": "rtmp://b/live"},
+            ]}),
+        );
+        assert_eq!(awaiting(&state), [true, false]);
+
+        let (endpoint, output) = {
+            let restreams = state.restreams.lock_ref();
+            (
+                restreams[0].input.endpoints[0].id,
+                restreams[0].outputs[0].id,
+            )
+        };
+
+        let _ = state.statuses.set(endpoint, Status::Online);
+        assert_eq!(awaiting(&state), [false, false]);
+
+        {
+            let mut statuses = state.statuses.lock_mut("follows_input_status");
+            let _ = statuses.set(endpoint, Status::Offline);
+            let _ = statuses.set(output, Status::Online);
+        }
+        // Not idling until its process is stopped.
+        assert_eq!(awaiting(&state), [false, false]);
+
+        // Restarting process idles anyway, as won't go online without input.
+        let _ = state.statuses.set(output, Status::Initializing);
+        assert_eq!(awaiting(&state), [true, false]);
+
+        let _ = state.statuses.set(output, Status::Offline);
+        assert_eq!(awaiting(&state), [true, false]);
+
+        state.restreams.lock_mut()[0].input.enabled = false;
+        assert_eq!(awaiting(&state), [false, false]);
+    }
+}
+
+#[cfg(test)]
+mod trash_spec {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{Duplicate, Rejected, RestreamId, State};
+
+    fn add(state: &State, key: &str) -> RestreamId {
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": key,
+                    "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.key == *key)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn removes_into_trash_and_restores() {
+        let state = State::new_in_memory();
+        let id = add(&state, "main");
+
+        assert_eq!(state.remove_restream(id, false).unwrap(), Some(()));
+        assert!(state.restreams.lock_ref().is_empty());
+        assert_eq!(state.trash.lock_ref()[0].id, id);
+        assert!(state.trash.lock_ref()[0].deleted_at.is_some());
+
+        assert_eq!(state.restore_restream(id).unwrap(), Some(()));
+        assert!(state.trash.lock_ref().is_empty());
+        assert_eq!(state.restreams.lock_ref()[0].id, id);
+        assert!(state.restreams.lock_ref()[0].deleted_at.is_none());
+
+        assert_eq!(state.restore_restream(id).unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_trash_on_duplicates_check() {
+        let state = State::new_in_memory();
+        let old = add(&state, "main");
+        state.remove_restream(old, false).unwrap();
+
+        let new = add(&state, "main");
+        match state.restore_restream(old).unwrap_err() {
+            Rejected::Duplicate(Duplicate::Restream { id, .. }) => {
+                assert_eq!(id, new)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(state.trash.lock_ref().len(), 1);
+    }
+
+    #[test]
+    fn purges_from_trash() {
+        let state = State::new_in_memory();
+        let (first, second) = (add(&state, "first"), add(&state, "second"));
+        state.remove_restream(first, false).unwrap();
+        state.remove_restream(second, false).unwrap();
+
+        assert_eq!(state.purge_restream(first), Some(()));
+        assert_eq!(state.purge_restream(first), None);
+        assert_eq!(state.trash.lock_ref().len(), 1);
+
+        assert_eq!(state.purge_expired_trash(Duration::from_secs(60)), 0);
+        assert_eq!(state.purge_expired_trash(Duration::from_secs(0)), 1);
+        assert!(state.trash.lock_ref().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod chained_restreams_spec {
+    use serde_json::json;
+    use url::Url;
+
+    use crate::spec;
+
+    use super::{InputSrcUrl, Rejected, RestreamId, State};
+
+    fn add(state: &State, key: &str, src: Option<RestreamId>) -> RestreamId {
+        let src = src.map(|id| format!("ephyr://restream/{}", id));
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": key,
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "src": src.map(|url| json!({"remote_url": url})),
+                    },
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.key == *key)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn parses_restream_id() {
+        let id = RestreamId::random();
+        let url = Url::parse(&format!("ephyr://restream/{}", id)).unwrap();
+        assert_eq!(InputSrcUrl::new(url).unwrap().restream_id(), Some(id));
+
+        for url in &[
+            "ephyr://restream/abc",
+            "ephyr://output/x",
+            "ephyr://restream/",
+        ] {
+            assert!(InputSrcUrl::new(Url::parse(url).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let state = State::new_in_memory();
+        let a = add(&state, "a", None);
+        let b = add(&state, "b", Some(a));
+        let c = add(&state, "c", Some(b));
+
+        assert!(state.forms_chain_cycle(a, c));
+        assert!(state.forms_chain_cycle(a, b));
+        assert!(state.forms_chain_cycle(a, a));
+        assert!(!state.forms_chain_cycle(c, a));
+        assert!(!state.forms_chain_cycle(c, b));
+    }
+
+    #[test]
+    fn rejects_cyclic_specs() {
+        let state = State::new_in_memory();
+        let a = add(&state, "a", None);
+        let b = add(&state, "b", Some(a));
+        let spec = || {
+            serde_json::from_value::<spec::v1::Spec>(json!({
+                "restreams": [{
+                    "id": a,
+                    "key": "a",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "src": {
+                            "remote_url": format!("ephyr://restream/{}", b),
+                        },
+                    },
+                }],
+            }))
+            .unwrap()
+        };
+
+        assert!(matches!(
+            state.apply_checked(spec(), false),
+            Err(Rejected::ChainCycle(_)),
+        ));
+        let reload = state.reload(spec(), false);
+        assert!(reload.error.unwrap().contains("cycle"));
+
+        let restreams = state.restreams.lock_ref();
+        assert!(restreams
+            .iter()
+            .find(|r| r.id == a)
+            .unwrap()
+            .input
+            .src
+            .is_none());
+    }
+}
+
+#[cfg(test)]
+mod srs_app_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{InputEndpointKind, State};
+
+    fn pull_spec(url: &str) -> spec::v1::Restream {
+        serde_json::from_value(json!({
+            "key": "relay",
+            "input": {
+                "key": "origin",
+                "endpoints": [{"kind": "rtmp"}],
+                "src": {"remote_url": url},
+                "enabled": true,
+            },
+        }))
+        .unwrap()
+    }
+
+    /// Path of the SRS app and stream the pulled live stream is published to.
+    fn app_path(state: &State) -> String {
+        let restreams = state.restreams.lock_ref();
+        let r = &restreams[0];
+        InputEndpointKind::Rtmp
+            .rtmp_url(r, &r.input.key)
+            .path()
+            .to_owned()
+    }
+
+    #[test]
+    fn keeps_app_on_src_url_edit() {
+        let state = State::new_in_memory();
+        state
+            .add_restream(pull_spec("rtmp://example.com/live/main"))
+            .unwrap();
+        let (id, endpoint_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].input.endpoints[0].id)
+        };
+        assert_eq!(app_path(&state), "/relay/origin");
+
+        let _ = state
+            .edit_restream(id, pull_spec("rtmp://example.org/other/stream"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(app_path(&state), "/relay/origin");
+        assert_eq!(
+            state.restreams.lock_ref()[0].input.endpoints[0].id,
+            endpoint_id,
+        );
+    }
+
+    #[test]
+    fn keeps_app_of_imported_restream() {
+        let old = State::new_in_memory();
+        old.add_restream(pull_spec("rtmp://example.com/live/main"))
+            .unwrap();
+        let exported = old.restreams.lock_ref()[0].export();
+
+        let new = State::new_in_memory();
+        new.add_restream(exported).unwrap();
+
+        assert_eq!(app_path(&new), app_path(&old));
+        assert_eq!(
+            new.restreams.lock_ref()[0].input.id,
+            old.restreams.lock_ref()[0].input.id,
+        );
+    }
+}
+
+#[cfg(test)]
+mod connection_history_spec {
+    use std::{
+        collections::HashSet,
+        iter,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use futures::{FutureExt as _, StreamExt as _};
+    use futures_signals::signal::SignalExt as _;
+
+    use super::{
+        ConnectionEvent, ConnectionEventKind, ConnectionHistory, RestreamId,
+        State,
+    };
+
+    fn event(n: u8) -> ConnectionEvent {
+        ConnectionEvent::new(
+            ConnectionEventKind::Connect,
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, n)),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn evicts_oldest_events() {
+        let history = ConnectionHistory::default();
+        let id = RestreamId::random();
+        for n in 0..60 {
+            history.record(id, event(n));
+        }
+
+        let events = history.recent(id, usize::MAX);
+        assert_eq!(events.len(), ConnectionHistory::MAX_EVENTS);
+        assert_eq!(events[0].client_ip, "10.0.0.59");
+        assert_eq!(events.last().unwrap().client_ip, "10.0.0.10");
+        assert_eq!(history.recent(id, 1).len(), 1);
+    }
+
+    #[test]
+    fn forgets_removed_restreams() {
+        let history = ConnectionHistory::default();
+        let (kept, removed) = (RestreamId::random(), RestreamId::random());
+        history.record(kept, event(1));
+        history.record(removed, event(2));
+
+        history.retain(&iter::once(kept).collect::<HashSet<_>>());
+
+        assert_eq!(history.recent(kept, usize::MAX).len(), 1);
+        assert!(history.recent(removed, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn records_apart_from_restreams() {
+        let state = State::default();
+        let mut changes = state.restreams.signal_ref(|_| ()).to_stream();
+        let _ = changes.next().now_or_never();
+
+        state
+            .connection_history
+            .record(RestreamId::random(), event(1));
+
+        assert!(changes.next().now_or_never().is_none());
+    }
+}
+
+#[cfg(test)]
+mod low_latency_spec {
+    use serde_json::json;
+
+    use crate::{spec, srs};
+
+    use super::{InputEndpointKind, InputKey, Restream, RestreamKey};
+
+    fn restream(key: &str, low_latency: bool) -> Restream {
+        let mut restream = Restream::new(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "live",
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                "low_latency": low_latency,
+            }))
+            .unwrap(),
+        );
+        // Keys persisted by older versions are not validated.
+        restream.key = RestreamKey(key.into());
+        restream
     }
 
-    /// Exports this [`Restream`] as a [`spec::v1::Restream`].
-    #[inline]
-    #[must_use]
-    pub fn export(&self) -> spec::v1::Restream {
-        spec::v1::Restream {
-            key: self.key.clone(),
-            label: self.label.clone(),
-            input: self.input.export(),
-            outputs: self.outputs.iter().map(Output::export).collect(),
-        }
+    #[test]
+    fn serves_on_dedicated_vhost() {
+        let restream = restream("live", true);
+        let input = InputKey::new("origin").unwrap();
+
+        assert_eq!(restream.srs_vhost().as_deref(), Some("live.lowlatency"));
+        assert_eq!(
+            InputEndpointKind::Rtmp.rtmp_url(&restream, &input).as_str(),
+            format!(
+                "rtmp://127.0.0.1:{}/live?vhost=live.lowlatency/origin",
+                srs::rtmp_port(),
+            ),
+        );
+        assert!(InputEndpointKind::Hls
+            .rtmp_url(&restream, &input)
+            .as_str()
+            .contains("?vhost=hls/"));
     }
 
-    /// Returns an URL on a local [SRS] server of the endpoint representing a
-    /// main [`Input`] in this [`Restream`].
-    ///
-    /// # Errors
-    ///
-    /// If not found any RTMP [`Input`] endpoint
-    ///
-    /// [SRS]: https://github.com/ossrs/srs
-    pub fn main_input_rtmp_endpoint_url(&self) -> anyhow::Result<Url> {
-        match self.input.endpoints.iter().find(|e| e.is_rtmp()) {
-            Some(main) => Ok(main.kind.rtmp_url(&self.key, &self.input.key)),
-            None => Err(anyhow!("Not found any RTMP endpoint")),
-        }
+    #[test]
+    fn serves_on_default_vhost_otherwise() {
+        assert_eq!(restream("live", false).srs_vhost(), None);
+        assert_eq!(restream("live;evil", true).srs_vhost(), None);
     }
-}
 
-/// ID of a `Restream`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLScalarValue,
-    Into,
-    PartialEq,
-    Serialize,
-)]
-pub struct RestreamId(Uuid);
+    #[test]
+    fn serves_hls_on_dedicated_vhost() {
+        let mut restream = restream("live", false);
+        restream.hls = true;
+
+        assert_eq!(restream.srs_vhost().as_deref(), Some("live.hls"));
+        assert_eq!(
+            restream.hls_url("example.com:8080").as_deref(),
+            Some("http://example.com:8000/live/live/origin.m3u8"),
+        );
 
-impl RestreamId {
-    /// Generates a new random [`RestreamId`].
-    #[inline]
-    #[must_use]
-    pub fn random() -> Self {
-        Self(Uuid::new_v4())
+        restream.low_latency = true;
+        let vhost = restream.srs_vhost_config().unwrap();
+        assert_eq!(vhost.name, "live.lowlatency");
+        assert!(vhost.low_latency && vhost.hls);
+
+        restream.hls = false;
+        assert_eq!(restream.hls_url("example.com"), None);
+        assert!(!restream.srs_vhost_config().unwrap().hls);
     }
 }
 
-/// Key of a [`Restream`] identifying it, and used to form its endpoints URLs.
-#[derive(
-    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
-)]
-pub struct RestreamKey(String);
+#[cfg(test)]
+mod credentials_spec {
+    use crate::{password, spec};
 
-impl RestreamKey {
-    /// Creates a new [`RestreamKey`] if the given value meets its invariants.
-    #[must_use]
-    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
-        static REGEX: Lazy<Regex> =
-            Lazy::new(|| Regex::new("^[a-z0-9_-]{1,20}$").unwrap());
+    use super::Settings;
 
-        let val = val.into();
-        (!val.is_empty() && REGEX.is_match(&val))
-            .then(|| Self(val.into_owned()))
+    fn settings() -> Settings {
+        Settings {
+            password_hash: Some(password::hash("admin")),
+            viewer_password_hash: Some(password::hash("viewer")),
+            ..Settings::default()
+        }
     }
-}
 
-impl<'de> Deserialize<'de> for RestreamKey {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
-            .ok_or_else(|| D::Error::custom("Not a valid Restream.key"))
+    #[test]
+    fn round_trips_via_export() {
+        let old = settings();
+        let mut new = Settings::default();
+
+        new.apply_credentials(old.export_credentials());
+
+        assert_eq!(new.password_hash, old.password_hash);
+        assert_eq!(new.viewer_password_hash, old.viewer_password_hash);
     }
-}
 
-/// Type of `Restream`'s `key` identifying it, and used to form its endpoints
-/// URLs.
-///
-/// It should meet `[a-z0-9_-]{1,20}` format.
-#[graphql_scalar]
-impl<S> GraphQLScalar for RestreamKey
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+    #[test]
+    fn invalidates_sessions_even_if_unchanged() {
+        let mut settings = settings();
+        let generation = settings.session_generation;
+
+        settings.apply_credentials(settings.export_credentials());
+
+        assert_ne!(settings.session_generation, generation);
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(Self::new)
+    #[test]
+    fn drops_viewer_password_without_admin_one() {
+        let mut settings = settings();
+
+        settings.apply_credentials(spec::v1::Credentials {
+            password_hash: None,
+            viewer_password_hash: Some(password::hash("viewer")),
+        });
+
+        assert_eq!(settings.viewer_password_hash, None);
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn redacts_debug_output() {
+        let creds = settings().export_credentials();
+
+        assert!(!format!("{:?}", creds).contains("argon2"));
     }
 }
 
-impl PartialEq<str> for RestreamKey {
-    #[inline]
-    fn eq(&self, other: &str) -> bool {
-        self.0 == other
+#[cfg(test)]
+mod output_pause_mode_spec {
+    use serde_json::{json, Value};
+
+    use crate::spec;
+
+    use super::{Output, PauseMode};
+
+    fn output(spec: Value) -> Output {
+        Output::new(serde_json::from_value::<spec::v1::Output>(spec).unwrap())
+    }
+
+    #[test]
+    fn pauses_live_when_mixed_via_filter_graph() {
+        let mixins = json!([{"src": "ts://127.0.0.1:3000/dev/audio"}]);
+
+        for spec in vec![
+            json!({"dst": "icecast://127.0.0.1/live", "mixins": mixins}),
+            json!({
+                "dst": "rtmp://127.0.0.1/live/out",
+                "mixins": mixins,
+                "conditioning": {"preset": "youtube"},
+            }),
+            json!({
+                "dst": "rtmp://127.0.0.1/live/out",
+                "mixins": mixins,
+                "overlay": {"path": "/tmp/logo.png"},
+            }),
+        ] {
+            assert_eq!(output(spec).detect_pause_mode(), PauseMode::Live);
+        }
+    }
+
+    #[test]
+    fn pauses_with_restart_when_copying_video() {
+        let mixins = json!([{"src": "ts://127.0.0.1:3000/dev/audio"}]);
+
+        for spec in vec![
+            json!({"dst": "rtmp://127.0.0.1/live/out"}),
+            json!({"dst": "icecast://127.0.0.1/live"}),
+            json!({
+                "dst": "rtmp://127.0.0.1/live/out",
+                "overlay": {"path": "/tmp/logo.png"},
+            }),
+            json!({"dst": "rtmp://127.0.0.1/live/out", "mixins": mixins}),
+        ] {
+            assert_eq!(output(spec).detect_pause_mode(), PauseMode::Restart);
+        }
+    }
+
+    #[test]
+    fn prewarms_only_disabled_live_pausable_outputs() {
+        let mixins = json!([{"src": "ts://127.0.0.1:3000/dev/audio"}]);
+        let spec = |dst: &str, enabled: bool, prewarm: bool| {
+            json!({
+                "dst": dst,
+                "mixins": mixins,
+                "overlay": {"path": "/tmp/logo.png"},
+                "enabled": enabled,
+                "prewarm": prewarm,
+            })
+        };
+
+        let rtmp = "rtmp://127.0.0.1/live/out";
+        assert!(output(spec(rtmp, false, true)).is_prewarmed());
+        assert!(!output(spec(rtmp, false, false)).is_prewarmed());
+        assert!(!output(spec(rtmp, true, true)).is_prewarmed());
+        assert!(!output(spec("file:///rec.flv", false, true)).is_prewarmed());
+        assert!(!output(json!({"dst": rtmp, "prewarm": true})).is_prewarmed());
     }
 }
 
-/// Upstream source that a `Restream` receives a live stream from.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct Input {
-    /// Unique ID of this `Input`.
-    ///
-    /// Once assigned, it never changes.
-    pub id: InputId,
+#[cfg(test)]
+mod output_boot_policy_spec {
+    use serde_json::{json, Value};
 
-    /// Key of this `Input` to expose its `InputEndpoint`s with for accepting
-    /// and serving a live stream.
-    pub key: InputKey,
+    use crate::spec;
 
-    /// Endpoints of this `Input` serving a live stream for `Output`s and
-    /// clients.
-    pub endpoints: Vec<InputEndpoint>,
+    use super::{Output, OutputBootPolicy};
 
-    /// Source to pull a live stream from.
-    ///
-    /// If specified, then this `Input` will pull a live stream from it (pull
-    /// kind), otherwise this `Input` will await a live stream to be pushed
-    /// (push kind).
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub src: Option<InputSrc>,
+    fn output(spec: Value) -> Output {
+        Output::new(serde_json::from_value::<spec::v1::Output>(spec).unwrap())
+    }
 
-    /// Indicator whether this `Input` is enabled, so is allowed to receive a
-    /// live stream from its upstream sources.
-    #[serde(default, skip_serializing_if = "is_false")]
-    pub enabled: bool,
-}
+    #[test]
+    fn resumes_enabled_output_by_default() {
+        let mut o = output(json!({
+            "dst": "rtmp://127.0.0.1/live/out",
+            "enabled": true,
+        }));
+        assert_eq!(o.on_boot, OutputBootPolicy::Resume);
+
+        o.apply_boot_policy();
+        assert!(o.enabled);
+        assert!(o.disabled_reason.is_none());
+    }
 
-impl Input {
-    /// Creates a new [`Input`] out of the given [`spec::v1::Input`].
-    #[must_use]
-    pub fn new(spec: spec::v1::Input) -> Self {
-        Self {
-            id: InputId::random(),
-            key: spec.key,
-            endpoints: spec
-                .endpoints
-                .into_iter()
-                .map(InputEndpoint::new)
-                .collect(),
-            src: spec.src.map(InputSrc::new),
-            enabled: spec.enabled,
-        }
+    #[test]
+    fn disables_enabled_output_staying_off() {
+        let mut o = output(json!({
+            "dst": "rtmp://127.0.0.1/live/out",
+            "enabled": true,
+            "on_boot": "stay_off",
+        }));
+
+        o.apply_boot_policy();
+        assert!(!o.enabled);
+        assert!(o.disabled_reason.is_some());
     }
 
-    /// Applies the given [`spec::v1::Input`] to this [`Input`].
-    pub fn apply(&mut self, new: spec::v1::Input) {
-        if self.key != new.key
-            || !new.enabled
-            || (self.src.is_none() && new.src.is_some())
-            || (self.src.is_some() && new.src.is_none())
-        {
-            // SRS endpoints have changed, disabled, or push/pull type has been
-            // switched, so we should kick the publisher and all the players.
-            for e in &mut self.endpoints {
-                e.srs_publisher_id = None;
-                e.srs_player_ids.clear();
-            }
-        }
+    #[test]
+    fn leaves_disabled_output_untouched() {
+        let mut o = output(json!({
+            "dst": "rtmp://127.0.0.1/live/out",
+            "on_boot": "stay_off",
+        }));
+
+        o.apply_boot_policy();
+        assert!(!o.enabled);
+        assert!(o.disabled_reason.is_none());
+    }
+}
 
-        self.key = new.key;
-        // Temporary omit changing existing `enabled` value to avoid unexpected
-        // breakages of ongoing re-streams.
-        //self.enabled = new.enabled;
+#[cfg(test)]
+mod output_max_bitrate_spec {
+    use serde_json::json;
 
-        let mut olds = mem::replace(
-            &mut self.endpoints,
-            Vec::with_capacity(new.endpoints.len()),
+    use crate::spec;
+
+    use super::{Output, OutputId, State};
+
+    #[test]
+    fn sets_and_removes_cap() {
+        let state = State::with_restream(
+            "partner",
+            json!({"outputs": [{"dst": "rtmp://127.0.0.1/live/out"}]}),
         );
-        for new in new.endpoints {
-            if let Some(mut old) = olds
-                .iter()
-                .enumerate()
-                .find_map(|(n, o)| (o.kind == new.kind).then(|| n))
-                .map(|n| olds.swap_remove(n))
-            {
-                old.apply(new);
-                self.endpoints.push(old);
-            } else {
-                self.endpoints.push(InputEndpoint::new(new));
-            }
-        }
+        let (restream, output) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
+        let cap = || state.restreams.lock_ref()[0].outputs[0].max_bitrate_kbps;
 
-        match (self.src.as_mut(), new.src) {
-            (Some(old), Some(new)) => old.apply(new),
-            (None, Some(new)) => self.src = Some(InputSrc::new(new)),
-            _ => self.src = None,
-        }
+        assert_eq!(
+            state.set_output_max_bitrate(restream, output, Some(5000)),
+            Some(true),
+        );
+        assert_eq!(cap(), Some(5000));
+        assert_eq!(
+            state.set_output_max_bitrate(restream, output, Some(5000)),
+            Some(false),
+        );
+
+        assert_eq!(
+            state.set_output_max_bitrate(restream, output, Some(0)),
+            Some(true),
+        );
+        assert_eq!(cap(), None);
+        assert_eq!(
+            state.set_output_max_bitrate(restream, output, None),
+            Some(false),
+        );
+
+        assert_eq!(
+            state.set_output_max_bitrate(
+                restream,
+                OutputId::random(),
+                Some(5000),
+            ),
+            None,
+        );
     }
 
-    /// Exports this [`Input`] as a [`spec::v1::Input`].
-    #[must_use]
-    pub fn export(&self) -> spec::v1::Input {
-        spec::v1::Input {
-            key: self.key.clone(),
-            endpoints: self
-                .endpoints
-                .iter()
-                .map(InputEndpoint::export)
-                .collect(),
-            src: self.src.as_ref().map(InputSrc::export),
-            enabled: self.enabled,
+    #[test]
+    fn survives_export_without_zero_cap() {
+        let output = |kbps: u32| {
+            Output::new(
+                serde_json::from_value::<spec::v1::Output>(json!({
+                    "dst": "rtmp://127.0.0.1/live/out",
+                    "max_bitrate_kbps": kbps,
+                }))
+                .unwrap(),
+            )
+        };
+
+        assert_eq!(output(5000).export().max_bitrate_kbps, Some(5000));
+        assert_eq!(output(0).max_bitrate_kbps, None);
+    }
+}
+
+#[cfg(test)]
+mod problem_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{InputSrc, ProblemInfo, ProblemKind, State};
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "test",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "src": {"failover_inputs": [{
+                            "key": "main",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "src": {"remote_url": "rtmp://example.com/a/b"},
+                            "enabled": true,
+                        }]},
+                        "enabled": true,
+                    },
+                    "outputs": [{"dst": "rtmp://example.com/live/stream"}],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+    }
+
+    #[test]
+    fn sets_and_clears_output_problem() {
+        let state = state();
+        let id = state.restreams.lock_ref()[0].outputs[0].id;
+        let problem = ProblemInfo::new(ProblemKind::Connect, "Refused");
+
+        assert!(state.set_output_problem(id, problem.clone()));
+        assert_eq!(
+            state.restreams.lock_ref()[0].outputs[0].problem,
+            Some(problem),
+        );
+
+        assert!(state.clear_output_problem(id));
+        assert!(!state.clear_output_problem(id));
+        assert!(state.restreams.lock_ref()[0].outputs[0].problem.is_none());
+    }
+
+    #[test]
+    fn sets_problem_of_input_owning_endpoint() {
+        let state = state();
+        let id = match &state.restreams.lock_ref()[0].input.src {
+            Some(InputSrc::Failover(s)) => s.inputs[0].endpoints[0].id,
+            _ => unreachable!(),
+        };
+
+        assert!(state.set_input_problem(
+            id,
+            ProblemInfo::new(ProblemKind::Connect, "Refused"),
+        ));
+
+        let restreams = state.restreams.lock_ref();
+        assert!(restreams[0].input.problem.is_none());
+        match &restreams[0].input.src {
+            Some(InputSrc::Failover(s)) => {
+                assert!(s.inputs[0].problem.is_some())
+            }
+            _ => unreachable!(),
         }
     }
+}
 
-    /// Enables this [`Input`].
-    ///
-    /// Returns `false` if it has been enabled already.
-    #[must_use]
-    pub fn enable(&mut self) -> bool {
-        let mut changed = !self.enabled;
+#[cfg(test)]
+mod disable_reason_spec {
+    use serde_json::json;
+
+    use super::{DisableReason, DisableReasonKind, InputSrc, State};
+
+    fn state() -> State {
+        State::with_restream(
+            "test",
+            json!({"input": {"src": {"failover_inputs": [{
+                "key": "main",
+                "endpoints": [{"kind": "rtmp"}],
+                "src": {"remote_url": "rtmp://example.com/a/b"},
+                "enabled": true,
+            }]}}}),
+        )
+    }
 
-        self.enabled = true;
+    #[test]
+    fn records_reason_of_disabling_and_clears_on_enabling() {
+        let state = state();
+        let (restream_id, input_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].input.id)
+        };
 
-        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
-            for i in &mut s.inputs {
-                changed |= i.enable();
+        assert_eq!(
+            state.disable_input(
+                input_id,
+                restream_id,
+                DisableReason::manual("REST API"),
+            ),
+            Some(true),
+        );
+        {
+            let restreams = state.restreams.lock_ref();
+            let reason = restreams[0].input.disabled_reason.as_ref().unwrap();
+            assert_eq!(reason.kind, DisableReasonKind::Manual);
+            assert_eq!(reason.actor, "REST API");
+            match &restreams[0].input.src {
+                Some(InputSrc::Failover(s)) => {
+                    assert_eq!(
+                        s.inputs[0].disabled_reason.as_ref(),
+                        Some(reason)
+                    );
+                }
+                _ => unreachable!(),
             }
         }
 
-        changed
+        assert_eq!(state.enable_input(input_id, restream_id), Some(true));
+        assert!(state.restreams.lock_ref()[0]
+            .input
+            .disabled_reason
+            .is_none());
     }
 
-    /// Disables this [`Input`].
-    ///
-    /// Returns `false` if it has been disabled already.
-    #[must_use]
-    pub fn disable(&mut self) -> bool {
-        let mut changed = self.enabled;
+    #[test]
+    fn preserves_original_reason_of_disabled_input() {
+        let state = state();
+        let restream_id = state.restreams.lock_ref()[0].id;
+
+        assert_eq!(
+            state.disable_restream(
+                restream_id,
+                DisableReason::new(DisableReasonKind::Idle, "Idle", "test"),
+            ),
+            Some(true),
+        );
+        assert_eq!(
+            state.disable_restream(restream_id, DisableReason::default()),
+            Some(false),
+        );
 
-        self.enabled = false;
+        assert_eq!(
+            state.restreams.lock_ref()[0]
+                .input
+                .disabled_reason
+                .as_ref()
+                .map(|r| r.kind),
+            Some(DisableReasonKind::Idle),
+        );
+    }
 
-        for e in &mut self.endpoints {
-            e.srs_publisher_id = None;
-            e.srs_player_ids.clear();
-            // Do not rely only on SRS to set status, as it sporadically races.
-            e.status = Status::Offline;
-        }
+    #[test]
+    fn deserializes_legacy_reason() {
+        let reason = serde_json::from_value::<DisableReason>(json!(
+            "Session exceeded its maximum of 60 seconds"
+        ))
+        .unwrap();
+        assert_eq!(reason.kind, DisableReasonKind::SessionExpiry);
 
-        if let Some(InputSrc::Failover(s)) = self.src.as_mut() {
-            for i in &mut s.inputs {
-                changed |= i.disable();
-            }
-        }
+        let reason = serde_json::from_value::<DisableReason>(json!(
+            "Received no live stream for 60 seconds"
+        ))
+        .unwrap();
+        assert_eq!(reason.kind, DisableReasonKind::Idle);
+    }
 
-        changed
+    #[test]
+    fn is_persisted() {
+        let reason = DisableReason::manual("GraphQL API");
+
+        let restored = serde_json::from_value::<DisableReason>(
+            serde_json::to_value(&reason).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(restored.kind, reason.kind);
+        assert_eq!(restored.detail, reason.detail);
+        assert_eq!(restored.actor, reason.actor);
+        assert_eq!(restored.at.to_rfc3339(), reason.at.to_rfc3339());
     }
+}
 
-    /// Lookups for an [`Input`] with the given `id` inside this [`Input`] or
-    /// its [`FailoverInputSrc::inputs`].
-    #[must_use]
-    pub fn find_mut(&mut self, id: InputId) -> Option<&mut Self> {
-        if self.id == id {
-            return Some(self);
-        }
-        if let Some(InputSrc::Failover(s)) = &mut self.src {
-            s.inputs.iter_mut().find_map(|i| i.find_mut(id))
-        } else {
-            None
-        }
+#[cfg(test)]
+mod usage_counters_spec {
+    use serde_json::json;
+
+    use super::{InputId, InputSrc, Restream, State};
+
+    fn state() -> State {
+        State::with_restream(
+            "test",
+            json!({
+                "input": {"src": {"failover_inputs": [{
+                    "key": "main",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "enabled": true,
+                }]}},
+                "outputs": [{"dst": "rtmp://example.com/live/stream"}],
+            }),
+        )
     }
 
-    /// Indicates whether this [`Input`] is ready to serve a live stream for
-    /// [`Output`]s.
-    #[must_use]
-    pub fn is_ready_to_serve(&self) -> bool {
-        let mut is_online = self
-            .endpoints
-            .iter()
-            .any(|e| e.is_rtmp() && e.status == Status::Online);
+    #[test]
+    fn persists_counters() {
+        let state = state();
+        let mut restream = state.restreams.lock_ref()[0].clone();
+        restream.input_online_secs = 3600;
+        restream.outputs[0].online_secs = 1800;
+
+        let restored: Restream =
+            serde_json::from_value(serde_json::to_value(&restream).unwrap())
+                .unwrap();
+
+        assert_eq!(restored.input_online_secs, 3600);
+        assert_eq!(restored.outputs[0].online_secs, 1800);
+        assert_eq!(
+            restored.usage_since.map(|at| at.to_rfc3339()),
+            restream.usage_since.map(|at| at.to_rfc3339()),
+        );
+    }
 
-        if !is_online {
-            if let Some(InputSrc::Failover(s)) = &self.src {
-                is_online = s.inputs.iter().any(|i| {
-                    i.endpoints
-                        .iter()
-                        .any(|e| e.is_rtmp() && e.status == Status::Online)
-                });
+    #[test]
+    fn resets_counters_of_owning_restream() {
+        let state = state();
+        let failover_id = {
+            let mut restreams = state.restreams.lock_mut();
+            let r = &mut restreams[0];
+            r.input_online_secs = 3600;
+            r.outputs[0].online_secs = 1800;
+            r.usage_since = None;
+            match &r.input.src {
+                Some(InputSrc::Failover(s)) => s.inputs[0].id,
+                _ => unreachable!(),
             }
-        }
+        };
 
-        is_online
+        let reset = state.reset_usage_counters(failover_id).unwrap();
+        assert_eq!(reset.input_online_secs, 3600);
+        assert_eq!(reset.outputs_online_secs, 1800);
+
+        let restreams = state.restreams.lock_ref();
+        assert_eq!(restreams[0].input_online_secs, 0);
+        assert_eq!(restreams[0].outputs[0].online_secs, 0);
+        assert_eq!(restreams[0].usage_since, Some(reset.at));
+        assert_eq!(restreams[0].usage_resets, vec![reset]);
     }
-}
 
-/// Endpoint of an `Input` serving a live stream for `Output`s and clients.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct InputEndpoint {
-    /// Unique ID of this `InputEndpoint`.
-    ///
-    /// Once assigned, it never changes.
-    pub id: EndpointId,
+    #[test]
+    fn persists_resets() {
+        let state = state();
+        let id = state.restreams.lock_ref()[0].input.id;
+        let reset = state.reset_usage_counters(id).unwrap();
 
-    /// Kind of this `InputEndpoint`.
-    pub kind: InputEndpointKind,
+        let restored: Restream = serde_json::from_value(
+            serde_json::to_value(&state.restreams.lock_ref()[0]).unwrap(),
+        )
+        .unwrap();
 
-    /// `Status` of this `InputEndpoint` indicating whether it actually serves a
-    /// live stream ready to be consumed by `Output`s and clients.
-    #[serde(skip)]
-    pub status: Status,
+        assert_eq!(restored.usage_resets.len(), 1);
+        assert_eq!(
+            restored.usage_resets[0].at.to_rfc3339(),
+            reset.at.to_rfc3339(),
+        );
+    }
 
-    /// ID of [SRS] client who publishes a live stream to this [`InputEndpoint`]
-    /// (either an external client or a local process).
-    ///
-    /// [SRS]: https://github.com/ossrs/srs
-    #[graphql(skip)]
-    #[serde(skip)]
-    pub srs_publisher_id: Option<srs::ClientId>,
+    #[test]
+    fn ignores_unknown_input() {
+        let state = state();
 
-    /// IDs of [SRS] clients who play a live stream from this [`InputEndpoint`]
-    /// (either an external clients or a local processes).
-    ///
-    /// [SRS]: https://github.com/ossrs/srs
-    #[graphql(skip)]
-    #[serde(skip)]
-    pub srs_player_ids: HashSet<srs::ClientId>,
+        assert_eq!(state.reset_usage_counters(InputId::random()), None);
+    }
 }
 
-impl InputEndpoint {
-    /// Creates a new [`InputEndpoint`] out of the given
-    /// [`spec::v1::InputEndpoint`].
-    #[inline]
-    #[must_use]
-    pub fn new(spec: spec::v1::InputEndpoint) -> Self {
-        Self {
-            id: EndpointId::random(),
-            kind: spec.kind,
-            status: Status::Offline,
-            srs_publisher_id: None,
-            srs_player_ids: HashSet::new(),
-        }
+#[cfg(test)]
+mod src_stream_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{InputKey, Rejected, State};
+
+    fn state() -> State {
+        State::with_restream(
+            "contrib",
+            json!({
+                "input": {"src": {"failover_inputs": [{
+                    "key": "in",
+                    "endpoints": [{"kind": "rtmp"}],
+                }, {
+                    "key": "clean",
+                    "endpoints": [{"kind": "rtmp"}],
+                }]}},
+                "outputs": [{"dst": "rtmp://example.com/live/stream"}],
+            }),
+        )
     }
 
-    /// Applies the given [`spec::v1::InputEndpoint`] to this [`InputEndpoint`].
-    #[inline]
-    pub fn apply(&mut self, new: spec::v1::InputEndpoint) {
-        self.kind = new.kind;
+    fn key(val: &str) -> InputKey {
+        InputKey::new(val).unwrap()
     }
 
-    /// Exports this [`InputEndpoint`] as a [`spec::v1::InputEndpoint`].
-    #[inline]
-    #[must_use]
-    pub fn export(&self) -> spec::v1::InputEndpoint {
-        spec::v1::InputEndpoint { kind: self.kind }
+    #[test]
+    fn selects_input_by_key() {
+        let state = state();
+        let (restream_id, output_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
+
+        assert_eq!(
+            state
+                .set_output_src_stream(
+                    restream_id,
+                    output_id,
+                    Some(key("clean")),
+                )
+                .unwrap(),
+            Some(true),
+        );
+
+        let restreams = state.restreams.lock_ref();
+        let (r, o) = (&restreams[0], &restreams[0].outputs[0]);
+        assert_eq!(o.src_stream, Some(key("clean")));
+        let src = r.output_src_input(o).unwrap();
+        assert_eq!(src.key, key("clean"));
+        assert_eq!(
+            r.input_rtmp_endpoint_url(src).unwrap().path(),
+            "/contrib/clean",
+        );
     }
 
-    /// Indicates whether this [`InputEndpoint`] is an
-    /// [`InputEndpointKind::Rtmp`].
-    #[inline]
-    #[must_use]
-    pub fn is_rtmp(&self) -> bool {
-        matches!(self.kind, InputEndpointKind::Rtmp)
+    #[test]
+    fn normalizes_main_input_key() {
+        let state = state();
+        let (restream_id, output_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
+
+        assert_eq!(
+            state
+                .set_output_src_stream(
+                    restream_id,
+                    output_id,
+                    Some(key("origin")),
+                )
+                .unwrap(),
+            Some(false),
+        );
+
+        let restreams = state.restreams.lock_ref();
+        let (r, o) = (&restreams[0], &restreams[0].outputs[0]);
+        assert_eq!(o.src_stream, None);
+        assert_eq!(r.output_src_input(o).unwrap().id, r.input.id);
     }
-}
 
-/// Possible kinds of an `InputEndpoint`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLEnum,
-    Hash,
-    PartialEq,
-    Serialize,
-)]
-#[serde(rename_all = "lowercase")]
-pub enum InputEndpointKind {
-    /// [RTMP] endpoint.
-    ///
-    /// Can accept a live stream and serve it for playing.
-    ///
-    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-    #[display(fmt = "RTMP")]
-    Rtmp,
+    #[test]
+    fn rejects_unknown_stream() {
+        let state = state();
+        let (restream_id, output_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
 
-    /// [HLS] endpoint.
-    ///
-    /// Only serves a live stream for playing and is not able to accept one.
-    ///
-    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
-    #[display(fmt = "HLS")]
-    Hls,
-}
+        assert!(state
+            .set_output_src_stream(restream_id, output_id, Some(key("dirty")))
+            .is_err());
+        assert_eq!(state.restreams.lock_ref()[0].outputs[0].src_stream, None);
+    }
 
-impl InputEndpointKind {
-    /// Returns RTMP URL on a local [SRS] server of this [`InputEndpointKind`]
-    /// for the given `restream` and `input`.
-    ///
-    /// # Panics
-    /// No panics, because [`RestreamKey`] and [`InputKey`] are validated.
-    ///
-    /// [SRS]: https://github.com/ossrs/srs
-    #[must_use]
-    pub fn rtmp_url(self, restream: &RestreamKey, input: &InputKey) -> Url {
-        Url::parse(&format!(
-            "rtmp://127.0.0.1:1935/{}{}/{}",
-            restream,
-            match self {
-                Self::Rtmp => "",
-                Self::Hls => "?vhost=hls",
-            },
-            input,
-        ))
-        .unwrap()
+    #[test]
+    fn checks_added_output_stream() {
+        let state = state();
+        let restream_id = state.restreams.lock_ref()[0].id;
+        let output = |stream| {
+            serde_json::from_value::<spec::v1::Output>(json!({
+                "dst": format!("rtmp://example.com/live/{}", stream),
+                "src_stream": stream,
+            }))
+            .unwrap()
+        };
+
+        assert!(matches!(
+            state.add_output(restream_id, output("dirty")),
+            Err(Rejected::SrcStream(k)) if k == key("dirty"),
+        ));
+        state
+            .add_output(restream_id, output("clean"))
+            .unwrap()
+            .unwrap();
+        state
+            .add_output(restream_id, output("origin"))
+            .unwrap()
+            .unwrap();
+
+        let restreams = state.restreams.lock_ref();
+        let streams: Vec<_> = restreams[0]
+            .outputs
+            .iter()
+            .map(|o| o.src_stream.clone())
+            .collect();
+        assert_eq!(streams, [None, Some(key("clean")), None]);
     }
 }
 
-/// ID of an `InputEndpoint`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLScalarValue,
-    Into,
-    PartialEq,
-    Serialize,
-)]
-pub struct EndpointId(Uuid);
+#[cfg(test)]
+mod output_test_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{OutputId, RestreamId, State, Status};
+
+    fn state(enabled: bool) -> (State, RestreamId, OutputId) {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "test",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "enabled": true,
+                    },
+                    "outputs": [{
+                        "dst": "rtmp://example.com/live/stream",
+                        "enabled": enabled,
+                    }],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let (restream_id, output_id) = {
+            let restreams = state.restreams.lock_ref();
+            (restreams[0].id, restreams[0].outputs[0].id)
+        };
+        (state, restream_id, output_id)
+    }
+
+    #[test]
+    fn refuses_testing_active_output() {
+        let (state, restream_id, id) = state(true);
+
+        assert!(state.start_output_test(id, restream_id, 30).is_err());
+        assert!(state.restreams.lock_ref()[0].outputs[0].test.is_none());
+    }
+
+    #[test]
+    fn reports_testing_status_until_stopped() {
+        let (state, restream_id, id) = state(false);
+
+        assert_eq!(
+            state.start_output_test(id, restream_id, 30).unwrap(),
+            Some(true),
+        );
+        assert_eq!(
+            state.start_output_test(id, restream_id, 30).unwrap(),
+            Some(false),
+        );
+        assert_eq!(state.statuses.get(id), Status::Testing);
+
+        assert_eq!(state.stop_output_test(id, restream_id), Some(true));
+        assert_eq!(state.stop_output_test(id, restream_id), Some(false));
+        assert_eq!(state.statuses.get(id), Status::Offline);
+        assert!(state.restreams.lock_ref()[0].outputs[0].last_test.is_none());
+    }
 
-impl EndpointId {
-    /// Generates a new random [`EndpointId`].
-    #[inline]
-    #[must_use]
-    pub fn random() -> Self {
-        Self(Uuid::new_v4())
+    #[test]
+    fn records_result_of_finished_test() {
+        let (state, restream_id, id) = state(false);
+        let _ = state.start_output_test(id, restream_id, 30).unwrap();
+        let started_at = state.restreams.lock_ref()[0].outputs[0]
+            .test
+            .as_ref()
+            .unwrap()
+            .started_at
+            .clone();
+
+        state.finish_output_test(
+            id,
+            &started_at,
+            Some(Err("Connection refused".into())),
+        );
+
+        let restreams = state.restreams.lock_ref();
+        let output = &restreams[0].outputs[0];
+        assert!(output.test.is_none());
+        assert_eq!(state.statuses.get(id), Status::Offline);
+        assert!(!output.last_test.as_ref().unwrap().succeeded);
+        assert_eq!(
+            output.problem.as_ref().unwrap().message,
+            "Connection refused"
+        );
     }
 }
 
-/// Source to pull a live stream by an `Input` from.
-#[derive(
-    Clone, Debug, Deserialize, Eq, From, GraphQLUnion, PartialEq, Serialize,
-)]
-#[serde(rename_all = "lowercase")]
-pub enum InputSrc {
-    /// Remote endpoint.
-    Remote(RemoteInputSrc),
+#[cfg(test)]
+mod validate_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::State;
+
+    fn state_json() -> serde_json::Value {
+        let state = State::new_in_memory();
+        state.apply(
+            serde_json::from_value::<spec::Spec>(json!({
+                "version": "v1",
+                "restreams": [{
+                    "key": "main",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                    },
+                    "outputs": [
+                        {"dst": "rtmp://example.com/live/a"},
+                        {"dst": "rtmp://example.com/live/b"},
+                    ],
+                }],
+            }))
+            .unwrap()
+            .into_v1(),
+            true,
+        );
+        serde_json::to_value(&state).unwrap()
+    }
 
-    /// Multiple local endpoints forming a failover source.
-    Failover(FailoverInputSrc),
-}
+    fn paths(json: &serde_json::Value) -> Vec<String> {
+        State::validate(&serde_json::to_vec(json).unwrap())
+            .into_iter()
+            .map(|v| v.path)
+            .collect()
+    }
 
-impl InputSrc {
-    /// Creates a new [`InputSrc`] out of the given [`spec::v1::InputSrc`].
-    #[inline]
-    #[must_use]
-    pub fn new(spec: spec::v1::InputSrc) -> Self {
-        match spec {
-            spec::v1::InputSrc::RemoteUrl(url) => {
-                Self::Remote(RemoteInputSrc { url })
-            }
-            spec::v1::InputSrc::FailoverInputs(inputs) => {
-                Self::Failover(FailoverInputSrc {
-                    inputs: inputs.into_iter().map(Input::new).collect(),
-                })
-            }
-        }
+    #[test]
+    fn accepts_valid_state() {
+        assert!(paths(&state_json()).is_empty());
+        assert!(State::validate(b"").is_empty());
     }
 
-    /// Applies the given [`spec::v1::InputSrc`] to this [`InputSrc`].
-    ///
-    /// Replaces all the [`FailoverInputSrc::inputs`] with new ones.
-    pub fn apply(&mut self, new: spec::v1::InputSrc) {
-        match (self, new) {
-            (Self::Remote(old), spec::v1::InputSrc::RemoteUrl(new_url)) => {
-                old.url = new_url
-            }
-            (Self::Failover(src), spec::v1::InputSrc::FailoverInputs(news)) => {
-                let mut olds = mem::replace(
-                    &mut src.inputs,
-                    Vec::with_capacity(news.len()),
-                );
-                for new in news {
-                    if let Some(mut old) = olds
-                        .iter()
-                        .enumerate()
-                        .find_map(|(n, o)| (o.key == new.key).then(|| n))
-                        .map(|n| olds.swap_remove(n))
-                    {
-                        old.apply(new);
-                        src.inputs.push(old);
-                    } else {
-                        src.inputs.push(Input::new(new));
-                    }
-                }
-            }
-            (old, new) => *old = Self::new(new),
-        }
+    #[test]
+    fn rejects_malformed_json() {
+        let violations = State::validate(b"{\"restreams\": [");
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "$");
     }
 
-    /// Exports this [`InputSrc`] as a [`spec::v1::InputSrc`].
-    #[inline]
-    #[must_use]
-    pub fn export(&self) -> spec::v1::InputSrc {
-        match self {
-            Self::Remote(i) => spec::v1::InputSrc::RemoteUrl(i.url.clone()),
-            Self::Failover(src) => spec::v1::InputSrc::FailoverInputs(
-                src.inputs.iter().map(Input::export).collect(),
-            ),
-        }
+    #[test]
+    fn locates_invalid_values() {
+        let mut json = state_json();
+        json["restreams"][0]["outputs"][1]["dst"] = json!("ftp://example.com");
+        json["restreams"][0]["input"]["key"] = json!("");
+
+        assert_eq!(
+            paths(&json),
+            ["$.restreams[0].input", "$.restreams[0].outputs[1]"],
+        );
     }
-}
 
-/// Remote upstream source to pull a live stream by an `Input` from.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct RemoteInputSrc {
-    /// URL of this `RemoteInputSrc`.
-    pub url: InputSrcUrl,
-}
+    #[test]
+    fn checks_invariants() {
+        let mut json = state_json();
+        json["restreams"][0]["outputs"][1]["dst"] =
+            json["restreams"][0]["outputs"][0]["dst"].clone();
 
-/// Failover source of multiple `Input`s to pull a live stream by an `Input`
-/// from.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct FailoverInputSrc {
-    /// `Input`s forming this `FailoverInputSrc`.
-    ///
-    /// Failover is implemented by attempting to pull the first `Input` falling
-    /// back to the second one, and so on. Once the first source is restored,
-    /// we pool from it once again.
-    pub inputs: Vec<Input>,
-}
+        assert_eq!(paths(&json), ["$.restreams[0].outputs"]);
+    }
 
-/// ID of an `Input`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLScalarValue,
-    Into,
-    PartialEq,
-    Serialize,
-)]
-pub struct InputId(Uuid);
+    #[test]
+    fn checks_schedules() {
+        let mut json = state_json();
+        json["restreams"][0]["input"]["schedule"] = json!([
+            {"weekday": "monday", "start": "10:00", "end": "12:00"},
+        ]);
 
-impl InputId {
-    /// Generates a new random [`InputId`].
-    #[inline]
-    #[must_use]
-    pub fn random() -> Self {
-        Self(Uuid::new_v4())
+        assert_eq!(paths(&json), ["$.restreams[0].input.schedule"]);
+
+        json["restreams"][0]["input"]["src"] =
+            json!({"remote": {"url": "rtmp://example.com/live/in"}});
+
+        assert!(paths(&json).is_empty());
+
+        json["restreams"][0]["input"]["schedule"] = json!([]);
+
+        assert_eq!(paths(&json), ["$.restreams[0].input.schedule"]);
+
+        json["restreams"][0]["input"]["schedule"] = json!([
+            {"weekday": "monday", "start": "12:00", "end": "12:00"},
+        ]);
+
+        assert_eq!(paths(&json), ["$.restreams[0].input"]);
     }
 }
 
-/// Key of an [`Input`] used to form its endpoint URL.
-#[derive(
-    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
-)]
-pub struct InputKey(String);
+#[cfg(test)]
+mod status_debouncer_spec {
+    use std::{
+        iter,
+        time::{Duration, Instant},
+    };
 
-impl InputKey {
-    /// Creates a new [`InputKey`] if the given value meets its invariants.
-    #[must_use]
-    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
-        static REGEX: Lazy<Regex> =
-            Lazy::new(|| Regex::new("^[a-z0-9_-]{1,50}$").unwrap());
+    use uuid::Uuid;
 
-        let val = val.into();
-        (!val.is_empty() && REGEX.is_match(&val))
-            .then(|| Self(val.into_owned()))
+    use super::{Status, StatusDebouncer};
+
+    fn debouncer(grace: u64) -> StatusDebouncer {
+        let debouncer = StatusDebouncer::default();
+        debouncer.grace.set(Duration::from_secs(grace));
+        debouncer
     }
-}
 
-impl<'de> Deserialize<'de> for InputKey {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
-            .ok_or_else(|| D::Error::custom("Not a valid Input.key"))
+    #[test]
+    fn commits_right_away_without_grace() {
+        let (debouncer, id, now) =
+            (debouncer(0), Uuid::new_v4(), Instant::now());
+
+        assert!(debouncer.record(id, Status::Online, now).is_none());
+        assert!(debouncer.record(id, Status::Offline, now).is_none());
+        assert_eq!(debouncer.raw_status(id), None);
     }
-}
 
-/// Type of `Input`'s `key` used to form its endpoint URL.
-///
-/// It should meet `[a-z0-9_-]{1,50}` format.
-#[graphql_scalar]
-impl<S> GraphQLScalar for InputKey
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+    #[test]
+    fn postpones_leaving_online() {
+        let (debouncer, id, now) =
+            (debouncer(5), Uuid::new_v4(), Instant::now());
+
+        assert!(debouncer.record(id, Status::Initializing, now).is_none());
+        assert!(debouncer.record(id, Status::Online, now).is_none());
+
+        let (rev, delay) = debouncer.record(id, Status::Offline, now).unwrap();
+        assert_eq!(delay, Duration::from_secs(5));
+        assert_eq!(debouncer.raw_status(id), Some(Status::Offline));
+
+        assert!(debouncer.complete(id, rev));
+        assert_eq!(debouncer.raw_status(id), None);
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(Self::new)
+    #[test]
+    fn treats_unstable_as_online() {
+        let (debouncer, id, now) =
+            (debouncer(5), Uuid::new_v4(), Instant::now());
+        let _ = debouncer.record(id, Status::Online, now);
+
+        assert!(debouncer.record(id, Status::Unstable, now).is_none());
+        assert_eq!(debouncer.raw_status(id), None);
+        assert!(debouncer.record(id, Status::Offline, now).is_some());
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn drops_transition_reverted_within_grace() {
+        let (debouncer, id, now) =
+            (debouncer(5), Uuid::new_v4(), Instant::now());
+        let _ = debouncer.record(id, Status::Online, now);
+
+        let (rev, _) = debouncer.record(id, Status::Offline, now).unwrap();
+        assert!(debouncer.record(id, Status::Online, now).is_none());
+
+        assert!(!debouncer.complete(id, rev));
+        assert_eq!(debouncer.raw_status(id), None);
     }
-}
 
-impl PartialEq<str> for InputKey {
-    #[inline]
-    fn eq(&self, other: &str) -> bool {
-        self.0 == other
+    #[test]
+    fn does_not_prolong_grace_on_subsequent_transitions() {
+        let (debouncer, id, now) =
+            (debouncer(5), Uuid::new_v4(), Instant::now());
+        let _ = debouncer.record(id, Status::Online, now);
+
+        let (first, _) = debouncer.record(id, Status::Offline, now).unwrap();
+        let later = now + Duration::from_secs(3);
+        let (last, delay) =
+            debouncer.record(id, Status::Initializing, later).unwrap();
+        assert_eq!(delay, Duration::from_secs(2));
+        assert!(!debouncer.complete(id, first));
+        assert!(debouncer.complete(id, last));
     }
-}
 
-/// [`Url`] of a [`RemoteInputSrc`].
-///
-/// Only the following URLs are allowed at the moment:
-/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
-///   host);
-/// - [HLS] URL (starting with `http://` or `https://` scheme, having a host,
-///   and with `.m3u8` extension in its path).
-///
-/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
-/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-#[derive(
-    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
-)]
-pub struct InputSrcUrl(Url);
+    #[test]
+    fn commits_right_away_once_grace_has_passed() {
+        let (debouncer, id, now) =
+            (debouncer(5), Uuid::new_v4(), Instant::now());
+        let _ = debouncer.record(id, Status::Online, now);
+
+        assert!(debouncer.record(id, Status::Offline, now).is_some());
+        assert!(debouncer
+            .record(id, Status::Initializing, now + Duration::from_secs(6))
+            .is_none());
+        assert_eq!(debouncer.raw_status(id), None);
+    }
 
-impl InputSrcUrl {
-    /// Creates a new [`InputSrcUrl`] if the given [`Url`] is suitable for that.
-    ///
-    /// # Errors
-    ///
-    /// Returns the given [`Url`] back if it doesn't represent a valid
-    /// [`InputSrcUrl`].
-    #[inline]
-    pub fn new(url: Url) -> Result<Self, Url> {
-        if Self::validate(&url) {
-            Ok(Self(url))
-        } else {
-            Err(url)
+    #[test]
+    fn forgets_removed_entities() {
+        let (debouncer, now) = (debouncer(5), Instant::now());
+        let (kept, removed) = (Uuid::new_v4(), Uuid::new_v4());
+        for id in &[kept, removed] {
+            let _ = debouncer.record(*id, Status::Online, now);
         }
+        let (rev, _) = debouncer.record(removed, Status::Offline, now).unwrap();
+
+        debouncer.retain(&iter::once(kept).collect());
+
+        assert_eq!(debouncer.raw.lock().unwrap().len(), 1);
+        assert!(!debouncer.complete(removed, rev));
+        assert_eq!(debouncer.raw_status(removed), None);
     }
+}
 
-    /// Validates the given [`Url`] to represent a valid [`InputSrcUrl`].
-    #[must_use]
-    pub fn validate(url: &Url) -> bool {
-        match url.scheme() {
-            "rtmp" | "rtmps" => url.has_host(),
-            "http" | "https" => {
-                url.has_host()
-                    && Path::new(url.path()).extension()
-                        == Some("m3u8".as_ref())
-            }
-            _ => false,
-        }
+#[cfg(test)]
+mod positions_spec {
+    use serde_json::json;
+
+    use crate::spec;
+
+    use super::{
+        insert_positioned, normalize_positions, Restream, RestreamId, State,
+        POSITION_GAP,
+    };
+
+    fn add(state: &State, key: &str) -> RestreamId {
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": key,
+                    "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                    "outputs": [
+                        {"dst": "rtmp://example.com/live/a"},
+                        {"dst": "rtmp://example.com/live/b"},
+                    ],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
+            .restreams
+            .lock_ref()
+            .iter()
+            .find(|r| r.key == *key)
+            .unwrap()
+            .id
+    }
+
+    fn positions(restreams: &[Restream]) -> Vec<u32> {
+        restreams.iter().map(|r| r.position).collect()
+    }
+
+    #[test]
+    fn assigns_positions_with_gaps() {
+        let state = State::new_in_memory();
+        let _ = add(&state, "first");
+        let _ = add(&state, "second");
+
+        let restreams = state.restreams.get_cloned();
+        assert_eq!(positions(&restreams), [POSITION_GAP, 2 * POSITION_GAP]);
+        assert_eq!(
+            restreams[1]
+                .outputs
+                .iter()
+                .map(|o| o.position)
+                .collect::<Vec<_>>(),
+            [POSITION_GAP, 2 * POSITION_GAP],
+        );
+    }
+
+    #[test]
+    fn restores_into_former_place() {
+        let state = State::new_in_memory();
+        let _ = add(&state, "first");
+        let id = add(&state, "second");
+        let _ = add(&state, "third");
+
+        let _ = state.remove_restream(id, false).unwrap();
+        let _ = state.restore_restream(id).unwrap();
+
+        let restreams = state.restreams.get_cloned();
+        assert_eq!(restreams[1].id, id);
+        assert_eq!(
+            positions(&restreams),
+            [POSITION_GAP, 2 * POSITION_GAP, 3 * POSITION_GAP],
+        );
+    }
+
+    #[test]
+    fn inserts_after_occupied_position() {
+        let state = State::new_in_memory();
+        let ids: Vec<_> = ["first", "second", "third"]
+            .iter()
+            .map(|k| add(&state, k))
+            .collect();
+        let mut restreams = state.restreams.get_cloned();
+        let mut moved = restreams.remove(2);
+        moved.position = POSITION_GAP;
+
+        insert_positioned(&mut restreams, moved.clone());
+        assert_eq!(restreams[1].id, ids[2]);
+        assert_eq!(restreams[1].position, POSITION_GAP + POSITION_GAP / 2);
+
+        let _ = restreams.remove(1);
+        moved.position = POSITION_GAP;
+        restreams[1].position = POSITION_GAP + 1;
+        insert_positioned(&mut restreams, moved);
+        assert_eq!(restreams[1].id, ids[2]);
+        assert_eq!(
+            positions(&restreams),
+            [POSITION_GAP, 2 * POSITION_GAP, 3 * POSITION_GAP],
+        );
     }
-}
 
-impl<'de> Deserialize<'de> for InputSrcUrl {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
-            D::Error::custom(format!("Not a valid RemoteInputSrc.url: {}", url))
-        })
+    #[test]
+    fn migrates_missing_positions_from_order() {
+        let state = State::new_in_memory();
+        let ids: Vec<_> = ["first", "second", "third"]
+            .iter()
+            .map(|k| add(&state, k))
+            .collect();
+        let mut restreams = state.restreams.get_cloned();
+        for r in &mut restreams {
+            r.position = 0;
+        }
+
+        normalize_positions(&mut restreams);
+        assert_eq!(restreams.iter().map(|r| r.id).collect::<Vec<_>>(), ids,);
+        assert_eq!(
+            positions(&restreams),
+            [POSITION_GAP, 2 * POSITION_GAP, 3 * POSITION_GAP],
+        );
     }
 }
 
-/// Type of a `RemoteInputSrc.url`.
-///
-/// Only the following URLs are allowed at the moment:
-/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
-///   host);
-/// - [HLS] URL (starting with `http://` or `https://` scheme, having a host,
-///   and with `.m3u8` extension in its path).
-///
-/// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
-/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-#[graphql_scalar]
-impl<S> GraphQLScalar for InputSrcUrl
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+#[cfg(test)]
+mod restreams_version_spec {
+    use super::{
+        ProblemInfo, ProblemKind, Restream, RestreamsVersion, Status, Statuses,
+    };
+
+    fn restreams() -> Vec<Restream> {
+        vec![Restream::new(
+            serde_json::from_value(serde_json::json!({
+                "key": "guest",
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+            }))
+            .unwrap(),
+        )]
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(|s| Url::parse(s).ok())
-            .and_then(|url| Self::new(url).ok())
+    fn calculate(restreams: &[Restream], statuses: &Statuses) -> String {
+        RestreamsVersion::calculate(restreams, statuses).unwrap()
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn changes_on_status_change() {
+        let (restreams, mut statuses) = (restreams(), Statuses::default());
+        let before = calculate(&restreams, &statuses);
+        assert_eq!(before, calculate(&restreams, &statuses));
+
+        let _ =
+            statuses.set(restreams[0].input.endpoints[0].id, Status::Online);
+        assert_ne!(before, calculate(&restreams, &statuses));
     }
-}
 
-/// Downstream destination that a `Restream` re-streams a live stream to.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct Output {
-    /// Unique ID of this `Output`.
-    ///
-    /// Once assigned, it never changes.
-    pub id: OutputId,
+    #[test]
+    fn changes_on_runtime_change() {
+        let (mut restreams, statuses) = (restreams(), Statuses::default());
+        let before = calculate(&restreams, &statuses);
 
-    /// Downstream URL to re-stream a live stream onto.
-    ///
-    /// At the moment only [RTMP] and [Icecast] are supported.
-    ///
-    /// [Icecast]: https://icecast.org
-    /// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-    pub dst: OutputDstUrl,
+        restreams[0].input.problem =
+            Some(ProblemInfo::new(ProblemKind::Connect, "Connection refused"));
+        assert_ne!(before, calculate(&restreams, &statuses));
+    }
 
-    /// Optional label of this `Output`.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub label: Option<Label>,
+    #[test]
+    fn is_cached_until_invalidated() {
+        let (restreams, mut statuses) = (restreams(), Statuses::default());
+        let version = RestreamsVersion::default();
+        let before = version.get(&restreams, &statuses);
 
-    /// Url of stream preview.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub preview_url: Option<Url>,
+        let _ =
+            statuses.set(restreams[0].input.endpoints[0].id, Status::Online);
+        assert_eq!(version.get(&restreams, &statuses), before);
 
-    /// Volume rate of this `Output`'s audio tracks when mixed with
-    /// `Output.mixins`.
-    ///
-    /// Has no effect when there is no `Output.mixins`.
-    #[serde(default, skip_serializing_if = "Volume::is_origin")]
-    pub volume: Volume,
+        version.invalidate();
+        assert_ne!(version.get(&restreams, &statuses), before);
+    }
+}
 
-    /// `Mixin`s to mix this `Output` with before re-streaming it to its
-    /// downstream destination.
-    ///
-    /// If empty, then no mixing is performed and re-streaming is as cheap as
-    /// possible (just copies bytes "as is").
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub mixins: Vec<Mixin>,
+#[cfg(test)]
+mod change_feed_spec {
+    use super::{ChangeFeed, ChangeKind, ChangeSeq, Label, Restream};
+
+    fn restream(key: &str) -> Restream {
+        Restream::new(
+            serde_json::from_value(serde_json::json!({
+                "key": key,
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+            }))
+            .unwrap(),
+        )
+    }
 
-    /// Indicator whether this `Output` is enabled, so is allowed to perform a
-    /// live stream re-streaming to its downstream destination.
-    #[serde(default, skip_serializing_if = "is_false")]
-    pub enabled: bool,
+    #[test]
+    fn records_created_updated_and_deleted() {
+        let feed = ChangeFeed::default();
+        let mut restreams = vec![restream("a"), restream("b")];
+        feed.start(&restreams);
+        let cursor = feed.seq();
+
+        restreams[0].label = Label::new("Updated").ok();
+        restreams.remove(1);
+        restreams.push(restream("c"));
+        feed.record(&ChangeFeed::digests(&restreams));
+
+        let (seq, changes) = feed.since(cursor).unwrap();
+        assert_eq!(seq, ChangeSeq(cursor.0 + 1));
+        let kinds: Vec<_> = changes.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ChangeKind::Updated,
+                ChangeKind::Created,
+                ChangeKind::Deleted
+            ],
+        );
+        assert!(feed.since(seq).unwrap().1.is_empty());
+    }
 
-    /// `Status` of this `Output` indicating whether it actually re-streams a
-    /// live stream to its downstream destination.
-    #[serde(skip)]
-    pub status: Status,
-}
+    #[test]
+    fn ignores_non_persisted_changes() {
+        let feed = ChangeFeed::default();
+        let mut restreams = vec![restream("a")];
+        feed.start(&restreams);
+        let cursor = feed.seq();
 
-impl Output {
-    /// Creates a new [`Output`] out of the given [`spec::v1::Output`].
-    #[inline]
-    #[must_use]
-    pub fn new(spec: spec::v1::Output) -> Self {
-        Self {
-            id: OutputId::random(),
-            dst: spec.dst,
-            label: spec.label,
-            preview_url: spec.preview_url,
-            volume: spec.volume,
-            mixins: spec.mixins.into_iter().map(Mixin::new).collect(),
-            enabled: spec.enabled,
-            status: Status::Offline,
-        }
+        restreams[0].input.endpoints[0].offline_reason = Some("gone".into());
+        feed.record(&ChangeFeed::digests(&restreams));
+
+        assert!(feed.since(cursor).unwrap().1.is_empty());
     }
 
-    /// Applies the given [`spec::v1::Output`] to this [`Output`].
-    ///
-    /// If `replace` is `true` then all the [`Output::mixins`] will be replaced
-    /// with new ones, otherwise new ones will be merged with already existing
-    /// [`Output::mixins`].
-    pub fn apply(&mut self, new: spec::v1::Output, replace: bool) {
-        self.dst = new.dst;
-        self.label = new.label;
-        self.preview_url = new.preview_url;
-        self.volume = new.volume;
-        // Temporary omit changing existing `enabled` value to avoid unexpected
-        // breakages of ongoing re-streams.
-        //self.enabled = new.enabled;
-        if replace {
-            let mut olds = mem::replace(
-                &mut self.mixins,
-                Vec::with_capacity(new.mixins.len()),
-            );
-            for new in new.mixins {
-                if let Some(mut old) = olds
-                    .iter()
-                    .enumerate()
-                    .find_map(|(n, o)| (o.src == new.src).then(|| n))
-                    .map(|n| olds.swap_remove(n))
-                {
-                    old.apply(new);
-                    self.mixins.push(old);
-                } else {
-                    self.mixins.push(Mixin::new(new));
-                }
-            }
-        } else {
-            for new in new.mixins {
-                if let Some(old) =
-                    self.mixins.iter_mut().find(|o| o.src == new.src)
-                {
-                    old.apply(new);
-                } else {
-                    self.mixins.push(Mixin::new(new));
-                }
-            }
-        }
+    #[test]
+    fn merges_changes_of_same_restream() {
+        let feed = ChangeFeed::default();
+        let mut restreams = vec![];
+        feed.start(&restreams);
+        let cursor = feed.seq();
+
+        restreams.push(restream("a"));
+        feed.record(&ChangeFeed::digests(&restreams));
+        restreams[0].label = Label::new("Updated").ok();
+        feed.record(&ChangeFeed::digests(&restreams));
+
+        let (seq, changes) = feed.since(cursor).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Created);
+        assert_eq!(changes[0].seq, seq);
     }
 
-    /// Exports this [`Output`] as a [`spec::v1::Output`].
-    #[inline]
-    #[must_use]
-    pub fn export(&self) -> spec::v1::Output {
-        spec::v1::Output {
-            dst: self.dst.clone(),
-            label: self.label.clone(),
-            preview_url: self.preview_url.clone(),
-            volume: self.volume,
-            mixins: self.mixins.iter().map(Mixin::export).collect(),
-            enabled: self.enabled,
+    #[test]
+    fn requires_reset_beyond_horizon() {
+        let feed = ChangeFeed::default();
+        let mut restreams = vec![restream("a")];
+        feed.start(&restreams);
+        let cursor = feed.seq();
+        assert!(feed.since(ChangeSeq(cursor.0 - 1)).is_none());
+        assert!(feed.since(ChangeSeq(cursor.0 + 1)).is_none());
+
+        for n in 0..=ChangeFeed::CAPACITY {
+            restreams[0].label = Label::new(format!("Label {}", n)).ok();
+            feed.record(&ChangeFeed::digests(&restreams));
         }
+
+        assert!(feed.since(cursor).is_none());
+        assert!(feed.since(ChangeSeq(feed.seq().0 - 1)).is_some());
     }
 }
 
-/// ID of an `Output`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLScalarValue,
-    Into,
-    PartialEq,
-    Serialize,
-)]
-pub struct OutputId(Uuid);
+#[cfg(test)]
+mod persistence_spec {
+    use std::{
+        env, fmt, fs, iter,
+        os::unix::fs::PermissionsExt as _,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use actix_web::rt::System;
+    use futures::future::{self, BoxFuture, FutureExt as _};
+    use serde_json::json;
+    use tokio::{task, time};
+    use uuid::Uuid;
+
+    use crate::spec;
+
+    use super::{
+        DirPersistence, EncryptionKey, FilePersistence, InMemoryPersistence,
+        PersistenceHealth, State, StatePersistence, Status,
+    };
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!("ephyr-state-{}.json", Uuid::new_v4()))
+    }
 
-impl OutputId {
-    /// Generates a new random [`OutputId`].
-    #[inline]
-    #[must_use]
-    pub fn random() -> Self {
-        Self(Uuid::new_v4())
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
     }
-}
 
-/// [`Url`] of an [`Output::dst`].
-///
-/// Only the following URLs are allowed at the moment:
-/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
-///   host);
-/// - [SRT] URL (starting with `srt://` scheme and having a host);
-/// - [Icecast] URL (starting with `icecast://` scheme and having a host);
-/// - [FLV] file URL (starting with `file:///` scheme, without host and
-///   subdirectories, and with `.flv` extension in its path).
-///
-/// [FLV]: https://en.wikipedia.org/wiki/Flash_Video
-/// [Icecast]: https://icecast.org
-/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-/// [SRT]: https://en.wikipedia.org/wiki/Secure_Reliable_Transport
-#[derive(
-    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
-)]
-pub struct OutputDstUrl(Url);
+    #[test]
+    fn file_backend_round_trips_contents() {
+        let path = env::temp_dir()
+            .join(format!("ephyr-state-{}.json", Uuid::new_v4()));
+        let file = FilePersistence::new(&path);
+
+        System::new("test").block_on(async {
+            assert!(file.load().await.unwrap().is_empty());
+            assert!(!path.exists());
+            file.save(br#"{"restreams":[]}"#.to_vec()).await.unwrap();
+            assert_eq!(file.load().await.unwrap(), br#"{"restreams":[]}"#);
+        });
 
-impl OutputDstUrl {
-    /// Creates a new [`OutputDstUrl`] if the given [`Url`] is suitable for
-    /// that.
-    ///
-    /// # Errors
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Serialized [`State`] with the given `(id, key)` pairs of
+    /// [`Restream`]s.
     ///
-    /// Returns the given [`Url`] back if it doesn't represent a valid
-    /// [`OutputDstUrl`].
-    #[inline]
-    pub fn new(url: Url) -> Result<Self, Url> {
-        if Self::validate(&url) {
-            Ok(Self(url))
-        } else {
-            Err(url)
-        }
+    /// [`Restream`]: super::Restream
+    fn state_with(restreams: &[(Uuid, &str)]) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "settings": {"title": "test"},
+            "restreams": restreams
+                .iter()
+                .map(|(id, key)| json!({"id": id, "key": key}))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
     }
 
-    /// Validates the given [`Url`] to represent a valid [`OutputDstUrl`].
-    #[must_use]
-    pub fn validate(url: &Url) -> bool {
-        match url.scheme() {
-            "icecast" | "rtmp" | "rtmps" | "srt" => url.has_host(),
-            "file" => {
-                let path = Path::new(url.path());
-                !url.has_host()
-                    && path.is_absolute()
-                    && path.extension() == Some("flv".as_ref())
-                    && path.parent() == Some("/".as_ref())
-                    && !url.path().contains("/../")
-            }
-            _ => false,
-        }
+    #[test]
+    fn dir_backend_round_trips_contents() {
+        let dir = temp_path().with_extension("d");
+        let persistence = DirPersistence::new(&dir, temp_path());
+        let contents =
+            state_with(&[(Uuid::new_v4(), "main"), (Uuid::new_v4(), "backup")]);
+
+        System::new("test").block_on(async {
+            assert!(persistence.load().await.unwrap().is_empty());
+            persistence.save(contents.clone()).await.unwrap();
+
+            let loaded: serde_json::Value =
+                serde_json::from_slice(&persistence.load().await.unwrap())
+                    .unwrap();
+            let expected: serde_json::Value =
+                serde_json::from_slice(&contents).unwrap();
+            assert_eq!(loaded, expected);
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_backend_rewrites_changed_files_only() {
+        let dir = temp_path().with_extension("d");
+        let persistence = DirPersistence::new(&dir, temp_path());
+        let (main, backup, extra) =
+            (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        System::new("test").block_on(async {
+            persistence
+                .save(state_with(&[(main, "main"), (backup, "backup")]))
+                .await
+                .unwrap();
+            // Unchanged files are not touched, so stay removed.
+            fs::remove_file(dir.join(format!("{}.json", main))).unwrap();
+
+            persistence
+                .save(state_with(&[(main, "main"), (backup, "changed")]))
+                .await
+                .unwrap();
+            assert!(!dir.join(format!("{}.json", main)).exists());
+            let changed =
+                fs::read_to_string(dir.join(format!("{}.json", backup)))
+                    .unwrap();
+            assert!(changed.contains("changed"), "{}", changed);
+
+            persistence
+                .save(state_with(&[(extra, "extra")]))
+                .await
+                .unwrap();
+            assert!(!dir.join(format!("{}.json", backup)).exists());
+            assert!(dir.join(format!("{}.json", extra)).exists());
+        });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_backend_migrates_legacy_file() {
+        let (dir, legacy) = (temp_path().with_extension("d"), temp_path());
+        let contents = state_with(&[(Uuid::new_v4(), "main")]);
+        fs::write(&legacy, &contents).unwrap();
+        let persistence = DirPersistence::new(&dir, &legacy);
+
+        System::new("test").block_on(async {
+            let loaded = persistence.load().await.unwrap();
+            assert_eq!(loaded, contents);
+
+            persistence.save(loaded).await.unwrap();
+            assert!(!legacy.exists());
+            let loaded: serde_json::Value =
+                serde_json::from_slice(&persistence.load().await.unwrap())
+                    .unwrap();
+            assert_eq!(loaded["restreams"][0]["key"], "main");
+        });
+
+        let mut migrated = legacy.into_os_string();
+        migrated.push(".migrated");
+        fs::remove_file(migrated).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
     }
-}
 
-impl<'de> Deserialize<'de> for OutputDstUrl {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
-            D::Error::custom(format!("Not a valid Output.src URL: {}", url))
-        })
+    #[test]
+    fn file_backend_reports_file_path() {
+        // Not a directory, so the file cannot be opened at all.
+        let parent = temp_path();
+        fs::write(&parent, b"").unwrap();
+        let path = parent.join("state.json");
+        let file = FilePersistence::new(&path);
+
+        let err = System::new("test")
+            .block_on(file.load())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with(&format!(
+                "Failed to open '{}' file: ",
+                path.display(),
+            )),
+            "{}",
+            err,
+        );
+
+        fs::remove_file(&parent).unwrap();
     }
-}
 
-/// Type of an `Output.dst` URL.
-///
-/// Only the following URLs are allowed at the moment:
-/// - [RTMP] URL (starting with `rtmp://` or `rtmps://` scheme and having a
-///   host);
-/// - [SRT] URL (starting with `srt://` scheme and having a host);
-/// - [Icecast] URL (starting with `icecast://` scheme and having a host);
-/// - [FLV] file URL (starting with `file:///` scheme, without host and
-///   subdirectories, and with `.flv` extension in its path).
-///
-/// [FLV]: https://en.wikipedia.org/wiki/Flash_Video
-/// [Icecast]: https://icecast.org
-/// [RTMP]: https://en.wikipedia.org/wiki/Real-Time_Messaging_Protocol
-/// [SRT]: https://en.wikipedia.org/wiki/Secure_Reliable_Transport
-#[graphql_scalar]
-impl<S> GraphQLScalar for OutputDstUrl
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+    #[test]
+    fn file_backend_creates_private_file() {
+        let path = temp_path();
+        let file = FilePersistence::new(&path);
+
+        System::new("test").block_on(async {
+            assert!(file.load().await.unwrap().is_empty());
+            assert!(!path.exists());
+
+            file.save(b"{}".to_vec()).await.unwrap();
+            assert_eq!(mode(&path), 0o600);
+        });
+
+        fs::remove_file(&path).unwrap();
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(|s| Url::parse(s).ok())
-            .and_then(|url| Self::new(url).ok())
+    #[test]
+    fn file_backend_restricts_loose_permissions() {
+        let path = temp_path();
+        fs::write(&path, b"{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let file = FilePersistence::new(&path);
+
+        let contents = System::new("test").block_on(file.load()).unwrap();
+        assert_eq!(contents, b"{}");
+        assert_eq!(mode(&path), 0o600);
+
+        fs::remove_file(&path).unwrap();
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn file_backend_encrypts_contents() {
+        let path = temp_path();
+        let key = EncryptionKey::derive(b"secret\n").unwrap();
+        let file = FilePersistence::new(&path).encrypted(key);
+
+        System::new("test").block_on(async {
+            file.save(br#"{"restreams":["key"]}"#.to_vec())
+                .await
+                .unwrap();
+            let raw = fs::read(&path).unwrap();
+            assert!(raw.starts_with(EncryptionKey::MAGIC));
+            assert!(!String::from_utf8_lossy(&raw).contains("restreams"));
+
+            assert_eq!(
+                file.load().await.unwrap(),
+                br#"{"restreams":["key"]}"#,
+            );
+            let same_key = EncryptionKey::derive(b"  secret").unwrap();
+            assert_eq!(
+                FilePersistence::new(&path)
+                    .encrypted(same_key)
+                    .load()
+                    .await
+                    .unwrap(),
+                br#"{"restreams":["key"]}"#,
+            );
+        });
+
+        fs::remove_file(&path).unwrap();
     }
-}
 
-/// Additional source for an `Output` to be mixed with before re-streaming to
-/// the destination.
-#[derive(
-    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
-)]
-pub struct Mixin {
-    /// Unique ID of this `Mixin`.
-    ///
-    /// Once assigned, it never changes.
-    pub id: MixinId,
+    #[test]
+    fn file_backend_rejects_missing_or_wrong_key() {
+        let path = temp_path();
+        let key = EncryptionKey::derive(b"secret").unwrap();
+
+        System::new("test").block_on(async {
+            FilePersistence::new(&path)
+                .encrypted(key)
+                .save(b"{}".to_vec())
+                .await
+                .unwrap();
+
+            let err = FilePersistence::new(&path)
+                .load()
+                .await
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("no state encryption key"), "{}", err);
+
+            let wrong = EncryptionKey::derive(b"other").unwrap();
+            let err = FilePersistence::new(&path)
+                .encrypted(wrong)
+                .load()
+                .await
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("wrong state encryption key"), "{}", err);
+        });
 
-    /// URL of the source to be mixed with an `Output`.
-    ///
-    /// At the moment, only [TeamSpeak] is supported.
-    ///
-    /// [TeamSpeak]: https://teamspeak.com
-    pub src: MixinSrcUrl,
+        fs::remove_file(&path).unwrap();
+    }
 
-    /// Volume rate of this `Mixin`'s audio tracks to mix them with.
-    #[serde(default, skip_serializing_if = "Volume::is_origin")]
-    pub volume: Volume,
+    #[test]
+    fn file_backend_loads_not_yet_encrypted_contents() {
+        let path = temp_path();
+        fs::write(&path, b"{}").unwrap();
+        let key = EncryptionKey::derive(b"secret").unwrap();
+        let file = FilePersistence::new(&path).encrypted(key);
 
-    /// Delay that this `Mixin` should wait before being mixed with an `Output`.
-    ///
-    /// Very useful to fix de-synchronization issues and correct timings between
-    /// a `Mixin` and its `Output`.
-    #[serde(default, skip_serializing_if = "Delay::is_zero")]
-    pub delay: Delay,
+        let contents = System::new("test").block_on(file.load()).unwrap();
+        assert_eq!(contents, b"{}");
 
-    /// `Status` of this `Mixin` indicating whether it provides an actual media
-    /// stream to be mixed with its `Output`.
-    #[serde(skip)]
-    pub status: Status,
-}
+        fs::remove_file(&path).unwrap();
+    }
 
-impl Mixin {
-    /// Creates a new [`Mixin`] out of the given [`spec::v1::Mixin`].
-    #[inline]
-    #[must_use]
-    pub fn new(spec: spec::v1::Mixin) -> Self {
-        Self {
-            id: MixinId::random(),
-            src: spec.src,
-            volume: spec.volume,
-            delay: spec.delay,
-            status: Status::Offline,
-        }
+    #[test]
+    fn empty_key_is_rejected() {
+        assert!(EncryptionKey::derive(b" \n").is_none());
     }
 
-    /// Applies the given [`spec::v1::Mixin`] to this [`Mixin`].
-    #[inline]
-    pub fn apply(&mut self, new: spec::v1::Mixin) {
-        self.src = new.src;
-        self.volume = new.volume;
-        self.delay = new.delay;
+    /// [`StatePersistence`] counting how many times it has been saved.
+    #[derive(Debug, Default)]
+    struct CountingPersistence {
+        /// Number of times this [`CountingPersistence`] has been saved.
+        saves: AtomicUsize,
+
+        /// Contents this [`CountingPersistence`] has been saved with the last
+        /// time.
+        last: Mutex<Vec<u8>>,
     }
 
-    /// Exports this [`Mixin`] as a [`spec::v1::Mixin`].
-    #[inline]
-    #[must_use]
-    pub fn export(&self) -> spec::v1::Mixin {
-        spec::v1::Mixin {
-            src: self.src.clone(),
-            volume: self.volume,
-            delay: self.delay,
+    impl CountingPersistence {
+        /// Returns the number of times this [`CountingPersistence`] has been
+        /// saved.
+        fn saves(&self) -> usize {
+            self.saves.load(Ordering::SeqCst)
         }
-    }
-}
 
-/// ID of a `Mixin`.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Display,
-    Eq,
-    From,
-    GraphQLScalarValue,
-    Into,
-    PartialEq,
-    Serialize,
-)]
-pub struct MixinId(Uuid);
+        /// Indicates whether the current snapshot of the given [`State`] is
+        /// the last one saved into this [`CountingPersistence`].
+        fn is_synced_with(&self, state: &State) -> bool {
+            *self.last.lock().unwrap()
+                == serde_json::to_vec(&state.snapshot()).unwrap()
+        }
+    }
 
-impl MixinId {
-    /// Generates a new random [`MixinId`].
-    #[inline]
-    #[must_use]
-    pub fn random() -> Self {
-        Self(Uuid::new_v4())
+    impl fmt::Display for CountingPersistence {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "counting")
+        }
     }
-}
 
-/// [`Url`] of a [`Mixin::src`].
-///
-/// Only the following URLs are allowed at the moment:
-/// - [TeamSpeak] URL (starting with `ts://` scheme and having a host);
-/// - [MP3] HTTP URL (starting with `http://` or `https://` scheme, having a
-///   host and `.mp3` extension in its path).
-///
-/// [MP3]: https://en.wikipedia.org/wiki/MP3
-/// [TeamSpeak]: https://teamspeak.com
-#[derive(
-    Clone, Debug, Deref, Display, Eq, Hash, Into, PartialEq, Serialize,
-)]
-pub struct MixinSrcUrl(Url);
+    impl StatePersistence for Arc<CountingPersistence> {
+        fn load(&self) -> BoxFuture<'_, Result<Vec<u8>, anyhow::Error>> {
+            future::ok(vec![]).boxed()
+        }
 
-impl MixinSrcUrl {
-    /// Creates a new [`MixinSrcUrl`] if the given [`Url`] is suitable for that.
-    ///
-    /// # Errors
-    ///
-    /// Returns the given [`Url`] back if it doesn't represent a valid
-    /// [`MixinSrcUrl`].
-    #[inline]
-    pub fn new(url: Url) -> Result<Self, Url> {
-        if Self::validate(&url) {
-            Ok(Self(url))
-        } else {
-            Err(url)
+        fn save(
+            &self,
+            contents: Vec<u8>,
+        ) -> BoxFuture<'static, Result<(), anyhow::Error>> {
+            *self.last.lock().unwrap() = contents;
+            let _ = self.saves.fetch_add(1, Ordering::SeqCst);
+            future::ok(()).boxed()
         }
     }
 
-    /// Validates the given [`Url`] to represent a valid [`MixinSrcUrl`].
-    #[must_use]
-    pub fn validate(url: &Url) -> bool {
-        url.has_host()
-            && match url.scheme() {
-                "ts" => true,
-                "http" | "https" => {
-                    Path::new(url.path()).extension() == Some("mp3".as_ref())
+    #[test]
+    fn ignores_status_changes() {
+        System::new("test").block_on(async {
+            let saves = Arc::new(CountingPersistence::default());
+            let state = State::try_new_with(Arc::new(Arc::clone(&saves)))
+                .await
+                .unwrap();
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": "main",
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                        },
+                        "outputs": (0..50)
+                            .map(|n| json!({"dst": format!("rtmp://a/{}", n)}))
+                            .collect::<Vec<_>>(),
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+            wait_until("added restream persisting", || {
+                saves.is_synced_with(&state)
+            })
+            .await;
+            let saved = saves.saves();
+            let ids = {
+                let restreams = state.restreams.lock_ref();
+                iter::once(restreams[0].input.endpoints[0].id.into())
+                    .chain(restreams[0].outputs.iter().map(|o| o.id.into()))
+                    .collect::<Vec<Uuid>>()
+            };
+
+            // Flapping `Status`es, as they do under churn.
+            for n in 0..10_000 {
+                let status = if n % 2 == 0 {
+                    Status::Online
+                } else {
+                    Status::Offline
+                };
+                let mut statuses =
+                    state.statuses.lock_mut("ignores_status_changes");
+                for id in &ids {
+                    let _ = statuses.set(*id, status);
+                }
+                drop(statuses);
+                if n % 100 == 0 {
+                    task::yield_now().await;
                 }
-                _ => false,
             }
+
+            // Persisted change goes after any ones caused by the flapping, so
+            // once it's persisted, all of them would have been too.
+            state.restreams.lock_mut()[0].outputs[0].enabled = true;
+            wait_until("enabled output persisting", || {
+                saves.is_synced_with(&state)
+            })
+            .await;
+            assert_eq!(saves.saves(), saved + 1);
+        });
     }
-}
 
-impl<'de> Deserialize<'de> for MixinSrcUrl {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(Url::deserialize(deserializer)?).map_err(|url| {
-            D::Error::custom(format!("Not a valid Mixin.src URL: {}", url))
-        })
+    /// Waits until the given `cond`ition holds, polling it periodically.
+    async fn wait_until<F: FnMut() -> bool>(what: &str, mut cond: F) {
+        for _ in 0..400 {
+            if cond() {
+                return;
+            }
+            time::delay_for(Duration::from_millis(50)).await;
+        }
+        panic!("Timed out waiting {}", what);
+    }
+
+    #[test]
+    fn reports_failing_persistence_and_recovers() {
+        let dir = temp_path().with_extension("d");
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("state.json");
+
+        System::new("test").block_on(async {
+            let state = State::try_new(&path).await.unwrap();
+            wait_until("initial persisting", || {
+                fs::read_to_string(&path).map_or(false, |s| !s.is_empty())
+            })
+            .await;
+
+            // Regular file in place of the directory makes the path
+            // unwritable even for `root`.
+            fs::remove_dir_all(&dir).unwrap();
+            fs::write(&dir, b"").unwrap();
+            state.settings.lock_mut().title = Some("lost".into());
+            wait_until("unhealthy persistence", || {
+                !state.persistence_health.lock_ref().is_healthy()
+            })
+            .await;
+            assert!(state.persistence_health.lock_ref().error().is_some());
+
+            state.settings.lock_mut().title = Some("latest".into());
+            fs::remove_file(&dir).unwrap();
+            fs::create_dir(&dir).unwrap();
+            wait_until("recovered persistence", || {
+                *state.persistence_health.lock_ref()
+                    == PersistenceHealth::default()
+            })
+            .await;
+            let persisted = fs::read_to_string(&path).unwrap();
+            assert!(persisted.contains("latest"), "{}", persisted);
+        });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn in_memory_backend_starts_empty() {
+        System::new("test").block_on(async {
+            let state = State::try_new_with(Arc::new(InMemoryPersistence))
+                .await
+                .unwrap();
+            assert!(state.restreams.get_cloned().is_empty());
+
+            InMemoryPersistence.save(b"garbage".to_vec()).await.unwrap();
+            assert!(InMemoryPersistence.load().await.unwrap().is_empty());
+        });
     }
 }
 
-/// Type of a `Mixin.src` URL.
-///
-/// Only the following URLs are allowed at the moment:
-/// - [TeamSpeak] URL (starting with `ts://` scheme and having a host);
-/// - [MP3] HTTP URL (starting with `http://` or `https://` scheme, having a
-///   host and `.mp3` extension in its path).
-///
-/// [MP3]: https://en.wikipedia.org/wiki/MP3
-/// [TeamSpeak]: https://teamspeak.com
-#[graphql_scalar]
-impl<S> GraphQLScalar for MixinSrcUrl
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+#[cfg(test)]
+mod transaction_spec {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use actix_web::rt::System;
+    use futures::future;
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::spec;
+
+    use super::{Quota, Rejected, RestreamGroup, State};
+
+    fn import(count: usize) -> spec::v1::Spec {
+        serde_json::from_value::<spec::Spec>(json!({
+            "version": "v1",
+            "restreams": (0..count).map(|n| json!({
+                "key": format!("imported{}", n),
+                "group": "imported",
+                "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                "outputs": [{"dst": format!("rtmp://example.com/{}", n)}],
+            })).collect::<Vec<_>>(),
+        }))
+        .unwrap()
+        .into_v1()
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(|s| Url::parse(s).ok())
-            .and_then(|url| Self::new(url).ok())
+    /// Subscribes to the [`State::restreams`] the same way the re-streaming
+    /// pool does, recording the number of [`Restream`]s it's called with.
+    ///
+    /// [`Restream`]: super::Restream
+    fn subscribe(state: &State) -> Arc<Mutex<Vec<usize>>> {
+        let applies = Arc::new(Mutex::new(vec![]));
+        let hook_applies = Arc::clone(&applies);
+        state.on_restreams_change("count_applies", move |restreams, _| {
+            hook_applies.lock().unwrap().push(restreams.len());
+            future::ready(())
+        });
+        applies
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    /// Awaits the given `applies` to be recorded `count` times, failing after
+    /// a deadline.
+    async fn applied(applies: &Mutex<Vec<usize>>, count: usize) -> Vec<usize> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let applies = applies.lock().unwrap().clone();
+            if applies.len() >= count {
+                return applies;
+            }
+            assert!(Instant::now() < deadline, "applied {:?} only", applies);
+            time::delay_for(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[test]
+    fn applies_import_at_once() {
+        System::new("test").block_on(async {
+            let state = State::new_in_memory();
+            let applies = subscribe(&state);
+            assert_eq!(applied(&applies, 1).await, vec![0]);
+
+            state.apply_checked(import(100), false).unwrap();
+            assert_eq!(applied(&applies, 2).await, vec![0, 100]);
+
+            let group = RestreamGroup::new("imported").unwrap();
+            assert_eq!(state.enable_group_outputs(&group), Some(true));
+
+            // Any excessive apply of the import would precede this one.
+            assert_eq!(applied(&applies, 3).await, vec![0, 100, 100]);
+            assert!(state
+                .restreams
+                .lock_ref()
+                .iter()
+                .all(|r| r.outputs[0].enabled));
+        });
+    }
+
+    #[test]
+    fn commits_nothing_on_rejection() {
+        System::new("test").block_on(async {
+            let state = State::new_in_memory();
+            state.quota.set(Quota {
+                max_restreams: Some(50),
+                max_outputs_per_restream: None,
+            });
+            let applies = subscribe(&state);
+            assert_eq!(applied(&applies, 1).await, vec![0]);
+
+            match state.apply_checked(import(100), false).unwrap_err() {
+                Rejected::Quota(e) => assert_eq!(e.excess(), 50),
+                e => panic!("unexpected error: {}", e),
+            }
+            assert!(state.restreams.lock_ref().is_empty());
+
+            state.apply_checked(import(10), false).unwrap();
+            assert_eq!(applied(&applies, 2).await, vec![0, 10]);
+        });
     }
 }
 
-/// Status indicating availability of an `Input`, `Output`, or a `Mixin`.
-#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, PartialEq, SmartDefault)]
-pub enum Status {
-    /// Inactive, no operations are performed and no media traffic is flowed.
-    #[default]
-    Offline,
+#[cfg(test)]
+mod hooks_spec {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
 
-    /// Initializing, media traffic doesn't yet flow as expected.
-    Initializing,
+    use actix_web::rt::System;
+    use futures::future;
+    use futures_signals::signal::Mutable;
+    use tokio::time;
 
-    /// Active, all operations are performing successfully and media traffic
-    /// flows as expected.
-    Online,
-}
+    use super::{HookPanics, State};
 
-/// Label of a [`Restream`] or an [`Output`].
-#[derive(Clone, Debug, Deref, Display, Eq, Into, PartialEq, Serialize)]
-pub struct Label(String);
+    #[test]
+    fn survives_panic() {
+        System::new("test").block_on(async {
+            let val = Mutable::new(0);
+            let received = Arc::new(Mutex::new(vec![]));
+            let hook_received = Arc::clone(&received);
+            State::on_change("panicking", &val, move |v| {
+                hook_received.lock().unwrap().push(v);
+                assert_ne!(v, 0, "first value");
+                future::ready(())
+            });
+            wait_received(&received, &[0]).await;
+
+            val.set(1);
+            wait_received(&received, &[0, 1]).await;
+            val.set(2);
+            wait_received(&received, &[0, 1, 2]).await;
+        });
+    }
 
-impl Label {
-    /// Creates a new [`Label`] if the given value meets its invariants.
-    #[must_use]
-    pub fn new<'s, S: Into<Cow<'s, str>>>(val: S) -> Option<Self> {
-        static REGEX: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"^[^,\n\t\r\f\v]{1,70}$").unwrap());
+    /// Waits until the hook has `received` exactly the `expected` values.
+    async fn wait_received(received: &Mutex<Vec<i32>>, expected: &[i32]) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *received.lock().unwrap() != expected {
+            assert!(Instant::now() < deadline, "{:?}", received.lock());
+            time::delay_for(Duration::from_millis(1)).await;
+        }
+    }
 
-        let val = val.into();
-        (!val.is_empty() && REGEX.is_match(&val))
-            .then(|| Self(val.into_owned()))
+    #[test]
+    fn exceeds_max_panics_per_window_only() {
+        let mut panics = HookPanics::default();
+        let now = Instant::now();
+        for n in 0..HookPanics::MAX {
+            assert!(!panics.exceeded(now + Duration::from_secs(n as u64)));
+        }
+
+        assert!(!panics.exceeded(now + HookPanics::WINDOW));
+        assert!(panics.exceeded(now + HookPanics::WINDOW));
     }
 }
 
-impl<'de> Deserialize<'de> for Label {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Self::new(<Cow<'_, str>>::deserialize(deserializer)?)
-            .ok_or_else(|| D::Error::custom("Not a valid Label"))
+#[cfg(test)]
+mod subscriptions_spec {
+    use std::time::Duration;
+
+    use actix_web::rt::System;
+    use futures::{FutureExt as _, StreamExt as _};
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::{extension::EntityKind, spec};
+
+    use super::{Label, State, Status};
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "main",
+                    "input": {
+                        "key": "origin",
+                        "endpoints": [{"kind": "rtmp"}],
+                        "enabled": true,
+                    },
+                    "outputs": [{
+                        "dst": "rtmp://example.com/live/stream",
+                        "enabled": true,
+                    }],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        state
     }
-}
 
-/// Type of a `Restream` or an `Output` label.
-///
-/// It should meet `[^,\n\t\r\f\v]{1,70}` format.
-#[graphql_scalar]
-impl<S> GraphQLScalar for Label
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.0.as_str().to_owned())
+    #[test]
+    fn emits_current_snapshot_first() {
+        let state = state();
+        let mut changes = Box::pin(state.subscribe_changes());
+
+        let snapshot = changes.next().now_or_never().unwrap().unwrap();
+        assert_eq!(snapshot, state.snapshot());
+        assert!(changes.next().now_or_never().is_none());
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_str)
-            .and_then(Self::new)
+    #[test]
+    fn dedupes_non_persisted_changes() {
+        let state = state();
+        let mut changes = Box::pin(state.subscribe_changes());
+        let _ = changes.next().now_or_never();
+
+        let id = state.restreams.lock_ref()[0].outputs[0].id;
+        let _ = state.statuses.set(id, Status::Online);
+        assert!(changes.next().now_or_never().is_none());
+
+        state.settings.lock_mut().title = Some("Changed".into());
+        assert!(changes.next().now_or_never().is_some());
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn emits_latest_snapshot_only() {
+        let state = state();
+        let mut changes = Box::pin(state.subscribe_changes());
+        let _ = changes.next().now_or_never();
+
+        for n in 0..3 {
+            state.restreams.lock_mut()[0].label =
+                Label::new(format!("Label {}", n));
+        }
+
+        let snapshot = changes.next().now_or_never().unwrap().unwrap();
+        assert_eq!(snapshot.restreams[0].label, Label::new("Label 2").ok());
+        assert!(changes.next().now_or_never().is_none());
+    }
+
+    #[test]
+    fn emits_status_events_since_subscription() {
+        System::new("test").block_on(async {
+            let state = state();
+            let (endpoint, output) = {
+                let restreams = state.restreams.lock_ref();
+                (
+                    restreams[0].input.endpoints[0].id,
+                    restreams[0].outputs[0].id,
+                )
+            };
+            let _ = state.statuses.set(output, Status::Online);
+            let mut events = Box::pin(state.subscribe_status_events());
+
+            // Changed back and forth before being observed.
+            let _ = state.statuses.set(output, Status::Offline);
+            let _ = state.statuses.set(output, Status::Online);
+            let _ = state.statuses.set(endpoint, Status::Online);
+
+            let timeout = Duration::from_secs(1);
+            let ev = time::timeout(timeout, events.next()).await.unwrap();
+            let ev = ev.unwrap();
+            assert_eq!(ev.kind, EntityKind::Input);
+            assert_eq!(ev.previous, Status::Offline);
+            assert_eq!(ev.status, Status::Online);
+            assert!(events.next().now_or_never().is_none());
+
+            let _ = state.statuses.set(output, Status::Offline);
+            let ev = time::timeout(timeout, events.next()).await.unwrap();
+            let ev = ev.unwrap();
+            assert_eq!(ev.kind, EntityKind::Output);
+            assert_eq!(ev.previous, Status::Online);
+            assert_eq!(ev.status, Status::Offline);
+            assert!(events.next().now_or_never().is_none());
+        });
     }
 }
 
-/// Volume rate of an audio track in percents.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Eq,
-    Ord,
-    PartialEq,
-    PartialOrd,
-    Serialize,
-    SmartDefault,
-)]
-pub struct Volume(#[default(Self::ORIGIN.0)] u16);
+#[cfg(test)]
+mod pending_dst_spec {
+    use std::time::{Duration, SystemTime};
 
-impl Volume {
-    /// Maximum possible value of a [`Volume`] rate.
-    pub const MAX: Volume = Volume(1000);
+    use serde_json::json;
+    use url::Url;
 
-    /// Value of a [`Volume`] rate corresponding to the original one of an audio
-    /// track.
-    pub const ORIGIN: Volume = Volume(100);
+    use crate::{api::graphql::DateTime, spec};
 
-    /// Minimum possible value of a [`Volume`] rate. Actually, disables audio.
-    pub const OFF: Volume = Volume(0);
+    use super::{
+        Duplicate, OutputDstUrl, OutputId, PendingDstError, Restream,
+        RestreamId, State,
+    };
 
-    /// Creates a new [`Volume`] rate value if it satisfies the required
-    /// invariants:
-    /// - within [`Volume::OFF`] and [`Volume::MAX`] values.
-    #[must_use]
-    pub fn new<N: TryInto<u16>>(num: N) -> Option<Self> {
-        let num = num.try_into().ok()?;
-        if (Self::OFF.0..=Self::MAX.0).contains(&num) {
-            Some(Self(num))
-        } else {
-            None
-        }
+    fn dst(url: &str) -> OutputDstUrl {
+        OutputDstUrl::new(Url::parse(url).unwrap()).unwrap()
     }
 
-    /// Displays this [`Volume`] as a fraction of `1`, i.e. `100%` as `1`, `50%`
-    /// as `0.50`, and so on.
-    #[must_use]
-    pub fn display_as_fraction(self) -> String {
-        format!("{}.{:02}", self.0 / 100, self.0 % 100)
+    fn state() -> (State, RestreamId, Vec<OutputId>) {
+        let state = State::new_in_memory();
+        state
+            .add_restream(
+                serde_json::from_value::<spec::v1::Restream>(json!({
+                    "key": "main",
+                    "input": {"key": "origin", "endpoints": [{"kind": "rtmp"}]},
+                    "outputs": [
+                        {"dst": "rtmp://a/live/old"},
+                        {"dst": "rtmp://b/live/key"},
+                    ],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let (id, outputs) = {
+            let restreams = state.restreams.lock_ref();
+            let r = &restreams[0];
+            (r.id, r.outputs.iter().map(|o| o.id).collect())
+        };
+        (state, id, outputs)
     }
 
-    /// Indicates whether this [`Volume`] rate value corresponds is the
-    /// [`Volume::ORIGIN`]al one.
-    #[allow(clippy::trivially_copy_pass_by_ref)] // required for `serde`
-    #[inline]
-    #[must_use]
-    pub fn is_origin(&self) -> bool {
-        *self == Self::ORIGIN
+    fn output_dsts(state: &State) -> Vec<(String, Option<String>)> {
+        state.restreams.lock_ref()[0]
+            .outputs
+            .iter()
+            .map(|o| {
+                (
+                    o.dst.to_string(),
+                    o.pending_dst.as_ref().map(ToString::to_string),
+                )
+            })
+            .collect()
     }
-}
 
-/// Type a volume rate of audio track in percents.
-///
-/// It's values are always within range of `0` and `1000` (inclusively).
-///
-/// `0` means disabled audio.
-#[graphql_scalar]
-impl<S> GraphQLScalar for Volume
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(i32::from(self.0))
+    #[test]
+    fn schedules_and_cancels() {
+        let (state, id, outputs) = state();
+
+        let res = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            None,
+        );
+        assert_eq!(res.unwrap(), Some(true));
+        let res = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            None,
+        );
+        assert_eq!(res.unwrap(), Some(false));
+        assert_eq!(
+            output_dsts(&state)[0],
+            ("rtmp://a/live/old".into(), Some("rtmp://a/live/new".into())),
+        );
+
+        assert_eq!(state.cancel_output_dst_change(id, outputs[0]), Some(true));
+        assert_eq!(state.cancel_output_dst_change(id, outputs[0]), Some(false));
+        assert_eq!(output_dsts(&state)[0].1, None);
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_int)
-            .and_then(Self::new)
+    #[test]
+    fn rejects_duplicates() {
+        let (state, id, outputs) = state();
+
+        let res = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://b/live/key"),
+            None,
+        );
+        assert!(
+            matches!(
+                res,
+                Err(PendingDstError::Duplicate(Duplicate::Output { .. })),
+            ),
+            "{:?}",
+            res,
+        );
+        assert_eq!(output_dsts(&state)[0].1, None);
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn rejects_same_dst() {
+        let (state, id, outputs) = state();
+
+        let res = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/old"),
+            None,
+        );
+        assert!(matches!(res, Err(PendingDstError::SameDst)), "{:?}", res);
+        assert_eq!(output_dsts(&state)[0].1, None);
     }
-}
 
-/// Delay of a [`Mixin`] being mixed with an [`Output`].
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Default,
-    Eq,
-    Ord,
-    PartialEq,
-    PartialOrd,
-    Serialize,
-)]
-pub struct Delay(#[serde(with = "serde_humantime")] Duration);
+    #[test]
+    fn applies_only_due_changes() {
+        let (state, id, outputs) = state();
+        let future =
+            DateTime::from(SystemTime::now() + Duration::from_secs(60));
+        let past = DateTime::from(SystemTime::now() - Duration::from_secs(60));
+
+        let _ = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            Some(future),
+        );
+        assert_eq!(
+            state
+                .apply_pending_output_dst(id, outputs[0], true)
+                .unwrap(),
+            Some(false),
+        );
 
-impl Delay {
-    /// Creates a new [`Delay`] out of the given milliseconds.
-    #[inline]
-    #[must_use]
-    pub fn from_millis<N: TryInto<u64>>(millis: N) -> Option<Self> {
-        millis
-            .try_into()
-            .ok()
-            .map(|m| Self(Duration::from_millis(m)))
+        let _ = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            Some(past),
+        );
+        assert_eq!(
+            state
+                .apply_pending_output_dst(id, outputs[0], true)
+                .unwrap(),
+            Some(true),
+        );
+        assert_eq!(output_dsts(&state)[0], ("rtmp://a/live/new".into(), None),);
+        assert_eq!(state.restreams.lock_ref()[0].outputs[0].apply_at, None);
     }
 
-    /// Returns milliseconds of this [`Delay`].
-    #[inline]
-    #[must_use]
-    #[allow(clippy::missing_panics_doc)]
-    pub fn as_millis(&self) -> i32 {
-        self.0.as_millis().try_into().unwrap()
+    #[test]
+    fn applies_manually_and_keeps_conflicting_ones() {
+        let (state, id, outputs) = state();
+
+        let _ = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            None,
+        );
+        assert_eq!(
+            state
+                .apply_pending_output_dst(id, outputs[0], true)
+                .unwrap(),
+            Some(false),
+        );
+
+        // Another `Output` takes the pending URL in the meantime.
+        state.restreams.lock_mut()[0].outputs[1].dst = dst("rtmp://a/live/new");
+        let res = state.apply_pending_output_dst(id, outputs[0], false);
+        assert!(matches!(res, Err(Duplicate::Output { .. })), "{:?}", res);
+        assert_eq!(output_dsts(&state)[0].1, Some("rtmp://a/live/new".into()),);
+
+        state.restreams.lock_mut()[0].outputs[1].dst = dst("rtmp://b/live/key");
+        assert_eq!(
+            state
+                .apply_pending_output_dst(id, outputs[0], false)
+                .unwrap(),
+            Some(true),
+        );
+        assert_eq!(output_dsts(&state)[0].0, "rtmp://a/live/new");
     }
 
-    /// Indicates whether this [`Delay`] introduces no actual delay.
-    #[inline]
-    #[must_use]
-    pub fn is_zero(&self) -> bool {
-        self.0 == Duration::default()
+    #[test]
+    fn is_persisted() {
+        let (state, id, outputs) = state();
+        let at = DateTime::from(SystemTime::now() + Duration::from_secs(60));
+        let _ = state.schedule_output_dst_change(
+            id,
+            outputs[0],
+            dst("rtmp://a/live/new"),
+            Some(at),
+        );
+
+        let json =
+            serde_json::to_value(&state.restreams.lock_ref()[0]).unwrap();
+        let restored = serde_json::from_value::<Restream>(json).unwrap();
+        let output = &restored.outputs[0];
+        assert_eq!(output.pending_dst, Some(dst("rtmp://a/live/new")));
+        assert_eq!(
+            output.apply_at.map(|at| at.to_rfc3339()),
+            Some(at.to_rfc3339()),
+        );
     }
 }
 
-/// Type of a `Mixin` delay in milliseconds.
-///
-/// Negative values are not allowed.
-#[graphql_scalar]
-impl<S> GraphQLScalar for Delay
-where
-    S: ScalarValue,
-{
-    fn resolve(&self) -> Value {
-        Value::scalar(self.as_millis())
+#[cfg(test)]
+mod restream_workflow_spec {
+    use serde_json::json;
+
+    use crate::workflow::WorkflowStage;
+
+    use super::{RestreamId, State};
+
+    fn state() -> (State, RestreamId) {
+        let state = State::with_restream("main", json!({}));
+        let id = state.restreams.lock_ref()[0].id;
+        (state, id)
     }
 
-    fn from_input_value(v: &InputValue) -> Option<Self> {
-        v.as_scalar()
-            .and_then(ScalarValue::as_int)
-            .and_then(Self::from_millis)
+    fn stages(state: &State) -> (WorkflowStage, Vec<WorkflowStage>) {
+        let workflow = &state.restreams.lock_ref()[0].workflow;
+        (
+            workflow.stage,
+            workflow.steps.iter().map(|s| s.stage).collect(),
+        )
     }
 
-    fn from_str(value: ScalarToken<'_>) -> ParseScalarResult<'_, S> {
-        <String as ParseScalarValue<S>>::from_str(value)
+    #[test]
+    fn advances_by_operator_until_wrapped() {
+        let (state, id) = state();
+
+        for _ in 0..4 {
+            assert_eq!(state.advance_restream_workflow(id), Some(true));
+        }
+        assert_eq!(state.advance_restream_workflow(id), Some(false));
+        assert_eq!(stages(&state).0, WorkflowStage::Wrapped);
+        assert!(state.restreams.lock_ref()[0]
+            .workflow
+            .steps
+            .iter()
+            .all(|s| !s.auto));
+
+        assert_eq!(state.advance_restream_workflow(RestreamId::random()), None);
     }
-}
 
-#[cfg(test)]
-mod volume_spec {
-    use super::Volume;
+    #[test]
+    fn resets_to_planned() {
+        let (state, id) = state();
+        assert_eq!(state.reset_restream_workflow(id), Some(false));
+
+        let _ = state.advance_restream_workflow(id);
+        assert_eq!(
+            stages(&state),
+            (
+                WorkflowStage::InputConfirmed,
+                vec![WorkflowStage::InputConfirmed],
+            ),
+        );
+
+        assert_eq!(state.reset_restream_workflow(id), Some(true));
+        assert_eq!(stages(&state), (WorkflowStage::Planned, vec![]));
+        assert_eq!(state.reset_restream_workflow(RestreamId::random()), None);
+    }
 
     #[test]
-    fn displays_as_fraction() {
-        for (input, expected) in &[
-            (1, "0.01"),
-            (10, "0.10"),
-            (200, "2.00"),
-            (107, "1.07"),
-            (170, "1.70"),
-            (1000, "10.00"),
-        ] {
-            let actual = Volume::new(*input).unwrap().display_as_fraction();
-            assert_eq!(&actual, *expected);
-        }
+    fn sets_auto_confirm() {
+        let (state, id) = state();
+
+        assert_eq!(
+            state.set_restream_workflow_auto_confirm(id, true),
+            Some(true),
+        );
+        assert_eq!(
+            state.set_restream_workflow_auto_confirm(id, true),
+            Some(false),
+        );
+        assert!(state.restreams.lock_ref()[0].workflow.auto_confirm_input);
+
+        assert_eq!(
+            state.set_restream_workflow_auto_confirm(id, false),
+            Some(true),
+        );
+        assert_eq!(
+            state
+                .set_restream_workflow_auto_confirm(RestreamId::random(), true),
+            None,
+        );
     }
 }
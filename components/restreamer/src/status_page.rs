@@ -0,0 +1,148 @@
+//! Public read-only status page of this server.
+//!
+//! Lists only [`Restream::label`]s and online statuses of [`Restream`]s marked
+//! as [`Restream::public`], never exposing any keys or URLs.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use askama::Template;
+
+use crate::state::{Restream, State, Statuses};
+
+/// Rendered public status page, cached by [`State::restreams_version()`], so
+/// refreshing it doesn't re-render anything until [`State::restreams`]
+/// change.
+#[derive(Clone, Debug, Default)]
+pub struct Cache(Arc<Mutex<Option<(String, Arc<str>)>>>);
+
+impl Cache {
+    /// Returns the public status page of the given [`State`] along with the
+    /// [`State::restreams_version()`] it's rendered for, rendering it only if
+    /// the version has changed since the last time.
+    #[must_use]
+    pub fn get(&self, state: &State) -> (String, Arc<str>) {
+        let version = state.restreams_version();
+        let mut cached = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((v, page)) = &*cached {
+            if *v == version {
+                return (version, page.clone());
+            }
+        }
+        let page: Arc<str> =
+            render(&state.restreams.lock_ref(), &state.statuses.lock_ref())
+                .into();
+        *cached = Some((version.clone(), page.clone()));
+        (version, page)
+    }
+}
+
+/// Renders the public status page of the given [`Restream`]s having the given
+/// `statuses` as HTML.
+#[must_use]
+pub fn render(restreams: &[Restream], statuses: &Statuses) -> String {
+    Page {
+        restreams: restreams
+            .iter()
+            .filter(|r| r.public)
+            .map(|r| Entry {
+                label: r
+                    .label
+                    .as_ref()
+                    .map_or("Untitled stream", |l| l.as_str()),
+                online: r.is_live(statuses),
+            })
+            .collect(),
+    }
+    .render()
+    .unwrap()
+}
+
+/// Template of the public status page.
+#[derive(Debug, Template)]
+#[template(path = "status.html")]
+struct Page<'a> {
+    /// Entries of the public [`Restream`]s to show.
+    restreams: Vec<Entry<'a>>,
+}
+
+/// Entry of a single public [`Restream`] on the status page.
+#[derive(Debug)]
+struct Entry<'a> {
+    /// [`Restream::label`] to show.
+    label: &'a str,
+
+    /// Indicator whether the [`Restream`] is live at the moment (see
+    /// [`Restream::is_live()`]).
+    online: bool,
+}
+
+#[cfg(test)]
+mod status_page_spec {
+    use serde_json::json;
+
+    use crate::{spec, state::State};
+
+    use super::{render, Cache};
+
+    fn state() -> State {
+        let state = State::new_in_memory();
+        for (key, label, public) in &[
+            ("secret1", "Main <stage>", true),
+            ("secret2", "Backstage", false),
+        ] {
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": key,
+                        "label": label,
+                        "public": public,
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                            "enabled": true,
+                        },
+                        "outputs": [{
+                            "dst": format!("rtmp://example.com/{}/out", key),
+                            "enabled": true,
+                        }],
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn lists_only_public_restreams_labels() {
+        let state = state();
+        let page =
+            render(&state.restreams.lock_ref(), &state.statuses.lock_ref());
+
+        assert!(page.contains("Main &lt;stage&gt;"), "{}", page);
+        assert!(page.contains("Offline"), "{}", page);
+        assert!(!page.contains("Backstage"), "{}", page);
+        assert!(!page.contains("secret"), "{}", page);
+        assert!(!page.contains("example.com"), "{}", page);
+    }
+
+    #[test]
+    fn rerenders_only_on_changes() {
+        let state = state();
+        let cache = Cache::default();
+
+        let (version, page) = cache.get(&state);
+        let (same_version, same_page) = cache.get(&state);
+        assert_eq!(version, same_version);
+        assert!(std::sync::Arc::ptr_eq(&page, &same_page));
+
+        for r in state.restreams.lock_mut().iter_mut() {
+            r.public = true;
+        }
+        // In-memory `State` doesn't invalidate its version on its own.
+        state.restreams_version.invalidate();
+        let (new_version, new_page) = cache.get(&state);
+        assert_ne!(version, new_version);
+        assert!(new_page.contains("Backstage"), "{}", new_page);
+    }
+}
@@ -0,0 +1,650 @@
+//! Sweeping of leftovers of removed [`state::Restream`]s.
+//!
+//! Removing a [`state::Restream`] stops its re-streaming, but [SRS] may still
+//! hold clients connected to its app, and this application may leave behind
+//! the files it has written for it ([DVR] recordings, [HLS] segments, uploaded
+//! overlay images, rolling buffers). Most of them are cleaned up as soon as
+//! the [`state::Restream`] is removed, while [`sweep_periodically()`] is a
+//! backstop reclaiming anything left behind, so it doesn't accumulate on
+//! long-lived servers.
+//!
+//! Only the direct entries of the directories this application writes into
+//! are ever considered, and only the ones named the way this application
+//! names them, so nothing put there by someone else is touched. Symbolic links
+//! are never followed.
+//!
+//! [DVR]: https://en.wikipedia.org/wiki/Digital_video_recorder
+//! [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+//! [SRS]: https://github.com/ossrs/srs
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use ephyr_log::log;
+use futures::future;
+use juniper::{GraphQLEnum, GraphQLObject};
+use tokio::{fs, time};
+use uuid::Uuid;
+
+use crate::{
+    api::srs::ClientInfo,
+    cli, dvr,
+    evict::SrsApi,
+    srs,
+    state::{self, OutputId, RestreamId, RestreamKey, State},
+};
+
+/// Kind of a `Leftover`.
+#[derive(Clone, Copy, Debug, Eq, GraphQLEnum, Hash, PartialEq)]
+pub enum LeftoverKind {
+    /// [SRS] client connected to an app of no `Restream`.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    SrsClient,
+
+    /// Directory of [DVR] files of an `Output` not existing anymore.
+    ///
+    /// [DVR]: https://en.wikipedia.org/wiki/Digital_video_recorder
+    Dvr,
+
+    /// Directory of [HLS] files of an app not being served via [HLS] anymore.
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    Hls,
+
+    /// Uploaded overlay image of an `Output` not existing anymore.
+    Overlay,
+
+    /// Directory of a rolling buffer of a `Restream` not existing anymore.
+    Buffer,
+}
+
+/// Leftover of a removed `Restream`, reclaimable by sweeping.
+#[derive(Clone, Debug, Eq, GraphQLObject, PartialEq)]
+pub struct Leftover {
+    /// Kind of this `Leftover`.
+    pub kind: LeftoverKind,
+
+    /// Absolute path of the leftover file or directory, or URL path the
+    /// leftover SRS client is connected to (like `/{app}/{stream}`).
+    pub location: String,
+
+    /// ID of the [SRS] client to kick off, if this [`Leftover`] is the one.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[graphql(skip)]
+    pub client_id: Option<String>,
+}
+
+impl Leftover {
+    /// Creates a new [`Leftover`] of the given `kind` located by the given
+    /// `path`.
+    #[inline]
+    #[must_use]
+    fn file(kind: LeftoverKind, path: &Path) -> Self {
+        Self {
+            kind,
+            location: path.display().to_string(),
+            client_id: None,
+        }
+    }
+
+    /// Creates a new [`Leftover`] out of the given [SRS] client.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    fn client(info: ClientInfo) -> Self {
+        Self {
+            kind: LeftoverKind::SrsClient,
+            location: info.url,
+            client_id: Some(info.id),
+        }
+    }
+}
+
+/// Directories this application writes files of [`state::Restream`]s into.
+#[derive(Clone, Debug)]
+pub struct Dirs {
+    /// Root directory of [`dvr::Storage`].
+    pub dvr: PathBuf,
+
+    /// Directory [SRS] writes [HLS] files into (see [`srs::HLS_DIR`]).
+    ///
+    /// [HLS]: https://en.wikipedia.org/wiki/HTTP_Live_Streaming
+    /// [SRS]: https://github.com/ossrs/srs
+    pub hls: PathBuf,
+
+    /// Directory of uploaded overlay images (see [`cli::Opts::overlays_dir`]).
+    pub overlays: PathBuf,
+
+    /// Directory of rolling buffers (see [`cli::Opts::buffers_dir`]).
+    pub buffers: PathBuf,
+}
+
+impl Dirs {
+    /// Returns [`Dirs`] configured by the given [`cli::Opts`].
+    ///
+    /// # Panics
+    ///
+    /// If the global [`dvr::Storage`] hasn't been initialized yet.
+    #[must_use]
+    pub fn new(cfg: &cli::Opts) -> Self {
+        Self {
+            dvr: dvr::Storage::global().root_path.clone(),
+            hls: cfg.srs_http_dir.join(srs::HLS_DIR),
+            overlays: cfg.overlays_dir.clone(),
+            buffers: cfg.buffers_dir.clone(),
+        }
+    }
+}
+
+/// Finds all the [`Leftover`]s of the [`state::Restream`]s not being in the
+/// given [`State`] anymore, without reclaiming them.
+///
+/// Files of the [`state::Restream`]s being in the [`State::trash`] are not
+/// considered as [`Leftover`]s, as they may be restored.
+pub async fn find<A: SrsApi + ?Sized>(
+    api: &A,
+    dirs: &Dirs,
+    state: &State,
+) -> Vec<Leftover> {
+    // Candidates are listed before inspecting the `State`, so the files of a
+    // `Restream` added in the meantime are never considered as leftovers.
+    let clients = api.clients().await.unwrap_or_else(|e| {
+        log::warn!("Failed to list SRS clients for sweeping: {}", e);
+        vec![]
+    });
+    let dvr = list(&dirs.dvr, true).await;
+    let hls = list(&dirs.hls, true).await;
+    let overlays = list(&dirs.overlays, false).await;
+    let buffers = list(&dirs.buffers, true).await;
+
+    let (apps, hls_apps) = {
+        let restreams = state.restreams.lock_ref();
+        let apps: HashSet<_> =
+            restreams.iter().map(|r| r.key.to_string()).collect();
+        let hls_apps: HashSet<_> = restreams
+            .iter()
+            .filter(|r| r.srs_vhost_config().map_or(false, |v| v.hls))
+            .map(|r| r.key.to_string())
+            .collect();
+        (apps, hls_apps)
+    };
+    let (restreams, outputs) = {
+        let (restreams, trash) =
+            (state.restreams.lock_ref(), state.trash.lock_ref());
+        let kept = || restreams.iter().chain(trash.iter());
+        let ids: HashSet<_> = kept().map(|r| r.id).collect();
+        let outputs: HashSet<_> = kept()
+            .flat_map(|r| r.outputs.iter().map(|o| o.id))
+            .collect();
+        (ids, outputs)
+    };
+    let is_gone_output = |id: Uuid| !outputs.contains(&OutputId::from(id));
+
+    let mut leftovers: Vec<_> = clients
+        .into_iter()
+        .filter(|c| {
+            c.app_stream().map_or(false, |(app, _)| {
+                !apps.contains(app)
+                    && !RestreamKey::RESERVED_PREFIXES
+                        .iter()
+                        .any(|p| app.starts_with(p))
+            })
+        })
+        .map(Leftover::client)
+        .collect();
+    leftovers.extend(
+        dvr.iter()
+            .filter(|p| uuid_named(p, None).map_or(false, is_gone_output))
+            .map(|p| Leftover::file(LeftoverKind::Dvr, p)),
+    );
+    leftovers.extend(
+        hls.iter()
+            .filter(|p| {
+                file_name(p).map_or(false, |n| {
+                    RestreamKey::validate(n).is_ok() && !hls_apps.contains(n)
+                })
+            })
+            .map(|p| Leftover::file(LeftoverKind::Hls, p)),
+    );
+    leftovers.extend(
+        overlays
+            .iter()
+            .filter(|p| {
+                uuid_named(p, Some("png")).map_or(false, is_gone_output)
+            })
+            .map(|p| Leftover::file(LeftoverKind::Overlay, p)),
+    );
+    leftovers.extend(
+        buffers
+            .iter()
+            .filter(|p| {
+                uuid_named(p, None).map_or(false, |id| {
+                    !restreams.contains(&RestreamId::from(id))
+                })
+            })
+            .map(|p| Leftover::file(LeftoverKind::Buffer, p)),
+    );
+    leftovers
+}
+
+/// Reclaims the given [`Leftover`]s, kicking off [SRS] clients and removing
+/// files, and logs each reclaimed one.
+///
+/// Returns the number of the reclaimed [`Leftover`]s.
+///
+/// [SRS]: https://github.com/ossrs/srs
+pub async fn reclaim<A: SrsApi + ?Sized>(
+    api: &A,
+    leftovers: &[Leftover],
+) -> usize {
+    let reclaims = leftovers.iter().map(|l| async move {
+        let res = match (&l.client_id, l.kind) {
+            (Some(id), _) => api
+                .kickoff_client(id.clone())
+                .await
+                .map_err(|e| e.to_string()),
+            (None, LeftoverKind::Overlay) => fs::remove_file(&l.location)
+                .await
+                .map_err(|e| e.to_string()),
+            (None, _) => fs::remove_dir_all(&l.location)
+                .await
+                .map_err(|e| e.to_string()),
+        };
+        match res {
+            Ok(()) => {
+                log::info!("Reclaimed {:?} leftover {}", l.kind, l.location);
+                true
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to reclaim {:?} leftover {}: {}",
+                    l.kind,
+                    l.location,
+                    e,
+                );
+                false
+            }
+        }
+    });
+    future::join_all(reclaims)
+        .await
+        .into_iter()
+        .filter(|reclaimed| *reclaimed)
+        .count()
+}
+
+/// Spawns a background task reclaiming the [`Leftover`]s of the given
+/// [`State`] once per the given `interval`.
+pub fn sweep_periodically<A: SrsApi + 'static>(
+    api: A,
+    dirs: Dirs,
+    interval: Duration,
+    state: State,
+) {
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(interval).await;
+            let leftovers = find(&api, &dirs, &state).await;
+            if leftovers.is_empty() {
+                continue;
+            }
+            let reclaimed = reclaim(&api, &leftovers).await;
+            log::info!(
+                "Swept {} of {} leftover(s) of removed restreams",
+                reclaimed,
+                leftovers.len(),
+            );
+        }
+    }));
+}
+
+/// Watcher of [`state::Restream`]s, kicking off all the [SRS] clients still
+/// connected to the apps of the removed ones.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[derive(Debug)]
+pub struct RemovedAppsEvictor<A> {
+    /// [`SrsApi`] to evict [SRS] clients via.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    api: Arc<A>,
+
+    /// Apps of the [`state::Restream`]s seen on the previous
+    /// [`RemovedAppsEvictor::apply()`] call.
+    ///
+    /// [`None`] if there was no call yet.
+    apps: Option<HashSet<String>>,
+}
+
+impl<A: SrsApi + 'static> RemovedAppsEvictor<A> {
+    /// Creates a new [`RemovedAppsEvictor`] evicting [SRS] clients via the
+    /// given [`SrsApi`].
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    #[inline]
+    #[must_use]
+    pub fn new(api: A) -> Self {
+        Self {
+            api: Arc::new(api),
+            apps: None,
+        }
+    }
+
+    /// Evicts [SRS] clients of the apps of the [`state::Restream`]s which
+    /// have been removed from the given `restreams` since the previous call.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn apply(&mut self, restreams: &[state::Restream]) {
+        let apps: HashSet<_> =
+            restreams.iter().map(|r| r.key.to_string()).collect();
+
+        if let Some(prev) = &self.apps {
+            let removed: HashSet<_> = prev.difference(&apps).cloned().collect();
+            if !removed.is_empty() {
+                let api = Arc::clone(&self.api);
+                drop(tokio::spawn(async move {
+                    let _ = evict_apps(&*api, &removed).await;
+                }));
+            }
+        }
+        self.apps = Some(apps);
+    }
+}
+
+/// Kicks off all the [SRS] clients connected to any of the given `apps`.
+///
+/// Returns the number of the kicked clients.
+///
+/// [SRS]: https://github.com/ossrs/srs
+async fn evict_apps<A: SrsApi + ?Sized>(
+    api: &A,
+    apps: &HashSet<String>,
+) -> usize {
+    let leftovers: Vec<_> = match api.clients().await {
+        Ok(clients) => clients
+            .into_iter()
+            .filter(|c| {
+                c.app_stream().map_or(false, |(app, _)| apps.contains(app))
+            })
+            .map(Leftover::client)
+            .collect(),
+        Err(e) => {
+            log::warn!("Failed to list SRS clients for evicting: {}", e);
+            return 0;
+        }
+    };
+    reclaim(api, &leftovers).await
+}
+
+/// Lists the direct entries of the given `dir` being directories (if `dirs`
+/// is `true`) or regular files (otherwise).
+///
+/// Symbolic links are never listed.
+async fn list(dir: &Path, dirs: bool) -> Vec<PathBuf> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            log::warn!("Failed to read {} for sweeping: {}", dir.display(), e);
+            return vec![];
+        }
+    };
+    let mut paths = vec![];
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!(
+                    "Failed to read {} for sweeping: {}",
+                    dir.display(),
+                    e,
+                );
+                break;
+            }
+        };
+        // `DirEntry::file_type()` doesn't follow symbolic links.
+        let is_listed = entry.file_type().await.map_or(false, |t| {
+            if dirs {
+                t.is_dir()
+            } else {
+                t.is_file()
+            }
+        });
+        if is_listed {
+            paths.push(entry.path());
+        }
+    }
+    paths
+}
+
+/// Returns the file name of the given `path`, if it's a valid UTF-8 one.
+#[inline]
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()
+}
+
+/// Parses the [`Uuid`] the given `path` is named by, having the given
+/// extension, if any.
+fn uuid_named(path: &Path, ext: Option<&str>) -> Option<Uuid> {
+    let name = file_name(path)?;
+    let name = match ext {
+        Some(ext) => name.strip_suffix(ext)?.strip_suffix('.')?,
+        None => name,
+    };
+    Uuid::parse_str(name).ok()
+}
+
+#[cfg(test)]
+mod sweep_spec {
+    use std::{
+        env, fs,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use actix_web::rt::System;
+    use futures::future::{self, BoxFuture, FutureExt as _};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        api::srs::{self as srs_api, ClientInfo},
+        spec,
+        state::State,
+    };
+
+    use super::{find, reclaim, Dirs, LeftoverKind, SrsApi};
+
+    /// Mocked [`SrsApi`].
+    #[derive(Debug, Default)]
+    struct MockApi {
+        /// Clients connected to the mocked [SRS] server.
+        ///
+        /// [SRS]: https://github.com/ossrs/srs
+        clients: Vec<ClientInfo>,
+
+        /// IDs of the kicked clients.
+        kicked: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SrsApi for MockApi {
+        fn clients(
+            &self,
+        ) -> BoxFuture<'_, Result<Vec<ClientInfo>, srs_api::Error>> {
+            future::ok(self.clients.clone()).boxed()
+        }
+
+        fn kickoff_client(
+            &self,
+            id: String,
+        ) -> BoxFuture<'_, Result<(), srs_api::Error>> {
+            self.kicked.lock().unwrap().push(id);
+            future::ok(()).boxed()
+        }
+    }
+
+    fn client(id: &str, url: &str) -> ClientInfo {
+        ClientInfo {
+            id: id.into(),
+            url: url.into(),
+            publish: false,
+        }
+    }
+
+    fn dirs(root: &Path) -> Dirs {
+        let dirs = Dirs {
+            dvr: root.join("dvr"),
+            hls: root.join("hls"),
+            overlays: root.join("overlays"),
+            buffers: root.join("buffers"),
+        };
+        for dir in &[&dirs.dvr, &dirs.hls, &dirs.overlays, &dirs.buffers] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        dirs
+    }
+
+    #[test]
+    fn reclaims_leftovers_only() {
+        System::new("test").block_on(async {
+            let root =
+                env::temp_dir().join(format!("ephyr-sweep-{}", Uuid::new_v4()));
+            let dirs = dirs(&root);
+
+            let state = State::new_in_memory();
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": "main",
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                        },
+                        "outputs": [{"dst": "rtmp://a/live"}],
+                        "hls": true,
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+            let (restream_id, output_id) = {
+                let restreams = state.restreams.lock_ref();
+                (restreams[0].id, restreams[0].outputs[0].id)
+            };
+            let gone = Uuid::new_v4();
+
+            for dir in &[
+                dirs.dvr.join(output_id.to_string()),
+                dirs.dvr.join(gone.to_string()),
+                dirs.dvr.join("foreign"),
+                dirs.hls.join("main"),
+                dirs.hls.join("removed"),
+                dirs.buffers.join(restream_id.to_string()),
+                dirs.buffers.join(gone.to_string()),
+            ] {
+                fs::create_dir_all(dir).unwrap();
+            }
+            for file in &[
+                dirs.overlays.join(format!("{}.png", output_id)),
+                dirs.overlays.join(format!("{}.png", gone)),
+                dirs.overlays.join("foreign.png"),
+                // Only directories of DVR files are considered.
+                dirs.dvr.join(Uuid::new_v4().to_string()),
+            ] {
+                fs::write(file, b"").unwrap();
+            }
+
+            let api = MockApi {
+                clients: vec![
+                    client("1", "/main/origin"),
+                    client("2", "/removed/origin"),
+                    client("3", "/pull_main/origin"),
+                ],
+                ..MockApi::default()
+            };
+
+            let leftovers = find(&api, &dirs, &state).await;
+            let root_prefix = root.display().to_string();
+            let mut found: Vec<_> = leftovers
+                .iter()
+                .map(|l| (l.kind, l.location.replace(&root_prefix, "")))
+                .collect();
+            found.sort_by(|a, b| a.1.cmp(&b.1));
+            assert_eq!(
+                found,
+                vec![
+                    (LeftoverKind::Buffer, format!("/buffers/{}", gone)),
+                    (LeftoverKind::Dvr, format!("/dvr/{}", gone)),
+                    (LeftoverKind::Hls, "/hls/removed".into()),
+                    (LeftoverKind::Overlay, format!("/overlays/{}.png", gone),),
+                    (LeftoverKind::SrsClient, "/removed/origin".into()),
+                ],
+            );
+
+            assert_eq!(reclaim(&api, &leftovers).await, 5);
+            assert_eq!(*api.kicked.lock().unwrap(), vec!["2"]);
+            assert!(!dirs.dvr.join(gone.to_string()).exists());
+            assert!(dirs.dvr.join(output_id.to_string()).exists());
+            assert!(dirs.dvr.join("foreign").exists());
+            assert!(dirs.hls.join("main").exists());
+            assert!(dirs.overlays.join("foreign.png").exists());
+            // Mocked SRS server still reports the kicked client.
+            assert_eq!(find(&api, &dirs, &state).await.len(), 1);
+
+            fs::remove_dir_all(&root).unwrap();
+        });
+    }
+
+    #[test]
+    fn keeps_files_of_trashed_restreams() {
+        System::new("test").block_on(async {
+            let root =
+                env::temp_dir().join(format!("ephyr-sweep-{}", Uuid::new_v4()));
+            let dirs = dirs(&root);
+
+            let state = State::new_in_memory();
+            state
+                .add_restream(
+                    serde_json::from_value::<spec::v1::Restream>(json!({
+                        "key": "main",
+                        "input": {
+                            "key": "origin",
+                            "endpoints": [{"kind": "rtmp"}],
+                        },
+                        "outputs": [{"dst": "rtmp://a/live"}],
+                    }))
+                    .unwrap(),
+                )
+                .unwrap();
+            let (restream_id, output_id) = {
+                let restreams = state.restreams.lock_ref();
+                (restreams[0].id, restreams[0].outputs[0].id)
+            };
+            fs::create_dir_all(dirs.dvr.join(output_id.to_string())).unwrap();
+            fs::create_dir_all(dirs.buffers.join(restream_id.to_string()))
+                .unwrap();
+            let _ = state.remove_restream(restream_id, true).unwrap();
+
+            let leftovers = find(&MockApi::default(), &dirs, &state).await;
+            assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+            let _ = state.purge_restream(restream_id);
+            let kinds: Vec<_> = find(&MockApi::default(), &dirs, &state)
+                .await
+                .into_iter()
+                .map(|l| l.kind)
+                .collect();
+            assert_eq!(kinds, [LeftoverKind::Dvr, LeftoverKind::Buffer]);
+
+            fs::remove_dir_all(&root).unwrap();
+        });
+    }
+}
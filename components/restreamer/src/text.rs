@@ -0,0 +1,135 @@
+//! Validation of user-supplied text.
+//!
+//! Any text coming from users ends up in logs, generated configuration files
+//! and [FFmpeg] arguments, so should be checked before being accepted.
+//!
+//! [FFmpeg]: https://ffmpeg.org
+
+use derive_more::Display;
+use unicode_normalization::UnicodeNormalization as _;
+
+/// Maximum allowed length (in characters) of a single value of
+/// [`state::OutputMetadata`].
+///
+/// [`state::OutputMetadata`]: crate::state::OutputMetadata
+pub const MAX_METADATA_LEN: usize = 256;
+
+/// Maximum allowed length (in characters) of the server's title.
+pub const MAX_TITLE_LEN: usize = 70;
+
+/// Maximum allowed length (in characters) of a filesystem path.
+pub const MAX_PATH_LEN: usize = 4096;
+
+/// Violation of the rules a user-supplied text should follow.
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum Violation {
+    /// Text contains control characters (line breaks, tabs, etc).
+    #[display(fmt = "`{}` must not contain control characters", _0)]
+    ControlChars(&'static str),
+
+    /// Text is longer than the allowed maximum number of characters.
+    #[display(fmt = "`{}` must not be longer than {} characters", _0, _1)]
+    TooLong(&'static str, usize),
+
+    /// Text is empty, while is required not to be.
+    #[display(fmt = "`{}` must not be empty", _0)]
+    Empty(&'static str),
+
+    /// Text contains a character forbidden in its field.
+    #[display(fmt = "`{}` must not contain `{}` character", _0, _1)]
+    ForbiddenChar(&'static str, char),
+}
+
+impl Violation {
+    /// Returns name of the field containing the offending text.
+    #[inline]
+    #[must_use]
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::ControlChars(f)
+            | Self::TooLong(f, _)
+            | Self::Empty(f)
+            | Self::ForbiddenChar(f, _) => f,
+        }
+    }
+}
+
+/// Sanitizes the given user-supplied `text` of the given `field`, normalizing
+/// it to [Unicode NFC][1].
+///
+/// # Errors
+///
+/// If the normalized `text` violates the rules of [`check()`].
+///
+/// [1]: https://unicode.org/reports/tr15
+pub fn sanitize(
+    field: &'static str,
+    text: &str,
+    max_len: usize,
+) -> Result<String, Violation> {
+    let text: String = text.nfc().collect();
+    check(field, &text, max_len)?;
+    Ok(text)
+}
+
+/// Checks the given user-supplied `text` of the given `field` as it is.
+///
+/// Prefer [`sanitize()`] unless the `text` should be kept byte-exact (like a
+/// filesystem path).
+///
+/// # Errors
+///
+/// If the `text` contains control characters, or is longer than `max_len`
+/// characters.
+pub fn check(
+    field: &'static str,
+    text: &str,
+    max_len: usize,
+) -> Result<(), Violation> {
+    if text.chars().any(char::is_control) {
+        return Err(Violation::ControlChars(field));
+    }
+    if text.chars().count() > max_len {
+        return Err(Violation::TooLong(field, max_len));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod sanitize_spec {
+    use super::{check, sanitize, Violation};
+
+    #[test]
+    fn normalizes_to_nfc() {
+        let decomposed = "Cafe\u{301}";
+        assert_eq!(sanitize("title", decomposed, 10).unwrap(), "Café");
+        assert_eq!(
+            sanitize("title", decomposed, 4).unwrap().chars().count(),
+            4,
+        );
+    }
+
+    #[test]
+    fn denies_control_characters() {
+        for text in &["a\nb", "a\rb", "a\tb", "a\u{0}b", "a\u{1b}[31mb"] {
+            assert_eq!(
+                sanitize("title", text, 100),
+                Err(Violation::ControlChars("title")),
+            );
+        }
+        assert!(sanitize("title", "Tom & \"Jerry\" — live", 100).is_ok());
+    }
+
+    #[test]
+    fn denies_overlong_text() {
+        assert_eq!(
+            sanitize("comment", &"я".repeat(6), 5),
+            Err(Violation::TooLong("comment", 5)),
+        );
+        assert!(sanitize("comment", &"я".repeat(5), 5).is_ok());
+        assert_eq!(
+            check("path", &"a".repeat(6), 5).unwrap_err().field(),
+            "path",
+        );
+    }
+}
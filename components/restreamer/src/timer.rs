@@ -0,0 +1,158 @@
+//! Timers firing for entities of a [`State`] at moments derived from it.
+//!
+//! Watchers of time-bound [`State`] entities (like idle [`state::Input`]s or
+//! scheduled [`state::Output::pending_dst`]s) don't poll the [`State`].
+//! Instead, on each its change they describe which entities should have a
+//! timer running and with what, and [`Timers::rearm()`] starts the missing
+//! [`Timer`]s and drops the stale ones.
+//!
+//! [`state::Input`]: crate::state::Input
+//! [`state::Output::pending_dst`]: crate::state::Output::pending_dst
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, SystemTime},
+};
+
+use futures::future::{self, AbortHandle};
+use tokio::time;
+
+use crate::state::State;
+
+/// Task spawned to be run at some moment, cancelled when dropped.
+#[derive(Debug)]
+pub struct Timer(AbortHandle);
+
+impl Timer {
+    /// Spawns the given `task` to be run once the given wall-clock moment
+    /// comes (right away, if it has passed already).
+    #[must_use]
+    pub fn at<F>(at: SystemTime, task: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (abort, registration) = AbortHandle::new_pair();
+        drop(tokio::spawn(future::Abortable::new(
+            async move {
+                // Wall clock may drift away from the monotonic one, so ensure
+                // the moment has come indeed.
+                while let Ok(left) = at.duration_since(SystemTime::now()) {
+                    time::delay_for(left + Duration::from_millis(1)).await;
+                }
+                task();
+            },
+            registration,
+        )));
+        Self(abort)
+    }
+}
+
+impl Drop for Timer {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Running [`Timer`]s of [`State`] entities identified by `K`, each armed with
+/// a value `T` it has been started for (like a deadline).
+#[derive(Debug)]
+pub struct Timers<K, T> {
+    /// Running [`Timer`]s along with the values they're armed with.
+    timers: HashMap<K, (T, Timer)>,
+
+    /// Application [`State`] the [`Timer`]s fire in.
+    state: State,
+}
+
+impl<K, T> Timers<K, T>
+where
+    K: Copy + Eq + Hash + Send + 'static,
+    T: Copy + PartialEq + Send + 'static,
+{
+    /// Creates new empty [`Timers`] firing in the given [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self {
+            timers: HashMap::new(),
+            state,
+        }
+    }
+
+    /// Re-arms these [`Timers`] to match the `wanted` ones.
+    ///
+    /// Keeps the running [`Timer`]s armed with the same values, drops the
+    /// ones not wanted anymore or wanted with other values, and starts the
+    /// missing ones to call `fire` at their `deadline`.
+    pub fn rearm<D, F>(&mut self, wanted: HashMap<K, T>, deadline: D, fire: F)
+    where
+        D: Fn(&T) -> SystemTime,
+        F: Fn(&State, K, T) + Copy + Send + 'static,
+    {
+        self.timers
+            .retain(|key, (val, _)| wanted.get(key) == Some(val));
+
+        for (key, val) in wanted {
+            if self.timers.contains_key(&key) {
+                continue;
+            }
+            let state = self.state.clone();
+            let timer =
+                Timer::at(deadline(&val), move || fire(&state, key, val));
+            let _ = self.timers.insert(key, (val, timer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod timers_spec {
+    use std::{
+        collections::HashMap,
+        sync::Mutex,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use actix_web::rt::System;
+    use once_cell::sync::Lazy;
+    use tokio::time;
+
+    use crate::state::State;
+
+    use super::Timers;
+
+    /// Keys fired by [`Timers`] under test, along with their values.
+    static FIRED: Lazy<Mutex<Vec<(u8, u64)>>> = Lazy::new(Mutex::default);
+
+    fn fire(_: &State, key: u8, val: u64) {
+        FIRED.lock().unwrap().push((key, val));
+    }
+
+    fn after(millis: &u64) -> SystemTime {
+        SystemTime::now() + Duration::from_millis(*millis)
+    }
+
+    #[test]
+    fn rearms_changed_timers_only() {
+        System::new("test").block_on(async {
+            let mut timers = Timers::new(State::new_in_memory());
+
+            let wanted = |pairs: &[(u8, u64)]| {
+                pairs.iter().copied().collect::<HashMap<_, _>>()
+            };
+            timers.rearm(wanted(&[(1, 50), (2, 50), (3, 0)]), after, fire);
+            // Keeps the first, re-arms the second and drops the third.
+            timers.rearm(wanted(&[(1, 50), (2, 60)]), after, fire);
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while FIRED.lock().unwrap().len() < 2 {
+                assert!(Instant::now() < deadline, "{:?}", FIRED.lock());
+                time::delay_for(Duration::from_millis(1)).await;
+            }
+            let mut fired = FIRED.lock().unwrap().clone();
+            fired.sort_unstable();
+            assert_eq!(fired, vec![(1, 50), (2, 60)]);
+        });
+    }
+}
@@ -0,0 +1,251 @@
+//! Optional [tracing] instrumentation of the latency-sensitive paths of this
+//! application: mutations of [`State::restreams`], persisting of the
+//! [`State`], applying [`State::restreams`] to the [FFmpeg] processes pool,
+//! and handling [SRS] HTTP callbacks.
+//!
+//! Spans are emitted only when `tracing` feature is compiled in. Otherwise,
+//! everything in this module compiles down to no-ops: [`Span`] is a
+//! zero-sized type, and [`LockMut`] is just a [`MutableLockMut`].
+//!
+//! [`State`]: crate::State
+//! [`State::restreams`]: crate::State::restreams
+//! [FFmpeg]: https://ffmpeg.org
+//! [SRS]: https://github.com/ossrs/srs
+//! [tracing]: https://docs.rs/tracing
+
+#[cfg(feature = "tracing")]
+pub mod otlp;
+
+#[cfg(feature = "tracing")]
+use std::{convert::TryFrom as _, time::Instant};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use futures::Future;
+use futures_signals::signal::{Mutable, MutableLockMut};
+
+use crate::api::srs::callback;
+
+/// Span of an instrumented operation.
+///
+/// Is a zero-sized no-op, unless `tracing` feature is compiled in.
+#[derive(Clone, Debug)]
+pub struct Span {
+    /// Underlying [`tracing::Span`].
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+impl Span {
+    /// Executes the given function inside this [`Span`].
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub fn in_scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        self.inner.in_scope(f)
+    }
+
+    /// Executes the given function inside this [`Span`].
+    #[cfg(not(feature = "tracing"))]
+    #[inline]
+    pub fn in_scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        f()
+    }
+
+    /// Instruments the given [`Future`] with this [`Span`], so each its poll
+    /// happens inside this [`Span`].
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub fn instrument<F: Future>(
+        self,
+        fut: F,
+    ) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(fut, self.inner)
+    }
+
+    /// Instruments the given [`Future`] with this [`Span`], so each its poll
+    /// happens inside this [`Span`].
+    #[cfg(not(feature = "tracing"))]
+    #[inline]
+    pub fn instrument<F: Future>(
+        self,
+        fut: F,
+    ) -> impl Future<Output = F::Output> {
+        fut
+    }
+}
+
+/// Creates a [`Span`] of persisting the [`State`] having the given number of
+/// serialized `bytes`.
+///
+/// [`State`]: crate::State
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+#[must_use]
+pub fn persist(bytes: usize) -> Span {
+    Span {
+        #[cfg(feature = "tracing")]
+        inner: tracing::info_span!("state.persist", bytes = bytes as u64),
+    }
+}
+
+/// Creates a [`Span`] of applying the given number of `restreams` to the
+/// [`ffmpeg::RestreamersPool`].
+///
+/// [`ffmpeg::RestreamersPool`]: crate::ffmpeg::RestreamersPool
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+#[must_use]
+pub fn pool_apply(restreams: usize) -> Span {
+    Span {
+        #[cfg(feature = "tracing")]
+        inner: tracing::info_span!(
+            "ffmpeg.pool_apply",
+            restreams = restreams as u64
+        ),
+    }
+}
+
+/// Creates a [`Span`] of handling the given [SRS] HTTP callback `action`.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+#[must_use]
+pub fn callback(action: callback::Event) -> Span {
+    Span {
+        #[cfg(feature = "tracing")]
+        inner: tracing::info_span!("srs.callback", action = ?action),
+    }
+}
+
+/// Locks the given [`Mutable`] for the mutation named as `op`, recording the
+/// time it's held locked for.
+///
+/// The returned [`LockMut`] is instrumented with a `state.mutation` span,
+/// having the `lock_hold_us` field recorded once it's dropped.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+#[must_use]
+pub fn lock_mut<'a, T>(
+    mutable: &'a Mutable<T>,
+    op: &'static str,
+) -> LockMut<'a, T> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "state.mutation",
+        op,
+        lock_hold_us = tracing::field::Empty,
+    );
+    LockMut {
+        guard: mutable.lock_mut(),
+        #[cfg(feature = "tracing")]
+        span,
+        #[cfg(feature = "tracing")]
+        locked_at: Instant::now(),
+    }
+}
+
+/// [`MutableLockMut`] recording the time it's held for into its span.
+///
+/// Is a bare [`MutableLockMut`], unless `tracing` feature is compiled in.
+pub struct LockMut<'a, T> {
+    /// Underlying [`MutableLockMut`].
+    guard: MutableLockMut<'a, T>,
+
+    /// Span of the mutation this [`LockMut`] is held for.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+
+    /// Moment this [`LockMut`] has been acquired at.
+    #[cfg(feature = "tracing")]
+    locked_at: Instant,
+}
+
+impl<T> Deref for LockMut<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &*self.guard
+    }
+}
+
+impl<T> DerefMut for LockMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.guard
+    }
+}
+
+impl<T> fmt::Debug for LockMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockMut").finish()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T> Drop for LockMut<'_, T> {
+    fn drop(&mut self) {
+        let held = u64::try_from(self.locked_at.elapsed().as_micros())
+            .unwrap_or(u64::MAX);
+        let _ = self.span.record("lock_hold_us", &held);
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod spans_spec {
+    use actix_web::rt::System;
+    use serde_json::json;
+
+    use crate::{api::srs::callback, spec, state::State};
+
+    use super::{
+        callback as callback_span,
+        otlp::{Collector, Value},
+    };
+
+    #[test]
+    fn emits_spans() {
+        let collector = Collector::default();
+
+        tracing::subscriber::with_default(collector.clone(), || {
+            System::new("test").block_on(async {
+                let state = State::new_in_memory();
+                callback_span(callback::Event::OnPublish).in_scope(|| {
+                    state
+                        .add_restream(
+                            serde_json::from_value::<spec::v1::Restream>(
+                                json!({
+                                    "key": "main",
+                                    "input": {
+                                        "key": "origin",
+                                        "endpoints": [{"kind": "rtmp"}],
+                                    },
+                                }),
+                            )
+                            .unwrap(),
+                        )
+                        .unwrap();
+                });
+            });
+        });
+
+        let spans = collector.drain();
+        let callback = spans
+            .iter()
+            .find(|s| s.name == "srs.callback")
+            .expect("no callback span");
+        let mutation = spans
+            .iter()
+            .find(|s| {
+                s.name == "state.mutation"
+                    && s.field("op") == Some(&Value::Str("add_restream".into()))
+            })
+            .expect("no mutation span");
+        assert_eq!(mutation.parent_id, Some(callback.id));
+        assert_eq!(mutation.trace_id, callback.trace_id);
+        assert!(mutation.field("lock_hold_us").is_some());
+    }
+}
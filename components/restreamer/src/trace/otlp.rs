@@ -0,0 +1,444 @@
+//! Exporting [tracing] spans to an [OpenTelemetry] collector via [OTLP/HTTP]
+//! with JSON encoding.
+//!
+//! Only the tiny subset of the protocol required for exporting spans is
+//! implemented here, so this feature pulls the `tracing` crate only. Spans are
+//! sent with the same [`reqwest`] client as everything else, so collectors
+//! behind HTTPS are supported too.
+//!
+//! [OpenTelemetry]: https://opentelemetry.io
+//! [OTLP/HTTP]: https://opentelemetry.io/docs/specs/otlp/#otlphttp
+//! [tracing]: https://docs.rs/tracing
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ephyr_log::log;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value as Json};
+use tokio::time;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+use url::Url;
+
+/// Name of the service the exported spans are reported by.
+const SERVICE_NAME: &str = "ephyr-restreamer";
+
+/// Interval to export the collected spans with.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Maximum number of finished spans kept by a [`Collector`] awaiting their
+/// export.
+///
+/// The oldest spans are dropped once exceeded, so an unreachable collector
+/// doesn't make this application to grow its memory unboundedly.
+const MAX_PENDING: usize = 4096;
+
+/// [`Collector`] installed as the global [`Subscriber`].
+///
+/// The global [`Subscriber`] may be installed only once per process, so is
+/// reused by the consequent [`install()`]s.
+static GLOBAL: OnceCell<Collector> = OnceCell::new();
+
+/// Generation of the last spawned exporting of the [`GLOBAL`] spans, so the
+/// previously spawned one stops once a new one is spawned.
+static EXPORTING: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Stack of the spans entered on the current thread.
+    static ENTERED: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+}
+
+/// Value of a span field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// String value, or the [`fmt::Debug`] representation of any other value.
+    Str(String),
+
+    /// Integer value.
+    Int(i64),
+
+    /// Floating point value.
+    Float(f64),
+
+    /// Boolean value.
+    Bool(bool),
+}
+
+impl Value {
+    /// Encodes this [`Value`] as an OTLP JSON `AnyValue`.
+    fn to_json(&self) -> Json {
+        match self {
+            Self::Str(s) => json!({ "stringValue": s }),
+            // 64-bit integers are encoded as strings in OTLP JSON.
+            Self::Int(i) => json!({ "intValue": i.to_string() }),
+            Self::Float(f) => json!({ "doubleValue": f }),
+            Self::Bool(b) => json!({ "boolValue": b }),
+        }
+    }
+}
+
+/// Fields of a span.
+#[derive(Debug, Default)]
+struct Fields(Vec<(&'static str, Value)>);
+
+impl Fields {
+    /// Sets the field of the given `name` to the given [`Value`].
+    fn set(&mut self, name: &'static str, val: Value) {
+        match self.0.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = val,
+            None => self.0.push((name, val)),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field.name(), Value::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        #[allow(clippy::cast_possible_wrap)]
+        self.set(field.name(), Value::Int(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field.name(), Value::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field.name(), Value::Str(value.to_owned()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field.name(), Value::Str(format!("{:?}", value)));
+    }
+}
+
+/// Span being opened at the moment.
+#[derive(Debug)]
+struct OpenSpan {
+    /// Name of this span.
+    name: &'static str,
+
+    /// ID of the trace this span belongs to.
+    trace_id: u128,
+
+    /// ID of the parent span, if any.
+    parent_id: Option<u64>,
+
+    /// Moment this span has been opened at.
+    started_at: SystemTime,
+
+    /// Fields of this span.
+    fields: Fields,
+
+    /// Number of handles to this span being alive.
+    refs: usize,
+}
+
+/// Span being closed already.
+#[derive(Clone, Debug)]
+pub struct FinishedSpan {
+    /// Name of this span.
+    pub name: &'static str,
+
+    /// ID of this span.
+    pub id: u64,
+
+    /// ID of the trace this span belongs to.
+    pub trace_id: u128,
+
+    /// ID of the parent span, if any.
+    pub parent_id: Option<u64>,
+
+    /// Moment this span has been opened at.
+    pub started_at: SystemTime,
+
+    /// Moment this span has been closed at.
+    pub ended_at: SystemTime,
+
+    /// Fields of this span along with their values.
+    pub fields: Vec<(&'static str, Value)>,
+}
+
+impl FinishedSpan {
+    /// Returns [`Value`] of the field with the given `name`, if it has been
+    /// recorded.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Encodes this [`FinishedSpan`] as an OTLP JSON `Span`.
+    fn to_json(&self) -> Json {
+        /// Returns nanoseconds since UNIX epoch of the given `time` as a
+        /// string, like OTLP JSON encodes 64-bit integers.
+        fn nanos(time: SystemTime) -> String {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .to_string()
+        }
+
+        let mut span = json!({
+            "traceId": format!("{:032x}", self.trace_id),
+            "spanId": format!("{:016x}", self.id),
+            "name": self.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": nanos(self.started_at),
+            "endTimeUnixNano": nanos(self.ended_at),
+            "attributes": self
+                .fields
+                .iter()
+                .map(|(k, v)| json!({ "key": k, "value": v.to_json() }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(id) = self.parent_id {
+            span["parentSpanId"] = format!("{:016x}", id).into();
+        }
+        span
+    }
+}
+
+/// [`Subscriber`] collecting finished spans to be exported to an
+/// [OpenTelemetry] collector.
+///
+/// Events are not collected, as this application logs them separately.
+///
+/// [OpenTelemetry]: https://opentelemetry.io
+#[derive(Clone, Debug, Default)]
+pub struct Collector(Arc<Spans>);
+
+/// Spans tracked by a [`Collector`].
+#[derive(Debug, Default)]
+struct Spans {
+    /// Last issued span ID.
+    last_id: AtomicU64,
+
+    /// Spans being opened at the moment.
+    open: Mutex<HashMap<u64, OpenSpan>>,
+
+    /// Finished spans awaiting their export.
+    finished: Mutex<VecDeque<FinishedSpan>>,
+}
+
+/// Installs a [`Collector`] as the global [`Subscriber`], unless installed
+/// already, and spawns exporting of its spans to the given OTLP/HTTP
+/// `endpoint` periodically, instead of the previously spawned one, if any.
+///
+/// # Errors
+///
+/// If another global [`Subscriber`] has been installed already.
+pub fn install(endpoint: &Url) -> Result<(), anyhow::Error> {
+    let collector = GLOBAL
+        .get_or_try_init(|| {
+            let collector = Collector::default();
+            tracing::subscriber::set_global_default(collector.clone())
+                .map(|()| collector)
+        })?
+        .clone();
+    let generation = EXPORTING.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let url = format!("{}/v1/traces", endpoint.as_str().trim_end_matches('/'));
+    let client = reqwest::Client::new();
+    drop(tokio::spawn(async move {
+        loop {
+            time::delay_for(EXPORT_INTERVAL).await;
+            if EXPORTING.load(Ordering::SeqCst) != generation {
+                break;
+            }
+            let spans = collector.drain();
+            if spans.is_empty() {
+                continue;
+            }
+            let res = client
+                .post(&url)
+                .json(&request(&spans))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(e) = res {
+                log::warn!(
+                    "Failed to export {} spans to {}: {}",
+                    spans.len(),
+                    url,
+                    e,
+                );
+            }
+        }
+    }));
+    Ok(())
+}
+
+impl Collector {
+    /// Takes all the [`FinishedSpan`]s collected so far.
+    #[must_use]
+    pub fn drain(&self) -> Vec<FinishedSpan> {
+        self.0
+            .finished
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain(..)
+            .collect()
+    }
+}
+
+impl Subscriber for Collector {
+    #[inline]
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span()
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let id = self.0.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let parent_id = attrs.parent().map(span::Id::into_u64).or_else(|| {
+            if attrs.is_contextual() {
+                ENTERED.with(|s| s.borrow().last().copied())
+            } else {
+                None
+            }
+        });
+
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        let mut open =
+            self.0.open.lock().unwrap_or_else(PoisonError::into_inner);
+        let trace_id = parent_id
+            .and_then(|p| open.get(&p))
+            .map_or_else(rand::random, |p| p.trace_id);
+        let _ = open.insert(
+            id,
+            OpenSpan {
+                name: attrs.metadata().name(),
+                trace_id,
+                parent_id,
+                started_at: SystemTime::now(),
+                fields,
+                refs: 1,
+            },
+        );
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut open =
+            self.0.open.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(s) = open.get_mut(&span.into_u64()) {
+            values.record(&mut s.fields);
+        }
+    }
+
+    #[inline]
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    #[inline]
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &span::Id) {
+        ENTERED.with(|s| s.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        ENTERED.with(|s| {
+            let mut stack = s.borrow_mut();
+            if let Some(i) = stack.iter().rposition(|id| *id == span.into_u64())
+            {
+                let _ = stack.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        let mut open =
+            self.0.open.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(s) = open.get_mut(&span.into_u64()) {
+            s.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: &span::Id) -> bool {
+        let id = span.into_u64();
+        let s = {
+            let mut open =
+                self.0.open.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(s) = open.get_mut(&id).filter(|s| s.refs > 1) {
+                s.refs -= 1;
+                return false;
+            }
+            match open.remove(&id) {
+                Some(s) => s,
+                None => return false,
+            }
+        };
+
+        let mut finished = self
+            .0
+            .finished
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if finished.len() >= MAX_PENDING {
+            let _ = finished.pop_front();
+        }
+        finished.push_back(FinishedSpan {
+            name: s.name,
+            id,
+            trace_id: s.trace_id,
+            parent_id: s.parent_id,
+            started_at: s.started_at,
+            ended_at: SystemTime::now(),
+            fields: s.fields.0,
+        });
+        true
+    }
+}
+
+/// Builds an OTLP JSON `ExportTraceServiceRequest` of the given `spans`.
+fn request(spans: &[FinishedSpan]) -> Json {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{
+                    "key": "service.name",
+                    "value": { "stringValue": SERVICE_NAME },
+                }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": SERVICE_NAME },
+                "spans": spans.iter().map(FinishedSpan::to_json)
+                    .collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+#[cfg(test)]
+mod install_spec {
+    use actix_web::rt::System;
+    use url::Url;
+
+    use super::install;
+
+    #[test]
+    fn installs_repeatedly() {
+        System::new("test").block_on(async {
+            let endpoint = Url::parse("https://127.0.0.1:4318").unwrap();
+
+            install(&endpoint).unwrap();
+            install(&endpoint).unwrap();
+        });
+    }
+}
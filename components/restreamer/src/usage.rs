@@ -0,0 +1,479 @@
+//! Metering of online time of [`state::Input`]s and [`state::Output`]s for
+//! billing.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use tokio::time;
+
+use crate::{
+    api::graphql::DateTime,
+    state::{self, OutputId, RestreamId, State, Statuses},
+    trace,
+};
+
+/// Meter of the time [`state::Input`]s serve a live stream and
+/// [`state::Output`]s are [`state::Status::Online`], accumulating it into
+/// [`state::Restream::input_online_secs`] and [`state::Output::online_secs`].
+///
+/// Status transitions are tracked in memory on [`State`] changes, while the
+/// counters are updated (and so persisted) once per flush interval only, so
+/// flapping statuses don't cause a storm of [`State`] writes. Prewarmed
+/// (disabled) [`state::Output`]s are never metered, as don't serve anything.
+///
+/// Online periods are never persisted, only the accumulated counters, so the
+/// time since the last flush is lost on a server crash, but nothing is counted
+/// twice after the restart.
+#[derive(Debug)]
+pub struct UsageMeter {
+    /// [`Ledger`] of online periods not flushed yet, shared with the flushing
+    /// task.
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl UsageMeter {
+    /// Creates a new [`UsageMeter`] flushing the metered time into the given
+    /// [`State`] every `interval`.
+    #[must_use]
+    pub fn spawn(state: State, interval: Duration) -> Self {
+        let ledger = Arc::new(Mutex::new(Ledger::default()));
+        let flushed = ledger.clone();
+        drop(tokio::spawn(async move {
+            loop {
+                time::delay_for(interval).await;
+                flush(&state, &flushed);
+            }
+        }));
+        Self { ledger }
+    }
+
+    /// Tracks online periods of the [`state::Input`]s and [`state::Output`]s
+    /// of the given `restreams` having the given `statuses`, starting the ones
+    /// which have gone online and finishing the ones which have gone offline
+    /// since the previous call.
+    pub fn apply(
+        &mut self,
+        restreams: &[state::Restream],
+        statuses: &Statuses,
+    ) {
+        self.ledger
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .track(restreams, statuses, SystemTime::now());
+    }
+}
+
+/// Flushes the time accumulated in the given [`Ledger`] into the `actual`
+/// [`State`].
+///
+/// Doesn't touch the [`State`] if no whole second has been accumulated since
+/// the previous flush. The [`Ledger`] is unlocked before the [`State`] is
+/// locked, so the two locks are never held together.
+fn flush(actual: &State, ledger: &Mutex<Ledger>) {
+    let usage = ledger
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .flush(SystemTime::now());
+    if usage.is_empty() {
+        return;
+    }
+    let mut restreams = trace::lock_mut(&actual.restreams, "flush_usage");
+    for u in &usage {
+        u.add_to(&mut restreams);
+    }
+}
+
+/// Element whose online time is metered.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Metered {
+    /// [`state::Input`] of the [`state::Restream`] with the given ID.
+    Input(RestreamId),
+
+    /// [`state::Output`] with the given ID.
+    Output(OutputId),
+}
+
+/// Online periods of metered elements, not flushed into [`State`] yet.
+#[derive(Debug, Default)]
+struct Ledger {
+    /// Moments the elements being online at the moment have gone online at,
+    /// or have been flushed at, whichever is later.
+    online: HashMap<Metered, SystemTime>,
+
+    /// Online periods finished since the previous flush.
+    finished: HashMap<Metered, Vec<(SystemTime, SystemTime)>>,
+
+    /// Sub-second remainders of the online elements, left unflushed, so they
+    /// don't get lost on every flush.
+    carry: HashMap<Metered, Duration>,
+
+    /// [`state::Restream::usage_since`] moments of the tracked
+    /// [`state::Restream`]s.
+    since: HashMap<RestreamId, Option<DateTime>>,
+
+    /// IDs of the [`state::Restream`]s owning the tracked [`state::Output`]s.
+    owners: HashMap<OutputId, RestreamId>,
+}
+
+impl Ledger {
+    /// Indicates whether this [`Ledger`] has nothing to be flushed.
+    fn is_empty(&self) -> bool {
+        self.online.is_empty() && self.finished.is_empty()
+    }
+
+    /// Starts online periods of the elements of the given `restreams` which
+    /// have gone online (according to the given `statuses`), and finishes the
+    /// ones of the elements which have gone offline (or have been removed) at
+    /// the given moment.
+    fn track(
+        &mut self,
+        restreams: &[state::Restream],
+        statuses: &Statuses,
+        now: SystemTime,
+    ) {
+        self.since = restreams.iter().map(|r| (r.id, r.usage_since)).collect();
+        self.owners = restreams
+            .iter()
+            .flat_map(|r| r.outputs.iter().map(move |o| (o.id, r.id)))
+            .collect();
+
+        let online: HashSet<_> = restreams
+            .iter()
+            .flat_map(|r| {
+                let input = r
+                    .input
+                    .is_ready_to_serve(statuses)
+                    .then(|| Metered::Input(r.id));
+                let outputs = r
+                    .outputs
+                    .iter()
+                    .filter(|o| o.enabled && statuses.get(o.id).is_online())
+                    .map(|o| Metered::Output(o.id));
+                input.into_iter().chain(outputs)
+            })
+            .collect();
+
+        let gone: Vec<_> = self
+            .online
+            .keys()
+            .filter(|m| !online.contains(m))
+            .copied()
+            .collect();
+        for m in gone {
+            if let Some(since) = self.online.remove(&m) {
+                self.finished.entry(m).or_default().push((since, now));
+            }
+        }
+        for m in online {
+            let _ = self.online.entry(m).or_insert(now);
+        }
+    }
+
+    /// Takes the whole seconds accumulated by this [`Ledger`] until the given
+    /// moment, as the [`Usage`] of every tracked [`state::Restream`] having
+    /// any.
+    ///
+    /// Time before [`state::Restream::usage_since`] is not counted, so the
+    /// periods started before the counters reset don't leak into them.
+    fn flush(&mut self, now: SystemTime) -> Vec<Usage> {
+        let mut usage: HashMap<_, _> = self
+            .since
+            .clone()
+            .into_iter()
+            .map(|(id, since)| {
+                let secs = self.take(
+                    Metered::Input(id),
+                    since.map(SystemTime::from),
+                    now,
+                );
+                (id, Usage::new(id, since, secs))
+            })
+            .collect();
+        for (output_id, restream_id) in self.owners.clone() {
+            if let Some(u) = usage.get_mut(&restream_id) {
+                let since = u.since.map(SystemTime::from);
+                let secs = self.take(Metered::Output(output_id), since, now);
+                if secs > 0 {
+                    let _ = u.outputs_secs.insert(output_id, secs);
+                }
+            }
+        }
+        // Whatever is left belongs to the removed elements.
+        self.finished.clear();
+        let online = &self.online;
+        self.carry.retain(|m, _| online.contains_key(m));
+
+        usage
+            .into_iter()
+            .map(|(_, u)| u)
+            .filter(|u| !u.is_empty())
+            .collect()
+    }
+
+    /// Takes the whole seconds the given metered element has been online for
+    /// since the previous flush until the given moment, not counting the time
+    /// before the given `since` moment.
+    fn take(
+        &mut self,
+        metered: Metered,
+        since: Option<SystemTime>,
+        now: SystemTime,
+    ) -> u64 {
+        let clamp = |from: SystemTime| since.map_or(from, |s| from.max(s));
+        let elapsed = |from, to: SystemTime| {
+            to.duration_since(clamp(from)).unwrap_or_default()
+        };
+
+        let mut total = self.carry.remove(&metered).unwrap_or_default();
+        for (from, to) in self.finished.remove(&metered).unwrap_or_default() {
+            total += elapsed(from, to);
+        }
+        if let Some(from) = self.online.get_mut(&metered) {
+            total += elapsed(*from, now);
+            *from = now;
+        }
+
+        let secs = total.as_secs();
+        let _ = self
+            .carry
+            .insert(metered, total - Duration::from_secs(secs));
+        secs
+    }
+}
+
+/// Whole seconds of online time of a [`state::Restream`] taken from a
+/// [`Ledger`], to be added into its counters.
+#[derive(Debug)]
+struct Usage {
+    /// ID of the [`state::Restream`] this [`Usage`] belongs to.
+    restream_id: RestreamId,
+
+    /// [`state::Restream::usage_since`] moment this [`Usage`] has been
+    /// counted since.
+    since: Option<DateTime>,
+
+    /// Seconds to be added into [`state::Restream::input_online_secs`].
+    input_secs: u64,
+
+    /// Seconds to be added into [`state::Output::online_secs`].
+    outputs_secs: HashMap<OutputId, u64>,
+}
+
+impl Usage {
+    /// Creates a new [`Usage`] of the [`state::Restream`] with the given `id`,
+    /// counted `since` the given moment.
+    fn new(id: RestreamId, since: Option<DateTime>, input_secs: u64) -> Self {
+        Self {
+            restream_id: id,
+            since,
+            input_secs,
+            outputs_secs: HashMap::new(),
+        }
+    }
+
+    /// Indicates whether this [`Usage`] has nothing to be added.
+    fn is_empty(&self) -> bool {
+        self.input_secs == 0 && self.outputs_secs.is_empty()
+    }
+
+    /// Adds this [`Usage`] into the counters of its [`state::Restream`] among
+    /// the given `restreams`.
+    ///
+    /// Does nothing if the counters have been reset after this [`Usage`] has
+    /// been taken, so the time before the reset is never counted after it.
+    fn add_to(&self, restreams: &mut [state::Restream]) {
+        let restream =
+            match restreams.iter_mut().find(|r| r.id == self.restream_id) {
+                Some(r) if r.usage_since == self.since => r,
+                _ => return,
+            };
+        restream.input_online_secs =
+            restream.input_online_secs.saturating_add(self.input_secs);
+        for o in &mut restream.outputs {
+            if let Some(secs) = self.outputs_secs.get(&o.id) {
+                o.online_secs = o.online_secs.saturating_add(*secs);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ledger_spec {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::{
+        spec,
+        state::{DateTime, Restream, Status, Statuses},
+    };
+
+    use super::Ledger;
+
+    fn restream() -> Restream {
+        let mut restream = Restream::new(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "client",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "enabled": true,
+                },
+                "outputs": [{
+                    "dst": "rtmp://example.com/live/stream",
+                    "enabled": true,
+                }],
+            }))
+            .unwrap(),
+        );
+        restream.usage_since = None;
+        restream
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + secs)
+    }
+
+    fn flush(ledger: &mut Ledger, restreams: &mut [Restream], now: SystemTime) {
+        for u in ledger.flush(now) {
+            u.add_to(restreams);
+        }
+    }
+
+    fn statuses(restream: &Restream, online: bool) -> Statuses {
+        let status = if online {
+            Status::Online
+        } else {
+            Status::Offline
+        };
+        let mut statuses = Statuses::default();
+        let _ = statuses.set(restream.input.endpoints[0].id, status);
+        let _ = statuses.set(restream.outputs[0].id, status);
+        statuses
+    }
+
+    #[test]
+    fn counts_online_time_on_flush_only() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+        assert_eq!(restreams[0].input_online_secs, 0);
+
+        flush(&mut ledger, &mut restreams, at(60));
+        assert_eq!(restreams[0].input_online_secs, 60);
+        assert_eq!(restreams[0].outputs[0].online_secs, 60);
+
+        flush(&mut ledger, &mut restreams, at(90));
+        assert_eq!(restreams[0].input_online_secs, 90);
+    }
+
+    #[test]
+    fn accumulates_flapping_periods() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        for n in 0..5 {
+            let online = statuses(&restreams[0], true);
+            ledger.track(&restreams, &online, at(n * 10));
+            let offline = statuses(&restreams[0], false);
+            ledger.track(&restreams, &offline, at(n * 10 + 3));
+        }
+        flush(&mut ledger, &mut restreams, at(60));
+
+        assert_eq!(restreams[0].input_online_secs, 15);
+        assert_eq!(restreams[0].outputs[0].online_secs, 15);
+        assert!(ledger.is_empty());
+    }
+
+    #[test]
+    fn does_not_count_before_reset() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+        restreams[0].usage_since = Some(DateTime::from(at(40)));
+        ledger.track(&restreams, &online, at(40));
+        flush(&mut ledger, &mut restreams, at(60));
+
+        assert_eq!(restreams[0].input_online_secs, 20);
+        assert_eq!(restreams[0].outputs[0].online_secs, 20);
+    }
+
+    #[test]
+    fn discards_usage_taken_before_reset() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+        let usage = ledger.flush(at(60));
+        restreams[0].usage_since = Some(DateTime::from(at(50)));
+        for u in usage {
+            u.add_to(&mut restreams);
+        }
+
+        assert_eq!(restreams[0].input_online_secs, 0);
+        assert_eq!(restreams[0].outputs[0].online_secs, 0);
+    }
+
+    #[test]
+    fn takes_nothing_without_whole_seconds() {
+        let restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+
+        assert!(ledger.flush(at(0) + Duration::from_millis(500)).is_empty());
+    }
+
+    #[test]
+    fn meters_unstable_but_not_prewarmed_outputs() {
+        let mut restreams = vec![restream(), restream()];
+        restreams[1].outputs[0].enabled = false;
+        let mut ledger = Ledger::default();
+
+        let mut statuses = Statuses::default();
+        let _ = statuses.set(restreams[0].outputs[0].id, Status::Unstable);
+        let _ = statuses.set(restreams[1].outputs[0].id, Status::Online);
+        ledger.track(&restreams, &statuses, at(0));
+        flush(&mut ledger, &mut restreams, at(60));
+
+        assert_eq!(restreams[0].outputs[0].online_secs, 60);
+        assert_eq!(restreams[1].outputs[0].online_secs, 0);
+    }
+
+    #[test]
+    fn carries_sub_second_remainders() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+        let half = Duration::from_millis(500);
+        flush(&mut ledger, &mut restreams, at(1) + half);
+        flush(&mut ledger, &mut restreams, at(2) + half + half);
+
+        assert_eq!(restreams[0].input_online_secs, 3);
+    }
+
+    #[test]
+    fn forgets_removed_elements() {
+        let mut restreams = vec![restream()];
+        let mut ledger = Ledger::default();
+
+        let online = statuses(&restreams[0], true);
+        ledger.track(&restreams, &online, at(0));
+        ledger.track(&[], &online, at(10));
+        flush(&mut ledger, &mut [], at(60));
+
+        assert!(ledger.is_empty());
+        assert!(ledger.carry.is_empty());
+    }
+}
@@ -69,6 +69,16 @@ export function sanitizeUrl(url: string): string {
   return url.replace(/[\s]+/g, '');
 }
 
+/**
+ * Indicates whether the given `status` means that media traffic flows, even if
+ * not as expected (`ONLINE` or `UNSTABLE`).
+ *
+ * @param status    Status of an `Output` or an `InputEndpoint`.
+ */
+export function isOnline(status: string): boolean {
+  return status === 'ONLINE' || status === 'UNSTABLE';
+}
+
 /**
  * Checks whether the given location page corresponds to
  * `/restream/:restream_id/output/:output_id` route.
@@ -79,3 +89,16 @@ export function isOutputPage(location: string): boolean {
   const p = location.split('/');
   return p[1] === 'restream' && p[3] === 'output';
 }
+
+/**
+ * Splits the given public `host` into its hostname and an optional port.
+ *
+ * @param host    Public host (like `example.com` or `10.0.0.1:1935`) to be
+ *                split.
+ *
+ * @returns    Hostname and port (or `null` if the `host` has no port).
+ */
+export function splitHostPort(host: string): [string, number | null] {
+  const m = host.match(/^(.*):(\d+)$/);
+  return m ? [m[1], parseInt(m[2], 10)] : [host, null];
+}
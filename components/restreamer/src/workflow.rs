@@ -0,0 +1,372 @@
+//! Advisory "go live" checklist of [`state::Restream`]s.
+//!
+//! It only tracks the progress of operators, so never blocks any re-streaming.
+
+use ephyr_log::log;
+use juniper::{graphql_object, GraphQLEnum, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use smart_default::SmartDefault;
+
+use crate::{
+    api::graphql::DateTime,
+    serde::is_false,
+    state::{self, State, Statuses},
+    trace,
+};
+
+/// Stage of a "go live" `Workflow` of a `Restream`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    GraphQLEnum,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    SmartDefault,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowStage {
+    /// `Restream` is planned, but nothing is confirmed yet.
+    #[default]
+    Planned,
+
+    /// `Input` of the `Restream` is confirmed to receive a live stream.
+    InputConfirmed,
+
+    /// `Output`s of the `Restream` are tested (with color bars, for example).
+    Tested,
+
+    /// Real `Output`s of the `Restream` are enabled.
+    Live,
+
+    /// Broadcasting is over.
+    Wrapped,
+}
+
+impl WorkflowStage {
+    /// Returns the [`WorkflowStage`] following this one, if any.
+    #[inline]
+    #[must_use]
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Planned => Some(Self::InputConfirmed),
+            Self::InputConfirmed => Some(Self::Tested),
+            Self::Tested => Some(Self::Live),
+            Self::Live => Some(Self::Wrapped),
+            Self::Wrapped => None,
+        }
+    }
+}
+
+/// Step of a "go live" `Workflow` of a `Restream` reached at some moment.
+#[derive(
+    Clone, Debug, Deserialize, Eq, GraphQLObject, PartialEq, Serialize,
+)]
+pub struct WorkflowStep {
+    /// `WorkflowStage` reached by this `WorkflowStep`.
+    pub stage: WorkflowStage,
+
+    /// Moment when the `WorkflowStage` has been reached.
+    pub at: DateTime,
+
+    /// Indicator whether the `WorkflowStage` has been reached automatically
+    /// by this server, rather than by an operator.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub auto: bool,
+}
+
+/// Advisory "go live" checklist of a [`state::Restream`].
+///
+/// Never affects re-streaming in any way.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Workflow {
+    /// Current [`WorkflowStage`] of this [`Workflow`].
+    #[serde(default)]
+    pub stage: WorkflowStage,
+
+    /// Indicator whether this [`Workflow`] should be advanced from
+    /// [`WorkflowStage::Planned`] to [`WorkflowStage::InputConfirmed`]
+    /// automatically, once the [`state::Input`] of its [`state::Restream`]
+    /// serves a live stream.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub auto_confirm_input: bool,
+
+    /// [`WorkflowStep`]s reached since the last reset of this [`Workflow`],
+    /// the oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<WorkflowStep>,
+}
+
+impl Workflow {
+    /// Indicates whether this [`Workflow`] is in its initial state, and has
+    /// never been configured.
+    #[inline]
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Advances this [`Workflow`] to the next [`WorkflowStage`], recording the
+    /// reached [`WorkflowStep`].
+    ///
+    /// `auto` indicates whether it's advanced automatically by this server.
+    ///
+    /// Returns `false` if there is no next [`WorkflowStage`].
+    #[must_use]
+    pub fn advance(&mut self, auto: bool) -> bool {
+        let next = match self.stage.next() {
+            Some(s) => s,
+            None => return false,
+        };
+        self.stage = next;
+        self.steps.push(WorkflowStep {
+            stage: next,
+            at: DateTime::now(),
+            auto,
+        });
+        true
+    }
+
+    /// Resets this [`Workflow`] to [`WorkflowStage::Planned`], forgetting all
+    /// its [`WorkflowStep`]s.
+    ///
+    /// Returns `false` if it has been reset already.
+    #[must_use]
+    pub fn reset(&mut self) -> bool {
+        let changed =
+            self.stage != WorkflowStage::Planned || !self.steps.is_empty();
+        self.stage = WorkflowStage::Planned;
+        self.steps.clear();
+        changed
+    }
+
+    /// Indicates whether this [`Workflow`] should be advanced automatically
+    /// from [`WorkflowStage::Planned`], given the `restream` it belongs to
+    /// and its `statuses`.
+    #[must_use]
+    fn should_confirm_input(
+        &self,
+        restream: &state::Restream,
+        statuses: &Statuses,
+    ) -> bool {
+        self.auto_confirm_input
+            && self.stage == WorkflowStage::Planned
+            && restream.input.is_ready_to_serve(statuses)
+    }
+}
+
+/// Advisory "go live" checklist of a `Restream`.
+///
+/// It only tracks the progress of operators, and never blocks re-streaming.
+#[graphql_object]
+impl Workflow {
+    /// Current `WorkflowStage` of this `Workflow`.
+    fn stage(&self) -> WorkflowStage {
+        self.stage
+    }
+
+    /// Indicator whether this `Workflow` is advanced from `PLANNED` to
+    /// `INPUT_CONFIRMED` automatically, once the `Input` of its `Restream`
+    /// serves a live stream.
+    fn auto_confirm_input(&self) -> bool {
+        self.auto_confirm_input
+    }
+
+    /// `WorkflowStep`s reached since the last reset of this `Workflow`, the
+    /// oldest first.
+    fn steps(&self) -> &[WorkflowStep] {
+        &self.steps
+    }
+}
+
+/// Watcher of [`state::Restream`]s with [`Workflow::auto_confirm_input`]
+/// enabled, advancing their [`Workflow`]s to
+/// [`WorkflowStage::InputConfirmed`] once their [`state::Input`]s serve a live
+/// stream.
+#[derive(Debug)]
+pub struct InputConfirmationsWatcher {
+    /// Application [`State`] to advance [`Workflow`]s in.
+    state: State,
+}
+
+impl InputConfirmationsWatcher {
+    /// Creates a new [`InputConfirmationsWatcher`] operating on the given
+    /// [`State`].
+    #[inline]
+    #[must_use]
+    pub fn new(state: State) -> Self {
+        Self { state }
+    }
+
+    /// Advances the [`Workflow`]s of the given `restreams`, whose
+    /// [`state::Input`]s are confirmed to serve a live stream by the given
+    /// `statuses`.
+    pub fn apply(&self, restreams: &[state::Restream], statuses: &Statuses) {
+        if !restreams
+            .iter()
+            .any(|r| r.workflow.should_confirm_input(r, statuses))
+        {
+            return;
+        }
+        let state = self.state.clone();
+        drop(tokio::spawn(async move { confirm_inputs(&state) }));
+    }
+}
+
+/// Advances the [`Workflow`]s of [`state::Restream`]s in the given [`State`],
+/// whose [`state::Input`]s are confirmed to serve a live stream.
+fn confirm_inputs(state: &State) {
+    let mut restreams = trace::lock_mut(&state.restreams, "confirm_inputs");
+    let statuses = state.statuses.lock_ref();
+    for r in restreams.iter_mut() {
+        if r.workflow.should_confirm_input(r, &statuses)
+            && r.workflow.advance(true)
+        {
+            log::info!("Confirmed input of {} restream automatically", r.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod workflow_spec {
+    use super::{Workflow, WorkflowStage};
+
+    #[test]
+    fn advances_through_all_stages() {
+        let mut workflow = Workflow::default();
+
+        for stage in &[
+            WorkflowStage::InputConfirmed,
+            WorkflowStage::Tested,
+            WorkflowStage::Live,
+            WorkflowStage::Wrapped,
+        ] {
+            assert!(workflow.advance(false));
+            assert_eq!(workflow.stage, *stage);
+        }
+        assert!(!workflow.advance(false));
+        assert_eq!(workflow.stage, WorkflowStage::Wrapped);
+
+        let stages: Vec<_> = workflow.steps.iter().map(|s| s.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                WorkflowStage::InputConfirmed,
+                WorkflowStage::Tested,
+                WorkflowStage::Live,
+                WorkflowStage::Wrapped,
+            ],
+        );
+    }
+
+    #[test]
+    fn resets_to_planned() {
+        let mut workflow = Workflow {
+            auto_confirm_input: true,
+            ..Workflow::default()
+        };
+        assert!(!workflow.reset());
+
+        assert!(workflow.advance(true));
+        assert!(workflow.steps[0].auto);
+        assert!(workflow.reset());
+
+        assert_eq!(workflow.stage, WorkflowStage::Planned);
+        assert!(workflow.steps.is_empty());
+        assert!(workflow.auto_confirm_input);
+    }
+}
+
+#[cfg(test)]
+mod input_confirmations_watcher_spec {
+    use std::time::Duration;
+
+    use actix_web::rt::System;
+    use serde_json::json;
+    use tokio::time;
+
+    use crate::state::{State, Status};
+
+    use super::{InputConfirmationsWatcher, WorkflowStage};
+
+    async fn apply(state: &State) -> (WorkflowStage, Vec<bool>) {
+        InputConfirmationsWatcher::new(state.clone())
+            .apply(&state.restreams.get_cloned(), &state.statuses.lock_ref());
+        time::delay_for(Duration::from_millis(50)).await;
+
+        let workflow = state.restreams.lock_ref()[0].workflow.clone();
+        (
+            workflow.stage,
+            workflow.steps.iter().map(|s| s.auto).collect(),
+        )
+    }
+
+    #[test]
+    fn confirms_input_once_it_serves() {
+        System::new("test").block_on(async {
+            let state = State::with_restream("main", json!({}));
+            let (id, endpoint) = {
+                let restreams = state.restreams.lock_ref();
+                (restreams[0].id, restreams[0].input.endpoints[0].id)
+            };
+            assert_eq!(
+                state.set_restream_workflow_auto_confirm(id, true),
+                Some(true),
+            );
+
+            assert_eq!(apply(&state).await, (WorkflowStage::Planned, vec![]));
+
+            let _ = state.statuses.set(endpoint, Status::Online);
+            assert_eq!(
+                apply(&state).await,
+                (WorkflowStage::InputConfirmed, vec![true]),
+            );
+
+            // Never advances further automatically.
+            assert_eq!(
+                apply(&state).await,
+                (WorkflowStage::InputConfirmed, vec![true]),
+            );
+        });
+    }
+
+    #[test]
+    fn never_confirms_without_auto_confirm() {
+        System::new("test").block_on(async {
+            let state = State::with_restream("main", json!({}));
+            let endpoint = state.restreams.lock_ref()[0].input.endpoints[0].id;
+            let _ = state.statuses.set(endpoint, Status::Online);
+
+            assert_eq!(apply(&state).await, (WorkflowStage::Planned, vec![]));
+        });
+    }
+
+    #[test]
+    fn reconfirms_input_after_reset() {
+        System::new("test").block_on(async {
+            let state = State::with_restream("main", json!({}));
+            let (id, endpoint) = {
+                let restreams = state.restreams.lock_ref();
+                (restreams[0].id, restreams[0].input.endpoints[0].id)
+            };
+            let _ = state.set_restream_workflow_auto_confirm(id, true);
+            let _ = state.advance_restream_workflow(id);
+            let _ = state.statuses.set(endpoint, Status::Online);
+
+            assert_eq!(
+                apply(&state).await,
+                (WorkflowStage::InputConfirmed, vec![false]),
+            );
+
+            assert_eq!(state.reset_restream_workflow(id), Some(true));
+            assert_eq!(
+                apply(&state).await,
+                (WorkflowStage::InputConfirmed, vec![true]),
+            );
+        });
+    }
+}
@@ -0,0 +1,74 @@
+//! End-to-end tests of enabling and disabling `Output`s.
+
+mod support;
+
+use ephyr_restreamer::{spec, state::Status};
+use serde_json::json;
+
+use self::support::{wait_until, Server};
+
+const DST: &str = "rtmp://127.0.0.1:1/live/e2e";
+
+#[test]
+#[ignore = "requires SRS, run via `make test.e2e`"]
+fn output_is_restreamed_only_while_enabled() {
+    let server = Server::start();
+    let state = server.state();
+    state
+        .add_restream(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "main",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "enabled": true,
+                },
+                "outputs": [{"dst": DST}],
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+    let (restream_id, output_id) = {
+        let restreams = state.restreams.lock_ref();
+        (restreams[0].id, restreams[0].outputs[0].id)
+    };
+    let output_status = || state.restreams.lock_ref()[0].outputs[0].status;
+
+    let _ = server.srs().publish("main", "origin").unwrap();
+    wait_until("input to go online", || {
+        state.restreams.lock_ref()[0].input.is_ready_to_serve()
+    });
+    assert!(server.ffmpeg.running_with(DST).is_empty());
+
+    let data = server.graphql(&format!(
+        r#"mutation {{ enableOutput(id: "{}", restreamId: "{}") }}"#,
+        output_id, restream_id,
+    ));
+    assert_eq!(data["enableOutput"], json!(true));
+    wait_until("FFmpeg to be spawned", || {
+        server.ffmpeg.running_with(DST).len() == 1
+    });
+    wait_until("output to go online", || output_status() == Status::Online);
+
+    // Failed FFmpeg is re-spawned.
+    let failed = server.ffmpeg.running_with(DST).remove(0);
+    failed.exit(1);
+    wait_until("FFmpeg to be re-spawned", || {
+        let running = server.ffmpeg.running_with(DST);
+        running.len() == 1 && running[0].pid != failed.pid
+    });
+
+    let data = server.graphql(&format!(
+        r#"mutation {{ disableOutput(id: "{}", restreamId: "{}") }}"#,
+        output_id, restream_id,
+    ));
+    assert_eq!(data["disableOutput"], json!(true));
+    wait_until("FFmpeg to be stopped", || {
+        server.ffmpeg.running_with(DST).is_empty()
+    });
+    wait_until("output to go offline", || {
+        output_status() == Status::Offline
+    });
+
+    server.stop();
+}
@@ -0,0 +1,61 @@
+//! End-to-end tests of publishing live streams into `Input`s.
+
+mod support;
+
+use ephyr_restreamer::{spec, state::Status};
+use serde_json::json;
+
+use self::support::{wait_until, Server};
+
+const DST: &str = "rtmp://127.0.0.1:1/live/e2e";
+
+#[test]
+#[ignore = "requires SRS, run via `make test.e2e`"]
+fn outputs_follow_publisher() {
+    let server = Server::start();
+    let state = server.state();
+    state
+        .add_restream(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "main",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "enabled": true,
+                },
+                "outputs": [{"dst": DST, "enabled": true}],
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+    let endpoint_status =
+        || state.restreams.lock_ref()[0].input.endpoints[0].status;
+    let srs = server.srs();
+
+    assert_eq!(srs.publish("unknown", "origin"), Err(404));
+    assert_eq!(srs.publish("main", "unknown"), Err(404));
+    assert_eq!(endpoint_status(), Status::Offline);
+    assert!(server.ffmpeg.running_with(DST).is_empty());
+
+    let client_id = srs.publish("main", "origin").unwrap();
+    wait_until("input to go online", || endpoint_status() == Status::Online);
+    wait_until("FFmpeg to be spawned", || {
+        server.ffmpeg.running_with(DST).len() == 1
+    });
+
+    assert_eq!(srs.unpublish(client_id, "main", "origin"), 200);
+    wait_until("input to go offline", || {
+        endpoint_status() == Status::Offline
+    });
+    wait_until("FFmpeg to be stopped", || {
+        server.ffmpeg.running_with(DST).is_empty()
+    });
+
+    // Republishing restores re-streaming.
+    let _ = srs.publish("main", "origin").unwrap();
+    wait_until("FFmpeg to be re-spawned", || {
+        server.ffmpeg.running_with(DST).len() == 1
+    });
+
+    server.stop();
+}
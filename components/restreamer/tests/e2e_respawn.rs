@@ -0,0 +1,82 @@
+//! End-to-end tests of re-spawning `Output`s, whose FFmpeg processes don't
+//! stop on termination signals, like hung ones.
+
+mod support;
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use ephyr_restreamer::spec;
+use serde_json::json;
+
+use self::support::{wait_until, Server};
+
+const DST: &str = "rtmp://127.0.0.1:1/live/e2e";
+
+const BACKUP_DST: &str = "rtmp://127.0.0.1:1/live/e2e-backup";
+
+#[test]
+#[ignore = "requires SRS, run via `make test.e2e`"]
+fn never_runs_two_generations_of_output() {
+    let server = Server::start();
+    let state = server.state();
+    state
+        .add_restream(
+            serde_json::from_value::<spec::v1::Restream>(json!({
+                "key": "main",
+                "input": {
+                    "key": "origin",
+                    "endpoints": [{"kind": "rtmp"}],
+                    "enabled": true,
+                },
+                "outputs": [{
+                    "dst": DST,
+                    "backup_dst": BACKUP_DST,
+                    "enabled": true,
+                }],
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+    let (restream_id, output_id) = {
+        let restreams = state.restreams.lock_ref();
+        (restreams[0].id, restreams[0].outputs[0].id)
+    };
+
+    let _ = server.srs().publish("main", "origin").unwrap();
+    wait_until("FFmpeg to be spawned", || {
+        server.ffmpeg.running_with(DST).len() == 1
+    });
+
+    // Every switch of the destination re-spawns the FFmpeg process, while the
+    // API stays responsive regardless of how long it takes.
+    for dst in ["BACKUP", "PRIMARY"].iter().cycle().take(7) {
+        let started_at = Instant::now();
+        let _ = server.graphql(&format!(
+            r#"mutation {{
+                switchOutputDestination(
+                    restreamId: "{}", outputId: "{}", dst: {},
+                )
+            }}"#,
+            restream_id, output_id, dst,
+        ));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while Instant::now() < deadline {
+        // Both destinations contain `DST`.
+        let running = server.ffmpeg.running_with(DST);
+        assert!(running.len() <= 1, "{:?}", running);
+        thread::sleep(Duration::from_millis(10));
+    }
+    wait_until("FFmpeg of backup destination to be spawned", || {
+        let running = server.ffmpeg.running_with(DST);
+        running.len() == 1
+            && running[0].args.iter().any(|a| a.contains(BACKUP_DST))
+    });
+
+    server.stop();
+}
@@ -0,0 +1,69 @@
+//! Tests of embedding [`Restreamer`] into another application.
+
+use std::{
+    env, fs,
+    io::{Read as _, Write as _},
+    net::{Ipv4Addr, SocketAddr, TcpStream},
+    os::unix::fs::PermissionsExt as _,
+    path::Path,
+};
+
+use ephyr_restreamer::Restreamer;
+use futures::future;
+use uuid::Uuid;
+
+/// Creates an executable shell script at the given `path`, pretending to be
+/// a binary.
+fn fake_binary(path: &Path, script: &str) {
+    fs::write(path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Performs a `GET` HTTP request of the given `path` to the given `addr`,
+/// returning the whole raw response.
+fn http_get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut resp = String::new();
+    let _ = stream.read_to_string(&mut resp).unwrap();
+    resp
+}
+
+#[test]
+fn boots_on_ephemeral_ports_and_shuts_down() {
+    let dir = env::temp_dir().join(format!("ephyr-embed-{}", Uuid::new_v4()));
+    let srs_dir = dir.join("srs");
+    fs::create_dir_all(srs_dir.join("conf")).unwrap();
+    let ffmpeg = dir.join("ffmpeg");
+    fake_binary(&ffmpeg, "echo 'ffmpeg version 4.3.1'");
+
+    let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let handle = Restreamer::builder()
+        .state_path(dir.join("state.json"))
+        .ffmpeg_path(&ffmpeg)
+        .ffprobe_path(&ffmpeg)
+        .srs_path(&srs_dir)
+        .srs_http_dir(dir.join("www"))
+        .overlays_dir(dir.join("overlays"))
+        .buffers_dir(dir.join("buffers"))
+        .client_addr(localhost)
+        .callback_addr(localhost)
+        .public_host("127.0.0.1")
+        .build()
+        .run(future::pending())
+        .unwrap();
+
+    let client_addr = handle.client_addr();
+    assert_ne!(client_addr.port(), 0);
+    assert_ne!(handle.callback_addr().map(|a| a.port()), Some(0));
+    assert!(handle.state().restreams.get_cloned().is_empty());
+
+    let resp = http_get(client_addr, "/api/v1/health");
+    assert!(resp.contains(" 200 OK\r\n"), "{}", resp);
+    assert!(resp.ends_with(r#"{"status":"ok"}"#), "{}", resp);
+
+    handle.shutdown().unwrap();
+    assert!(TcpStream::connect(client_addr).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
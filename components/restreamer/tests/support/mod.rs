@@ -0,0 +1,364 @@
+//! Harness of end-to-end tests, running the whole server along with a real
+//! [SRS] and fakes of [SRS] callbacks and [FFmpeg] it interacts with.
+//!
+//! As some parts of the server are process-global, only a single [`Server`]
+//! may be started per test binary, so each end-to-end test lives in its own
+//! file.
+//!
+//! End-to-end tests are built with `e2e` feature only and are ignored by
+//! default, as require [SRS] to be installed into [`srs_path()`]:
+//! ```bash
+//! make test.e2e
+//! ```
+//!
+//! [FFmpeg]: https://ffmpeg.org
+//! [SRS]: https://github.com/ossrs/srs
+
+// Not every test binary uses every helper.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::{Read as _, Write as _},
+    net::{Ipv4Addr, SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ephyr_restreamer::{embed::Handle, Restreamer, State};
+use futures::future;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// Returns path to the [SRS] installation directory to run the [`Server`]
+/// with.
+///
+/// Is taken from `EPHYR_E2E_SRS_PATH` environment variable, if any, or
+/// defaults to the one of the Docker image.
+///
+/// [SRS]: https://github.com/ossrs/srs
+#[must_use]
+pub fn srs_path() -> PathBuf {
+    env::var_os("EPHYR_E2E_SRS_PATH")
+        .map_or_else(|| "/usr/local/srs".into(), PathBuf::from)
+}
+
+/// Maximum duration to wait for a condition in [`wait_until()`].
+///
+/// Generous, as [FFmpeg] processes are considered online only after running
+/// for 5 seconds.
+///
+/// [FFmpeg]: https://ffmpeg.org
+pub const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Blocks until the given `cond`ition holds, polling it periodically.
+///
+/// # Panics
+///
+/// If the `cond`ition doesn't hold within [`TIMEOUT`].
+pub fn wait_until<F: FnMut() -> bool>(what: &str, mut cond: F) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !cond() {
+        assert!(Instant::now() < deadline, "Timed out waiting {}", what);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Whole server running on ephemeral ports with an in-memory state, and
+/// using a [`FakeFfmpeg`].
+#[derive(Debug)]
+pub struct Server {
+    /// [`Handle`] of the running server.
+    handle: Handle,
+
+    /// Temporary directory the server keeps its files in.
+    dir: PathBuf,
+
+    /// [`FakeFfmpeg`] the server spawns its re-streaming processes with.
+    pub ffmpeg: FakeFfmpeg,
+}
+
+impl Server {
+    /// Starts a new [`Server`] in a fresh temporary directory.
+    ///
+    /// # Panics
+    ///
+    /// If the [`Server`] fails to start.
+    #[must_use]
+    pub fn start() -> Self {
+        let dir = env::temp_dir().join(format!("ephyr-e2e-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let ffmpeg = FakeFfmpeg::install(&dir.join("ffmpeg"));
+
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let handle = Restreamer::builder()
+            .state_path(dir.join("state.json"))
+            .no_persist(true)
+            .ffmpeg_path(ffmpeg.binary())
+            .ffprobe_path(ffmpeg.binary())
+            .srs_path(srs_path())
+            .srs_http_dir(dir.join("www"))
+            .overlays_dir(dir.join("overlays"))
+            .buffers_dir(dir.join("buffers"))
+            .client_addr(localhost)
+            .callback_addr(localhost)
+            .public_host("127.0.0.1")
+            .build()
+            .run(future::pending())
+            .unwrap();
+
+        Self {
+            handle,
+            dir,
+            ffmpeg,
+        }
+    }
+
+    /// Returns [`State`] of this [`Server`].
+    #[inline]
+    #[must_use]
+    pub fn state(&self) -> &State {
+        self.handle.state()
+    }
+
+    /// Returns a [`FakeSrs`] performing callbacks to this [`Server`].
+    #[must_use]
+    pub fn srs(&self) -> FakeSrs {
+        FakeSrs {
+            callback_addr: self.handle.callback_addr().unwrap(),
+            next_client_id: AtomicU32::new(100),
+        }
+    }
+
+    /// Executes the given GraphQL `query` on the full (admin) client API of
+    /// this [`Server`], returning its `data`.
+    ///
+    /// # Panics
+    ///
+    /// If the `query` fails.
+    pub fn graphql(&self, query: &str) -> Value {
+        let (status, body) = http_post(
+            self.handle.client_addr(),
+            "/api/admin",
+            &json!({ "query": query }),
+        );
+        assert_eq!(status, 200, "{}", body);
+        let resp: Value = serde_json::from_str(&body).unwrap();
+        assert!(resp.get("errors").is_none(), "{}", body);
+        resp["data"].clone()
+    }
+
+    /// Shuts this [`Server`] down gracefully and removes its files.
+    ///
+    /// # Panics
+    ///
+    /// If the [`Server`] has failed while running.
+    pub fn stop(self) {
+        self.handle.shutdown().unwrap();
+        fs::remove_dir_all(&self.dir).unwrap();
+    }
+}
+
+/// Fake [SRS] server, performing [HTTP callbacks][1] on command.
+///
+/// [SRS]: https://github.com/ossrs/srs
+/// [1]: https://github.com/ossrs/srs/wiki/v3_EN_HTTPCallback
+#[derive(Debug)]
+pub struct FakeSrs {
+    /// Address of the callback HTTP server to perform callbacks to.
+    callback_addr: SocketAddr,
+
+    /// ID to be assigned to the next connected client.
+    next_client_id: AtomicU32,
+}
+
+impl FakeSrs {
+    /// Connects a new local client publishing a live stream into the given
+    /// `app` and `stream`, the same way [SRS] does.
+    ///
+    /// Returns ID of the published client on success, or the HTTP status of
+    /// the rejected callback otherwise.
+    ///
+    /// [SRS]: https://github.com/ossrs/srs
+    pub fn publish(&self, app: &str, stream: &str) -> Result<u32, u16> {
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        for action in &["on_connect", "on_publish"] {
+            match self.callback(action, client_id, app, stream) {
+                200 => {}
+                status => return Err(status),
+            }
+        }
+        Ok(client_id)
+    }
+
+    /// Stops publishing of the client with the given `client_id`.
+    ///
+    /// Returns the HTTP status of the callback.
+    pub fn unpublish(&self, client_id: u32, app: &str, stream: &str) -> u16 {
+        self.callback("on_unpublish", client_id, app, stream)
+    }
+
+    /// Performs the given `action` callback, returning its HTTP status.
+    pub fn callback(
+        &self,
+        action: &str,
+        client_id: u32,
+        app: &str,
+        stream: &str,
+    ) -> u16 {
+        http_post(
+            self.callback_addr,
+            "/",
+            &json!({
+                "action": action,
+                "client_id": client_id,
+                "ip": "127.0.0.1",
+                "vhost": "__defaultVhost__",
+                "app": app,
+                "stream": stream,
+            }),
+        )
+        .0
+    }
+}
+
+/// Fake [FFmpeg] binary, whose processes record their arguments and run until
+/// told to exit (see `src/bin/e2e_fake_ffmpeg.rs`).
+///
+/// [FFmpeg]: https://ffmpeg.org
+#[derive(Debug)]
+pub struct FakeFfmpeg {
+    /// Directory the fake binary and its processes' files are placed in.
+    dir: PathBuf,
+}
+
+impl FakeFfmpeg {
+    /// Installs a new [`FakeFfmpeg`] into the given `dir`ectory.
+    ///
+    /// The binary is copied, so its processes record themselves into this
+    /// `dir`ectory only.
+    ///
+    /// # Panics
+    ///
+    /// If the directory cannot be populated.
+    #[must_use]
+    pub fn install(dir: &Path) -> Self {
+        fs::create_dir_all(dir.join("procs")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let ffmpeg = Self { dir };
+        let fake = env!("CARGO_BIN_EXE_e2e_fake_ffmpeg");
+        let _ = fs::copy(fake, ffmpeg.binary()).unwrap();
+        ffmpeg
+    }
+
+    /// Returns path to the fake binary.
+    #[inline]
+    #[must_use]
+    pub fn binary(&self) -> PathBuf {
+        self.dir.join("ffmpeg")
+    }
+
+    /// Returns all the [`FakeProcess`]es spawned so far, in order of their
+    /// spawning.
+    #[must_use]
+    pub fn processes(&self) -> Vec<FakeProcess> {
+        let mut procs: Vec<_> = fs::read_dir(self.dir.join("procs"))
+            .unwrap()
+            .filter_map(|e| {
+                let e = e.unwrap();
+                let pid = e.file_name().to_str()?.parse().ok()?;
+                let spawned_at = e.metadata().ok()?.modified().ok()?;
+                let args = fs::read_to_string(e.path()).ok()?;
+                Some((
+                    spawned_at,
+                    FakeProcess {
+                        pid,
+                        args: args.lines().map(ToOwned::to_owned).collect(),
+                        dir: self.dir.join("procs"),
+                    },
+                ))
+            })
+            .collect();
+        procs.sort_by_key(|(at, p)| (*at, p.pid));
+        procs.into_iter().map(|(_, p)| p).collect()
+    }
+
+    /// Returns the running [`FakeProcess`]es having an argument containing the
+    /// given `pattern`.
+    #[must_use]
+    pub fn running_with(&self, pattern: &str) -> Vec<FakeProcess> {
+        self.processes()
+            .into_iter()
+            .filter(|p| {
+                p.is_running() && p.args.iter().any(|a| a.contains(pattern))
+            })
+            .collect()
+    }
+}
+
+/// Process of a [`FakeFfmpeg`].
+#[derive(Clone, Debug)]
+pub struct FakeProcess {
+    /// ID of this process in OS.
+    pub pid: u32,
+
+    /// Arguments this process has been spawned with.
+    pub args: Vec<String>,
+
+    /// Directory of the process' files.
+    dir: PathBuf,
+}
+
+impl FakeProcess {
+    /// Indicates whether this process is still running (not exited, nor
+    /// killed).
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        // Exited, but not reaped yet, process is a zombie.
+        fs::read_to_string(format!("/proc/{}/stat", self.pid))
+            .ok()
+            .and_then(|s| {
+                let state = s.rsplit(") ").next()?.chars().next()?;
+                Some(state != 'Z' && state != 'X')
+            })
+            .unwrap_or(false)
+    }
+
+    /// Makes this process to exit with the given `code`.
+    ///
+    /// # Panics
+    ///
+    /// If the exit code cannot be given.
+    pub fn exit(&self, code: i32) {
+        let path = self.dir.join(format!("{}.exit", self.pid));
+        fs::write(path, code.to_string()).unwrap();
+    }
+}
+
+/// Performs a `POST` HTTP request of the given JSON `body` to the given `path`
+/// of the given `addr`, returning the status and the body of the response.
+fn http_post(addr: SocketAddr, path: &str, body: &Value) -> (u16, String) {
+    let body = body.to_string();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.0\r\nHost: localhost\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        body.len(),
+        body,
+    )
+    .unwrap();
+    let mut resp = String::new();
+    let _ = stream.read_to_string(&mut resp).unwrap();
+
+    let status = resp
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| panic!("Malformed HTTP response: {}", resp));
+    let body = resp.find("\r\n\r\n").map_or("", |i| &resp[i + 4..]);
+    (status, body.to_owned())
+}